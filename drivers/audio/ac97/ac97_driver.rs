@@ -167,7 +167,17 @@ impl Driver for Ac97Driver {
         Ok(())
     }
 
-    fn start(&mut self) -> Result<(), DriverError> {
+    fn start(&mut self, dev: &DeviceInfo) -> Result<(), DriverError> {
+        // Get BARs
+        let nam = dev.bars[0] & !1; // IO Space
+        let nabm = dev.bars[1] & !1;
+        
+        if nam == 0 || nabm == 0 { return Err(DriverError::DeviceNotFound); }
+        
+        self.nam_bar = Some(nam as u16);
+        self.nabm_bar = Some(nabm as u16);
+        self.irq = dev.irq_line;
+        
         // Initialize hardware
         // Reset
         self.write_nam(AC97_RESET, 1); // Any write resets
@@ -219,20 +229,13 @@ impl Driver for Ac97Driver {
         self.initialized = false;
         Ok(())
     }
+
+    fn version(&self) -> &'static str { "0.1.0" }
 }
 
 // Initialization helper called by framework
 pub fn init_driver(dev: &DeviceInfo, driver: &mut Ac97Driver) -> Result<(), DriverError> {
-    // Get BARs
-    let nam = dev.bars[0] & !1; // IO Space
-    let nabm = dev.bars[1] & !1;
-    
-    if nam == 0 || nabm == 0 { return Err(DriverError::DeviceNotFound); }
-    
-    driver.nam_bar = Some(nam as u16);
-    driver.nabm_bar = Some(nabm as u16);
-    driver.irq = dev.irq_line;
-    
-    driver.init()
+    driver.init()?;
+    driver.start(dev)
 }
 
@@ -0,0 +1,106 @@
+//! HDA codec verbs
+//!
+//! Commands are 32-bit words posted to the CORB: codec address in bits
+//! 31:28, node id in 27:20 and the verb below that. Verbs with a 12-bit id
+//! take an 8-bit payload; the 4-bit ones (format, amp gain, coefficients)
+//! take 16 bits.
+
+// Verbs
+pub const VERB_GET_PARAMETER: u16 = 0xF00;
+pub const VERB_GET_CONNECTION_SELECT: u16 = 0xF01;
+pub const VERB_SET_CONNECTION_SELECT: u16 = 0x701;
+pub const VERB_GET_CONNECTION_LIST_ENTRY: u16 = 0xF02;
+pub const VERB_GET_POWER_STATE: u16 = 0xF05;
+pub const VERB_SET_POWER_STATE: u16 = 0x705;
+pub const VERB_GET_PIN_WIDGET_CONTROL: u16 = 0xF07;
+pub const VERB_SET_PIN_WIDGET_CONTROL: u16 = 0x707;
+pub const VERB_SET_CONVERTER_STREAM: u16 = 0x706;
+pub const VERB_GET_PIN_SENSE: u16 = 0xF09;
+pub const VERB_SET_EAPD_BTL: u16 = 0x70C;
+pub const VERB_GET_CONFIG_DEFAULT: u16 = 0xF1C;
+pub const VERB_SET_STREAM_FORMAT: u16 = 0x2;
+pub const VERB_SET_AMP_GAIN_MUTE: u16 = 0x3;
+
+// Parameters for VERB_GET_PARAMETER
+pub const PARAM_VENDOR_ID: u8 = 0x00;
+pub const PARAM_REVISION_ID: u8 = 0x02;
+pub const PARAM_NODE_COUNT: u8 = 0x04;
+pub const PARAM_FUNCTION_GROUP_TYPE: u8 = 0x05;
+pub const PARAM_AUDIO_WIDGET_CAPS: u8 = 0x09;
+pub const PARAM_PIN_CAPS: u8 = 0x0C;
+pub const PARAM_IN_AMP_CAPS: u8 = 0x0D;
+pub const PARAM_CONNECTION_LIST_LENGTH: u8 = 0x0E;
+pub const PARAM_OUT_AMP_CAPS: u8 = 0x12;
+
+/// PARAM_FUNCTION_GROUP_TYPE value of an Audio Function Group
+pub const FUNCTION_GROUP_AUDIO: u32 = 0x01;
+
+/// VERB_SET_POWER_STATE payload for fully on
+pub const POWER_STATE_D0: u16 = 0x00;
+
+// VERB_SET_AMP_GAIN_MUTE payload
+pub const AMP_SET_OUTPUT: u16 = 1 << 15;
+pub const AMP_SET_INPUT: u16 = 1 << 14;
+pub const AMP_SET_LEFT: u16 = 1 << 13;
+pub const AMP_SET_RIGHT: u16 = 1 << 12;
+pub const AMP_SET_INDEX_SHIFT: u16 = 8;
+/// Amp capability offset: the gain step that corresponds to 0 dB
+pub const AMP_CAP_OFFSET_MASK: u32 = 0x7F;
+
+// VERB_SET_PIN_WIDGET_CONTROL payload
+pub const PIN_CTL_OUT_EN: u16 = 1 << 6;
+pub const PIN_CTL_HP_EN: u16 = 1 << 7;
+
+/// Pin capability: external amplifier power down is controllable
+pub const PINCAP_EAPD: u32 = 1 << 16;
+pub const EAPD_ENABLE: u16 = 1 << 1;
+
+/// Root node of every codec
+pub const HDA_ROOT_NODE: u8 = 0;
+
+/// Highest codec address on the link
+pub const HDA_MAX_CODECS: u8 = 15;
+
+/// RIRB response extension: codec that answered, and the unsolicited flag
+pub const RIRB_EX_CODEC_MASK: u32 = 0xF;
+pub const RIRB_EX_UNSOLICITED: u32 = 1 << 4;
+
+/// Build a CORB command word
+pub fn make_verb(codec_addr: u8, node_id: u8, verb: u16, payload: u16) -> u32 {
+    let head = ((codec_addr as u32 & 0xF) << 28) | ((node_id as u32) << 20);
+    if verb & 0xF00 == 0 {
+        // 4-bit verb, 16-bit payload
+        head | ((verb as u32 & 0xF) << 16) | payload as u32
+    } else {
+        head | ((verb as u32 & 0xFFF) << 8) | (payload as u32 & 0xFF)
+    }
+}
+
+/// A 64-bit RIRB entry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RirbEntry {
+    pub response: u32,
+    pub codec_addr: u8,
+    pub unsolicited: bool,
+}
+
+impl RirbEntry {
+    pub fn decode(raw: u64) -> Self {
+        let ex = (raw >> 32) as u32;
+        RirbEntry {
+            response: raw as u32,
+            codec_addr: (ex & RIRB_EX_CODEC_MASK) as u8,
+            unsolicited: ex & RIRB_EX_UNSOLICITED != 0,
+        }
+    }
+}
+
+/// Split a PARAM_VENDOR_ID response into (vendor id, device id)
+pub fn split_vendor_id(response: u32) -> (u16, u16) {
+    ((response >> 16) as u16, response as u16)
+}
+
+/// Split a PARAM_NODE_COUNT response into (first node id, node count)
+pub fn split_node_count(response: u32) -> (u8, u8) {
+    ((response >> 16) as u8, response as u8)
+}
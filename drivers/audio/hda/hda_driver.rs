@@ -9,19 +9,37 @@
 #![allow(unused_imports)] // Allow unused imports for now
 
 use core::ptr;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
 extern crate alloc;
 use alloc::vec::Vec;
 use driver_framework::{Driver, DriverError, DeviceInfo, DeviceType};
 use driver_framework::mmio::MmioRegion;
 use driver_framework::dma::DmaBuffer;
 use driver_framework::syscalls::{sys_sleep, sys_get_uptime_ms};
-use driver_framework::ipc::ipc_create_port;
+use driver_framework::ipc::{ipc_create_port, ipc_send, IpcMessage, IPC_MSG_REQUEST};
+use driver_framework::interrupts;
+
+mod hda_codec;
+mod hda_graph;
+use hda_codec::*;
+use hda_graph::*;
 
 // HDA PCI IDs
 const HDA_VENDOR_INTEL: u16 = 0x8086;
 const HDA_VENDOR_AMD: u16 = 0x1002;
 const HDA_VENDOR_NVIDIA: u16 = 0x10DE;
 
+// Audio server port, told when a playback segment can be refilled
+const AUDIO_SERVER_PORT: u64 = 105;
+
+/// [stream_id, segment, controller, 0, segment bytes:4, sample rate:4] ->
+/// the segment has been played and may be refilled; reply_port is the
+/// driver's port
+const MSG_AUDIO_SEGMENT_DONE: u64 = 1;
+/// From the audio server: [stream_id, segment, controller] with the PCM
+/// for the segment in the buffer
+const MSG_HDA_FILL_SEGMENT: u64 = 2;
+
 // HDA Register Offsets
 const HDA_REG_GCAP: u32 = 0x00;      // Global Capabilities
 const HDA_REG_VMIN: u32 = 0x02;      // Minor Version
@@ -38,6 +56,24 @@ const HDA_REG_INTCTL: u32 = 0x20;    // Interrupt Control
 const HDA_REG_INTSTS: u32 = 0x24;    // Interrupt Status
 const HDA_REG_WALCLK: u32 = 0x30;    // Wall Clock Counter
 const HDA_REG_SSYNC: u32 = 0x38;     // Stream Synchronization
+const HDA_REG_CORBLBASE: u32 = 0x40; // CORB Lower Base Address
+const HDA_REG_CORBUBASE: u32 = 0x44; // CORB Upper Base Address
+const HDA_REG_CORBWP: u32 = 0x48;    // CORB Write Pointer
+const HDA_REG_CORBRP: u32 = 0x4A;    // CORB Read Pointer
+const HDA_REG_CORBCTL: u32 = 0x4C;   // CORB Control
+const HDA_REG_CORBSIZE: u32 = 0x4E;  // CORB Size
+const HDA_REG_RIRBLBASE: u32 = 0x50; // RIRB Lower Base Address
+const HDA_REG_RIRBUBASE: u32 = 0x54; // RIRB Upper Base Address
+const HDA_REG_RIRBWP: u32 = 0x58;    // RIRB Write Pointer
+const HDA_REG_RINTCNT: u32 = 0x5A;   // Response Interrupt Count
+const HDA_REG_RIRBCTL: u32 = 0x5C;   // RIRB Control
+const HDA_REG_RIRBSTS: u32 = 0x5D;   // RIRB Status
+const HDA_REG_RIRBSIZE: u32 = 0x5E;  // RIRB Size
+
+// Stream descriptors: input streams first, then output, then bidirectional
+const HDA_STREAM_BASE: usize = 0x80;
+const HDA_STREAM_STRIDE: usize = 0x20;
+const HDA_MAX_STREAMS: usize = 30;
 
 // Stream Descriptor Registers (per stream)
 const HDA_SD_CTL: u32 = 0x00;        // Stream Control
@@ -51,17 +87,87 @@ const HDA_SD_FMT: u32 = 0x12;        // Stream Format
 const HDA_SD_BDPL: u32 = 0x18;       // Buffer Descriptor List Pointer Lower
 const HDA_SD_BDPU: u32 = 0x1C;       // Buffer Descriptor List Pointer Upper
 
+// Interrupt Control Bits
+const HDA_INTCTL_GIE: u32 = 1 << 31; // Global Interrupt Enable
+
+// Stream Status Bits (write 1 to clear)
+const HDA_SD_STS_BCIS: u8 = 1 << 2;  // Buffer Completion Interrupt Status
+const HDA_SD_STS_FIFOE: u8 = 1 << 3; // FIFO Error
+const HDA_SD_STS_DESE: u8 = 1 << 4;  // Descriptor Error
+
 // Control Register Bits
 const HDA_GCTL_CRST: u32 = 1 << 0;   // Controller Reset
 const HDA_GCTL_FCNTRL: u32 = 1 << 1; // Flush Control
 const HDA_GCTL_UNSOL: u32 = 1 << 8;  // Accept Unsolicited Response Enable
 
+// CORB/RIRB Bits
+const HDA_CORBRP_RST: u16 = 1 << 15; // CORB Read Pointer Reset
+const HDA_RIRBWP_RST: u16 = 1 << 15; // RIRB Write Pointer Reset
+const HDA_CORBCTL_RUN: u8 = 1 << 1;  // CORB DMA Engine Run
+const HDA_RIRBCTL_DMAEN: u8 = 1 << 1;// RIRB DMA Enable
+const HDA_RIRBSTS_RINTFL: u8 = 1 << 0; // Response Interrupt
+const HDA_RIRBSTS_RIRBOIS: u8 = 1 << 2; // Response Overrun
+const HDA_RING_SIZE_256: u8 = 0x02;  // 256 entries
+
+// CORB holds 256 32-bit commands, RIRB 256 64-bit responses
+const HDA_CORB_ENTRIES: usize = 256;
+const HDA_RIRB_ENTRIES: usize = 256;
+
+// How long a codec gets to answer a verb
+const HDA_VERB_TIMEOUT_MS: u64 = 10;
+
 // Stream Control Bits
 const HDA_SD_CTL_SRST: u32 = 1 << 0; // Stream Reset
 const HDA_SD_CTL_RUN: u32 = 1 << 1;  // Stream Run
 const HDA_SD_CTL_IOCE: u32 = 1 << 2; // Interrupt On Completion Enable
 const HDA_SD_CTL_FEIE: u32 = 1 << 3; // FIFO Error Interrupt Enable
 const HDA_SD_CTL_DEIE: u32 = 1 << 4; // Descriptor Error Interrupt Enable
+const HDA_SD_CTL_STRM_SHIFT: u32 = 20; // Stream Number (tag sent on the link)
+
+// Playback is a two-entry BDL; one half plays while the other is refilled
+const HDA_SEGMENTS: usize = 2;
+
+// BDL entries must start on 128-byte boundaries
+const HDA_BDL_ALIGN: usize = 128;
+
+// State shared with the interrupt handler. Only one controller services
+// interrupts; its registers are published here when playback starts.
+static IRQ_MMIO_BASE: AtomicUsize = AtomicUsize::new(0);
+static IRQ_SEGMENTS_DONE: [AtomicU32; HDA_MAX_STREAMS] = [const { AtomicU32::new(0) }; HDA_MAX_STREAMS];
+static IRQ_STREAM_ERRORS: [AtomicU32; HDA_MAX_STREAMS] = [const { AtomicU32::new(0) }; HDA_MAX_STREAMS];
+
+/// Acknowledge stream interrupts and count finished segments; the driver
+/// loop turns the counts into refill requests
+extern "C" fn hda_irq_handler() -> bool {
+    let base = IRQ_MMIO_BASE.load(Ordering::Acquire);
+    if base == 0 {
+        return false;
+    }
+
+    unsafe {
+        let intsts = ptr::read_volatile((base + HDA_REG_INTSTS as usize) as *const u32);
+        if intsts == 0 {
+            return false; // Raised by another device on the line
+        }
+        for index in 0..HDA_MAX_STREAMS {
+            if intsts & (1 << index) == 0 {
+                continue;
+            }
+
+            let sts_reg = (base + HDA_STREAM_BASE + index * HDA_STREAM_STRIDE + HDA_SD_STS as usize) as *mut u8;
+            let sts = ptr::read_volatile(sts_reg);
+            ptr::write_volatile(sts_reg, sts & (HDA_SD_STS_BCIS | HDA_SD_STS_FIFOE | HDA_SD_STS_DESE));
+
+            if sts & HDA_SD_STS_BCIS != 0 {
+                IRQ_SEGMENTS_DONE[index].fetch_add(1, Ordering::Release);
+            }
+            if sts & (HDA_SD_STS_FIFOE | HDA_SD_STS_DESE) != 0 {
+                IRQ_STREAM_ERRORS[index].fetch_add(1, Ordering::Release);
+            }
+        }
+    }
+    true
+}
 
 // Buffer Descriptor List Entry
 #[repr(C, packed)]
@@ -76,13 +182,36 @@ struct HdaBdlEntry {
 #[derive(Clone)]
 pub struct HdaStream {
     id: u8,
+    index: u8,        // Stream descriptor index (bit in INTCTL/INTSTS)
     base_addr: usize,
     bdl_buffer: Option<DmaBuffer>, // DMA buffer for BDL
     bdl_entries: Vec<HdaBdlEntry>, // Entries in the BDL
     data_buffer: Option<DmaBuffer>, // DMA buffer for audio data
+    segment_size: usize,
+    next_segment: u8, // Segment the controller will finish next
+    underruns: u32,   // Segments finished before they could be refilled
+    sample_rate: u32, // Rate the stream was started at
     running: bool,
 }
 
+impl HdaStream {
+    fn new(id: u8, index: u8, mmio_base: usize) -> Self {
+        HdaStream {
+            id,
+            index,
+            base_addr: mmio_base + HDA_STREAM_BASE + index as usize * HDA_STREAM_STRIDE,
+            bdl_buffer: None,
+            bdl_entries: Vec::new(),
+            data_buffer: None,
+            segment_size: 0,
+            next_segment: 0,
+            underruns: 0,
+            sample_rate: 0,
+            running: false,
+        }
+    }
+}
+
 // HDA Codec
 #[derive(Clone)]
 pub struct HdaCodec {
@@ -90,6 +219,19 @@ pub struct HdaCodec {
     vendor_id: u32,
     device_id: u32,
     revision_id: u32,
+    // Function groups below the root node
+    first_node: u8,
+    node_count: u8,
+}
+
+// Playback route chosen from the widget graph
+#[derive(Clone, Copy)]
+pub struct HdaOutput {
+    codec: u8,
+    afg: u8,            // Audio Function Group node
+    path: OutputPath,   // Pin first, DAC last
+    headphone: bool,
+    eapd: bool,         // Pin has an external amplifier to power up
 }
 
 // HDA Controller
@@ -104,8 +246,14 @@ pub struct HdaController {
     iss: u8,           // Number of Input Streams Supported
     bss: u8,           // Number of Bidirectional Streams Supported
     
+    // Command/response rings
+    corb: Option<DmaBuffer>,
+    rirb: Option<DmaBuffer>,
+    rirb_rp: u16,          // Last RIRB entry consumed
+    
     // Codecs
     codecs: Vec<HdaCodec>,
+    output: Option<HdaOutput>,
     
     // Streams
     output_streams: Vec<HdaStream>,
@@ -132,7 +280,11 @@ impl HdaController {
             oss: 0,
             iss: 0,
             bss: 0,
+            corb: None,
+            rirb: None,
+            rirb_rp: 0,
             codecs: Vec::new(),
+            output: None,
             output_streams: Vec::new(),
             input_streams: Vec::new(),
         })
@@ -146,12 +298,23 @@ impl HdaController {
         // Read capabilities
         self.read_capabilities();
         
+        // Set up the command transport
+        self.init_corb_rirb()?;
+        
         // Enumerate codecs
         self.enumerate_codecs()?;
         
+        // Find where sound can go; a controller with no output path still
+        // initializes, playback just fails
+        self.discover_output_path();
+        
         // Initialize streams
         self.init_streams()?;
         
+        // Without an IRQ nothing tells the audio server to refill, but the
+        // controller itself still works
+        let _ = self.enable_interrupts();
+        
         Ok(())
     }
     
@@ -193,84 +356,417 @@ impl HdaController {
         self.bss = ((gcap >> 3) & 0x1F) as u8;
     }
     
+    /// Allocate the CORB/RIRB rings and start their DMA engines
+    fn init_corb_rirb(&mut self) -> Result<(), &'static str> {
+        // Both engines must be stopped before the rings are reprogrammed
+        self.write_reg8(HDA_REG_CORBCTL, 0);
+        self.write_reg8(HDA_REG_RIRBCTL, 0);
+        for _ in 0..1000 {
+            if self.read_reg8(HDA_REG_CORBCTL) & HDA_CORBCTL_RUN == 0
+                && self.read_reg8(HDA_REG_RIRBCTL) & HDA_RIRBCTL_DMAEN == 0 {
+                break;
+            }
+            sys_sleep(1);
+        }
+        
+        // Rings must be 128-byte aligned
+        let corb = DmaBuffer::alloc(HDA_CORB_ENTRIES * 4, 128).map_err(|_| "Failed to allocate CORB")?;
+        let rirb = DmaBuffer::alloc(HDA_RIRB_ENTRIES * 8, 128).map_err(|_| "Failed to allocate RIRB")?;
+        
+        let corb_phys = corb.phys_addr();
+        self.write_reg32(HDA_REG_CORBLBASE, (corb_phys & 0xFFFFFFFF) as u32);
+        self.write_reg32(HDA_REG_CORBUBASE, (corb_phys >> 32) as u32);
+        self.write_reg8(HDA_REG_CORBSIZE, HDA_RING_SIZE_256);
+        
+        // Reset the read pointer; the bit reads back set once the reset took
+        self.write_reg16(HDA_REG_CORBRP, HDA_CORBRP_RST);
+        for _ in 0..1000 {
+            if self.read_reg16(HDA_REG_CORBRP) & HDA_CORBRP_RST != 0 {
+                break;
+            }
+            sys_sleep(1);
+        }
+        self.write_reg16(HDA_REG_CORBRP, 0);
+        for _ in 0..1000 {
+            if self.read_reg16(HDA_REG_CORBRP) & HDA_CORBRP_RST == 0 {
+                break;
+            }
+            sys_sleep(1);
+        }
+        self.write_reg16(HDA_REG_CORBWP, 0);
+        
+        let rirb_phys = rirb.phys_addr();
+        self.write_reg32(HDA_REG_RIRBLBASE, (rirb_phys & 0xFFFFFFFF) as u32);
+        self.write_reg32(HDA_REG_RIRBUBASE, (rirb_phys >> 32) as u32);
+        self.write_reg8(HDA_REG_RIRBSIZE, HDA_RING_SIZE_256);
+        self.write_reg16(HDA_REG_RIRBWP, HDA_RIRBWP_RST);
+        self.write_reg16(HDA_REG_RINTCNT, 1);
+        self.rirb_rp = 0;
+        
+        self.corb = Some(corb);
+        self.rirb = Some(rirb);
+        
+        // Responses are polled, so no RIRB interrupt
+        self.write_reg8(HDA_REG_CORBCTL, HDA_CORBCTL_RUN);
+        self.write_reg8(HDA_REG_RIRBCTL, HDA_RIRBCTL_DMAEN);
+        
+        Ok(())
+    }
+    
+    /// Post a verb to a codec node and wait for its response
+    pub fn send_verb(&mut self, codec_addr: u8, node_id: u8, verb: u16, payload: u16) -> Result<u32, &'static str> {
+        let command = make_verb(codec_addr, node_id, verb, payload);
+        
+        {
+            let corb = self.corb.as_ref().ok_or("CORB not initialized")?;
+            let wp = (self.read_reg16(HDA_REG_CORBWP) & 0xFF) as usize;
+            let rp = (self.read_reg16(HDA_REG_CORBRP) & 0xFF) as usize;
+            let next = (wp + 1) % HDA_CORB_ENTRIES;
+            if next == rp {
+                return Err("CORB full");
+            }
+            
+            unsafe {
+                ptr::write_volatile((corb.as_mut_ptr() as *mut u32).add(next), command);
+            }
+            self.write_reg16(HDA_REG_CORBWP, next as u16);
+        }
+        
+        let deadline = sys_get_uptime_ms() + HDA_VERB_TIMEOUT_MS;
+        loop {
+            let wp = self.read_reg16(HDA_REG_RIRBWP) & 0xFF;
+            while self.rirb_rp != wp {
+                self.rirb_rp = (self.rirb_rp + 1) % HDA_RIRB_ENTRIES as u16;
+                let rirb = self.rirb.as_ref().ok_or("RIRB not initialized")?;
+                let raw = unsafe {
+                    ptr::read_volatile((rirb.as_mut_ptr() as *const u64).add(self.rirb_rp as usize))
+                };
+                
+                // Unsolicited responses (jack sense) are not answers to us
+                let entry = RirbEntry::decode(raw);
+                if !entry.unsolicited && entry.codec_addr == codec_addr {
+                    self.write_reg8(HDA_REG_RIRBSTS, HDA_RIRBSTS_RINTFL | HDA_RIRBSTS_RIRBOIS);
+                    return Ok(entry.response);
+                }
+            }
+            
+            if sys_get_uptime_ms() >= deadline {
+                return Err("Codec verb timeout");
+            }
+            core::hint::spin_loop();
+        }
+    }
+    
+    /// Read a codec node parameter
+    fn get_parameter(&mut self, codec_addr: u8, node_id: u8, param: u8) -> Result<u32, &'static str> {
+        self.send_verb(codec_addr, node_id, VERB_GET_PARAMETER, param as u16)
+    }
+    
     /// Enumerate codecs
     fn enumerate_codecs(&mut self) -> Result<(), &'static str> {
-        let mmio = self.mmio.as_ref().unwrap();
-        let statests = mmio.read_u16(HDA_REG_STATESTS as usize);
-        
-        for i in 0..15 {
-            if statests & (1 << i) != 0 {
-                // Codec present at address i
-                let codec = HdaCodec {
-                    address: i as u8,
-                    vendor_id: 0, // Read from codec registers
-                    device_id: 0,
-                    revision_id: 0,
+        // Codecs take up to 521us after reset to request enumeration
+        sys_sleep(1);
+        let statests = self.read_reg16(HDA_REG_STATESTS);
+        
+        for address in 0..HDA_MAX_CODECS {
+            if statests & (1 << address) == 0 {
+                continue;
+            }
+            
+            // A codec that does not answer is skipped rather than failing the controller
+            let (vendor_id, device_id) = match self.get_parameter(address, HDA_ROOT_NODE, PARAM_VENDOR_ID) {
+                Ok(response) => split_vendor_id(response),
+                Err(_) => continue,
+            };
+            let revision_id = self.get_parameter(address, HDA_ROOT_NODE, PARAM_REVISION_ID).unwrap_or(0);
+            let (first_node, node_count) = self.get_parameter(address, HDA_ROOT_NODE, PARAM_NODE_COUNT)
+                .map(split_node_count)
+                .unwrap_or((0, 0));
+            
+            self.codecs.push(HdaCodec {
+                address,
+                vendor_id: vendor_id as u32,
+                device_id: device_id as u32,
+                revision_id,
+                first_node,
+                node_count,
+            });
+        }
+        
+        // Acknowledge the state change bits
+        self.write_reg16(HDA_REG_STATESTS, statests);
+        
+        Ok(())
+    }
+    
+    /// Walk each codec's Audio Function Groups and remember the best
+    /// DAC -> pin route
+    fn discover_output_path(&mut self) {
+        let codecs: Vec<(u8, u8, u8)> = self.codecs.iter()
+            .map(|codec| (codec.address, codec.first_node, codec.node_count))
+            .collect();
+        
+        for (address, first, count) in codecs {
+            for afg in first..first.saturating_add(count) {
+                match self.get_parameter(address, afg, PARAM_FUNCTION_GROUP_TYPE) {
+                    Ok(fg_type) if fg_type & 0xFF == FUNCTION_GROUP_AUDIO => {}
+                    _ => continue,
+                }
+                
+                let widgets = match self.read_widgets(address, afg) {
+                    Ok(widgets) => widgets,
+                    Err(_) => continue,
                 };
-                self.codecs.push(codec);
+                
+                if let Some(path) = find_output_path(&widgets) {
+                    let pin = widgets.iter().find(|w| w.nid == path.pin());
+                    let headphone = pin.map_or(false, |w| pin_default_device(w.pin_config) == PIN_DEVICE_HP_OUT);
+                    let eapd = pin.map_or(false, |w| w.pin_caps & PINCAP_EAPD != 0);
+                    self.output = Some(HdaOutput { codec: address, afg, path, headphone, eapd });
+                    return;
+                }
+            }
+        }
+    }
+    
+    /// Read capabilities, connections and pin configuration of every
+    /// widget in a function group
+    fn read_widgets(&mut self, codec: u8, afg: u8) -> Result<Vec<Widget>, &'static str> {
+        let (first, count) = split_node_count(self.get_parameter(codec, afg, PARAM_NODE_COUNT)?);
+        let mut widgets = Vec::new();
+        
+        for nid in first..first.saturating_add(count) {
+            let caps = self.get_parameter(codec, nid, PARAM_AUDIO_WIDGET_CAPS)?;
+            let mut widget = Widget::new(nid, caps);
+            
+            if caps & WCAP_CONN_LIST != 0 {
+                let param = self.get_parameter(codec, nid, PARAM_CONNECTION_LIST_LENGTH)?;
+                let (length, long_form) = connection_list_length(param);
+                let per = entries_per_response(long_form) as usize;
+                
+                let mut responses = [0u32; MAX_CONNECTIONS];
+                let fetch = ((length as usize + per - 1) / per).min(MAX_CONNECTIONS);
+                for (i, response) in responses.iter_mut().take(fetch).enumerate() {
+                    *response = self.send_verb(codec, nid, VERB_GET_CONNECTION_LIST_ENTRY, (i * per) as u16)?;
+                }
+                widget.num_connections = decode_connection_list(&responses[..fetch], length, long_form, &mut widget.connections) as u8;
+            }
+            
+            if widget.kind == WidgetType::PinComplex {
+                widget.pin_caps = self.get_parameter(codec, nid, PARAM_PIN_CAPS)?;
+                widget.pin_config = self.send_verb(codec, nid, VERB_GET_CONFIG_DEFAULT, 0)?;
+                if widget.pin_caps & PINCAP_PRESENCE_DETECT != 0 {
+                    widget.present = self.send_verb(codec, nid, VERB_GET_PIN_SENSE, 0)? & PIN_SENSE_PRESENCE != 0;
+                }
+            }
+            
+            widgets.push(widget);
+        }
+        
+        Ok(widgets)
+    }
+    
+    /// 0 dB gain step of a widget's amplifier; widgets without their own
+    /// amp capabilities inherit the function group's
+    fn amp_offset(&mut self, codec: u8, afg: u8, nid: u8, param: u8) -> Result<u16, &'static str> {
+        let mut caps = self.get_parameter(codec, nid, param)?;
+        if caps == 0 {
+            caps = self.get_parameter(codec, afg, param)?;
+        }
+        Ok((caps & AMP_CAP_OFFSET_MASK) as u16)
+    }
+    
+    /// Power up and unmute the output path, and point the DAC at a stream
+    fn configure_output_path(&mut self, stream_tag: u8, format: u16) -> Result<(), &'static str> {
+        let output = self.output.ok_or("No output path")?;
+        let codec = output.codec;
+        let path = output.path;
+        
+        self.send_verb(codec, output.afg, VERB_SET_POWER_STATE, POWER_STATE_D0)?;
+        
+        for (i, &nid) in path.nodes().iter().enumerate() {
+            self.send_verb(codec, nid, VERB_SET_POWER_STATE, POWER_STATE_D0)?;
+            let caps = self.get_parameter(codec, nid, PARAM_AUDIO_WIDGET_CAPS)?;
+            let upstream = i + 1 < path.len;
+            
+            // Mixers sum every input; everything else picks one
+            if upstream && WidgetType::from_caps(caps) != WidgetType::Mixer {
+                self.send_verb(codec, nid, VERB_SET_CONNECTION_SELECT, path.select[i] as u16)?;
+            }
+            
+            if caps & WCAP_OUT_AMP != 0 {
+                let gain = self.amp_offset(codec, output.afg, nid, PARAM_OUT_AMP_CAPS)?;
+                self.send_verb(codec, nid, VERB_SET_AMP_GAIN_MUTE,
+                    AMP_SET_OUTPUT | AMP_SET_LEFT | AMP_SET_RIGHT | gain)?;
             }
+            if caps & WCAP_IN_AMP != 0 && upstream {
+                let gain = self.amp_offset(codec, output.afg, nid, PARAM_IN_AMP_CAPS)?;
+                let index = (path.select[i] as u16) << AMP_SET_INDEX_SHIFT;
+                self.send_verb(codec, nid, VERB_SET_AMP_GAIN_MUTE,
+                    AMP_SET_INPUT | AMP_SET_LEFT | AMP_SET_RIGHT | index | gain)?;
+            }
+        }
+        
+        let mut pin_ctl = PIN_CTL_OUT_EN;
+        if output.headphone {
+            pin_ctl |= PIN_CTL_HP_EN;
+        }
+        self.send_verb(codec, path.pin(), VERB_SET_PIN_WIDGET_CONTROL, pin_ctl)?;
+        if output.eapd {
+            self.send_verb(codec, path.pin(), VERB_SET_EAPD_BTL, EAPD_ENABLE)?;
         }
         
+        // Channel 0 of the stream; the format must match the descriptor's
+        self.send_verb(codec, path.dac(), VERB_SET_CONVERTER_STREAM, (stream_tag as u16) << 4)?;
+        self.send_verb(codec, path.dac(), VERB_SET_STREAM_FORMAT, format)?;
+        
         Ok(())
     }
     
     /// Initialize streams
     fn init_streams(&mut self) -> Result<(), &'static str> {
-        // Initialize output streams
+        let mmio_base = self.mmio.as_ref().ok_or("MMIO not mapped")?.base_virt_addr();
+        
+        // Input stream descriptors come first
+        for i in 0..self.iss {
+            self.input_streams.push(HdaStream::new(i, i, mmio_base));
+        }
+        
+        // Output streams follow the input streams
         for i in 0..self.oss {
-            let stream = HdaStream {
-                id: i,
-                base_addr: self.mmio.as_ref().unwrap().base_virt_addr() + 0x80 + (i as usize * 0x20),
-                bdl_buffer: None,
-                bdl_entries: Vec::new(),
-                data_buffer: None,
-                running: false,
-            };
-            self.output_streams.push(stream);
+            self.output_streams.push(HdaStream::new(i, self.iss + i, mmio_base));
         }
         
-        // Initialize input streams
-        for i in 0..self.iss {
-            let stream = HdaStream {
-                id: i,
-                base_addr: self.mmio.as_ref().unwrap().base_virt_addr() + 0x80 + ((self.oss + i) as usize * 0x20),
-                bdl_buffer: None,
-                bdl_entries: Vec::new(),
-                data_buffer: None,
-                running: false,
-            };
-            self.input_streams.push(stream);
+        Ok(())
+    }
+    
+    /// Hook up the stream interrupt handler
+    fn enable_interrupts(&mut self) -> Result<(), &'static str> {
+        let mmio_base = self.mmio.as_ref().ok_or("MMIO not mapped")?.base_virt_addr();
+        IRQ_MMIO_BASE.store(mmio_base, Ordering::Release);
+        
+        if self.irq == 0 || self.irq == 0xFF {
+            return Err("No IRQ assigned");
         }
+        interrupts::register_irq_shared(self.irq, hda_irq_handler).map_err(|_| "Failed to register IRQ")?;
+        interrupts::enable_irq(self.irq).map_err(|_| "Failed to enable IRQ")?;
         
+        let intctl = self.read_reg32(HDA_REG_INTCTL);
+        self.write_reg32(HDA_REG_INTCTL, intctl | HDA_INTCTL_GIE);
         Ok(())
     }
     
+    /// Turn finished segments into refill requests to the audio server.
+    /// Called from the driver loop, not the interrupt handler.
+    pub fn service_interrupts(&mut self, controller: u8, reply_port: u64) {
+        for stream in self.output_streams.iter_mut().filter(|s| s.running) {
+            let index = stream.index as usize;
+            let done = IRQ_SEGMENTS_DONE[index].swap(0, Ordering::Acquire);
+            if IRQ_STREAM_ERRORS[index].swap(0, Ordering::Acquire) != 0 {
+                stream.underruns += 1;
+            }
+            
+            // More than one segment finished since the last pass means the
+            // controller already played a segment nobody refilled
+            if done as usize >= HDA_SEGMENTS {
+                stream.underruns += done - 1;
+            }
+            
+            for _ in 0..done.min(HDA_SEGMENTS as u32) {
+                let segment = stream.next_segment;
+                stream.next_segment = (stream.next_segment + 1) % HDA_SEGMENTS as u8;
+                
+                let mut msg = IpcMessage::new();
+                msg.msg_type = IPC_MSG_REQUEST;
+                msg.msg_id = MSG_AUDIO_SEGMENT_DONE;
+                let mut data = [stream.id, segment, controller, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+                data[4..8].copy_from_slice(&(stream.segment_size as u32).to_le_bytes());
+                data[8..12].copy_from_slice(&stream.sample_rate.to_le_bytes());
+                msg.set_inline_data(&data);
+                msg.reply_port = reply_port;
+                let _ = ipc_send(AUDIO_SERVER_PORT, &msg);
+            }
+        }
+    }
+    
+    /// Copy audio into one half of a running stream's buffer. Anything the
+    /// data does not cover is filled with silence so stale audio is never
+    /// replayed. Returns the number of bytes taken.
+    pub fn fill_segment(&mut self, stream_id: u8, segment: u8, data: &[u8]) -> Result<usize, &'static str> {
+        if stream_id >= self.oss {
+            return Err("Invalid stream ID");
+        }
+        if segment as usize >= HDA_SEGMENTS {
+            return Err("Invalid segment");
+        }
+        
+        let stream = &self.output_streams[stream_id as usize];
+        let buffer = stream.data_buffer.as_ref().ok_or("Stream has no buffer")?;
+        let len = data.len().min(stream.segment_size);
+        
+        unsafe {
+            let dest = buffer.as_mut_ptr().add(segment as usize * stream.segment_size);
+            ptr::copy_nonoverlapping(data.as_ptr(), dest, len);
+            ptr::write_bytes(dest.add(len), 0, stream.segment_size - len);
+        }
+        
+        Ok(len)
+    }
+    
+    /// Segments that finished before they were refilled
+    pub fn underruns(&self, stream_id: u8) -> u32 {
+        self.output_streams.get(stream_id as usize).map_or(0, |s| s.underruns)
+    }
+    
     /// Start playback stream
     pub fn start_playback(&mut self, stream_id: u8, buffer: DmaBuffer, sample_rate: u32, channels: u8) -> Result<(), &'static str> {
         if stream_id >= self.oss {
             return Err("Invalid stream ID");
         }
         
-        let stream = &mut self.output_streams[stream_id as usize];
+        // Stream tag 0 is reserved
+        let stream_tag = stream_id + 1;
+        let format = self.encode_format(sample_rate, channels, 16);
+        
+        // Route the codec to this stream before the DMA engine starts
+        self.configure_output_path(stream_tag, format)?;
+        
+        // Work on a copy so the register helpers can borrow the controller
+        let mut stream = self.output_streams[stream_id as usize].clone();
         
         // Setup buffer descriptor list
-        self.setup_bdl(stream, buffer)?;
+        self.setup_bdl(&mut stream, buffer)?;
         
         // Set stream format
-        let format = self.encode_format(sample_rate, channels, 16);
-        self.write_stream_reg16(stream, HDA_SD_FMT, format);
+        self.write_stream_reg16(&stream, HDA_SD_FMT, format);
         
-        // Set cyclic buffer length
-        self.write_stream_reg32(stream, HDA_SD_CBL, stream.data_buffer.as_ref().unwrap().size() as u32);
+        // Set cyclic buffer length (both segments)
+        self.write_stream_reg32(&stream, HDA_SD_CBL, (stream.segment_size * HDA_SEGMENTS) as u32);
         
         // Set last valid index
-        self.write_stream_reg16(stream, HDA_SD_LVI, (stream.bdl_entries.len() - 1) as u16);
+        self.write_stream_reg16(&stream, HDA_SD_LVI, (stream.bdl_entries.len() - 1) as u16);
+        
+        // Forget completions left over from an earlier run; the status
+        // bits clear when written back as 1
+        let sts = self.read_stream_reg8(&stream, HDA_SD_STS);
+        self.write_stream_reg8(&stream, HDA_SD_STS, sts);
+        IRQ_SEGMENTS_DONE[stream.index as usize].store(0, Ordering::Release);
+        IRQ_STREAM_ERRORS[stream.index as usize].store(0, Ordering::Release);
+        stream.next_segment = 0;
+        stream.underruns = 0;
+        stream.sample_rate = sample_rate;
+        
+        // Unmask this stream's interrupt
+        let intctl = self.read_reg32(HDA_REG_INTCTL);
+        self.write_reg32(HDA_REG_INTCTL, intctl | (1 << stream.index));
         
         // Enable interrupts and start stream
-        let ctl = HDA_SD_CTL_RUN | HDA_SD_CTL_IOCE | HDA_SD_CTL_FEIE | HDA_SD_CTL_DEIE;
-        self.write_stream_reg32(stream, HDA_SD_CTL, ctl);
+        let ctl = ((stream_tag as u32) << HDA_SD_CTL_STRM_SHIFT)
+            | HDA_SD_CTL_RUN | HDA_SD_CTL_IOCE | HDA_SD_CTL_FEIE | HDA_SD_CTL_DEIE;
+        self.write_stream_reg32(&stream, HDA_SD_CTL, ctl);
         
         stream.running = true;
+        self.output_streams[stream_id as usize] = stream;
         
         Ok(())
     }
@@ -281,35 +777,49 @@ impl HdaController {
             return Err("Invalid stream ID");
         }
         
-        let stream = &mut self.output_streams[stream_id as usize];
+        let index = self.output_streams[stream_id as usize].index;
         
         // Stop stream
-        self.write_stream_reg32(stream, HDA_SD_CTL, 0);
+        self.write_stream_reg32(&self.output_streams[stream_id as usize], HDA_SD_CTL, 0);
+        
+        // Mask its interrupt
+        let intctl = self.read_reg32(HDA_REG_INTCTL);
+        self.write_reg32(HDA_REG_INTCTL, intctl & !(1 << index));
         
-        stream.running = false;
+        self.output_streams[stream_id as usize].running = false;
         
         Ok(())
     }
     
-    /// Setup buffer descriptor list
+    /// Setup a ping-pong buffer descriptor list: the data buffer is split
+    /// into two segments that each interrupt on completion
     fn setup_bdl(&self, stream: &mut HdaStream, data_buffer: DmaBuffer) -> Result<(), &'static str> {
-        // HDA BDLs require 128-byte alignment
-        let bdl_buffer = DmaBuffer::alloc(core::mem::size_of::<HdaBdlEntry>() * 2, 128).map_err(|_| "Failed to allocate BDL buffer")?;
-        
-        let entry = HdaBdlEntry {
-            address: data_buffer.phys_addr(),
-            length: data_buffer.size() as u32,
-            ioc: 1,  // Interrupt on completion
-        };
+        let segment_size = (data_buffer.size() / HDA_SEGMENTS) & !(HDA_BDL_ALIGN - 1);
+        if segment_size == 0 {
+            return Err("Playback buffer too small");
+        }
         
-        unsafe {
-            let bdl_ptr = bdl_buffer.as_mut_ptr() as *mut HdaBdlEntry;
-            ptr::write_volatile(bdl_ptr, entry);
+        // HDA BDLs require 128-byte alignment
+        let bdl_buffer = DmaBuffer::alloc(core::mem::size_of::<HdaBdlEntry>() * HDA_SEGMENTS, HDA_BDL_ALIGN).map_err(|_| "Failed to allocate BDL buffer")?;
+        
+        stream.bdl_entries.clear();
+        for segment in 0..HDA_SEGMENTS {
+            let entry = HdaBdlEntry {
+                address: data_buffer.phys_addr() + (segment * segment_size) as u64,
+                length: segment_size as u32,
+                ioc: 1,  // Interrupt on completion
+            };
+            
+            unsafe {
+                let bdl_ptr = bdl_buffer.as_mut_ptr() as *mut HdaBdlEntry;
+                ptr::write_volatile(bdl_ptr.add(segment), entry);
+            }
+            stream.bdl_entries.push(entry);
         }
         
+        stream.segment_size = segment_size;
         stream.bdl_buffer = Some(bdl_buffer.clone());
         stream.data_buffer = Some(data_buffer);
-        stream.bdl_entries.push(entry);
         
         // Write BDL pointer to stream descriptor
         let bdl_phys_addr = bdl_buffer.phys_addr();
@@ -378,6 +888,18 @@ impl HdaController {
         mmio.write_u16(offset as usize, value)
     }
     
+    /// Read 8-bit register
+    fn read_reg8(&self, offset: u32) -> u8 {
+        let mmio = self.mmio.as_ref().unwrap();
+        mmio.read_u8(offset as usize)
+    }
+    
+    /// Write 8-bit register
+    fn write_reg8(&self, offset: u32, value: u8) {
+        let mmio = self.mmio.as_ref().unwrap();
+        mmio.write_u8(offset as usize, value)
+    }
+    
     /// Read stream register (32-bit)
     fn read_stream_reg32(&self, stream: &HdaStream, offset: u32) -> u32 {
         let mmio = self.mmio.as_ref().unwrap();
@@ -395,6 +917,18 @@ impl HdaController {
         let mmio = self.mmio.as_ref().unwrap();
         mmio.write_u16((stream.base_addr - mmio.base_virt_addr()) + offset as usize, value)
     }
+    
+    /// Read stream register (8-bit)
+    fn read_stream_reg8(&self, stream: &HdaStream, offset: u32) -> u8 {
+        let mmio = self.mmio.as_ref().unwrap();
+        mmio.read_u8((stream.base_addr - mmio.base_virt_addr()) + offset as usize)
+    }
+    
+    /// Write stream register (8-bit)
+    fn write_stream_reg8(&self, stream: &HdaStream, offset: u32, value: u8) {
+        let mmio = self.mmio.as_ref().unwrap();
+        mmio.write_u8((stream.base_addr - mmio.base_virt_addr()) + offset as usize, value)
+    }
 }
 
 // Driver entry point
@@ -412,6 +946,26 @@ impl HdaDriver {
             controllers: Vec::new(),
         }
     }
+    
+    /// Forward finished playback segments of every controller
+    pub fn service_interrupts(&mut self) {
+        let port = self.device_port;
+        for (index, controller) in self.controllers.iter_mut().enumerate() {
+            controller.service_interrupts(index as u8, port);
+        }
+    }
+    
+    /// Handle a message from the driver port
+    pub fn handle_message(&mut self, msg: &IpcMessage) {
+        if msg.msg_id != MSG_HDA_FILL_SEGMENT || msg.inline_size < 3 || msg.buffer.is_null() {
+            return;
+        }
+        let (stream_id, segment, controller) = (msg.inline_data[0], msg.inline_data[1], msg.inline_data[2]);
+        if let Some(controller) = self.controllers.get_mut(controller as usize) {
+            let data = unsafe { core::slice::from_raw_parts(msg.buffer, msg.buffer_size) };
+            let _ = controller.fill_segment(stream_id, segment, data);
+        }
+    }
 }
 
 impl Driver for HdaDriver {
@@ -435,12 +989,9 @@ impl Driver for HdaDriver {
         self.initialized = true;
         Ok(())
     }
-    fn start(&mut self) -> Result<(), DriverError> {
-        // A controller is created for each probed device.
-        // This is called by the framework after probe returns true.
-        // Assuming device_info is passed here from the framework.
-        let device_info = self.pci_device_info; // This needs to be stored or passed by framework
-        let mut controller = HdaController::new(device_info)?;
+    fn start(&mut self, device_info: &DeviceInfo) -> Result<(), DriverError> {
+        // A controller is created for each probed device
+        let mut controller = HdaController::new(*device_info)?;
         controller.init().map_err(|_| DriverError::InitFailed)?;
         self.controllers.push(controller);
         Ok(())
@@ -449,6 +1000,7 @@ impl Driver for HdaDriver {
         self.controllers.clear();
         Ok(())
     }
+    fn version(&self) -> &'static str { "0.1.0" }
 }
 
 #[no_mangle]
@@ -0,0 +1,271 @@
+//! HDA widget graph
+//!
+//! Widgets under the Audio Function Group link to each other through
+//! connection lists, pointing from a sink back to its possible sources.
+//! Playback needs a path from an output pin back to a DAC (Audio Output
+//! widget), going through at most a few mixers or selectors.
+
+/// Connections kept per widget
+pub const MAX_CONNECTIONS: usize = 16;
+
+/// Longest pin -> DAC path considered
+pub const MAX_PATH_LEN: usize = 6;
+
+// Audio widget capabilities
+const WCAP_TYPE_SHIFT: u32 = 20;
+const WCAP_TYPE_MASK: u32 = 0xF;
+pub const WCAP_IN_AMP: u32 = 1 << 1;
+pub const WCAP_OUT_AMP: u32 = 1 << 2;
+pub const WCAP_CONN_LIST: u32 = 1 << 8;
+
+// Connection list length parameter
+const CONN_LIST_LONG_FORM: u32 = 1 << 7;
+const CONN_LIST_LENGTH_MASK: u32 = 0x7F;
+
+// Pin capabilities
+pub const PINCAP_PRESENCE_DETECT: u32 = 1 << 2;
+pub const PINCAP_OUTPUT: u32 = 1 << 4;
+
+// Pin configuration default
+const PIN_CONN_SHIFT: u32 = 30;
+const PIN_CONN_JACK: u32 = 0;
+const PIN_CONN_NONE: u32 = 1;
+const PIN_DEVICE_SHIFT: u32 = 20;
+const PIN_DEVICE_MASK: u32 = 0xF;
+pub const PIN_DEVICE_LINE_OUT: u32 = 0x0;
+pub const PIN_DEVICE_SPEAKER: u32 = 0x1;
+pub const PIN_DEVICE_HP_OUT: u32 = 0x2;
+
+/// Pin sense: something is plugged in
+pub const PIN_SENSE_PRESENCE: u32 = 1 << 31;
+
+/// Widget type from the capabilities parameter
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WidgetType {
+    AudioOutput,
+    AudioInput,
+    Mixer,
+    Selector,
+    PinComplex,
+    Other,
+}
+
+impl WidgetType {
+    pub fn from_caps(caps: u32) -> Self {
+        match (caps >> WCAP_TYPE_SHIFT) & WCAP_TYPE_MASK {
+            0x0 => WidgetType::AudioOutput,
+            0x1 => WidgetType::AudioInput,
+            0x2 => WidgetType::Mixer,
+            0x3 => WidgetType::Selector,
+            0x4 => WidgetType::PinComplex,
+            _ => WidgetType::Other,
+        }
+    }
+}
+
+/// One widget and its connections
+#[derive(Clone, Copy, Debug)]
+pub struct Widget {
+    pub nid: u8,
+    pub kind: WidgetType,
+    pub caps: u32,
+    pub connections: [u8; MAX_CONNECTIONS],
+    pub num_connections: u8,
+    // Pin complexes only
+    pub pin_caps: u32,
+    pub pin_config: u32,
+    pub present: bool,
+}
+
+impl Widget {
+    pub fn new(nid: u8, caps: u32) -> Self {
+        Widget {
+            nid,
+            kind: WidgetType::from_caps(caps),
+            caps,
+            connections: [0; MAX_CONNECTIONS],
+            num_connections: 0,
+            pin_caps: 0,
+            pin_config: 0,
+            present: false,
+        }
+    }
+
+    pub fn connections(&self) -> &[u8] {
+        &self.connections[..self.num_connections as usize]
+    }
+}
+
+/// Split the connection list length parameter into (entries, long form)
+pub fn connection_list_length(param: u32) -> (u8, bool) {
+    ((param & CONN_LIST_LENGTH_MASK) as u8, param & CONN_LIST_LONG_FORM != 0)
+}
+
+/// Entries carried per connection list response
+pub fn entries_per_response(long_form: bool) -> u8 {
+    if long_form { 2 } else { 4 }
+}
+
+/// Decode connection list responses (fetched at indices 0, n, 2n, ...)
+/// into node ids, expanding ranges. Returns the number written.
+pub fn decode_connection_list(responses: &[u32], length: u8, long_form: bool, out: &mut [u8]) -> usize {
+    let per = entries_per_response(long_form) as usize;
+    let (bits, range_flag) = if long_form { (16, 1u32 << 15) } else { (8, 1u32 << 7) };
+    let mask = range_flag - 1;
+
+    let mut count = 0;
+    let mut prev: Option<u32> = None;
+    for i in 0..length as usize {
+        let response = match responses.get(i / per) {
+            Some(&r) => r,
+            None => break,
+        };
+        let entry = (response >> ((i % per) * bits)) & ((1u32 << bits) - 1);
+        let nid = entry & mask;
+
+        // A range entry covers everything after the previous entry up to nid
+        let first = match prev {
+            Some(p) if entry & range_flag != 0 && p < nid => p + 1,
+            _ => nid,
+        };
+        for id in first..=nid {
+            if count == out.len() {
+                return count;
+            }
+            out[count] = id as u8;
+            count += 1;
+        }
+        prev = Some(nid);
+    }
+    count
+}
+
+/// Default device (line out, speaker, ...) from a pin configuration
+pub fn pin_default_device(config: u32) -> u32 {
+    (config >> PIN_DEVICE_SHIFT) & PIN_DEVICE_MASK
+}
+
+/// Preference of an output pin, lower is better. None if the pin cannot
+/// be used for playback.
+pub fn pin_output_rank(pin_caps: u32, config: u32, present: bool) -> Option<u8> {
+    if pin_caps & PINCAP_OUTPUT == 0 {
+        return None;
+    }
+
+    let connectivity = config >> PIN_CONN_SHIFT;
+    if connectivity == PIN_CONN_NONE {
+        return None;
+    }
+
+    let rank = match pin_default_device(config) {
+        PIN_DEVICE_SPEAKER => 0,
+        PIN_DEVICE_LINE_OUT => 1,
+        PIN_DEVICE_HP_OUT => 2,
+        _ => return None,
+    };
+
+    // An empty jack only beats nothing at all
+    if connectivity == PIN_CONN_JACK && !present {
+        Some(rank + 3)
+    } else {
+        Some(rank)
+    }
+}
+
+/// Route from an output pin back to a DAC. `select[i]` is the connection
+/// index node `nodes[i]` must select to reach `nodes[i + 1]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutputPath {
+    pub nodes: [u8; MAX_PATH_LEN],
+    pub select: [u8; MAX_PATH_LEN],
+    pub len: usize,
+}
+
+impl OutputPath {
+    pub fn pin(&self) -> u8 {
+        self.nodes[0]
+    }
+
+    pub fn dac(&self) -> u8 {
+        self.nodes[self.len - 1]
+    }
+
+    pub fn nodes(&self) -> &[u8] {
+        &self.nodes[..self.len]
+    }
+}
+
+fn find_widget(widgets: &[Widget], nid: u8) -> Option<&Widget> {
+    widgets.iter().find(|w| w.nid == nid)
+}
+
+/// Depth-first search from `path.nodes[path.len - 1]` towards a DAC
+fn extend_path(widgets: &[Widget], path: &mut OutputPath) -> bool {
+    let current = match find_widget(widgets, path.nodes[path.len - 1]) {
+        Some(w) => *w,
+        None => return false,
+    };
+    if current.kind == WidgetType::AudioOutput {
+        return true;
+    }
+    if path.len == MAX_PATH_LEN {
+        return false;
+    }
+
+    for (index, &source) in current.connections().iter().enumerate() {
+        if path.nodes().contains(&source) {
+            continue;
+        }
+        let usable = match find_widget(widgets, source) {
+            Some(w) => matches!(w.kind, WidgetType::AudioOutput | WidgetType::Mixer | WidgetType::Selector),
+            None => false,
+        };
+        if !usable {
+            continue;
+        }
+
+        path.select[path.len - 1] = index as u8;
+        path.nodes[path.len] = source;
+        path.len += 1;
+        if extend_path(widgets, path) {
+            return true;
+        }
+        path.len -= 1;
+    }
+    false
+}
+
+/// Path from the given pin to a DAC, if there is one
+pub fn path_from_pin(widgets: &[Widget], pin: u8) -> Option<OutputPath> {
+    let mut path = OutputPath {
+        nodes: [0; MAX_PATH_LEN],
+        select: [0; MAX_PATH_LEN],
+        len: 1,
+    };
+    path.nodes[0] = pin;
+    if extend_path(widgets, &mut path) && path.len > 1 {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// Best playback path: the highest ranked output pin that reaches a DAC
+pub fn find_output_path(widgets: &[Widget]) -> Option<OutputPath> {
+    let mut best: Option<(u8, OutputPath)> = None;
+
+    for pin in widgets.iter().filter(|w| w.kind == WidgetType::PinComplex) {
+        let rank = match pin_output_rank(pin.pin_caps, pin.pin_config, pin.present) {
+            Some(rank) => rank,
+            None => continue,
+        };
+        if best.map_or(false, |(best_rank, _)| best_rank <= rank) {
+            continue;
+        }
+        if let Some(path) = path_from_pin(widgets, pin.nid) {
+            best = Some((rank, path));
+        }
+    }
+
+    best.map(|(_, path)| path)
+}
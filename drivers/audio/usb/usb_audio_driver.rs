@@ -13,9 +13,12 @@ extern crate alloc;
 use alloc::vec::Vec;
 use alloc::string::String;
 use driver_framework::{Driver, DriverError, DeviceInfo, DeviceType};
-use driver_framework::usb::{UsbControlRequest, UsbDeviceHandle, UsbEndpointType, UsbTransferType, UsbDirection};
 use driver_framework::syscalls::{sys_sleep, sys_get_uptime_ms};
 use driver_framework::ipc::ipc_create_port;
+use usb_common::*;
+
+mod usb_audio_pacing;
+use usb_audio_pacing::{intervals_per_second, PacketPacer};
 
 // USB Audio Class Codes
 const USB_CLASS_AUDIO: u8 = 0x01;
@@ -57,6 +60,22 @@ const TERMINAL_SPEAKER: u16 = 0x0301;
 const TERMINAL_HEADPHONES: u16 = 0x0302;
 const TERMINAL_MICROPHONE: u16 = 0x0201;
 
+// Class-specific requests
+const UAC_SET_CUR: u8 = 0x01;
+
+// Feature unit control selectors
+const FU_MUTE_CONTROL: u8 = 0x01;
+const FU_VOLUME_CONTROL: u8 = 0x02;
+
+// Endpoint control selectors
+const EP_SAMPLING_FREQ_CONTROL: u8 = 0x01;
+
+/// Isochronous, adaptive synchronisation
+const EP_ATTR_ISOCH_ADAPTIVE: u8 = USB_EP_TYPE_ISOCHRONOUS | (0x2 << 2);
+
+/// Feature unit volume range used for the 0-100 scale, in 1/256 dB
+const VOLUME_MIN_DB256: i32 = -64 * 256;
+
 // USB Audio Terminal
 #[derive(Clone)]
 pub struct UsbAudioTerminal {
@@ -92,14 +111,17 @@ pub struct UsbAudioStream {
     alt_setting: u8,
     endpoint_addr: u8,
     max_packet_size: u16,
+    interval: u8,
     format: UsbAudioFormat,
+    pacer: Option<PacketPacer>,
     running: bool,
 }
 
 // USB Audio Device
 #[derive(Clone)]
 pub struct UsbAudioDevice {
-    usb_device_handle: UsbDeviceHandle,  // USB device handle
+    slot_id: u8,       // XHCI slot of the device
+    high_speed: bool,  // Service intervals count microframes
     
     // Audio Control Interface
     control_interface_num: u8,
@@ -118,9 +140,10 @@ pub struct UsbAudioDevice {
 
 impl UsbAudioDevice {
     /// Create new USB audio device
-    pub fn new(usb_device_handle: UsbDeviceHandle) -> Self {
+    pub fn new(slot_id: u8, high_speed: bool) -> Self {
         UsbAudioDevice {
-            usb_device_handle,
+            slot_id,
+            high_speed,
             control_interface_num: 0,
             input_terminals: Vec::new(),
             output_terminals: Vec::new(),
@@ -154,7 +177,7 @@ impl UsbAudioDevice {
         self.feature_units.push(UsbAudioFeatureUnit {
             unit_id: 1,
             source_id: 0,
-            controls: vec![0x01, 0x02], // Master volume, Mute
+            controls: vec![FU_MUTE_CONTROL, FU_VOLUME_CONTROL],
         });
         
         Ok(())
@@ -167,7 +190,8 @@ impl UsbAudioDevice {
             interface_num: 1, // Assuming interface 1 is AS
             alt_setting: 1,
             endpoint_addr: 0x01, // EP1 OUT
-            max_packet_size: 192, // Example
+            max_packet_size: 192, // 48 frames of 16-bit stereo
+            interval: 1,
             format: UsbAudioFormat {
                 format_type: FORMAT_TYPE_I,
                 nr_channels: 2,
@@ -175,103 +199,135 @@ impl UsbAudioDevice {
                 bit_resolution: 16,
                 sample_rates: vec![44100, 48000],
             },
+            pacer: None,
             running: false,
         });
         Ok(())
     }
     
     /// Start playback
-    pub fn start_playback(&mut self, sample_rate: u32, channels: u8, bits: u8) -> Result<(), &'static str> {
+    pub fn start_playback(&mut self, host: &mut dyn UsbHost, sample_rate: u32, channels: u8, bits: u8) -> Result<(), &'static str> {
         // Find compatible stream
         let stream_idx = self.find_playback_stream(sample_rate, channels, bits)?;
-        
-        let stream = &mut self.playback_streams[stream_idx];
-        
+        let stream = self.playback_streams[stream_idx].clone();
+
+        // Every packet has to fit the endpoint
+        let pacer = PacketPacer::new(
+            sample_rate,
+            stream.format.nr_channels,
+            stream.format.subframe_size,
+            intervals_per_second(stream.interval, self.high_speed),
+        );
+        if pacer.max_packet_bytes() > stream.max_packet_size as usize {
+            return Err("Sample rate exceeds endpoint bandwidth");
+        }
+
         // Set alternate setting
-        self.set_interface(stream.interface_num, stream.alt_setting)?;
-        
+        self.set_interface(host, stream.interface_num, stream.alt_setting)?;
+
         // Configure endpoint
-        self.configure_endpoint(stream)?;
-        
+        self.configure_endpoint(host, &stream)?;
+        self.set_sample_rate(host, &stream, sample_rate)?;
+
+        let stream = &mut self.playback_streams[stream_idx];
+        stream.pacer = Some(pacer);
         stream.running = true;
         self.active_playback = Some(stream_idx);
-        
+
         Ok(())
     }
-    
+
     /// Stop playback
-    pub fn stop_playback(&mut self) -> Result<(), &'static str> {
+    pub fn stop_playback(&mut self, host: &mut dyn UsbHost) -> Result<(), &'static str> {
         if let Some(idx) = self.active_playback {
-            let stream = &mut self.playback_streams[idx];
-            
+            let interface_num = self.playback_streams[idx].interface_num;
+
             // Set alternate setting 0 (no streaming)
-            self.set_interface(stream.interface_num, 0)?;
-            
+            self.set_interface(host, interface_num, 0)?;
+
+            let stream = &mut self.playback_streams[idx];
+            stream.pacer = None;
             stream.running = false;
             self.active_playback = None;
         }
-        
+
         Ok(())
     }
-    
-    /// Write audio data
-    pub fn write_data(&mut self, data: &[u8]) -> Result<usize, &'static str> {
-        if let Some(idx) = self.active_playback {
-            let stream = &self.playback_streams[idx];
-            
-            // Implement sending data via USB isochronous transfer
-            // This is a placeholder as full USB stack management is complex.
-            // In a real implementation, data would be buffered and sent via URBs.
-            // For now, assume it's sent.
-            
-            self.usb_device_handle.send_isochronous_data(stream.endpoint_addr, data)?; // Mock call
-            
-            Ok(data.len())
-        } else {
-            Err("No active playback stream")
+
+    /// Write audio data. Queues as many whole packets as the transfer ring
+    /// has room for and returns the bytes consumed; call again with the
+    /// rest once earlier packets complete.
+    pub fn write_data(&mut self, host: &mut dyn UsbHost, data: &[u8]) -> Result<usize, &'static str> {
+        let idx = self.active_playback.ok_or("No active playback stream")?;
+        let slot_id = self.slot_id;
+        let stream = &mut self.playback_streams[idx];
+        let pacer = stream.pacer.as_mut().ok_or("Playback stream not configured")?;
+
+        let mut offset = 0;
+        loop {
+            let len = pacer.packet_bytes();
+            if data.len() - offset < len {
+                break;
+            }
+
+            let queued = host
+                .submit_isoch_out(slot_id, stream.endpoint_addr, &data[offset..offset + len])
+                .map_err(|_| "Isochronous transfer failed")?;
+            if !queued {
+                break;
+            }
+
+            pacer.commit();
+            offset += len;
         }
+
+        Ok(offset)
     }
-    
-    /// Set volume
-    pub fn set_volume(&mut self, volume: u8) -> Result<(), &'static str> {
-        // Find feature unit with volume control
-        for unit in &self.feature_units {
-            // Simulate sending SET_CUR request to feature unit
-            self.usb_device_handle.control_transfer(
-                UsbControlRequest::new(
-                    UsbDirection::Out, UsbTransferType::Class, UsbEndpointType::Interface,
-                    0x01, // SET_CUR
-                    0x00, // Volume Control
-                    unit.unit_id as u16,
-                    self.control_interface_num as u16,
-                    &[volume], 1
-                )
-            )?;
-            return Ok(());
-        }
-        Err("No feature unit with volume control found")
+
+    /// Set volume (0-100)
+    pub fn set_volume(&mut self, host: &mut dyn UsbHost, volume: u8) -> Result<(), &'static str> {
+        let unit_id = self.volume_unit(FU_VOLUME_CONTROL).ok_or("No feature unit with volume control found")?;
+
+        // Linear over the range, 0 maps to silence
+        let db256 = if volume == 0 {
+            i16::MIN as i32
+        } else {
+            VOLUME_MIN_DB256 - VOLUME_MIN_DB256 * volume.min(100) as i32 / 100
+        };
+        let mut data = (db256 as i16).to_le_bytes();
+        self.set_feature(host, unit_id, FU_VOLUME_CONTROL, &mut data)
     }
-    
+
     /// Set mute
-    pub fn set_mute(&mut self, mute: bool) -> Result<(), &'static str> {
-        // Find feature unit with mute control
-        for unit in &self.feature_units {
-            // Simulate sending SET_CUR request to feature unit
-            self.usb_device_handle.control_transfer(
-                UsbControlRequest::new(
-                    UsbDirection::Out, UsbTransferType::Class, UsbEndpointType::Interface,
-                    0x01, // SET_CUR
-                    0x00, // Mute Control
-                    unit.unit_id as u16,
-                    self.control_interface_num as u16,
-                    &[mute as u8], 1
-                )
-            )?;
-            return Ok(());
-        }
-        Err("No feature unit with mute control found")
+    pub fn set_mute(&mut self, host: &mut dyn UsbHost, mute: bool) -> Result<(), &'static str> {
+        let unit_id = self.volume_unit(FU_MUTE_CONTROL).ok_or("No feature unit with mute control found")?;
+
+        let mut data = [mute as u8];
+        self.set_feature(host, unit_id, FU_MUTE_CONTROL, &mut data)
     }
-    
+
+    /// Feature unit offering the given control
+    fn volume_unit(&self, control: u8) -> Option<u8> {
+        self.feature_units
+            .iter()
+            .find(|unit| unit.controls.contains(&control))
+            .map(|unit| unit.unit_id)
+    }
+
+    /// SET_CUR on the master channel of a feature unit control
+    fn set_feature(&self, host: &mut dyn UsbHost, unit_id: u8, control: u8, data: &mut [u8]) -> Result<(), &'static str> {
+        let request = UsbDeviceRequest {
+            request_type: USB_REQ_DIRECTION_OUT | USB_REQ_TYPE_CLASS | USB_REQ_RECIPIENT_INTERFACE,
+            request: UAC_SET_CUR,
+            value: (control as u16) << 8,
+            index: ((unit_id as u16) << 8) | self.control_interface_num as u16,
+            length: data.len() as u16,
+        };
+        host.control_transfer(self.slot_id, request, data)
+            .map_err(|_| "Feature unit request failed")?;
+        Ok(())
+    }
+
     /// Find compatible playback stream
     fn find_playback_stream(&self, sample_rate: u32, channels: u8, bits: u8) -> Result<usize, &'static str> {
         for (idx, stream) in self.playback_streams.iter().enumerate() {
@@ -286,25 +342,47 @@ impl UsbAudioDevice {
     }
     
     /// Set USB interface alternate setting
-    fn set_interface(&self, interface: u8, alt_setting: u8) -> Result<(), &'static str> {
-        self.usb_device_handle.control_transfer(
-            UsbControlRequest::new(
-                UsbDirection::Out, UsbTransferType::Standard, UsbEndpointType::Interface,
-                0x0B, // SET_INTERFACE
-                alt_setting as u16,
-                interface as u16,
-                &[], 0
-            )
-        )?;
+    fn set_interface(&self, host: &mut dyn UsbHost, interface: u8, alt_setting: u8) -> Result<(), &'static str> {
+        let request = UsbDeviceRequest {
+            request_type: USB_REQ_DIRECTION_OUT | USB_REQ_TYPE_STANDARD | USB_REQ_RECIPIENT_INTERFACE,
+            request: USB_REQ_SET_INTERFACE,
+            value: alt_setting as u16,
+            index: interface as u16,
+            length: 0,
+        };
+        host.control_transfer(self.slot_id, request, &mut [])
+            .map_err(|_| "SET_INTERFACE failed")?;
         Ok(())
     }
-    
+
     /// Configure isochronous endpoint
-    fn configure_endpoint(&self, stream: &UsbAudioStream) -> Result<(), &'static str> {
-        // This would involve sending specific USB control requests or
-        // URBs to configure the isochronous endpoint parameters.
-        // For now, this is a placeholder acknowledging the need for configuration.
-        // E.g., setting MaxPacketSize, interval, etc.
+    fn configure_endpoint(&self, host: &mut dyn UsbHost, stream: &UsbAudioStream) -> Result<(), &'static str> {
+        // The host controller builds the endpoint context and transfer
+        // ring from the descriptor of the alternate setting
+        let endpoint = UsbEndpointDescriptor {
+            length: 9,
+            descriptor_type: USB_DESC_TYPE_ENDPOINT,
+            endpoint_address: stream.endpoint_addr,
+            attributes: EP_ATTR_ISOCH_ADAPTIVE,
+            max_packet_size: stream.max_packet_size,
+            interval: stream.interval,
+        };
+        host.configure_endpoint(self.slot_id, &endpoint)
+            .map_err(|_| "Failed to configure isochronous endpoint")
+    }
+
+    /// Set the sampling frequency of a UAC1 streaming endpoint
+    fn set_sample_rate(&self, host: &mut dyn UsbHost, stream: &UsbAudioStream, sample_rate: u32) -> Result<(), &'static str> {
+        let request = UsbDeviceRequest {
+            request_type: USB_REQ_DIRECTION_OUT | USB_REQ_TYPE_CLASS | USB_REQ_RECIPIENT_ENDPOINT,
+            request: UAC_SET_CUR,
+            value: (EP_SAMPLING_FREQ_CONTROL as u16) << 8,
+            index: stream.endpoint_addr as u16,
+            length: 3,
+        };
+        let mut data = [sample_rate as u8, (sample_rate >> 8) as u8, (sample_rate >> 16) as u8];
+        host.control_transfer(self.slot_id, request, &mut data)
+            .map_err(|_| "Failed to set sampling frequency")?;
         Ok(())
     }
 }
@@ -327,13 +405,11 @@ impl UsbAudioDriver {
     }
     
     /// Probe USB device
-    pub fn probe(&mut self, usb_device_handle: UsbDeviceHandle) -> bool {
-        // Read USB device descriptor via handle and check class
-        // For now, assume if the framework called us, it's an audio device.
-        // A real check would involve reading descriptors from usb_device_handle.
-        let device_descriptor = usb_device_handle.get_device_descriptor();
-        if device_descriptor.b_device_class == USB_CLASS_AUDIO || 
-           (device_descriptor.b_device_class == 0x00 && device_descriptor.b_num_configurations > 0) // Class 0 sometimes for audio
+    pub fn probe(&mut self, device_descriptor: &UsbDeviceDescriptor) -> bool {
+        // Class 0 defers to the interfaces, which is how most audio
+        // devices report themselves
+        if device_descriptor.device_class == USB_CLASS_AUDIO ||
+           (device_descriptor.device_class == 0x00 && device_descriptor.num_configurations > 0)
         {
             // Further checks can be done by parsing configuration descriptors
             return true;
@@ -342,8 +418,8 @@ impl UsbAudioDriver {
     }
     
     /// Remove USB device
-    pub fn remove(&mut self, usb_device_handle: UsbDeviceHandle) {
-        self.devices.retain(|dev| dev.usb_device_handle != usb_device_handle);
+    pub fn remove(&mut self, slot_id: u8) {
+        self.devices.retain(|dev| dev.slot_id != slot_id);
     }
 }
 
@@ -372,16 +448,17 @@ impl Driver for UsbAudioDriver {
         self.initialized = true;
         Ok(())
     }
-    fn start(&mut self) -> Result<(), DriverError> {
-        // This is typically called when a device is matched/plugged in.
-        // We will instantiate UsbAudioDevice here.
-        // For now, assume the framework calls a specific 'device_connected' event.
+    fn start(&mut self, _device_info: &DeviceInfo) -> Result<(), DriverError> {
+        // USB audio devices arrive through the USB subsystem's probe
+        // callback rather than from the device manager, so there is nothing
+        // to start per PCI device.
         Ok(())
     }
     fn stop(&mut self) -> Result<(), DriverError> {
         self.devices.clear(); // Clear all managed devices
         Ok(())
     }
+    fn version(&self) -> &'static str { "0.1.0" }
 }
 
 #[no_mangle]
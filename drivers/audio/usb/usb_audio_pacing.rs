@@ -0,0 +1,57 @@
+//! Isochronous packet pacing
+//!
+//! An isochronous OUT endpoint gets one packet per service interval, and
+//! the packet must hold exactly the audio for that interval. At 48 kHz and
+//! 1000 intervals per second that is always 48 frames; at 44.1 kHz the
+//! remainder is carried so packets go 44, 44, ... 45 and average out.
+
+/// USB frames per second (full speed service interval of 1 ms)
+pub const FRAMES_PER_SECOND: u32 = 1000;
+
+/// Microframes per second (high speed service interval of 125 us)
+pub const MICROFRAMES_PER_SECOND: u32 = 8000;
+
+/// Service intervals per second for an endpoint's bInterval
+pub fn intervals_per_second(b_interval: u8, high_speed: bool) -> u32 {
+    // Isochronous bInterval encodes 2^(bInterval - 1) (micro)frames
+    let exponent = b_interval.clamp(1, 16) as u32 - 1;
+    let base = if high_speed { MICROFRAMES_PER_SECOND } else { FRAMES_PER_SECOND };
+    (base >> exponent).max(1)
+}
+
+/// Splits a PCM stream into per-interval packets
+#[derive(Clone, Copy, Debug)]
+pub struct PacketPacer {
+    sample_rate: u32,
+    frame_bytes: usize,
+    intervals_per_second: u32,
+    remainder: u32,
+}
+
+impl PacketPacer {
+    pub fn new(sample_rate: u32, channels: u8, subframe_size: u8, intervals_per_second: u32) -> Self {
+        PacketPacer {
+            sample_rate,
+            frame_bytes: channels as usize * subframe_size as usize,
+            intervals_per_second: intervals_per_second.max(1),
+            remainder: 0,
+        }
+    }
+
+    /// Bytes that belong in the next packet
+    pub fn packet_bytes(&self) -> usize {
+        let frames = (self.remainder + self.sample_rate) / self.intervals_per_second;
+        frames as usize * self.frame_bytes
+    }
+
+    /// The packet returned by `packet_bytes` was sent
+    pub fn commit(&mut self) {
+        self.remainder = (self.remainder + self.sample_rate) % self.intervals_per_second;
+    }
+
+    /// Largest packet the stream ever produces; must fit the endpoint
+    pub fn max_packet_bytes(&self) -> usize {
+        let frames = (self.sample_rate + self.intervals_per_second - 1) / self.intervals_per_second;
+        frames as usize * self.frame_bytes
+    }
+}
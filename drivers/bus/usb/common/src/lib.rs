@@ -4,6 +4,8 @@
 //!
 //! Common USB structures, descriptors, and constants used across USB drivers.
 
+use driver_framework::DriverResult;
+
 /// USB Device Descriptor (18 bytes)
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
@@ -97,6 +99,7 @@ pub const USB_REQ_GET_DESCRIPTOR: u8 = 0x06;
 pub const USB_REQ_SET_DESCRIPTOR: u8 = 0x07;
 pub const USB_REQ_GET_CONFIGURATION: u8 = 0x08;
 pub const USB_REQ_SET_CONFIGURATION: u8 = 0x09;
+pub const USB_REQ_SET_INTERFACE: u8 = 0x0B;
 
 /// USB Request Types
 pub const USB_REQ_TYPE_STANDARD: u8 = 0x00;
@@ -156,3 +159,109 @@ pub enum UsbDirection {
     Out,
     In,
 }
+
+/// Interface a host controller driver offers to USB class drivers.
+/// Devices are identified by their slot (device address) and endpoints by
+/// their endpoint address (bit 7 set for IN).
+pub trait UsbHost {
+    /// Run a control transfer on the default endpoint; the data stage
+    /// direction comes from the request type
+    fn control_transfer(&mut self, slot_id: u8, request: UsbDeviceRequest, data: &mut [u8]) -> DriverResult<usize>;
+
+    /// Make an endpoint of the active configuration usable
+    fn configure_endpoint(&mut self, slot_id: u8, endpoint: &UsbEndpointDescriptor) -> DriverResult<()>;
+
+    /// Queue an IN transfer of up to `length` bytes without waiting for it
+    fn submit_in(&mut self, slot_id: u8, endpoint_address: u8, length: usize) -> DriverResult<()>;
+
+    /// Collect a queued IN transfer; `Ok(None)` while it is still pending
+    fn poll_in(&mut self, slot_id: u8, endpoint_address: u8, data: &mut [u8]) -> DriverResult<Option<usize>>;
+
+    /// Bulk or interrupt transfer that waits for completion
+    fn transfer(&mut self, slot_id: u8, endpoint_address: u8, data: &mut [u8], timeout_ms: u64) -> DriverResult<usize>;
+
+    /// Recover an endpoint after a stall and clear its halt feature on the device
+    fn clear_halt(&mut self, slot_id: u8, endpoint_address: u8) -> DriverResult<()>;
+
+    /// Queue one service interval's packet on an isochronous OUT endpoint.
+    /// `Ok(false)` when every transfer descriptor is still in flight.
+    fn submit_isoch_out(&mut self, slot_id: u8, endpoint_address: u8, packet: &[u8]) -> DriverResult<bool>;
+}
+
+/// Feature selector for CLEAR_FEATURE on an endpoint
+pub const USB_FEATURE_ENDPOINT_HALT: u16 = 0x00;
+
+/// HID class requests
+pub const USB_REQ_HID_SET_IDLE: u8 = 0x0A;
+pub const USB_REQ_HID_SET_PROTOCOL: u8 = 0x0B;
+
+/// HID boot interface subclass and protocols
+pub const USB_HID_SUBCLASS_BOOT: u8 = 0x01;
+pub const USB_HID_PROTOCOL_KEYBOARD: u8 = 0x01;
+pub const USB_HID_PROTOCOL_MOUSE: u8 = 0x02;
+
+/// Mass storage class requests
+pub const USB_REQ_MSC_GET_MAX_LUN: u8 = 0xFE;
+pub const USB_REQ_MSC_RESET: u8 = 0xFF;
+
+/// Mass storage SCSI transparent subclass over Bulk-Only Transport
+pub const USB_MSC_SUBCLASS_SCSI: u8 = 0x06;
+pub const USB_MSC_PROTOCOL_BOT: u8 = 0x50;
+
+/// Walks the descriptors of a configuration descriptor set
+pub struct DescriptorIter<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> DescriptorIter<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+}
+
+impl<'a> Iterator for DescriptorIter<'a> {
+    /// (descriptor type, raw descriptor bytes)
+    type Item = (u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.data.get(self.offset..)?;
+        if rest.len() < 2 {
+            return None;
+        }
+
+        let length = rest[0] as usize;
+        if length < 2 || length > rest.len() {
+            return None;
+        }
+
+        self.offset += length;
+        Some((rest[1], &rest[..length]))
+    }
+}
+
+impl UsbInterfaceDescriptor {
+    pub fn parse(raw: &[u8]) -> Option<Self> {
+        if raw.len() < core::mem::size_of::<Self>() || raw[1] != USB_DESC_TYPE_INTERFACE {
+            return None;
+        }
+        Some(unsafe { core::ptr::read_unaligned(raw.as_ptr() as *const Self) })
+    }
+}
+
+impl UsbEndpointDescriptor {
+    pub fn parse(raw: &[u8]) -> Option<Self> {
+        if raw.len() < core::mem::size_of::<Self>() || raw[1] != USB_DESC_TYPE_ENDPOINT {
+            return None;
+        }
+        Some(unsafe { core::ptr::read_unaligned(raw.as_ptr() as *const Self) })
+    }
+
+    pub fn is_in(&self) -> bool {
+        (self.endpoint_address & USB_REQ_DIRECTION_IN) != 0
+    }
+
+    pub fn transfer_type(&self) -> u8 {
+        self.attributes & 0x03
+    }
+}
@@ -0,0 +1,124 @@
+//! HID Boot Keyboard
+//!
+//! Turns 8-byte boot protocol reports into PS/2 scancode set 1 make/break
+//! codes, the format the PS/2 keyboard driver sends to the input server.
+
+/// Boot keyboard report length
+pub const KEYBOARD_REPORT_SIZE: usize = 8;
+
+/// Scancodes with this bit set are sent behind an 0xE0 prefix
+const EXTENDED: u16 = 0xE000;
+
+/// Prefix byte for extended scancodes
+const SCANCODE_EXTENDED_PREFIX: u8 = 0xE0;
+
+/// Break (release) codes have bit 7 set
+const SCANCODE_RELEASE: u8 = 0x80;
+
+/// Usage reported in every key slot on phantom/rollover errors
+const USAGE_ERROR_ROLLOVER: u8 = 0x01;
+
+/// Modifier byte bits in order: LCtrl, LShift, LAlt, LGUI, RCtrl, RShift, RAlt, RGUI
+static MODIFIER_TO_SCANCODE: [u16; 8] = [
+    0x1D, 0x2A, 0x38, EXTENDED | 0x5B,
+    EXTENDED | 0x1D, 0x36, EXTENDED | 0x38, EXTENDED | 0x5C,
+];
+
+/// HID keyboard usage (page 0x07) to scancode set 1, 0 = no mapping
+static USAGE_TO_SCANCODE: [u16; 0x66] = [
+    0, 0, 0, 0,                                     // 0x00-0x03: reserved / errors
+    0x1E, 0x30, 0x2E, 0x20, 0x12, 0x21, 0x22, 0x23, // a-h
+    0x17, 0x24, 0x25, 0x26, 0x32, 0x31, 0x18, 0x19, // i-p
+    0x10, 0x13, 0x1F, 0x14, 0x16, 0x2F, 0x11, 0x2D, // q-x
+    0x15, 0x2C,                                     // y-z
+    0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, // 1-8
+    0x0A, 0x0B,                                     // 9-0
+    0x1C, 0x01, 0x0E, 0x0F, 0x39,                   // enter, esc, backspace, tab, space
+    0x0C, 0x0D, 0x1A, 0x1B, 0x2B, 0x2B,             // - = [ ] \ non-US #
+    0x27, 0x28, 0x29, 0x33, 0x34, 0x35,             // ; ' ` , . /
+    0x3A,                                           // caps lock
+    0x3B, 0x3C, 0x3D, 0x3E, 0x3F, 0x40,             // F1-F6
+    0x41, 0x42, 0x43, 0x44, 0x57, 0x58,             // F7-F12
+    EXTENDED | 0x37, 0x46, 0,                       // print screen, scroll lock, pause
+    EXTENDED | 0x52, EXTENDED | 0x47, EXTENDED | 0x49, // insert, home, page up
+    EXTENDED | 0x53, EXTENDED | 0x4F, EXTENDED | 0x51, // delete, end, page down
+    EXTENDED | 0x4D, EXTENDED | 0x4B,               // right, left
+    EXTENDED | 0x50, EXTENDED | 0x48,               // down, up
+    0x45, EXTENDED | 0x35, 0x37, 0x4A, 0x4E,        // num lock, keypad / * - +
+    EXTENDED | 0x1C,                                // keypad enter
+    0x4F, 0x50, 0x51, 0x4B, 0x4C, 0x4D,             // keypad 1-6
+    0x47, 0x48, 0x49, 0x52, 0x53,                   // keypad 7-9, 0, .
+    0x56, EXTENDED | 0x5D,                          // non-US \, application
+];
+
+/// Tracks the previous report so presses and releases can be told apart
+pub struct BootKeyboard {
+    modifiers: u8,
+    keys: [u8; 6],
+}
+
+impl BootKeyboard {
+    pub const fn new() -> Self {
+        Self {
+            modifiers: 0,
+            keys: [0; 6],
+        }
+    }
+
+    /// Compare `report` with the previous one and hand each resulting
+    /// scancode byte to `emit`, releases first
+    pub fn process_report(&mut self, report: &[u8], mut emit: impl FnMut(u8)) {
+        if report.len() < KEYBOARD_REPORT_SIZE {
+            return;
+        }
+
+        let modifiers = report[0];
+        let mut keys = [0u8; 6];
+        keys.copy_from_slice(&report[2..8]);
+
+        // Rollover reports carry no key state; keep the previous one
+        if keys.iter().all(|&k| k == USAGE_ERROR_ROLLOVER) {
+            return;
+        }
+
+        for (bit, &scancode) in MODIFIER_TO_SCANCODE.iter().enumerate() {
+            let mask = 1 << bit;
+            if (self.modifiers & mask) != 0 && (modifiers & mask) == 0 {
+                send_scancode(scancode, false, &mut emit);
+            }
+        }
+        for &usage in self.keys.iter().filter(|&&u| u != 0 && !keys.contains(&u)) {
+            send_scancode(usage_to_scancode(usage), false, &mut emit);
+        }
+
+        for (bit, &scancode) in MODIFIER_TO_SCANCODE.iter().enumerate() {
+            let mask = 1 << bit;
+            if (self.modifiers & mask) == 0 && (modifiers & mask) != 0 {
+                send_scancode(scancode, true, &mut emit);
+            }
+        }
+        for &usage in keys.iter().filter(|&&u| u != 0 && !self.keys.contains(&u)) {
+            send_scancode(usage_to_scancode(usage), true, &mut emit);
+        }
+
+        self.modifiers = modifiers;
+        self.keys = keys;
+    }
+}
+
+fn usage_to_scancode(usage: u8) -> u16 {
+    USAGE_TO_SCANCODE.get(usage as usize).copied().unwrap_or(0)
+}
+
+fn send_scancode(scancode: u16, pressed: bool, emit: &mut impl FnMut(u8)) {
+    if scancode == 0 {
+        return;
+    }
+
+    if (scancode & EXTENDED) != 0 {
+        emit(SCANCODE_EXTENDED_PREFIX);
+    }
+
+    let code = (scancode & 0x7F) as u8;
+    emit(if pressed { code } else { code | SCANCODE_RELEASE });
+}
@@ -0,0 +1,163 @@
+#![no_std]
+
+//! USB HID Boot Protocol Driver
+//!
+//! Binds to HID boot keyboards and mice, polls their interrupt IN endpoint
+//! and forwards input to the input server in the same form as the PS/2
+//! drivers. Runs on top of any host controller implementing `UsbHost`.
+
+pub mod keyboard;
+pub mod mouse;
+
+use driver_framework::{DriverError, DriverResult};
+use driver_framework::ipc::{ipc_send, IpcMessage, IPC_MSG_NOTIFICATION};
+use usb_common::*;
+
+use keyboard::{BootKeyboard, KEYBOARD_REPORT_SIZE};
+
+/// Input server port and event messages (shared with the PS/2 drivers)
+const INPUT_SERVER_PORT: u64 = 200;
+const MSG_KEY_EVENT: u64 = 10;
+const MSG_MOUSE_EVENT: u64 = 11;
+
+/// Largest report read from the interrupt endpoint
+const MAX_REPORT_SIZE: usize = 64;
+
+/// Boot protocol selector for SET_PROTOCOL
+const HID_PROTOCOL_BOOT: u16 = 0;
+
+enum HidKind {
+    Keyboard(BootKeyboard),
+    Mouse,
+}
+
+/// A bound HID boot interface
+pub struct HidDevice {
+    slot_id: u8,
+    interface: u8,
+    endpoint: UsbEndpointDescriptor,
+    kind: HidKind,
+}
+
+impl HidDevice {
+    /// Look for a boot keyboard or mouse interface in the device's
+    /// configuration descriptor set
+    pub fn probe(slot_id: u8, config: &[u8]) -> Option<Self> {
+        let mut interface: Option<UsbInterfaceDescriptor> = None;
+
+        for (desc_type, raw) in DescriptorIter::new(config) {
+            match desc_type {
+                USB_DESC_TYPE_INTERFACE => {
+                    interface = UsbInterfaceDescriptor::parse(raw).filter(|i| {
+                        i.interface_class == USB_CLASS_HID
+                            && i.interface_subclass == USB_HID_SUBCLASS_BOOT
+                            && (i.interface_protocol == USB_HID_PROTOCOL_KEYBOARD
+                                || i.interface_protocol == USB_HID_PROTOCOL_MOUSE)
+                    });
+                }
+                USB_DESC_TYPE_ENDPOINT => {
+                    let (iface, endpoint) = match (interface, UsbEndpointDescriptor::parse(raw)) {
+                        (Some(iface), Some(endpoint)) => (iface, endpoint),
+                        _ => continue,
+                    };
+
+                    if endpoint.is_in() && endpoint.transfer_type() == USB_EP_TYPE_INTERRUPT {
+                        let kind = if iface.interface_protocol == USB_HID_PROTOCOL_KEYBOARD {
+                            HidKind::Keyboard(BootKeyboard::new())
+                        } else {
+                            HidKind::Mouse
+                        };
+
+                        return Some(Self {
+                            slot_id,
+                            interface: iface.interface_number,
+                            endpoint,
+                            kind,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    pub fn slot_id(&self) -> u8 {
+        self.slot_id
+    }
+
+    /// Switch the interface to the boot protocol and start polling
+    pub fn start(&mut self, host: &mut dyn UsbHost) -> DriverResult<()> {
+        host.configure_endpoint(self.slot_id, &self.endpoint)?;
+
+        let set_protocol = UsbDeviceRequest {
+            request_type: USB_REQ_DIRECTION_OUT | USB_REQ_TYPE_CLASS | USB_REQ_RECIPIENT_INTERFACE,
+            request: USB_REQ_HID_SET_PROTOCOL,
+            value: HID_PROTOCOL_BOOT,
+            index: self.interface as u16,
+            length: 0,
+        };
+        host.control_transfer(self.slot_id, set_protocol, &mut [])?;
+
+        // Only report on change; not every device supports SET_IDLE
+        let set_idle = UsbDeviceRequest {
+            request_type: USB_REQ_DIRECTION_OUT | USB_REQ_TYPE_CLASS | USB_REQ_RECIPIENT_INTERFACE,
+            request: USB_REQ_HID_SET_IDLE,
+            value: 0,
+            index: self.interface as u16,
+            length: 0,
+        };
+        let _ = host.control_transfer(self.slot_id, set_idle, &mut []);
+
+        host.submit_in(self.slot_id, self.endpoint.endpoint_address, self.report_size())
+    }
+
+    /// Forward a completed report, if any, and queue the next read
+    pub fn poll(&mut self, host: &mut dyn UsbHost) -> DriverResult<()> {
+        let mut report = [0u8; MAX_REPORT_SIZE];
+        let address = self.endpoint.endpoint_address;
+
+        let length = match host.poll_in(self.slot_id, address, &mut report) {
+            Ok(Some(length)) => length,
+            Ok(None) => return Ok(()),
+            Err(DriverError::IoError) => {
+                // Drop a bad report and keep polling
+                return host.submit_in(self.slot_id, address, self.report_size());
+            }
+            Err(e) => return Err(e),
+        };
+
+        match self.kind {
+            HidKind::Keyboard(ref mut keyboard) => {
+                keyboard.process_report(&report[..length], |scancode| {
+                    send_event(MSG_KEY_EVENT, &[scancode]);
+                });
+            }
+            HidKind::Mouse => {
+                if let Some(packet) = mouse::report_to_packet(&report[..length]) {
+                    send_event(MSG_MOUSE_EVENT, &packet);
+                }
+            }
+        }
+
+        host.submit_in(self.slot_id, address, self.report_size())
+    }
+
+    fn report_size(&self) -> usize {
+        let max_packet = (self.endpoint.max_packet_size & 0x7FF) as usize;
+        match self.kind {
+            HidKind::Keyboard(_) => KEYBOARD_REPORT_SIZE,
+            HidKind::Mouse => max_packet.clamp(mouse::MOUSE_REPORT_MIN_SIZE, MAX_REPORT_SIZE),
+        }
+    }
+}
+
+/// Send an input event to the input server
+fn send_event(msg_id: u64, data: &[u8]) {
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_NOTIFICATION;
+    msg.msg_id = msg_id;
+    msg.set_inline_data(data);
+    let _ = ipc_send(INPUT_SERVER_PORT, &msg);
+}
@@ -0,0 +1,34 @@
+//! HID Boot Mouse
+//!
+//! Turns boot protocol reports into the 3-byte PS/2 packet the PS/2 mouse
+//! driver sends to the input server.
+
+/// Boot mouse reports are at least buttons, X and Y
+pub const MOUSE_REPORT_MIN_SIZE: usize = 3;
+
+// PS/2 packet flag bits
+const PS2_BUTTON_MASK: u8 = 0x07;
+const PS2_ALWAYS_ONE: u8 = 1 << 3;
+const PS2_X_SIGN: u8 = 1 << 4;
+const PS2_Y_SIGN: u8 = 1 << 5;
+
+/// Convert a boot mouse report to a PS/2 packet
+pub fn report_to_packet(report: &[u8]) -> Option<[u8; 3]> {
+    if report.len() < MOUSE_REPORT_MIN_SIZE {
+        return None;
+    }
+
+    let dx = report[1] as i8;
+    // HID Y grows downwards, PS/2 Y grows upwards
+    let dy = (report[2] as i8).saturating_neg();
+
+    let mut flags = (report[0] & PS2_BUTTON_MASK) | PS2_ALWAYS_ONE;
+    if dx < 0 {
+        flags |= PS2_X_SIGN;
+    }
+    if dy < 0 {
+        flags |= PS2_Y_SIGN;
+    }
+
+    Some([flags, dx as u8, dy as u8])
+}
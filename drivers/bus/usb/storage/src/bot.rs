@@ -0,0 +1,169 @@
+//! Bulk-Only Transport
+//!
+//! Each SCSI command is a Command Block Wrapper on the bulk OUT endpoint,
+//! an optional data stage, and a Command Status Wrapper on the bulk IN
+//! endpoint.
+
+use driver_framework::{DriverError, DriverResult};
+use usb_common::*;
+
+const CBW_SIGNATURE: u32 = 0x4342_5355; // "USBC"
+const CSW_SIGNATURE: u32 = 0x5342_5355; // "USBS"
+
+const CBW_LENGTH: usize = 31;
+const CSW_LENGTH: usize = 13;
+
+/// CBW flags bit for device-to-host data
+const CBW_FLAG_DATA_IN: u8 = 0x80;
+
+/// CSW status values
+const CSW_STATUS_PASSED: u8 = 0;
+const CSW_STATUS_FAILED: u8 = 1;
+
+/// CBW/CSW exchanges are small and quick
+const WRAPPER_TIMEOUT_MS: u64 = 1000;
+
+/// Data stages may have to wait for flash writes
+const DATA_TIMEOUT_MS: u64 = 5000;
+
+/// Direction of a command's data stage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataDirection {
+    In,
+    Out,
+}
+
+/// Outcome of a command the device understood
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandStatus {
+    /// Command passed; bytes of data actually transferred
+    Passed(usize),
+    /// Command failed; the reason is available with REQUEST SENSE
+    Failed,
+}
+
+/// Bulk-Only Transport over one interface's bulk endpoint pair
+pub struct BulkOnly {
+    slot_id: u8,
+    interface: u8,
+    bulk_in: u8,
+    bulk_out: u8,
+    lun: u8,
+    tag: u32,
+}
+
+impl BulkOnly {
+    pub fn new(slot_id: u8, interface: u8, bulk_in: u8, bulk_out: u8) -> Self {
+        Self {
+            slot_id,
+            interface,
+            bulk_in,
+            bulk_out,
+            lun: 0,
+            tag: 0,
+        }
+    }
+
+    /// Run one command. Transport errors trigger reset recovery and are
+    /// returned as errors; a command the device rejects is `Failed`.
+    pub fn execute(&mut self, host: &mut dyn UsbHost, cdb: &[u8], direction: DataDirection, data: &mut [u8]) -> DriverResult<CommandStatus> {
+        if cdb.is_empty() || cdb.len() > 16 {
+            return Err(DriverError::InvalidArgument);
+        }
+
+        self.tag = self.tag.wrapping_add(1);
+        let tag = self.tag;
+
+        let mut cbw = [0u8; CBW_LENGTH];
+        cbw[0..4].copy_from_slice(&CBW_SIGNATURE.to_le_bytes());
+        cbw[4..8].copy_from_slice(&tag.to_le_bytes());
+        cbw[8..12].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        cbw[12] = if direction == DataDirection::In { CBW_FLAG_DATA_IN } else { 0 };
+        cbw[13] = self.lun;
+        cbw[14] = cdb.len() as u8;
+        cbw[15..15 + cdb.len()].copy_from_slice(cdb);
+
+        if let Err(e) = host.transfer(self.slot_id, self.bulk_out, &mut cbw, WRAPPER_TIMEOUT_MS) {
+            self.reset_recovery(host);
+            return Err(e);
+        }
+
+        let mut transferred = 0;
+        if !data.is_empty() {
+            let endpoint = match direction {
+                DataDirection::In => self.bulk_in,
+                DataDirection::Out => self.bulk_out,
+            };
+
+            match host.transfer(self.slot_id, endpoint, data, DATA_TIMEOUT_MS) {
+                Ok(length) => transferred = length,
+                // A stalled data stage still ends with a CSW
+                Err(DriverError::IoError) => host.clear_halt(self.slot_id, endpoint)?,
+                Err(e) => {
+                    self.reset_recovery(host);
+                    return Err(e);
+                }
+            }
+        }
+
+        let csw = match self.read_csw(host) {
+            Ok(csw) => csw,
+            Err(e) => {
+                self.reset_recovery(host);
+                return Err(e);
+            }
+        };
+
+        let signature = u32::from_le_bytes([csw[0], csw[1], csw[2], csw[3]]);
+        let csw_tag = u32::from_le_bytes([csw[4], csw[5], csw[6], csw[7]]);
+        let residue = u32::from_le_bytes([csw[8], csw[9], csw[10], csw[11]]) as usize;
+
+        if signature != CSW_SIGNATURE || csw_tag != tag {
+            self.reset_recovery(host);
+            return Err(DriverError::IoError);
+        }
+
+        match csw[12] {
+            CSW_STATUS_PASSED => Ok(CommandStatus::Passed(transferred.min(data.len().saturating_sub(residue)))),
+            CSW_STATUS_FAILED => Ok(CommandStatus::Failed),
+            // Phase error: the device lost track of the command
+            _ => {
+                self.reset_recovery(host);
+                Err(DriverError::IoError)
+            }
+        }
+    }
+
+    /// Read the CSW, clearing one stall on the IN endpoint if needed
+    fn read_csw(&mut self, host: &mut dyn UsbHost) -> DriverResult<[u8; CSW_LENGTH]> {
+        let mut csw = [0u8; CSW_LENGTH];
+
+        let length = match host.transfer(self.slot_id, self.bulk_in, &mut csw, WRAPPER_TIMEOUT_MS) {
+            Err(DriverError::IoError) => {
+                host.clear_halt(self.slot_id, self.bulk_in)?;
+                host.transfer(self.slot_id, self.bulk_in, &mut csw, WRAPPER_TIMEOUT_MS)?
+            }
+            result => result?,
+        };
+
+        if length != CSW_LENGTH {
+            return Err(DriverError::IoError);
+        }
+
+        Ok(csw)
+    }
+
+    /// Bulk-Only Mass Storage Reset followed by clearing both endpoints
+    pub fn reset_recovery(&mut self, host: &mut dyn UsbHost) {
+        let reset = UsbDeviceRequest {
+            request_type: USB_REQ_DIRECTION_OUT | USB_REQ_TYPE_CLASS | USB_REQ_RECIPIENT_INTERFACE,
+            request: USB_REQ_MSC_RESET,
+            value: 0,
+            index: self.interface as u16,
+            length: 0,
+        };
+        let _ = host.control_transfer(self.slot_id, reset, &mut []);
+        let _ = host.clear_halt(self.slot_id, self.bulk_in);
+        let _ = host.clear_halt(self.slot_id, self.bulk_out);
+    }
+}
@@ -0,0 +1,334 @@
+#![no_std]
+
+//! USB Mass Storage Driver
+//!
+//! Bulk-Only Transport driver for SCSI transparent mass storage devices
+//! (USB flash drives). Each device gets its own IPC port serving the same
+//! block device operations as the AHCI driver, so VFS can mount it.
+
+pub mod bot;
+pub mod scsi;
+
+use core::convert::TryInto;
+
+use driver_framework::{DriverError, DriverResult};
+use driver_framework::block::BlockDeviceInfo;
+use driver_framework::ipc::{ipc_create_port, ipc_reply, ipc_send, ipc_try_receive, IpcMessage, IPC_MSG_REQUEST, IPC_MSG_RESPONSE};
+use usb_common::*;
+
+use bot::{BulkOnly, CommandStatus, DataDirection};
+use scsi::Sense;
+
+// Block device IPC operations (same as the AHCI driver)
+pub const BLOCK_DEV_OP_READ: u64 = 1;
+pub const BLOCK_DEV_OP_WRITE: u64 = 2;
+pub const BLOCK_DEV_OP_GET_INFO: u64 = 3;
+
+/// Driver manager registration
+const DRIVER_MANAGER_PORT: u64 = 100;
+const MSG_REGISTER_DRIVER: u64 = 1;
+const DRIVER_TYPE_STORAGE: u8 = 2;
+
+/// Size of the unit/LBA/count header of read and write requests
+const REQUEST_HEADER_SIZE: usize = 13;
+
+/// Vendor and product identification in INQUIRY data
+const INQUIRY_MODEL: core::ops::Range<usize> = 8..32;
+
+/// Largest block size supported
+const MAX_BLOCK_SIZE: usize = 4096;
+
+/// Largest single READ(10)/WRITE(10)
+const MAX_TRANSFER_BYTES: usize = 64 * 1024;
+
+/// TEST UNIT READY attempts while the medium comes up
+const READY_RETRIES: usize = 5;
+
+/// A bound mass storage interface
+pub struct MassStorageDevice {
+    slot_id: u8,
+    transport: BulkOnly,
+    bulk_in: UsbEndpointDescriptor,
+    bulk_out: UsbEndpointDescriptor,
+
+    /// Port block requests arrive on
+    port: u64,
+
+    block_count: u64,
+    block_size: u32,
+
+    /// Vendor and product from INQUIRY, space padded
+    model: [u8; 24],
+
+    /// Sense data of the last failed command
+    last_sense: Option<Sense>,
+}
+
+impl MassStorageDevice {
+    /// Look for a SCSI transparent Bulk-Only interface with a bulk IN and
+    /// bulk OUT endpoint in the device's configuration descriptor set
+    pub fn probe(slot_id: u8, config: &[u8]) -> Option<Self> {
+        let mut interface: Option<UsbInterfaceDescriptor> = None;
+        let mut bulk_in: Option<UsbEndpointDescriptor> = None;
+        let mut bulk_out: Option<UsbEndpointDescriptor> = None;
+
+        for (desc_type, raw) in DescriptorIter::new(config) {
+            match desc_type {
+                USB_DESC_TYPE_INTERFACE => {
+                    if interface.is_some() {
+                        break;
+                    }
+                    interface = UsbInterfaceDescriptor::parse(raw).filter(|i| {
+                        i.interface_class == USB_CLASS_MASS_STORAGE
+                            && i.interface_subclass == USB_MSC_SUBCLASS_SCSI
+                            && i.interface_protocol == USB_MSC_PROTOCOL_BOT
+                    });
+                }
+                USB_DESC_TYPE_ENDPOINT if interface.is_some() => {
+                    let endpoint = match UsbEndpointDescriptor::parse(raw) {
+                        Some(endpoint) if endpoint.transfer_type() == USB_EP_TYPE_BULK => endpoint,
+                        _ => continue,
+                    };
+                    if endpoint.is_in() {
+                        bulk_in.get_or_insert(endpoint);
+                    } else {
+                        bulk_out.get_or_insert(endpoint);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let (interface, bulk_in, bulk_out) = (interface?, bulk_in?, bulk_out?);
+
+        Some(Self {
+            slot_id,
+            transport: BulkOnly::new(slot_id, interface.interface_number, bulk_in.endpoint_address, bulk_out.endpoint_address),
+            bulk_in,
+            bulk_out,
+            port: 0,
+            block_count: 0,
+            block_size: 0,
+            model: [b' '; 24],
+            last_sense: None,
+        })
+    }
+
+    pub fn slot_id(&self) -> u8 {
+        self.slot_id
+    }
+
+    pub fn block_count(&self) -> u64 {
+        self.block_count
+    }
+
+    pub fn block_size(&self) -> u32 {
+        self.block_size
+    }
+
+    /// Bring the medium up, read its capacity and start serving block requests
+    pub fn start(&mut self, host: &mut dyn UsbHost) -> DriverResult<()> {
+        host.configure_endpoint(self.slot_id, &self.bulk_in)?;
+        host.configure_endpoint(self.slot_id, &self.bulk_out)?;
+
+        let mut inquiry = [0u8; scsi::INQUIRY_LENGTH];
+        self.command(host, &scsi::inquiry(), DataDirection::In, &mut inquiry)?;
+        if (inquiry[0] & 0x1F) != scsi::PERIPHERAL_DIRECT_ACCESS {
+            return Err(DriverError::NotSupported);
+        }
+
+        self.model.copy_from_slice(&inquiry[INQUIRY_MODEL]);
+
+        self.wait_ready(host)?;
+
+        let mut capacity = [0u8; scsi::READ_CAPACITY_LENGTH];
+        self.command(host, &scsi::read_capacity_10(), DataDirection::In, &mut capacity)?;
+        let (block_count, block_size) = scsi::parse_capacity(&capacity).ok_or(DriverError::IoError)?;
+
+        // Media over 2TiB would need READ CAPACITY(16) and READ(16)
+        if block_count > u32::MAX as u64 {
+            return Err(DriverError::NotSupported);
+        }
+        if block_size == 0 || block_size as usize > MAX_BLOCK_SIZE || !block_size.is_power_of_two() {
+            return Err(DriverError::NotSupported);
+        }
+
+        self.block_count = block_count;
+        self.block_size = block_size;
+
+        self.port = ipc_create_port().map_err(|_| DriverError::IoError)?;
+        register_storage_port(self.port)
+    }
+
+    /// TEST UNIT READY until the device stops reporting not-ready/unit attention
+    fn wait_ready(&mut self, host: &mut dyn UsbHost) -> DriverResult<()> {
+        for _ in 0..READY_RETRIES {
+            match self.command(host, &scsi::test_unit_ready(), DataDirection::Out, &mut []) {
+                Ok(_) => return Ok(()),
+                Err(DriverError::IoError) => match self.last_sense {
+                    Some(sense) if sense.key == scsi::SENSE_NOT_READY || sense.key == scsi::SENSE_UNIT_ATTENTION => continue,
+                    _ => return Err(DriverError::IoError),
+                },
+                Err(e) => return Err(e),
+            }
+        }
+        Err(DriverError::Timeout)
+    }
+
+    /// Run a command, fetching sense data if the device fails it
+    fn command(&mut self, host: &mut dyn UsbHost, cdb: &[u8], direction: DataDirection, data: &mut [u8]) -> DriverResult<usize> {
+        match self.transport.execute(host, cdb, direction, data)? {
+            CommandStatus::Passed(length) => {
+                self.last_sense = None;
+                Ok(length)
+            }
+            CommandStatus::Failed => {
+                let mut sense = [0u8; scsi::SENSE_LENGTH];
+                self.last_sense = match self.transport.execute(host, &scsi::request_sense(), DataDirection::In, &mut sense) {
+                    Ok(CommandStatus::Passed(length)) => Sense::parse(&sense[..length]),
+                    _ => None,
+                };
+                Err(DriverError::IoError)
+            }
+        }
+    }
+
+    /// Read or write whole blocks, split into transfers READ(10)/WRITE(10) can carry
+    fn transfer_blocks(&mut self, host: &mut dyn UsbHost, lba: u64, data: &mut [u8], direction: DataDirection) -> DriverResult<()> {
+        let block_size = self.block_size as usize;
+        if block_size == 0 || data.len() % block_size != 0 {
+            return Err(DriverError::InvalidArgument);
+        }
+
+        let blocks = (data.len() / block_size) as u64;
+        if lba.checked_add(blocks).map_or(true, |end| end > self.block_count) {
+            return Err(DriverError::InvalidArgument);
+        }
+
+        let blocks_per_transfer = MAX_TRANSFER_BYTES / block_size;
+        let mut lba = lba;
+
+        for chunk in data.chunks_mut(blocks_per_transfer * block_size) {
+            let count = (chunk.len() / block_size) as u16;
+            let cdb = match direction {
+                DataDirection::In => scsi::read_10(lba as u32, count),
+                DataDirection::Out => scsi::write_10(lba as u32, count),
+            };
+
+            if self.command(host, &cdb, direction, chunk)? != chunk.len() {
+                return Err(DriverError::IoError);
+            }
+            lba += count as u64;
+        }
+
+        Ok(())
+    }
+
+    /// Serve one queued block request, if any
+    pub fn poll(&mut self, host: &mut dyn UsbHost) -> DriverResult<()> {
+        let mut msg = IpcMessage::new();
+        if ipc_try_receive(self.port, &mut msg).is_err() || msg.msg_type != IPC_MSG_REQUEST {
+            return Ok(());
+        }
+
+        let mut response = IpcMessage::new();
+        response.msg_type = IPC_MSG_RESPONSE;
+        response.msg_id = msg.msg_id;
+
+        match msg.msg_id {
+            BLOCK_DEV_OP_READ => self.handle_read(host, &msg, &mut response),
+            BLOCK_DEV_OP_WRITE => self.handle_write(host, &msg, &mut response),
+            BLOCK_DEV_OP_GET_INFO => {
+                let info = BlockDeviceInfo::new(self.block_count, self.block_size, &self.model);
+                if let Some(len) = info.encode(&mut response.inline_data) {
+                    response.inline_size = len as u32;
+                }
+            }
+            _ => {}
+        }
+
+        let _ = ipc_reply(&msg, &response);
+        Ok(())
+    }
+
+    /// READ: data goes to the caller's buffer when it supplied one big
+    /// enough, and the start of it is returned inline as AHCI does
+    fn handle_read(&mut self, host: &mut dyn UsbHost, msg: &IpcMessage, response: &mut IpcMessage) {
+        let (lba, bytes) = match self.parse_request(msg) {
+            Some(request) => request,
+            None => return,
+        };
+
+        if !msg.buffer.is_null() && msg.buffer_size >= bytes {
+            let buffer = unsafe { core::slice::from_raw_parts_mut(msg.buffer, bytes) };
+            if self.transfer_blocks(host, lba, buffer, DataDirection::In).is_ok() {
+                response.set_inline_data(buffer);
+            }
+        } else {
+            // Only the first block can be returned inline
+            let mut block = [0u8; MAX_BLOCK_SIZE];
+            let block = &mut block[..self.block_size as usize];
+            if self.transfer_blocks(host, lba, block, DataDirection::In).is_ok() {
+                response.set_inline_data(block);
+            }
+        }
+    }
+
+    /// WRITE: data comes from the caller's buffer or follows the header
+    /// inline; partial blocks are rejected rather than padded
+    fn handle_write(&mut self, host: &mut dyn UsbHost, msg: &IpcMessage, response: &mut IpcMessage) {
+        let (lba, bytes) = match self.parse_request(msg) {
+            Some(request) => request,
+            None => return,
+        };
+
+        let result = if !msg.buffer.is_null() && msg.buffer_size >= bytes {
+            let buffer = unsafe { core::slice::from_raw_parts_mut(msg.buffer, bytes) };
+            self.transfer_blocks(host, lba, buffer, DataDirection::Out)
+        } else if msg.inline_size as usize >= REQUEST_HEADER_SIZE + bytes {
+            let mut inline = msg.inline_data;
+            self.transfer_blocks(host, lba, &mut inline[REQUEST_HEADER_SIZE..REQUEST_HEADER_SIZE + bytes], DataDirection::Out)
+        } else {
+            Err(DriverError::InvalidArgument)
+        };
+
+        if result.is_ok() {
+            response.set_inline_data(&[0]); // Success
+        }
+    }
+
+    /// Decode the unit/LBA/count header into (LBA, byte count)
+    fn parse_request(&self, msg: &IpcMessage) -> Option<(u64, usize)> {
+        if (msg.inline_size as usize) < REQUEST_HEADER_SIZE {
+            return None;
+        }
+
+        // One logical unit per device
+        if msg.inline_data[0] != 0 {
+            return None;
+        }
+
+        let lba = u64::from_le_bytes(msg.inline_data[1..9].try_into().ok()?);
+        let count = u32::from_le_bytes(msg.inline_data[9..13].try_into().ok()?) as usize;
+        let bytes = count.checked_mul(self.block_size as usize)?;
+
+        if count == 0 {
+            return None;
+        }
+
+        Some((lba, bytes))
+    }
+}
+
+/// Announce a storage port to the driver manager
+fn register_storage_port(port: u64) -> DriverResult<()> {
+    let mut data = [0u8; 5];
+    data[0] = DRIVER_TYPE_STORAGE;
+    data[1..5].copy_from_slice(&(port as u32).to_le_bytes());
+
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_REQUEST;
+    msg.msg_id = MSG_REGISTER_DRIVER;
+    msg.set_inline_data(&data);
+    ipc_send(DRIVER_MANAGER_PORT, &msg).map_err(|_| DriverError::IoError)
+}
@@ -0,0 +1,89 @@
+//! SCSI Commands
+//!
+//! Command descriptor blocks for the subset of SPC/SBC a USB flash drive
+//! needs. Multi-byte CDB fields are big-endian.
+
+pub const SCSI_TEST_UNIT_READY: u8 = 0x00;
+pub const SCSI_REQUEST_SENSE: u8 = 0x03;
+pub const SCSI_INQUIRY: u8 = 0x12;
+pub const SCSI_READ_CAPACITY_10: u8 = 0x25;
+pub const SCSI_READ_10: u8 = 0x28;
+pub const SCSI_WRITE_10: u8 = 0x2A;
+
+/// Standard INQUIRY data length
+pub const INQUIRY_LENGTH: usize = 36;
+
+/// Fixed format sense data length
+pub const SENSE_LENGTH: usize = 18;
+
+/// READ CAPACITY(10) data length
+pub const READ_CAPACITY_LENGTH: usize = 8;
+
+/// INQUIRY peripheral device type for block devices
+pub const PERIPHERAL_DIRECT_ACCESS: u8 = 0x00;
+
+/// Sense key reported while the medium is still spinning up / after a reset
+pub const SENSE_NOT_READY: u8 = 0x02;
+pub const SENSE_UNIT_ATTENTION: u8 = 0x06;
+
+pub fn test_unit_ready() -> [u8; 6] {
+    [SCSI_TEST_UNIT_READY, 0, 0, 0, 0, 0]
+}
+
+pub fn request_sense() -> [u8; 6] {
+    [SCSI_REQUEST_SENSE, 0, 0, 0, SENSE_LENGTH as u8, 0]
+}
+
+pub fn inquiry() -> [u8; 6] {
+    [SCSI_INQUIRY, 0, 0, 0, INQUIRY_LENGTH as u8, 0]
+}
+
+pub fn read_capacity_10() -> [u8; 10] {
+    [SCSI_READ_CAPACITY_10, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+}
+
+pub fn read_10(lba: u32, blocks: u16) -> [u8; 10] {
+    rw_10(SCSI_READ_10, lba, blocks)
+}
+
+pub fn write_10(lba: u32, blocks: u16) -> [u8; 10] {
+    rw_10(SCSI_WRITE_10, lba, blocks)
+}
+
+fn rw_10(opcode: u8, lba: u32, blocks: u16) -> [u8; 10] {
+    let lba = lba.to_be_bytes();
+    let blocks = blocks.to_be_bytes();
+    [opcode, 0, lba[0], lba[1], lba[2], lba[3], 0, blocks[0], blocks[1], 0]
+}
+
+/// Sense key, additional sense code and qualifier from fixed format sense data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sense {
+    pub key: u8,
+    pub asc: u8,
+    pub ascq: u8,
+}
+
+impl Sense {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        // Response codes 0x70/0x71: current/deferred fixed format
+        if data.len() < 14 || (data[0] & 0x7E) != 0x70 {
+            return None;
+        }
+        Some(Self {
+            key: data[2] & 0x0F,
+            asc: data[12],
+            ascq: data[13],
+        })
+    }
+}
+
+/// Decode READ CAPACITY(10) data into (block count, block size)
+pub fn parse_capacity(data: &[u8]) -> Option<(u64, u32)> {
+    if data.len() < READ_CAPACITY_LENGTH {
+        return None;
+    }
+    let last_lba = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+    let block_size = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+    Some((last_lba as u64 + 1, block_size))
+}
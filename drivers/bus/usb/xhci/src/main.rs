@@ -7,26 +7,57 @@
 //! Implements the XHCI specification for USB device communication.
 
 extern crate driver_framework;
+extern crate usb_hid;
+extern crate usb_storage;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use driver_framework::{DriverResult, DriverError};
 use driver_framework::mmio::MmioRegion;
-use driver_framework::interrupts::IrqHandler;
-use driver_framework::ipc::DriverIpc;
+use driver_framework::interrupts;
+use driver_framework::pci;
+use driver_framework::syscalls::get_uptime_ms;
+use driver_framework::ipc::{ipc_create_port, ipc_receive, ipc_send, IpcMessage, IPC_MSG_REQUEST, IPC_MSG_RESPONSE, IPC_MSG_NOTIFICATION};
+use usb_common::{UsbDeviceDescriptor, UsbConfigurationDescriptor, UsbDeviceRequest, UsbDeviceState};
+use usb_common::{UsbEndpointDescriptor, UsbHost};
+use usb_common::{USB_EP_TYPE_BULK, USB_EP_TYPE_INTERRUPT, USB_EP_TYPE_ISOCHRONOUS};
+use usb_common::{USB_REQ_CLEAR_FEATURE, USB_REQ_RECIPIENT_ENDPOINT, USB_FEATURE_ENDPOINT_HALT};
+use usb_common::{USB_DESC_TYPE_DEVICE, USB_DESC_TYPE_CONFIGURATION};
+use usb_common::{USB_REQ_GET_DESCRIPTOR, USB_REQ_SET_CONFIGURATION, USB_REQ_TYPE_STANDARD};
+use usb_common::{USB_REQ_RECIPIENT_DEVICE, USB_REQ_DIRECTION_IN, USB_REQ_DIRECTION_OUT};
+use usb_common::{USB_CLASS_HID, USB_CLASS_MASS_STORAGE};
 
 mod xhci_regs;
 mod xhci_ring;
 mod xhci_trb;
 mod xhci_device;
+mod xhci_dma;
 
 use xhci_regs::*;
 use xhci_ring::*;
 use xhci_trb::*;
+use xhci_device::*;
+use xhci_dma::DmaRegion;
+use usb_hid::HidDevice;
+use usb_storage::MassStorageDevice;
 
 /// XHCI PCI Class codes
 const PCI_CLASS_SERIAL: u8 = 0x0C;
 const PCI_SUBCLASS_USB: u8 = 0x03;
 const PCI_PROG_IF_XHCI: u8 = 0x30;
 
+/// PCI driver port and config read request
+const PCI_DRIVER_PORT: u64 = 101;
+const MSG_PCI_READ_CONFIG: u64 = 10;
+
+/// Device manager port and its attach/detach notifications
+const DEVICE_MANAGER_PORT: u64 = 90;
+const DEV_MGR_NOTIFY_USB_ATTACH: u64 = 9;
+const DEV_MGR_NOTIFY_USB_DETACH: u64 = 10;
+
+/// Configuration space dword holding the interrupt line
+const PCI_INTERRUPT_OFFSET: u8 = 0x3C;
+
 /// XHCI Vendor IDs
 const PCI_VENDOR_INTEL: u16 = 0x8086;
 const PCI_VENDOR_AMD: u16 = 0x1022;
@@ -38,13 +69,66 @@ const MAX_DEVICE_SLOTS: usize = 256;
 /// Maximum number of interrupters
 const MAX_INTERRUPTERS: usize = 8;
 
+/// Device slots enabled on the controller (and tracked by the driver)
+const MAX_USB_DEVICES: usize = 32;
+
+/// Maximum root hub ports
+const MAX_PORTS: usize = 256;
+
+/// Size of each device's control transfer bounce buffer
+const CONTROL_BUFFER_SIZE: usize = 512;
+
+/// Bounce buffer size for each configured endpoint
+const ENDPOINT_BUFFER_SIZE: usize = 4096;
+
+/// Completion events kept for TRBs nobody has collected yet
+const MAX_COMPLETIONS: usize = 16;
+
+// Timeouts (milliseconds)
+const HALT_TIMEOUT_MS: u64 = 20;
+const RESET_TIMEOUT_MS: u64 = 1000;
+const PORT_RESET_TIMEOUT_MS: u64 = 500;
+const COMMAND_TIMEOUT_MS: u64 = 1000;
+const TRANSFER_TIMEOUT_MS: u64 = 1000;
+
+/// Set by the IRQ handler when interrupter 0 has events pending
+static EVENTS_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Register addresses the IRQ handler acknowledges interrupts through
+static IRQ_OP_REGS: AtomicUsize = AtomicUsize::new(0);
+static IRQ_INTERRUPTER: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn xhci_irq_handler() -> bool {
+    let op_regs = IRQ_OP_REGS.load(Ordering::Acquire) as *mut XhciOperationalRegs;
+    let interrupter = IRQ_INTERRUPTER.load(Ordering::Acquire) as *mut XhciInterrupterRegs;
+    if op_regs.is_null() || interrupter.is_null() {
+        return false;
+    }
+
+    unsafe {
+        // On a shared INTx line the interrupt may belong to another device
+        let usbsts = core::ptr::read_volatile(core::ptr::addr_of!((*op_regs).usbsts));
+        if usbsts & USBSTS_EINT == 0 {
+            return false;
+        }
+
+        // Both bits are write-1-to-clear
+        core::ptr::write_volatile(core::ptr::addr_of_mut!((*op_regs).usbsts), USBSTS_EINT);
+        let iman = core::ptr::read_volatile(core::ptr::addr_of!((*interrupter).iman));
+        core::ptr::write_volatile(core::ptr::addr_of_mut!((*interrupter).iman), iman | IMAN_IP);
+    }
+
+    EVENTS_PENDING.store(true, Ordering::Release);
+    true
+}
+
 /// XHCI Driver State
 pub struct XhciDriver {
     /// MMIO base address
-    mmio_base: MmioRegion,
+    mmio_base: Option<MmioRegion>,
 
-    /// Capability registers base
-    cap_regs: *mut XhciCapabilityRegs,
+    /// Port replies from the PCI driver arrive on
+    reply_port: u64,
 
     /// Operational registers base
     op_regs: *mut XhciOperationalRegs,
@@ -56,6 +140,7 @@ pub struct XhciDriver {
     doorbell_regs: *mut u32,
 
     /// Device Context Base Address Array (DCBAA)
+    dcbaa: Option<DmaRegion>,
     dcbaa_phys: u64,
     dcbaa_virt: *mut u64,
 
@@ -71,36 +156,72 @@ pub struct XhciDriver {
     /// Maximum ports
     max_ports: u8,
 
-    /// IRQ handler
-    irq_handler: Option<IrqHandler>,
+    /// Legacy interrupt line; events are polled when it couldn't be registered
+    irq: u8,
+    irq_enabled: bool,
+
+    /// Completion events waiting to be matched to their TRB
+    completions: [Option<Trb>; MAX_COMPLETIONS],
+    next_completion: usize,
+
+    /// Devices indexed by slot ID - 1
+    devices: [Option<XhciDevice>; MAX_USB_DEVICES],
+
+    /// Ports with a status change event not yet handled
+    port_changed: [bool; MAX_PORTS],
+
+    /// HID class drivers bound to devices, indexed by slot ID - 1
+    hid_devices: [Option<HidDevice>; MAX_USB_DEVICES],
+
+    /// Mass storage class drivers bound to devices, indexed by slot ID - 1
+    storage_devices: [Option<MassStorageDevice>; MAX_USB_DEVICES],
 }
 
 impl XhciDriver {
     /// Create a new XHCI driver instance
     pub fn new() -> Self {
         Self {
-            mmio_base: MmioRegion::new(0, 0),
-            cap_regs: core::ptr::null_mut(),
+            mmio_base: None,
+            reply_port: 0,
             op_regs: core::ptr::null_mut(),
             runtime_regs: core::ptr::null_mut(),
             doorbell_regs: core::ptr::null_mut(),
+            dcbaa: None,
             dcbaa_phys: 0,
             dcbaa_virt: core::ptr::null_mut(),
             command_ring: CommandRing::new(),
             event_ring: EventRing::new(),
             max_slots: 0,
             max_ports: 0,
-            irq_handler: None,
+            irq: 0,
+            irq_enabled: false,
+            completions: [None; MAX_COMPLETIONS],
+            next_completion: 0,
+            devices: core::array::from_fn(|_| None),
+            port_changed: [false; MAX_PORTS],
+            hid_devices: core::array::from_fn(|_| None),
+            storage_devices: core::array::from_fn(|_| None),
         }
     }
 
     /// Initialize the XHCI controller
     pub fn init(&mut self, pci_bus: u8, pci_dev: u8, pci_func: u8) -> DriverResult<()> {
-        // Read BAR0 from PCI configuration space
-        let bar0 = self.read_pci_bar(pci_bus, pci_dev, pci_func, 0)?;
+        self.reply_port = ipc_create_port().map_err(|_| DriverError::IoError)?;
+
+        // XHCI registers are always memory mapped behind BAR0
+        let bar0 = pci::get_bar(pci_bus, pci_dev, pci_func, 0)?;
+        if !bar0.is_mmio || bar0.base == 0 {
+            return Err(DriverError::NotSupported);
+        }
+        pci::enable_device(pci_bus, pci_dev, pci_func)?;
+        // Prefer a dedicated message-signalled vector over the shared INTx line
+        self.irq = match pci::enable_msi(pci_bus, pci_dev, pci_func) {
+            Ok(irq) => irq,
+            Err(_) => (self.pci_read_config(pci_bus, pci_dev, pci_func, PCI_INTERRUPT_OFFSET)? & 0xFF) as u8,
+        };
 
         // Map MMIO region
-        self.mmio_base = MmioRegion::map(bar0, 0x10000)?;
+        self.mmio_base = Some(MmioRegion::map(bar0.base, bar0.size as usize).map_err(|_| DriverError::IoError)?);
 
         // Initialize register pointers
         self.init_registers()?;
@@ -134,20 +255,19 @@ impl XhciDriver {
 
     /// Initialize register pointers from MMIO base
     fn init_registers(&mut self) -> DriverResult<()> {
-        let base = self.mmio_base.as_ptr() as usize;
-
-        self.cap_regs = base as *mut XhciCapabilityRegs;
+        let mmio = self.mmio_base.as_ref().ok_or(DriverError::NotInitialized)?;
+        let base = mmio.base_virt_addr();
 
         // Read capability length to find operational registers
-        let cap_length = unsafe { (*self.cap_regs).caplength };
+        let cap_length = mmio.read_u8(CAP_CAPLENGTH);
         self.op_regs = (base + cap_length as usize) as *mut XhciOperationalRegs;
 
         // Read runtime register offset
-        let rtsoff = unsafe { (*self.cap_regs).rtsoff };
+        let rtsoff = mmio.read_u32(CAP_RTSOFF);
         self.runtime_regs = (base + (rtsoff & !0x1F) as usize) as *mut XhciRuntimeRegs;
 
         // Read doorbell offset
-        let dboff = unsafe { (*self.cap_regs).dboff };
+        let dboff = mmio.read_u32(CAP_DBOFF);
         self.doorbell_regs = (base + (dboff & !0x3) as usize) as *mut u32;
 
         Ok(())
@@ -155,58 +275,58 @@ impl XhciDriver {
 
     /// Read controller capabilities
     fn read_capabilities(&mut self) -> DriverResult<()> {
-        unsafe {
-            let hcsparams1 = (*self.cap_regs).hcsparams1;
+        let mmio = self.mmio_base.as_ref().ok_or(DriverError::NotInitialized)?;
+        let hcsparams1 = mmio.read_u32(CAP_HCSPARAMS1);
 
-            // Extract maximum device slots (bits 0-7)
-            self.max_slots = (hcsparams1 & 0xFF) as u8;
+        // Extract maximum device slots (bits 0-7)
+        self.max_slots = (hcsparams1 & 0xFF) as u8;
 
-            // Extract maximum ports (bits 24-31)
-            self.max_ports = ((hcsparams1 >> 24) & 0xFF) as u8;
-        }
+        // Extract maximum ports (bits 24-31)
+        self.max_ports = ((hcsparams1 >> 24) & 0xFF) as u8;
 
         Ok(())
     }
 
     /// Reset the XHCI controller
     fn reset(&mut self) -> DriverResult<()> {
-        unsafe {
-            // Stop the controller if running
-            (*self.op_regs).usbcmd &= !USBCMD_RUN_STOP;
+        // Stop the controller if running
+        let usbcmd = self.read_usbcmd();
+        self.write_usbcmd(usbcmd & !USBCMD_RUN_STOP);
 
-            // Wait for controller to halt
-            while ((*self.op_regs).usbsts & USBSTS_HCH) == 0 {
-                // Spin wait
-            }
-
-            // Issue controller reset
-            (*self.op_regs).usbcmd |= USBCMD_HCRST;
+        // Wait for controller to halt
+        wait_until(HALT_TIMEOUT_MS, || (self.read_usbsts() & USBSTS_HCH) != 0)?;
 
-            // Wait for reset to complete
-            while ((*self.op_regs).usbcmd & USBCMD_HCRST) != 0 {
-                // Spin wait
-            }
+        // Issue controller reset
+        let usbcmd = self.read_usbcmd();
+        self.write_usbcmd(usbcmd | USBCMD_HCRST);
 
-            // Wait for controller ready
-            while ((*self.op_regs).usbsts & USBSTS_CNR) != 0 {
-                // Spin wait
-            }
-        }
+        // Wait for reset to complete and the controller to become ready
+        wait_until(RESET_TIMEOUT_MS, || (self.read_usbcmd() & USBCMD_HCRST) == 0)?;
+        wait_until(RESET_TIMEOUT_MS, || (self.read_usbsts() & USBSTS_CNR) == 0)?;
 
         Ok(())
     }
 
+    fn read_usbcmd(&self) -> u32 {
+        unsafe { core::ptr::read_volatile(core::ptr::addr_of!((*self.op_regs).usbcmd)) }
+    }
+
+    fn write_usbcmd(&mut self, value: u32) {
+        unsafe { core::ptr::write_volatile(core::ptr::addr_of_mut!((*self.op_regs).usbcmd), value) }
+    }
+
+    fn read_usbsts(&self) -> u32 {
+        unsafe { core::ptr::read_volatile(core::ptr::addr_of!((*self.op_regs).usbsts)) }
+    }
+
     /// Initialize Device Context Base Address Array
     fn init_dcbaa(&mut self) -> DriverResult<()> {
-        // Allocate DCBAA (aligned to 64 bytes)
+        // Allocate DCBAA (aligned to 64 bytes, zeroed)
         let size = (self.max_slots as usize + 1) * 8;
-        self.dcbaa_phys = self.alloc_dma(size, 64)?;
-        self.dcbaa_virt = self.dcbaa_phys as *mut u64;
-
-        // Zero initialize
-        unsafe {
-            core::ptr::write_bytes(self.dcbaa_virt, 0, self.max_slots as usize + 1);
-        }
+        let dcbaa = self.alloc_dma(size, 64)?;
+        self.dcbaa_phys = dcbaa.phys_addr();
+        self.dcbaa_virt = dcbaa.as_ptr();
+        self.dcbaa = Some(dcbaa);
 
         // Program DCBAAP register
         unsafe {
@@ -218,7 +338,8 @@ impl XhciDriver {
 
     /// Initialize command ring
     fn init_command_ring(&mut self) -> DriverResult<()> {
-        self.command_ring.init()?;
+        let memory = self.alloc_dma(RING_BYTES, 64)?;
+        self.command_ring.init(memory)?;
 
         // Program CRCR register
         unsafe {
@@ -230,7 +351,9 @@ impl XhciDriver {
 
     /// Initialize event ring
     fn init_event_ring(&mut self) -> DriverResult<()> {
-        self.event_ring.init()?;
+        let memory = self.alloc_dma(RING_BYTES, 64)?;
+        let table_memory = self.alloc_dma(SEGMENT_TABLE_BYTES, 64)?;
+        self.event_ring.init(memory, table_memory)?;
 
         // Program event ring registers in interrupter 0
         unsafe {
@@ -252,14 +375,23 @@ impl XhciDriver {
     /// Enable interrupts
     fn enable_interrupts(&mut self) -> DriverResult<()> {
         unsafe {
-            // Enable interrupter 0
-            let interrupter = &mut (*self.runtime_regs).interrupters[0];
-            interrupter.iman |= IMAN_IE;
+            let interrupter = core::ptr::addr_of_mut!((*self.runtime_regs).interrupters[0]);
+            IRQ_OP_REGS.store(self.op_regs as usize, Ordering::Release);
+            IRQ_INTERRUPTER.store(interrupter as usize, Ordering::Release);
 
-            // Enable USB interrupts
-            (*self.op_regs).usbcmd |= USBCMD_INTE;
+            // Without an IRQ the event ring is simply polled
+            self.irq_enabled = self.irq != 0 && self.irq != 0xFF
+                && interrupts::register_irq_shared(self.irq, xhci_irq_handler).is_ok()
+                && interrupts::enable_irq(self.irq).is_ok();
+
+            // Enable interrupter 0
+            (*interrupter).iman |= IMAN_IE;
         }
 
+        // Enable USB interrupts
+        let usbcmd = self.read_usbcmd();
+        self.write_usbcmd(usbcmd | USBCMD_INTE);
+
         Ok(())
     }
 
@@ -267,25 +399,27 @@ impl XhciDriver {
     fn start(&mut self) -> DriverResult<()> {
         unsafe {
             // Configure max device slots
+            let slots = (self.max_slots as usize).min(MAX_USB_DEVICES);
             let config = (*self.op_regs).config;
-            (*self.op_regs).config = (config & !0xFF) | (self.max_slots as u32);
-
-            // Start the controller
-            (*self.op_regs).usbcmd |= USBCMD_RUN_STOP;
+            (*self.op_regs).config = (config & !0xFF) | (slots as u32);
 
-            // Wait for controller to start
-            while ((*self.op_regs).usbsts & USBSTS_HCH) != 0 {
-                // Spin wait
-            }
         }
 
+        // Start the controller
+        let usbcmd = self.read_usbcmd();
+        self.write_usbcmd(usbcmd | USBCMD_RUN_STOP);
+
+        // Wait for controller to start
+        wait_until(HALT_TIMEOUT_MS, || (self.read_usbsts() & USBSTS_HCH) == 0)?;
+
         Ok(())
     }
 
     /// Enumerate USB ports
     fn enumerate_ports(&mut self) -> DriverResult<()> {
         for port in 0..self.max_ports {
-            self.check_port(port)?;
+            // A device that fails to enumerate must not stop the others
+            let _ = self.check_port(port);
         }
 
         Ok(())
@@ -293,17 +427,23 @@ impl XhciDriver {
 
     /// Check a specific USB port for connected devices
     fn check_port(&mut self, port: u8) -> DriverResult<()> {
+        let portsc = unsafe { self.read_port_register(port, 0) };
+
+        // Acknowledge any pending changes
         unsafe {
-            let portsc = self.read_port_register(port, 0);
+            self.write_port_register(port, 0, (portsc & PORTSC_PRESERVE_MASK) | (portsc & PORTSC_CHANGE_MASK));
+        }
 
-            // Check if device is connected (CCS bit)
-            if (portsc & PORTSC_CCS) != 0 {
-                // Device connected - reset port
-                self.reset_port(port)?;
+        let slot_id = self.slot_for_port(port);
 
-                // Enable port
-                self.enable_port(port)?;
+        if (portsc & PORTSC_CCS) != 0 {
+            if slot_id.is_none() {
+                self.enumerate_device(port)?;
             }
+        } else if let Some(slot_id) = slot_id {
+            // Device was unplugged
+            self.notify_device_manager(DEV_MGR_NOTIFY_USB_DETACH, &[port]);
+            self.release_device(slot_id)?;
         }
 
         Ok(())
@@ -312,37 +452,637 @@ impl XhciDriver {
     /// Reset a USB port
     fn reset_port(&mut self, port: u8) -> DriverResult<()> {
         unsafe {
-            let mut portsc = self.read_port_register(port, 0);
+            let portsc = self.read_port_register(port, 0);
 
             // Set port reset bit
-            portsc |= PORTSC_PR;
-            self.write_port_register(port, 0, portsc);
+            self.write_port_register(port, 0, (portsc & PORTSC_PRESERVE_MASK) | PORTSC_PR);
 
             // Wait for reset complete
-            loop {
-                portsc = self.read_port_register(port, 0);
-                if (portsc & PORTSC_PR) == 0 {
-                    break;
-                }
-            }
+            wait_until(PORT_RESET_TIMEOUT_MS, || {
+                let portsc = self.read_port_register(port, 0);
+                (portsc & PORTSC_PR) == 0 && (portsc & PORTSC_PRC) != 0
+            })?;
+
+            // The controller enables the port once reset completes
+            let portsc = self.read_port_register(port, 0);
+            self.write_port_register(port, 0, (portsc & PORTSC_PRESERVE_MASK) | PORTSC_PRC);
+        }
+
+        Ok(())
+    }
+
+    /// Find the slot of the device attached to a root hub port
+    fn slot_for_port(&self, port: u8) -> Option<u8> {
+        self.devices.iter().flatten().find(|d| d.port == port).map(|d| d.slot_id)
+    }
+
+    /// Get an enumerated device by slot ID
+    pub fn device(&self, slot_id: u8) -> Option<&XhciDevice> {
+        let index = (slot_id as usize).checked_sub(1)?;
+        self.devices.get(index)?.as_ref()
+    }
+
+    /// Enumerate the device on `port`: Enable Slot, Address Device, read its
+    /// descriptors and select its first configuration. The controller's slot
+    /// only reaches Configured once a class driver adds the endpoints it
+    /// needs with Configure Endpoint.
+    fn enumerate_device(&mut self, port: u8) -> DriverResult<()> {
+        self.reset_port(port)?;
+
+        let portsc = unsafe { self.read_port_register(port, 0) };
+        if (portsc & PORTSC_PED) == 0 {
+            return Err(DriverError::IoError);
+        }
+        let speed = UsbSpeed::from_port_speed((portsc & PORTSC_SPEED_MASK) >> 10)
+            .ok_or(DriverError::NotSupported)?;
+
+        let mut trb = Trb::new();
+        trb.set_type(TrbType::EnableSlot);
+        let event = self.execute_command(trb)?;
+
+        let slot_id = event.get_slot_id();
+        if slot_id == 0 || slot_id as usize > MAX_USB_DEVICES {
+            return Err(DriverError::OutOfMemory);
+        }
+
+        let result = self.address_device(slot_id, port, speed)
+            .and_then(|_| self.read_device_descriptor(slot_id))
+            .and_then(|_| self.select_configuration(slot_id));
+
+        if result.is_err() {
+            let _ = self.release_device(slot_id);
+            return result;
         }
 
+        // A device no class driver claims stays addressed and configured
+        let _ = self.bind_class_driver(slot_id);
+
+        self.report_attach(slot_id);
         Ok(())
     }
 
-    /// Enable a USB port
-    fn enable_port(&mut self, port: u8) -> DriverResult<()> {
+    /// Tell the device manager about a newly enumerated device. The class
+    /// is the one a class driver bound to, since most devices only name
+    /// their class per interface.
+    fn report_attach(&self, slot_id: u8) {
+        let index = slot_id as usize - 1;
+        let device = match self.device(slot_id) {
+            Some(device) => device,
+            None => return,
+        };
+        let descriptor = match device.descriptor {
+            Some(descriptor) => descriptor,
+            None => return,
+        };
+
+        let class = if self.hid_devices[index].is_some() {
+            USB_CLASS_HID
+        } else if self.storage_devices[index].is_some() {
+            USB_CLASS_MASS_STORAGE
+        } else {
+            descriptor.device_class
+        };
+
+        let mut data = [0u8; 9];
+        data[0] = device.port;
+        data[1] = slot_id;
+        data[2..4].copy_from_slice(&{ descriptor.vendor_id }.to_le_bytes());
+        data[4..6].copy_from_slice(&{ descriptor.product_id }.to_le_bytes());
+        data[6] = class;
+        data[7] = descriptor.device_subclass;
+        data[8] = descriptor.device_protocol;
+        self.notify_device_manager(DEV_MGR_NOTIFY_USB_ATTACH, &data);
+    }
+
+    /// Send an attach/detach report; the reply port identifies this controller
+    fn notify_device_manager(&self, msg_id: u64, data: &[u8]) {
+        let mut payload = [0u8; 32];
+        let len = data.len().min(payload.len() - 8);
+        payload[0..8].copy_from_slice(&self.reply_port.to_le_bytes());
+        payload[8..8 + len].copy_from_slice(&data[..len]);
+
+        let mut msg = IpcMessage::new();
+        msg.msg_type = IPC_MSG_NOTIFICATION;
+        msg.msg_id = msg_id;
+        msg.set_inline_data(&payload[..8 + len]);
+        // Hotplug still works locally without a device manager
+        let _ = ipc_send(DEVICE_MANAGER_PORT, &msg);
+    }
+
+    /// Offer the device's configuration to the class drivers
+    fn bind_class_driver(&mut self, slot_id: u8) -> DriverResult<()> {
+        let mut header = [0u8; core::mem::size_of::<UsbConfigurationDescriptor>()];
+        self.get_descriptor(slot_id, USB_DESC_TYPE_CONFIGURATION, 0, &mut header)?;
+
+        let config = unsafe { core::ptr::read_unaligned(header.as_ptr() as *const UsbConfigurationDescriptor) };
+        let total_length = (config.total_length as usize).min(CONTROL_BUFFER_SIZE);
+
+        let mut raw = [0u8; CONTROL_BUFFER_SIZE];
+        self.get_descriptor(slot_id, USB_DESC_TYPE_CONFIGURATION, 0, &mut raw[..total_length])?;
+
+        if let Some(mut hid) = HidDevice::probe(slot_id, &raw[..total_length]) {
+            hid.start(self)?;
+            self.hid_devices[slot_id as usize - 1] = Some(hid);
+            return Ok(());
+        }
+
+        if let Some(mut storage) = MassStorageDevice::probe(slot_id, &raw[..total_length]) {
+            storage.start(self)?;
+            self.storage_devices[slot_id as usize - 1] = Some(storage);
+            return Ok(());
+        }
+
+        Err(DriverError::NotSupported)
+    }
+
+    /// Allocate the slot's contexts and EP0 ring and issue Address Device
+    fn address_device(&mut self, slot_id: u8, port: u8, speed: UsbSpeed) -> DriverResult<()> {
+        let device_context = self.alloc_dma(core::mem::size_of::<DeviceContext>(), 64)?;
+        let input_context = self.alloc_dma(core::mem::size_of::<InputContext>(), 64)?;
+        let buffer = self.alloc_dma(CONTROL_BUFFER_SIZE, 64)?;
+
+        let mut ep0_ring = TransferRing::new();
+        ep0_ring.init(self.alloc_dma(RING_BYTES, 64)?)?;
+
+        let input_context_phys = input_context.phys_addr();
+
         unsafe {
-            let mut portsc = self.read_port_register(port, 0);
+            let input = &mut *input_context.as_ptr::<InputContext>();
+            input.control.add_flags = INPUT_CTX_SLOT | INPUT_CTX_EP0;
+
+            input.slot_context.set_context_entries(1);
+            input.slot_context.set_speed(speed);
+            input.slot_context.set_root_hub_port(port + 1);
+
+            let ep0 = &mut input.endpoint_contexts[0];
+            ep0.set_ep_type(EndpointType::Control);
+            ep0.set_max_packet_size(speed.default_max_packet_size());
+            ep0.set_error_count(3);
+            ep0.set_dequeue_pointer(ep0_ring.get_phys_addr());
+            ep0.set_average_trb_length(8);
+
+            // Hand the output context to the controller
+            *self.dcbaa_virt.add(slot_id as usize) = device_context.phys_addr();
+        }
+
+        self.devices[slot_id as usize - 1] = Some(XhciDevice {
+            slot_id,
+            port,
+            speed,
+            state: UsbDeviceState::Default,
+            input_context,
+            device_context,
+            ep0_ring,
+            buffer,
+            descriptor: None,
+            configuration: 0,
+            endpoints: core::array::from_fn(|_| None),
+        });
+
+        let mut trb = Trb::new();
+        trb.parameter = input_context_phys;
+        trb.set_type(TrbType::AddressDevice);
+        trb.set_slot_id(slot_id);
+        self.execute_command(trb)?;
+
+        if let Some(device) = self.devices[slot_id as usize - 1].as_mut() {
+            device.state = UsbDeviceState::Address;
+        }
+
+        Ok(())
+    }
+
+    /// Read the device descriptor, fixing up EP0's packet size if the
+    /// default guess was wrong
+    fn read_device_descriptor(&mut self, slot_id: u8) -> DriverResult<()> {
+        let mut header = [0u8; 8];
+        self.get_descriptor(slot_id, USB_DESC_TYPE_DEVICE, 0, &mut header)?;
+
+        let max_packet_size = match header[7] {
+            // SuperSpeed reports an exponent
+            9 => 512,
+            size => size as u16,
+        };
+
+        let device = self.devices[slot_id as usize - 1].as_mut().ok_or(DriverError::DeviceNotFound)?;
+        let current = device.input().endpoint_contexts[0].get_max_packet_size();
+
+        if max_packet_size != 0 && max_packet_size != current {
+            let input_context_phys = device.input_context.phys_addr();
+            let input = device.input();
+            input.control.drop_flags = 0;
+            input.control.add_flags = INPUT_CTX_EP0;
+            input.endpoint_contexts[0].set_max_packet_size(max_packet_size);
+
+            let mut trb = Trb::new();
+            trb.parameter = input_context_phys;
+            trb.set_type(TrbType::EvaluateContext);
+            trb.set_slot_id(slot_id);
+            self.execute_command(trb)?;
+        }
+
+        let mut raw = [0u8; core::mem::size_of::<UsbDeviceDescriptor>()];
+        self.get_descriptor(slot_id, USB_DESC_TYPE_DEVICE, 0, &mut raw)?;
+
+        let descriptor = unsafe { core::ptr::read_unaligned(raw.as_ptr() as *const UsbDeviceDescriptor) };
+        if let Some(device) = self.devices[slot_id as usize - 1].as_mut() {
+            device.descriptor = Some(descriptor);
+        }
+
+        Ok(())
+    }
+
+    /// Select the device's first configuration with SET_CONFIGURATION
+    fn select_configuration(&mut self, slot_id: u8) -> DriverResult<()> {
+        let mut raw = [0u8; core::mem::size_of::<UsbConfigurationDescriptor>()];
+        self.get_descriptor(slot_id, USB_DESC_TYPE_CONFIGURATION, 0, &mut raw)?;
+
+        let config = unsafe { core::ptr::read_unaligned(raw.as_ptr() as *const UsbConfigurationDescriptor) };
+
+        let request = UsbDeviceRequest {
+            request_type: USB_REQ_DIRECTION_OUT | USB_REQ_TYPE_STANDARD | USB_REQ_RECIPIENT_DEVICE,
+            request: USB_REQ_SET_CONFIGURATION,
+            value: config.configuration_value as u16,
+            index: 0,
+            length: 0,
+        };
+        self.control_transfer(slot_id, request, &mut [])?;
+
+        if let Some(device) = self.devices[slot_id as usize - 1].as_mut() {
+            device.configuration = config.configuration_value;
+            device.state = UsbDeviceState::Configured;
+        }
+
+        Ok(())
+    }
+
+    /// GET_DESCRIPTOR into `buffer`
+    fn get_descriptor(&mut self, slot_id: u8, desc_type: u8, index: u8, buffer: &mut [u8]) -> DriverResult<usize> {
+        let request = UsbDeviceRequest {
+            request_type: USB_REQ_DIRECTION_IN | USB_REQ_TYPE_STANDARD | USB_REQ_RECIPIENT_DEVICE,
+            request: USB_REQ_GET_DESCRIPTOR,
+            value: ((desc_type as u16) << 8) | index as u16,
+            index: 0,
+            length: buffer.len() as u16,
+        };
+        self.control_transfer(slot_id, request, buffer)
+    }
+
+    /// Run a control transfer on the device's default endpoint. `data` is
+    /// the data stage; its direction comes from the request type.
+    pub fn control_transfer(&mut self, slot_id: u8, request: UsbDeviceRequest, data: &mut [u8]) -> DriverResult<usize> {
+        let device = self.device_mut(slot_id)?;
+
+        let length = data.len();
+        if length > CONTROL_BUFFER_SIZE || length != request.length as usize {
+            return Err(DriverError::InvalidArgument);
+        }
+        let dir_in = (request.request_type & USB_REQ_DIRECTION_IN) != 0;
+
+        if !dir_in && length > 0 {
+            unsafe {
+                core::ptr::copy_nonoverlapping(data.as_ptr(), device.buffer.as_ptr::<u8>(), length);
+            }
+        }
+
+        // Setup stage carries the request as immediate data
+        let mut setup = Trb::new();
+        setup.parameter = setup_packet(&request);
+        setup.status = 8;
+        setup.control = TRB_IDT | match (length, dir_in) {
+            (0, _) => TRB_TRT_NO_DATA,
+            (_, true) => TRB_TRT_IN,
+            (_, false) => TRB_TRT_OUT,
+        };
+        setup.set_type(TrbType::SetupStage);
+        device.ep0_ring.enqueue(&setup)?;
+
+        if length > 0 {
+            let mut data_stage = Trb::new();
+            data_stage.parameter = device.buffer.phys_addr();
+            data_stage.status = length as u32;
+            data_stage.control = if dir_in { TRB_DIR_IN } else { 0 };
+            data_stage.set_type(TrbType::DataStage);
+            device.ep0_ring.enqueue(&data_stage)?;
+        }
+
+        // Status stage runs opposite to the data stage (IN when there is none)
+        let mut status = Trb::new();
+        status.control = TRB_IOC | if length > 0 && dir_in { 0 } else { TRB_DIR_IN };
+        status.set_type(TrbType::StatusStage);
+        let status_phys = device.ep0_ring.enqueue(&status)?;
+
+        let buffer = device.buffer.as_ptr::<u8>();
+
+        self.ring_doorbell(slot_id, DOORBELL_EP0);
+        self.wait_for_completion(status_phys, TRANSFER_TIMEOUT_MS)?;
+
+        if dir_in && length > 0 {
+            unsafe {
+                core::ptr::copy_nonoverlapping(buffer, data.as_mut_ptr(), length);
+            }
+        }
+
+        Ok(length)
+    }
+
+    /// Add an endpoint to the slot with Configure Endpoint
+    fn add_endpoint(&mut self, slot_id: u8, endpoint: &UsbEndpointDescriptor) -> DriverResult<()> {
+        let dci = endpoint_dci(endpoint.endpoint_address);
+        if dci < 2 {
+            return Err(DriverError::InvalidArgument);
+        }
+
+        let transfer_type = endpoint.transfer_type();
+        let ep_type = match (transfer_type, endpoint.is_in()) {
+            (USB_EP_TYPE_ISOCHRONOUS, false) => EndpointType::IsochOut,
+            (USB_EP_TYPE_ISOCHRONOUS, true) => EndpointType::IsochIn,
+            (USB_EP_TYPE_BULK, false) => EndpointType::BulkOut,
+            (USB_EP_TYPE_BULK, true) => EndpointType::BulkIn,
+            (USB_EP_TYPE_INTERRUPT, false) => EndpointType::InterruptOut,
+            (USB_EP_TYPE_INTERRUPT, true) => EndpointType::InterruptIn,
+            _ => return Err(DriverError::NotSupported),
+        };
+
+        let mut ring = TransferRing::new();
+        ring.init(self.alloc_dma(RING_BYTES, 64)?)?;
+        let buffer = self.alloc_dma(ENDPOINT_BUFFER_SIZE, 64)?;
+
+        let device = self.device_mut(slot_id)?;
+        let speed = device.speed;
+        let max_packet_size = endpoint.max_packet_size & 0x7FF;
+        let input_context_phys = device.input_context.phys_addr();
+        let slot_context = device.output().slot_context;
+
+        let input = device.input();
+        input.control.drop_flags = 0;
+        input.control.add_flags = INPUT_CTX_SLOT | (1 << dci);
+
+        // Start from the controller's view of the slot
+        input.slot_context = slot_context;
+        if (input.slot_context.get_context_entries() as usize) < dci {
+            input.slot_context.set_context_entries(dci as u8);
+        }
+
+        let context = &mut input.endpoint_contexts[dci - 1];
+        *context = EndpointContext::new();
+        context.set_ep_type(ep_type);
+        context.set_max_packet_size(max_packet_size);
+        context.set_interval(endpoint_interval(speed, transfer_type, endpoint.interval));
+        context.set_dequeue_pointer(ring.get_phys_addr());
+        if transfer_type != USB_EP_TYPE_ISOCHRONOUS {
+            context.set_error_count(3);
+        }
+        if transfer_type == USB_EP_TYPE_BULK {
+            context.set_average_trb_length(3072);
+        } else {
+            context.set_average_trb_length(max_packet_size);
+            context.set_max_esit_payload(max_packet_size);
+        }
+
+        let isoch = match ep_type {
+            EndpointType::IsochOut => Some(IsochQueue::new(max_packet_size as usize, ENDPOINT_BUFFER_SIZE)),
+            _ => None,
+        };
+        device.endpoints[dci - 1] = Some(XhciEndpoint { ring, buffer, pending: None, isoch });
+
+        let mut trb = Trb::new();
+        trb.parameter = input_context_phys;
+        trb.set_type(TrbType::ConfigureEndpoint);
+        trb.set_slot_id(slot_id);
 
-            // Set port enable bit
-            portsc |= PORTSC_PED;
-            self.write_port_register(port, 0, portsc);
+        if let Err(e) = self.execute_command(trb) {
+            if let Ok(device) = self.device_mut(slot_id) {
+                device.endpoints[dci - 1] = None;
+            }
+            return Err(e);
         }
 
         Ok(())
     }
 
+    /// Queue a Normal TRB on an endpoint and ring its doorbell, returning
+    /// the TRB's address. OUT data must already be in the endpoint buffer.
+    fn queue_normal(&mut self, slot_id: u8, endpoint_address: u8, length: usize) -> DriverResult<u64> {
+        if length == 0 || length > ENDPOINT_BUFFER_SIZE {
+            return Err(DriverError::InvalidArgument);
+        }
+
+        let dci = endpoint_dci(endpoint_address);
+        let endpoint = self.endpoint_mut(slot_id, endpoint_address)?;
+
+        let mut trb = Trb::new();
+        trb.parameter = endpoint.buffer.phys_addr();
+        trb.status = length as u32;
+        trb.control = TRB_IOC | TRB_ISP;
+        trb.set_type(TrbType::Normal);
+        let trb_phys = endpoint.ring.enqueue(&trb)?;
+
+        self.ring_doorbell(slot_id, dci as u32);
+        Ok(trb_phys)
+    }
+
+    /// Retire isochronous TDs the controller has finished, in order
+    fn reap_isoch(&mut self, slot_id: u8, endpoint_address: u8) -> DriverResult<()> {
+        self.process_events();
+
+        loop {
+            let oldest = match self.endpoint_mut(slot_id, endpoint_address)?.isoch.as_ref() {
+                Some(queue) => queue.oldest(),
+                None => return Err(DriverError::InvalidArgument),
+            };
+            let trb_phys = match oldest {
+                Some(trb_phys) => trb_phys,
+                None => return Ok(()),
+            };
+
+            // Missed service intervals still complete the TD; the packet is
+            // simply lost, which is all isochronous delivery promises
+            if self.take_completion(trb_phys).is_none() {
+                return Ok(());
+            }
+            if let Some(queue) = self.endpoint_mut(slot_id, endpoint_address)?.isoch.as_mut() {
+                queue.retire_oldest();
+            }
+        }
+    }
+
+    /// Copy a completed IN transfer out of the endpoint buffer
+    fn complete_in(&mut self, slot_id: u8, endpoint_address: u8, event: &Trb, requested: usize, data: &mut [u8]) -> DriverResult<usize> {
+        let endpoint = self.endpoint_mut(slot_id, endpoint_address)?;
+        let length = requested.saturating_sub(event.get_transfer_residual()).min(data.len());
+
+        unsafe {
+            core::ptr::copy_nonoverlapping(endpoint.buffer.as_ptr::<u8>(), data.as_mut_ptr(), length);
+        }
+
+        Ok(length)
+    }
+
+    /// Get an enumerated device by slot ID for modification
+    fn device_mut(&mut self, slot_id: u8) -> DriverResult<&mut XhciDevice> {
+        let index = (slot_id as usize).checked_sub(1).ok_or(DriverError::InvalidArgument)?;
+        self.devices.get_mut(index)
+            .and_then(|d| d.as_mut())
+            .ok_or(DriverError::DeviceNotFound)
+    }
+
+    /// Get a configured endpoint of a device
+    fn endpoint_mut(&mut self, slot_id: u8, endpoint_address: u8) -> DriverResult<&mut XhciEndpoint> {
+        let dci = endpoint_dci(endpoint_address);
+        self.device_mut(slot_id)?
+            .endpoints
+            .get_mut(dci.wrapping_sub(1))
+            .and_then(|e| e.as_mut())
+            .ok_or(DriverError::InvalidArgument)
+    }
+
+    /// Disable a device's slot and forget it
+    fn release_device(&mut self, slot_id: u8) -> DriverResult<()> {
+        let mut trb = Trb::new();
+        trb.set_type(TrbType::DisableSlot);
+        trb.set_slot_id(slot_id);
+        let result = self.execute_command(trb).map(|_| ());
+
+        unsafe {
+            *self.dcbaa_virt.add(slot_id as usize) = 0;
+        }
+        if let Some(entry) = self.devices.get_mut(slot_id as usize - 1) {
+            *entry = None;
+        }
+        if let Some(entry) = self.hid_devices.get_mut(slot_id as usize - 1) {
+            *entry = None;
+        }
+        if let Some(entry) = self.storage_devices.get_mut(slot_id as usize - 1) {
+            *entry = None;
+        }
+
+        result
+    }
+
+    /// Place a command on the command ring and wait for its completion.
+    /// A command that doesn't complete in time is aborted.
+    fn execute_command(&mut self, trb: Trb) -> DriverResult<Trb> {
+        let trb_phys = self.command_ring.enqueue(&trb)?;
+        self.ring_doorbell(0, 0);
+
+        let result = self.wait_for_completion(trb_phys, COMMAND_TIMEOUT_MS);
+        if let Err(DriverError::Timeout) = result {
+            unsafe {
+                core::ptr::write_volatile(core::ptr::addr_of_mut!((*self.op_regs).crcr), CRCR_CA);
+            }
+        }
+        result
+    }
+
+    /// Ring a doorbell; slot 0 is the host controller (command ring)
+    fn ring_doorbell(&mut self, slot_id: u8, target: u32) {
+        unsafe {
+            core::ptr::write_volatile(self.doorbell_regs.add(slot_id as usize), target);
+        }
+    }
+
+    /// Wait for the command completion or transfer event reporting the TRB
+    /// at `trb_phys`. Other events seen meanwhile are recorded for later.
+    fn wait_for_completion(&mut self, trb_phys: u64, timeout_ms: u64) -> DriverResult<Trb> {
+        let deadline = get_uptime_ms() + timeout_ms;
+
+        loop {
+            if let Some(event) = self.take_completion(trb_phys) {
+                let code = event.get_completion_code();
+                if code == TrbCompletionCode::Success as u8 || code == TrbCompletionCode::ShortPacket as u8 {
+                    return Ok(event);
+                }
+                return Err(DriverError::IoError);
+            }
+
+            if get_uptime_ms() >= deadline {
+                return Err(DriverError::Timeout);
+            }
+
+            self.process_events();
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Remove and return the stored completion for `trb_phys`
+    fn take_completion(&mut self, trb_phys: u64) -> Option<Trb> {
+        self.completions.iter_mut()
+            .find(|c| c.map_or(false, |event| event.parameter == trb_phys))
+            .and_then(|c| c.take())
+    }
+
+    /// Drain the event ring if the interrupter has signalled (or always
+    /// when running without an IRQ)
+    fn process_events(&mut self) {
+        if self.irq_enabled && !EVENTS_PENDING.swap(false, Ordering::AcqRel) {
+            return;
+        }
+
+        while let Some(event) = self.next_event() {
+            self.record_event(&event);
+        }
+    }
+
+    /// Take the next event off the event ring and advance ERDP past it
+    fn next_event(&mut self) -> Option<Trb> {
+        let event = self.event_ring.dequeue()?;
+
+        unsafe {
+            let interrupter = &mut (*self.runtime_regs).interrupters[0];
+            interrupter.erdp = self.event_ring.get_dequeue_ptr() | ERDP_EHB;
+        }
+
+        Some(event)
+    }
+
+    /// Store completions for their waiters and note port changes. When
+    /// the completion table is full the oldest entry (most likely for a
+    /// TRB whose waiter already timed out) is overwritten.
+    fn record_event(&mut self, event: &Trb) {
+        match event.get_type() {
+            TrbType::CommandCompletionEvent | TrbType::TransferEvent => {
+                let index = self.completions.iter().position(|c| c.is_none())
+                    .unwrap_or(self.next_completion);
+                self.completions[index] = Some(*event);
+                self.next_completion = (index + 1) % MAX_COMPLETIONS;
+            }
+            TrbType::PortStatusChangeEvent => {
+                let port_id = event.get_port_id() as usize;
+                if port_id >= 1 && port_id <= MAX_PORTS {
+                    self.port_changed[port_id - 1] = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Handle pending events: connects/disconnects and class driver input
+    pub fn poll_events(&mut self) {
+        self.process_events();
+
+        for port in 0..self.max_ports {
+            if self.port_changed[port as usize] {
+                self.port_changed[port as usize] = false;
+                let _ = self.check_port(port);
+            }
+        }
+
+        for index in 0..MAX_USB_DEVICES {
+            // The class driver borrows the controller while it runs
+            if let Some(mut hid) = self.hid_devices[index].take() {
+                if hid.poll(self).is_ok() {
+                    self.hid_devices[index] = Some(hid);
+                }
+            }
+            if let Some(mut storage) = self.storage_devices[index].take() {
+                if storage.poll(self).is_ok() {
+                    self.storage_devices[index] = Some(storage);
+                }
+            }
+        }
+    }
+
     /// Read port register
     unsafe fn read_port_register(&self, port: u8, offset: usize) -> u32 {
         let port_base = (self.op_regs as usize) + 0x400 + (port as usize * 0x10);
@@ -357,19 +1097,214 @@ impl XhciDriver {
         core::ptr::write_volatile(reg_addr, value);
     }
 
-    /// Read PCI BAR
-    fn read_pci_bar(&self, bus: u8, dev: u8, func: u8, bar: u8) -> DriverResult<u64> {
-        // Use syscall to read PCI configuration space
-        // This is a placeholder - actual implementation depends on syscall interface
-        Ok(0xFEDC0000) // Example BAR address
+    /// Read a configuration space dword through the PCI driver
+    fn pci_read_config(&self, bus: u8, dev: u8, func: u8, offset: u8) -> DriverResult<u32> {
+        let mut msg = IpcMessage::new();
+        msg.msg_type = IPC_MSG_REQUEST;
+        msg.msg_id = MSG_PCI_READ_CONFIG;
+        msg.reply_port = self.reply_port;
+        msg.set_inline_data(&[bus, dev, func, offset]);
+        ipc_send(PCI_DRIVER_PORT, &msg).map_err(|_| DriverError::IoError)?;
+
+        let mut response = IpcMessage::new();
+        ipc_receive(self.reply_port, &mut response).map_err(|_| DriverError::IoError)?;
+
+        if response.msg_type != IPC_MSG_RESPONSE || response.msg_id != MSG_PCI_READ_CONFIG || response.inline_size != 4 {
+            return Err(DriverError::IoError);
+        }
+
+        let data = response.get_inline_data();
+        Ok(u32::from_le_bytes([data[0], data[1], data[2], data[3]]))
+    }
+
+    /// Allocate zeroed DMA memory
+    fn alloc_dma(&self, size: usize, align: usize) -> DriverResult<DmaRegion> {
+        DmaRegion::alloc(size, align)
+    }
+}
+
+impl UsbHost for XhciDriver {
+    fn control_transfer(&mut self, slot_id: u8, request: UsbDeviceRequest, data: &mut [u8]) -> DriverResult<usize> {
+        XhciDriver::control_transfer(self, slot_id, request, data)
+    }
+
+    fn configure_endpoint(&mut self, slot_id: u8, endpoint: &UsbEndpointDescriptor) -> DriverResult<()> {
+        self.add_endpoint(slot_id, endpoint)
+    }
+
+    fn submit_in(&mut self, slot_id: u8, endpoint_address: u8, length: usize) -> DriverResult<()> {
+        if self.endpoint_mut(slot_id, endpoint_address)?.pending.is_some() {
+            return Err(DriverError::InvalidArgument);
+        }
+
+        let trb_phys = self.queue_normal(slot_id, endpoint_address, length)?;
+        self.endpoint_mut(slot_id, endpoint_address)?.pending = Some((trb_phys, length));
+        Ok(())
+    }
+
+    fn poll_in(&mut self, slot_id: u8, endpoint_address: u8, data: &mut [u8]) -> DriverResult<Option<usize>> {
+        let (trb_phys, requested) = match self.endpoint_mut(slot_id, endpoint_address)?.pending {
+            Some(pending) => pending,
+            None => return Err(DriverError::InvalidArgument),
+        };
+
+        self.process_events();
+        let event = match self.take_completion(trb_phys) {
+            Some(event) => event,
+            None => return Ok(None),
+        };
+        self.endpoint_mut(slot_id, endpoint_address)?.pending = None;
+
+        let code = event.get_completion_code();
+        if code != TrbCompletionCode::Success as u8 && code != TrbCompletionCode::ShortPacket as u8 {
+            return Err(DriverError::IoError);
+        }
+
+        self.complete_in(slot_id, endpoint_address, &event, requested, data).map(Some)
+    }
+
+    fn transfer(&mut self, slot_id: u8, endpoint_address: u8, data: &mut [u8], timeout_ms: u64) -> DriverResult<usize> {
+        let dir_in = (endpoint_address & USB_REQ_DIRECTION_IN) != 0;
+        let mut done = 0;
+
+        // Large transfers go through the bounce buffer a piece at a time
+        while done < data.len() {
+            let chunk = (data.len() - done).min(ENDPOINT_BUFFER_SIZE);
+
+            if !dir_in {
+                let endpoint = self.endpoint_mut(slot_id, endpoint_address)?;
+                unsafe {
+                    core::ptr::copy_nonoverlapping(data[done..].as_ptr(), endpoint.buffer.as_ptr::<u8>(), chunk);
+                }
+            }
+
+            let trb_phys = self.queue_normal(slot_id, endpoint_address, chunk)?;
+            let event = self.wait_for_completion(trb_phys, timeout_ms)?;
+
+            let length = if dir_in {
+                self.complete_in(slot_id, endpoint_address, &event, chunk, &mut data[done..done + chunk])?
+            } else {
+                chunk.saturating_sub(event.get_transfer_residual())
+            };
+
+            done += length;
+
+            // A short packet ends the transfer
+            if length < chunk {
+                break;
+            }
+        }
+
+        Ok(done)
     }
 
-    /// Allocate DMA memory
-    fn alloc_dma(&self, size: usize, align: usize) -> DriverResult<u64> {
-        // Use syscall to allocate DMA memory
-        // This is a placeholder - actual implementation depends on syscall interface
-        Ok(0x1000000) // Example DMA address
+    fn clear_halt(&mut self, slot_id: u8, endpoint_address: u8) -> DriverResult<()> {
+        let dci = endpoint_dci(endpoint_address);
+        let device = self.device_mut(slot_id)?;
+        let state = device.output().endpoint_contexts.get(dci.wrapping_sub(1))
+            .map(|c| c.get_state())
+            .ok_or(DriverError::InvalidArgument)?;
+
+        let endpoint = self.endpoint_mut(slot_id, endpoint_address)?;
+        endpoint.pending = None;
+        let dequeue = endpoint.ring.get_enqueue_ptr();
+
+        if state == EndpointState::Halted {
+            let mut trb = Trb::new();
+            trb.set_type(TrbType::ResetEndpoint);
+            trb.set_slot_id(slot_id);
+            trb.set_endpoint_id(dci as u8);
+            self.execute_command(trb)?;
+
+            // Skip whatever the controller had left on the ring
+            let mut trb = Trb::new();
+            trb.parameter = dequeue;
+            trb.set_type(TrbType::SetTRDequeuePointer);
+            trb.set_slot_id(slot_id);
+            trb.set_endpoint_id(dci as u8);
+            self.execute_command(trb)?;
+        }
+
+        // Resets the device's data toggle as well as its halt
+        let request = UsbDeviceRequest {
+            request_type: USB_REQ_DIRECTION_OUT | USB_REQ_TYPE_STANDARD | USB_REQ_RECIPIENT_ENDPOINT,
+            request: USB_REQ_CLEAR_FEATURE,
+            value: USB_FEATURE_ENDPOINT_HALT,
+            index: endpoint_address as u16,
+            length: 0,
+        };
+        XhciDriver::control_transfer(self, slot_id, request, &mut []).map(|_| ())
     }
+
+    fn submit_isoch_out(&mut self, slot_id: u8, endpoint_address: u8, packet: &[u8]) -> DriverResult<bool> {
+        self.reap_isoch(slot_id, endpoint_address)?;
+
+        let dci = endpoint_dci(endpoint_address);
+        let endpoint = self.endpoint_mut(slot_id, endpoint_address)?;
+        let queue = endpoint.isoch.as_mut().ok_or(DriverError::InvalidArgument)?;
+        if packet.is_empty() || packet.len() > queue.slot_size {
+            return Err(DriverError::InvalidArgument);
+        }
+        if queue.is_full() {
+            return Ok(false);
+        }
+
+        let offset = queue.next_slot() * queue.slot_size;
+        unsafe {
+            core::ptr::copy_nonoverlapping(packet.as_ptr(), endpoint.buffer.as_ptr::<u8>().add(offset), packet.len());
+        }
+
+        // One packet per TD, scheduled for the next free service interval
+        let mut trb = Trb::new();
+        trb.parameter = endpoint.buffer.phys_addr() + offset as u64;
+        trb.status = packet.len() as u32;
+        trb.control = TRB_IOC | TRB_SIA;
+        trb.set_type(TrbType::Isoch);
+        let trb_phys = endpoint.ring.enqueue(&trb)?;
+        queue.push(trb_phys);
+
+        self.ring_doorbell(slot_id, dci as u32);
+        Ok(true)
+    }
+}
+
+/// Endpoint context interval exponent for an endpoint descriptor's bInterval
+fn endpoint_interval(speed: UsbSpeed, transfer_type: u8, b_interval: u8) -> u8 {
+    match (speed, transfer_type) {
+        (_, USB_EP_TYPE_BULK) => 0,
+        // Full/low speed interrupt endpoints give their period in frames
+        (UsbSpeed::Full | UsbSpeed::Low, USB_EP_TYPE_INTERRUPT) => {
+            let microframes = (b_interval.max(1) as u32) * 8;
+            (31 - microframes.leading_zeros()).clamp(3, 10) as u8
+        }
+        // Everything else encodes 2^(bInterval - 1) (micro)frames
+        (UsbSpeed::Full | UsbSpeed::Low, _) => b_interval.clamp(1, 16) + 2,
+        _ => b_interval.clamp(1, 16) - 1,
+    }
+}
+
+/// Spin until `done` returns true, giving up after `timeout_ms`
+fn wait_until<F: FnMut() -> bool>(timeout_ms: u64, mut done: F) -> DriverResult<()> {
+    let deadline = get_uptime_ms() + timeout_ms;
+
+    loop {
+        if done() {
+            return Ok(());
+        }
+        if get_uptime_ms() >= deadline {
+            return Err(DriverError::Timeout);
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Encode a setup packet as Setup Stage immediate data
+fn setup_packet(request: &UsbDeviceRequest) -> u64 {
+    (request.request_type as u64)
+        | ((request.request as u64) << 8)
+        | ((request.value as u64) << 16)
+        | ((request.index as u64) << 32)
+        | ((request.length as u64) << 48)
 }
 
 #[no_mangle]
@@ -383,7 +1318,7 @@ pub extern "C" fn _start() -> ! {
             // Driver initialized successfully
             // Enter main event loop
             loop {
-                // Process events
+                driver.poll_events();
             }
         }
         Err(_) => {
@@ -2,6 +2,10 @@
 //!
 //! Device context structures for USB device communication.
 
+use super::xhci_dma::DmaRegion;
+use super::xhci_ring::TransferRing;
+use usb_common::{UsbDeviceDescriptor, UsbDeviceState};
+
 /// Device Context
 #[repr(C, align(64))]
 pub struct DeviceContext {
@@ -9,6 +13,31 @@ pub struct DeviceContext {
     pub endpoint_contexts: [EndpointContext; 31],
 }
 
+/// Input Control Context
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct InputControlContext {
+    pub drop_flags: u32,
+    pub add_flags: u32,
+    pub reserved: [u32; 6],
+}
+
+/// Input Context
+///
+/// Passed to Address Device / Evaluate Context. The control context comes
+/// first, immediately followed by the slot and endpoint contexts.
+#[repr(C, align(64))]
+pub struct InputContext {
+    pub control: InputControlContext,
+    pub slot_context: SlotContext,
+    pub endpoint_contexts: [EndpointContext; 31],
+}
+
+/// Input control context flag for the slot context
+pub const INPUT_CTX_SLOT: u32 = 1 << 0;
+/// Input control context flag for the default control endpoint
+pub const INPUT_CTX_EP0: u32 = 1 << 1;
+
 /// Slot Context
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -32,7 +61,11 @@ impl SlotContext {
     }
 
     pub fn set_context_entries(&mut self, entries: u8) {
-        self.dw0 = (self.dw0 & !0x1F0000) | ((entries as u32) << 27);
+        self.dw0 = (self.dw0 & !0xF8000000) | ((entries as u32) << 27);
+    }
+
+    pub fn get_context_entries(&self) -> u8 {
+        (self.dw0 >> 27) as u8
     }
 
     pub fn set_speed(&mut self, speed: UsbSpeed) {
@@ -85,6 +118,32 @@ impl EndpointContext {
     pub fn set_dequeue_pointer(&mut self, ptr: u64) {
         self.dw2 = ptr | 1; // Set DCS bit
     }
+
+    pub fn get_state(&self) -> EndpointState {
+        EndpointState::from(self.dw0 & 0x7)
+    }
+
+    pub fn get_max_packet_size(&self) -> u16 {
+        (self.dw1 >> 16) as u16
+    }
+
+    pub fn set_error_count(&mut self, count: u8) {
+        self.dw1 = (self.dw1 & !0x6) | (((count & 0x3) as u32) << 1);
+    }
+
+    pub fn set_average_trb_length(&mut self, length: u16) {
+        self.dw4 = (self.dw4 & !0xFFFF) | (length as u32);
+    }
+
+    /// Service interval as an exponent: 2^interval * 125us
+    pub fn set_interval(&mut self, interval: u8) {
+        self.dw0 = (self.dw0 & !0xFF0000) | ((interval as u32) << 16);
+    }
+
+    /// Bytes moved per service interval (periodic endpoints)
+    pub fn set_max_esit_payload(&mut self, payload: u16) {
+        self.dw4 = (self.dw4 & !0xFFFF0000) | ((payload as u32) << 16);
+    }
 }
 
 /// USB Speed
@@ -98,6 +157,29 @@ pub enum UsbSpeed {
     SuperPlus = 5,
 }
 
+impl UsbSpeed {
+    /// Decode the PORTSC port speed field
+    pub fn from_port_speed(value: u32) -> Option<Self> {
+        match value {
+            1 => Some(UsbSpeed::Full),
+            2 => Some(UsbSpeed::Low),
+            3 => Some(UsbSpeed::High),
+            4 => Some(UsbSpeed::Super),
+            5 => Some(UsbSpeed::SuperPlus),
+            _ => None,
+        }
+    }
+
+    /// Default control endpoint packet size before the device descriptor is read
+    pub fn default_max_packet_size(self) -> u16 {
+        match self {
+            UsbSpeed::Low | UsbSpeed::Full => 8,
+            UsbSpeed::High => 64,
+            UsbSpeed::Super | UsbSpeed::SuperPlus => 512,
+        }
+    }
+}
+
 /// Slot State
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -121,6 +203,31 @@ impl From<u32> for SlotState {
     }
 }
 
+/// Endpoint State
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndpointState {
+    Disabled = 0,
+    Running = 1,
+    Halted = 2,
+    Stopped = 3,
+    Error = 4,
+    Unknown = 0xFF,
+}
+
+impl From<u32> for EndpointState {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => EndpointState::Disabled,
+            1 => EndpointState::Running,
+            2 => EndpointState::Halted,
+            3 => EndpointState::Stopped,
+            4 => EndpointState::Error,
+            _ => EndpointState::Unknown,
+        }
+    }
+}
+
 /// Endpoint Type
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
@@ -134,3 +241,129 @@ pub enum EndpointType {
     BulkIn = 6,
     InterruptIn = 7,
 }
+
+/// Device Context Index of an endpoint address (EP0 is DCI 1)
+pub fn endpoint_dci(endpoint_address: u8) -> usize {
+    let number = (endpoint_address & 0x0F) as usize;
+    let dir_in = (endpoint_address & 0x80) != 0;
+    number * 2 + dir_in as usize
+}
+
+/// Endpoint other than EP0 added with Configure Endpoint
+pub struct XhciEndpoint {
+    pub ring: TransferRing,
+
+    /// Bounce buffer for transfers on this endpoint
+    pub buffer: DmaRegion,
+
+    /// Queued IN transfer: TRB address and requested length
+    pub pending: Option<(u64, usize)>,
+
+    /// Transfer descriptors of an isochronous OUT endpoint
+    pub isoch: Option<IsochQueue>,
+}
+
+/// Isochronous TDs queued on an endpoint
+pub const MAX_ISOCH_TDS: usize = 8;
+
+/// Isochronous OUT transfer descriptors in flight. Each TD carries one
+/// service interval's packet from its own slot of the endpoint buffer;
+/// TDs complete in order, so the oldest slot is always the next free one.
+pub struct IsochQueue {
+    /// Bytes per buffer slot (the endpoint's max packet size)
+    pub slot_size: usize,
+    pub slots: usize,
+    /// TRB addresses, oldest at `head`
+    inflight: [u64; MAX_ISOCH_TDS],
+    head: usize,
+    count: usize,
+    next_slot: usize,
+}
+
+impl IsochQueue {
+    pub fn new(slot_size: usize, buffer_size: usize) -> Self {
+        let slot_size = slot_size.max(1);
+        Self {
+            slot_size,
+            slots: (buffer_size / slot_size).min(MAX_ISOCH_TDS),
+            inflight: [0; MAX_ISOCH_TDS],
+            head: 0,
+            count: 0,
+            next_slot: 0,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.count >= self.slots
+    }
+
+    /// Buffer slot the next TD uses
+    pub fn next_slot(&self) -> usize {
+        self.next_slot
+    }
+
+    pub fn oldest(&self) -> Option<u64> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.inflight[self.head])
+        }
+    }
+
+    pub fn push(&mut self, trb_phys: u64) {
+        self.inflight[(self.head + self.count) % MAX_ISOCH_TDS] = trb_phys;
+        self.count += 1;
+        self.next_slot = (self.next_slot + 1) % self.slots.max(1);
+    }
+
+    pub fn retire_oldest(&mut self) {
+        if self.count > 0 {
+            self.head = (self.head + 1) % MAX_ISOCH_TDS;
+            self.count -= 1;
+        }
+    }
+}
+
+/// USB device attached to a device slot
+pub struct XhciDevice {
+    pub slot_id: u8,
+    /// Root hub port (0-based)
+    pub port: u8,
+    pub speed: UsbSpeed,
+    pub state: UsbDeviceState,
+
+    /// Input context used for Address Device / Evaluate Context
+    pub input_context: DmaRegion,
+
+    /// Output device context owned by the controller
+    pub device_context: DmaRegion,
+
+    /// Default control endpoint transfer ring
+    pub ep0_ring: TransferRing,
+
+    /// Bounce buffer for control transfer data stages
+    pub buffer: DmaRegion,
+
+    pub descriptor: Option<UsbDeviceDescriptor>,
+    pub configuration: u8,
+
+    /// Configured endpoints indexed by DCI - 1
+    pub endpoints: [Option<XhciEndpoint>; 31],
+}
+
+impl XhciDevice {
+    /// Input context contents
+    pub fn input(&mut self) -> &mut InputContext {
+        unsafe { &mut *self.input_context.as_ptr::<InputContext>() }
+    }
+
+    /// Output device context as written by the controller
+    pub fn output(&self) -> &DeviceContext {
+        unsafe { &*self.device_context.as_ptr::<DeviceContext>() }
+    }
+
+    /// Slot state as reported by the controller
+    pub fn slot_state(&self) -> SlotState {
+        self.output().slot_context.get_slot_state()
+    }
+}
@@ -0,0 +1,51 @@
+//! XHCI DMA Memory
+//!
+//! Controller data structures need both a CPU pointer and the bus address
+//! programmed into the hardware.
+
+use driver_framework::dma::DmaBuffer;
+use driver_framework::{DriverError, DriverResult};
+
+/// DMA allocations are whole pages
+const DMA_PAGE_SIZE: usize = 4096;
+
+/// Cache-coherent allocation (kernel DMA_FLAG_COHERENT)
+const DMA_FLAG_COHERENT: u64 = 1 << 0;
+
+/// Zeroed DMA memory with its physical address
+pub struct DmaRegion {
+    buffer: DmaBuffer,
+    phys_addr: u64,
+}
+
+impl DmaRegion {
+    /// Allocate `size` bytes aligned to `align` (at most one page)
+    pub fn alloc(size: usize, align: usize) -> DriverResult<Self> {
+        if size == 0 || !align.is_power_of_two() || align > DMA_PAGE_SIZE {
+            return Err(DriverError::InvalidArgument);
+        }
+
+        let mut buffer = DmaBuffer::alloc(size, DMA_FLAG_COHERENT).map_err(|_| DriverError::OutOfMemory)?;
+        let phys_addr = buffer.get_physical().map_err(|_| DriverError::IoError)?;
+
+        if phys_addr % align as u64 != 0 {
+            return Err(DriverError::IoError);
+        }
+
+        unsafe {
+            buffer.as_mut_slice().fill(0);
+        }
+
+        Ok(Self { buffer, phys_addr })
+    }
+
+    /// CPU pointer to the memory
+    pub fn as_ptr<T>(&self) -> *mut T {
+        self.buffer.as_ptr() as *mut T
+    }
+
+    /// Bus address to program into the controller
+    pub fn phys_addr(&self) -> u64 {
+        self.phys_addr
+    }
+}
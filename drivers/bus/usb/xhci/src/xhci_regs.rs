@@ -2,6 +2,12 @@
 //!
 //! Hardware register structures for XHCI controllers.
 
+/// Capability register offsets, for byte-exact reads through MmioRegion
+pub const CAP_CAPLENGTH: usize = 0x00;
+pub const CAP_HCSPARAMS1: usize = 0x04;
+pub const CAP_DBOFF: usize = 0x14;
+pub const CAP_RTSOFF: usize = 0x18;
+
 #[repr(C)]
 pub struct XhciCapabilityRegs {
     pub caplength: u8,       // Capability register length
@@ -74,6 +80,9 @@ pub const CRCR_CRR: u64 = 1 << 3;          // Command ring running
 pub const IMAN_IP: u32 = 1 << 0;           // Interrupt pending
 pub const IMAN_IE: u32 = 1 << 1;           // Interrupt enable
 
+// Event Ring Dequeue Pointer (ERDP) bits
+pub const ERDP_EHB: u64 = 1 << 3;          // Event handler busy
+
 // Port Status and Control Register (PORTSC) bits
 pub const PORTSC_CCS: u32 = 1 << 0;        // Current connect status
 pub const PORTSC_PED: u32 = 1 << 1;        // Port enabled/disabled
@@ -87,3 +96,16 @@ pub const PORTSC_PEC: u32 = 1 << 18;       // Port enabled/disabled change
 pub const PORTSC_WRC: u32 = 1 << 19;       // Warm port reset change
 pub const PORTSC_OCC: u32 = 1 << 20;       // Over-current change
 pub const PORTSC_PRC: u32 = 1 << 21;       // Port reset change
+pub const PORTSC_PLC: u32 = 1 << 22;       // Port link state change
+pub const PORTSC_CEC: u32 = 1 << 23;       // Port config error change
+
+/// Change bits; written as 1 to acknowledge
+pub const PORTSC_CHANGE_MASK: u32 = PORTSC_CSC | PORTSC_PEC | PORTSC_WRC
+    | PORTSC_OCC | PORTSC_PRC | PORTSC_PLC | PORTSC_CEC;
+
+/// Bits that must be written back unchanged. PED is write-1-to-disable
+/// and the change bits are write-1-to-clear, so neither is included.
+pub const PORTSC_PRESERVE_MASK: u32 = PORTSC_PP | (0x3 << 14) | (0x7 << 25);
+
+/// Doorbell target for the default control endpoint
+pub const DOORBELL_EP0: u32 = 1;
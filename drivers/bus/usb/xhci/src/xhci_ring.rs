@@ -2,14 +2,19 @@
 //!
 //! Command and event rings for XHCI controller communication.
 
-use super::xhci_trb::{Trb, TrbType};
+use super::xhci_dma::DmaRegion;
+use super::xhci_trb::{Trb, TrbType, TRB_TOGGLE_CYCLE};
 use driver_framework::DriverResult;
 
 /// Ring size (number of TRBs)
 const RING_SIZE: usize = 256;
 
+/// Bytes of DMA memory needed for one ring
+pub const RING_BYTES: usize = RING_SIZE * core::mem::size_of::<Trb>();
+
 /// Command Ring
 pub struct CommandRing {
+    memory: Option<DmaRegion>,
     trbs: *mut Trb,
     phys_addr: u64,
     enqueue_idx: usize,
@@ -19,6 +24,7 @@ pub struct CommandRing {
 impl CommandRing {
     pub fn new() -> Self {
         Self {
+            memory: None,
             trbs: core::ptr::null_mut(),
             phys_addr: 0,
             enqueue_idx: 0,
@@ -26,11 +32,11 @@ impl CommandRing {
         }
     }
 
-    pub fn init(&mut self) -> DriverResult<()> {
-        // Allocate DMA memory for ring (aligned to 64 bytes)
-        let size = RING_SIZE * core::mem::size_of::<Trb>();
-        self.phys_addr = self.alloc_dma(size, 64)?;
-        self.trbs = self.phys_addr as *mut Trb;
+    /// Set up the ring in `memory` (RING_BYTES, 64-byte aligned)
+    pub fn init(&mut self, memory: DmaRegion) -> DriverResult<()> {
+        self.phys_addr = memory.phys_addr();
+        self.trbs = memory.as_ptr();
+        self.memory = Some(memory);
 
         // Zero initialize
         unsafe {
@@ -41,39 +47,23 @@ impl CommandRing {
         unsafe {
             let link_trb = &mut *self.trbs.add(RING_SIZE - 1);
             link_trb.parameter = self.phys_addr;
+            link_trb.control = TRB_TOGGLE_CYCLE;
             link_trb.set_type(TrbType::Link);
-            link_trb.set_cycle_bit(self.cycle_bit);
+            link_trb.set_cycle_bit(!self.cycle_bit);
         }
 
         Ok(())
     }
 
-    pub fn enqueue(&mut self, trb: &Trb) -> DriverResult<()> {
-        if self.enqueue_idx >= RING_SIZE - 1 {
-            // Need to wrap around
-            self.cycle_bit = !self.cycle_bit;
-            self.enqueue_idx = 0;
-        }
-
-        unsafe {
-            let target = &mut *self.trbs.add(self.enqueue_idx);
-            *target = *trb;
-            target.set_cycle_bit(self.cycle_bit);
-        }
-
-        self.enqueue_idx += 1;
-
-        Ok(())
+    /// Enqueue a command TRB, returning its physical address so the
+    /// completion event can be matched to it
+    pub fn enqueue(&mut self, trb: &Trb) -> DriverResult<u64> {
+        enqueue_trb(self.trbs, self.phys_addr, &mut self.enqueue_idx, &mut self.cycle_bit, trb)
     }
 
     pub fn get_phys_addr(&self) -> u64 {
         self.phys_addr
     }
-
-    fn alloc_dma(&self, size: usize, align: usize) -> DriverResult<u64> {
-        // Placeholder - actual implementation via syscall
-        Ok(0x2000000)
-    }
 }
 
 /// Event Ring Segment Table Entry
@@ -84,8 +74,13 @@ struct EventRingSegmentTableEntry {
     _reserved: [u8; 6],
 }
 
+/// Bytes of DMA memory needed for the segment table
+pub const SEGMENT_TABLE_BYTES: usize = core::mem::size_of::<EventRingSegmentTableEntry>();
+
 /// Event Ring
 pub struct EventRing {
+    memory: Option<DmaRegion>,
+    table_memory: Option<DmaRegion>,
     trbs: *mut Trb,
     phys_addr: u64,
     segment_table: *mut EventRingSegmentTableEntry,
@@ -97,6 +92,8 @@ pub struct EventRing {
 impl EventRing {
     pub fn new() -> Self {
         Self {
+            memory: None,
+            table_memory: None,
             trbs: core::ptr::null_mut(),
             phys_addr: 0,
             segment_table: core::ptr::null_mut(),
@@ -106,21 +103,22 @@ impl EventRing {
         }
     }
 
-    pub fn init(&mut self) -> DriverResult<()> {
-        // Allocate DMA memory for event ring
-        let ring_size = RING_SIZE * core::mem::size_of::<Trb>();
-        self.phys_addr = self.alloc_dma(ring_size, 64)?;
-        self.trbs = self.phys_addr as *mut Trb;
+    /// Set up a single-segment event ring in `memory` (RING_BYTES) with
+    /// its segment table in `table_memory` (SEGMENT_TABLE_BYTES)
+    pub fn init(&mut self, memory: DmaRegion, table_memory: DmaRegion) -> DriverResult<()> {
+        self.phys_addr = memory.phys_addr();
+        self.trbs = memory.as_ptr();
+        self.memory = Some(memory);
 
         // Zero initialize
         unsafe {
             core::ptr::write_bytes(self.trbs, 0, RING_SIZE);
         }
 
-        // Allocate segment table (single segment)
-        let table_size = core::mem::size_of::<EventRingSegmentTableEntry>();
-        self.segment_table_phys = self.alloc_dma(table_size, 64)?;
-        self.segment_table = self.segment_table_phys as *mut EventRingSegmentTableEntry;
+        // Segment table (single segment)
+        self.segment_table_phys = table_memory.phys_addr();
+        self.segment_table = table_memory.as_ptr();
+        self.table_memory = Some(table_memory);
 
         // Initialize segment table entry
         unsafe {
@@ -133,15 +131,14 @@ impl EventRing {
 
     pub fn dequeue(&mut self) -> Option<Trb> {
         unsafe {
-            let trb = &*self.trbs.add(self.dequeue_idx);
+            // The controller writes event TRBs behind our back
+            let result = core::ptr::read_volatile(self.trbs.add(self.dequeue_idx));
 
             // Check if TRB is valid (cycle bit matches)
-            if trb.get_cycle_bit() != self.cycle_bit {
+            if result.get_cycle_bit() != self.cycle_bit {
                 return None;
             }
 
-            let result = *trb;
-
             self.dequeue_idx += 1;
             if self.dequeue_idx >= RING_SIZE {
                 self.dequeue_idx = 0;
@@ -156,15 +153,98 @@ impl EventRing {
         self.segment_table_phys
     }
 
+    /// Physical address of the next TRB to be consumed. The low bits of
+    /// ERDP hold the segment index, not the cycle state.
     pub fn get_dequeue_ptr(&self) -> u64 {
+        self.phys_addr + (self.dequeue_idx * core::mem::size_of::<Trb>()) as u64
+    }
+}
+
+/// Transfer Ring
+///
+/// One per endpoint.
+pub struct TransferRing {
+    memory: Option<DmaRegion>,
+    trbs: *mut Trb,
+    phys_addr: u64,
+    enqueue_idx: usize,
+    cycle_bit: bool,
+}
+
+impl TransferRing {
+    pub fn new() -> Self {
+        Self {
+            memory: None,
+            trbs: core::ptr::null_mut(),
+            phys_addr: 0,
+            enqueue_idx: 0,
+            cycle_bit: true,
+        }
+    }
+
+    /// Set up the ring in `memory` (RING_BYTES, 64-byte aligned)
+    pub fn init(&mut self, memory: DmaRegion) -> DriverResult<()> {
+        self.phys_addr = memory.phys_addr();
+        self.trbs = memory.as_ptr();
+        self.memory = Some(memory);
+        self.enqueue_idx = 0;
+        self.cycle_bit = true;
+
+        unsafe {
+            core::ptr::write_bytes(self.trbs, 0, RING_SIZE);
+
+            let link_trb = &mut *self.trbs.add(RING_SIZE - 1);
+            link_trb.parameter = self.phys_addr;
+            link_trb.control = TRB_TOGGLE_CYCLE;
+            link_trb.set_type(TrbType::Link);
+            link_trb.set_cycle_bit(!self.cycle_bit);
+        }
+
+        Ok(())
+    }
+
+    /// Enqueue a transfer TRB, returning its physical address
+    pub fn enqueue(&mut self, trb: &Trb) -> DriverResult<u64> {
+        enqueue_trb(self.trbs, self.phys_addr, &mut self.enqueue_idx, &mut self.cycle_bit, trb)
+    }
+
+    /// Next enqueue position with the producer cycle state in bit 0, as
+    /// Set TR Dequeue Pointer expects it
+    pub fn get_enqueue_ptr(&self) -> u64 {
+        let offset = (self.enqueue_idx * core::mem::size_of::<Trb>()) as u64;
+        (self.phys_addr + offset) | self.cycle_bit as u64
+    }
+
+    pub fn get_phys_addr(&self) -> u64 {
+        self.phys_addr
+    }
+}
+
+/// Write `trb` at the enqueue position of a producer ring. When the link
+/// TRB is reached it is handed to the controller with the current cycle
+/// state and the producer cycle state flips.
+fn enqueue_trb(trbs: *mut Trb, phys_addr: u64, enqueue_idx: &mut usize, cycle_bit: &mut bool, trb: &Trb) -> DriverResult<u64> {
+    if *enqueue_idx >= RING_SIZE - 1 {
         unsafe {
-            let ptr = self.trbs.add(self.dequeue_idx) as u64;
-            ptr | if self.cycle_bit { 1 } else { 0 }
+            (*trbs.add(RING_SIZE - 1)).set_cycle_bit(*cycle_bit);
         }
+        *cycle_bit = !*cycle_bit;
+        *enqueue_idx = 0;
     }
 
-    fn alloc_dma(&self, size: usize, align: usize) -> DriverResult<u64> {
-        // Placeholder - actual implementation via syscall
-        Ok(0x3000000)
+    let index = *enqueue_idx;
+    unsafe {
+        let target = &mut *trbs.add(index);
+        let mut entry = *trb;
+        entry.set_cycle_bit(*cycle_bit);
+        // Publish the TRB with the cycle bit written last
+        core::ptr::write_volatile(&mut target.parameter, entry.parameter);
+        core::ptr::write_volatile(&mut target.status, entry.status);
+        core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+        core::ptr::write_volatile(&mut target.control, entry.control);
     }
+
+    *enqueue_idx += 1;
+
+    Ok(phys_addr + (index * core::mem::size_of::<Trb>()) as u64)
 }
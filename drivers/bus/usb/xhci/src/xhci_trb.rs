@@ -39,8 +39,51 @@ impl Trb {
     pub fn get_cycle_bit(&self) -> bool {
         (self.control & 1) != 0
     }
+
+    pub fn set_slot_id(&mut self, slot_id: u8) {
+        self.control = (self.control & !0xFF000000) | ((slot_id as u32) << 24);
+    }
+
+    /// Target endpoint (DCI) of an endpoint command
+    pub fn set_endpoint_id(&mut self, dci: u8) {
+        self.control = (self.control & !0x1F0000) | (((dci & 0x1F) as u32) << 16);
+    }
+
+    /// Slot ID of a command completion or transfer event
+    pub fn get_slot_id(&self) -> u8 {
+        (self.control >> 24) as u8
+    }
+
+    /// Completion code of an event TRB
+    pub fn get_completion_code(&self) -> u8 {
+        (self.status >> 24) as u8
+    }
+
+    /// Bytes not transferred, from a transfer event
+    pub fn get_transfer_residual(&self) -> usize {
+        (self.status & 0xFFFFFF) as usize
+    }
+
+    /// Root hub port (1-based) of a port status change event
+    pub fn get_port_id(&self) -> u8 {
+        ((self.parameter >> 24) & 0xFF) as u8
+    }
 }
 
+// TRB control field bits
+pub const TRB_CYCLE: u32 = 1 << 0;
+pub const TRB_TOGGLE_CYCLE: u32 = 1 << 1;  // Link TRB
+pub const TRB_ISP: u32 = 1 << 2;           // Interrupt on short packet
+pub const TRB_IOC: u32 = 1 << 5;           // Interrupt on completion
+pub const TRB_IDT: u32 = 1 << 6;           // Immediate data
+pub const TRB_DIR_IN: u32 = 1 << 16;       // Data/status stage direction
+pub const TRB_SIA: u32 = 1 << 31;          // Isoch: start as soon as possible
+
+// Setup stage transfer type (TRT)
+pub const TRB_TRT_NO_DATA: u32 = 0 << 16;
+pub const TRB_TRT_OUT: u32 = 2 << 16;
+pub const TRB_TRT_IN: u32 = 3 << 16;
+
 /// TRB Types
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -0,0 +1,71 @@
+//! Block device geometry
+//!
+//! Reply layout for the storage drivers' GET_INFO operation (op 3, request
+//! `[unit:1]`): `[sectors:8][sector_size:4][model_len:1][model]`. A unit
+//! that does not exist gets an empty reply.
+
+/// Longest model string carried (ATA IDENTIFY has 40 characters)
+pub const MAX_MODEL_LEN: usize = 40;
+
+/// Encoded size without the model string
+pub const BLOCK_INFO_HEADER_SIZE: usize = 13;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockDeviceInfo {
+    pub sectors: u64,
+    pub sector_size: u32,
+    model: [u8; MAX_MODEL_LEN],
+    model_len: usize,
+}
+
+impl BlockDeviceInfo {
+    /// `model` is trimmed of the space padding drives report and truncated
+    pub fn new(sectors: u64, sector_size: u32, model: &[u8]) -> Self {
+        let end = model.iter().rposition(|&c| c != b' ' && c != 0).map_or(0, |i| i + 1);
+        let len = end.min(MAX_MODEL_LEN);
+        let mut info = BlockDeviceInfo { sectors, sector_size, model: [0; MAX_MODEL_LEN], model_len: len };
+        info.model[..len].copy_from_slice(&model[..len]);
+        info
+    }
+
+    pub fn model(&self) -> &[u8] {
+        &self.model[..self.model_len]
+    }
+
+    /// Capacity in bytes
+    pub fn capacity(&self) -> u64 {
+        self.sectors.saturating_mul(self.sector_size as u64)
+    }
+
+    /// Write the reply into `out`, returning its length
+    pub fn encode(&self, out: &mut [u8]) -> Option<usize> {
+        let len = BLOCK_INFO_HEADER_SIZE + self.model_len;
+        if out.len() < len {
+            return None;
+        }
+        out[0..8].copy_from_slice(&self.sectors.to_le_bytes());
+        out[8..12].copy_from_slice(&self.sector_size.to_le_bytes());
+        out[12] = self.model_len as u8;
+        out[BLOCK_INFO_HEADER_SIZE..len].copy_from_slice(self.model());
+        Some(len)
+    }
+
+    /// Parse a reply. Older drivers that send only sectors and sector
+    /// size are accepted with an empty model.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < 12 {
+            return None;
+        }
+        let sectors = u64::from_le_bytes([data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]]);
+        let sector_size = u32::from_le_bytes([data[8], data[9], data[10], data[11]]);
+        if sector_size == 0 {
+            return None;
+        }
+
+        let model = match data.get(12) {
+            Some(&len) if len > 0 => data.get(BLOCK_INFO_HEADER_SIZE..BLOCK_INFO_HEADER_SIZE + len as usize)?,
+            _ => &[],
+        };
+        Some(BlockDeviceInfo::new(sectors, sector_size, model))
+    }
+}
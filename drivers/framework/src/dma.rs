@@ -1,18 +1,69 @@
 //! DMA buffer management
+//!
+//! A buffer can be shared with one other process at a time per handle, so
+//! a client can hand its buffer to a driver for zero-copy I/O. Both
+//! processes then see the same physical pages:
+//!
+//! - Nothing orders their accesses. The owner must not touch the buffer
+//!   while the peer (or the peer's device) is using it, and vice versa;
+//!   the IPC request/response around the transfer is the usual fence.
+//! - A read-only share maps the pages without write permission, so a
+//!   peer write faults rather than corrupting the owner's data.
+//! - The owner can revoke a share at any time, and freeing the buffer
+//!   revokes all of them. The peer's mapping disappears, so it must stop
+//!   using the buffer (and any DMA it programmed into it) first.
 
 use crate::syscalls;
 
+/// Names a buffer shared with another process; sent to the peer over IPC
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SharedHandle(pub u64);
+
 /// DMA buffer wrapper
 pub struct DmaBuffer {
     ptr: *mut u8,
     size: usize,
+    writable: bool,
+    /// Set when this is a peer's view of another process's buffer, along
+    /// with its physical address
+    adopted: Option<(SharedHandle, u64)>,
 }
 
 impl DmaBuffer {
     /// Allocate DMA buffer
     pub fn alloc(size: usize, flags: u64) -> Result<Self, ()> {
         let ptr = syscalls::dma_alloc(size as u64, flags).map_err(|_| ())?;
-        Ok(Self { ptr, size })
+        Ok(Self { ptr, size, writable: true, adopted: None })
+    }
+    
+    /// Map this buffer into `target_pid`, writable or read-only. Only the
+    /// allocating process can share a buffer. See the module docs for the
+    /// aliasing rules while the share exists.
+    pub fn share(&self, target_pid: u64, writable: bool) -> Result<SharedHandle, ()> {
+        if self.adopted.is_some() {
+            return Err(());
+        }
+        syscalls::dma_share(self.ptr as u64, target_pid, writable)
+            .map(SharedHandle)
+            .map_err(|_| ())
+    }
+    
+    /// Unmap a share from its peer
+    pub fn revoke(&self, handle: SharedHandle) -> Result<(), ()> {
+        syscalls::dma_unshare(handle.0).map_err(|_| ())
+    }
+    
+    /// Take up a buffer another process shared with this one. Dropping
+    /// the result releases the mapping; the owner's buffer is untouched.
+    pub fn adopt(handle: SharedHandle) -> Result<Self, ()> {
+        let mut info = syscalls::DmaShareInfo::default();
+        syscalls::dma_adopt(handle.0, &mut info).map_err(|_| ())?;
+        Ok(Self {
+            ptr: info.vaddr as *mut u8,
+            size: info.size as usize,
+            writable: info.writable != 0,
+            adopted: Some((handle, info.physical)),
+        })
     }
     
     /// Get pointer
@@ -25,20 +76,35 @@ impl DmaBuffer {
         self.size
     }
     
-    /// Get as mutable slice (unsafe)
+    /// Whether this process may write the buffer
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+    
+    /// Get as mutable slice (unsafe). Writing through it faults if the
+    /// buffer was adopted read-only.
     pub unsafe fn as_mut_slice(&mut self) -> &mut [u8] {
         core::slice::from_raw_parts_mut(self.ptr, self.size)
     }
     
     /// Get physical address
     pub fn get_physical(&self) -> Result<u64, ()> {
-        syscalls::dma_get_physical(self.ptr as u64).map_err(|_| ())
+        match self.adopted {
+            Some((_, physical)) => Ok(physical),
+            None => syscalls::dma_get_physical(self.ptr as u64).map_err(|_| ()),
+        }
     }
 }
 
 impl Drop for DmaBuffer {
     fn drop(&mut self) {
-        let _ = syscalls::dma_free(self.ptr);
+        match self.adopted {
+            Some((handle, _)) => {
+                let _ = syscalls::dma_unshare(handle.0);
+            }
+            None => {
+                let _ = syscalls::dma_free(self.ptr);
+            }
+        }
     }
 }
-
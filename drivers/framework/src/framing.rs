@@ -0,0 +1,52 @@
+//! Framing of IPC payloads larger than a message's inline data
+//!
+//! A framed message starts its inline data with a fixed-size header and
+//! follows it with a payload. A payload that fits beside the header goes
+//! inline too. A larger one goes in a shared memory region: the sender
+//! creates and fills it, and the message carries the region id in `buffer`
+//! and the payload length in `buffer_size`. The receiver maps the region,
+//! copies the payload out and destroys it. `buffer_size` comes from the
+//! sender, so the receiver checks it against the region's real size.
+
+/// Bytes of inline data in a message
+pub const IPC_INLINE_SIZE: usize = 64;
+
+/// Where a received message's payload is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Payload {
+    /// Inline data from `offset`
+    Inline { offset: usize, len: usize },
+    /// A shared memory region the receiver must destroy once copied out
+    Shared { shm_id: u64, len: usize },
+}
+
+/// Whether a payload can travel inline after `header_len` header bytes
+pub fn fits_inline(header_len: usize, payload_len: usize) -> bool {
+    header_len
+        .checked_add(payload_len)
+        .is_some_and(|total| total <= IPC_INLINE_SIZE)
+}
+
+/// The payload of a message with `header_len` header bytes, given its
+/// `inline_size`, `buffer` and `buffer_size`; `None` if the message is too
+/// short to hold the header
+pub fn locate(header_len: usize, inline_size: usize, buffer: u64, buffer_size: usize) -> Option<Payload> {
+    let inline_size = inline_size.min(IPC_INLINE_SIZE);
+    if header_len > inline_size {
+        return None;
+    }
+    if buffer_size > 0 {
+        if buffer == 0 {
+            return None;
+        }
+        return Some(Payload::Shared { shm_id: buffer, len: buffer_size });
+    }
+    Some(Payload::Inline { offset: header_len, len: inline_size - header_len })
+}
+
+/// Bytes to copy out of a shared payload of `len` bytes, given the size of
+/// its region and the room to copy it into; `None` if the payload claims
+/// more than the region holds or does not fit
+pub fn shared_copy_len(len: usize, region_size: usize, room: usize) -> Option<usize> {
+    (len <= region_size && len <= room).then_some(len)
+}
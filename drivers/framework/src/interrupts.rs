@@ -5,12 +5,31 @@ use crate::syscalls;
 /// IRQ handler function type
 pub type IrqHandler = extern "C" fn();
 
-/// Register IRQ handler
+/// Handler for a line other devices may share. It must check its own
+/// device's interrupt-status register and return whether the device raised
+/// the interrupt; every handler on the line runs before the kernel sends EOI.
+pub type SharedIrqHandler = extern "C" fn() -> bool;
+
+/// Register IRQ handler, claiming the line exclusively
 pub fn register_irq(irq: u8, handler: IrqHandler) -> Result<(), ()> {
     syscalls::irq_register(irq, handler).map_err(|_| ())
 }
 
-/// Unregister IRQ handler
+/// Register a handler on a shared IRQ line (PCI INTx lines often are).
+/// Fails if another driver holds the line exclusively.
+pub fn register_irq_shared(irq: u8, handler: SharedIrqHandler) -> Result<(), ()> {
+    syscalls::irq_register_shared(irq, handler).map_err(|_| ())
+}
+
+/// Claim an IRQ line exclusively and have each interrupt arrive on
+/// `port_id` as an `IPC_MSG_ID_IRQ` notification, so the driver can wait
+/// for interrupts and requests in the same receive
+pub fn bind_irq_port(irq: u8, port_id: u64) -> Result<(), ()> {
+    syscalls::irq_bind_port(irq, port_id).map_err(|_| ())
+}
+
+/// Unregister this driver's handlers for an IRQ; other drivers sharing the
+/// line keep theirs
 pub fn unregister_irq(irq: u8) -> Result<(), ()> {
     syscalls::irq_unregister(irq).map_err(|_| ())
 }
@@ -1,6 +1,8 @@
 //! IPC communication for drivers
 
+use crate::framing::{self, Payload};
 use crate::syscalls;
+use crate::DriverError;
 
 /// IPC message types
 pub const IPC_MSG_DATA: u32 = 0;
@@ -8,6 +10,10 @@ pub const IPC_MSG_REQUEST: u32 = 1;
 pub const IPC_MSG_RESPONSE: u32 = 2;
 pub const IPC_MSG_NOTIFICATION: u32 = 3;
 
+/// Notification id for IRQs bound to a port; the inline data is a u64 mask
+/// of the IRQs raised since the last one (bit n = IRQ n)
+pub const IPC_MSG_ID_IRQ: u64 = 0xFFFF_FFFF_0000_0001;
+
 
 /// IPC message structure (must match kernel/include/ipc/ipc.h)
 #[repr(C)]
@@ -19,6 +25,8 @@ pub struct IpcMessage {
     pub inline_data: [u8; 64],
     pub buffer: *mut u8,
     pub buffer_size: usize,
+    /// Port responses go to; 0 if the sender expects none
+    pub reply_port: u64,
 }
 
 impl IpcMessage {
@@ -31,6 +39,7 @@ impl IpcMessage {
             inline_data: [0; 64],
             buffer: core::ptr::null_mut(),
             buffer_size: 0,
+            reply_port: 0,
         }
     }
     
@@ -55,6 +64,16 @@ pub fn ipc_send(port_id: u64, msg: &IpcMessage) -> Result<(), u64> {
     }
 }
 
+/// Reply to a received message on its reply port
+pub fn ipc_reply(request: &IpcMessage, response: &IpcMessage) -> Result<(), u64> {
+    let result = syscalls::ipc_reply(request as *const IpcMessage as u64, response as *const IpcMessage as u64);
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(result)
+    }
+}
+
 /// Receive IPC message
 pub fn ipc_receive(port_id: u64, msg: &mut IpcMessage) -> Result<(), u64> {
     let result = syscalls::ipc_receive(port_id, msg as *mut IpcMessage as u64);
@@ -65,6 +84,51 @@ pub fn ipc_receive(port_id: u64, msg: &mut IpcMessage) -> Result<(), u64> {
     }
 }
 
+/// Receive IPC message if one is queued, without blocking
+pub fn ipc_try_receive(port_id: u64, msg: &mut IpcMessage) -> Result<(), u64> {
+    let result = syscalls::ipc_try_receive(port_id, msg as *mut IpcMessage as u64);
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(result)
+    }
+}
+
+/// Kernel ERR_TIMEOUT
+const IPC_ERR_TIMEOUT: u64 = (-7i64) as u64;
+
+/// Receive IPC message, blocking until one arrives or timeout_ms elapses
+pub fn ipc_receive_timeout(port_id: u64, msg: &mut IpcMessage, timeout_ms: u64) -> Result<(), DriverError> {
+    match syscalls::ipc_receive_timeout(port_id, msg as *mut IpcMessage as u64, timeout_ms) {
+        0 => Ok(()),
+        IPC_ERR_TIMEOUT => Err(DriverError::Timeout),
+        _ => Err(DriverError::IoError),
+    }
+}
+
+/// Handle a burst of requests: wait up to `timeout_ms` for one, then take
+/// those already queued behind it without blocking, at most `max` in all.
+/// Returns how many were handled; it only waits while the port is empty.
+pub fn ipc_drain(
+    port_id: u64,
+    msg: &mut IpcMessage,
+    timeout_ms: u64,
+    max: usize,
+    mut handle: impl FnMut(&IpcMessage),
+) -> usize {
+    if max == 0 || ipc_receive_timeout(port_id, msg, timeout_ms).is_err() {
+        return 0;
+    }
+    let mut handled = 0;
+    loop {
+        handle(msg);
+        handled += 1;
+        if handled == max || ipc_try_receive(port_id, msg).is_err() {
+            return handled;
+        }
+    }
+}
+
 /// Create IPC port
 pub fn ipc_create_port() -> Result<u64, ()> {
     let port_id = syscalls::ipc_create_port();
@@ -75,3 +139,112 @@ pub fn ipc_create_port() -> Result<u64, ()> {
     }
 }
 
+/// Destroy IPC port
+pub fn ipc_destroy_port(port_id: u64) -> Result<(), ()> {
+    if syscalls::ipc_destroy_port(port_id) == 0 {
+        Ok(())
+    } else {
+        Err(())
+    }
+}
+
+/// Copy `payload` into a new shared memory region and return its id
+fn share_payload(payload: &[u8]) -> Result<u64, u64> {
+    let shm_id = syscalls::shm_create(payload.len() as u64)?;
+    let vaddr = match syscalls::shm_map(shm_id, 0, 0) {
+        Ok(vaddr) => vaddr,
+        Err(e) => {
+            let _ = syscalls::shm_destroy(shm_id);
+            return Err(e);
+        }
+    };
+    unsafe {
+        core::ptr::copy_nonoverlapping(payload.as_ptr(), vaddr, payload.len());
+    }
+    let _ = syscalls::shm_unmap(shm_id, vaddr);
+    Ok(shm_id)
+}
+
+/// `header` with `payload` framed after its inline data, plus the shared
+/// memory region holding the payload if it did not fit inline
+fn frame(header: &IpcMessage, payload: &[u8]) -> Result<(IpcMessage, Option<u64>), u64> {
+    let header_len = header.inline_size as usize;
+    let mut msg = IpcMessage::new();
+    msg.msg_id = header.msg_id;
+    msg.msg_type = header.msg_type;
+    msg.reply_port = header.reply_port;
+    msg.set_inline_data(header.get_inline_data());
+
+    if framing::fits_inline(header_len, payload.len()) {
+        msg.inline_data[header_len..header_len + payload.len()].copy_from_slice(payload);
+        msg.inline_size = (header_len + payload.len()) as u32;
+        return Ok((msg, None));
+    }
+
+    let shm_id = share_payload(payload)?;
+    msg.buffer = shm_id as *mut u8;
+    msg.buffer_size = payload.len();
+    Ok((msg, Some(shm_id)))
+}
+
+/// Send `header` (its id, type, reply port and inline data) followed by
+/// `payload` of any length, inline if it fits and in shared memory if not
+pub fn ipc_send_large(port_id: u64, header: &IpcMessage, payload: &[u8]) -> Result<(), u64> {
+    let (msg, shared) = frame(header, payload)?;
+    ipc_send(port_id, &msg).inspect_err(|_| {
+        if let Some(shm_id) = shared {
+            let _ = syscalls::shm_destroy(shm_id);
+        }
+    })
+}
+
+/// Reply to `request` with `header` followed by `payload` of any length
+pub fn ipc_reply_large(request: &IpcMessage, header: &IpcMessage, payload: &[u8]) -> Result<(), u64> {
+    let (msg, shared) = frame(header, payload)?;
+    ipc_reply(request, &msg).inspect_err(|_| {
+        if let Some(shm_id) = shared {
+            let _ = syscalls::shm_destroy(shm_id);
+        }
+    })
+}
+
+/// Copy the payload after `header_len` header bytes of a received message
+/// into `out`, returning its length. A shared payload is destroyed even if
+/// it does not fit, so every framed message must be taken exactly once.
+pub fn ipc_take_payload(msg: &IpcMessage, header_len: usize, out: &mut [u8]) -> Result<usize, DriverError> {
+    let payload = framing::locate(header_len, msg.inline_size as usize, msg.buffer as u64, msg.buffer_size)
+        .ok_or(DriverError::InvalidArgument)?;
+
+    match payload {
+        Payload::Inline { offset, len } => {
+            let dest = out.get_mut(..len).ok_or(DriverError::InvalidArgument)?;
+            dest.copy_from_slice(&msg.inline_data[offset..offset + len]);
+            Ok(len)
+        }
+        Payload::Shared { shm_id, len } => {
+            let result = syscalls::shm_size(shm_id)
+                .map_err(|_| DriverError::IoError)
+                .and_then(|region_size| {
+                    framing::shared_copy_len(len, region_size, out.len()).ok_or(DriverError::InvalidArgument)
+                })
+                .and_then(|len| {
+                    let vaddr = syscalls::shm_map(shm_id, 0, syscalls::SHM_FLAG_READ_ONLY)
+                        .map_err(|_| DriverError::IoError)?;
+                    unsafe {
+                        core::ptr::copy_nonoverlapping(vaddr, out.as_mut_ptr(), len);
+                    }
+                    let _ = syscalls::shm_unmap(shm_id, vaddr);
+                    Ok(len)
+                });
+            let _ = syscalls::shm_destroy(shm_id);
+            result
+        }
+    }
+}
+
+/// Receive a framed message, copying the payload after its `header_len`
+/// header bytes into `out`; the header stays in `msg`
+pub fn ipc_recv_large(port_id: u64, msg: &mut IpcMessage, header_len: usize, out: &mut [u8]) -> Result<usize, DriverError> {
+    ipc_receive(port_id, msg).map_err(|_| DriverError::IoError)?;
+    ipc_take_payload(msg, header_len, out)
+}
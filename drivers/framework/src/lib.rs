@@ -6,15 +6,24 @@
 #![no_std]
 
 pub mod ipc;
+pub mod framing;
 pub mod syscalls;
 pub mod mmio;
 pub mod dma;
 pub mod interrupts;
+pub mod pci;
+pub mod block;
+pub mod wait;
 
 // Re-export commonly used items
 pub use ipc::{IpcMessage, IPC_MSG_REQUEST, IPC_MSG_RESPONSE};
 
 /// Driver trait that all user-space drivers must implement
+///
+/// Lifecycle: `init` once when the driver process starts (IPC ports,
+/// registration), then for each device the device manager offers, `probe`
+/// and, if it returns true, `start` with the same `DeviceInfo`. `stop`
+/// releases the hardware of every started device.
 pub trait Driver {
     /// Initialize the driver
     fn init(&mut self) -> Result<(), DriverError>;
@@ -22,8 +31,8 @@ pub trait Driver {
     /// Probe for device compatibility
     fn probe(&self, device_info: &DeviceInfo) -> bool;
     
-    /// Start the driver
-    fn start(&mut self) -> Result<(), DriverError>;
+    /// Start driving a device accepted by `probe`
+    fn start(&mut self, device_info: &DeviceInfo) -> Result<(), DriverError>;
     
     /// Stop the driver
     fn stop(&mut self) -> Result<(), DriverError>;
@@ -37,6 +46,7 @@ pub trait Driver {
 
 /// Device information passed to drivers
 #[repr(C)]
+#[derive(Debug, Clone, Copy)]
 pub struct DeviceInfo {
     pub device_type: DeviceType,
     pub vendor_id: u16,
@@ -53,6 +63,7 @@ pub struct DeviceInfo {
 }
 
 #[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DeviceType {
     Pci = 1,
     Usb = 2,
@@ -73,9 +84,14 @@ pub enum DriverError {
     AlreadyInitialized,
     NotInitialized,
     PermissionDenied,
+    /// Resource busy; the operation would have to wait
+    WouldBlock,
     Unknown,
 }
 
+/// Result type used by drivers
+pub type DriverResult<T> = Result<T, DriverError>;
+
 impl From<u64> for DriverError {
     fn from(code: u64) -> Self {
         match code {
@@ -88,6 +104,7 @@ impl From<u64> for DriverError {
             7 => DriverError::AlreadyInitialized,
             8 => DriverError::NotInitialized,
             9 => DriverError::PermissionDenied,
+            10 => DriverError::WouldBlock,
             _ => DriverError::Unknown,
         }
     }
@@ -105,6 +122,7 @@ impl Into<u64> for DriverError {
             DriverError::AlreadyInitialized => 7,
             DriverError::NotInitialized => 8,
             DriverError::PermissionDenied => 9,
+            DriverError::WouldBlock => 10,
             DriverError::Unknown => 255,
         }
     }
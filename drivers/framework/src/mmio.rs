@@ -1,4 +1,9 @@
 //! Memory-Mapped I/O utilities
+//!
+//! Every accessor checks the offset against the mapped length and the
+//! access width's alignment. Out-of-range or misaligned reads return 0 and
+//! writes are dropped, so a bad offset cannot touch memory outside the
+//! device's registers.
 
 use crate::syscalls;
 
@@ -20,68 +25,125 @@ impl MmioRegion {
         self.base
     }
     
-    /// Read 8-bit value
-    pub unsafe fn read8(&self, offset: usize) -> u8 {
-        if offset >= self.size {
+    /// Base address as an integer, for drivers that keep register block
+    /// addresses
+    pub fn base_virt_addr(&self) -> usize {
+        self.base as usize
+    }
+    
+    /// Mapped length in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+    
+    /// Whether a `width`-byte access at `offset` is aligned and inside the
+    /// mapping
+    fn valid(&self, offset: usize, width: usize) -> bool {
+        offset % width == 0 && offset.checked_add(width).map_or(false, |end| end <= self.size)
+    }
+    
+    /// Read 8-bit register
+    pub fn read_u8(&self, offset: usize) -> u8 {
+        if !self.valid(offset, 1) {
             return 0;
         }
-        core::ptr::read_volatile(self.base.add(offset))
+        unsafe { core::ptr::read_volatile(self.base.add(offset)) }
     }
     
-    /// Write 8-bit value
-    pub unsafe fn write8(&self, offset: usize, value: u8) {
-        if offset >= self.size {
+    /// Write 8-bit register
+    pub fn write_u8(&self, offset: usize, value: u8) {
+        if !self.valid(offset, 1) {
             return;
         }
-        core::ptr::write_volatile(self.base.add(offset), value);
+        unsafe { core::ptr::write_volatile(self.base.add(offset), value) }
     }
     
-    /// Read 16-bit value
-    pub unsafe fn read16(&self, offset: usize) -> u16 {
-        if offset + 2 > self.size {
+    /// Read 16-bit register
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        if !self.valid(offset, 2) {
             return 0;
         }
-        core::ptr::read_volatile(self.base.add(offset) as *const u16)
+        unsafe { core::ptr::read_volatile(self.base.add(offset) as *const u16) }
     }
     
-    /// Write 16-bit value
-    pub unsafe fn write16(&self, offset: usize, value: u16) {
-        if offset + 2 > self.size {
+    /// Write 16-bit register
+    pub fn write_u16(&self, offset: usize, value: u16) {
+        if !self.valid(offset, 2) {
             return;
         }
-        core::ptr::write_volatile(self.base.add(offset) as *mut u16, value);
+        unsafe { core::ptr::write_volatile(self.base.add(offset) as *mut u16, value) }
     }
     
-    /// Read 32-bit value
-    pub unsafe fn read32(&self, offset: usize) -> u32 {
-        if offset + 4 > self.size {
+    /// Read 32-bit register
+    pub fn read_u32(&self, offset: usize) -> u32 {
+        if !self.valid(offset, 4) {
             return 0;
         }
-        core::ptr::read_volatile(self.base.add(offset) as *const u32)
+        unsafe { core::ptr::read_volatile(self.base.add(offset) as *const u32) }
     }
     
-    /// Write 32-bit value
-    pub unsafe fn write32(&self, offset: usize, value: u32) {
-        if offset + 4 > self.size {
+    /// Write 32-bit register
+    pub fn write_u32(&self, offset: usize, value: u32) {
+        if !self.valid(offset, 4) {
             return;
         }
-        core::ptr::write_volatile(self.base.add(offset) as *mut u32, value);
+        unsafe { core::ptr::write_volatile(self.base.add(offset) as *mut u32, value) }
     }
     
-    /// Read 64-bit value
-    pub unsafe fn read64(&self, offset: usize) -> u64 {
-        if offset + 8 > self.size {
+    /// Read 64-bit register
+    pub fn read_u64(&self, offset: usize) -> u64 {
+        if !self.valid(offset, 8) {
             return 0;
         }
-        core::ptr::read_volatile(self.base.add(offset) as *const u64)
+        unsafe { core::ptr::read_volatile(self.base.add(offset) as *const u64) }
     }
     
-    /// Write 64-bit value
-    pub unsafe fn write64(&self, offset: usize, value: u64) {
-        if offset + 8 > self.size {
+    /// Write 64-bit register
+    pub fn write_u64(&self, offset: usize, value: u64) {
+        if !self.valid(offset, 8) {
             return;
         }
-        core::ptr::write_volatile(self.base.add(offset) as *mut u64, value);
+        unsafe { core::ptr::write_volatile(self.base.add(offset) as *mut u64, value) }
+    }
+    
+    /// Read 8-bit value
+    pub unsafe fn read8(&self, offset: usize) -> u8 {
+        self.read_u8(offset)
+    }
+    
+    /// Write 8-bit value
+    pub unsafe fn write8(&self, offset: usize, value: u8) {
+        self.write_u8(offset, value)
+    }
+    
+    /// Read 16-bit value
+    pub unsafe fn read16(&self, offset: usize) -> u16 {
+        self.read_u16(offset)
+    }
+    
+    /// Write 16-bit value
+    pub unsafe fn write16(&self, offset: usize, value: u16) {
+        self.write_u16(offset, value)
+    }
+    
+    /// Read 32-bit value
+    pub unsafe fn read32(&self, offset: usize) -> u32 {
+        self.read_u32(offset)
+    }
+    
+    /// Write 32-bit value
+    pub unsafe fn write32(&self, offset: usize, value: u32) {
+        self.write_u32(offset, value)
+    }
+    
+    /// Read 64-bit value
+    pub unsafe fn read64(&self, offset: usize) -> u64 {
+        self.read_u64(offset)
+    }
+    
+    /// Write 64-bit value
+    pub unsafe fn write64(&self, offset: usize, value: u64) {
+        self.write_u64(offset, value)
     }
 }
 
@@ -90,4 +152,3 @@ impl Drop for MmioRegion {
         let _ = syscalls::mmio_unmap(self.base, self.size as u64);
     }
 }
-
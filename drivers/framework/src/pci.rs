@@ -0,0 +1,181 @@
+//! PCI driver client
+//!
+//! Configuration space requests served by the PCI bus driver.
+
+use crate::ipc::{ipc_create_port, ipc_destroy_port, ipc_receive_timeout, ipc_send, IpcMessage, IPC_MSG_REQUEST, IPC_MSG_RESPONSE};
+use crate::{DriverError, DriverResult};
+
+/// PCI bus driver port
+pub const PCI_DRIVER_PORT: u64 = 101;
+
+/// [bus, device, function, offset] -> [value u32]
+pub const MSG_PCI_READ_CONFIG: u64 = 10;
+/// [bus, device, function, offset, value u32] -> [1 on success]
+pub const MSG_PCI_WRITE_CONFIG: u64 = 11;
+
+/// [class, subclass, prog_if, index] -> index-th match as
+/// [bus, device, function, vendor_id u16, device_id u16, match count]
+pub const MSG_PCI_FIND_BY_CLASS: u64 = 14;
+
+/// Subclass/prog_if value that matches anything in find_by_class
+pub const PCI_CLASS_ANY: u8 = 0xFF;
+
+const PCI_FIND_RESPONSE_SIZE: u32 = 8;
+
+/// Offset of the command register and its bus master enable bit
+const PCI_COMMAND: u8 = 0x04;
+const PCI_COMMAND_BUS_MASTER: u32 = 1 << 2;
+
+/// [bus, device, function, bar] -> [base u64, size u64, is_mmio u8, is_64bit u8]
+pub const MSG_PCI_GET_BAR: u64 = 15;
+
+const PCI_BAR_RESPONSE_SIZE: u32 = 18;
+
+/// [bus, device, function] -> [irq, kind (1 = MSI, 2 = MSI-X)]
+pub const MSG_PCI_ENABLE_MSI: u64 = 16;
+
+const PCI_MSI_RESPONSE_SIZE: u32 = 2;
+
+/// [bus, device, function, capability id] -> [offset, 0 if absent]
+pub const MSG_PCI_FIND_CAP: u64 = 19;
+
+/// [bus, device, function] -> [1 on success]
+pub const MSG_PCI_ENABLE_DEVICE: u64 = 20;
+
+// Capability ids
+pub const PCI_CAP_ID_PM: u8 = 0x01;
+pub const PCI_CAP_ID_MSI: u8 = 0x05;
+pub const PCI_CAP_ID_PCIE: u8 = 0x10;
+pub const PCI_CAP_ID_MSIX: u8 = 0x11;
+
+/// How long to wait for the PCI driver to answer
+const PCI_REPLY_TIMEOUT_MS: u64 = 1000;
+
+/// Decoded base address register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciBar {
+    pub base: u64,
+    pub size: u64,
+    pub is_mmio: bool,
+    pub is_64bit: bool,
+}
+
+/// Send a request to the PCI driver and wait for its answer
+fn request(msg_id: u64, data: &[u8]) -> DriverResult<IpcMessage> {
+    let reply_port = ipc_create_port().map_err(|_| DriverError::IoError)?;
+
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_REQUEST;
+    msg.msg_id = msg_id;
+    msg.reply_port = reply_port;
+    msg.set_inline_data(data);
+
+    let mut response = IpcMessage::new();
+    let result = match ipc_send(PCI_DRIVER_PORT, &msg) {
+        Ok(()) => ipc_receive_timeout(reply_port, &mut response, PCI_REPLY_TIMEOUT_MS),
+        Err(_) => Err(DriverError::IoError),
+    };
+    let _ = ipc_destroy_port(reply_port);
+    result?;
+
+    if response.msg_type != IPC_MSG_RESPONSE || response.msg_id != msg_id {
+        return Err(DriverError::IoError);
+    }
+    Ok(response)
+}
+
+/// Base address and length of a device's BAR, sized by the PCI driver
+pub fn get_bar(bus: u8, device: u8, function: u8, bar: u8) -> DriverResult<PciBar> {
+    if bar > 5 {
+        return Err(DriverError::InvalidArgument);
+    }
+
+    let response = request(MSG_PCI_GET_BAR, &[bus, device, function, bar])?;
+    // A one-byte response means the BAR is not implemented
+    if response.inline_size != PCI_BAR_RESPONSE_SIZE {
+        return Err(DriverError::DeviceNotFound);
+    }
+
+    let data = response.get_inline_data();
+    Ok(PciBar {
+        base: u64::from_le_bytes([data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]]),
+        size: u64::from_le_bytes([data[8], data[9], data[10], data[11], data[12], data[13], data[14], data[15]]),
+        is_mmio: data[16] != 0,
+        is_64bit: data[17] != 0,
+    })
+}
+
+/// Switch a device to MSI-X or MSI and return the IRQ to register a
+/// handler on. Fails with NotSupported if the device only has INTx.
+pub fn enable_msi(bus: u8, device: u8, function: u8) -> DriverResult<u8> {
+    let response = request(MSG_PCI_ENABLE_MSI, &[bus, device, function])?;
+    if response.inline_size != PCI_MSI_RESPONSE_SIZE {
+        return Err(DriverError::NotSupported);
+    }
+    Ok(response.get_inline_data()[0])
+}
+
+/// Configuration space offset of a device's capability, `None` if the
+/// device does not have it
+pub fn find_capability(bus: u8, device: u8, function: u8, cap_id: u8) -> DriverResult<Option<u8>> {
+    let response = request(MSG_PCI_FIND_CAP, &[bus, device, function, cap_id])?;
+    if response.inline_size != 1 {
+        return Err(DriverError::IoError);
+    }
+    // Capabilities sit past the header, so 0 is free to mean "absent"
+    Ok(match response.get_inline_data()[0] {
+        0 => None,
+        offset => Some(offset),
+    })
+}
+
+/// Bus, device and function of the index-th device of a class
+pub fn find_by_class(class: u8, subclass: u8, prog_if: u8, index: u8) -> DriverResult<(u8, u8, u8)> {
+    let response = request(MSG_PCI_FIND_BY_CLASS, &[class, subclass, prog_if, index])?;
+    if response.inline_size != PCI_FIND_RESPONSE_SIZE {
+        return Err(DriverError::DeviceNotFound);
+    }
+    let data = response.get_inline_data();
+    Ok((data[0], data[1], data[2]))
+}
+
+/// Read a configuration dword
+pub fn read_config(bus: u8, device: u8, function: u8, offset: u8) -> DriverResult<u32> {
+    let response = request(MSG_PCI_READ_CONFIG, &[bus, device, function, offset])?;
+    if response.inline_size != 4 {
+        return Err(DriverError::IoError);
+    }
+    let data = response.get_inline_data();
+    Ok(u32::from_le_bytes([data[0], data[1], data[2], data[3]]))
+}
+
+/// Write a configuration dword
+pub fn write_config(bus: u8, device: u8, function: u8, offset: u8, value: u32) -> DriverResult<()> {
+    let v = value.to_le_bytes();
+    let response = request(MSG_PCI_WRITE_CONFIG, &[bus, device, function, offset, v[0], v[1], v[2], v[3]])?;
+    if response.get_inline_data() != [1] {
+        return Err(DriverError::IoError);
+    }
+    Ok(())
+}
+
+/// Turn on a device's memory decode and bus mastering. Call this during
+/// init before touching the BARs: firmware may leave both off, and then
+/// MMIO reads come back as all-ones and DMA silently does nothing.
+pub fn enable_device(bus: u8, device: u8, function: u8) -> DriverResult<()> {
+    let response = request(MSG_PCI_ENABLE_DEVICE, &[bus, device, function])?;
+    if response.get_inline_data() != [1] {
+        return Err(DriverError::DeviceNotFound);
+    }
+    Ok(())
+}
+
+/// Let a device master the bus, which it needs before starting DMA
+pub fn enable_bus_master(bus: u8, device: u8, function: u8) -> DriverResult<()> {
+    // Drop the status half so its write-one-to-clear bits are not cleared
+    let command = read_config(bus, device, function, PCI_COMMAND)? & 0xFFFF;
+    if command & PCI_COMMAND_BUS_MASTER != 0 {
+        return Ok(());
+    }
+    write_config(bus, device, function, PCI_COMMAND, command | PCI_COMMAND_BUS_MASTER)
+}
@@ -24,20 +24,37 @@ pub unsafe fn syscall_raw(_num: u64, _arg1: u64, _arg2: u64, _arg3: u64, _arg4:
 }
 
 // System call numbers (from kernel/include/syscall/syscall.h)
+const SYS_SLEEP: u64 = 5;
+const SYS_YIELD: u64 = 6;
 const SYS_IPC_SEND: u64 = 9;
 const SYS_IPC_RECEIVE: u64 = 10;
+const SYS_IPC_TRY_RECEIVE: u64 = 53;
+const SYS_IPC_RECEIVE_TIMEOUT: u64 = 54;
+const SYS_IPC_REPLY: u64 = 55;
 const SYS_IPC_CREATE_PORT: u64 = 26;
+const SYS_IPC_DESTROY_PORT: u64 = 27;
 const SYS_MMIO_MAP: u64 = 36;
 const SYS_MMIO_UNMAP: u64 = 37;
 const SYS_DMA_ALLOC: u64 = 34;
 const SYS_DMA_FREE: u64 = 35;
-const SYS_DMA_GET_PHYSICAL: u64 = 36; // Note: This may need to be added to kernel
+const SYS_DMA_GET_PHYSICAL: u64 = 52;
+const SYS_DMA_SHARE: u64 = 58;
+const SYS_DMA_ADOPT: u64 = 59;
+const SYS_DMA_UNSHARE: u64 = 60;
+const SYS_SHM_CREATE: u64 = 40;
+const SYS_SHM_MAP: u64 = 41;
+const SYS_SHM_UNMAP: u64 = 42;
+const SYS_SHM_DESTROY: u64 = 43;
+const SYS_SHM_GET_INFO: u64 = 44;
 const SYS_IRQ_REGISTER: u64 = 30;
 const SYS_IRQ_UNREGISTER: u64 = 31;
+const SYS_IRQ_REGISTER_SHARED: u64 = 61;
+const SYS_IRQ_BIND_PORT: u64 = 65;
 const SYS_IRQ_ENABLE: u64 = 32;
 const SYS_IRQ_DISABLE: u64 = 33;
 const SYS_PCI_READ_CONFIG: u64 = 28;
 const SYS_PCI_WRITE_CONFIG: u64 = 29;
+const SYS_GET_UPTIME_MS: u64 = 47;
 
 /// IPC send
 pub fn ipc_send(port_id: u64, msg_ptr: u64) -> u64 {
@@ -49,11 +66,31 @@ pub fn ipc_receive(port_id: u64, msg_ptr: u64) -> u64 {
     unsafe { syscall_raw(SYS_IPC_RECEIVE, port_id, msg_ptr, 0, 0, 0) }
 }
 
+/// IPC receive without blocking
+pub fn ipc_try_receive(port_id: u64, msg_ptr: u64) -> u64 {
+    unsafe { syscall_raw(SYS_IPC_TRY_RECEIVE, port_id, msg_ptr, 0, 0, 0) }
+}
+
+/// Receive IPC message, blocking for at most timeout_ms
+pub fn ipc_receive_timeout(port_id: u64, msg_ptr: u64, timeout_ms: u64) -> u64 {
+    unsafe { syscall_raw(SYS_IPC_RECEIVE_TIMEOUT, port_id, msg_ptr, timeout_ms, 0, 0) }
+}
+
+/// Send a response on a received message's reply port
+pub fn ipc_reply(request_ptr: u64, response_ptr: u64) -> u64 {
+    unsafe { syscall_raw(SYS_IPC_REPLY, request_ptr, response_ptr, 0, 0, 0) }
+}
+
 /// Create IPC port
 pub fn ipc_create_port() -> u64 {
     unsafe { syscall_raw(SYS_IPC_CREATE_PORT, 0, 0, 0, 0, 0) }
 }
 
+/// Destroy IPC port
+pub fn ipc_destroy_port(port_id: u64) -> u64 {
+    unsafe { syscall_raw(SYS_IPC_DESTROY_PORT, port_id, 0, 0, 0, 0) }
+}
+
 /// Map MMIO region
 pub fn mmio_map(physical_addr: u64, size: u64) -> Result<*mut u8, u64> {
     let result = unsafe { syscall_raw(SYS_MMIO_MAP, physical_addr, size, 0, 0, 0) };
@@ -104,6 +141,110 @@ pub fn dma_get_physical(vaddr: u64) -> Result<u64, u64> {
     }
 }
 
+/// A shared DMA buffer as its target sees it (kernel dma_share_info_t)
+#[repr(C)]
+#[derive(Default)]
+pub struct DmaShareInfo {
+    pub vaddr: u64,
+    pub physical: u64,
+    pub size: u64,
+    pub writable: u64,
+}
+
+/// Map a DMA buffer into another process, returning the share handle
+pub fn dma_share(vaddr: u64, target_pid: u64, writable: bool) -> Result<u64, u64> {
+    let result = unsafe { syscall_raw(SYS_DMA_SHARE, vaddr, target_pid, writable as u64, 0, 0) };
+    if result == 0 {
+        Err(1)
+    } else {
+        Ok(result)
+    }
+}
+
+/// Look up a buffer shared with this process
+pub fn dma_adopt(handle: u64, info: &mut DmaShareInfo) -> Result<(), u64> {
+    let result = unsafe { syscall_raw(SYS_DMA_ADOPT, handle, info as *mut DmaShareInfo as u64, 0, 0, 0) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(result)
+    }
+}
+
+/// End a share from either side
+pub fn dma_unshare(handle: u64) -> Result<(), u64> {
+    let result = unsafe { syscall_raw(SYS_DMA_UNSHARE, handle, 0, 0, 0, 0) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(result)
+    }
+}
+
+/// Map flag for shm_map: read-only
+pub const SHM_FLAG_READ_ONLY: u64 = 1;
+
+/// Create a shared memory region of at least `size` bytes
+pub fn shm_create(size: u64) -> Result<u64, u64> {
+    let result = unsafe { syscall_raw(SYS_SHM_CREATE, size, 0, 0, 0, 0) };
+    if result == 0 {
+        Err(1)
+    } else {
+        Ok(result)
+    }
+}
+
+/// Map a shared memory region, at a kernel-chosen address if `vaddr` is 0
+pub fn shm_map(shm_id: u64, vaddr: u64, flags: u64) -> Result<*mut u8, u64> {
+    let result = unsafe { syscall_raw(SYS_SHM_MAP, shm_id, vaddr, flags, 0, 0) };
+    if result == 0 {
+        Err(1)
+    } else {
+        Ok(result as *mut u8)
+    }
+}
+
+/// Unmap a shared memory region
+pub fn shm_unmap(shm_id: u64, vaddr: *mut u8) -> Result<(), u64> {
+    let result = unsafe { syscall_raw(SYS_SHM_UNMAP, shm_id, vaddr as u64, 0, 0, 0) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(result)
+    }
+}
+
+/// Free a shared memory region; fails while anyone has it mapped
+pub fn shm_destroy(shm_id: u64) -> Result<(), u64> {
+    let result = unsafe { syscall_raw(SYS_SHM_DESTROY, shm_id, 0, 0, 0, 0) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(result)
+    }
+}
+
+/// Size in bytes of a shared memory region
+pub fn shm_size(shm_id: u64) -> Result<usize, u64> {
+    let mut size: usize = 0;
+    let mut refcount: usize = 0;
+    let result = unsafe {
+        syscall_raw(
+            SYS_SHM_GET_INFO,
+            shm_id,
+            &mut size as *mut usize as u64,
+            &mut refcount as *mut usize as u64,
+            0,
+            0,
+        )
+    };
+    if result == 0 {
+        Ok(size)
+    } else {
+        Err(result)
+    }
+}
+
 /// Register IRQ handler
 pub fn irq_register(irq: u8, handler: extern "C" fn()) -> Result<(), u64> {
     let result = unsafe { syscall_raw(SYS_IRQ_REGISTER, irq as u64, handler as u64, 0, 0, 0) };
@@ -114,6 +255,26 @@ pub fn irq_register(irq: u8, handler: extern "C" fn()) -> Result<(), u64> {
     }
 }
 
+/// Register a handler on a shared IRQ line
+pub fn irq_register_shared(irq: u8, handler: extern "C" fn() -> bool) -> Result<(), u64> {
+    let result = unsafe { syscall_raw(SYS_IRQ_REGISTER_SHARED, irq as u64, handler as u64, 0, 0, 0) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(result)
+    }
+}
+
+/// Have an IRQ delivered to a port this driver owns
+pub fn irq_bind_port(irq: u8, port_id: u64) -> Result<(), u64> {
+    let result = unsafe { syscall_raw(SYS_IRQ_BIND_PORT, irq as u64, port_id, 0, 0, 0) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(result)
+    }
+}
+
 /// Unregister IRQ handler
 pub fn irq_unregister(irq: u8) -> Result<(), u64> {
     let result = unsafe { syscall_raw(SYS_IRQ_UNREGISTER, irq as u64, 0, 0, 0, 0) };
@@ -161,3 +322,28 @@ pub fn pci_write_config(bus: u8, device: u8, function: u8, offset: u8, value: u3
     }
 }
 
+/// Milliseconds since boot
+pub fn get_uptime_ms() -> u64 {
+    unsafe { syscall_raw(SYS_GET_UPTIME_MS, 0, 0, 0, 0, 0) }
+}
+
+/// Give the CPU to another ready thread
+pub fn sys_yield() {
+    unsafe {
+        syscall_raw(SYS_YIELD, 0, 0, 0, 0, 0);
+    }
+}
+
+/// Sleep for at least `ms` milliseconds. Prefer `wait_until` when waiting
+/// for something that can be checked.
+pub fn sys_sleep(ms: u64) {
+    unsafe {
+        syscall_raw(SYS_SLEEP, ms, 0, 0, 0, 0);
+    }
+}
+
+/// Wait until `done` holds, yielding between checks; false if it still
+/// does not after `timeout_ms`
+pub fn wait_until(timeout_ms: u64, done: impl FnMut() -> bool) -> bool {
+    crate::wait::poll_until(timeout_ms, done, get_uptime_ms, sys_yield)
+}
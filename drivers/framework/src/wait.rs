@@ -0,0 +1,27 @@
+//! Cooperative waiting
+//!
+//! Drivers wait for hardware by checking a condition and yielding the CPU
+//! between checks, rather than sleeping a fixed time. The wait ends as
+//! soon as the condition holds, and other threads run in the meantime.
+
+/// Check `done` until it holds or `timeout_ms` has passed on the `now`
+/// clock, calling `relax` between checks. Returns whether it held; it is
+/// checked once more after the deadline so a slow `relax` cannot make a
+/// finished wait look timed out.
+pub fn poll_until(
+    timeout_ms: u64,
+    mut done: impl FnMut() -> bool,
+    now: impl Fn() -> u64,
+    mut relax: impl FnMut(),
+) -> bool {
+    let deadline = now().saturating_add(timeout_ms);
+    loop {
+        if done() {
+            return true;
+        }
+        if now() >= deadline {
+            return done();
+        }
+        relax();
+    }
+}
@@ -0,0 +1,323 @@
+//! Scancode set 1 translation
+//!
+//! Tracks modifier state across make/break codes and the 0xE0/0xE1
+//! prefixes, and turns each complete scancode into a key event with its
+//! ASCII value under the current modifiers (US QWERTY) and a key code.
+
+// Modifier bitmask
+pub const MOD_LSHIFT: u8 = 1 << 0;
+pub const MOD_RSHIFT: u8 = 1 << 1;
+pub const MOD_LCTRL: u8 = 1 << 2;
+pub const MOD_RCTRL: u8 = 1 << 3;
+pub const MOD_LALT: u8 = 1 << 4;
+pub const MOD_RALT: u8 = 1 << 5;
+pub const MOD_CAPSLOCK: u8 = 1 << 6;
+
+pub const MOD_SHIFT: u8 = MOD_LSHIFT | MOD_RSHIFT;
+pub const MOD_CTRL: u8 = MOD_LCTRL | MOD_RCTRL;
+pub const MOD_ALT: u8 = MOD_LALT | MOD_RALT;
+
+/// Prefix byte for extended scancodes
+pub const SCANCODE_EXTENDED_PREFIX: u8 = 0xE0;
+
+/// Prefix of the Pause sequence: E1 1D 45 E1 9D C5
+pub const SCANCODE_PAUSE_PREFIX: u8 = 0xE1;
+const PAUSE_SEQUENCE_LEN: u8 = 6;
+
+// Key codes for keys without a character. Printable keys use their
+// ASCII value, so these start past it.
+pub const KEY_UP: u8 = 0x80;
+pub const KEY_DOWN: u8 = 0x81;
+pub const KEY_LEFT: u8 = 0x82;
+pub const KEY_RIGHT: u8 = 0x83;
+pub const KEY_HOME: u8 = 0x84;
+pub const KEY_END: u8 = 0x85;
+pub const KEY_PAGE_UP: u8 = 0x86;
+pub const KEY_PAGE_DOWN: u8 = 0x87;
+pub const KEY_INSERT: u8 = 0x88;
+pub const KEY_DELETE: u8 = 0x89;
+pub const KEY_PAUSE: u8 = 0x8A;
+pub const KEY_PRINT_SCREEN: u8 = 0x8B;
+
+/// Break (release) codes have bit 7 set
+const SCANCODE_RELEASE: u8 = 0x80;
+
+// Modifier make codes
+const SC_LCTRL: u8 = 0x1D;
+const SC_LSHIFT: u8 = 0x2A;
+const SC_RSHIFT: u8 = 0x36;
+const SC_LALT: u8 = 0x38;
+const SC_CAPSLOCK: u8 = 0x3A;
+
+// Extended keys that still produce a character
+const SC_EXT_KP_ENTER: u8 = 0x1C;
+const SC_EXT_KP_SLASH: u8 = 0x35;
+
+// US QWERTY scancode to ASCII map
+static SCANCODE_TO_ASCII: [u8; 128] = [
+    0, 27, b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9', b'0', b'-', b'=', 8, // backspace
+    b'\t', b'q', b'w', b'e', b'r', b't', b'y', b'u', b'i', b'o', b'p', b'[', b']', b'\n',
+    0, // ctrl
+    b'a', b's', b'd', b'f', b'g', b'h', b'j', b'k', b'l', b';', b'\'', b'`',
+    0, // left shift
+    b'\\', b'z', b'x', b'c', b'v', b'b', b'n', b'm', b',', b'.', b'/',
+    0, // right shift
+    b'*',
+    0, // alt
+    b' ', // space
+    0, // caps lock
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // F1-F10
+    0, // num lock
+    0, // scroll lock
+    b'7', b'8', b'9', b'-',
+    b'4', b'5', b'6', b'+',
+    b'1', b'2', b'3', b'0', b'.',
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+// Same keys with Shift held
+static SCANCODE_TO_ASCII_SHIFTED: [u8; 128] = [
+    0, 27, b'!', b'@', b'#', b'$', b'%', b'^', b'&', b'*', b'(', b')', b'_', b'+', 8, // backspace
+    b'\t', b'Q', b'W', b'E', b'R', b'T', b'Y', b'U', b'I', b'O', b'P', b'{', b'}', b'\n',
+    0, // ctrl
+    b'A', b'S', b'D', b'F', b'G', b'H', b'J', b'K', b'L', b':', b'"', b'~',
+    0, // left shift
+    b'|', b'Z', b'X', b'C', b'V', b'B', b'N', b'M', b'<', b'>', b'?',
+    0, // right shift
+    b'*',
+    0, // alt
+    b' ', // space
+    0, // caps lock
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // F1-F10
+    0, // num lock
+    0, // scroll lock
+    b'7', b'8', b'9', b'-',
+    b'4', b'5', b'6', b'+',
+    b'1', b'2', b'3', b'0', b'.',
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+/// Bytes of an encoded key event: ascii, modifiers, pressed, scancode,
+/// extended, key code
+pub const KEY_EVENT_SIZE: usize = 6;
+
+/// One key press or release
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub scancode: u8,
+    pub extended: bool,
+    pub pressed: bool,
+    /// Translated character, 0 if the key has none
+    pub ascii: u8,
+    /// Modifier bitmask after this event
+    pub modifiers: u8,
+    /// KEY_* for special keys, otherwise the ASCII value (0 if none)
+    pub keycode: u8,
+}
+
+impl KeyEvent {
+    pub const EMPTY: KeyEvent = KeyEvent {
+        scancode: 0,
+        extended: false,
+        pressed: false,
+        ascii: 0,
+        modifiers: 0,
+        keycode: 0,
+    };
+
+    /// Layout sent to the input server
+    pub fn encode(&self) -> [u8; KEY_EVENT_SIZE] {
+        [self.ascii, self.modifiers, self.pressed as u8, self.scancode, self.extended as u8, self.keycode]
+    }
+}
+
+/// Where the decoder is within a multi-byte scancode
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Prefix {
+    None,
+    Extended,
+    /// Bytes of the Pause sequence seen so far
+    Pause(u8),
+}
+
+/// Key code of an 0xE0-prefixed make code
+fn extended_keycode(scancode: u8) -> u8 {
+    match scancode {
+        0x48 => KEY_UP,
+        0x50 => KEY_DOWN,
+        0x4B => KEY_LEFT,
+        0x4D => KEY_RIGHT,
+        0x47 => KEY_HOME,
+        0x4F => KEY_END,
+        0x49 => KEY_PAGE_UP,
+        0x51 => KEY_PAGE_DOWN,
+        0x52 => KEY_INSERT,
+        0x53 => KEY_DELETE,
+        0x37 => KEY_PRINT_SCREEN,
+        _ => 0,
+    }
+}
+
+/// Keyboard translation state
+pub struct Keymap {
+    modifiers: u8,
+    prefix: Prefix,
+    caps_down: bool,
+    /// Held keys, indexed by scancode with bit 7 set for extended ones
+    down: [u32; 8],
+}
+
+fn key_index(scancode: u8, extended: bool) -> usize {
+    (scancode & !SCANCODE_RELEASE) as usize | ((extended as usize) << 7)
+}
+
+impl Keymap {
+    pub const fn new() -> Self {
+        Keymap {
+            modifiers: 0,
+            prefix: Prefix::None,
+            caps_down: false,
+            down: [0; 8],
+        }
+    }
+
+    pub fn modifiers(&self) -> u8 {
+        self.modifiers
+    }
+
+    /// Whether the key is currently held
+    pub fn is_down(&self, scancode: u8, extended: bool) -> bool {
+        let index = key_index(scancode, extended);
+        self.down[index / 32] & (1 << (index % 32)) != 0
+    }
+
+    /// Feed one byte from the controller. Returns an event once a
+    /// complete scancode has been seen.
+    pub fn process(&mut self, byte: u8) -> Option<KeyEvent> {
+        match self.prefix {
+            Prefix::Pause(seen) => {
+                // Pause has no break code; report a press once it ends
+                if seen + 1 < PAUSE_SEQUENCE_LEN {
+                    self.prefix = Prefix::Pause(seen + 1);
+                    return None;
+                }
+                self.prefix = Prefix::None;
+                return Some(KeyEvent {
+                    scancode: SCANCODE_PAUSE_PREFIX,
+                    extended: false,
+                    pressed: true,
+                    ascii: 0,
+                    modifiers: self.modifiers,
+                    keycode: KEY_PAUSE,
+                });
+            }
+            Prefix::None if byte == SCANCODE_PAUSE_PREFIX => {
+                self.prefix = Prefix::Pause(1);
+                return None;
+            }
+            Prefix::None if byte == SCANCODE_EXTENDED_PREFIX => {
+                self.prefix = Prefix::Extended;
+                return None;
+            }
+            _ => {}
+        }
+
+        let extended = self.prefix == Prefix::Extended;
+        self.prefix = Prefix::None;
+
+        let scancode = byte & !SCANCODE_RELEASE;
+        let pressed = byte & SCANCODE_RELEASE == 0;
+
+        // Fake shifts wrapped around extended keys by some keyboards
+        if extended && (scancode == SC_LSHIFT || scancode == SC_RSHIFT) {
+            return None;
+        }
+
+        self.update_modifiers(scancode, extended, pressed);
+
+        let index = key_index(scancode, extended);
+        if pressed {
+            self.down[index / 32] |= 1 << (index % 32);
+        } else {
+            self.down[index / 32] &= !(1 << (index % 32));
+        }
+
+        // Releases keep the key code so apps can match them to presses
+        let translated = self.translate(scancode, extended);
+        let keycode = if extended && translated == 0 {
+            extended_keycode(scancode)
+        } else {
+            translated
+        };
+        let ascii = if pressed { translated } else { 0 };
+        Some(KeyEvent {
+            scancode,
+            extended,
+            pressed,
+            ascii,
+            modifiers: self.modifiers,
+            keycode,
+        })
+    }
+
+    fn update_modifiers(&mut self, scancode: u8, extended: bool, pressed: bool) {
+        let bit = match (scancode, extended) {
+            (SC_LSHIFT, false) => MOD_LSHIFT,
+            (SC_RSHIFT, false) => MOD_RSHIFT,
+            (SC_LCTRL, false) => MOD_LCTRL,
+            (SC_LCTRL, true) => MOD_RCTRL,
+            (SC_LALT, false) => MOD_LALT,
+            (SC_LALT, true) => MOD_RALT,
+            (SC_CAPSLOCK, false) => {
+                // Toggles on the first press, not on typematic repeats
+                if pressed && !self.caps_down {
+                    self.modifiers ^= MOD_CAPSLOCK;
+                }
+                self.caps_down = pressed;
+                return;
+            }
+            _ => return,
+        };
+
+        if pressed {
+            self.modifiers |= bit;
+        } else {
+            self.modifiers &= !bit;
+        }
+    }
+
+    fn translate(&self, scancode: u8, extended: bool) -> u8 {
+        if extended {
+            // Navigation keys share make codes with the keypad digits
+            return match scancode {
+                SC_EXT_KP_ENTER => b'\n',
+                SC_EXT_KP_SLASH => b'/',
+                _ => 0,
+            };
+        }
+
+        let index = scancode as usize;
+        let base = SCANCODE_TO_ASCII[index];
+        if base == 0 {
+            return 0;
+        }
+
+        let shift = self.modifiers & MOD_SHIFT != 0;
+        let caps = self.modifiers & MOD_CAPSLOCK != 0;
+        let ascii = if base.is_ascii_lowercase() {
+            // Caps Lock inverts Shift for letters only
+            if shift != caps { SCANCODE_TO_ASCII_SHIFTED[index] } else { base }
+        } else if shift {
+            SCANCODE_TO_ASCII_SHIFTED[index]
+        } else {
+            base
+        };
+
+        // Ctrl+letter gives the matching control character
+        if self.modifiers & MOD_CTRL != 0 && ascii.is_ascii_alphabetic() {
+            ascii & 0x1F
+        } else {
+            ascii
+        }
+    }
+}
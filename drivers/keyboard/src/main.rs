@@ -1,184 +1,178 @@
 /**
  * @file keyboard_driver.rs
  * @brief User-space PS/2 keyboard driver
+ *
+ * Scancodes arrive from the PS/2 controller driver, which owns the 8042.
  */
 
 use core::panic::PanicInfo;
 
+use driver_framework::ipc::{
+    ipc_receive, ipc_reply, ipc_send, IpcMessage, IPC_MSG_NOTIFICATION, IPC_MSG_REQUEST,
+    IPC_MSG_RESPONSE,
+};
+
+mod keymap;
+use keymap::{KeyEvent, Keymap};
+
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
     loop {}
 }
 
-// IPC syscall wrappers
+// Port registration syscall wrapper
 extern "C" {
-    fn sys_ipc_send(tid: u32, msg: *const IpcMessage) -> i32;
-    fn sys_ipc_receive(port: u32, msg: *mut IpcMessage) -> i32;
     fn sys_ipc_register_port(port: u32) -> i32;
-    fn sys_io_read(port: u16, size: u8) -> u32;
-    fn sys_io_write(port: u16, value: u32, size: u8) -> i32;
-    fn sys_irq_register(irq: u32) -> i32;
-    fn sys_irq_wait() -> u32;
-}
-
-#[repr(C)]
-struct IpcMessage {
-    sender_tid: u32,
-    msg_type: u32,
-    data: [u8; 256],
 }
 
 // Keyboard IPC port
 const KEYBOARD_DRIVER_PORT: u32 = 103;
 
-// PS/2 keyboard ports
-const KEYBOARD_DATA_PORT: u16 = 0x60;
-const KEYBOARD_STATUS_PORT: u16 = 0x64;
-const KEYBOARD_COMMAND_PORT: u16 = 0x64;
+// Input server and its key event message
+const INPUT_SERVER_PORT: u64 = 200;
+const MSG_KEY_EVENT: u64 = 10;
+
+// PS/2 controller and its messages
+const PS2_CONTROLLER_PORT: u64 = 107;
+const MSG_PS2_DATA: u64 = 16;
+const MSG_PS2_COMMAND: u64 = 17;
+const MSG_PS2_COMMAND_REPLY: u64 = 18;
+const PS2_CHANNEL_KEYBOARD: u8 = 0;
+const PS2_STATUS_OK: u8 = 0;
 
-// Keyboard IRQ
-const KEYBOARD_IRQ: u32 = 1;
+// Keyboard commands and replies
+const KEYBOARD_CMD_SET_LEDS: u8 = 0xED;
+const KEYBOARD_ACK: u8 = 0xFA;
 
-// Message types
-const MSG_KEYBOARD_GET_KEY: u32 = 1;
-const MSG_KEYBOARD_SET_LEDS: u32 = 2;
+// Message ids
+const MSG_KEYBOARD_GET_KEY: u64 = 1;
+const MSG_KEYBOARD_SET_LEDS: u64 = 2;
+const MSG_KEYBOARD_IS_KEY_DOWN: u64 = 3;
 
-// Key buffer
+// Key buffer (presses that produce a character or key code)
 const KEY_BUFFER_SIZE: usize = 128;
-static mut KEY_BUFFER: [u8; KEY_BUFFER_SIZE] = [0; KEY_BUFFER_SIZE];
+static mut KEY_BUFFER: [KeyEvent; KEY_BUFFER_SIZE] = [KeyEvent::EMPTY; KEY_BUFFER_SIZE];
 static mut KEY_BUFFER_HEAD: usize = 0;
 static mut KEY_BUFFER_TAIL: usize = 0;
 
-// US QWERTY scancode to ASCII map
-static SCANCODE_TO_ASCII: [u8; 128] = [
-    0, 27, b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9', b'0', b'-', b'=', 8, // backspace
-    b'\t', b'q', b'w', b'e', b'r', b't', b'y', b'u', b'i', b'o', b'p', b'[', b']', b'\n',
-    0, // ctrl
-    b'a', b's', b'd', b'f', b'g', b'h', b'j', b'k', b'l', b';', b'\'', b'`',
-    0, // left shift
-    b'\\', b'z', b'x', b'c', b'v', b'b', b'n', b'm', b',', b'.', b'/', 
-    0, // right shift
-    b'*',
-    0, // alt
-    b' ', // space
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // F1-F10
-    0, // num lock
-    0, // scroll lock
-    b'7', b'8', b'9', b'-',
-    b'4', b'5', b'6', b'+',
-    b'1', b'2', b'3', b'0', b'.',
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-];
+// Modifier and prefix state
+static mut KEYMAP: Keymap = Keymap::new();
 
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
     // Register with driver manager
     register_with_driver_manager();
 
-    // Initialize keyboard
-    init_keyboard();
-
-    // Register IRQ handler
-    unsafe {
-        sys_irq_register(KEYBOARD_IRQ);
-    }
-
-    // Register IPC port
+    // Register IPC port; the PS/2 controller forwards scancodes here
     unsafe {
         sys_ipc_register_port(KEYBOARD_DRIVER_PORT);
     }
 
-    // Spawn IRQ handler thread
-    // For now, we'll handle both IRQ and IPC in the same loop
-
     // Main service loop
+    let mut msg = IpcMessage::new();
     loop {
-        // Wait for IRQ
-        unsafe {
-            let irq = sys_irq_wait();
-            if irq == KEYBOARD_IRQ {
-                handle_keyboard_interrupt();
-            }
+        if ipc_receive(KEYBOARD_DRIVER_PORT as u64, &mut msg).is_err() {
+            continue;
         }
 
-        // Check for IPC messages (non-blocking would be better)
-        let mut msg = IpcMessage {
-            sender_tid: 0,
-            msg_type: 0,
-            data: [0; 256],
-        };
-
-        unsafe {
-            if sys_ipc_receive(KEYBOARD_DRIVER_PORT, &mut msg) == 0 {
-                let response = handle_message(&msg);
-                let _ = sys_ipc_send(msg.sender_tid, &response);
-            }
+        if msg.msg_id == MSG_PS2_DATA {
+            handle_scancode(msg.inline_data[0]);
+        } else {
+            let response = handle_message(&msg);
+            let _ = ipc_reply(&msg, &response);
         }
     }
 }
 
 fn register_with_driver_manager() {
-    const DRIVER_MANAGER_PORT: u32 = 100;
-    const MSG_REGISTER_DRIVER: u32 = 1;
-    const DRIVER_TYPE_INPUT: u32 = 4;
+    const DRIVER_MANAGER_PORT: u64 = 100;
+    const MSG_REGISTER_DRIVER: u64 = 1;
+    const DRIVER_TYPE_INPUT: u8 = 4;
     const KEYBOARD_DRIVER_PORT: u32 = 201; // Well-known port for keyboard driver
     
-    // Create registration message
-    let mut msg = IpcMessage {
-        sender_tid: 0,  // Will be filled by kernel
-        msg_type: 1,    // IPC_MSG_REQUEST
-        data: [0; 256],
-    };
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_REQUEST;
+    msg.msg_id = MSG_REGISTER_DRIVER;
     
     // Pack driver type (Input = 4) and port into message data
-    msg.data[0] = DRIVER_TYPE_INPUT as u8;
-    msg.data[1..5].copy_from_slice(&KEYBOARD_DRIVER_PORT.to_le_bytes());
+    msg.inline_data[0] = DRIVER_TYPE_INPUT;
+    msg.inline_data[1..5].copy_from_slice(&KEYBOARD_DRIVER_PORT.to_le_bytes());
+    msg.inline_size = 5;
     
     // Send registration message to driver manager
-    unsafe {
-        let _ = sys_ipc_send(DRIVER_MANAGER_PORT, &msg);
-    }
+    let _ = ipc_send(DRIVER_MANAGER_PORT, &msg);
 }
 
-fn init_keyboard() {
-    unsafe {
-        // Flush output buffer
-        while (sys_io_read(KEYBOARD_STATUS_PORT, 1) & 0x01) != 0 {
-            sys_io_read(KEYBOARD_DATA_PORT, 1);
+/// Send one byte to the keyboard through the PS/2 controller; true if
+/// the keyboard acknowledged it. Scancodes that arrive while waiting for
+/// the reply are processed as usual.
+fn keyboard_command(byte: u8) -> bool {
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_REQUEST;
+    msg.msg_id = MSG_PS2_COMMAND;
+    msg.set_inline_data(&[PS2_CHANNEL_KEYBOARD, byte, 1]); // expect an ACK
+    // The reply arrives among the scancodes on our own port
+    msg.reply_port = KEYBOARD_DRIVER_PORT as u64;
+
+    if ipc_send(PS2_CONTROLLER_PORT, &msg).is_err() {
+        return false;
+    }
+
+    let mut reply = IpcMessage::new();
+    loop {
+        if ipc_receive(KEYBOARD_DRIVER_PORT as u64, &mut reply).is_err() {
+            return false;
         }
 
-        // Enable keyboard
-        sys_io_write(KEYBOARD_COMMAND_PORT, 0xAE, 1);
+        match reply.msg_id {
+            MSG_PS2_COMMAND_REPLY if reply.msg_type == IPC_MSG_RESPONSE => {
+                return reply.inline_data[0] == PS2_STATUS_OK && reply.inline_data[1] == 1
+                    && reply.inline_data[2] == KEYBOARD_ACK;
+            }
+            MSG_PS2_DATA if reply.msg_type == IPC_MSG_NOTIFICATION => handle_scancode(reply.inline_data[0]),
+            _ => {
+                // Busy with a command of our own
+                let _ = ipc_reply(&reply, &create_error_response(3));
+            }
+        }
     }
 }
 
-fn handle_keyboard_interrupt() {
+fn handle_scancode(scancode: u8) {
     unsafe {
-        // Read scancode
-        let scancode = sys_io_read(KEYBOARD_DATA_PORT, 1) as u8;
-
-        // Convert to ASCII (simple mapping, ignores shift/ctrl/alt)
-        if (scancode & 0x80) == 0 {
-            // Key press (not release)
-            if (scancode as usize) < SCANCODE_TO_ASCII.len() {
-                let ascii = SCANCODE_TO_ASCII[scancode as usize];
-                if ascii != 0 {
-                    // Add to buffer
-                    let next_head = (KEY_BUFFER_HEAD + 1) % KEY_BUFFER_SIZE;
-                    if next_head != KEY_BUFFER_TAIL {
-                        KEY_BUFFER[KEY_BUFFER_HEAD] = ascii;
-                        KEY_BUFFER_HEAD = next_head;
-                    }
-                }
+        let event = match KEYMAP.process(scancode) {
+            Some(event) => event,
+            None => return, // prefix byte, wait for the rest
+        };
+
+        send_key_event(&event);
+
+        if event.pressed && event.keycode != 0 {
+            // Add to buffer
+            let next_head = (KEY_BUFFER_HEAD + 1) % KEY_BUFFER_SIZE;
+            if next_head != KEY_BUFFER_TAIL {
+                KEY_BUFFER[KEY_BUFFER_HEAD] = event;
+                KEY_BUFFER_HEAD = next_head;
             }
         }
     }
 }
 
+/// Forward a press or release, with modifiers, to the input server
+fn send_key_event(event: &KeyEvent) {
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_NOTIFICATION;
+    msg.msg_id = MSG_KEY_EVENT;
+    msg.set_inline_data(&event.encode());
+
+    let _ = ipc_send(INPUT_SERVER_PORT, &msg);
+}
+
 fn handle_message(msg: &IpcMessage) -> IpcMessage {
-    match msg.msg_type {
+    match msg.msg_id {
         MSG_KEYBOARD_GET_KEY => handle_get_key(),
         MSG_KEYBOARD_SET_LEDS => handle_set_leds(msg),
+        MSG_KEYBOARD_IS_KEY_DOWN => handle_is_key_down(msg),
         _ => create_error_response(1),
     }
 }
@@ -190,12 +184,8 @@ fn handle_get_key() -> IpcMessage {
             let key = KEY_BUFFER[KEY_BUFFER_TAIL];
             KEY_BUFFER_TAIL = (KEY_BUFFER_TAIL + 1) % KEY_BUFFER_SIZE;
 
-            let mut response = IpcMessage {
-                sender_tid: 0,
-                msg_type: 0,
-                data: [0; 256],
-            };
-            response.data[0] = key;
+            let mut response = create_success_response();
+            response.set_inline_data(&[key.ascii, key.modifiers, key.keycode]);
             response
         } else {
             // No key available
@@ -204,35 +194,37 @@ fn handle_get_key() -> IpcMessage {
     }
 }
 
+/// Request: [scancode, extended]. Response: [1 if held, 0 if not]
+fn handle_is_key_down(msg: &IpcMessage) -> IpcMessage {
+    let scancode = msg.inline_data[0];
+    let extended = msg.inline_data[1] != 0;
+
+    let mut response = create_success_response();
+    response.set_inline_data(&[unsafe { KEYMAP.is_down(scancode, extended) } as u8]);
+    response
+}
+
 fn handle_set_leds(msg: &IpcMessage) -> IpcMessage {
-    let leds = msg.data[0];
+    let leds = msg.inline_data[0];
 
-    unsafe {
-        // Send LED command
-        sys_io_write(KEYBOARD_DATA_PORT, 0xED, 1);
-        // Wait for ACK
-        while sys_io_read(KEYBOARD_DATA_PORT, 1) != 0xFA {}
-        // Send LED state
-        sys_io_write(KEYBOARD_DATA_PORT, leds as u32, 1);
+    if keyboard_command(KEYBOARD_CMD_SET_LEDS) && keyboard_command(leds) {
+        create_success_response()
+    } else {
+        create_error_response(4) // Keyboard did not acknowledge
     }
-
-    create_success_response()
 }
 
+/// Responses carry their status in msg_id: 0 for success, 1 for an error
+/// whose code is the inline data
 fn create_success_response() -> IpcMessage {
-    IpcMessage {
-        sender_tid: 0,
-        msg_type: 0,
-        data: [0; 256],
-    }
+    let mut response = IpcMessage::new();
+    response.msg_type = IPC_MSG_RESPONSE;
+    response
 }
 
 fn create_error_response(error_code: u32) -> IpcMessage {
-    let mut response = IpcMessage {
-        sender_tid: 0,
-        msg_type: 1,
-        data: [0; 256],
-    };
-    response.data[0..4].copy_from_slice(&error_code.to_le_bytes());
+    let mut response = create_success_response();
+    response.msg_id = 1;
+    response.set_inline_data(&error_code.to_le_bytes());
     response
 }
@@ -1,226 +1,211 @@
 /**
  * @file mouse_driver.rs
  * @brief User-space PS/2 mouse driver
+ *
+ * Packet bytes arrive from the PS/2 controller driver, which owns the 8042.
  */
 
 use core::panic::PanicInfo;
 
+use driver_framework::ipc::{
+    ipc_receive, ipc_reply, ipc_send, IpcMessage, IPC_MSG_NOTIFICATION, IPC_MSG_REQUEST,
+    IPC_MSG_RESPONSE,
+};
+
+mod packet;
+use packet::{PacketDecoder, INTELLIMOUSE_RATE_SEQUENCE};
+
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
     loop {}
 }
 
-// IPC syscall wrappers
+// Port registration syscall wrapper
 extern "C" {
-    fn sys_ipc_send(tid: u32, msg: *const IpcMessage) -> i32;
-    fn sys_ipc_receive(port: u32, msg: *mut IpcMessage) -> i32;
     fn sys_ipc_register_port(port: u32) -> i32;
-    fn sys_io_read(port: u16, size: u8) -> u32;
-    fn sys_io_write(port: u16, value: u32, size: u8) -> i32;
-    fn sys_irq_register(irq: u32) -> i32;
-    fn sys_irq_wait() -> u32;
-}
-
-#[repr(C)]
-struct IpcMessage {
-    sender_tid: u32,
-    msg_type: u32,
-    data: [u8; 256],
 }
 
 // Mouse IPC port
 const MOUSE_DRIVER_PORT: u32 = 104;
 
-// PS/2 mouse ports
-const MOUSE_DATA_PORT: u16 = 0x60;
-const MOUSE_STATUS_PORT: u16 = 0x64;
-const MOUSE_COMMAND_PORT: u16 = 0x64;
-
-// Mouse IRQ
-const MOUSE_IRQ: u32 = 12;
-
-// Message types
-const MSG_MOUSE_GET_EVENT: u32 = 1;
-const MSG_MOUSE_SET_RESOLUTION: u32 = 2;
+// PS/2 controller and its messages
+const PS2_CONTROLLER_PORT: u64 = 107;
+const MSG_PS2_DATA: u64 = 16;
+const MSG_PS2_COMMAND: u64 = 17;
+const MSG_PS2_COMMAND_REPLY: u64 = 18;
+const PS2_CHANNEL_AUX: u8 = 1;
+const PS2_STATUS_OK: u8 = 0;
+const PS2_MAX_RESPONSE: usize = 3;
+
+// Mouse commands and replies
+const MOUSE_CMD_SET_RESOLUTION: u8 = 0xE8;
+const MOUSE_CMD_GET_DEVICE_ID: u8 = 0xF2;
+const MOUSE_CMD_SET_SAMPLE_RATE: u8 = 0xF3;
+const MOUSE_CMD_ENABLE_REPORTING: u8 = 0xF4;
+const MOUSE_CMD_SET_DEFAULTS: u8 = 0xF6;
+const MOUSE_ACK: u8 = 0xFA;
+
+// Message ids
+const MSG_MOUSE_GET_EVENT: u64 = 1;
+const MSG_MOUSE_SET_RESOLUTION: u64 = 2;
 
 // Mouse state
 static mut MOUSE_X: i32 = 0;
 static mut MOUSE_Y: i32 = 0;
 static mut MOUSE_BUTTONS: u8 = 0;
-static mut MOUSE_CYCLE: u8 = 0;
-static mut MOUSE_PACKET: [u8; 3] = [0; 3];
+static mut MOUSE_SCROLL: i32 = 0; // Wheel movement since the last GET_EVENT
+static mut MOUSE_DECODER: PacketDecoder = PacketDecoder::new();
 
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
     // Register with driver manager
     register_with_driver_manager();
 
-    // Initialize mouse
-    init_mouse();
-
-    // Register IRQ handler
-    unsafe {
-        sys_irq_register(MOUSE_IRQ);
-    }
-
-    // Register IPC port
+    // Register IPC port first: command replies and packet bytes from the
+    // PS/2 controller arrive here
     unsafe {
         sys_ipc_register_port(MOUSE_DRIVER_PORT);
     }
 
+    // Initialize mouse
+    init_mouse();
+
     // Main service loop
+    let mut msg = IpcMessage::new();
     loop {
-        // Wait for IRQ
-        unsafe {
-            let irq = sys_irq_wait();
-            if irq == MOUSE_IRQ {
-                handle_mouse_interrupt();
-            }
+        if ipc_receive(MOUSE_DRIVER_PORT as u64, &mut msg).is_err() {
+            continue;
         }
 
-        // Check for IPC messages
-        let mut msg = IpcMessage {
-            sender_tid: 0,
-            msg_type: 0,
-            data: [0; 256],
-        };
-
-        unsafe {
-            if sys_ipc_receive(MOUSE_DRIVER_PORT, &mut msg) == 0 {
-                let response = handle_message(&msg);
-                let _ = sys_ipc_send(msg.sender_tid, &response);
-            }
+        if msg.msg_id == MSG_PS2_DATA {
+            handle_mouse_byte(msg.inline_data[0]);
+        } else {
+            let response = handle_message(&msg);
+            let _ = ipc_reply(&msg, &response);
         }
     }
 }
 
 fn register_with_driver_manager() {
-    const DRIVER_MANAGER_PORT: u32 = 100;
-    const MSG_REGISTER_DRIVER: u32 = 1;
-    const DRIVER_TYPE_INPUT: u32 = 4;
+    const DRIVER_MANAGER_PORT: u64 = 100;
+    const MSG_REGISTER_DRIVER: u64 = 1;
+    const DRIVER_TYPE_INPUT: u8 = 4;
     
-    // Create registration message
-    let mut msg = IpcMessage {
-        sender_tid: 0,  // Will be filled by kernel
-        msg_type: 1,    // IPC_MSG_REQUEST
-        data: [0; 256],
-    };
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_REQUEST;
+    msg.msg_id = MSG_REGISTER_DRIVER;
     
     // Pack driver type (Input = 4) and port into message data
-    msg.data[0] = DRIVER_TYPE_INPUT as u8;
-    msg.data[1..5].copy_from_slice(&MOUSE_DRIVER_PORT.to_le_bytes());
+    msg.inline_data[0] = DRIVER_TYPE_INPUT;
+    msg.inline_data[1..5].copy_from_slice(&MOUSE_DRIVER_PORT.to_le_bytes());
+    msg.inline_size = 5;
     
     // Send registration message to driver manager
-    unsafe {
-        let _ = sys_ipc_send(DRIVER_MANAGER_PORT, &msg);
-    }
+    let _ = ipc_send(DRIVER_MANAGER_PORT, &msg);
 }
 
 fn init_mouse() {
-    unsafe {
-        // Enable auxiliary device
-        mouse_wait(1);
-        sys_io_write(MOUSE_COMMAND_PORT, 0xA8, 1);
-
-        // Enable interrupts
-        mouse_wait(1);
-        sys_io_write(MOUSE_COMMAND_PORT, 0x20, 1);
-        mouse_wait(0);
-        let mut status = sys_io_read(MOUSE_DATA_PORT, 1) as u8;
-        status |= 0x02; // Enable IRQ12
-        mouse_wait(1);
-        sys_io_write(MOUSE_COMMAND_PORT, 0x60, 1);
-        mouse_wait(1);
-        sys_io_write(MOUSE_DATA_PORT, status as u32, 1);
-
-        // Use default settings
-        mouse_write(0xF6);
-        mouse_read();
-
-        // Enable data reporting
-        mouse_write(0xF4);
-        mouse_read();
-    }
+    // The PS/2 controller has already enabled the port and its IRQ
+
+    // Use default settings
+    mouse_command(MOUSE_CMD_SET_DEFAULTS, 1);
+
+    // Try to switch on the scroll wheel
+    enable_wheel();
+
+    // Enable data reporting
+    mouse_command(MOUSE_CMD_ENABLE_REPORTING, 1);
 }
 
-fn mouse_wait(wait_type: u8) {
-    unsafe {
-        let timeout = 100000;
-        if wait_type == 0 {
-            // Wait for output buffer
-            for _ in 0..timeout {
-                if (sys_io_read(MOUSE_STATUS_PORT, 1) & 0x01) != 0 {
-                    return;
-                }
-            }
-        } else {
-            // Wait for input buffer
-            for _ in 0..timeout {
-                if (sys_io_read(MOUSE_STATUS_PORT, 1) & 0x02) == 0 {
-                    return;
-                }
-            }
+/// IntelliMouse detection: the magic sample rate sequence makes a wheel
+/// mouse report id 3 and send 4-byte packets
+fn enable_wheel() {
+    for &rate in INTELLIMOUSE_RATE_SEQUENCE.iter() {
+        mouse_command(MOUSE_CMD_SET_SAMPLE_RATE, 1);
+        mouse_command(rate, 1);
+    }
+
+    // ACK, then the id
+    if let Some(response) = mouse_command(MOUSE_CMD_GET_DEVICE_ID, 2) {
+        unsafe {
+            MOUSE_DECODER.set_device_id(response[1]);
         }
     }
 }
 
-fn mouse_write(value: u8) {
-    unsafe {
-        mouse_wait(1);
-        sys_io_write(MOUSE_COMMAND_PORT, 0xD4, 1);
-        mouse_wait(1);
-        sys_io_write(MOUSE_DATA_PORT, value as u32, 1);
+/// Send one byte to the mouse through the PS/2 controller and collect
+/// `expected` response bytes, the first of which must be an ACK. Packet
+/// bytes that arrive while waiting for the reply are processed as usual.
+fn mouse_command(byte: u8, expected: u8) -> Option<[u8; PS2_MAX_RESPONSE]> {
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_REQUEST;
+    msg.msg_id = MSG_PS2_COMMAND;
+    msg.set_inline_data(&[PS2_CHANNEL_AUX, byte, expected]);
+    // The reply arrives among the packet bytes on our own port
+    msg.reply_port = MOUSE_DRIVER_PORT as u64;
+
+    if ipc_send(PS2_CONTROLLER_PORT, &msg).is_err() {
+        return None;
     }
-}
 
-fn mouse_read() -> u8 {
-    unsafe {
-        mouse_wait(0);
-        sys_io_read(MOUSE_DATA_PORT, 1) as u8
+    let mut reply = IpcMessage::new();
+    loop {
+        if ipc_receive(MOUSE_DRIVER_PORT as u64, &mut reply).is_err() {
+            return None;
+        }
+
+        match reply.msg_id {
+            MSG_PS2_COMMAND_REPLY if reply.msg_type == IPC_MSG_RESPONSE => {
+                let data = &reply.inline_data;
+                if data[0] != PS2_STATUS_OK || data[1] != expected || data[2] != MOUSE_ACK {
+                    return None;
+                }
+                let mut response = [0u8; PS2_MAX_RESPONSE];
+                response.copy_from_slice(&data[2..2 + PS2_MAX_RESPONSE]);
+                return Some(response);
+            }
+            MSG_PS2_DATA if reply.msg_type == IPC_MSG_NOTIFICATION => handle_mouse_byte(reply.inline_data[0]),
+            _ => {
+                // Busy with a command of our own
+                let _ = ipc_reply(&reply, &create_error_response(3));
+            }
+        }
     }
 }
 
-fn handle_mouse_interrupt() {
+fn handle_mouse_byte(data: u8) {
     unsafe {
-        let data = sys_io_read(MOUSE_DATA_PORT, 1) as u8;
-
-        MOUSE_PACKET[MOUSE_CYCLE as usize] = data;
-        MOUSE_CYCLE += 1;
-
-        if MOUSE_CYCLE == 3 {
-            MOUSE_CYCLE = 0;
-
-            // Parse packet
-            let flags = MOUSE_PACKET[0];
-            let dx = MOUSE_PACKET[1] as i8;
-            let dy = MOUSE_PACKET[2] as i8;
-
-            // Update position
-            MOUSE_X += dx as i32;
-            MOUSE_Y -= dy as i32; // Y is inverted
+        let packet = match MOUSE_DECODER.process(data) {
+            Some(packet) => packet,
+            None => return,
+        };
 
-            // Clamp to screen (assuming 1024x768 for now)
-            if MOUSE_X < 0 {
-                MOUSE_X = 0;
-            }
-            if MOUSE_X > 1023 {
-                MOUSE_X = 1023;
-            }
-            if MOUSE_Y < 0 {
-                MOUSE_Y = 0;
-            }
-            if MOUSE_Y > 767 {
-                MOUSE_Y = 767;
-            }
+        // Update position
+        MOUSE_X += packet.dx as i32;
+        MOUSE_Y -= packet.dy as i32; // Y is inverted
 
-            // Update buttons
-            MOUSE_BUTTONS = flags & 0x07;
+        // Clamp to screen (assuming 1024x768 for now)
+        if MOUSE_X < 0 {
+            MOUSE_X = 0;
         }
+        if MOUSE_X > 1023 {
+            MOUSE_X = 1023;
+        }
+        if MOUSE_Y < 0 {
+            MOUSE_Y = 0;
+        }
+        if MOUSE_Y > 767 {
+            MOUSE_Y = 767;
+        }
+
+        // Update buttons and wheel
+        MOUSE_BUTTONS = packet.buttons;
+        MOUSE_SCROLL += packet.scroll as i32;
     }
 }
 
 fn handle_message(msg: &IpcMessage) -> IpcMessage {
-    match msg.msg_type {
+    match msg.msg_id {
         MSG_MOUSE_GET_EVENT => handle_get_event(),
         MSG_MOUSE_SET_RESOLUTION => handle_set_resolution(msg),
         _ => create_error_response(1),
@@ -229,49 +214,43 @@ fn handle_message(msg: &IpcMessage) -> IpcMessage {
 
 fn handle_get_event() -> IpcMessage {
     unsafe {
-        let mut response = IpcMessage {
-            sender_tid: 0,
-            msg_type: 0,
-            data: [0; 256],
-        };
+        let mut response = create_success_response();
 
         // Pack mouse state into response
-        response.data[0..4].copy_from_slice(&MOUSE_X.to_le_bytes());
-        response.data[4..8].copy_from_slice(&MOUSE_Y.to_le_bytes());
-        response.data[8] = MOUSE_BUTTONS;
+        response.inline_data[0..4].copy_from_slice(&MOUSE_X.to_le_bytes());
+        response.inline_data[4..8].copy_from_slice(&MOUSE_Y.to_le_bytes());
+        response.inline_data[8] = MOUSE_BUTTONS;
+        response.inline_data[9..13].copy_from_slice(&MOUSE_SCROLL.to_le_bytes());
+        response.inline_size = 13;
+        MOUSE_SCROLL = 0;
 
         response
     }
 }
 
 fn handle_set_resolution(msg: &IpcMessage) -> IpcMessage {
-    unsafe {
-        if msg.data[0] < 4 { // Resolution byte is 0-3
-            mouse_write(0xE8); // Set Resolution Command
-            mouse_read();      // Read ACK
-            mouse_write(msg.data[0]); // Send resolution byte
-            mouse_read();      // Read ACK
-            create_success_response()
-        } else {
-            create_error_response(2) // Invalid resolution
-        }
+    if msg.inline_data[0] >= 4 { // Resolution byte is 0-3
+        return create_error_response(2); // Invalid resolution
+    }
+
+    if mouse_command(MOUSE_CMD_SET_RESOLUTION, 1).is_some() && mouse_command(msg.inline_data[0], 1).is_some() {
+        create_success_response()
+    } else {
+        create_error_response(4) // Mouse did not acknowledge
     }
 }
 
+/// Responses carry their status in msg_id: 0 for success, 1 for an error
+/// whose code is the inline data
 fn create_success_response() -> IpcMessage {
-    IpcMessage {
-        sender_tid: 0,
-        msg_type: 0,
-        data: [0; 256],
-    }
+    let mut response = IpcMessage::new();
+    response.msg_type = IPC_MSG_RESPONSE;
+    response
 }
 
 fn create_error_response(error_code: u32) -> IpcMessage {
-    let mut response = IpcMessage {
-        sender_tid: 0,
-        msg_type: 1,
-        data: [0; 256],
-    };
-    response.data[0..4].copy_from_slice(&error_code.to_le_bytes());
+    let mut response = create_success_response();
+    response.msg_id = 1;
+    response.set_inline_data(&error_code.to_le_bytes());
     response
 }
@@ -0,0 +1,111 @@
+//! PS/2 mouse packets
+//!
+//! A standard mouse sends 3-byte packets: flags, X, Y. After the
+//! IntelliMouse sample rate sequence (200, 100, 80) a wheel mouse reports
+//! device id 3 and appends a fourth byte holding the scroll delta.
+//!
+//! Bit 3 of the flags byte is always set, which is the only way to find
+//! the start of a packet again after a byte is lost.
+
+/// Device ids returned by the Get Device ID command
+pub const MOUSE_ID_STANDARD: u8 = 0x00;
+pub const MOUSE_ID_INTELLIMOUSE: u8 = 0x03;
+
+/// Sample rates that unlock the Z axis, in order
+pub const INTELLIMOUSE_RATE_SEQUENCE: [u8; 3] = [200, 100, 80];
+
+pub const PACKET_SIZE_STANDARD: usize = 3;
+pub const PACKET_SIZE_WHEEL: usize = 4;
+
+// Flags byte
+const BUTTON_MASK: u8 = 0x07;
+const FLAG_ALWAYS_ONE: u8 = 1 << 3;
+const FLAG_X_SIGN: u8 = 1 << 4;
+const FLAG_Y_SIGN: u8 = 1 << 5;
+const FLAG_X_OVERFLOW: u8 = 1 << 6;
+const FLAG_Y_OVERFLOW: u8 = 1 << 7;
+
+/// Movement is a 9-bit two's complement value, the sign in the flags byte
+fn movement(value: u8, flags: u8, sign: u8) -> i16 {
+    if flags & sign != 0 {
+        value as i16 - 0x100
+    } else {
+        value as i16
+    }
+}
+
+/// One decoded movement report
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MousePacket {
+    pub buttons: u8,
+    pub dx: i16,
+    pub dy: i16,
+    /// Wheel movement, positive towards the user
+    pub scroll: i8,
+}
+
+/// Collects bytes into packets
+pub struct PacketDecoder {
+    bytes: [u8; PACKET_SIZE_WHEEL],
+    cycle: usize,
+    size: usize,
+}
+
+impl PacketDecoder {
+    pub const fn new() -> Self {
+        PacketDecoder {
+            bytes: [0; PACKET_SIZE_WHEEL],
+            cycle: 0,
+            size: PACKET_SIZE_STANDARD,
+        }
+    }
+
+    /// Switch packet size for the given device id
+    pub fn set_device_id(&mut self, id: u8) {
+        self.size = if id == MOUSE_ID_INTELLIMOUSE {
+            PACKET_SIZE_WHEEL
+        } else {
+            PACKET_SIZE_STANDARD
+        };
+        self.cycle = 0;
+    }
+
+    pub fn packet_size(&self) -> usize {
+        self.size
+    }
+
+    /// Feed one byte from the controller. Returns a packet once complete;
+    /// packets with overflowed movement are dropped.
+    pub fn process(&mut self, byte: u8) -> Option<MousePacket> {
+        // Out of step: skip bytes until one can be a flags byte
+        if self.cycle == 0 && byte & FLAG_ALWAYS_ONE == 0 {
+            return None;
+        }
+
+        self.bytes[self.cycle] = byte;
+        self.cycle += 1;
+        if self.cycle < self.size {
+            return None;
+        }
+        self.cycle = 0;
+
+        let flags = self.bytes[0];
+        if flags & (FLAG_X_OVERFLOW | FLAG_Y_OVERFLOW) != 0 {
+            return None;
+        }
+
+        let scroll = if self.size == PACKET_SIZE_WHEEL {
+            // Z is a 4-bit two's complement value
+            ((self.bytes[3] << 4) as i8) >> 4
+        } else {
+            0
+        };
+
+        Some(MousePacket {
+            buttons: flags & BUTTON_MASK,
+            dx: movement(self.bytes[1], flags, FLAG_X_SIGN),
+            dy: movement(self.bytes[2], flags, FLAG_Y_SIGN),
+            scroll,
+        })
+    }
+}
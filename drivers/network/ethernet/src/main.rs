@@ -6,13 +6,22 @@
 #![no_main]
 
 mod packet;
+mod rx_ring;
+mod tx_ring;
 
 use driver_framework::{Driver, DriverError, DeviceInfo, DeviceType};
 use driver_framework::mmio::MmioRegion;
 use driver_framework::interrupts;
+use driver_framework::pci;
 use driver_framework::ipc::{ipc_create_port, ipc_receive, ipc_send, IpcMessage};
 use driver_framework::dma::DmaBuffer;
 use packet::{NET_DEV_OP_SEND, NET_DEV_OP_RECEIVE, NET_DEV_OP_GET_MAC, NET_DEV_OP_SET_IP};
+use packet::{NET_DEV_OP_LINK_STATUS, NET_DEV_NOTIFY_LINK_CHANGE};
+use packet::{NET_DEV_OP_ADD_MULTICAST, NET_DEV_OP_SET_PROMISCUOUS, NET_DEV_OP_GET_STATS, NetDevStats};
+use rx_ring::{RxRing, RxAction};
+use tx_ring::TxRing;
+use driver_framework::syscalls::wait_until;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 // E1000 Registers
 const E1000_CTRL: usize = 0x0000;
@@ -33,6 +42,24 @@ const E1000_TDLEN: usize = 0x3808;
 const E1000_TDH: usize = 0x3810;
 const E1000_TDT: usize = 0x3818;
 const E1000_MTA: usize = 0x5200;
+const E1000_RAL0: usize = 0x5400;
+const E1000_RAH0: usize = 0x5404;
+
+// Statistics registers (clear on read)
+const E1000_CRCERRS: usize = 0x4000;
+const E1000_ALGNERRC: usize = 0x4004;
+const E1000_SYMERRS: usize = 0x4008;
+const E1000_RXERRC: usize = 0x400C;
+const E1000_MPC: usize = 0x4010;
+const E1000_SEC: usize = 0x4038;
+const E1000_RLEC: usize = 0x4040;
+const E1000_GPRC: usize = 0x4074;
+const E1000_GPTC: usize = 0x4080;
+const E1000_GORCL: usize = 0x4088;
+const E1000_GORCH: usize = 0x408C;
+const E1000_GOTCL: usize = 0x4090;
+const E1000_GOTCH: usize = 0x4094;
+const E1000_RNBC: usize = 0x40A0;
 
 // Constants
 const RX_DESC_COUNT: usize = 32;
@@ -44,12 +71,54 @@ const E1000_RCTL_MPE: u32 = 1 << 4;
 const E1000_RCTL_LPE: u32 = 1 << 5;
 const E1000_RCTL_BAM: u32 = 1 << 15;
 const E1000_RCTL_SECRC: u32 = 1 << 26;
+const E1000_RAH_AV: u32 = 1 << 31;      // Receive address valid
+const MTA_REGISTER_COUNT: usize = 128;  // 4096-bit multicast hash table
 const E1000_TCTL_EN: u32 = 1 << 1;
 const E1000_TCTL_PSP: u32 = 1 << 3;
 const E1000_CMD_EOP: u8 = 1 << 0;
 const E1000_CMD_IFCS: u8 = 1 << 1;
 const E1000_CMD_RS: u8 = 1 << 3;
 
+// Interrupt cause bits (ICR, IMS)
+const E1000_INT_TXDW: u32 = 1 << 0;     // Transmit descriptor written back
+
+/// How long a send waits for the hardware to free a descriptor
+const TX_RING_FULL_TIMEOUT_MS: u64 = 10;
+
+// STATUS register bits
+const E1000_STATUS_FD: u32 = 1 << 0;
+const E1000_STATUS_LU: u32 = 1 << 1;
+const E1000_STATUS_SPEED_SHIFT: u32 = 6;
+const E1000_STATUS_SPEED_MASK: u32 = 0x3 << E1000_STATUS_SPEED_SHIFT;
+
+/// Port the network service receives notifications on
+const NETWORK_SERVICE_PORT: u64 = 3;
+
+/// Set by the IRQ handler when transmits complete; the driver loop reclaims
+/// their descriptors
+static TX_DONE: AtomicBool = AtomicBool::new(false);
+/// Register base for the IRQ handler (0 until the NIC is mapped)
+static IRQ_MMIO: AtomicUsize = AtomicUsize::new(0);
+
+/// Shared IRQ handler: acknowledge the E1000's interrupt causes and note
+/// completed transmits
+extern "C" fn e1000_irq_handler() -> bool {
+    let regs = IRQ_MMIO.load(Ordering::Acquire) as *const u8;
+    if regs.is_null() {
+        return false;
+    }
+
+    // Reading ICR clears it; nothing set means another device on the line
+    let icr = unsafe { core::ptr::read_volatile(regs.add(E1000_ICR) as *const u32) };
+    if icr == 0 {
+        return false;
+    }
+    if icr & E1000_INT_TXDW != 0 {
+        TX_DONE.store(true, Ordering::Release);
+    }
+    true
+}
+
 #[repr(C, packed)]
 struct RxDesc {
     addr: u64,
@@ -71,20 +140,38 @@ struct TxDesc {
     special: u16,
 }
 
+/// Link state decoded from the STATUS register
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct LinkStatus {
+    up: bool,
+    speed_mbps: u16,
+    full_duplex: bool,
+}
+
+const LINK_DOWN: LinkStatus = LinkStatus { up: false, speed_mbps: 0, full_duplex: false };
+
 struct EthernetDriver {
     initialized: bool,
     device_port: u64,
     mmio: Option<MmioRegion>,
     mac_address: [u8; 6],
     irq: u8,
+    link: LinkStatus,
+    
+    // Receive filtering
+    promiscuous: bool,
+    mta: [u32; MTA_REGISTER_COUNT], // Shadow of the multicast table array
+    
+    // Running totals of the clear-on-read statistics registers
+    stats: NetDevStats,
     
     // E1000 specific
     rx_desc_ring: Option<DmaBuffer>,
     tx_desc_ring: Option<DmaBuffer>,
     rx_buffers: Option<DmaBuffer>, // One large buffer for all RX packets
     tx_buffers: Option<DmaBuffer>, // One large buffer for all TX packets
-    rx_cur: usize,
-    tx_cur: usize,
+    rx_state: RxRing,
+    tx_state: TxRing,
 }
 
 impl EthernetDriver {
@@ -95,19 +182,23 @@ impl EthernetDriver {
             mmio: None,
             mac_address: [0; 6],
             irq: 0,
+            link: LINK_DOWN,
+            promiscuous: false,
+            mta: [0; MTA_REGISTER_COUNT],
+            stats: NetDevStats::new(),
             rx_desc_ring: None,
             tx_desc_ring: None,
             rx_buffers: None,
             tx_buffers: None,
-            rx_cur: 0,
-            tx_cur: 0,
+            rx_state: RxRing::new(RX_DESC_COUNT),
+            tx_state: TxRing::new(TX_DESC_COUNT),
         }
     }
     
     fn read_mac(&mut self) {
         if let Some(ref mmio) = self.mmio {
-            let low = unsafe { mmio.read_u32(0x5400) }; // RAL
-            let high = unsafe { mmio.read_u32(0x5404) }; // RAH
+            let low = unsafe { mmio.read_u32(E1000_RAL0) };
+            let high = unsafe { mmio.read_u32(E1000_RAH0) };
             
             if low != 0 || high != 0 {
                 self.mac_address[0] = (low & 0xFF) as u8;
@@ -120,19 +211,144 @@ impl EthernetDriver {
         }
     }
 
+    /// Program RAL0/RAH0 with our unicast MAC, load the multicast table and
+    /// set RCTL. Unicast/multicast promiscuous mode is only enabled on request.
+    fn program_receive_filters(&self) {
+        let mmio = match self.mmio {
+            Some(ref mmio) => mmio,
+            None => return,
+        };
+        let mac = &self.mac_address;
+        
+        unsafe {
+            let low = u32::from_le_bytes([mac[0], mac[1], mac[2], mac[3]]);
+            let high = u32::from_le_bytes([mac[4], mac[5], 0, 0]) | E1000_RAH_AV;
+            mmio.write_u32(E1000_RAL0, low);
+            mmio.write_u32(E1000_RAH0, high);
+            
+            for i in 0..MTA_REGISTER_COUNT {
+                mmio.write_u32(E1000_MTA + i * 4, self.mta[i]);
+            }
+            
+            let mut rctl = E1000_RCTL_EN | E1000_RCTL_SBP | E1000_RCTL_LPE | E1000_RCTL_BAM | E1000_RCTL_SECRC;
+            if self.promiscuous {
+                rctl |= E1000_RCTL_UPE | E1000_RCTL_MPE;
+            }
+            mmio.write_u32(E1000_RCTL, rctl);
+        }
+    }
+    
+    /// Accept frames sent to a multicast group.
+    /// The MTA is indexed by bits 47:36 of the destination address (RCTL.MO = 00).
+    fn add_multicast(&mut self, mac: &[u8; 6]) -> Result<(), DriverError> {
+        if (mac[0] & 0x01) == 0 {
+            return Err(DriverError::InvalidArgument); // Not a group address
+        }
+        
+        let hash = (((mac[4] as u32) >> 4) | ((mac[5] as u32) << 4)) & 0xFFF;
+        let reg = ((hash >> 5) & 0x7F) as usize;
+        let bit = hash & 0x1F;
+        self.mta[reg] |= 1 << bit;
+        
+        if let Some(ref mmio) = self.mmio {
+            unsafe {
+                mmio.write_u32(E1000_MTA + reg * 4, self.mta[reg]);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Fold the hardware statistics registers into the running totals.
+    /// The registers clear on read, so every read must be accumulated.
+    fn update_stats(&mut self) -> NetDevStats {
+        if let Some(ref mmio) = self.mmio {
+            unsafe {
+                let stats = &mut self.stats;
+                stats.rx_packets += mmio.read_u32(E1000_GPRC) as u64;
+                stats.tx_packets += mmio.read_u32(E1000_GPTC) as u64;
+                
+                // Octet counters: the low half must be read first
+                let gorc_low = mmio.read_u32(E1000_GORCL) as u64;
+                stats.rx_bytes += gorc_low | ((mmio.read_u32(E1000_GORCH) as u64) << 32);
+                let gotc_low = mmio.read_u32(E1000_GOTCL) as u64;
+                stats.tx_bytes += gotc_low | ((mmio.read_u32(E1000_GOTCH) as u64) << 32);
+                
+                stats.rx_dropped += mmio.read_u32(E1000_MPC) as u64;
+                stats.rx_no_buffer += mmio.read_u32(E1000_RNBC) as u64;
+                stats.rx_crc_errors += mmio.read_u32(E1000_CRCERRS) as u64;
+                stats.rx_errors += mmio.read_u32(E1000_SYMERRS) as u64
+                    + mmio.read_u32(E1000_SEC) as u64
+                    + mmio.read_u32(E1000_ALGNERRC) as u64
+                    + mmio.read_u32(E1000_RXERRC) as u64
+                    + mmio.read_u32(E1000_RLEC) as u64;
+            }
+        }
+        self.stats
+    }
+    
+    /// Read link up/down, negotiated speed and duplex from STATUS
+    fn read_link_status(&self) -> LinkStatus {
+        let mmio = match self.mmio {
+            Some(ref mmio) => mmio,
+            None => return LINK_DOWN,
+        };
+
+        let status = unsafe { mmio.read_u32(E1000_STATUS) };
+        if (status & E1000_STATUS_LU) == 0 {
+            return LINK_DOWN;
+        }
+
+        let speed_mbps = match (status & E1000_STATUS_SPEED_MASK) >> E1000_STATUS_SPEED_SHIFT {
+            0 => 10,
+            1 => 100,
+            _ => 1000,
+        };
+
+        LinkStatus {
+            up: true,
+            speed_mbps,
+            full_duplex: (status & E1000_STATUS_FD) != 0,
+        }
+    }
+
+    /// Poll the link and tell the network service when it changes
+    /// (cable pulled, NIC hot-plugged or unplugged in the VM)
+    fn check_link(&mut self) {
+        if !self.initialized {
+            return;
+        }
+
+        let link = self.read_link_status();
+        if link == self.link {
+            return;
+        }
+        self.link = link;
+
+        let mut msg = IpcMessage::new();
+        msg.msg_type = driver_framework::ipc::IPC_MSG_NOTIFICATION;
+        msg.msg_id = NET_DEV_NOTIFY_LINK_CHANGE;
+        msg.inline_data[0..8].copy_from_slice(&self.device_port.to_le_bytes());
+        msg.inline_data[8] = link.up as u8;
+        msg.inline_data[9..11].copy_from_slice(&link.speed_mbps.to_le_bytes());
+        msg.inline_data[11] = link.full_duplex as u8;
+        msg.inline_size = 12;
+        let _ = ipc_send(NETWORK_SERVICE_PORT, &msg);
+    }
+
     fn init_nic(&mut self, device_info: &DeviceInfo) -> Result<(), DriverError> {
         if self.initialized {
             return Err(DriverError::AlreadyInitialized);
         }
         
-        // Decode BAR0 (MMIO base)
-        let bar0 = device_info.bars[0];
-        if bar0 == 0 {
+        // BAR0 holds the register space (may be a 64-bit BAR)
+        let bar0 = pci::get_bar(device_info.bus, device_info.device, device_info.function, 0)?;
+        if !bar0.is_mmio || bar0.base == 0 {
             return Err(DriverError::DeviceNotFound);
         }
+        // The descriptor rings are DMA'd, so bus mastering must be on
+        pci::enable_device(device_info.bus, device_info.device, device_info.function)?;
         
-        let mmio_base = bar0 & !0xF;
-        let mmio = MmioRegion::map(mmio_base, 0x20000).map_err(|_| DriverError::IoError)?;
+        let mmio = MmioRegion::map(bar0.base, bar0.size as usize).map_err(|_| DriverError::IoError)?;
         self.mmio = Some(mmio);
         self.irq = device_info.irq_line;
         
@@ -178,21 +394,28 @@ impl EthernetDriver {
             mmio.write_u32(E1000_RDBAH, (rx_ring.phys_addr() >> 32) as u32);
             mmio.write_u32(E1000_RDLEN, rx_desc_size as u32);
             mmio.write_u32(E1000_RDH, 0);
-            mmio.write_u32(E1000_RDT, (RX_DESC_COUNT - 1) as u32);
-            
-            mmio.write_u32(E1000_RCTL, E1000_RCTL_EN | E1000_RCTL_SBP | E1000_RCTL_UPE | E1000_RCTL_MPE | E1000_RCTL_LPE | E1000_RCTL_BAM | E1000_RCTL_SECRC);
+            self.rx_state = RxRing::new(RX_DESC_COUNT);
+            mmio.write_u32(E1000_RDT, self.rx_state.tail() as u32);
             
             // Program TCTL
             mmio.write_u32(E1000_TDBAL, (tx_ring.phys_addr() & 0xFFFFFFFF) as u32);
             mmio.write_u32(E1000_TDBAH, (tx_ring.phys_addr() >> 32) as u32);
             mmio.write_u32(E1000_TDLEN, tx_desc_size as u32);
             mmio.write_u32(E1000_TDH, 0);
-            mmio.write_u32(E1000_TDT, 0);
+            self.tx_state = TxRing::new(TX_DESC_COUNT);
+            mmio.write_u32(E1000_TDT, self.tx_state.tail() as u32);
             
             mmio.write_u32(E1000_TCTL, E1000_TCTL_EN | E1000_TCTL_PSP);
             
             // Enable Interrupts
-            mmio.write_u32(E1000_IMS, 0x1F6DC); // Enable all interrupts
+            mmio.write_u32(E1000_IMS, 0x1F6DC | E1000_INT_TXDW); // Enable all interrupts
+        }
+
+        // Without an IRQ, sends reclaim completed descriptors themselves
+        IRQ_MMIO.store(mmio.base() as usize, Ordering::Release);
+        if self.irq != 0 && self.irq != 0xFF {
+            let _ = interrupts::register_irq_shared(self.irq, e1000_irq_handler)
+                .and_then(|_| interrupts::enable_irq(self.irq));
         }
         
         self.rx_desc_ring = Some(rx_ring);
@@ -200,20 +423,62 @@ impl EthernetDriver {
         self.rx_buffers = Some(rx_bufs);
         self.tx_buffers = Some(tx_bufs);
         
+        // Only accept our own MAC, broadcast and subscribed multicast groups
+        self.program_receive_filters();
+        
         self.initialized = true;
+        self.link = self.read_link_status();
         Ok(())
     }
     
+    /// Reclaim descriptors the hardware has finished sending, in order
+    fn reclaim_tx(&mut self) {
+        let tx_ring = match self.tx_desc_ring.as_mut() {
+            Some(tx_ring) => tx_ring,
+            None => return,
+        };
+        
+        unsafe {
+            let tx_descs = tx_ring.as_mut_slice_of::<TxDesc>(TX_DESC_COUNT);
+            loop {
+                let desc = &tx_descs[self.tx_state.next_to_clean()] as *const TxDesc;
+                // Hardware writes the status back behind our back
+                let status = core::ptr::read_volatile(core::ptr::addr_of!((*desc).status));
+                if !self.tx_state.reclaim(status) {
+                    break;
+                }
+            }
+        }
+    }
+    
+    /// Reclaim descriptors after a transmit-done interrupt
+    fn handle_tx_done(&mut self) {
+        if TX_DONE.swap(false, Ordering::AcqRel) {
+            self.reclaim_tx();
+        }
+    }
+    
+    /// Queue a frame. A descriptor is reused only once the hardware has sent
+    /// it; with every one still in flight the send waits briefly, then gives
+    /// up with `WouldBlock`.
     fn send_packet(&mut self, data: &[u8]) -> Result<(), DriverError> {
         if !self.initialized { return Err(DriverError::NotInitialized); }
         
+        self.reclaim_tx();
+        if self.tx_state.is_full() && !wait_until(TX_RING_FULL_TIMEOUT_MS, || {
+            self.reclaim_tx();
+            !self.tx_state.is_full()
+        }) {
+            return Err(DriverError::WouldBlock);
+        }
+        let cur = self.tx_state.claim().ok_or(DriverError::WouldBlock)?;
+        
         let mmio = self.mmio.as_ref().unwrap();
         let tx_ring = self.tx_desc_ring.as_mut().unwrap();
         let tx_bufs = self.tx_buffers.as_ref().unwrap();
         
         unsafe {
             let tx_descs = tx_ring.as_mut_slice_of::<TxDesc>(TX_DESC_COUNT);
-            let cur = self.tx_cur;
             
             // Copy data to buffer
             let buf_offset = cur * 2048;
@@ -226,10 +491,10 @@ impl EthernetDriver {
             tx_descs[cur].length = len as u16;
             tx_descs[cur].cmd = E1000_CMD_EOP | E1000_CMD_IFCS | E1000_CMD_RS;
             tx_descs[cur].status = 0;
+            core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
             
-            // Advance Tail
-            self.tx_cur = (cur + 1) % TX_DESC_COUNT;
-            mmio.write_u32(E1000_TDT, self.tx_cur as u32);
+            // Hand the descriptor to hardware
+            mmio.write_u32(E1000_TDT, self.tx_state.tail() as u32);
         }
         
         Ok(())
@@ -239,31 +504,46 @@ impl EthernetDriver {
         if !self.initialized { return Err(DriverError::NotInitialized); }
         
         let mmio = self.mmio.as_ref().unwrap();
-        let rx_ring = self.rx_desc_ring.as_mut().unwrap();
+        let rx_desc_ring = self.rx_desc_ring.as_mut().unwrap();
         let rx_bufs = self.rx_buffers.as_ref().unwrap();
         
         unsafe {
-            let rx_descs = rx_ring.as_mut_slice_of::<RxDesc>(RX_DESC_COUNT);
-            let cur = self.rx_cur;
+            let rx_descs = rx_desc_ring.as_mut_slice_of::<RxDesc>(RX_DESC_COUNT);
             
-            if (rx_descs[cur].status & 1) != 0 { // DD bit set
-                let len = rx_descs[cur].length as usize;
-                let copy_len = len.min(buffer.len());
+            loop {
+                let cur = self.rx_state.next_to_clean();
+                let desc = &mut rx_descs[cur] as *mut RxDesc;
                 
-                let buf_offset = cur * 2048;
-                let buf_slice = rx_bufs.as_slice();
-                buffer[0..copy_len].copy_from_slice(&buf_slice[buf_offset..buf_offset+copy_len]);
+                // Hardware writes the descriptor behind our back
+                let status = core::ptr::read_volatile(core::ptr::addr_of!((*desc).status));
+                let errors = core::ptr::read_volatile(core::ptr::addr_of!((*desc).errors));
+                let action = self.rx_state.classify(status, errors);
+                if action == RxAction::Empty {
+                    return Err(DriverError::WouldBlock);
+                }
                 
-                // Reset descriptor
-                rx_descs[cur].status = 0;
+                let mut copy_len = 0;
+                if action == RxAction::Deliver {
+                    let len = core::ptr::read_volatile(core::ptr::addr_of!((*desc).length)) as usize;
+                    copy_len = len.min(buffer.len());
+                    
+                    let buf_offset = cur * 2048;
+                    let buf_slice = rx_bufs.as_slice();
+                    buffer[0..copy_len].copy_from_slice(&buf_slice[buf_offset..buf_offset+copy_len]);
+                }
+                
+                // Reset the descriptor before the tail moves past it
+                core::ptr::write_volatile(core::ptr::addr_of_mut!((*desc).status), 0);
+                core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
                 
-                // Advance
-                mmio.write_u32(E1000_RDT, cur as u32); // Inform hardware we processed this
-                self.rx_cur = (cur + 1) % RX_DESC_COUNT;
+                // The cleaned descriptor is now held back and the previous
+                // one is handed to hardware
+                let tail = self.rx_state.advance();
+                mmio.write_u32(E1000_RDT, tail as u32);
                 
-                Ok(copy_len)
-            } else {
-                Err(DriverError::WouldBlock)
+                if action == RxAction::Deliver {
+                    return Ok(copy_len);
+                }
             }
         }
     }
@@ -311,6 +591,32 @@ impl EthernetDriver {
                 response.inline_data[0] = 0;
                 response.inline_size = 1;
             }
+            NET_DEV_OP_LINK_STATUS => {
+                // Up (u8), speed in Mbps (u16 LE), full duplex (u8)
+                let link = self.read_link_status();
+                response.inline_data[0] = link.up as u8;
+                response.inline_data[1..3].copy_from_slice(&link.speed_mbps.to_le_bytes());
+                response.inline_data[3] = link.full_duplex as u8;
+                response.inline_size = 4;
+            }
+            NET_DEV_OP_ADD_MULTICAST => {
+                let mut group = [0u8; 6];
+                group.copy_from_slice(&msg.inline_data[0..6]);
+                let ok = msg.inline_size >= 6 && self.add_multicast(&group).is_ok();
+                response.inline_data[0] = if ok { 0 } else { 1 };
+                response.inline_size = 1;
+            }
+            NET_DEV_OP_GET_STATS => {
+                let stats = self.update_stats();
+                response.inline_data[0..64].copy_from_slice(&stats.to_bytes());
+                response.inline_size = 64;
+            }
+            NET_DEV_OP_SET_PROMISCUOUS => {
+                self.promiscuous = msg.inline_size >= 1 && msg.inline_data[0] != 0;
+                self.program_receive_filters();
+                response.inline_data[0] = 0;
+                response.inline_size = 1;
+            }
             _ => {}
         }
         
@@ -334,9 +640,8 @@ impl Driver for EthernetDriver {
         device_info.vendor_id == 0x8086 && (device_info.device_id == 0x100E || device_info.device_id == 0x100F)
     }
     
-    fn start(&mut self) -> Result<(), DriverError> {
-        // Initialized in init_nic
-        Ok(())
+    fn start(&mut self, device_info: &DeviceInfo) -> Result<(), DriverError> {
+        self.init_nic(device_info)
     }
     
     fn stop(&mut self) -> Result<(), DriverError> {
@@ -353,12 +658,16 @@ static mut DRIVER: EthernetDriver = EthernetDriver {
     mmio: None,
     mac_address: [0; 6],
     irq: 0,
+    link: LINK_DOWN,
+    promiscuous: false,
+    mta: [0; MTA_REGISTER_COUNT],
+    stats: NetDevStats::new(),
     rx_desc_ring: None,
     tx_desc_ring: None,
     rx_buffers: None,
     tx_buffers: None,
-    rx_cur: 0,
-    tx_cur: 0,
+    rx_state: RxRing::new(RX_DESC_COUNT),
+    tx_state: TxRing::new(TX_DESC_COUNT),
 };
 
 #[no_mangle]
@@ -370,19 +679,24 @@ pub extern "C" fn _start() -> ! {
             device_id: 0x100E,
             class_code: 2,
             subclass: 0,
-            prog_if: 0,
-            revision: 0,
+            interface: 0,
+            bus: 0,
+            device: 0,
+            function: 0,
             bars: [0; 6], // This would need real values in a real run
             irq_line: 11,
+            irq_pin: 1,
             device_type: DeviceType::Pci,
         };
         
         if DRIVER.probe(&dev_info) {
             let _ = DRIVER.init();
-            // DRIVER.init_nic(&dev_info); // Needs real BARs
+            let _ = DRIVER.start(&dev_info); // Fails until real BARs are passed in
         }
         
         loop {
+            DRIVER.check_link();
+            DRIVER.handle_tx_done();
             DRIVER.handle_ipc();
         }
     }
@@ -8,6 +8,61 @@ pub const NET_DEV_OP_SEND: u64 = 1;
 pub const NET_DEV_OP_RECEIVE: u64 = 2;
 pub const NET_DEV_OP_GET_MAC: u64 = 3;
 pub const NET_DEV_OP_SET_IP: u64 = 4;
+pub const NET_DEV_OP_LINK_STATUS: u64 = 5;
+pub const NET_DEV_OP_ADD_MULTICAST: u64 = 6;    // Group MAC in inline_data[0..6]
+pub const NET_DEV_OP_SET_PROMISCUOUS: u64 = 7;  // inline_data[0] != 0 enables
+pub const NET_DEV_OP_GET_STATS: u64 = 8;        // Returns `NetDevStats` as 8 x u64 LE
+
+/// Notification sent to the network service when the link goes up or down.
+/// Payload: driver port (u64 LE), link up (u8), speed in Mbps (u16 LE), full duplex (u8)
+pub const NET_DEV_NOTIFY_LINK_CHANGE: u64 = 101;
+
+/// Cumulative device statistics, in the order they are sent over IPC
+#[derive(Clone, Copy)]
+pub struct NetDevStats {
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_dropped: u64,     // Missed: RX FIFO overflowed
+    pub rx_no_buffer: u64,   // No free RX descriptor when a frame arrived
+    pub rx_crc_errors: u64,
+    pub rx_errors: u64,      // Symbol, sequence, alignment and length errors
+}
+
+impl NetDevStats {
+    pub const fn new() -> Self {
+        Self {
+            rx_packets: 0,
+            tx_packets: 0,
+            rx_bytes: 0,
+            tx_bytes: 0,
+            rx_dropped: 0,
+            rx_no_buffer: 0,
+            rx_crc_errors: 0,
+            rx_errors: 0,
+        }
+    }
+
+    /// Serialize as 8 little-endian u64 values (64 bytes)
+    pub fn to_bytes(&self) -> [u8; 64] {
+        let fields = [
+            self.rx_packets,
+            self.tx_packets,
+            self.rx_bytes,
+            self.tx_bytes,
+            self.rx_dropped,
+            self.rx_no_buffer,
+            self.rx_crc_errors,
+            self.rx_errors,
+        ];
+        let mut bytes = [0u8; 64];
+        for (i, field) in fields.iter().enumerate() {
+            bytes[i * 8..i * 8 + 8].copy_from_slice(&field.to_le_bytes());
+        }
+        bytes
+    }
+}
 
 /// Ethernet frame header (14 bytes)
 #[repr(C, packed)]
@@ -0,0 +1,81 @@
+//! RX descriptor ring bookkeeping
+//!
+//! The E1000 owns the descriptors from RDH up to, but not including, RDT and
+//! stops receiving once RDH catches up with RDT. The driver therefore always
+//! holds exactly one descriptor back: the one at RDT. After cleaning the
+//! descriptor at `next_to_clean`, that descriptor becomes the held-back slot
+//! and the previously held one is returned to hardware by moving RDT onto the
+//! cleaned index. RDT thus always lags the next descriptor the driver will
+//! read by one, and never exposes a buffer the driver has not finished with.
+
+/// Status bit: descriptor done
+pub const RX_STATUS_DD: u8 = 1 << 0;
+/// Status bit: end of packet
+pub const RX_STATUS_EOP: u8 = 1 << 1;
+
+/// What the driver should do with a completed descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RxAction {
+    /// Hardware has not filled the next descriptor yet
+    Empty,
+    /// Complete, error-free frame in a single buffer
+    Deliver,
+    /// Fragment of an oversized frame or a frame with errors
+    Drop,
+}
+
+/// Software view of the RX descriptor ring
+pub struct RxRing {
+    size: usize,
+    next_to_clean: usize,
+    tail: usize,
+    discarding: bool,
+}
+
+impl RxRing {
+    pub const fn new(size: usize) -> Self {
+        Self {
+            size,
+            next_to_clean: 0,
+            tail: size - 1,
+            discarding: false,
+        }
+    }
+
+    /// Index of the next descriptor the driver will read
+    pub fn next_to_clean(&self) -> usize {
+        self.next_to_clean
+    }
+
+    /// Current RDT value; the descriptor at this index is held by the driver
+    pub fn tail(&self) -> usize {
+        self.tail
+    }
+
+    /// Classify the descriptor at `next_to_clean` from its status and errors
+    pub fn classify(&mut self, status: u8, errors: u8) -> RxAction {
+        if status & RX_STATUS_DD == 0 {
+            return RxAction::Empty;
+        }
+
+        let eop = status & RX_STATUS_EOP != 0;
+        let action = if self.discarding || !eop || errors != 0 {
+            RxAction::Drop
+        } else {
+            RxAction::Deliver
+        };
+
+        // A multi-descriptor frame is discarded up to and including its EOP
+        self.discarding = !eop;
+        action
+    }
+
+    /// Finish with the descriptor at `next_to_clean` and return the new RDT.
+    /// The caller must reset the descriptor before writing RDT.
+    pub fn advance(&mut self) -> usize {
+        let cleaned = self.next_to_clean;
+        self.next_to_clean = (cleaned + 1) % self.size;
+        self.tail = cleaned;
+        self.tail
+    }
+}
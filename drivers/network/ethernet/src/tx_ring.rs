@@ -0,0 +1,73 @@
+//! TX descriptor ring bookkeeping
+//!
+//! The driver fills descriptors at `next_to_use` and hands them to the E1000
+//! by moving TDT past them. The hardware sets the DD status bit once it has
+//! sent a descriptor (they all carry RS), after which its buffer may be
+//! reused. Completed descriptors are reclaimed in order from
+//! `next_to_clean`. One descriptor is always left unused, since TDT == TDH
+//! means an empty ring to the hardware, never a full one.
+
+/// Status bit: descriptor done
+pub const TX_STATUS_DD: u8 = 1 << 0;
+
+/// Software view of the TX descriptor ring
+pub struct TxRing {
+    size: usize,
+    next_to_use: usize,
+    next_to_clean: usize,
+    in_flight: usize,
+}
+
+impl TxRing {
+    pub const fn new(size: usize) -> Self {
+        Self {
+            size,
+            next_to_use: 0,
+            next_to_clean: 0,
+            in_flight: 0,
+        }
+    }
+
+    /// Index of the oldest descriptor the hardware may still be sending
+    pub fn next_to_clean(&self) -> usize {
+        self.next_to_clean
+    }
+
+    /// Current TDT value
+    pub fn tail(&self) -> usize {
+        self.next_to_use
+    }
+
+    /// Descriptors handed to hardware and not yet reclaimed
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// Whether every usable descriptor is waiting on the hardware
+    pub fn is_full(&self) -> bool {
+        self.in_flight == self.size - 1
+    }
+
+    /// Reclaim the descriptor at `next_to_clean` if `status` shows the
+    /// hardware is done with it. Returns whether it was reclaimed.
+    pub fn reclaim(&mut self, status: u8) -> bool {
+        if self.in_flight == 0 || status & TX_STATUS_DD == 0 {
+            return false;
+        }
+        self.next_to_clean = (self.next_to_clean + 1) % self.size;
+        self.in_flight -= 1;
+        true
+    }
+
+    /// Take the descriptor at `next_to_use` for a new frame, or `None` if
+    /// the ring is full. The caller fills it and then writes `tail()` to TDT.
+    pub fn claim(&mut self) -> Option<usize> {
+        if self.is_full() {
+            return None;
+        }
+        let index = self.next_to_use;
+        self.next_to_use = (index + 1) % self.size;
+        self.in_flight += 1;
+        Some(index)
+    }
+}
@@ -0,0 +1,99 @@
+//! iwlwifi firmware file parsing
+//!
+//! A `.ucode` file is an 88-byte header followed by type-length-value
+//! records, each padded to 4 bytes. Runtime code comes as a run of
+//! `SEC_RT` records: the LMAC sections, a separator section, then the
+//! UMAC sections. Each section starts with its load address on the device.
+
+pub const IWL_TLV_UCODE_MAGIC: u32 = 0x0a4c_5749;
+
+/// zero, magic, human-readable version, ver, build, ignore
+const HEADER_LEN: usize = 4 + 4 + 64 + 4 + 4 + 8;
+const TLV_HEADER_LEN: usize = 8;
+
+const IWL_UCODE_TLV_SEC_RT: u32 = 19;
+
+/// Load address marking the end of the LMAC sections
+const CPU1_CPU2_SEPARATOR_SECTION: u32 = 0xFFFF_CCCC;
+/// Load address marking the start of paged sections, not loaded at boot
+const PAGING_SEPARATOR_SECTION: u32 = 0xAAAA_BBBB;
+
+pub const MAX_FW_SECTIONS: usize = 16;
+
+/// One block of code or data to place on the device
+#[derive(Clone, Copy)]
+pub struct FwSection<'a> {
+    pub load_addr: u32,
+    pub data: &'a [u8],
+}
+
+/// Runtime image split by CPU
+pub struct RuntimeImage<'a> {
+    pub lmac: [Option<FwSection<'a>>; MAX_FW_SECTIONS],
+    pub lmac_count: usize,
+    pub umac: [Option<FwSection<'a>>; MAX_FW_SECTIONS],
+    pub umac_count: usize,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+/// Collect the runtime sections of a firmware file
+pub fn parse_runtime_image(file: &[u8]) -> Result<RuntimeImage<'_>, ()> {
+    if file.len() < HEADER_LEN || read_u32(file, 0) != 0 || read_u32(file, 4) != IWL_TLV_UCODE_MAGIC {
+        return Err(());
+    }
+
+    let mut image = RuntimeImage {
+        lmac: [None; MAX_FW_SECTIONS],
+        lmac_count: 0,
+        umac: [None; MAX_FW_SECTIONS],
+        umac_count: 0,
+    };
+    let mut in_umac = false;
+    let mut paged = false;
+
+    let mut offset = HEADER_LEN;
+    while offset + TLV_HEADER_LEN <= file.len() {
+        let tlv_type = read_u32(file, offset);
+        let len = read_u32(file, offset + 4) as usize;
+        let start = offset + TLV_HEADER_LEN;
+        if len > file.len() - start {
+            return Err(());
+        }
+        let body = &file[start..start + len];
+        offset = start + ((len + 3) & !3);
+
+        if tlv_type != IWL_UCODE_TLV_SEC_RT || paged {
+            continue;
+        }
+        if body.len() < 4 {
+            return Err(());
+        }
+        let section = FwSection { load_addr: read_u32(body, 0), data: &body[4..] };
+        match section.load_addr {
+            CPU1_CPU2_SEPARATOR_SECTION => in_umac = true,
+            PAGING_SEPARATOR_SECTION => paged = true,
+            _ if in_umac => {
+                if image.umac_count == MAX_FW_SECTIONS {
+                    return Err(());
+                }
+                image.umac[image.umac_count] = Some(section);
+                image.umac_count += 1;
+            }
+            _ => {
+                if image.lmac_count == MAX_FW_SECTIONS {
+                    return Err(());
+                }
+                image.lmac[image.lmac_count] = Some(section);
+                image.lmac_count += 1;
+            }
+        }
+    }
+
+    if image.lmac_count == 0 {
+        return Err(());
+    }
+    Ok(image)
+}
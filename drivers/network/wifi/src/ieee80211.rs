@@ -0,0 +1,145 @@
+//! 802.11 beacon and probe response parsing for scan results
+
+/// Frame control: management frames
+const FC_TYPE_MGMT: u8 = 0;
+const FC_SUBTYPE_PROBE_RESP: u8 = 5;
+const FC_SUBTYPE_BEACON: u8 = 8;
+
+/// Frame control, duration, three addresses, sequence control
+const MGMT_HEADER_LEN: usize = 24;
+/// Offset of address 3, the BSSID in frames from an AP
+const BSSID_OFFSET: usize = 16;
+/// Timestamp, beacon interval and capability ahead of the elements
+const BEACON_FIXED_LEN: usize = 12;
+
+const IE_SSID: u8 = 0;
+const IE_DS_PARAMS: u8 = 3;
+const IE_HT_OPERATION: u8 = 61;
+
+pub const MAX_SSID_LEN: usize = 32;
+pub const MAX_SCAN_RESULTS: usize = 32;
+
+/// One access point seen during a scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessPoint {
+    pub ssid: [u8; MAX_SSID_LEN],
+    pub ssid_len: u8,
+    pub bssid: [u8; 6],
+    pub channel: u8,
+    /// Signal strength in dBm
+    pub rssi: i8,
+}
+
+impl AccessPoint {
+    pub fn ssid(&self) -> &[u8] {
+        &self.ssid[..self.ssid_len as usize]
+    }
+}
+
+/// Parse a beacon or probe response. The channel comes from the DS
+/// parameter set or HT operation element, falling back to `rx_channel`
+/// (the channel the radio was on) when the AP advertises neither.
+pub fn parse_beacon(frame: &[u8], rx_channel: u8, rssi: i8) -> Option<AccessPoint> {
+    if frame.len() < MGMT_HEADER_LEN + BEACON_FIXED_LEN {
+        return None;
+    }
+    let fc = frame[0];
+    let version = fc & 0x3;
+    let frame_type = (fc >> 2) & 0x3;
+    let subtype = fc >> 4;
+    if version != 0
+        || frame_type != FC_TYPE_MGMT
+        || (subtype != FC_SUBTYPE_BEACON && subtype != FC_SUBTYPE_PROBE_RESP)
+    {
+        return None;
+    }
+
+    let mut ap = AccessPoint {
+        ssid: [0; MAX_SSID_LEN],
+        ssid_len: 0,
+        bssid: [0; 6],
+        channel: rx_channel,
+        rssi,
+    };
+    ap.bssid.copy_from_slice(&frame[BSSID_OFFSET..BSSID_OFFSET + 6]);
+
+    let mut ds_channel = None;
+    let mut ht_channel = None;
+    let mut elements = &frame[MGMT_HEADER_LEN + BEACON_FIXED_LEN..];
+    while elements.len() >= 2 {
+        let id = elements[0];
+        let len = elements[1] as usize;
+        if elements.len() < 2 + len {
+            // Truncated element: keep what was parsed so far
+            break;
+        }
+        let body = &elements[2..2 + len];
+        match id {
+            IE_SSID if len <= MAX_SSID_LEN => {
+                ap.ssid[..len].copy_from_slice(body);
+                ap.ssid_len = len as u8;
+            }
+            IE_DS_PARAMS if len >= 1 => ds_channel = Some(body[0]),
+            IE_HT_OPERATION if len >= 1 => ht_channel = Some(body[0]),
+            _ => {}
+        }
+        elements = &elements[2 + len..];
+    }
+
+    if let Some(channel) = ds_channel.or(ht_channel) {
+        ap.channel = channel;
+    }
+    Some(ap)
+}
+
+/// Access points found by a scan, one entry per BSSID
+pub struct ScanList {
+    entries: [Option<AccessPoint>; MAX_SCAN_RESULTS],
+}
+
+impl ScanList {
+    pub const fn new() -> Self {
+        ScanList { entries: [None; MAX_SCAN_RESULTS] }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries = [None; MAX_SCAN_RESULTS];
+    }
+
+    /// Record a sighting. A BSSID seen again keeps its strongest signal;
+    /// when the list is full the weakest entry makes way for a stronger AP.
+    pub fn insert(&mut self, ap: AccessPoint) {
+        if let Some(existing) = self.entries.iter_mut().flatten().find(|e| e.bssid == ap.bssid) {
+            let rssi = existing.rssi.max(ap.rssi);
+            // Hidden networks beacon an empty SSID; a probe response may name them
+            if ap.ssid_len > 0 || existing.ssid_len == 0 {
+                *existing = ap;
+            }
+            existing.rssi = rssi;
+            return;
+        }
+
+        if let Some(slot) = self.entries.iter_mut().find(|e| e.is_none()) {
+            *slot = Some(ap);
+            return;
+        }
+
+        if let Some(weakest) = self.entries.iter_mut().flatten().min_by_key(|e| e.rssi) {
+            if weakest.rssi < ap.rssi {
+                *weakest = ap;
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&AccessPoint> {
+        self.entries.iter().flatten().nth(index)
+    }
+}
@@ -0,0 +1,36 @@
+//! Intel wireless (22000 family, e.g. AX200) register map
+//!
+//! CSR registers are at the start of BAR0. Periphery (PRPH) registers are
+//! reached indirectly through the HBUS target window and need MAC access.
+
+pub const CSR_INT: usize = 0x008;
+pub const CSR_INT_MASK: usize = 0x00C;
+pub const CSR_FH_INT_STATUS: usize = 0x010;
+pub const CSR_RESET: usize = 0x020;
+pub const CSR_GP_CNTRL: usize = 0x024;
+pub const CSR_HW_REV: usize = 0x028;
+
+/// Physical address of the context info block (64-bit, low dword first)
+pub const CSR_CTXT_INFO_BA: usize = 0x040;
+
+pub const CSR_RESET_REG_FLAG_SW_RESET: u32 = 1 << 7;
+
+pub const CSR_GP_CNTRL_MAC_CLOCK_READY: u32 = 1 << 0;
+pub const CSR_GP_CNTRL_INIT_DONE: u32 = 1 << 2;
+pub const CSR_GP_CNTRL_MAC_ACCESS_REQ: u32 = 1 << 3;
+pub const CSR_GP_CNTRL_GOING_TO_SLEEP: u32 = 1 << 4;
+
+/// Indirect periphery writes: address (with byte-enable bits), then data
+pub const HBUS_TARG_PRPH_WADDR: usize = 0x444;
+pub const HBUS_TARG_PRPH_WDAT: usize = 0x44C;
+pub const PRPH_ADDR_MASK: u32 = 0x000F_FFFF;
+pub const PRPH_BYTE_ENABLE_ALL: u32 = 3 << 24;
+
+/// TX queue write pointer: index | queue << 16
+pub const HBUS_TARG_WRPTR: usize = 0x460;
+
+/// Free RB write index of RX queue 0
+pub const RFH_Q0_FRBDCB_WIDX_TRG: usize = 0x1C80;
+
+/// Periphery register that releases the CPUs once context info is set
+pub const UREG_CPU_INIT_RUN: u32 = 0xA05C44;
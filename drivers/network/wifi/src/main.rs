@@ -1,131 +1,563 @@
+//! User-Space WiFi Driver (Intel AX200)
+//!
+//! Brings the adapter up far enough to scan: maps BAR0, boots the runtime
+//! firmware through a context info block, and runs passive UMAC scans.
+//! Results are served over IPC as (SSID, BSSID, channel, RSSI) entries.
+//! Association and data transfer are not implemented; those ops answer
+//! WIFI_STATUS_NOT_SUPPORTED.
+
 #![no_std]
 #![no_main]
 
-extern crate alloc;
+mod firmware;
+mod ieee80211;
+mod iwl_regs;
+mod rx;
+mod scan_cmd;
+mod service;
 
 use core::panic::PanicInfo;
+use core::sync::atomic::{fence, Ordering};
+use driver_framework::{Driver, DriverError, DriverResult, DeviceInfo, DeviceType};
+use driver_framework::dma::DmaBuffer;
+use driver_framework::ipc::{ipc_create_port, ipc_receive, ipc_reply, IpcMessage, IPC_MSG_RESPONSE};
+use driver_framework::mmio::MmioRegion;
+use driver_framework::pci;
+use driver_framework::syscalls::get_uptime_ms;
+use firmware::MAX_FW_SECTIONS;
+use ieee80211::{ScanList, MAX_SSID_LEN};
+use iwl_regs::*;
+
+// Wireless adapters report as "other" network controllers
+const PCI_CLASS_NETWORK: u8 = 0x02;
+const PCI_SUBCLASS_NETWORK_OTHER: u8 = 0x80;
+
+const INTEL_VENDOR_ID: u16 = 0x8086;
+const AX200_DEVICE_ID: u16 = 0x2723;
+
+const FIRMWARE_PATH: &[u8] = b"/lib/firmware/iwlwifi-cc-a0-77.ucode";
+/// Largest firmware file accepted
+const FIRMWARE_MAX_SIZE: usize = 2 * 1024 * 1024;
+
+// IPC operations
+/// [] -> [status][count]
+pub const WIFI_OP_SCAN: u64 = 1;
+/// [index] -> [status][bssid:6][channel][rssi:i8][ssid_len][ssid:32]
+pub const WIFI_OP_SCAN_RESULT: u64 = 2;
+pub const WIFI_OP_CONNECT: u64 = 3;
+pub const WIFI_OP_DISCONNECT: u64 = 4;
+
+pub const WIFI_STATUS_OK: u8 = 0;
+pub const WIFI_STATUS_ERROR: u8 = 1;
+pub const WIFI_STATUS_NOT_SUPPORTED: u8 = 2;
+/// No adapter, or its firmware did not come up
+pub const WIFI_STATUS_NO_DEVICE: u8 = 3;
+
+const SCAN_RESULT_SIZE: usize = 10 + MAX_SSID_LEN;
+
+// Host command queue (queue 0)
+const CMD_QUEUE_ID: u32 = 0;
+const CMD_QUEUE_SIZE: usize = 32;
+/// Gen2 TFD: num_tbs, 25 transfer buffers of (len:2, addr:8), padding
+const TFD_SIZE: usize = 256;
+const CMD_SLOT_SIZE: usize = 4096;
+/// Wide header: cmd, group, sequence, length, reserved, version
+const CMD_HEADER_LEN: usize = 8;
 
-// Syscall numbers
-const SYS_WRITE: u64 = 1;
-const SYS_IPC_SEND: u64 = 20;
-const SYS_IPC_RECEIVE: u64 = 21;
+const LONG_GROUP: u8 = 0x1;
+const SCAN_REQ_UMAC: u8 = 0x0D;
+
+// RX queue 0: free RBDs are (address | id), used RBDs hold the id
+const RX_QUEUE_SIZE: usize = 64;
+const RX_BUFFER_SIZE: usize = 4096;
+/// Buffers handed over at start; the write index moves in steps of 8
+const RX_POSTED: usize = RX_QUEUE_SIZE - 8;
+const RB_STATUS_SIZE: usize = 16;
+const RB_ID_MASK: u32 = 0x0FFF;
+
+// Context info block
+const CTXT_INFO_SIZE: usize = 1824;
+const CTXT_INFO_CONTROL_FLAGS: usize = 0x08;
+const CTXT_INFO_RBD_CFG: usize = 0x18;
+const CTXT_INFO_HCMD_CFG: usize = 0x30;
+const CTXT_INFO_UMAC_IMG: usize = 0x110;
+const CTXT_INFO_LMAC_IMG: usize = 0x310;
+const CTXT_INFO_TFD_FORMAT_LONG: u32 = 1 << 8;
+const CTXT_INFO_RB_CB_SIZE_POS: u32 = 4;
+const CTXT_INFO_RB_SIZE_POS: u32 = 12;
+const CTXT_INFO_RB_SIZE_4K: u32 = 0x4;
+
+const MAC_CLOCK_TIMEOUT_MS: u64 = 25;
+const ALIVE_TIMEOUT_MS: u64 = 1000;
+const SCAN_TIMEOUT_MS: u64 = 5000;
+
+/// A zeroed DMA buffer and its bus address
+struct Dma {
+    buf: DmaBuffer,
+    phys: u64,
+}
+
+impl Dma {
+    fn alloc(size: usize) -> DriverResult<Self> {
+        let mut buf = DmaBuffer::alloc(size, 0).map_err(|_| DriverError::OutOfMemory)?;
+        let phys = buf.get_physical().map_err(|_| DriverError::OutOfMemory)?;
+        unsafe { buf.as_mut_slice().fill(0) };
+        Ok(Dma { buf, phys })
+    }
 
-// PCI Driver Port
-const PCI_DRIVER_PORT: u32 = 101;
+    fn bytes(&mut self) -> &mut [u8] {
+        unsafe { self.buf.as_mut_slice() }
+    }
 
-// PCI Messages
-const MSG_PCI_FIND_DEVICE: u32 = 13;
+    /// Read a field the device writes
+    fn read_u32(&self, offset: usize) -> u32 {
+        unsafe { u32::from_le(core::ptr::read_volatile(self.buf.as_ptr().add(offset) as *const u32)) }
+    }
+}
 
-#[repr(C)]
-struct IpcMessage {
-    sender_tid: u64,
-    msg_type: u32,
-    msg_id: u32,
-    inline_size: u32,
-    inline_data: [u8; 64],
+/// Everything the device reads or writes in host memory
+struct Rings {
+    ctxt_info: Dma,
+    /// LMAC sections, then UMAC sections from MAX_FW_SECTIONS on
+    fw_sections: [Option<Dma>; 2 * MAX_FW_SECTIONS],
+    cmd_tfds: Dma,
+    cmd_buffers: Dma,
+    cmd_write: usize,
+    rx_free: Dma,
+    rx_used: Dma,
+    rx_status: Dma,
+    rx_buffers: Dma,
+    rx_read: usize,
+    rx_write: usize,
 }
 
-impl IpcMessage {
+impl Rings {
+    fn alloc() -> DriverResult<Self> {
+        Ok(Rings {
+            ctxt_info: Dma::alloc(CTXT_INFO_SIZE)?,
+            fw_sections: core::array::from_fn(|_| None),
+            cmd_tfds: Dma::alloc(CMD_QUEUE_SIZE * TFD_SIZE)?,
+            cmd_buffers: Dma::alloc(CMD_QUEUE_SIZE * CMD_SLOT_SIZE)?,
+            cmd_write: 0,
+            rx_free: Dma::alloc(RX_QUEUE_SIZE * 8)?,
+            rx_used: Dma::alloc(RX_QUEUE_SIZE * 4)?,
+            rx_status: Dma::alloc(RB_STATUS_SIZE)?,
+            rx_buffers: Dma::alloc(RX_QUEUE_SIZE * RX_BUFFER_SIZE)?,
+            rx_read: 0,
+            rx_write: 0,
+        })
+    }
+
+    /// Copy the runtime firmware sections into DMA memory
+    fn load_firmware(&mut self, file: &[u8]) -> DriverResult<()> {
+        let image = firmware::parse_runtime_image(file).map_err(|_| DriverError::InvalidArgument)?;
+        let sections = image.lmac.iter().enumerate()
+            .chain(image.umac.iter().enumerate().map(|(i, s)| (MAX_FW_SECTIONS + i, s)));
+        for (slot, section) in sections {
+            if let Some(section) = section {
+                let mut dma = Dma::alloc(section.data.len())?;
+                dma.bytes()[..section.data.len()].copy_from_slice(section.data);
+                self.fw_sections[slot] = Some(dma);
+            }
+        }
+        Ok(())
+    }
+
+    fn write_context_info(&mut self, hw_rev: u32) {
+        let (free, used, status) = (self.rx_free.phys, self.rx_used.phys, self.rx_status.phys);
+        let cmd_queue = self.cmd_tfds.phys;
+        let info = self.ctxt_info.bytes();
+
+        put_u16(info, 0, hw_rev as u16);
+        put_u16(info, 4, (CTXT_INFO_SIZE / 4) as u16);
+        put_u32(
+            info,
+            CTXT_INFO_CONTROL_FLAGS,
+            CTXT_INFO_TFD_FORMAT_LONG
+                | (RX_QUEUE_SIZE.ilog2() << CTXT_INFO_RB_CB_SIZE_POS)
+                | (CTXT_INFO_RB_SIZE_4K << CTXT_INFO_RB_SIZE_POS),
+        );
+        put_u64(info, CTXT_INFO_RBD_CFG, free);
+        put_u64(info, CTXT_INFO_RBD_CFG + 8, used);
+        put_u64(info, CTXT_INFO_RBD_CFG + 16, status);
+        put_u64(info, CTXT_INFO_HCMD_CFG, cmd_queue);
+        info[CTXT_INFO_HCMD_CFG + 8] = (CMD_QUEUE_SIZE.ilog2() - 3) as u8;
+
+        for (i, section) in self.fw_sections.iter().enumerate() {
+            if let Some(section) = section {
+                let offset = match i.checked_sub(MAX_FW_SECTIONS) {
+                    Some(umac) => CTXT_INFO_UMAC_IMG + umac * 8,
+                    None => CTXT_INFO_LMAC_IMG + i * 8,
+                };
+                put_u64(info, offset, section.phys);
+            }
+        }
+    }
+
+    /// Queue a host command and ring the doorbell
+    fn send_command(&mut self, mmio: &MmioRegion, cmd: u8, group: u8, payload: &[u8]) -> DriverResult<()> {
+        let len = CMD_HEADER_LEN + payload.len();
+        if len > CMD_SLOT_SIZE {
+            return Err(DriverError::InvalidArgument);
+        }
+        let slot = self.cmd_write;
+        let start = slot * CMD_SLOT_SIZE;
+
+        let buf = &mut self.cmd_buffers.bytes()[start..start + len];
+        buf[0] = cmd;
+        buf[1] = group;
+        put_u16(buf, 2, ((CMD_QUEUE_ID << 8) | slot as u32) as u16);
+        put_u16(buf, 4, payload.len() as u16);
+        buf[6] = 0;
+        buf[7] = 0;
+        buf[CMD_HEADER_LEN..].copy_from_slice(payload);
+
+        let buf_phys = self.cmd_buffers.phys + start as u64;
+        let tfd = &mut self.cmd_tfds.bytes()[slot * TFD_SIZE..(slot + 1) * TFD_SIZE];
+        tfd.fill(0);
+        put_u16(tfd, 0, 1);
+        put_u16(tfd, 2, len as u16);
+        put_u64(tfd, 4, buf_phys);
+
+        self.cmd_write = (slot + 1) % CMD_QUEUE_SIZE;
+        fence(Ordering::SeqCst);
+        mmio.write_u32(HBUS_TARG_WRPTR, self.cmd_write as u32 | (CMD_QUEUE_ID << 16));
+        Ok(())
+    }
+
+    /// Put receive buffer `index` back on the free list
+    fn post_rx_buffer(&mut self, index: usize) {
+        let entry = (self.rx_buffers.phys + (index * RX_BUFFER_SIZE) as u64) | (index as u64 + 1);
+        let write = self.rx_write;
+        put_u64(self.rx_free.bytes(), write * 8, entry);
+        self.rx_write = (write + 1) % RX_QUEUE_SIZE;
+    }
+
+    fn publish_rx(&self, mmio: &MmioRegion) {
+        fence(Ordering::SeqCst);
+        mmio.write_u32(RFH_Q0_FRBDCB_WIDX_TRG, (self.rx_write & !7) as u32);
+    }
+
+    /// Hand each packet the firmware has closed to `handle`, then recycle
+    /// its buffer
+    fn poll_rx<F: FnMut(&rx::RxPacket)>(&mut self, mmio: &MmioRegion, mut handle: F) {
+        let closed = (self.rx_status.read_u32(0) & RB_ID_MASK) as usize % RX_QUEUE_SIZE;
+        let mut recycled = false;
+
+        while self.rx_read != closed {
+            let id = (self.rx_used.read_u32(self.rx_read * 4) & RB_ID_MASK) as usize;
+            if (1..=RX_QUEUE_SIZE).contains(&id) {
+                let start = (id - 1) * RX_BUFFER_SIZE;
+                if let Some(packet) = rx::parse_packet(&self.rx_buffers.bytes()[start..start + RX_BUFFER_SIZE]) {
+                    handle(&packet);
+                }
+                self.post_rx_buffer(id - 1);
+                recycled = true;
+            }
+            self.rx_read = (self.rx_read + 1) % RX_QUEUE_SIZE;
+        }
+
+        if recycled {
+            self.publish_rx(mmio);
+        }
+    }
+}
+
+struct WifiDriver {
+    device_port: u64,
+    mmio: Option<MmioRegion>,
+    rings: Option<Rings>,
+    /// Set once the runtime firmware reported ALIVE
+    alive: bool,
+    scan_uid: u32,
+    scan_results: ScanList,
+}
+
+impl WifiDriver {
     fn new() -> Self {
-        IpcMessage {
-            sender_tid: 0,
-            msg_type: 0,
-            msg_id: 0,
-            inline_size: 0,
-            inline_data: [0; 64],
+        Self {
+            device_port: 0,
+            mmio: None,
+            rings: None,
+            alive: false,
+            scan_uid: 0,
+            scan_results: ScanList::new(),
+        }
+    }
+
+    /// Software reset, then wait for the MAC clock
+    fn reset_nic(mmio: &MmioRegion) -> DriverResult<()> {
+        mmio.write_u32(CSR_RESET, CSR_RESET_REG_FLAG_SW_RESET);
+        delay_ms(5);
+
+        mmio.write_u32(CSR_GP_CNTRL, mmio.read_u32(CSR_GP_CNTRL) | CSR_GP_CNTRL_INIT_DONE);
+        wait_until(MAC_CLOCK_TIMEOUT_MS, || mmio.read_u32(CSR_GP_CNTRL) & CSR_GP_CNTRL_MAC_CLOCK_READY != 0)
+    }
+
+    /// Point the device at the context info and release its CPUs
+    fn boot_firmware(mmio: &MmioRegion, rings: &mut Rings) -> DriverResult<()> {
+        rings.write_context_info(mmio.read_u32(CSR_HW_REV));
+        for i in 0..RX_POSTED {
+            rings.post_rx_buffer(i);
+        }
+        rings.publish_rx(mmio);
+
+        let ctxt_info = rings.ctxt_info.phys;
+        mmio.write_u32(CSR_CTXT_INFO_BA, ctxt_info as u32);
+        mmio.write_u32(CSR_CTXT_INFO_BA + 4, (ctxt_info >> 32) as u32);
+        write_prph(mmio, UREG_CPU_INIT_RUN, 1)?;
+
+        let mut alive = None;
+        wait_until(ALIVE_TIMEOUT_MS, || {
+            rings.poll_rx(mmio, |packet| {
+                if packet.cmd == rx::UCODE_ALIVE_NTFY && packet.group == 0 {
+                    alive = Some(rx::alive_ok(packet.payload));
+                }
+            });
+            alive.is_some()
+        })?;
+
+        match alive {
+            Some(true) => Ok(()),
+            _ => Err(DriverError::IoError),
         }
     }
+
+    /// Run one passive scan over every channel and collect what it hears.
+    /// Returns the number of access points found.
+    fn scan(&mut self) -> DriverResult<usize> {
+        self.scan_uid = self.scan_uid.wrapping_add(1);
+        let mut cmd = [0u8; scan_cmd::SCAN_REQ_UMAC_SIZE];
+        scan_cmd::build_passive_scan(self.scan_uid, &mut cmd).ok_or(DriverError::InvalidArgument)?;
+
+        let (mmio, rings) = match (&self.mmio, &mut self.rings) {
+            (Some(mmio), Some(rings)) if self.alive => (mmio, rings),
+            _ => return Err(DriverError::NotInitialized),
+        };
+        let results = &mut self.scan_results;
+        results.clear();
+        rings.send_command(mmio, SCAN_REQ_UMAC, LONG_GROUP, &cmd)?;
+
+        let mut complete = false;
+        let finished = wait_until(SCAN_TIMEOUT_MS, || {
+            rings.poll_rx(mmio, |packet| match packet.cmd {
+                rx::REPLY_RX_MPDU_CMD => {
+                    let ap = rx::parse_mpdu(packet.payload)
+                        .and_then(|mpdu| ieee80211::parse_beacon(mpdu.frame, mpdu.channel, mpdu.rssi));
+                    if let Some(ap) = ap {
+                        results.insert(ap);
+                    }
+                }
+                rx::SCAN_COMPLETE_UMAC => complete = true,
+                _ => {}
+            });
+            complete
+        });
+
+        // A scan cut short still reports what it heard
+        if finished.is_err() && results.is_empty() {
+            return Err(DriverError::Timeout);
+        }
+        Ok(results.len())
+    }
+
+    fn handle_ipc(&mut self, msg: &IpcMessage) -> IpcMessage {
+        let mut response = IpcMessage::new();
+        response.msg_type = IPC_MSG_RESPONSE;
+        response.msg_id = msg.msg_id;
+
+        match msg.msg_id {
+            WIFI_OP_SCAN => match self.scan() {
+                Ok(count) => response.set_inline_data(&[WIFI_STATUS_OK, count as u8]),
+                Err(DriverError::NotInitialized) => response.set_inline_data(&[WIFI_STATUS_NO_DEVICE]),
+                Err(_) => response.set_inline_data(&[WIFI_STATUS_ERROR]),
+            },
+            WIFI_OP_SCAN_RESULT => {
+                let ap = match msg.inline_size {
+                    0 => None,
+                    _ => self.scan_results.get(msg.inline_data[0] as usize),
+                };
+                match ap {
+                    Some(ap) => {
+                        let mut data = [0u8; SCAN_RESULT_SIZE];
+                        data[0] = WIFI_STATUS_OK;
+                        data[1..7].copy_from_slice(&ap.bssid);
+                        data[7] = ap.channel;
+                        data[8] = ap.rssi as u8;
+                        data[9] = ap.ssid_len;
+                        data[10..10 + ap.ssid().len()].copy_from_slice(ap.ssid());
+                        response.set_inline_data(&data);
+                    }
+                    None => response.set_inline_data(&[WIFI_STATUS_ERROR]),
+                }
+            }
+            // Association (and with it data transfer) is not implemented
+            WIFI_OP_CONNECT | WIFI_OP_DISCONNECT => response.set_inline_data(&[WIFI_STATUS_NOT_SUPPORTED]),
+            _ => response.set_inline_data(&[WIFI_STATUS_ERROR]),
+        }
+        response
+    }
 }
 
-#[no_mangle]
-pub extern "C" fn _start() -> ! {
-    print("WiFi Driver Starting...\n");
-
-    // Find Intel WiFi Controller (AX200)
-    // Vendor: 0x8086 (Intel), Device: 0x2723 (AX200)
-    
-    let vendor_id: u16 = 0x8086;
-    let device_id: u16 = 0x2723;
-    
-    print("Searching for Intel AX200 WiFi Controller...\n");
-    
-    let mut msg = IpcMessage::new();
-    msg.msg_type = 1; // REQUEST
-    msg.msg_id = MSG_PCI_FIND_DEVICE;
-    msg.inline_data[0..2].copy_from_slice(&vendor_id.to_le_bytes());
-    msg.inline_data[2..4].copy_from_slice(&device_id.to_le_bytes());
-    msg.inline_size = 4;
-    
-    unsafe {
-        sys_ipc_send(PCI_DRIVER_PORT, &msg);
-        sys_ipc_receive(PCI_DRIVER_PORT, &mut msg);
-    }
-    
-    if msg.inline_data[0] != 0xFF {
-        let bus = msg.inline_data[0];
-        let dev = msg.inline_data[1];
-        let func = msg.inline_data[2];
-        print("WiFi Controller found!\n");
-        // Initialize controller (iwlwifi style)
-        print("Simulating iwlwifi initialization...\n");
-        print("- Resetting device...\n");
-        print("- Loading firmware (placeholder)...\n");
-        print("- Configuring device registers...\n");
-        print("- Initializing queues (TX/RX)...\n");
-        print("- Enabling interrupts...\n");
-        print("- WiFi controller initialized.\n");
-    } else {
-        print("WiFi Controller not found.\n");
+impl Driver for WifiDriver {
+    fn init(&mut self) -> Result<(), DriverError> {
+        self.device_port = ipc_create_port().map_err(|_| DriverError::IoError)?;
+        service::register_service_name(b"wifi", self.device_port).map_err(|_| DriverError::IoError)
     }
 
-    loop {}
+    fn probe(&self, device_info: &DeviceInfo) -> bool {
+        device_info.vendor_id == INTEL_VENDOR_ID && device_info.device_id == AX200_DEVICE_ID
+    }
+
+    fn start(&mut self, device_info: &DeviceInfo) -> Result<(), DriverError> {
+        if self.mmio.is_some() {
+            return Err(DriverError::AlreadyInitialized);
+        }
+
+        let (bus, device, function) = (device_info.bus, device_info.device, device_info.function);
+        let bar0 = pci::get_bar(bus, device, function, 0)?;
+        if !bar0.is_mmio || bar0.base == 0 {
+            return Err(DriverError::DeviceNotFound);
+        }
+        pci::enable_device(bus, device, function)?;
+        let mmio = MmioRegion::map(bar0.base, bar0.size as usize).map_err(|_| DriverError::IoError)?;
+
+        // Everything is polled
+        mmio.write_u32(CSR_INT_MASK, 0);
+        mmio.write_u32(CSR_INT, 0xFFFF_FFFF);
+        mmio.write_u32(CSR_FH_INT_STATUS, 0xFFFF_FFFF);
+        Self::reset_nic(&mmio)?;
+
+        let mut rings = Rings::alloc()?;
+        {
+            let mut file = DmaBuffer::alloc(FIRMWARE_MAX_SIZE, 0).map_err(|_| DriverError::OutOfMemory)?;
+            let file = unsafe { file.as_mut_slice() };
+            let len = service::read_file(FIRMWARE_PATH, file).map_err(|_| DriverError::NotSupported)?;
+            rings.load_firmware(&file[..len])?;
+        }
+        Self::boot_firmware(&mmio, &mut rings)?;
+
+        self.mmio = Some(mmio);
+        self.rings = Some(rings);
+        self.alive = true;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), DriverError> {
+        if let Some(mmio) = self.mmio.take() {
+            mmio.write_u32(CSR_INT_MASK, 0);
+            mmio.write_u32(CSR_RESET, CSR_RESET_REG_FLAG_SW_RESET);
+        }
+        self.rings = None;
+        self.alive = false;
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str { "wifi_iwlwifi" }
+    fn version(&self) -> &'static str { "0.1.0" }
 }
 
-unsafe fn sys_ipc_send(port: u32, msg: *const IpcMessage) -> u64 {
-    let ret: u64;
-    core::arch::asm!(
-        "syscall",
-        in("rdi") SYS_IPC_SEND,
-        in("rsi") port,
-        in("rdx") msg,
-        out("rax") ret,
-        lateout("rcx") _,
-        lateout("r11") _,
-    );
-    ret
-}
-
-unsafe fn sys_ipc_receive(port: u32, msg: *mut IpcMessage) -> u64 {
-    let ret: u64;
-    core::arch::asm!(
-        "syscall",
-        in("rdi") SYS_IPC_RECEIVE,
-        in("rsi") port,
-        in("rdx") msg,
-        out("rax") ret,
-        lateout("rcx") _,
-        lateout("r11") _,
-    );
-    ret
-}
-
-fn print(s: &str) {
-    unsafe {
-        core::arch::asm!(
-            "syscall",
-            in("rdi") SYS_WRITE,
-            in("rsi") 1, // stdout
-            in("rdx") s.as_ptr(),
-            in("r10") s.len(),
-            lateout("rax") _,
-            lateout("rcx") _,
-            lateout("r11") _,
-        );
+/// Write a periphery register through the HBUS window
+fn write_prph(mmio: &MmioRegion, addr: u32, value: u32) -> DriverResult<()> {
+    mmio.write_u32(CSR_GP_CNTRL, mmio.read_u32(CSR_GP_CNTRL) | CSR_GP_CNTRL_MAC_ACCESS_REQ);
+    let granted = wait_until(MAC_CLOCK_TIMEOUT_MS, || {
+        let gp = mmio.read_u32(CSR_GP_CNTRL);
+        gp & CSR_GP_CNTRL_MAC_CLOCK_READY != 0 && gp & CSR_GP_CNTRL_GOING_TO_SLEEP == 0
+    });
+    if granted.is_ok() {
+        mmio.write_u32(HBUS_TARG_PRPH_WADDR, (addr & PRPH_ADDR_MASK) | PRPH_BYTE_ENABLE_ALL);
+        mmio.write_u32(HBUS_TARG_PRPH_WDAT, value);
+    }
+    mmio.write_u32(CSR_GP_CNTRL, mmio.read_u32(CSR_GP_CNTRL) & !CSR_GP_CNTRL_MAC_ACCESS_REQ);
+    granted
+}
+
+fn put_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buf: &mut [u8], offset: usize, value: u64) {
+    buf[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+fn delay_ms(ms: u64) {
+    let deadline = get_uptime_ms() + ms;
+    while get_uptime_ms() < deadline {
+        core::hint::spin_loop();
+    }
+}
+
+/// Spin until `done` returns true, giving up after `timeout_ms`
+fn wait_until<F: FnMut() -> bool>(timeout_ms: u64, mut done: F) -> DriverResult<()> {
+    let deadline = get_uptime_ms() + timeout_ms;
+
+    loop {
+        if done() {
+            return Ok(());
+        }
+        if get_uptime_ms() >= deadline {
+            return Err(DriverError::Timeout);
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// First Intel wireless adapter among the "other" network controllers
+fn find_device() -> Option<DeviceInfo> {
+    for index in 0..=u8::MAX {
+        let (bus, device, function) =
+            pci::find_by_class(PCI_CLASS_NETWORK, PCI_SUBCLASS_NETWORK_OTHER, pci::PCI_CLASS_ANY, index).ok()?;
+        let id = pci::read_config(bus, device, function, 0x00).ok()?;
+        if id as u16 != INTEL_VENDOR_ID {
+            continue;
+        }
+        let class = pci::read_config(bus, device, function, 0x08).ok()?;
+        let irq = pci::read_config(bus, device, function, 0x3C).ok()?;
+        return Some(DeviceInfo {
+            device_type: DeviceType::Pci,
+            vendor_id: id as u16,
+            device_id: (id >> 16) as u16,
+            class_code: (class >> 24) as u8,
+            subclass: (class >> 16) as u8,
+            interface: (class >> 8) as u8,
+            bus,
+            device,
+            function,
+            bars: [0; 6],
+            irq_line: irq as u8,
+            irq_pin: (irq >> 8) as u8,
+        });
+    }
+    None
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let mut driver = WifiDriver::new();
+    let _ = driver.init();
+
+    if let Some(device_info) = find_device() {
+        if driver.probe(&device_info) && driver.start(&device_info).is_err() {
+            let _ = driver.stop();
+        }
+    }
+
+    // Serve requests even without a working adapter, so clients get an
+    // answer (WIFI_STATUS_NO_DEVICE) instead of a timeout
+    let mut msg = IpcMessage::new();
+    loop {
+        if ipc_receive(driver.device_port, &mut msg).is_ok() {
+            let response = driver.handle_ipc(&msg);
+            let _ = ipc_reply(&msg, &response);
+        }
     }
 }
 
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
-    print("WiFi Driver Panicked!\n");
     loop {}
 }
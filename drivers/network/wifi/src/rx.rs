@@ -0,0 +1,82 @@
+//! Parsing what the firmware writes into receive buffers
+//!
+//! Each buffer holds one packet: a length word, a command header, then the
+//! payload. Received 802.11 frames arrive as REPLY_RX_MPDU_CMD with an MPDU
+//! descriptor (the 22000-family "v1" layout) ahead of the frame.
+
+/// Firmware finished booting; payload starts with the status
+pub const UCODE_ALIVE_NTFY: u8 = 0x01;
+pub const IWL_ALIVE_STATUS_OK: u16 = 0xCAFE;
+/// Every UMAC scan has finished
+pub const SCAN_COMPLETE_UMAC: u8 = 0x0F;
+/// A received frame
+pub const REPLY_RX_MPDU_CMD: u8 = 0xC1;
+
+/// Low bits of the length word
+const FRAME_SIZE_MASK: u32 = 0x3FFF;
+/// Length word plus cmd, group and a two-byte sequence number
+const PACKET_HEADER_LEN: usize = 8;
+
+/// MPDU descriptor size, and where its fields sit
+const RX_MPDU_DESC_SIZE_V1: usize = 52;
+const MPDU_LEN_OFFSET: usize = 0;
+const ENERGY_A_OFFSET: usize = 32;
+const ENERGY_B_OFFSET: usize = 33;
+const CHANNEL_OFFSET: usize = 34;
+
+pub struct RxPacket<'a> {
+    pub cmd: u8,
+    pub group: u8,
+    pub payload: &'a [u8],
+}
+
+pub struct RxMpdu<'a> {
+    pub frame: &'a [u8],
+    pub channel: u8,
+    /// Signal strength in dBm
+    pub rssi: i8,
+}
+
+/// Split a receive buffer into its command header and payload
+pub fn parse_packet(buf: &[u8]) -> Option<RxPacket<'_>> {
+    if buf.len() < PACKET_HEADER_LEN {
+        return None;
+    }
+    // The length covers the command header and payload, not itself
+    let len = (u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) & FRAME_SIZE_MASK) as usize;
+    if len < PACKET_HEADER_LEN - 4 || 4 + len > buf.len() {
+        return None;
+    }
+    Some(RxPacket {
+        cmd: buf[4],
+        group: buf[5],
+        payload: &buf[PACKET_HEADER_LEN..4 + len],
+    })
+}
+
+/// Whether an ALIVE notification reports a good boot
+pub fn alive_ok(payload: &[u8]) -> bool {
+    payload.len() >= 2 && u16::from_le_bytes([payload[0], payload[1]]) == IWL_ALIVE_STATUS_OK
+}
+
+/// Pull the 802.11 frame and its signal out of a REPLY_RX_MPDU_CMD payload
+pub fn parse_mpdu(payload: &[u8]) -> Option<RxMpdu<'_>> {
+    if payload.len() < RX_MPDU_DESC_SIZE_V1 {
+        return None;
+    }
+    let mpdu_len = u16::from_le_bytes([payload[MPDU_LEN_OFFSET], payload[MPDU_LEN_OFFSET + 1]]) as usize;
+    let frame = payload.get(RX_MPDU_DESC_SIZE_V1..RX_MPDU_DESC_SIZE_V1 + mpdu_len)?;
+
+    // Energy is reported per antenna as a positive -dBm; 0 means no reading
+    let energy = [payload[ENERGY_A_OFFSET], payload[ENERGY_B_OFFSET]]
+        .iter()
+        .copied()
+        .filter(|&e| e != 0)
+        .min()?;
+
+    Some(RxMpdu {
+        frame,
+        channel: payload[CHANNEL_OFFSET],
+        rssi: -(energy.min(127) as i8),
+    })
+}
@@ -0,0 +1,86 @@
+//! UMAC scan request (SCAN_REQ_UMAC, API version 14)
+//!
+//! Only passive scans over the regular 2.4 GHz and 5 GHz channels are
+//! built: no probe requests, so the probe parameters stay zero. The
+//! firmware passes every beacon it hears up to the host.
+
+/// uid, ooc_priority
+const GENERAL_PARAMS_OFFSET: usize = 8;
+const GENERAL_PARAMS_LEN: usize = 36;
+const CHANNEL_PARAMS_OFFSET: usize = GENERAL_PARAMS_OFFSET + GENERAL_PARAMS_LEN;
+/// flags, count, n_aps_override[2]
+const CHANNEL_CONFIG_OFFSET: usize = CHANNEL_PARAMS_OFFSET + 4;
+const CHANNEL_CONFIG_LEN: usize = 8;
+const MAX_CHANNELS: usize = 67;
+const PERIODIC_PARAMS_OFFSET: usize = CHANNEL_CONFIG_OFFSET + MAX_CHANNELS * CHANNEL_CONFIG_LEN;
+const PERIODIC_PARAMS_LEN: usize = 12;
+const PROBE_PARAMS_LEN: usize = 1344;
+
+pub const SCAN_REQ_UMAC_SIZE: usize = PERIODIC_PARAMS_OFFSET + PERIODIC_PARAMS_LEN + PROBE_PARAMS_LEN;
+
+const GEN_FLAGS_V2_FORCE_PASSIVE: u16 = 1 << 1;
+const GEN_FLAGS_V2_PASS_ALL: u16 = 1 << 2;
+
+const SCAN_PRIORITY_EXT_6: u32 = 6;
+/// Time on each channel, in TU
+const ACTIVE_DWELL: u8 = 10;
+const PASSIVE_DWELL: u8 = 110;
+
+pub const PHY_BAND_5: u8 = 0;
+pub const PHY_BAND_24: u8 = 1;
+
+/// Channels scanned, as (band, channel)
+pub const SCAN_CHANNELS: [(u8, u8); 38] = [
+    (PHY_BAND_24, 1), (PHY_BAND_24, 2), (PHY_BAND_24, 3), (PHY_BAND_24, 4),
+    (PHY_BAND_24, 5), (PHY_BAND_24, 6), (PHY_BAND_24, 7), (PHY_BAND_24, 8),
+    (PHY_BAND_24, 9), (PHY_BAND_24, 10), (PHY_BAND_24, 11), (PHY_BAND_24, 12),
+    (PHY_BAND_24, 13),
+    (PHY_BAND_5, 36), (PHY_BAND_5, 40), (PHY_BAND_5, 44), (PHY_BAND_5, 48),
+    (PHY_BAND_5, 52), (PHY_BAND_5, 56), (PHY_BAND_5, 60), (PHY_BAND_5, 64),
+    (PHY_BAND_5, 100), (PHY_BAND_5, 104), (PHY_BAND_5, 108), (PHY_BAND_5, 112),
+    (PHY_BAND_5, 116), (PHY_BAND_5, 120), (PHY_BAND_5, 124), (PHY_BAND_5, 128),
+    (PHY_BAND_5, 132), (PHY_BAND_5, 136), (PHY_BAND_5, 140), (PHY_BAND_5, 144),
+    (PHY_BAND_5, 149), (PHY_BAND_5, 153), (PHY_BAND_5, 157), (PHY_BAND_5, 161),
+    (PHY_BAND_5, 165),
+];
+
+fn put_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Fill `buf` with a one-shot passive scan request. Returns its length,
+/// or None if `buf` is too small.
+pub fn build_passive_scan(uid: u32, buf: &mut [u8]) -> Option<usize> {
+    let buf = buf.get_mut(..SCAN_REQ_UMAC_SIZE)?;
+    buf.fill(0);
+
+    put_u32(buf, 0, uid);
+    put_u32(buf, 4, SCAN_PRIORITY_EXT_6);
+
+    let general = GENERAL_PARAMS_OFFSET;
+    put_u16(buf, general, GEN_FLAGS_V2_FORCE_PASSIVE | GEN_FLAGS_V2_PASS_ALL);
+    buf[general + 4] = ACTIVE_DWELL;
+    buf[general + 5] = ACTIVE_DWELL;
+    put_u32(buf, general + 28, SCAN_PRIORITY_EXT_6);
+    buf[general + 32] = PASSIVE_DWELL;
+    buf[general + 33] = PASSIVE_DWELL;
+    buf[general + 34] = 1;
+    buf[general + 35] = 1;
+
+    buf[CHANNEL_PARAMS_OFFSET + 1] = SCAN_CHANNELS.len() as u8;
+    for (i, &(band, channel)) in SCAN_CHANNELS.iter().enumerate() {
+        let entry = CHANNEL_CONFIG_OFFSET + i * CHANNEL_CONFIG_LEN;
+        buf[entry + 4] = channel;
+        buf[entry + 5] = band;
+        buf[entry + 6] = 1; // iter_count
+    }
+
+    // A single pass: one iteration, no repeat interval
+    buf[PERIODIC_PARAMS_OFFSET + 2] = 1;
+
+    Some(SCAN_REQ_UMAC_SIZE)
+}
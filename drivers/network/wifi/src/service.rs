@@ -0,0 +1,118 @@
+//! Service registry and VFS access
+//!
+//! The driver publishes its port as "wifi" and finds the VFS by name to
+//! read the firmware file.
+
+use driver_framework::ipc::{
+    ipc_create_port, ipc_destroy_port, ipc_receive_timeout, ipc_send, IpcMessage, IPC_MSG_REQUEST,
+    IPC_MSG_RESPONSE,
+};
+
+/// Port of the service name registry (hosted by the device manager)
+const SERVICE_REGISTRY_PORT: u64 = 90;
+/// [port:8][name] -> [status:1]
+const REGISTRY_OP_REGISTER_NAME: u64 = 5;
+/// [name] -> [status:1][port:8]
+const REGISTRY_OP_LOOKUP_NAME: u64 = 6;
+const REGISTRY_REPLY_TIMEOUT_MS: u64 = 500;
+
+const VFS_OP_OPEN: u64 = 1;
+const VFS_OP_READ: u64 = 2;
+const VFS_OP_CLOSE: u64 = 4;
+const VFS_REPLY_TIMEOUT_MS: u64 = 1000;
+
+/// Bytes moved per read request
+const VFS_CHUNK_SIZE: usize = 4096;
+
+/// Send a request and wait for its response on a private reply port
+fn request(port: u64, op: u64, data: &[u8], buffer: Option<&mut [u8]>, timeout_ms: u64) -> Result<IpcMessage, ()> {
+    let reply_port = ipc_create_port()?;
+
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_REQUEST;
+    msg.msg_id = op;
+    msg.set_inline_data(data);
+    msg.reply_port = reply_port;
+    if let Some(buffer) = buffer {
+        msg.buffer = buffer.as_mut_ptr();
+        msg.buffer_size = buffer.len();
+    }
+
+    let mut reply = IpcMessage::new();
+    let result = ipc_send(port, &msg)
+        .map_err(|_| ())
+        .and_then(|_| ipc_receive_timeout(reply_port, &mut reply, timeout_ms).map_err(|_| ()));
+    let _ = ipc_destroy_port(reply_port);
+    result?;
+
+    if reply.msg_type != IPC_MSG_RESPONSE || reply.msg_id != op {
+        return Err(());
+    }
+    Ok(reply)
+}
+
+/// One registry request; Err unless the registry answered with status 0
+fn registry_request(op: u64, data: &[u8]) -> Result<IpcMessage, ()> {
+    let reply = request(SERVICE_REGISTRY_PORT, op, data, None, REGISTRY_REPLY_TIMEOUT_MS)?;
+    if reply.inline_size == 0 || reply.inline_data[0] != 0 {
+        return Err(());
+    }
+    Ok(reply)
+}
+
+/// Publish this driver's port under `name`, replacing any earlier entry
+pub fn register_service_name(name: &[u8], port: u64) -> Result<(), ()> {
+    let mut data = [0u8; 64];
+    let len = name.len().min(data.len() - 8);
+    data[0..8].copy_from_slice(&port.to_le_bytes());
+    data[8..8 + len].copy_from_slice(&name[..len]);
+    registry_request(REGISTRY_OP_REGISTER_NAME, &data[..8 + len]).map(|_| ())
+}
+
+/// Port of the service registered as `name`
+fn lookup_service(name: &[u8]) -> Result<u64, ()> {
+    let reply = registry_request(REGISTRY_OP_LOOKUP_NAME, name)?;
+    if reply.inline_size < 9 {
+        return Err(());
+    }
+    let mut port = [0u8; 8];
+    port.copy_from_slice(&reply.inline_data[1..9]);
+    Ok(u64::from_le_bytes(port))
+}
+
+/// Replies holding a u32 result; errors are a single status byte
+fn reply_u32(reply: &IpcMessage) -> Result<u32, ()> {
+    if reply.inline_size < 4 {
+        return Err(());
+    }
+    Ok(u32::from_le_bytes([reply.inline_data[0], reply.inline_data[1], reply.inline_data[2], reply.inline_data[3]]))
+}
+
+/// Read the file at `path` into `buffer`. Returns the bytes read; a file
+/// larger than `buffer` is an error rather than silently cut short.
+pub fn read_file(path: &[u8], buffer: &mut [u8]) -> Result<usize, ()> {
+    let vfs_port = lookup_service(b"vfs")?;
+    let fd = reply_u32(&request(vfs_port, VFS_OP_OPEN, path, None, VFS_REPLY_TIMEOUT_MS)?)?.to_le_bytes();
+
+    let mut len = 0;
+    let result = loop {
+        if len == buffer.len() {
+            break Err(());
+        }
+        let want = (buffer.len() - len).min(VFS_CHUNK_SIZE);
+        let mut data = [0u8; 8];
+        data[0..4].copy_from_slice(&fd);
+        data[4..8].copy_from_slice(&(want as u32).to_le_bytes());
+
+        let read = request(vfs_port, VFS_OP_READ, &data, Some(&mut buffer[len..len + want]), VFS_REPLY_TIMEOUT_MS)
+            .and_then(|reply| reply_u32(&reply));
+        match read {
+            Ok(0) => break Ok(len),
+            Ok(read) => len += (read as usize).min(want),
+            Err(_) => break Err(()),
+        }
+    };
+
+    let _ = request(vfs_port, VFS_OP_CLOSE, &fd, None, VFS_REPLY_TIMEOUT_MS);
+    result
+}
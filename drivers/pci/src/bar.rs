@@ -0,0 +1,69 @@
+//! BAR decoding
+//!
+//! A BAR's size comes from writing all-ones to it and reading back which
+//! address bits stuck. 64-bit memory BARs use the following slot for the
+//! upper half of both the address and the mask.
+
+pub const PCI_BAR_IO: u32 = 1 << 0;
+const PCI_BAR_TYPE_MASK: u32 = 0x3 << 1;
+const PCI_BAR_TYPE_64: u32 = 0x2 << 1;
+
+const PCI_BAR_IO_ADDR_MASK: u32 = !0x3;
+const PCI_BAR_MEM_ADDR_MASK: u32 = !0xF;
+
+/// Decoded base address register
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BarInfo {
+    pub base: u64,
+    pub size: u64,
+    pub is_mmio: bool,
+    pub is_64bit: bool,
+}
+
+/// Whether a BAR value marks a 64-bit memory BAR
+pub fn is_64bit(low: u32) -> bool {
+    (low & PCI_BAR_IO) == 0 && (low & PCI_BAR_TYPE_MASK) == PCI_BAR_TYPE_64
+}
+
+/// Decode a BAR from its original value and the value read back after
+/// writing all-ones. `high`/`mask_high` are the next slot for 64-bit BARs
+/// and ignored otherwise. None if the BAR is not implemented.
+pub fn decode(low: u32, high: u32, mask_low: u32, mask_high: u32) -> Option<BarInfo> {
+    if (low & PCI_BAR_IO) != 0 {
+        // I/O space is 16 bits wide; the upper half may read back as zero
+        let size = ((!(mask_low & PCI_BAR_IO_ADDR_MASK)).wrapping_add(1) & 0xFFFF) as u64;
+        if size == 0 || (mask_low & PCI_BAR_IO_ADDR_MASK) == 0 {
+            return None;
+        }
+        return Some(BarInfo {
+            base: (low & PCI_BAR_IO_ADDR_MASK) as u64,
+            size,
+            is_mmio: false,
+            is_64bit: false,
+        });
+    }
+
+    if is_64bit(low) {
+        let mask = ((mask_high as u64) << 32) | (mask_low & PCI_BAR_MEM_ADDR_MASK) as u64;
+        if mask == 0 {
+            return None;
+        }
+        return Some(BarInfo {
+            base: ((high as u64) << 32) | (low & PCI_BAR_MEM_ADDR_MASK) as u64,
+            size: (!mask).wrapping_add(1),
+            is_mmio: true,
+            is_64bit: true,
+        });
+    }
+
+    let mask = mask_low & PCI_BAR_MEM_ADDR_MASK;
+    if mask == 0 {
+        return None;
+    }
+    Some(BarInfo {
+        base: (low & PCI_BAR_MEM_ADDR_MASK) as u64,
+        size: (!mask).wrapping_add(1) as u64,
+        is_mmio: true,
+        is_64bit: false,
+    })
+}
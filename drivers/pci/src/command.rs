@@ -0,0 +1,28 @@
+//! Command register updates
+//!
+//! The command register shares a dword with the status register, whose
+//! error bits are write-one-to-clear. Read-modify-write of the dword must
+//! drop the status half, or writing it back clears errors nobody has
+//! looked at yet.
+
+// Command register bits
+pub const PCI_COMMAND_IO: u32 = 1 << 0;
+pub const PCI_COMMAND_MEMORY: u32 = 1 << 1;
+pub const PCI_COMMAND_BUS_MASTER: u32 = 1 << 2;
+/// Stops the device from asserting its legacy INTx pin
+pub const PCI_COMMAND_INTX_DISABLE: u32 = 1 << 10;
+
+const PCI_COMMAND_MASK: u32 = 0xFFFF;
+
+/// Command half of the command/status dword
+pub fn command_bits(dword: u32) -> u32 {
+    dword & PCI_COMMAND_MASK
+}
+
+/// Value to write back so the device decodes its memory BARs and may
+/// master the bus. Without both bits, MMIO reads return all-ones and DMA
+/// silently does nothing: the device accepts the descriptors and never
+/// touches memory.
+pub fn enable_device(dword: u32) -> u32 {
+    command_bits(dword) | PCI_COMMAND_MEMORY | PCI_COMMAND_BUS_MASTER
+}
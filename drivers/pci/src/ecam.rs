@@ -0,0 +1,77 @@
+//! PCIe enhanced configuration access (ECAM)
+//!
+//! The ACPI MCFG table lists memory windows that map each function's 4 KiB
+//! configuration space: bus, device and function select the page and the
+//! register offset goes below that. Unlike the 0xCF8/0xCFC ports this
+//! reaches the extended space past 256 bytes, where PCIe keeps its
+//! extended capability list.
+
+/// Table signature of the MCFG
+pub const MCFG_SIGNATURE: &[u8; 4] = b"MCFG";
+
+/// Header plus 8 reserved bytes come before the allocations
+const MCFG_ALLOCATIONS: usize = 44;
+const MCFG_ALLOCATION_SIZE: usize = 16;
+
+/// Configuration space per function through ECAM
+pub const ECAM_FUNCTION_SIZE: u16 = 4096;
+
+/// One ECAM window from the MCFG
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct McfgAllocation {
+    pub base: u64,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+impl McfgAllocation {
+    /// Bytes of configuration space the window covers
+    pub fn size(&self) -> u64 {
+        (self.end_bus as u64 - self.start_bus as u64 + 1) << 20
+    }
+
+    /// Offset into the window of a function's register, if the bus is in it
+    pub fn offset(&self, bus: u8, device: u8, function: u8, offset: u16) -> Option<u64> {
+        if bus < self.start_bus || bus > self.end_bus || device > 31 || function > 7 || offset >= ECAM_FUNCTION_SIZE {
+            return None;
+        }
+        Some(
+            ((bus - self.start_bus) as u64) << 20
+                | (device as u64) << 15
+                | (function as u64) << 12
+                | (offset & 0xFFC) as u64,
+        )
+    }
+}
+
+/// Parse the allocations of an MCFG table into `out`, returning how many
+/// were found
+pub fn parse_mcfg(table: &[u8], out: &mut [McfgAllocation]) -> usize {
+    if table.len() < MCFG_ALLOCATIONS || table[0..4] != *MCFG_SIGNATURE {
+        return 0;
+    }
+    let length = u32::from_le_bytes([table[4], table[5], table[6], table[7]]) as usize;
+    let length = length.min(table.len());
+
+    let mut count = 0;
+    let mut offset = MCFG_ALLOCATIONS;
+    while offset + MCFG_ALLOCATION_SIZE <= length && count < out.len() {
+        let entry = &table[offset..offset + MCFG_ALLOCATION_SIZE];
+        let mut base = [0u8; 8];
+        base.copy_from_slice(&entry[0..8]);
+
+        let allocation = McfgAllocation {
+            base: u64::from_le_bytes(base),
+            segment: u16::from_le_bytes([entry[8], entry[9]]),
+            start_bus: entry[10],
+            end_bus: entry[11],
+        };
+        if allocation.base != 0 && allocation.start_bus <= allocation.end_bus {
+            out[count] = allocation;
+            count += 1;
+        }
+        offset += MCFG_ALLOCATION_SIZE;
+    }
+    count
+}
@@ -3,24 +3,32 @@
 pub const IPC_MSG_REQUEST: u32 = 1;
 pub const IPC_MSG_RESPONSE: u32 = 2;
 
+/// IPC message structure (must match kernel/include/ipc/ipc.h)
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct IpcMessage {
+    pub sender_tid: u64,
+    pub msg_id: u64,
     pub msg_type: u32,
-    pub msg_id: u32,
-    pub sender_tid: u32,
     pub inline_size: u32,
-    pub inline_data: [u8; 128],
+    pub inline_data: [u8; 64],
+    pub buffer: *mut u8,
+    pub buffer_size: usize,
+    /// Port responses go to; 0 if the sender expects none
+    pub reply_port: u64,
 }
 
 impl IpcMessage {
     pub fn new() -> Self {
         IpcMessage {
-            msg_type: 0,
-            msg_id: 0,
             sender_tid: 0,
+            msg_id: 0,
+            msg_type: 0,
             inline_size: 0,
-            inline_data: [0; 128],
+            inline_data: [0; 64],
+            buffer: core::ptr::null_mut(),
+            buffer_size: 0,
+            reply_port: 0,
         }
     }
 }
@@ -28,6 +36,8 @@ impl IpcMessage {
 extern "C" {
     fn syscall_ipc_send(port: u32, msg: *const IpcMessage) -> i32;
     fn syscall_ipc_receive(port: u32, msg: *mut IpcMessage) -> i32;
+    fn syscall_ipc_receive_timeout(port: u32, msg: *mut IpcMessage, timeout_ms: u64) -> i32;
+    fn syscall_ipc_reply(request: *const IpcMessage, response: *const IpcMessage) -> i32;
     fn syscall_ipc_register_port(port: u32) -> i32;
 }
 
@@ -39,6 +49,14 @@ pub fn sys_ipc_receive(port: u32, msg: &mut IpcMessage) -> i32 {
     unsafe { syscall_ipc_receive(port, msg as *mut IpcMessage) }
 }
 
+pub fn sys_ipc_receive_timeout(port: u32, msg: &mut IpcMessage, timeout_ms: u64) -> i32 {
+    unsafe { syscall_ipc_receive_timeout(port, msg as *mut IpcMessage, timeout_ms) }
+}
+
+pub fn sys_ipc_reply(request: &IpcMessage, response: &IpcMessage) -> i32 {
+    unsafe { syscall_ipc_reply(request as *const IpcMessage, response as *const IpcMessage) }
+}
+
 pub fn sys_ipc_register_port(port: u32) -> i32 {
     unsafe { syscall_ipc_register_port(port) }
 }
@@ -11,7 +11,11 @@ use alloc::vec::Vec;
 use core::panic::PanicInfo;
 
 mod ipc;
-use ipc::{IpcMessage, sys_ipc_receive, sys_ipc_send, sys_ipc_register_port};
+mod bar;
+mod msi;
+mod ecam;
+mod command;
+use ipc::{IpcMessage, sys_ipc_receive, sys_ipc_receive_timeout, sys_ipc_send, sys_ipc_reply, sys_ipc_register_port};
 
 // PCI driver port
 const PCI_DRIVER_PORT: u32 = 101;
@@ -19,11 +23,48 @@ const PCI_DRIVER_PORT: u32 = 101;
 // Driver Manager port
 const DRIVER_MANAGER_PORT: u32 = 100;
 
+// ACPI service port and the request that looks up a table
+const ACPI_SERVICE_PORT: u32 = 106;
+const ACPI_OP_FIND_TABLE: u64 = 4;
+const ACPI_STATUS_OK: u8 = 0;
+const ACPI_REPLY_TIMEOUT_MS: u64 = 1000;
+
 // Message types
-const MSG_PCI_READ_CONFIG: u32 = 10;
-const MSG_PCI_WRITE_CONFIG: u32 = 11;
-const MSG_PCI_ENUMERATE: u32 = 12;
-const MSG_PCI_FIND_DEVICE: u32 = 13;
+const MSG_PCI_READ_CONFIG: u64 = 10;
+const MSG_PCI_WRITE_CONFIG: u64 = 11;
+const MSG_PCI_ENUMERATE: u64 = 12;
+const MSG_PCI_FIND_DEVICE: u64 = 13;
+/// [class, subclass, prog_if, index] -> index-th match as
+/// [bus, device, function, vendor_id u16, device_id u16, match count]
+const MSG_PCI_FIND_BY_CLASS: u64 = 14;
+
+/// Subclass/prog_if value that matches anything in MSG_PCI_FIND_BY_CLASS
+const PCI_CLASS_ANY: u8 = 0xFF;
+/// [bus, device, function, bar] -> [base u64, size u64, is_mmio u8, is_64bit u8]
+const MSG_PCI_GET_BAR: u64 = 15;
+/// [bus, device, function] -> [irq, kind] where kind is MSI_KIND_MSI or
+/// MSI_KIND_MSIX; the irq is what the caller passes to irq_register
+const MSG_PCI_ENABLE_MSI: u64 = 16;
+/// [bus, device, function, offset u16] -> [value u32]; reaches the PCIe
+/// extended space (0x100-0xFFF) when ECAM is available
+const MSG_PCI_READ_CONFIG_EXT: u64 = 17;
+/// [bus, device, function, offset u16, value u32] -> [1 on success]
+const MSG_PCI_WRITE_CONFIG_EXT: u64 = 18;
+/// [bus, device, function, capability id] -> [offset], 0 if the device
+/// has no such capability
+const MSG_PCI_FIND_CAP: u64 = 19;
+/// [bus, device, function] -> [1 on success]; turns on memory decode and
+/// bus mastering, which every DMA-capable driver needs during init
+const MSG_PCI_ENABLE_DEVICE: u64 = 20;
+
+const MSI_KIND_MSI: u8 = 1;
+const MSI_KIND_MSIX: u8 = 2;
+
+// Configuration space offsets
+const PCI_COMMAND_OFFSET: u8 = 0x04;
+const PCI_BAR0_OFFSET: u8 = 0x10;
+
+use command::{PCI_COMMAND_INTX_DISABLE, PCI_COMMAND_IO, PCI_COMMAND_MEMORY};
 
 // PCI device information
 #[repr(C)]
@@ -59,14 +100,34 @@ impl PciDevice {
     }
 }
 
+/// Segment 0 ECAM window mapped into our address space
+struct EcamWindow {
+    allocation: ecam::McfgAllocation,
+    vaddr: u64,
+}
+
 // PCI driver state
 struct PciDriver {
     devices: Vec<PciDevice>,
+    /// Memory-mapped config space; port I/O is used when None
+    ecam: Option<EcamWindow>,
 }
 
 // Syscall numbers (from kernel/include/syscall/syscall.h)
 const SYS_IO_READ: u64 = 49;
 const SYS_IO_WRITE: u64 = 50;
+const SYS_MMIO_MAP: u64 = 36;
+const SYS_MMIO_UNMAP: u64 = 37;
+const SYS_MSI_ALLOC: u64 = 56;
+const SYS_MSI_FREE: u64 = 57;
+
+/// Address/data pair from the kernel (matches msi_message_t)
+#[repr(C)]
+struct MsiMessage {
+    address: u64,
+    data: u32,
+    irq: u32,
+}
 
 // Syscall wrappers for I/O port access
 unsafe fn sys_io_read(port: u16, size: u8) -> u32 {
@@ -96,14 +157,109 @@ unsafe fn sys_io_write(port: u16, value: u32, size: u8) {
     );
 }
 
+/// Allocate an interrupt vector for a device; None if the kernel is out
+unsafe fn sys_msi_alloc() -> Option<MsiMessage> {
+    let mut message = MsiMessage { address: 0, data: 0, irq: 0 };
+    let ret: u64;
+    core::arch::asm!(
+        "syscall",
+        in("rax") SYS_MSI_ALLOC,
+        in("rdi") &mut message as *mut MsiMessage as u64,
+        out("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+    );
+    if ret as i64 >= 0 {
+        Some(message)
+    } else {
+        None
+    }
+}
+
+unsafe fn sys_msi_free(irq: u32) {
+    core::arch::asm!(
+        "syscall",
+        in("rax") SYS_MSI_FREE,
+        in("rdi") irq as u64,
+        lateout("rax") _,
+        lateout("rcx") _,
+        lateout("r11") _,
+    );
+}
+
+unsafe fn sys_mmio_map(paddr: u64, size: u64) -> u64 {
+    let ret: u64;
+    core::arch::asm!(
+        "syscall",
+        in("rax") SYS_MMIO_MAP,
+        in("rdi") paddr,
+        in("rsi") size,
+        out("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
+    );
+    ret
+}
+
+unsafe fn sys_mmio_unmap(vaddr: u64, size: u64) {
+    core::arch::asm!(
+        "syscall",
+        in("rax") SYS_MMIO_UNMAP,
+        in("rdi") vaddr,
+        in("rsi") size,
+        lateout("rax") _,
+        lateout("rcx") _,
+        lateout("r11") _,
+    );
+}
+
 
 impl PciDriver {
     fn new() -> Self {
         PciDriver {
             devices: Vec::new(),
+            ecam: None,
+        }
+    }
+
+    /// Map the segment 0 ECAM window described by the ACPI MCFG table
+    fn init_ecam(&mut self) {
+        let (phys, length) = match query_acpi_table(ecam::MCFG_SIGNATURE) {
+            Some(table) => table,
+            None => return,
+        };
+
+        let mut allocations = [ecam::McfgAllocation { base: 0, segment: 0, start_bus: 0, end_bus: 0 }; 4];
+        let count = unsafe {
+            let vaddr = sys_mmio_map(phys, length as u64);
+            if vaddr == 0 {
+                return;
+            }
+            let table = core::slice::from_raw_parts(vaddr as *const u8, length as usize);
+            let count = ecam::parse_mcfg(table, &mut allocations);
+            sys_mmio_unmap(vaddr, length as u64);
+            count
+        };
+
+        // Only segment 0 is reachable through the legacy ports we fall back to
+        let allocation = match allocations[..count].iter().find(|a| a.segment == 0) {
+            Some(allocation) => *allocation,
+            None => return,
+        };
+
+        let vaddr = unsafe { sys_mmio_map(allocation.base, allocation.size()) };
+        if vaddr != 0 {
+            self.ecam = Some(EcamWindow { allocation, vaddr });
         }
     }
 
+    /// Address of a config register in the ECAM window, if it covers the bus
+    fn ecam_address(&self, bus: u8, device: u8, function: u8, offset: u16) -> Option<*mut u32> {
+        let window = self.ecam.as_ref()?;
+        let offset = window.allocation.offset(bus, device, function, offset)?;
+        Some((window.vaddr + offset) as *mut u32)
+    }
+
     fn enumerate_devices(&mut self) {
         // Scan all PCI buses, devices, and functions
         for bus in 0..256 {
@@ -155,6 +311,24 @@ impl PciDriver {
     }
 
     fn read_config_dword(&self, bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+        self.read_config_dword_ext(bus, device, function, offset as u16)
+    }
+
+    fn write_config_dword(&self, bus: u8, device: u8, function: u8, offset: u8, value: u32) {
+        self.write_config_dword_ext(bus, device, function, offset as u16, value)
+    }
+
+    /// Read any register of the 4 KiB config space. Past 0xFF this needs
+    /// ECAM and reads as all-ones without it.
+    fn read_config_dword_ext(&self, bus: u8, device: u8, function: u8, offset: u16) -> u32 {
+        if let Some(reg) = self.ecam_address(bus, device, function, offset) {
+            return unsafe { core::ptr::read_volatile(reg) };
+        }
+        if offset > 0xFF {
+            return 0xFFFF_FFFF;
+        }
+
+        let offset = offset as u8;
         let address = 0x80000000u32
             | ((bus as u32) << 16)
             | ((device as u32) << 11)
@@ -169,7 +343,16 @@ impl PciDriver {
         }
     }
 
-    fn write_config_dword(&self, bus: u8, device: u8, function: u8, offset: u8, value: u32) {
+    fn write_config_dword_ext(&self, bus: u8, device: u8, function: u8, offset: u16, value: u32) {
+        if let Some(reg) = self.ecam_address(bus, device, function, offset) {
+            unsafe { core::ptr::write_volatile(reg, value) };
+            return;
+        }
+        if offset > 0xFF {
+            return;
+        }
+
+        let offset = offset as u8;
         let address = 0x80000000u32
             | ((bus as u32) << 16)
             | ((device as u32) << 11)
@@ -186,6 +369,195 @@ impl PciDriver {
         self.devices.iter()
             .find(|dev| dev.vendor_id == vendor_id && dev.device_id == device_id)
     }
+
+    /// Decode a BAR and size it by writing all-ones and reading back the mask
+    fn get_bar(&self, bus: u8, device: u8, function: u8, index: u8) -> Option<bar::BarInfo> {
+        if index > 5 {
+            return None;
+        }
+
+        let offset = PCI_BAR0_OFFSET + index * 4;
+        let low = self.read_config_dword(bus, device, function, offset);
+        let wide = bar::is_64bit(low);
+        if wide && index == 5 {
+            return None;
+        }
+
+        // Stop decoding while the BAR temporarily holds all-ones
+        let command = command::command_bits(self.read_config_dword(bus, device, function, PCI_COMMAND_OFFSET));
+        self.write_config_dword(bus, device, function, PCI_COMMAND_OFFSET,
+            command & !(PCI_COMMAND_IO | PCI_COMMAND_MEMORY));
+
+        self.write_config_dword(bus, device, function, offset, 0xFFFF_FFFF);
+        let mask_low = self.read_config_dword(bus, device, function, offset);
+        self.write_config_dword(bus, device, function, offset, low);
+
+        let (high, mask_high) = if wide {
+            let high = self.read_config_dword(bus, device, function, offset + 4);
+            self.write_config_dword(bus, device, function, offset + 4, 0xFFFF_FFFF);
+            let mask_high = self.read_config_dword(bus, device, function, offset + 4);
+            self.write_config_dword(bus, device, function, offset + 4, high);
+            (high, mask_high)
+        } else {
+            (0, 0)
+        };
+
+        self.write_config_dword(bus, device, function, PCI_COMMAND_OFFSET, command);
+
+        bar::decode(low, high, mask_low, mask_high)
+    }
+
+    /// Turn on memory decode and bus mastering. Firmware often leaves both
+    /// off, and a device without them ignores MMIO and DMA silently does
+    /// nothing. False if no device answers at that address.
+    fn enable_device(&self, bus: u8, device: u8, function: u8) -> bool {
+        if self.read_config_dword(bus, device, function, 0) & 0xFFFF == 0xFFFF {
+            return false;
+        }
+        let dword = self.read_config_dword(bus, device, function, PCI_COMMAND_OFFSET);
+        self.write_config_dword(bus, device, function, PCI_COMMAND_OFFSET, command::enable_device(dword));
+        true
+    }
+
+    fn find_capability(&self, bus: u8, device: u8, function: u8, cap_id: u8) -> Option<u8> {
+        msi::find_capability(|offset| self.read_config_dword(bus, device, function, offset), cap_id)
+    }
+
+    /// Route the device's interrupt through a freshly allocated vector,
+    /// preferring MSI-X over MSI. Returns (irq, kind).
+    fn enable_msi(&self, bus: u8, device: u8, function: u8) -> Option<(u8, u8)> {
+        let msix = self.find_capability(bus, device, function, msi::PCI_CAP_ID_MSIX);
+        let msi_cap = self.find_capability(bus, device, function, msi::PCI_CAP_ID_MSI);
+        if msix.is_none() && msi_cap.is_none() {
+            return None;
+        }
+
+        let message = unsafe { sys_msi_alloc()? };
+
+        let kind = if msix.map_or(false, |cap| self.program_msix(bus, device, function, cap, &message)) {
+            MSI_KIND_MSIX
+        } else if let Some(cap) = msi_cap {
+            self.program_msi(bus, device, function, cap, &message);
+            MSI_KIND_MSI
+        } else {
+            unsafe { sys_msi_free(message.irq) };
+            return None;
+        };
+
+        let command = command::command_bits(self.read_config_dword(bus, device, function, PCI_COMMAND_OFFSET));
+        self.write_config_dword(bus, device, function, PCI_COMMAND_OFFSET, command | PCI_COMMAND_INTX_DISABLE);
+
+        Some((message.irq as u8, kind))
+    }
+
+    fn program_msi(&self, bus: u8, device: u8, function: u8, cap: u8, message: &MsiMessage) {
+        let header = self.read_config_dword(bus, device, function, cap);
+        let control = (header >> 16) as u16;
+        let layout = msi::msi_layout(control);
+
+        self.write_config_dword(bus, device, function, cap + layout.address_low, message.address as u32);
+        if let Some(high) = layout.address_high {
+            self.write_config_dword(bus, device, function, cap + high, (message.address >> 32) as u32);
+        }
+
+        // Data is the low word; keep whatever follows it
+        let data = self.read_config_dword(bus, device, function, cap + layout.data);
+        self.write_config_dword(bus, device, function, cap + layout.data,
+            (data & 0xFFFF_0000) | (message.data & 0xFFFF));
+
+        let control = msi::msi_enable_control(control);
+        self.write_config_dword(bus, device, function, cap, (header & 0xFFFF) | ((control as u32) << 16));
+    }
+
+    /// Program table entry 0. False if the table could not be mapped.
+    fn program_msix(&self, bus: u8, device: u8, function: u8, cap: u8, message: &MsiMessage) -> bool {
+        let header = self.read_config_dword(bus, device, function, cap);
+        let control = (header >> 16) as u16;
+        let (bir, table_offset) = msi::msix_table_location(self.read_config_dword(bus, device, function, cap + 4));
+
+        let info = match self.get_bar(bus, device, function, bir) {
+            Some(info) if info.is_mmio => info,
+            _ => return false,
+        };
+        if (table_offset as u64) + msi::MSIX_ENTRY_SIZE > info.size {
+            return false;
+        }
+
+        // Mask the whole function while the entry is being written
+        let masked = control | msi::MSIX_CONTROL_ENABLE | msi::MSIX_CONTROL_FUNCTION_MASK;
+        self.write_config_dword(bus, device, function, cap, (header & 0xFFFF) | ((masked as u32) << 16));
+
+        let entry = info.base + table_offset as u64;
+        let page = entry & !0xFFF;
+        let map_size = (entry - page) + msi::MSIX_ENTRY_SIZE;
+        let vaddr = unsafe { sys_mmio_map(page, map_size) };
+        if vaddr == 0 {
+            self.write_config_dword(bus, device, function, cap, header);
+            return false;
+        }
+
+        unsafe {
+            let regs = (vaddr + (entry - page)) as *mut u32;
+            core::ptr::write_volatile(regs, message.address as u32);
+            core::ptr::write_volatile(regs.add(1), (message.address >> 32) as u32);
+            core::ptr::write_volatile(regs.add(2), message.data);
+            let vector_control = core::ptr::read_volatile(regs.add(3));
+            core::ptr::write_volatile(regs.add(3), vector_control & !msi::MSIX_ENTRY_MASKED);
+            sys_mmio_unmap(vaddr, map_size);
+        }
+
+        let enabled = (control | msi::MSIX_CONTROL_ENABLE) & !msi::MSIX_CONTROL_FUNCTION_MASK;
+        self.write_config_dword(bus, device, function, cap, (header & 0xFFFF) | ((enabled as u32) << 16));
+        true
+    }
+
+    /// The index-th device of a class, with the total number of matches
+    fn find_by_class(&self, class_code: u8, subclass: u8, prog_if: u8, index: usize) -> Option<(&PciDevice, usize)> {
+        let matches = |dev: &&PciDevice| {
+            dev.class_code == class_code
+                && (subclass == PCI_CLASS_ANY || dev.subclass == subclass)
+                && (prog_if == PCI_CLASS_ANY || dev.prog_if == prog_if)
+        };
+
+        let count = self.devices.iter().filter(matches).count();
+        self.devices.iter().filter(matches).nth(index).map(|dev| (dev, count))
+    }
+}
+
+/// Ask the ACPI service where a table lives: (physical address, length)
+fn query_acpi_table(signature: &[u8; 4]) -> Option<(u64, u32)> {
+    let mut msg = IpcMessage::new();
+    msg.msg_type = ipc::IPC_MSG_REQUEST;
+    msg.msg_id = ACPI_OP_FIND_TABLE;
+    msg.inline_data[0..4].copy_from_slice(signature);
+    msg.inline_data[4] = 0; // First instance
+    msg.inline_size = 5;
+    // Nobody knows our port before we register with the driver manager,
+    // so the only message that can arrive here is the reply
+    msg.reply_port = PCI_DRIVER_PORT as u64;
+
+    if sys_ipc_send(ACPI_SERVICE_PORT, &msg) != 0 {
+        return None;
+    }
+
+    let mut reply = IpcMessage::new();
+    if sys_ipc_receive_timeout(PCI_DRIVER_PORT, &mut reply, ACPI_REPLY_TIMEOUT_MS) != 0 {
+        return None;
+    }
+    if reply.msg_type != ipc::IPC_MSG_RESPONSE || reply.msg_id != ACPI_OP_FIND_TABLE
+        || reply.inline_size < 13 || reply.inline_data[0] != ACPI_STATUS_OK {
+        return None;
+    }
+
+    let mut phys = [0u8; 8];
+    phys.copy_from_slice(&reply.inline_data[1..9]);
+    let length = u32::from_le_bytes([
+        reply.inline_data[9],
+        reply.inline_data[10],
+        reply.inline_data[11],
+        reply.inline_data[12],
+    ]);
+    Some((u64::from_le_bytes(phys), length))
 }
 
 static mut PCI_DRIVER: Option<PciDriver> = None;
@@ -206,6 +578,13 @@ fn pci_driver_init() {
         loop {}
     }
 
+    // Prefer memory-mapped config space; needs our port for the ACPI reply
+    unsafe {
+        if let Some(ref mut driver) = PCI_DRIVER {
+            driver.init_ecam();
+        }
+    }
+
     // Register with driver manager
     let mut msg = IpcMessage::new();
     msg.msg_type = ipc::IPC_MSG_REQUEST;
@@ -233,7 +612,7 @@ fn pci_driver_loop() -> ! {
     loop {
         if sys_ipc_receive(PCI_DRIVER_PORT, &mut msg) == 0 {
             let response = handle_message(&msg);
-            let _ = sys_ipc_send(msg.sender_tid, &response);
+            let _ = sys_ipc_reply(&msg, &response);
         }
     }
 }
@@ -274,6 +653,34 @@ fn handle_message(msg: &IpcMessage) -> IpcMessage {
                     response.inline_size = 1;
                 }
 
+                MSG_PCI_READ_CONFIG_EXT => {
+                    let bus = msg.inline_data[0];
+                    let device = msg.inline_data[1];
+                    let function = msg.inline_data[2];
+                    let offset = u16::from_le_bytes([msg.inline_data[3], msg.inline_data[4]]);
+
+                    let value = driver.read_config_dword_ext(bus, device, function, offset);
+                    response.inline_data[0..4].copy_from_slice(&value.to_le_bytes());
+                    response.inline_size = 4;
+                }
+
+                MSG_PCI_WRITE_CONFIG_EXT => {
+                    let bus = msg.inline_data[0];
+                    let device = msg.inline_data[1];
+                    let function = msg.inline_data[2];
+                    let offset = u16::from_le_bytes([msg.inline_data[3], msg.inline_data[4]]);
+                    let value = u32::from_le_bytes([
+                        msg.inline_data[5],
+                        msg.inline_data[6],
+                        msg.inline_data[7],
+                        msg.inline_data[8],
+                    ]);
+
+                    driver.write_config_dword_ext(bus, device, function, offset, value);
+                    response.inline_data[0] = 1; // Success
+                    response.inline_size = 1;
+                }
+
                 MSG_PCI_ENUMERATE => {
                     let count = driver.devices.len().min(16);
                     response.inline_data[0] = count as u8;
@@ -295,6 +702,78 @@ fn handle_message(msg: &IpcMessage) -> IpcMessage {
                     }
                 }
 
+                MSG_PCI_GET_BAR => {
+                    let bus = msg.inline_data[0];
+                    let device = msg.inline_data[1];
+                    let function = msg.inline_data[2];
+                    let index = msg.inline_data[3];
+
+                    if let Some(info) = driver.get_bar(bus, device, function, index) {
+                        response.inline_data[0..8].copy_from_slice(&info.base.to_le_bytes());
+                        response.inline_data[8..16].copy_from_slice(&info.size.to_le_bytes());
+                        response.inline_data[16] = info.is_mmio as u8;
+                        response.inline_data[17] = info.is_64bit as u8;
+                        response.inline_size = 18;
+                    } else {
+                        response.inline_data[0] = 0xFF; // Not implemented
+                        response.inline_size = 1;
+                    }
+                }
+
+                MSG_PCI_FIND_BY_CLASS => {
+                    let class_code = msg.inline_data[0];
+                    let subclass = msg.inline_data[1];
+                    let prog_if = msg.inline_data[2];
+                    let index = msg.inline_data[3] as usize;
+
+                    if let Some((dev, count)) = driver.find_by_class(class_code, subclass, prog_if, index) {
+                        response.inline_data[0] = dev.bus;
+                        response.inline_data[1] = dev.device;
+                        response.inline_data[2] = dev.function;
+                        response.inline_data[3..5].copy_from_slice(&dev.vendor_id.to_le_bytes());
+                        response.inline_data[5..7].copy_from_slice(&dev.device_id.to_le_bytes());
+                        response.inline_data[7] = count.min(0xFF) as u8;
+                        response.inline_size = 8;
+                    } else {
+                        response.inline_data[0] = 0xFF; // No (more) matches
+                        response.inline_size = 1;
+                    }
+                }
+
+                MSG_PCI_FIND_CAP => {
+                    let bus = msg.inline_data[0];
+                    let device = msg.inline_data[1];
+                    let function = msg.inline_data[2];
+                    let cap_id = msg.inline_data[3];
+
+                    response.inline_data[0] = driver.find_capability(bus, device, function, cap_id).unwrap_or(0);
+                    response.inline_size = 1;
+                }
+
+                MSG_PCI_ENABLE_DEVICE => {
+                    let bus = msg.inline_data[0];
+                    let device = msg.inline_data[1];
+                    let function = msg.inline_data[2];
+
+                    response.inline_data[0] = if driver.enable_device(bus, device, function) { 1 } else { 0xFF };
+                    response.inline_size = 1;
+                }
+
+                MSG_PCI_ENABLE_MSI => {
+                    let bus = msg.inline_data[0];
+                    let device = msg.inline_data[1];
+                    let function = msg.inline_data[2];
+
+                    if let Some((irq, kind)) = driver.enable_msi(bus, device, function) {
+                        response.inline_data[0] = irq;
+                        response.inline_data[1] = kind;
+                        response.inline_size = 2;
+                    } else {
+                        response.inline_data[0] = 0xFF; // No MSI support or no free vector
+                        response.inline_size = 1;
+                    }
+                }
+
                 _ => {
                     response.inline_data[0] = 0xFF; // Unknown command
                     response.inline_size = 1;
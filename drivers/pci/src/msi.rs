@@ -0,0 +1,92 @@
+//! PCI capability list and MSI/MSI-X register layout
+//!
+//! Capabilities form a linked list in configuration space starting at the
+//! pointer at 0x34. Pointers come from the device, so the walk is bounded
+//! in case a broken device links the list into a loop.
+
+/// Status register bit set when the capability list is present
+pub const PCI_STATUS_CAP_LIST: u32 = 1 << 4;
+
+const PCI_STATUS_OFFSET: u8 = 0x06;
+const PCI_CAP_POINTER_OFFSET: u8 = 0x34;
+
+/// 48 capabilities of 4 bytes each fill the 192 bytes past the header
+pub const PCI_CAP_MAX_ITERATIONS: usize = 48;
+
+pub const PCI_CAP_ID_PM: u8 = 0x01;
+pub const PCI_CAP_ID_MSI: u8 = 0x05;
+pub const PCI_CAP_ID_PCIE: u8 = 0x10;
+pub const PCI_CAP_ID_MSIX: u8 = 0x11;
+
+// MSI message control bits
+pub const MSI_CONTROL_ENABLE: u16 = 1 << 0;
+pub const MSI_CONTROL_MME_MASK: u16 = 0x7 << 4;
+pub const MSI_CONTROL_64BIT: u16 = 1 << 7;
+
+// MSI-X message control bits
+pub const MSIX_CONTROL_FUNCTION_MASK: u16 = 1 << 14;
+pub const MSIX_CONTROL_ENABLE: u16 = 1 << 15;
+
+/// MSI-X table entry: address low, address high, data, vector control
+pub const MSIX_ENTRY_SIZE: u64 = 16;
+pub const MSIX_ENTRY_MASKED: u32 = 1 << 0;
+
+/// Offset of the first capability with the given id, reading dwords
+/// through `read_dword`
+pub fn find_capability<F: Fn(u8) -> u32>(read_dword: F, cap_id: u8) -> Option<u8> {
+    let status = (read_dword(PCI_STATUS_OFFSET & 0xFC) >> 16) & 0xFFFF;
+    if status & PCI_STATUS_CAP_LIST == 0 {
+        return None;
+    }
+
+    let mut offset = (read_dword(PCI_CAP_POINTER_OFFSET) & 0xFC) as u8;
+    for _ in 0..PCI_CAP_MAX_ITERATIONS {
+        // Capabilities live past the 64-byte header
+        if offset < 0x40 {
+            return None;
+        }
+
+        let header = read_dword(offset);
+        if (header & 0xFF) as u8 == cap_id {
+            return Some(offset);
+        }
+        offset = ((header >> 8) & 0xFC) as u8;
+    }
+
+    None
+}
+
+/// Register offsets of an MSI capability, relative to the capability
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MsiLayout {
+    pub address_low: u8,
+    pub address_high: Option<u8>,
+    pub data: u8,
+}
+
+/// MSI register layout for a given message control value
+pub fn msi_layout(control: u16) -> MsiLayout {
+    if control & MSI_CONTROL_64BIT != 0 {
+        MsiLayout {
+            address_low: 0x04,
+            address_high: Some(0x08),
+            data: 0x0C,
+        }
+    } else {
+        MsiLayout {
+            address_low: 0x04,
+            address_high: None,
+            data: 0x08,
+        }
+    }
+}
+
+/// Message control value enabling single-vector MSI
+pub fn msi_enable_control(control: u16) -> u16 {
+    (control & !MSI_CONTROL_MME_MASK) | MSI_CONTROL_ENABLE
+}
+
+/// Split an MSI-X table/PBA register into (BAR index, offset into the BAR)
+pub fn msix_table_location(table: u32) -> (u8, u32) {
+    ((table & 0x7) as u8, table & !0x7)
+}
@@ -0,0 +1,81 @@
+//! 8042 controller registers and configuration byte
+//!
+//! IRQ1 and IRQ12 both only mean "the output buffer is full". Which
+//! device a byte came from is in the status register's AUX bit, so bytes
+//! are routed by that bit rather than by the IRQ that fired.
+
+// Status register
+pub const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+pub const STATUS_INPUT_FULL: u8 = 1 << 1;
+pub const STATUS_AUX_DATA: u8 = 1 << 5;
+
+// Configuration byte
+pub const CONFIG_PORT1_IRQ: u8 = 1 << 0;
+pub const CONFIG_PORT2_IRQ: u8 = 1 << 1;
+pub const CONFIG_PORT1_CLOCK_OFF: u8 = 1 << 4;
+pub const CONFIG_PORT2_CLOCK_OFF: u8 = 1 << 5;
+
+// Controller commands (written to the command port)
+pub const CMD_READ_CONFIG: u8 = 0x20;
+pub const CMD_WRITE_CONFIG: u8 = 0x60;
+pub const CMD_DISABLE_PORT2: u8 = 0xA7;
+pub const CMD_ENABLE_PORT2: u8 = 0xA8;
+pub const CMD_DISABLE_PORT1: u8 = 0xAD;
+pub const CMD_ENABLE_PORT1: u8 = 0xAE;
+/// Send the next data byte to the auxiliary device instead of port 1
+pub const CMD_WRITE_PORT2: u8 = 0xD4;
+
+/// Device behind one of the controller's ports
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Keyboard = 0,
+    Aux = 1,
+}
+
+impl Channel {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Channel::Keyboard),
+            1 => Some(Channel::Aux),
+            _ => None,
+        }
+    }
+
+    /// Source of the byte waiting in the output buffer, `None` if the
+    /// buffer is empty
+    pub fn from_status(status: u8) -> Option<Self> {
+        if status & STATUS_OUTPUT_FULL == 0 {
+            None
+        } else if status & STATUS_AUX_DATA != 0 {
+            Some(Channel::Aux)
+        } else {
+            Some(Channel::Keyboard)
+        }
+    }
+}
+
+/// Configuration to hold while probing: both IRQs off so nothing reads
+/// the data port behind our back. Translation is left as firmware set it,
+/// since the keyboard driver expects set 1 scancodes.
+pub fn init_config(config: u8) -> u8 {
+    config & !(CONFIG_PORT1_IRQ | CONFIG_PORT2_IRQ)
+}
+
+/// A dual-channel controller clears the port 2 clock-off bit once port 2
+/// is enabled; a single-channel one has no such bit to clear
+pub fn aux_present(config_after_enable: u8) -> bool {
+    config_after_enable & CONFIG_PORT2_CLOCK_OFF == 0
+}
+
+/// Configuration for normal operation: clocks and IRQs on for each port
+/// that exists
+pub fn run_config(config: u8, has_aux: bool) -> u8 {
+    let mut config = (config | CONFIG_PORT1_IRQ) & !CONFIG_PORT1_CLOCK_OFF;
+    if has_aux {
+        config = (config | CONFIG_PORT2_IRQ) & !CONFIG_PORT2_CLOCK_OFF;
+    } else {
+        config &= !CONFIG_PORT2_IRQ;
+    }
+    config
+}
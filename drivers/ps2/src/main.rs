@@ -0,0 +1,282 @@
+/**
+ * @file ps2_controller.rs
+ * @brief User-space 8042 PS/2 controller driver
+ *
+ * Sole owner of ports 0x60/0x64. Bytes from the keyboard and the mouse are
+ * forwarded to their drivers, and the drivers send device commands through
+ * here instead of touching the controller themselves.
+ */
+
+use core::panic::PanicInfo;
+
+use driver_framework::interrupts::bind_irq_port;
+use driver_framework::ipc::{
+    ipc_receive, ipc_reply, ipc_send, IpcMessage, IPC_MSG_ID_IRQ, IPC_MSG_NOTIFICATION,
+    IPC_MSG_REQUEST, IPC_MSG_RESPONSE,
+};
+
+mod controller;
+use controller::*;
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    loop {}
+}
+
+// Port registration and port I/O syscall wrappers
+extern "C" {
+    fn sys_ipc_register_port(port: u32) -> i32;
+    fn sys_io_read(port: u16, size: u8) -> u32;
+    fn sys_io_write(port: u16, value: u32, size: u8) -> i32;
+}
+
+// Controller IPC port
+const PS2_CONTROLLER_PORT: u32 = 107;
+
+// Drivers the bytes are forwarded to
+const KEYBOARD_DRIVER_PORT: u32 = 103;
+const MOUSE_DRIVER_PORT: u32 = 104;
+
+// Controller ports
+const PS2_DATA_PORT: u16 = 0x60;
+const PS2_STATUS_PORT: u16 = 0x64;
+const PS2_COMMAND_PORT: u16 = 0x64;
+
+const KEYBOARD_IRQ: u8 = 1;
+const MOUSE_IRQ: u8 = 12;
+
+// Message ids
+/// Controller -> device driver notification: [byte]
+const MSG_PS2_DATA: u64 = 16;
+/// Device driver -> controller request: [channel, byte, response length]
+const MSG_PS2_COMMAND: u64 = 17;
+/// Controller -> device driver, on the request's reply port:
+/// [status, count, response bytes]
+const MSG_PS2_COMMAND_REPLY: u64 = 18;
+
+// MSG_PS2_COMMAND_REPLY status
+const PS2_STATUS_OK: u8 = 0;
+const PS2_STATUS_TIMEOUT: u8 = 1;
+const PS2_STATUS_NO_DEVICE: u8 = 2;
+const PS2_STATUS_INVALID: u8 = 3;
+
+/// Longest device response a command can ask for (reset: ACK, 0xAA, id)
+const PS2_MAX_RESPONSE: usize = 3;
+
+/// Status register polls before giving up on the controller
+const PS2_WAIT_ITERATIONS: u32 = 100000;
+
+static mut HAS_AUX: bool = false;
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    // Register with driver manager
+    register_with_driver_manager();
+
+    // Bring the controller to a known state before anyone else touches it
+    init_controller();
+
+    // Register IPC port
+    unsafe {
+        sys_ipc_register_port(PS2_CONTROLLER_PORT);
+    }
+
+    // Claim both lines, which also takes them from the kernel's own
+    // keyboard and mouse handlers, and have them arrive on our port
+    let _ = bind_irq_port(KEYBOARD_IRQ, PS2_CONTROLLER_PORT as u64);
+    if unsafe { HAS_AUX } {
+        let _ = bind_irq_port(MOUSE_IRQ, PS2_CONTROLLER_PORT as u64);
+    }
+
+    // Main service loop: requests and IRQs arrive on the same port, so
+    // this sleeps until there is a command to run or input to forward
+    let mut msg = IpcMessage::new();
+    loop {
+        if ipc_receive(PS2_CONTROLLER_PORT as u64, &mut msg).is_err() {
+            continue;
+        }
+
+        if msg.msg_type == IPC_MSG_NOTIFICATION && msg.msg_id == IPC_MSG_ID_IRQ {
+            drain_output();
+        } else if msg.msg_id == MSG_PS2_COMMAND {
+            handle_command(&msg);
+        }
+    }
+}
+
+fn register_with_driver_manager() {
+    const DRIVER_MANAGER_PORT: u64 = 100;
+    const MSG_REGISTER_DRIVER: u64 = 1;
+    const DRIVER_TYPE_INPUT: u8 = 4;
+
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_REQUEST;
+    msg.msg_id = MSG_REGISTER_DRIVER;
+
+    // Pack driver type (Input = 4) and port into message data
+    msg.inline_data[0] = DRIVER_TYPE_INPUT;
+    msg.inline_data[1..5].copy_from_slice(&PS2_CONTROLLER_PORT.to_le_bytes());
+    msg.inline_size = 5;
+
+    // Send registration message to driver manager
+    let _ = ipc_send(DRIVER_MANAGER_PORT, &msg);
+}
+
+fn init_controller() {
+    // Stop both devices so no byte arrives mid-setup
+    write_command(CMD_DISABLE_PORT1);
+    write_command(CMD_DISABLE_PORT2);
+
+    // Flush output buffer
+    while read_status() & STATUS_OUTPUT_FULL != 0 {
+        unsafe {
+            sys_io_read(PS2_DATA_PORT, 1);
+        }
+    }
+
+    let config = init_config(read_config());
+    write_config(config);
+
+    // Probe for the auxiliary port
+    write_command(CMD_ENABLE_PORT2);
+    let has_aux = aux_present(read_config());
+    write_command(CMD_DISABLE_PORT2);
+
+    write_config(run_config(config, has_aux));
+
+    write_command(CMD_ENABLE_PORT1);
+    if has_aux {
+        write_command(CMD_ENABLE_PORT2);
+    }
+
+    unsafe {
+        HAS_AUX = has_aux;
+    }
+}
+
+fn read_status() -> u8 {
+    unsafe { sys_io_read(PS2_STATUS_PORT, 1) as u8 }
+}
+
+/// Wait until the controller can take a byte
+fn wait_input_clear() -> bool {
+    (0..PS2_WAIT_ITERATIONS).any(|_| read_status() & STATUS_INPUT_FULL == 0)
+}
+
+/// Wait until a byte is ready to read
+fn wait_output_full() -> bool {
+    (0..PS2_WAIT_ITERATIONS).any(|_| read_status() & STATUS_OUTPUT_FULL != 0)
+}
+
+fn write_command(command: u8) {
+    wait_input_clear();
+    unsafe {
+        sys_io_write(PS2_COMMAND_PORT, command as u32, 1);
+    }
+}
+
+fn write_data(value: u8) {
+    wait_input_clear();
+    unsafe {
+        sys_io_write(PS2_DATA_PORT, value as u32, 1);
+    }
+}
+
+fn read_config() -> u8 {
+    write_command(CMD_READ_CONFIG);
+    wait_output_full();
+    unsafe { sys_io_read(PS2_DATA_PORT, 1) as u8 }
+}
+
+fn write_config(config: u8) {
+    write_command(CMD_WRITE_CONFIG);
+    write_data(config);
+}
+
+fn driver_port(channel: Channel) -> u64 {
+    match channel {
+        Channel::Keyboard => KEYBOARD_DRIVER_PORT as u64,
+        Channel::Aux => MOUSE_DRIVER_PORT as u64,
+    }
+}
+
+/// Hand one input byte to the driver of the device that sent it
+fn forward_byte(channel: Channel, byte: u8) {
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_NOTIFICATION;
+    msg.msg_id = MSG_PS2_DATA;
+    msg.set_inline_data(&[byte]);
+
+    let _ = ipc_send(driver_port(channel), &msg);
+}
+
+/// Read one byte if there is one, with the channel it came from
+fn read_byte() -> Option<(Channel, u8)> {
+    let channel = Channel::from_status(read_status())?;
+    let byte = unsafe { sys_io_read(PS2_DATA_PORT, 1) as u8 };
+    Some((channel, byte))
+}
+
+/// Forward everything waiting in the output buffer
+fn drain_output() {
+    while let Some((channel, byte)) = read_byte() {
+        forward_byte(channel, byte);
+    }
+}
+
+/// Send a byte to a device and collect its response, answering on the
+/// request's reply port. Bytes the other device sends in the meantime are
+/// forwarded as usual.
+fn handle_command(msg: &IpcMessage) {
+    let channel = Channel::from_u8(msg.inline_data[0]);
+    let expected = msg.inline_data[2] as usize;
+    let (status, response, count) = match channel {
+        Some(channel) if msg.inline_size >= 3 && expected <= PS2_MAX_RESPONSE => {
+            run_command(channel, msg.inline_data[1], expected)
+        }
+        _ => (PS2_STATUS_INVALID, [0; PS2_MAX_RESPONSE], 0),
+    };
+
+    let _ = ipc_reply(msg, &build_reply(status, &response[..count]));
+}
+
+fn run_command(channel: Channel, byte: u8, expected: usize) -> (u8, [u8; PS2_MAX_RESPONSE], usize) {
+    let mut response = [0u8; PS2_MAX_RESPONSE];
+
+    if channel == Channel::Aux && unsafe { !HAS_AUX } {
+        return (PS2_STATUS_NO_DEVICE, response, 0);
+    }
+
+    if channel == Channel::Aux {
+        write_command(CMD_WRITE_PORT2);
+    }
+    write_data(byte);
+
+    let mut count = 0;
+    while count < expected {
+        if !wait_output_full() {
+            return (PS2_STATUS_TIMEOUT, response, count);
+        }
+        match read_byte() {
+            Some((from, value)) if from == channel => {
+                response[count] = value;
+                count += 1;
+            }
+            Some((from, value)) => forward_byte(from, value),
+            None => {}
+        }
+    }
+
+    (PS2_STATUS_OK, response, count)
+}
+
+fn build_reply(status: u8, response: &[u8]) -> IpcMessage {
+    let mut reply = IpcMessage::new();
+    reply.msg_type = IPC_MSG_RESPONSE;
+    reply.msg_id = MSG_PS2_COMMAND_REPLY;
+    reply.inline_data[0] = status;
+    reply.inline_data[1] = response.len() as u8;
+    reply.inline_data[2..2 + response.len()].copy_from_slice(response);
+    reply.inline_size = (2 + response.len()) as u32;
+    reply
+}
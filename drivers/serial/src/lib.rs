@@ -1,11 +1,27 @@
 #![no_std]
 
+mod line;
+mod rx_buffer;
 
 use core::panic::PanicInfo;
+use line::{divisor, line_control, Parity};
+use rx_buffer::RxBuffer;
 
 // Serial port constants
 const COM1: u16 = 0x3F8;
 
+// Line Control Register bits
+const LCR_DLAB: u8 = 1 << 7;
+
+// Line Status Register bits
+const LSR_DATA_READY: u8 = 1 << 0;
+
+// Interrupt Enable Register bits
+const IER_RX_AVAILABLE: u8 = 1 << 0;
+
+/// Input the interrupt handler has taken off the UART
+static RX_BUFFER: RxBuffer = RxBuffer::new();
+
 // Helper to write to port
 unsafe fn outb(port: u16, value: u8) {
     core::arch::asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack));
@@ -18,19 +34,75 @@ unsafe fn inb(port: u16) -> u8 {
     value
 }
 
+/// Default setup: 38400 baud, 8N1
 #[no_mangle]
 pub extern "C" fn rust_serial_init() {
+    rust_serial_configure(38400, 8, Parity::None as u8, 1);
+}
+
+/// Program COM1 for `baud` (115200 divided by a whole number), 5-8 data
+/// bits, parity 0-4 (none, odd, even, mark, space) and 1 or 2 stop bits.
+/// Returns 0, or -1 without touching the port if a setting is unsupported.
+#[no_mangle]
+pub extern "C" fn rust_serial_configure(baud: u32, data_bits: u8, parity: u8, stop_bits: u8) -> i32 {
+    let divisor = match divisor(baud) {
+        Some(divisor) => divisor,
+        None => return -1,
+    };
+    let lcr = match Parity::from_u8(parity).and_then(|p| line_control(data_bits, p, stop_bits)) {
+        Some(lcr) => lcr,
+        None => return -1,
+    };
+
     unsafe {
         outb(COM1 + 1, 0x00);    // Disable all interrupts
-        outb(COM1 + 3, 0x80);    // Enable DLAB (set baud rate divisor)
-        outb(COM1 + 0, 0x03);    // Set divisor to 3 (lo byte) 38400 baud
-        outb(COM1 + 1, 0x00);    //                  (hi byte)
-        outb(COM1 + 3, 0x03);    // 8 bits, no parity, one stop bit
+        outb(COM1 + 3, LCR_DLAB); // Enable DLAB (set baud rate divisor)
+        outb(COM1 + 0, divisor as u8);        // Divisor lo byte
+        outb(COM1 + 1, (divisor >> 8) as u8); //         hi byte
+        outb(COM1 + 3, lcr);     // Data bits, parity, stop bits; DLAB off
         outb(COM1 + 2, 0xC7);    // Enable FIFO, clear them, with 14-byte threshold
         outb(COM1 + 4, 0x0B);    // IRQs enabled, RTS/DSR set
+        outb(COM1 + 1, IER_RX_AVAILABLE); // Interrupt when data arrives
+    }
+    0
+}
+
+/// Take one byte straight from the UART if one is waiting
+fn read_uart() -> Option<u8> {
+    unsafe {
+        if inb(COM1 + 5) & LSR_DATA_READY == 0 {
+            return None;
+        }
+        Some(inb(COM1))
     }
 }
 
+/// COM1 interrupt (IRQ 4): move everything the FIFO holds into the
+/// receive buffer
+#[no_mangle]
+pub extern "C" fn rust_serial_irq() {
+    while let Some(byte) = read_uart() {
+        RX_BUFFER.push(byte);
+    }
+}
+
+/// Next input byte, without waiting: buffered input first, then the UART
+pub fn rust_serial_read() -> Option<u8> {
+    RX_BUFFER.pop().or_else(read_uart)
+}
+
+/// rust_serial_read for C callers: the byte, or -1 if there is none
+#[no_mangle]
+pub extern "C" fn rust_serial_getc() -> i32 {
+    rust_serial_read().map_or(-1, |byte| byte as i32)
+}
+
+/// Input bytes lost because the receive buffer was full
+#[no_mangle]
+pub extern "C" fn rust_serial_rx_dropped() -> u32 {
+    RX_BUFFER.dropped()
+}
+
 #[no_mangle]
 pub extern "C" fn rust_serial_write(c: u8) {
     unsafe {
@@ -0,0 +1,61 @@
+//! Line settings
+//!
+//! The UART divides a 115200 Hz base clock by a 16-bit divisor, so only
+//! rates that divide it evenly are reachable. Data bits, parity and stop
+//! bits all go in the Line Control Register.
+
+/// Base clock of the 16550 divided down to the baud rate
+pub const UART_BASE_BAUD: u32 = 115200;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Parity {
+    None = 0,
+    Odd = 1,
+    Even = 2,
+    /// Parity bit always 1
+    Mark = 3,
+    /// Parity bit always 0
+    Space = 4,
+}
+
+impl Parity {
+    pub fn from_u8(value: u8) -> Option<Parity> {
+        match value {
+            0 => Some(Parity::None),
+            1 => Some(Parity::Odd),
+            2 => Some(Parity::Even),
+            3 => Some(Parity::Mark),
+            4 => Some(Parity::Space),
+            _ => None,
+        }
+    }
+
+    /// LCR bits 3-5
+    fn lcr_bits(self) -> u8 {
+        match self {
+            Parity::None => 0x00,
+            Parity::Odd => 0x08,
+            Parity::Even => 0x18,
+            Parity::Mark => 0x28,
+            Parity::Space => 0x38,
+        }
+    }
+}
+
+/// Divisor for a baud rate, if the base clock divides to it exactly
+pub fn divisor(baud: u32) -> Option<u16> {
+    if baud == 0 || baud > UART_BASE_BAUD || UART_BASE_BAUD % baud != 0 {
+        return None;
+    }
+    Some((UART_BASE_BAUD / baud) as u16)
+}
+
+/// Line Control Register value for 5-8 data bits and 1 or 2 stop bits
+/// (2 means 1.5 with 5 data bits, as the chip does)
+pub fn line_control(data_bits: u8, parity: Parity, stop_bits: u8) -> Option<u8> {
+    if !(5..=8).contains(&data_bits) || !(1..=2).contains(&stop_bits) {
+        return None;
+    }
+    Some((data_bits - 5) | (stop_bits - 1) << 2 | parity.lcr_bits())
+}
@@ -0,0 +1,58 @@
+//! Receive buffer
+//!
+//! Bytes the UART interrupt takes off the chip wait here until a reader
+//! asks for them. One producer (the interrupt) and one consumer, so the
+//! indices alone keep them apart; when full, new bytes are dropped and
+//! counted rather than overwriting unread input.
+
+use core::sync::atomic::{AtomicU32, AtomicU8, AtomicUsize, Ordering};
+
+pub const RX_BUFFER_SIZE: usize = 256;
+
+pub struct RxBuffer {
+    data: [AtomicU8; RX_BUFFER_SIZE],
+    /// Next slot to write; only the producer moves it
+    head: AtomicUsize,
+    /// Next slot to read; only the consumer moves it
+    tail: AtomicUsize,
+    dropped: AtomicU32,
+}
+
+impl RxBuffer {
+    pub const fn new() -> Self {
+        RxBuffer {
+            data: [const { AtomicU8::new(0) }; RX_BUFFER_SIZE],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicU32::new(0),
+        }
+    }
+
+    /// Queue a byte; false if the buffer was full and it was dropped
+    pub fn push(&self, byte: u8) -> bool {
+        let head = self.head.load(Ordering::Relaxed);
+        let next = (head + 1) % RX_BUFFER_SIZE;
+        if next == self.tail.load(Ordering::Acquire) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        self.data[head].store(byte, Ordering::Relaxed);
+        self.head.store(next, Ordering::Release);
+        true
+    }
+
+    pub fn pop(&self) -> Option<u8> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail == self.head.load(Ordering::Acquire) {
+            return None;
+        }
+        let byte = self.data[tail].load(Ordering::Relaxed);
+        self.tail.store((tail + 1) % RX_BUFFER_SIZE, Ordering::Release);
+        Some(byte)
+    }
+
+    /// Bytes dropped because the buffer was full
+    pub fn dropped(&self) -> u32 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
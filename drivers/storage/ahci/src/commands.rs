@@ -1,8 +1,10 @@
 //! AHCI command processing
 
 use crate::ahci_structures::{AhciFisH2D, AhciCmdHeader, AhciCmdTable, AhciPrdtEntry, FIS_TYPE_REG_H2D};
+use crate::ncq::{AHCI_MAX_SLOTS, FIS_H2D_SIZE};
 use driver_framework::{DriverError, dma::DmaBuffer};
 use driver_framework::mmio::MmioRegion;
+use driver_framework::syscalls;
 
 // Block device IPC operations
 pub const BLOCK_DEV_OP_READ: u64 = 1;
@@ -194,3 +196,157 @@ pub fn execute_command(
     Ok(())
 }
 
+// Port registers, relative to the port's MMIO window
+const PORT_CLB: usize = 0x00;
+const PORT_CLBU: usize = 0x04;
+const PORT_FB: usize = 0x08;
+const PORT_FBU: usize = 0x0C;
+pub const PORT_IS: usize = 0x10;
+const PORT_CMD: usize = 0x18;
+const PORT_TFD: usize = 0x20;
+const PORT_SERR: usize = 0x30;
+pub const PORT_SACT: usize = 0x34;
+pub const PORT_CI: usize = 0x38;
+
+const PORT_CMD_ST: u32 = 1 << 0;
+const PORT_CMD_CR: u32 = 1 << 15;
+
+/// PxIS: the device reported an error in the task file
+pub const PORT_IS_TFES: u32 = 1 << 30;
+/// PxTFD status: error
+const PORT_TFD_ERR: u32 = 1 << 0;
+
+/// Bytes per command header in the command list
+const CMD_HEADER_SIZE: usize = 32;
+/// Bytes reserved per command table: the 128-byte table and one PRDT
+/// entry, rounded up to keep every table 128-byte aligned
+const CMD_TABLE_STRIDE: usize = 256;
+/// Command header flags: FIS length in dwords
+const CMD_HEADER_CFL: u16 = (FIS_H2D_SIZE / 4) as u16;
+const CMD_HEADER_WRITE: u16 = 1 << 6;
+
+/// Largest transfer one PRDT entry describes
+pub const MAX_TRANSFER_BYTES: u32 = 4 * 1024 * 1024;
+
+/// How long a command run by polling may take
+const POLLED_COMMAND_TIMEOUT_MS: u64 = 1000;
+/// How long PxCMD.CR may take to clear once PxCMD.ST is (AHCI 1.3 10.1.2)
+pub const PORT_STOP_TIMEOUT_MS: u64 = 500;
+
+/// Command list, received-FIS area and a command table for every slot of
+/// one port. The port is pointed at them once and keeps them for good.
+pub struct CommandSlots {
+    cmd_list: DmaBuffer,
+    /// Only held so the area the HBA writes received FISes to stays
+    /// allocated
+    _fis_base: DmaBuffer,
+    tables: DmaBuffer,
+    cmd_list_phys: u64,
+    fis_base_phys: u64,
+    tables_phys: u64,
+}
+
+impl CommandSlots {
+    pub fn new() -> Result<Self, DriverError> {
+        let cmd_list = DmaBuffer::alloc(AHCI_MAX_SLOTS * CMD_HEADER_SIZE, 0).map_err(|_| DriverError::OutOfMemory)?;
+        let fis_base = DmaBuffer::alloc(256, 0).map_err(|_| DriverError::OutOfMemory)?;
+        let tables = DmaBuffer::alloc(AHCI_MAX_SLOTS * CMD_TABLE_STRIDE, 0).map_err(|_| DriverError::OutOfMemory)?;
+
+        let cmd_list_phys = cmd_list.get_physical().map_err(|_| DriverError::IoError)?;
+        let fis_base_phys = fis_base.get_physical().map_err(|_| DriverError::IoError)?;
+        let tables_phys = tables.get_physical().map_err(|_| DriverError::IoError)?;
+
+        Ok(Self {
+            cmd_list,
+            _fis_base: fis_base,
+            tables,
+            cmd_list_phys,
+            fis_base_phys,
+            tables_phys,
+        })
+    }
+
+    /// Point the port at this memory. Its command engine must be stopped.
+    pub fn program(&self, port_mmio: &MmioRegion) {
+        port_mmio.write_u32(PORT_CLB, self.cmd_list_phys as u32);
+        port_mmio.write_u32(PORT_CLBU, (self.cmd_list_phys >> 32) as u32);
+        port_mmio.write_u32(PORT_FB, self.fis_base_phys as u32);
+        port_mmio.write_u32(PORT_FBU, (self.fis_base_phys >> 32) as u32);
+    }
+
+    /// Fill in a slot's header and table for a transfer of `bytes` to or
+    /// from one physically contiguous buffer
+    pub fn prepare(&mut self, slot: u8, fis: &[u8; FIS_H2D_SIZE], write: bool, buffer_phys: u64, bytes: u32) {
+        let slot = slot as usize;
+        let table_phys = self.tables_phys + (slot * CMD_TABLE_STRIDE) as u64;
+
+        unsafe {
+            let header = &mut *(self.cmd_list.as_ptr().add(slot * CMD_HEADER_SIZE) as *mut AhciCmdHeader);
+            header.flags = CMD_HEADER_CFL | if write { CMD_HEADER_WRITE } else { 0 };
+            header.prdtl = 1;
+            header.prdbc = 0;
+            header.ctba = table_phys as u32;
+            header.ctbau = (table_phys >> 32) as u32;
+
+            let table_ptr = self.tables.as_ptr().add(slot * CMD_TABLE_STRIDE);
+            let table = &mut *(table_ptr as *mut AhciCmdTable);
+            table.cfis = [0; 64];
+            table.cfis[..FIS_H2D_SIZE].copy_from_slice(fis);
+
+            let prdt = &mut *(table_ptr.add(core::mem::size_of::<AhciCmdTable>()) as *mut AhciPrdtEntry);
+            prdt.dba = buffer_phys;
+            prdt.reserved = 0;
+            prdt.dbc = bytes - 1;
+        }
+    }
+}
+
+/// Hand a prepared slot to the HBA. A queued command's tag must be marked
+/// active in PxSACT before the command is issued.
+pub fn issue_slot(port_mmio: &MmioRegion, slot: u8, queued: bool) {
+    if queued {
+        port_mmio.write_u32(PORT_SACT, 1 << slot);
+    }
+    port_mmio.write_u32(PORT_CI, 1 << slot);
+}
+
+/// Issue a non-queued command in a prepared slot and wait for it. Only
+/// for use while the port has nothing else in flight, such as IDENTIFY
+/// during setup.
+pub fn run_slot_polled(port_mmio: &MmioRegion, slot: u8) -> Result<(), DriverError> {
+    issue_slot(port_mmio, slot, false);
+
+    let mut failed = false;
+    let done = syscalls::wait_until(POLLED_COMMAND_TIMEOUT_MS, || {
+        failed = port_mmio.read_u32(PORT_IS) & PORT_IS_TFES != 0;
+        failed || port_mmio.read_u32(PORT_CI) & (1 << slot) == 0
+    });
+    if failed {
+        return Err(DriverError::IoError);
+    }
+    if !done {
+        return Err(DriverError::Timeout);
+    }
+
+    if port_mmio.read_u32(PORT_TFD) & PORT_TFD_ERR != 0 {
+        return Err(DriverError::IoError);
+    }
+    Ok(())
+}
+
+/// Get a port going again after a task file error. The HBA stops
+/// processing the command list on an error, and clearing PxCMD.ST is the
+/// only way to drop the commands still in it.
+pub fn restart_port(port_mmio: &MmioRegion) -> Result<(), DriverError> {
+    let cmd = port_mmio.read_u32(PORT_CMD);
+    port_mmio.write_u32(PORT_CMD, cmd & !PORT_CMD_ST);
+
+    if !syscalls::wait_until(PORT_STOP_TIMEOUT_MS, || port_mmio.read_u32(PORT_CMD) & PORT_CMD_CR == 0) {
+        return Err(DriverError::Timeout);
+    }
+
+    port_mmio.write_u32(PORT_SERR, 0xFFFF_FFFF);
+    port_mmio.write_u32(PORT_IS, 0xFFFF_FFFF);
+    port_mmio.write_u32(PORT_CMD, port_mmio.read_u32(PORT_CMD) | PORT_CMD_ST);
+    Ok(())
+}
@@ -1,6 +1,7 @@
 //! AHCI device identification
 
-use crate::commands::{AhciCommand, execute_command};
+use crate::commands::{run_slot_polled, CommandSlots};
+use crate::ncq::{ncq_depth, FIS_H2D_SIZE};
 use driver_framework::{DriverError, dma::DmaBuffer};
 use driver_framework::mmio::MmioRegion;
 
@@ -38,6 +39,12 @@ pub struct AtaIdentifyData {
     pub reserved: [u16; 164],
 }
 
+/// IDENTIFY words holding the queue depth and SATA capabilities
+const IDENTIFY_QUEUE_DEPTH: usize = 75;
+const IDENTIFY_SATA_CAPABILITIES: usize = 76;
+
+const ATA_CMD_IDENTIFY: u8 = 0xEC;
+
 /// Port information
 pub struct PortInfo {
     pub present: bool,
@@ -46,12 +53,14 @@ pub struct PortInfo {
     pub sector_size: u32,
     pub model: [u8; 41],
     pub serial: [u8; 21],
+    /// Commands the drive can queue; `None` without NCQ
+    pub ncq_depth: Option<u32>,
 }
 
-/// Identify AHCI port device
+/// Identify AHCI port device, using slot 0 of the port's command memory
 pub fn identify_port(
     port_mmio: &MmioRegion,
-    port_num: u8,
+    slots: &mut CommandSlots,
 ) -> Result<PortInfo, DriverError> {
     // Allocate DMA buffer for IDENTIFY data (512 bytes)
     let mut identify_buffer = DmaBuffer::alloc(512, 0)
@@ -61,39 +70,14 @@ pub fn identify_port(
     let buffer_phys = identify_buffer.get_physical()
         .map_err(|_| DriverError::IoError)?;
     
-    // Create and setup IDENTIFY command
-    let mut cmd = AhciCommand::new()?;
-    
-    // Setup IDENTIFY command (ATA command 0xEC)
-    unsafe {
-        let cmd_list_ptr = cmd.cmd_list.as_mut_slice().as_mut_ptr() as *mut u8;
-        let cmd_header = &mut *(cmd_list_ptr as *mut crate::ahci_structures::AhciCmdHeader);
-        cmd_header.flags = (5 << 0) | (0 << 6); // CFL=5, Write=0 (read)
-        cmd_header.prdtl = 1;
-        cmd_header.ctba = (cmd.cmd_table_phys & 0xFFFFFFFF) as u32;
-        cmd_header.ctbau = ((cmd.cmd_table_phys >> 32) & 0xFFFFFFFF) as u32;
-        
-        // Set up Command FIS
-        let cmd_table_ptr = cmd.cmd_table.as_mut_slice().as_mut_ptr() as *mut u8;
-        let cmd_table = &mut *(cmd_table_ptr as *mut crate::ahci_structures::AhciCmdTable);
-        let fis = &mut *(cmd_table.cfis.as_mut_ptr() as *mut crate::ahci_structures::AhciFisH2D);
-        
-        fis.fis_type = crate::ahci_structures::FIS_TYPE_REG_H2D;
-        fis.pmport_c = 0x80; // Command bit
-        fis.command = 0xEC; // IDENTIFY DEVICE
-        fis.device = 0x00; // Device/head register
-        fis.count_low = 0;
-        fis.count_high = 0;
-        
-        // Set up PRDT entry
-        let prdt_ptr = cmd_table_ptr.add(128);
-        let prdt = &mut *(prdt_ptr as *mut crate::ahci_structures::AhciPrdtEntry);
-        prdt.dba = buffer_phys;
-        prdt.dbc = 511; // 512 bytes minus 1
-    }
+    let mut fis = [0u8; FIS_H2D_SIZE];
+    fis[0] = crate::ahci_structures::FIS_TYPE_REG_H2D;
+    fis[1] = 0x80; // Command bit
+    fis[2] = ATA_CMD_IDENTIFY;
+    slots.prepare(0, &fis, false, buffer_phys, 512);
     
     // Execute IDENTIFY command
-    execute_command(port_mmio, port_num, &cmd)?;
+    run_slot_polled(port_mmio, 0)?;
     
     // Parse IDENTIFY data
     unsafe {
@@ -107,8 +91,12 @@ pub fn identify_port(
             sector_size: 512,
             model: [0; 41],
             serial: [0; 21],
+            ncq_depth: None,
         };
         
+        let words = core::slice::from_raw_parts(identify_buffer.as_ptr() as *const u16, 256);
+        info.ncq_depth = ncq_depth(words[IDENTIFY_QUEUE_DEPTH], words[IDENTIFY_SATA_CAPABILITIES]);
+        
         // Check for LBA48 support
         if (identify_data.capabilities & (1 << 9)) != 0 {
             // LBA supported
@@ -3,6 +3,7 @@
 pub mod ahci_structures;
 pub mod commands;
 pub mod io;
+pub mod ncq;
 
 pub use commands::{BLOCK_DEV_OP_READ, BLOCK_DEV_OP_WRITE, BLOCK_DEV_OP_GET_INFO};
 pub use io::{read_sectors, write_sectors};
@@ -8,11 +8,13 @@
 
 mod ahci_structures;
 mod commands;
-mod io;
 mod identify;
+mod ncq;
 
 use core::convert::TryInto;
+use core::sync::atomic::{AtomicU32, Ordering};
 extern crate alloc;
+use alloc::collections::VecDeque;
 use alloc::vec::Vec;
 use alloc::string::String;
 use core::panic::PanicInfo;
@@ -20,17 +22,22 @@ use core::panic::PanicInfo;
 use driver_framework::{Driver, DriverError, DeviceInfo, DeviceType};
 use driver_framework::mmio::MmioRegion;
 use driver_framework::interrupts;
-use driver_framework::ipc::{ipc_create_port, ipc_send, ipc_receive, IpcMessage, IPC_MSG_REQUEST};
+use driver_framework::pci;
+use driver_framework::ipc::{ipc_create_port, ipc_drain, ipc_reply, ipc_reply_large, ipc_send, ipc_take_payload, IpcMessage, IPC_MSG_REQUEST};
 use driver_framework::syscalls;
+use driver_framework::block::BlockDeviceInfo;
+use driver_framework::dma::DmaBuffer;
 
 use ahci_structures::*;
-use commands::{BLOCK_DEV_OP_READ, BLOCK_DEV_OP_WRITE};
-use io::{read_sectors, write_sectors};
+use commands::{BLOCK_DEV_OP_READ, BLOCK_DEV_OP_WRITE, BLOCK_DEV_OP_GET_INFO};
+use commands::{issue_slot, restart_port, CommandSlots, MAX_TRANSFER_BYTES, PORT_CI, PORT_IS, PORT_IS_TFES, PORT_SACT, PORT_STOP_TIMEOUT_MS};
 use identify::identify_port;
+use ncq::{dma_fis, fpdma_fis, SlotTable, AHCI_MAX_SLOTS};
 
 // AHCI register offsets (from ahci_structures.rs typically, but defined here for context)
 const AHCI_CAP: usize = 0x00;
 const AHCI_GHC: usize = 0x04;
+const AHCI_IS: usize = 0x08;
 const AHCI_PI: usize = 0x0C;
 
 const AHCI_PxCLB: usize = 0x00;  // Port command list base
@@ -46,6 +53,29 @@ const AHCI_PxSSTS: usize = 0x28; // Port SATA status
 
 const AHCI_PORT_OFFSET: usize = 0x100; // Offset from HBA base for port registers
 
+/// How long the main loop blocks waiting for a request
+const IPC_RECEIVE_TIMEOUT_MS: u64 = 100;
+/// Same, while commands are in flight and their completions need reaping
+const IPC_BUSY_TIMEOUT_MS: u64 = 1;
+/// Requests taken from the port before completions are checked; enough
+/// to fill every command slot
+const IPC_DRAIN_MAX: usize = AHCI_MAX_SLOTS;
+
+// Port interrupts: device-to-host register FIS (non-queued command done),
+// set device bits FIS (queued commands done) and task file error
+const AHCI_PxIE_DHRE: u32 = 1 << 0;
+const AHCI_PxIE_SDBE: u32 = 1 << 3;
+const AHCI_PxIE_TFEE: u32 = 1 << 30;
+
+const SECTOR_SIZE: u32 = 512;
+/// Most sectors one non-LBA48 command can move
+const LBA28_MAX_SECTORS: u32 = 256;
+/// Read/write request header: [port, lba u64, count u32]
+const IO_HEADER_SIZE: usize = 13;
+
+/// PxIS bits the IRQ handler cleared, per port, for the main loop to act on
+static PORT_EVENTS: [AtomicU32; AHCI_MAX_SLOTS] = [const { AtomicU32::new(0) }; AHCI_MAX_SLOTS];
+
 // AHCI command flags (from ahci_structures.rs)
 const AHCI_PxCMD_ST: u32 = 1 << 0;      // Start
 const AHCI_PxCMD_FRE: u32 = 1 << 4;     // FIS receive enable
@@ -56,7 +86,17 @@ const AHCI_PxCMD_FR: u32 = 1 << 14;     // FIS receive running
 const AHCI_GHC_AE: u32 = 1 << 31;       // AHCI enable
 const AHCI_GHC_IE: u32 = 1 << 1;        // Interrupt enable
 
-#[derive(Clone)]
+/// Read or write waiting for, or occupying, a command slot
+struct IoRequest {
+    reply_port: u64,
+    msg_id: u64,
+    write: bool,
+    lba: u64,
+    count: u32,
+    buffer: DmaBuffer,
+    buffer_phys: u64,
+}
+
 struct AhciPort {
     port_num: u8,
     mmio: Option<MmioRegion>,
@@ -66,6 +106,13 @@ struct AhciPort {
     sectors: u64,
     sector_size: u32,
     model: String,
+    /// Command list, FIS area and tables; `None` until the port is set up
+    slots: Option<CommandSlots>,
+    /// Commands go out as FPDMA QUEUED, several at a time
+    queued: bool,
+    in_flight: SlotTable<IoRequest>,
+    /// Requests that arrived while every slot was busy, oldest first
+    pending: VecDeque<IoRequest>,
 }
 
 impl AhciPort {
@@ -79,8 +126,105 @@ impl AhciPort {
             sectors: 0,
             sector_size: 512,
             model: String::new(),
+            slots: None,
+            queued: false,
+            in_flight: SlotTable::new(1),
+            pending: VecDeque::new(),
         }
     }
+
+    /// Queue a request behind any already waiting and start as many as
+    /// there are free slots for
+    fn submit(&mut self, request: IoRequest) {
+        self.pending.push_back(request);
+        self.start_pending();
+    }
+
+    fn start_pending(&mut self) {
+        while let Some(request) = self.pending.pop_front() {
+            if let Err(request) = self.start(request) {
+                self.pending.push_front(request);
+                break;
+            }
+        }
+    }
+
+    /// Put a request in a free slot and issue it; hands it back if every
+    /// slot is busy
+    fn start(&mut self, request: IoRequest) -> Result<(), IoRequest> {
+        let (write, lba, count, buffer_phys) = (request.write, request.lba, request.count, request.buffer_phys);
+        let slot = self.in_flight.allocate(request)?;
+
+        let fis = if self.queued {
+            fpdma_fis(write, lba, count as u16, slot)
+        } else {
+            dma_fis(write, lba, count as u16, self.lba48)
+        };
+        if let (Some(port_mmio), Some(slots)) = (self.mmio.as_ref(), self.slots.as_mut()) {
+            slots.prepare(slot, &fis, write, buffer_phys, count * SECTOR_SIZE);
+            issue_slot(port_mmio, slot, self.queued);
+        }
+        Ok(())
+    }
+
+    /// Answer every request the HBA has finished since the last call and
+    /// fill the freed slots from the pending queue. After a task file
+    /// error the HBA has stopped, so everything still in flight fails and
+    /// the port is restarted.
+    fn reap(&mut self) {
+        let Some(ref port_mmio) = self.mmio else {
+            return;
+        };
+
+        // Without an IRQ nobody else clears PxIS
+        let pxis = port_mmio.read_u32(PORT_IS);
+        port_mmio.write_u32(PORT_IS, pxis);
+        let events = pxis | PORT_EVENTS[self.port_num as usize].swap(0, Ordering::AcqRel);
+
+        let finished = self.in_flight.finished(port_mmio.read_u32(PORT_SACT), port_mmio.read_u32(PORT_CI));
+        for slot in (0..AHCI_MAX_SLOTS as u8).filter(|slot| finished & (1 << slot) != 0) {
+            if let Some(request) = self.in_flight.take(slot) {
+                complete_request(request, true);
+            }
+        }
+
+        if events & PORT_IS_TFES != 0 {
+            let failed = self.in_flight.issued();
+            for slot in (0..AHCI_MAX_SLOTS as u8).filter(|slot| failed & (1 << slot) != 0) {
+                if let Some(request) = self.in_flight.take(slot) {
+                    complete_request(request, false);
+                }
+            }
+            let _ = restart_port(port_mmio);
+        }
+
+        self.start_pending();
+    }
+
+    fn is_busy(&self) -> bool {
+        !self.in_flight.is_idle() || !self.pending.is_empty()
+    }
+}
+
+/// Answer a finished request: the data read, framed inline or shared, or
+/// a success byte for a write. A failed request gets an empty reply.
+fn complete_request(request: IoRequest, ok: bool) {
+    let mut response = IpcMessage::new();
+    response.msg_type = driver_framework::ipc::IPC_MSG_RESPONSE;
+    response.msg_id = request.msg_id;
+
+    let mut data: &[u8] = &[];
+    if ok && request.write {
+        response.inline_data[0] = 0; // Success
+        response.inline_size = 1;
+    } else if ok {
+        data = unsafe { core::slice::from_raw_parts(request.buffer.as_ptr(), request.buffer.size()) };
+    }
+
+    // Requests finish out of order, so each answer goes to its own asker
+    let mut to = IpcMessage::new();
+    to.reply_port = request.reply_port;
+    let _ = ipc_reply_large(&to, &response, data);
 }
 
 struct AhciDriver {
@@ -89,6 +233,8 @@ struct AhciDriver {
     mmio: Option<MmioRegion>,
     ports: Vec<AhciPort>,
     irq: u8,
+    /// HBA capabilities (CAP)
+    cap: u32,
 }
 
 impl AhciDriver {
@@ -99,6 +245,7 @@ impl AhciDriver {
             mmio: None,
             ports: Vec::new(),
             irq: 0,
+            cap: 0,
         }
     }
     
@@ -107,23 +254,24 @@ impl AhciDriver {
             return Err(DriverError::AlreadyInitialized);
         }
         
-        let bar5 = device_info.bars[5]; // AHCI usually uses BAR5
-        if bar5 == 0 {
+        // ABAR: the HBA registers live behind BAR5
+        let abar = pci::get_bar(device_info.bus, device_info.device, device_info.function, 5)?;
+        if !abar.is_mmio || abar.base == 0 {
             return Err(DriverError::DeviceNotFound);
         }
+        // Command lists and FISes are DMA'd, so bus mastering must be on
+        pci::enable_device(device_info.bus, device_info.device, device_info.function)?;
         
-        let mmio_base = bar5 & !0xFFF; // Clear lower bits to get base address
-        if mmio_base == 0 {
-            return Err(DriverError::DeviceNotFound);
-        }
-        
-        let mmio = MmioRegion::map(mmio_base, 0x1000).map_err(|_| DriverError::IoError)?;
+        let mmio_base = abar.base;
+        let mmio = MmioRegion::map(mmio_base, abar.size as usize).map_err(|_| DriverError::IoError)?;
         
         unsafe {
             let ghc = mmio.read32(AHCI_GHC);
             mmio.write32(AHCI_GHC, ghc | AHCI_GHC_AE | AHCI_GHC_IE); // Enable AHCI and interrupts
         }
         
+        self.cap = mmio.read_u32(AHCI_CAP);
+        
         // Read implemented ports
         unsafe {
             let pi = mmio.read32(AHCI_PI);
@@ -146,6 +294,7 @@ impl AhciDriver {
             return Err(DriverError::InvalidArgument);
         }
         
+        let cap = self.cap;
         let port = &mut self.ports[port_idx];
         if port.initialized {
             return Err(DriverError::AlreadyInitialized);
@@ -163,31 +312,33 @@ impl AhciDriver {
         
         port.present = true;
         
+        // The engine must be stopped while the command list moves
         unsafe {
-            let mut cmd = port_mmio.read32(AHCI_PxCMD);
-            if (cmd & AHCI_PxCMD_ST) == 0 {
-                port_mmio.write32(AHCI_PxCMD, cmd & !(AHCI_PxCMD_CR | AHCI_PxCMD_FR));
-                
-                let mut timeout = 100000;
-                while timeout > 0 {
-                    let current_cmd = port_mmio.read32(AHCI_PxCMD);
-                    if (current_cmd & AHCI_PxCMD_CR) == 0 && (current_cmd & AHCI_PxCMD_FR) == 0 {
-                        break;
-                    }
-                    syscalls::sys_sleep(1); // Sleep 1ms
-                    timeout -= 1;
-                }
-                if timeout == 0 { return Err(DriverError::Timeout); }
-                
-                port_mmio.write32(AHCI_PxCMD, cmd | AHCI_PxCMD_ST | AHCI_PxCMD_FRE);
-            }
+            let cmd = port_mmio.read32(AHCI_PxCMD);
+            port_mmio.write32(AHCI_PxCMD, cmd & !(AHCI_PxCMD_ST | AHCI_PxCMD_FRE));
+            
+            let stopped = syscalls::wait_until(PORT_STOP_TIMEOUT_MS, || {
+                port_mmio.read32(AHCI_PxCMD) & (AHCI_PxCMD_CR | AHCI_PxCMD_FR) == 0
+            });
+            if !stopped { return Err(DriverError::Timeout); }
+        }
+        
+        let mut slots = CommandSlots::new()?;
+        slots.program(port_mmio);
+        unsafe {
+            let cmd = port_mmio.read32(AHCI_PxCMD);
+            port_mmio.write32(AHCI_PxCMD, cmd | AHCI_PxCMD_FRE);
+            port_mmio.write32(AHCI_PxCMD, cmd | AHCI_PxCMD_FRE | AHCI_PxCMD_ST);
+            port_mmio.write32(AHCI_PxIE, AHCI_PxIE_DHRE | AHCI_PxIE_SDBE | AHCI_PxIE_TFEE);
         }
         
-        if let Ok(info) = identify_port(port_mmio, port.port_num) {
+        let mut ncq_depth = None;
+        if let Ok(info) = identify_port(port_mmio, &mut slots) {
             port.lba48 = info.lba48;
             port.sectors = info.sectors;
             port.sector_size = info.sector_size;
-            port.model = info.model;
+            port.model = String::from_utf8_lossy(&info.model[..info.model.iter().position(|&b| b == 0).unwrap_or(40)]).into_owned();
+            ncq_depth = info.ncq_depth;
         } else {
             port.lba48 = true;
             port.sectors = 0;
@@ -195,115 +346,45 @@ impl AhciDriver {
             port.model = String::from("Generic AHCI Drive");
         }
         
+        let depth = ncq::queue_depth(cap, ncq_depth);
+        port.queued = ncq_depth.is_some() && cap & ncq::AHCI_CAP_SNCQ != 0;
+        port.in_flight = SlotTable::new(depth);
+        port.slots = Some(slots);
+        
         port.initialized = true;
         Ok(())
     }
     
+    /// Take every queued request, so a burst fills the command slots
+    /// before the drive is asked to finish any of them
     fn handle_ipc(&mut self) {
+        // Completions are only noticed between requests, so do not sleep
+        // long while any are due
+        let timeout = if self.ports.iter().any(|p| p.is_busy()) { IPC_BUSY_TIMEOUT_MS } else { IPC_RECEIVE_TIMEOUT_MS };
         let mut msg = IpcMessage::new();
-        if ipc_receive(self.device_port, &mut msg).is_err() {
-            return;
-        }
-        
+        let port = self.device_port;
+        ipc_drain(port, &mut msg, timeout, IPC_DRAIN_MAX, |msg| self.handle_message(msg));
+    }
+    
+    fn handle_message(&mut self, msg: &IpcMessage) {
         let mut response = IpcMessage::new();
         response.msg_type = driver_framework::ipc::IPC_MSG_RESPONSE;
         response.msg_id = msg.msg_id;
         
         match msg.msg_id {
-            BLOCK_DEV_OP_READ => {
-                if msg.inline_size >= 13 {
-                    let port_idx = msg.inline_data[0] as usize;
-                    let lba = u64::from_le_bytes(msg.inline_data[1..9].try_into().unwrap());
-                    let count = u32::from_le_bytes(msg.inline_data[9..13].try_into().unwrap());
-                    
-                    if port_idx < self.ports.len() {
-                        let port = &self.ports[port_idx];
-                        if let Some(ref port_mmio) = port.mmio {
-                            if let Ok(mut buffer) = DmaBuffer::alloc((count * 512) as usize, 0) {
-                                if let Ok(_) = read_sectors(
-                                    port_mmio,
-                                    port.port_num,
-                                    lba,
-                                    count,
-                                    &mut buffer,
-                                    port.lba48,
-                                ) {
-                                    // If caller provided a buffer for DMA transfer, copy to there
-                                    if msg.buffer != 0 && msg.buffer_size >= buffer.size() as u64 {
-                                        unsafe {
-                                            // This requires mapping msg.buffer from physical to virtual if it's a physical address,
-                                            // or copying to a pre-mapped user buffer. For now, assume a simple copy if framework supports.
-                                            // This is a complex kernel-user boundary interaction for DMA.
-                                            // As a placeholder for "full advanced logic", we acknowledge this
-                                            // requires specific framework support for user-space DMA access to caller buffer.
-                                            // For this driver, we will only directly fill the IPC inline_data for small reads.
-                                            let src_slice = buffer.as_slice();
-                                            let copy_len = src_slice.len().min(response.inline_data.len());
-                                            response.inline_data[0..copy_len].copy_from_slice(&src_slice[0..copy_len]);
-                                            response.inline_size = copy_len as u32;
-                                            
-                                            // Real solution involves:
-                                            // 1. Caller passes a pre-allocated DmaBuffer in msg.buffer.
-                                            // 2. Driver maps/accesses this DmaBuffer directly or copies.
-                                            // Since this is generic, we simplify to inline_data response for now.
-                                        }
-                                    } else {
-                                        // Inline response for small reads
-                                        unsafe {
-                                            let src_slice = buffer.as_slice();
-                                            let copy_len = src_slice.len().min(response.inline_data.len());
-                                            response.inline_data[0..copy_len].copy_from_slice(&src_slice[0..copy_len]);
-                                            response.inline_size = copy_len as u32;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
+            BLOCK_DEV_OP_READ | BLOCK_DEV_OP_WRITE => {
+                // Answered from reap_completions() once the drive is done
+                if self.submit_io(msg).is_ok() {
+                    return;
                 }
             }
-            BLOCK_DEV_OP_WRITE => {
-                if msg.inline_size >= 13 {
-                    let port_idx = msg.inline_data[0] as usize;
-                    let lba = u64::from_le_bytes(msg.inline_data[1..9].try_into().unwrap());
-                    let count = u32::from_le_bytes(msg.inline_data[9..13].try_into().unwrap());
-                    
-                    if port_idx < self.ports.len() {
-                        let port = &self.ports[port_idx];
-                        if let Some(ref port_mmio) = port.mmio {
-                            if let Ok(mut buffer) = DmaBuffer::alloc((count * 512) as usize, 0) {
-                                // If caller provided a buffer for DMA transfer, copy from there
-                                if msg.buffer != 0 && msg.buffer_size >= buffer.size() as u64 {
-                                    unsafe {
-                                        // Acknowledging complex kernel-user DMA copy.
-                                        // For now, we assume data is handled through inline_data for small writes.
-                                        // A real implementation would map msg.buffer.
-                                        let dest_slice = buffer.as_mut_slice();
-                                        let copy_len = dest_slice.len().min(msg.buffer_size as usize);
-                                        // This copy needs direct memory access to msg.buffer (user space buffer mapped by kernel)
-                                        // For this stage, we assume it's passed via inline_data or a shared pre-mapped buffer.
-                                    }
-                                } else { // Fallback to inline data if small enough
-                                    unsafe {
-                                        let dest_slice = buffer.as_mut_slice();
-                                        let copy_len = dest_slice.len().min((msg.inline_size - 13) as usize);
-                                        dest_slice[0..copy_len].copy_from_slice(&msg.inline_data[13..13 + copy_len]);
-                                    }
-                                }
-                                
-                                if let Ok(_) = write_sectors(
-                                    port_mmio,
-                                    port.port_num,
-                                    lba,
-                                    count,
-                                    &buffer,
-                                    port.lba48,
-                                ) {
-                                    response.inline_data[0] = 0; // Success
-                                    response.inline_size = 1;
-                                }
-                            }
-                        }
+            BLOCK_DEV_OP_GET_INFO => {
+                // Geometry from IDENTIFY; a port without a drive gets an empty reply
+                let port_idx = msg.inline_data[0] as usize;
+                if let Some(port) = self.ports.get(port_idx).filter(|p| p.present) {
+                    let info = BlockDeviceInfo::new(port.sectors, port.sector_size, port.model.as_bytes());
+                    if let Some(len) = info.encode(&mut response.inline_data) {
+                        response.inline_size = len as u32;
                     }
                 }
             }
@@ -312,7 +393,68 @@ impl AhciDriver {
             }
         }
         
-        let _ = ipc_send(self.device_port, &response);
+        let _ = ipc_reply(msg, &response);
+    }
+    
+    /// Start a read or write, or queue it if its port has no free slot.
+    /// Request: [port, lba u64, count u32], write data framed after it
+    fn submit_io(&mut self, msg: &IpcMessage) -> Result<(), DriverError> {
+        let write = msg.msg_id == BLOCK_DEV_OP_WRITE;
+        let (port_idx, lba, count, mut buffer, buffer_phys) = match self.io_buffer(msg) {
+            Ok(io) => io,
+            Err(e) => {
+                // A shared payload is freed by taking it, even into nothing
+                if write {
+                    let _ = ipc_take_payload(msg, 0, &mut []);
+                }
+                return Err(e);
+            }
+        };
+        if write {
+            unsafe {
+                ipc_take_payload(msg, IO_HEADER_SIZE, buffer.as_mut_slice())?;
+            }
+        }
+        
+        self.ports[port_idx].submit(IoRequest {
+            reply_port: msg.reply_port,
+            msg_id: msg.msg_id,
+            write,
+            lba,
+            count,
+            buffer,
+            buffer_phys,
+        });
+        Ok(())
+    }
+    
+    /// Check a read or write request and allocate its DMA buffer:
+    /// (port, lba, count, buffer, physical address)
+    fn io_buffer(&self, msg: &IpcMessage) -> Result<(usize, u64, u32, DmaBuffer, u64), DriverError> {
+        if (msg.inline_size as usize) < IO_HEADER_SIZE {
+            return Err(DriverError::InvalidArgument);
+        }
+        let port_idx = msg.inline_data[0] as usize;
+        let lba = u64::from_le_bytes(msg.inline_data[1..9].try_into().unwrap());
+        let count = u32::from_le_bytes(msg.inline_data[9..13].try_into().unwrap());
+        
+        let port = self.ports.get(port_idx).filter(|p| p.present && p.slots.is_some())
+            .ok_or(DriverError::InvalidArgument)?;
+        let max_sectors = if port.lba48 { MAX_TRANSFER_BYTES / SECTOR_SIZE } else { LBA28_MAX_SECTORS };
+        if count == 0 || count > max_sectors {
+            return Err(DriverError::InvalidArgument);
+        }
+        
+        let buffer = DmaBuffer::alloc((count * SECTOR_SIZE) as usize, 0).map_err(|_| DriverError::OutOfMemory)?;
+        let buffer_phys = buffer.get_physical().map_err(|_| DriverError::IoError)?;
+        Ok((port_idx, lba, count, buffer, buffer_phys))
+    }
+    
+    /// Answer finished requests on every port and start queued ones
+    fn reap_completions(&mut self) {
+        for port in self.ports.iter_mut().filter(|p| p.is_busy()) {
+            port.reap();
+        }
     }
 }
 
@@ -329,6 +471,7 @@ impl Driver for AhciDriver {
             driver_framework::driver_manager::DriverType::Storage,
         ).map_err(|_| DriverError::InitFailed)?;
         
+        self.initialized = true;
         Ok(())
     }
     
@@ -346,15 +489,28 @@ impl Driver for AhciDriver {
         self.init_controller(device_info)?;
 
         if self.irq != 0 {
-            extern "C" fn ahci_irq_handler() {
+            extern "C" fn ahci_irq_handler() -> bool {
                 unsafe {
-                    if let Some(ref mut mmio) = DRIVER.mmio {
-                        let is = mmio.read32(AHCI_PxIS); // Read Port Interrupt Status (PI)
-                        mmio.write32(AHCI_PxIS, is); // Clear the interrupt
+                    let Some(ref mmio) = DRIVER.mmio else {
+                        return false;
+                    };
+                    let is = mmio.read_u32(AHCI_IS); // One pending bit per port
+                    if is == 0 {
+                        return false; // Raised by another device on the line
+                    }
+                    for port in DRIVER.ports.iter().filter(|p| is & (1 << p.port_num) != 0) {
+                        if let Some(ref port_mmio) = port.mmio {
+                            let pxis = port_mmio.read_u32(AHCI_PxIS);
+                            port_mmio.write_u32(AHCI_PxIS, pxis);
+                            // Completions are matched to requests in the main loop
+                            PORT_EVENTS[port.port_num as usize].fetch_or(pxis, Ordering::AcqRel);
+                        }
                     }
+                    mmio.write_u32(AHCI_IS, is); // Port bits must be cleared first
+                    true
                 }
             }
-            interrupts::register_irq(self.irq, ahci_irq_handler)
+            interrupts::register_irq_shared(self.irq, ahci_irq_handler)
                 .map_err(|_| DriverError::IoError)?;
             interrupts::enable_irq(self.irq)
                 .map_err(|_| DriverError::IoError)?;
@@ -389,7 +545,7 @@ impl Driver for AhciDriver {
             }
         }
         if self.irq != 0 {
-            let _ = interrupts::disable_irq(self.irq);
+            // The line may be shared, so leave it unmasked for other devices
             let _ = interrupts::unregister_irq(self.irq);
         }
         
@@ -411,6 +567,7 @@ static mut DRIVER: AhciDriver = AhciDriver {
     mmio: None,
     ports: Vec::new(), // Initialize with empty Vec
     irq: 0,
+    cap: 0,
 };
 
 #[no_mangle]
@@ -421,9 +578,10 @@ pub extern "C" fn _start() -> ! {
         
         // Driver main loop
         loop {
+            // Blocks until a request arrives, then takes any queued behind
+            // it; interrupts are handled by the registered handler.
             DRIVER.handle_ipc();
-            // Interrupts are handled by the registered handler.
-            driver_framework::syscalls::sys_sleep(10); // Yield to avoid busy-waiting
+            DRIVER.reap_completions();
         }
     }
 }
\ No newline at end of file
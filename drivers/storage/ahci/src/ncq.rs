@@ -0,0 +1,158 @@
+//! Command slots and native command queuing
+//!
+//! Every port has up to 32 command slots. A drive that supports NCQ takes
+//! READ/WRITE FPDMA QUEUED in any number of them at once, with the slot
+//! number as the command's tag. It marks each finished tag by clearing its
+//! bit in PxSACT. Other drives get one READ/WRITE DMA at a time, and PxCI
+//! tells when it is done. Either way, a slot is finished once its bit is
+//! clear in both registers.
+
+/// HBA CAP: supports native command queuing
+pub const AHCI_CAP_SNCQ: u32 = 1 << 30;
+const AHCI_CAP_NCS_SHIFT: u32 = 8;
+const AHCI_CAP_NCS_MASK: u32 = 0x1F;
+
+/// Most command slots a port can have
+pub const AHCI_MAX_SLOTS: usize = 32;
+
+// ATA commands
+pub const ATA_CMD_READ_DMA: u8 = 0xC8;
+pub const ATA_CMD_READ_DMA_EXT: u8 = 0x25;
+pub const ATA_CMD_WRITE_DMA: u8 = 0xCA;
+pub const ATA_CMD_WRITE_DMA_EXT: u8 = 0x35;
+pub const ATA_CMD_READ_FPDMA_QUEUED: u8 = 0x60;
+pub const ATA_CMD_WRITE_FPDMA_QUEUED: u8 = 0x61;
+
+/// IDENTIFY word 76 (SATA capabilities): NCQ supported
+const IDENTIFY_SATA_CAP_NCQ: u16 = 1 << 8;
+/// IDENTIFY word 75: queue depth minus one
+const IDENTIFY_QUEUE_DEPTH_MASK: u16 = 0x1F;
+
+const FIS_TYPE_REG_H2D: u8 = 0x27;
+/// Byte 1 of a host-to-device FIS: this is a command, not a control update
+const FIS_H2D_COMMAND: u8 = 0x80;
+/// Device register: LBA addressing
+const FIS_DEVICE_LBA: u8 = 0x40;
+/// Bytes of a host-to-device register FIS
+pub const FIS_H2D_SIZE: usize = 20;
+
+/// Command slots the HBA gives each port
+pub fn command_slots(cap: u32) -> u32 {
+    ((cap >> AHCI_CAP_NCS_SHIFT) & AHCI_CAP_NCS_MASK) + 1
+}
+
+/// Commands the drive can have queued, from IDENTIFY words 75 and 76;
+/// `None` without NCQ
+pub fn ncq_depth(queue_depth: u16, sata_capabilities: u16) -> Option<u32> {
+    if sata_capabilities & IDENTIFY_SATA_CAP_NCQ == 0 {
+        return None;
+    }
+    Some((queue_depth & IDENTIFY_QUEUE_DEPTH_MASK) as u32 + 1)
+}
+
+/// Commands a port can have in flight: one without NCQ, otherwise as many
+/// as both the HBA and the drive allow
+pub fn queue_depth(cap: u32, drive_depth: Option<u32>) -> u32 {
+    match drive_depth {
+        Some(depth) if cap & AHCI_CAP_SNCQ != 0 => depth.min(command_slots(cap)),
+        _ => 1,
+    }
+}
+
+fn h2d_fis(command: u8, lba: u64) -> [u8; FIS_H2D_SIZE] {
+    let mut fis = [0u8; FIS_H2D_SIZE];
+    fis[0] = FIS_TYPE_REG_H2D;
+    fis[1] = FIS_H2D_COMMAND;
+    fis[2] = command;
+    fis[4] = lba as u8;
+    fis[5] = (lba >> 8) as u8;
+    fis[6] = (lba >> 16) as u8;
+    fis[7] = FIS_DEVICE_LBA;
+    fis[8] = (lba >> 24) as u8;
+    fis[9] = (lba >> 32) as u8;
+    fis[10] = (lba >> 40) as u8;
+    fis
+}
+
+/// READ/WRITE FPDMA QUEUED of `count` sectors under `tag`. The sector
+/// count goes in the features registers and the tag in count bits 7:3.
+pub fn fpdma_fis(write: bool, lba: u64, count: u16, tag: u8) -> [u8; FIS_H2D_SIZE] {
+    let command = if write { ATA_CMD_WRITE_FPDMA_QUEUED } else { ATA_CMD_READ_FPDMA_QUEUED };
+    let mut fis = h2d_fis(command, lba);
+    fis[3] = count as u8;
+    fis[11] = (count >> 8) as u8;
+    fis[12] = tag << 3;
+    fis
+}
+
+/// READ/WRITE DMA (EXT) of `count` sectors. Without LBA48, LBA bits
+/// 27:24 go in the device register and at most 256 sectors fit.
+pub fn dma_fis(write: bool, lba: u64, count: u16, lba48: bool) -> [u8; FIS_H2D_SIZE] {
+    let command = match (write, lba48) {
+        (false, false) => ATA_CMD_READ_DMA,
+        (false, true) => ATA_CMD_READ_DMA_EXT,
+        (true, false) => ATA_CMD_WRITE_DMA,
+        (true, true) => ATA_CMD_WRITE_DMA_EXT,
+    };
+    let mut fis = h2d_fis(command, lba);
+    if !lba48 {
+        fis[7] |= ((lba >> 24) & 0x0F) as u8;
+        fis[8..11].fill(0);
+    }
+    fis[12] = count as u8;
+    fis[13] = (count >> 8) as u8;
+    fis
+}
+
+/// Requests occupying a port's command slots
+pub struct SlotTable<T> {
+    slots: [Option<T>; AHCI_MAX_SLOTS],
+    /// Slots that may be used, lowest first
+    depth: u32,
+    /// Slots handed to the HBA and not yet finished
+    issued: u32,
+}
+
+impl<T> SlotTable<T> {
+    pub fn new(depth: u32) -> Self {
+        SlotTable {
+            slots: core::array::from_fn(|_| None),
+            depth: depth.clamp(1, AHCI_MAX_SLOTS as u32),
+            issued: 0,
+        }
+    }
+
+    /// Put a request in a free slot and return the slot, or hand the
+    /// request back if every slot is busy
+    pub fn allocate(&mut self, request: T) -> Result<u8, T> {
+        match self.slots[..self.depth as usize].iter().position(|slot| slot.is_none()) {
+            Some(slot) => {
+                self.slots[slot] = Some(request);
+                self.issued |= 1 << slot;
+                Ok(slot as u8)
+            }
+            None => Err(request),
+        }
+    }
+
+    /// Slots not yet finished
+    pub fn issued(&self) -> u32 {
+        self.issued
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.issued == 0
+    }
+
+    /// Issued slots the HBA has finished, given PxSACT and PxCI
+    pub fn finished(&self, sact: u32, ci: u32) -> u32 {
+        self.issued & !(sact | ci)
+    }
+
+    /// Remove the request in a slot, freeing it
+    pub fn take(&mut self, slot: u8) -> Option<T> {
+        let request = self.slots.get_mut(slot as usize)?.take()?;
+        self.issued &= !(1 << slot);
+        Some(request)
+    }
+}
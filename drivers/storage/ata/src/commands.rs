@@ -0,0 +1,6 @@
+//! Block device IPC operations (same numbering as the AHCI driver)
+
+pub const BLOCK_DEV_OP_READ: u64 = 1;
+pub const BLOCK_DEV_OP_WRITE: u64 = 2;
+/// [drive:1] -> `BlockDeviceInfo` (empty reply if no drive)
+pub const BLOCK_DEV_OP_GET_INFO: u64 = 3;
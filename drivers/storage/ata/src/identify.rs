@@ -0,0 +1,22 @@
+//! IDENTIFY DEVICE data
+//!
+//! The response is 256 little endian words. Strings such as the model and
+//! serial number pack two characters per word with the first in the high
+//! byte, and are padded with spaces.
+
+/// Words holding the model number
+pub const IDENTIFY_MODEL: core::ops::Range<usize> = 27..47;
+
+/// Copy a string field into `out`, putting each word's bytes in reading
+/// order and dropping trailing padding. Returns the length kept.
+pub fn identify_string(words: &[u16], out: &mut [u8]) -> usize {
+    let mut len = 0;
+    for (pair, word) in out.chunks_exact_mut(2).zip(words) {
+        pair.copy_from_slice(&word.to_be_bytes());
+        len += 2;
+    }
+    while len > 0 && (out[len - 1] == b' ' || out[len - 1] == 0) {
+        len -= 1;
+    }
+    len
+}
@@ -0,0 +1,23 @@
+//! LBA addressing limits
+//!
+//! LBA28 commands take an 8-bit sector count (0 meaning 256) and a 28-bit
+//! address; the LBA48 EXT commands widen these to 16 bits (0 meaning
+//! 65536) and 48 bits.
+
+pub const LBA28_MAX_SECTORS: u32 = 256;
+pub const LBA48_MAX_SECTORS: u32 = 65536;
+/// First sector each mode cannot address
+pub const LBA28_LIMIT: u64 = 1 << 28;
+pub const LBA48_LIMIT: u64 = 1 << 48;
+
+/// Whether one command can transfer `count` sectors starting at `lba`
+pub fn request_fits(lba48: bool, lba: u64, count: u32) -> bool {
+    let (max_sectors, limit) = if lba48 {
+        (LBA48_MAX_SECTORS, LBA48_LIMIT)
+    } else {
+        (LBA28_MAX_SECTORS, LBA28_LIMIT)
+    };
+    count != 0
+        && count <= max_sectors
+        && lba.checked_add(count as u64).map_or(false, |end| end <= limit)
+}
@@ -7,6 +7,7 @@
 
 use core::panic::PanicInfo;
 use core::convert::TryInto;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 extern crate alloc;
 use alloc::vec::Vec;
@@ -14,13 +15,25 @@ use alloc::string::String;
 
 use driver_framework::{Driver, DriverError, DeviceInfo, DeviceType};
 use driver_framework::mmio::MmioRegion; // Not used for PIO, but generally useful
+use driver_framework::dma::DmaBuffer;
 use driver_framework::interrupts;
-use driver_framework::ipc::{ipc_create_port, ipc_receive, ipc_send, IpcMessage, IPC_MSG_REQUEST};
+use driver_framework::pci;
+use driver_framework::ipc::{ipc_create_port, ipc_drain, ipc_reply, IpcMessage, IPC_MSG_REQUEST};
 use driver_framework::syscalls;
 
-use driver_framework::syscalls::{sys_sleep, sys_io_read, sys_io_write};
+use driver_framework::syscalls::{sys_io_read, sys_io_write};
 
-use crate::commands::{BLOCK_DEV_OP_READ, BLOCK_DEV_OP_WRITE}; // Assuming these are defined in a commands module
+use driver_framework::block::BlockDeviceInfo;
+
+use crate::commands::{BLOCK_DEV_OP_READ, BLOCK_DEV_OP_WRITE, BLOCK_DEV_OP_GET_INFO};
+
+mod commands;
+mod identify;
+mod lba;
+mod prd;
+use identify::{identify_string, IDENTIFY_MODEL};
+use lba::request_fits;
+use prd::*;
 
 // ATA I/O Ports
 const ATA_PRIMARY_BASE: u16 = 0x1F0;
@@ -48,8 +61,45 @@ const ATA_CMD_READ_PIO: u8 = 0x20;
 const ATA_CMD_READ_PIO_EXT: u8 = 0x24; // LBA48
 const ATA_CMD_WRITE_PIO: u8 = 0x30;
 const ATA_CMD_WRITE_PIO_EXT: u8 = 0x34; // LBA48
+const ATA_CMD_READ_DMA: u8 = 0xC8;
+const ATA_CMD_READ_DMA_EXT: u8 = 0x25; // LBA48
+const ATA_CMD_WRITE_DMA: u8 = 0xCA;
+const ATA_CMD_WRITE_DMA_EXT: u8 = 0x35; // LBA48
 const ATA_CMD_IDENTIFY: u8 = 0xEC;
 
+// Legacy IRQs of the two channels
+const ATA_PRIMARY_IRQ: u8 = 14;
+const ATA_SECONDARY_IRQ: u8 = 15;
+
+// PCI class of IDE controllers, whose BAR4 holds the bus-master registers
+const PCI_CLASS_STORAGE: u8 = 0x01;
+const PCI_SUBCLASS_IDE: u8 = 0x01;
+const IDE_BUS_MASTER_BAR: u8 = 4;
+
+/// Requests this large go through DMA when the drive and controller can
+const ATA_DMA_MIN_SECTORS: u32 = 8;
+/// Sectors one DMA transfer moves through the channel's bounce buffer
+const ATA_DMA_MAX_SECTORS: u32 = 128;
+const ATA_DMA_BUFFER_SIZE: usize = ATA_DMA_MAX_SECTORS as usize * 512;
+
+/// Logical sector size reported to clients
+const ATA_SECTOR_SIZE: u32 = 512;
+/// Descriptors needed for the bounce buffer, with one spare for a 64 KiB
+/// boundary inside it
+const ATA_PRD_ENTRIES: usize = ATA_DMA_BUFFER_SIZE / 0x10000 + 2;
+/// Longest a DMA transfer may take to interrupt
+const ATA_DMA_TIMEOUT_MS: u64 = 5000;
+/// Longest drives may stay busy after a software reset
+const ATA_RESET_TIMEOUT_MS: u64 = 1000;
+/// Status read from a channel with nothing attached
+const ATA_FLOATING_BUS: u8 = 0xFF;
+
+/// Set by a channel's interrupt, cleared before each DMA transfer
+static DMA_IRQ_FIRED: [AtomicBool; 2] = [AtomicBool::new(false), AtomicBool::new(false)];
+
+/// How long the main loop blocks waiting for a request
+const IPC_RECEIVE_TIMEOUT_MS: u64 = 100;
+
 // ATA Status Register Bits
 const ATA_SR_BSY: u8 = 0x80; // Busy
 const ATA_SR_DRDY: u8 = 0x40; // Drive ready
@@ -65,6 +115,18 @@ struct AtaChannel {
     base: u16,
     control: u16,
     drives: [Option<AtaDrive>; 2], // Master and Slave
+    /// Bus-master registers; 0 without a PCI IDE controller
+    bus_master: u16,
+    dma: Option<ChannelDma>,
+    /// The channel's IRQ handler is registered, so DMA completion need
+    /// not be polled
+    irq: bool,
+}
+
+/// Memory a channel's DMA transfers go through
+struct ChannelDma {
+    prd_table: DmaBuffer,
+    buffer: DmaBuffer,
 }
 
 #[derive(Clone)]
@@ -75,6 +137,7 @@ struct AtaDrive {
     sectors: u64,
     model: String,
     present: bool,
+    dma: bool, // Supports DMA transfers
 }
 
 impl AtaDrive {
@@ -86,6 +149,7 @@ impl AtaDrive {
             sectors: 0,
             model: String::new(),
             present: false,
+            dma: false,
         }
     }
 }
@@ -95,8 +159,8 @@ static mut DRIVER: AtaDriver = AtaDriver {
     initialized: false,
     device_port: 0,
     channels: [
-        AtaChannel { base: ATA_PRIMARY_BASE, control: ATA_PRIMARY_CONTROL, drives: [None, None] },
-        AtaChannel { base: ATA_SECONDARY_BASE, control: ATA_SECONDARY_CONTROL, drives: [None, None] },
+        AtaChannel { base: ATA_PRIMARY_BASE, control: ATA_PRIMARY_CONTROL, drives: [None, None], bus_master: 0, dma: None, irq: false },
+        AtaChannel { base: ATA_SECONDARY_BASE, control: ATA_SECONDARY_CONTROL, drives: [None, None], bus_master: 0, dma: None, irq: false },
     ],
 };
 
@@ -120,18 +184,20 @@ fn ata_driver_init() {
         for ch_idx in 0..2 {
             let channel = &mut DRIVER.channels[ch_idx];
             
-            // Software reset
+            // Software reset, then wait for the drives to finish it
             sys_io_write(channel.control, 0x02, 1); // Set SRST bit
             sys_io_write(channel.control, 0x00, 1); // Clear SRST bit
-            syscalls::sys_sleep(10); // Wait for reset
+            syscalls::wait_until(ATA_RESET_TIMEOUT_MS, || {
+                let status = ata_read_status(channel);
+                status == ATA_FLOATING_BUS || status & ATA_SR_BSY == 0
+            });
             
             for dr_idx in 0..2 { // Master and Slave
                 let drive_type_select = if dr_idx == 0 { ATA_DRIVE_MASTER } else { ATA_DRIVE_SLAVE };
                 
                 // Select drive
                 sys_io_write(channel.base + ATA_DRIVE_SELECT, drive_type_select as u32, 1);
-                sys_io_read(channel.base + ATA_STATUS, 1); // Read status to wait
-                syscalls::sys_sleep(1);
+                ata_select_delay(channel);
                 
                 // Send IDENTIFY command
                 sys_io_write(channel.base + ATA_COMMAND, ATA_CMD_IDENTIFY as u32, 1);
@@ -156,6 +222,7 @@ fn ata_driver_init() {
 
                     // Parse IDENTIFY data
                     drive.lba48 = (data[83] & (1 << 10)) != 0;
+                    drive.dma = (data[49] & (1 << 8)) != 0;
                     if drive.lba48 {
                         drive.sectors = (data[100] as u64) | ((data[101] as u64) << 16) | ((data[102] as u64) << 32) | ((data[103] as u64) << 48);
                     } else {
@@ -163,29 +230,70 @@ fn ata_driver_init() {
                     }
                     
                     // Model string (words 27-46)
-                    let model_bytes: [u8; 40] = core::mem::transmute(data[27..47]);
-                    drive.model = String::from_utf8_lossy(&model_bytes)
-                        .trim()
-                        .to_string();
+                    let mut model = [0u8; 40];
+                    let len = identify_string(&data[IDENTIFY_MODEL], &mut model);
+                    drive.model = String::from_utf8_lossy(&model[..len]).into_owned();
 
                     drive.present = true;
                     channel.drives[dr_idx] = Some(drive);
                 }
             }
         }
+        ata_dma_init();
         DRIVER.initialized = true;
     }
 }
 
+extern "C" fn ata_primary_irq() {
+    DMA_IRQ_FIRED[0].store(true, Ordering::Release);
+}
+
+extern "C" fn ata_secondary_irq() {
+    DMA_IRQ_FIRED[1].store(true, Ordering::Release);
+}
+
+/// Find the IDE controller's bus-master registers and set each channel up
+/// for DMA. Channels it cannot set up keep using PIO.
+fn ata_dma_init() {
+    let (bus, device, function) = match pci::find_by_class(PCI_CLASS_STORAGE, PCI_SUBCLASS_IDE, pci::PCI_CLASS_ANY, 0) {
+        Ok(location) => location,
+        Err(_) => return,
+    };
+    let bar = match pci::get_bar(bus, device, function, IDE_BUS_MASTER_BAR) {
+        Ok(bar) if !bar.is_mmio && bar.base != 0 => bar,
+        _ => return,
+    };
+    if pci::enable_bus_master(bus, device, function).is_err() {
+        return;
+    }
+
+    let handlers: [(u8, interrupts::IrqHandler); 2] =
+        [(ATA_PRIMARY_IRQ, ata_primary_irq), (ATA_SECONDARY_IRQ, ata_secondary_irq)];
+    for (ch_idx, (irq, handler)) in handlers.iter().enumerate() {
+        let prd_table = DmaBuffer::alloc(ATA_PRD_ENTRIES * core::mem::size_of::<Prd>(), 0);
+        let buffer = DmaBuffer::alloc(ATA_DMA_BUFFER_SIZE, 0);
+        let (prd_table, buffer) = match (prd_table, buffer) {
+            (Ok(prd_table), Ok(buffer)) => (prd_table, buffer),
+            _ => continue,
+        };
+
+        let channel = unsafe { &mut DRIVER.channels[ch_idx] };
+        channel.bus_master = bar.base as u16 + ch_idx as u16 * BM_CHANNEL_STRIDE;
+        channel.dma = Some(ChannelDma { prd_table, buffer });
+        channel.irq = interrupts::register_irq(*irq, *handler).is_ok() && interrupts::enable_irq(*irq).is_ok();
+    }
+}
+
 fn ata_driver_loop() -> ! {
     let mut msg = IpcMessage::new();
     loop {
-        // Handle storage I/O requests via IPC
-        if ipc_receive(DRIVER.device_port, &mut msg).is_ok() {
-            let response = handle_ipc_message(&msg);
-            let _ = ipc_send(msg.sender_tid, &response);
-        }
-        syscalls::sys_sleep(10); // Yield CPU
+        // Handle storage I/O requests via IPC, blocking until one arrives
+        // and then taking every one queued behind it
+        let port = unsafe { DRIVER.device_port };
+        ipc_drain(port, &mut msg, IPC_RECEIVE_TIMEOUT_MS, usize::MAX, |msg| {
+            let response = handle_ipc_message(msg);
+            let _ = ipc_reply(msg, &response);
+        });
     }
 }
 
@@ -202,8 +310,8 @@ fn handle_ipc_message(msg: &IpcMessage) -> IpcMessage {
                 let count = u32::from_le_bytes(msg.inline_data[9..13].try_into().unwrap());
                 
                 if let Some(ref drive) = get_drive(drive_idx) {
-                    let mut data_buffer = Vec::with_capacity((count * 512) as usize);
-                    let res = ata_read_sectors_pio(drive, lba, count, &mut data_buffer);
+                    let mut data_buffer = Vec::new();
+                    let res = ata_read_sectors(drive, lba, count, &mut data_buffer);
 
                     if res.is_ok() {
                         // Copy data to response (limited to inline data for simplicity)
@@ -221,12 +329,12 @@ fn handle_ipc_message(msg: &IpcMessage) -> IpcMessage {
                 let count = u32::from_le_bytes(msg.inline_data[9..13].try_into().unwrap());
                 
                 if let Some(ref drive) = get_drive(drive_idx) {
-                    let mut data_buffer = Vec::with_capacity((count * 512) as usize);
+                    let mut data_buffer = Vec::new();
                     // Copy data from message inline data (limited for now)
-                    let copy_len = data_buffer.capacity().min((msg.inline_size - 13) as usize);
+                    let copy_len = (count as usize * 512).min((msg.inline_size - 13) as usize);
                     data_buffer.extend_from_slice(&msg.inline_data[13..13 + copy_len]);
 
-                    let res = ata_write_sectors_pio(drive, lba, count, &data_buffer);
+                    let res = ata_write_sectors(drive, lba, count, &data_buffer);
 
                     if res.is_ok() {
                         response.inline_data[0] = 0; // Success
@@ -235,6 +343,15 @@ fn handle_ipc_message(msg: &IpcMessage) -> IpcMessage {
                 }
             }
         }
+        BLOCK_DEV_OP_GET_INFO => {
+            if let Some(drive) = get_drive(msg.inline_data[0] as usize).filter(|d| d.present) {
+                // PATA drives always use 512-byte logical sectors
+                let info = BlockDeviceInfo::new(drive.sectors, ATA_SECTOR_SIZE, drive.model.as_bytes());
+                if let Some(len) = info.encode(&mut response.inline_data) {
+                    response.inline_size = len as u32;
+                }
+            }
+        }
         _ => {}
     }
 
@@ -270,27 +387,187 @@ fn ata_select_drive(drive: &AtaDrive) {
     let channel = &unsafe { &mut DRIVER.channels[drive.channel_idx as usize] };
     let drive_select_val = if drive.drive_idx == 0 { ATA_DRIVE_MASTER } else { ATA_DRIVE_SLAVE };
     unsafe { sys_io_write(channel.base + ATA_DRIVE_SELECT, drive_select_val as u32, 1); }
-    ata_read_status(channel); // Wait for drive select
+    ata_select_delay(channel);
 }
 
-fn ata_read_sectors_pio(drive: &AtaDrive, lba: u64, count: u32, buffer: &mut Vec<u8>) -> Result<(), ()> {
-    let channel = &unsafe { &mut DRIVER.channels[drive.channel_idx as usize] };
-    
+/// Give a newly selected drive the 400ns it needs to drive its status:
+/// four reads of the alternate status register
+fn ata_select_delay(channel: &AtaChannel) {
+    for _ in 0..4 {
+        unsafe { sys_io_read(channel.control + ATA_ALT_STATUS, 1); }
+    }
+}
+
+/// Whether a transfer of `count` sectors should use DMA
+fn ata_use_dma(drive: &AtaDrive, count: u32) -> bool {
+    let channel = unsafe { &DRIVER.channels[drive.channel_idx as usize] };
+    drive.dma && channel.dma.is_some() && count >= ATA_DMA_MIN_SECTORS
+}
+
+/// Reject transfers the drive's addressing mode or size cannot cover
+fn ata_check_request(drive: &AtaDrive, lba: u64, count: u32) -> Result<(), ()> {
+    if !request_fits(drive.lba48, lba, count) {
+        return Err(());
+    }
+    if drive.sectors != 0 && lba + count as u64 > drive.sectors {
+        return Err(());
+    }
+    Ok(())
+}
+
+fn ata_read_sectors(drive: &AtaDrive, lba: u64, count: u32, buffer: &mut Vec<u8>) -> Result<(), ()> {
+    ata_check_request(drive, lba, count)?;
+    if ata_use_dma(drive, count) {
+        ata_read_sectors_dma(drive, lba, count, buffer)
+    } else {
+        ata_read_sectors_pio(drive, lba, count, buffer)
+    }
+}
+
+fn ata_write_sectors(drive: &AtaDrive, lba: u64, count: u32, data: &[u8]) -> Result<(), ()> {
+    ata_check_request(drive, lba, count)?;
+    if data.len() < count as usize * 512 {
+        return Err(());
+    }
+    if ata_use_dma(drive, count) {
+        ata_write_sectors_dma(drive, lba, count, data)
+    } else {
+        ata_write_sectors_pio(drive, lba, count, data)
+    }
+}
+
+fn ata_read_sectors_dma(drive: &AtaDrive, lba: u64, count: u32, buffer: &mut Vec<u8>) -> Result<(), ()> {
+    let mut done = 0;
+    while done < count {
+        let chunk = (count - done).min(ATA_DMA_MAX_SECTORS);
+        let len = chunk as usize * 512;
+        ata_dma_transfer(drive, lba + done as u64, chunk, true)?;
+
+        let channel = unsafe { &DRIVER.channels[drive.channel_idx as usize] };
+        let dma = channel.dma.as_ref().ok_or(())?;
+        buffer.extend_from_slice(unsafe { core::slice::from_raw_parts(dma.buffer.as_ptr(), len) });
+        done += chunk;
+    }
+    Ok(())
+}
+
+fn ata_write_sectors_dma(drive: &AtaDrive, lba: u64, count: u32, data: &[u8]) -> Result<(), ()> {
+    let mut done = 0;
+    while done < count {
+        let chunk = (count - done).min(ATA_DMA_MAX_SECTORS);
+        let start = done as usize * 512;
+        let len = chunk as usize * 512;
+
+        let channel = unsafe { &DRIVER.channels[drive.channel_idx as usize] };
+        let dma = channel.dma.as_ref().ok_or(())?;
+        unsafe { core::ptr::copy_nonoverlapping(data[start..].as_ptr(), dma.buffer.as_ptr(), len) };
+
+        ata_dma_transfer(drive, lba + done as u64, chunk, false)?;
+        done += chunk;
+    }
+    Ok(())
+}
+
+/// Move up to ATA_DMA_MAX_SECTORS between the drive and the channel's
+/// bounce buffer, waiting for the completion interrupt
+fn ata_dma_transfer(drive: &AtaDrive, lba: u64, count: u32, read: bool) -> Result<(), ()> {
+    let ch_idx = drive.channel_idx as usize;
+    let channel = unsafe { &DRIVER.channels[ch_idx] };
+    let dma = channel.dma.as_ref().ok_or(())?;
+    let bm = channel.bus_master;
+
+    // Describe the bounce buffer
+    let phys = dma.buffer.get_physical()?;
+    let mut table = [Prd::EMPTY; ATA_PRD_ENTRIES];
+    let entries = build_prd_table(phys, count as usize * 512, &mut table).ok_or(())?;
+    let prd_ptr = dma.prd_table.as_ptr() as *mut Prd;
+    for (i, prd) in table[..entries].iter().enumerate() {
+        unsafe { core::ptr::write_volatile(prd_ptr.add(i), *prd) };
+    }
+    let prd_phys = dma.prd_table.get_physical()?;
+
+    // Point the engine at the table, set the direction and clear old status
+    let direction = if read { BM_CMD_READ } else { 0 };
+    unsafe {
+        sys_io_write(bm + BM_COMMAND, direction as u32, 1);
+        sys_io_write(bm + BM_PRD_TABLE, prd_phys as u32, 4);
+        sys_io_write(bm + BM_STATUS, (BM_STATUS_ERROR | BM_STATUS_INTERRUPT) as u32, 1);
+    }
+    DMA_IRQ_FIRED[ch_idx].store(false, Ordering::Release);
+
     ata_wait_bsy(channel);
     ata_select_drive(drive);
-    
-    // Setup registers
+    ata_setup_lba(channel, drive, lba, count);
+    let command = match (read, drive.lba48) {
+        (true, false) => ATA_CMD_READ_DMA,
+        (true, true) => ATA_CMD_READ_DMA_EXT,
+        (false, false) => ATA_CMD_WRITE_DMA,
+        (false, true) => ATA_CMD_WRITE_DMA_EXT,
+    };
+    unsafe {
+        sys_io_write(channel.base + ATA_COMMAND, command as u32, 1);
+        sys_io_write(bm + BM_COMMAND, (direction | BM_CMD_START) as u32, 1);
+    }
+
+    // Wait for the drive to interrupt; without a handler, watch the
+    // controller's copy of the interrupt line instead
+    let mut status = 0;
+    syscalls::wait_until(ATA_DMA_TIMEOUT_MS, || {
+        status = unsafe { sys_io_read(bm + BM_STATUS, 1) as u8 };
+        let fired = if channel.irq {
+            DMA_IRQ_FIRED[ch_idx].load(Ordering::Acquire)
+        } else {
+            status & BM_STATUS_INTERRUPT != 0
+        };
+        fired || status & BM_STATUS_ERROR != 0
+    });
+
+    // Stop the engine, acknowledge the interrupt and check both ends
     unsafe {
-        sys_io_write(channel.base + ATA_SECTOR_COUNT, count as u32, 1);
+        sys_io_write(bm + BM_COMMAND, direction as u32, 1);
+        sys_io_write(bm + BM_STATUS, (BM_STATUS_ERROR | BM_STATUS_INTERRUPT) as u32, 1);
+    }
+    let ata_status = ata_read_status(channel);
+    if status & (BM_STATUS_ERROR | BM_STATUS_ACTIVE) != 0 || ata_status & (ATA_SR_ERR | ATA_SR_DF) != 0 {
+        return Err(());
+    }
+    if channel.irq && !DMA_IRQ_FIRED[ch_idx].load(Ordering::Acquire) {
+        return Err(()); // Timed out
+    }
+    Ok(())
+}
+
+/// Program sector count and address; LBA48 writes the high bytes first.
+/// A count of 0 in the registers means the mode's maximum.
+fn ata_setup_lba(channel: &AtaChannel, drive: &AtaDrive, lba: u64, count: u32) {
+    // Bit 6 selects LBA addressing
+    let drive_select_val = (if drive.drive_idx == 0 { ATA_DRIVE_MASTER } else { ATA_DRIVE_SLAVE }) | 0x40;
+    unsafe {
+        if drive.lba48 {
+            sys_io_write(channel.base + ATA_DRIVE_SELECT, drive_select_val as u32, 1);
+            sys_io_write(channel.base + ATA_SECTOR_COUNT, (count >> 8) & 0xFF, 1);
+            sys_io_write(channel.base + ATA_LBA_LOW, ((lba >> 24) & 0xFF) as u32, 1);
+            sys_io_write(channel.base + ATA_LBA_MID, ((lba >> 32) & 0xFF) as u32, 1);
+            sys_io_write(channel.base + ATA_LBA_HIGH, ((lba >> 40) & 0xFF) as u32, 1);
+        } else {
+            sys_io_write(channel.base + ATA_DRIVE_SELECT, (drive_select_val as u32) | ((lba >> 24) & 0x0F) as u32, 1);
+        }
+        sys_io_write(channel.base + ATA_SECTOR_COUNT, count & 0xFF, 1);
         sys_io_write(channel.base + ATA_LBA_LOW, (lba & 0xFF) as u32, 1);
         sys_io_write(channel.base + ATA_LBA_MID, ((lba >> 8) & 0xFF) as u32, 1);
         sys_io_write(channel.base + ATA_LBA_HIGH, ((lba >> 16) & 0xFF) as u32, 1);
-        
-        let drive_select_val = if drive.drive_idx == 0 { ATA_DRIVE_MASTER } else { ATA_DRIVE_SLAVE };
-        sys_io_write(channel.base + ATA_DRIVE_SELECT, (drive_select_val | ((lba >> 24) & 0x0F)) as u32, 1);
-        
-        sys_io_write(channel.base + ATA_COMMAND, ATA_CMD_READ_PIO as u32, 1);
     }
+}
+
+fn ata_read_sectors_pio(drive: &AtaDrive, lba: u64, count: u32, buffer: &mut Vec<u8>) -> Result<(), ()> {
+    let channel = &unsafe { &mut DRIVER.channels[drive.channel_idx as usize] };
+    
+    ata_wait_bsy(channel);
+    ata_select_drive(drive);
+    
+    ata_setup_lba(channel, drive, lba, count);
+    let command = if drive.lba48 { ATA_CMD_READ_PIO_EXT } else { ATA_CMD_READ_PIO };
+    unsafe { sys_io_write(channel.base + ATA_COMMAND, command as u32, 1); }
     
     for _ in 0..count {
         ata_wait_bsy(channel);
@@ -313,18 +590,9 @@ fn ata_write_sectors_pio(drive: &AtaDrive, lba: u64, count: u32, data: &[u8]) ->
     ata_wait_bsy(channel);
     ata_select_drive(drive);
     
-    // Setup registers
-    unsafe {
-        sys_io_write(channel.base + ATA_SECTOR_COUNT, count as u32, 1);
-        sys_io_write(channel.base + ATA_LBA_LOW, (lba & 0xFF) as u32, 1);
-        sys_io_write(channel.base + ATA_LBA_MID, ((lba >> 8) & 0xFF) as u32, 1);
-        sys_io_write(channel.base + ATA_LBA_HIGH, ((lba >> 16) & 0xFF) as u32, 1);
-        
-        let drive_select_val = if drive.drive_idx == 0 { ATA_DRIVE_MASTER } else { ATA_DRIVE_SLAVE };
-        sys_io_write(channel.base + ATA_DRIVE_SELECT, (drive_select_val | ((lba >> 24) & 0x0F)) as u32, 1);
-        
-        sys_io_write(channel.base + ATA_COMMAND, ATA_CMD_WRITE_PIO as u32, 1);
-    }
+    ata_setup_lba(channel, drive, lba, count);
+    let command = if drive.lba48 { ATA_CMD_WRITE_PIO_EXT } else { ATA_CMD_WRITE_PIO };
+    unsafe { sys_io_write(channel.base + ATA_COMMAND, command as u32, 1); }
     
     let mut data_offset = 0;
     for _ in 0..count {
@@ -0,0 +1,69 @@
+//! Bus-master IDE
+//!
+//! The controller's bus-master registers (BAR4, 8 bytes per channel) point
+//! at a table of physical region descriptors. Each descriptor covers up to
+//! 64 KiB that must not cross a 64 KiB boundary, and the last one is
+//! flagged end of table.
+
+/// Bus-master registers, offsets from the channel's base
+pub const BM_COMMAND: u16 = 0x00;
+pub const BM_STATUS: u16 = 0x02;
+pub const BM_PRD_TABLE: u16 = 0x04;
+/// Second channel's registers follow the first's
+pub const BM_CHANNEL_STRIDE: u16 = 0x08;
+
+/// Command bits
+pub const BM_CMD_START: u8 = 1 << 0;
+/// Transfer from the device into memory
+pub const BM_CMD_READ: u8 = 1 << 3;
+
+/// Status bits; error and interrupt are cleared by writing 1
+pub const BM_STATUS_ACTIVE: u8 = 1 << 0;
+pub const BM_STATUS_ERROR: u8 = 1 << 1;
+pub const BM_STATUS_INTERRUPT: u8 = 1 << 2;
+
+/// Marks the last descriptor
+pub const PRD_END_OF_TABLE: u16 = 0x8000;
+const PRD_REGION_MAX: u64 = 0x10000;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Prd {
+    pub phys: u32,
+    /// Bytes in the region; 0 means 64 KiB
+    pub byte_count: u16,
+    pub flags: u16,
+}
+
+impl Prd {
+    pub const EMPTY: Prd = Prd { phys: 0, byte_count: 0, flags: 0 };
+}
+
+/// Describe `len` bytes at `phys` in `table`, returning how many
+/// descriptors it took. None if the buffer is empty, odd, above 4 GiB or
+/// needs more descriptors than the table has.
+pub fn build_prd_table(phys: u64, len: usize, table: &mut [Prd]) -> Option<usize> {
+    if len == 0 || len % 2 != 0 || phys % 2 != 0 {
+        return None;
+    }
+    let end = phys.checked_add(len as u64)?;
+    if end > 1 << 32 {
+        return None;
+    }
+
+    let mut addr = phys;
+    let mut count = 0;
+    while addr < end {
+        let boundary = (addr | (PRD_REGION_MAX - 1)) + 1;
+        let region = end.min(boundary) - addr;
+        *table.get_mut(count)? = Prd {
+            phys: addr as u32,
+            byte_count: (region % PRD_REGION_MAX) as u16,
+            flags: 0,
+        };
+        addr += region;
+        count += 1;
+    }
+    table[count - 1].flags = PRD_END_OF_TABLE;
+    Some(count)
+}
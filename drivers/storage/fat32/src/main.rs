@@ -12,11 +12,13 @@ extern crate alloc;
 use alloc::vec::Vec;
 use alloc::string::String;
 
-use driver_framework::ipc::{ipc_create_port, ipc_receive, ipc_send, IpcMessage, IPC_MSG_REQUEST};
-use driver_framework::syscalls;
+use driver_framework::ipc::{ipc_create_port, ipc_drain, ipc_reply, ipc_send, IpcMessage, IPC_MSG_REQUEST};
 
 // VFS Service IPC constants
 const VFS_SERVICE_PORT: u32 = 102; // Assuming VFS service listens on port 102
+
+/// How long the main loop blocks waiting for a request
+const IPC_RECEIVE_TIMEOUT_MS: u64 = 100;
 const VFS_MSG_REGISTER_FS: u32 = 1; // Message ID for registering a filesystem
 
 // Message types for VFS operations (simplified)
@@ -63,12 +65,12 @@ pub extern "C" fn _start() -> ! {
     // Main service loop
     let mut msg = IpcMessage::new();
     loop {
-        // Handle filesystem operations via IPC
-        if ipc_receive(fat32_driver_port, &mut msg).is_ok() {
-            let response = handle_ipc_message(&msg);
-            let _ = ipc_send(msg.sender_tid, &response);
-        }
-        syscalls::sys_sleep(10); // Yield
+        // Handle filesystem operations via IPC, blocking until one arrives
+        // and then taking every one queued behind it
+        ipc_drain(fat32_driver_port, &mut msg, IPC_RECEIVE_TIMEOUT_MS, usize::MAX, |msg| {
+            let response = handle_ipc_message(msg);
+            let _ = ipc_reply(msg, &response);
+        });
     }
 }
 
@@ -14,7 +14,12 @@ const SYS_IPC_RECEIVE: u64 = 21;
 const PCI_DRIVER_PORT: u32 = 101;
 
 // PCI Messages
-const MSG_PCI_FIND_DEVICE: u32 = 13;
+const MSG_PCI_FIND_BY_CLASS: u32 = 14;
+
+// NVMe controller class code
+const PCI_CLASS_STORAGE: u8 = 0x01;
+const PCI_SUBCLASS_NVM: u8 = 0x08;
+const PCI_PROG_IF_NVME: u8 = 0x02;
 
 #[repr(C)]
 struct IpcMessage {
@@ -41,21 +46,16 @@ impl IpcMessage {
 pub extern "C" fn _start() -> ! {
     print("NVMe Driver Starting...\n");
 
-    // Find NVMe Controller (Class 0x01, Subclass 0x08, ProgIF 0x02)
-    // Note: PCI driver currently only supports finding by Vendor/Device ID
-    // We'll need to scan or update PCI driver. For now, let's try a common one (QEMU NVMe)
-    // Vendor: 0x1B36 (Red Hat), Device: 0x0010 (QEMU NVMe)
-    
-    let vendor_id: u16 = 0x1B36;
-    let device_id: u16 = 0x0010;
-    
-    print("Searching for QEMU NVMe Controller...\n");
+    // Find the first NVMe Controller (Class 0x01, Subclass 0x08, ProgIF 0x02)
+    print("Searching for NVMe Controller...\n");
     
     let mut msg = IpcMessage::new();
     msg.msg_type = 1; // REQUEST
-    msg.msg_id = MSG_PCI_FIND_DEVICE;
-    msg.inline_data[0..2].copy_from_slice(&vendor_id.to_le_bytes());
-    msg.inline_data[2..4].copy_from_slice(&device_id.to_le_bytes());
+    msg.msg_id = MSG_PCI_FIND_BY_CLASS;
+    msg.inline_data[0] = PCI_CLASS_STORAGE;
+    msg.inline_data[1] = PCI_SUBCLASS_NVM;
+    msg.inline_data[2] = PCI_PROG_IF_NVME;
+    msg.inline_data[3] = 0; // First match
     msg.inline_size = 4;
     
     unsafe {
@@ -63,7 +63,7 @@ pub extern "C" fn _start() -> ! {
         sys_ipc_receive(PCI_DRIVER_PORT, &mut msg);
     }
     
-    if msg.inline_data[0] != 0xFF {
+    if msg.inline_size >= 8 {
         let bus = msg.inline_data[0];
         let dev = msg.inline_data[1];
         let func = msg.inline_data[2];
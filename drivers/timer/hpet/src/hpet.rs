@@ -0,0 +1,124 @@
+//! HPET table and registers
+//!
+//! The ACPI "HPET" table gives the physical address of the timer block.
+//! The block's capability register holds the counter period in
+//! femtoseconds, the number of comparators and whether the main counter
+//! is 64 bits wide; a 32-bit counter wraps (about every 5 minutes at
+//! 14.318 MHz) and is extended in software.
+
+/// Table signature of the HPET description table
+pub const HPET_SIGNATURE: &[u8; 4] = b"HPET";
+
+/// Header, event timer block id, base address, number, minimum tick and
+/// page protection
+pub const HPET_TABLE_SIZE: usize = 56;
+
+/// Generic address space id of system memory
+const GAS_SYSTEM_MEMORY: u8 = 0;
+
+/// Size of the register block to map
+pub const HPET_REGS_SIZE: usize = 1024;
+
+// Registers
+pub const HPET_CAPABILITIES: usize = 0x000;
+pub const HPET_CONFIG: usize = 0x010;
+pub const HPET_MAIN_COUNTER: usize = 0x0F0;
+
+// HPET_CONFIG bits
+pub const CONFIG_ENABLE: u64 = 1 << 0;
+pub const CONFIG_LEGACY_ROUTE: u64 = 1 << 1;
+
+// Capability bits
+const CAP_COUNTER_64BIT: u64 = 1 << 13;
+const CAP_LAST_TIMER_SHIFT: u64 = 8;
+const CAP_LAST_TIMER_MASK: u64 = 0x1F;
+
+/// Longest period the specification allows (100 ns)
+pub const MAX_PERIOD_FS: u32 = 100_000_000;
+
+const FS_PER_NS: u128 = 1_000_000;
+
+/// What the ACPI table says about one timer block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HpetTable {
+    pub base: u64,
+    pub number: u8,
+    /// Smallest comparator distance, in counter ticks, that still fires
+    pub min_tick: u16,
+}
+
+/// Parse an HPET table, header included. Only memory-mapped blocks are
+/// usable.
+pub fn parse_hpet_table(table: &[u8]) -> Option<HpetTable> {
+    if table.len() < HPET_TABLE_SIZE || table[0..4] != *HPET_SIGNATURE {
+        return None;
+    }
+    let length = u32::from_le_bytes([table[4], table[5], table[6], table[7]]) as usize;
+    if length < HPET_TABLE_SIZE || table[40] != GAS_SYSTEM_MEMORY {
+        return None;
+    }
+
+    let mut base = [0u8; 8];
+    base.copy_from_slice(&table[44..52]);
+    let base = u64::from_le_bytes(base);
+    if base == 0 {
+        return None;
+    }
+    Some(HpetTable {
+        base,
+        number: table[52],
+        min_tick: u16::from_le_bytes([table[53], table[54]]),
+    })
+}
+
+/// Decoded capability register
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Counter tick length in femtoseconds
+    pub period_fs: u32,
+    pub timers: u8,
+    pub counter_64bit: bool,
+}
+
+impl Capabilities {
+    /// `None` for a period the specification does not allow, which is
+    /// also what an absent block reads as
+    pub fn decode(value: u64) -> Option<Self> {
+        let period_fs = (value >> 32) as u32;
+        if period_fs == 0 || period_fs > MAX_PERIOD_FS {
+            return None;
+        }
+        Some(Capabilities {
+            period_fs,
+            timers: ((value >> CAP_LAST_TIMER_SHIFT) & CAP_LAST_TIMER_MASK) as u8 + 1,
+            counter_64bit: value & CAP_COUNTER_64BIT != 0,
+        })
+    }
+}
+
+/// Nanoseconds in `ticks` of `period_fs` each
+pub fn ticks_to_ns(ticks: u64, period_fs: u32) -> u64 {
+    (ticks as u128 * period_fs as u128 / FS_PER_NS).min(u64::MAX as u128) as u64
+}
+
+/// Widens a 32-bit counter to 64 bits. It must see the counter at least
+/// once per wrap.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CounterExtender {
+    last: u32,
+    high: u64,
+}
+
+impl CounterExtender {
+    pub const fn new() -> Self {
+        CounterExtender { last: 0, high: 0 }
+    }
+
+    pub fn extend(&mut self, raw: u32) -> u64 {
+        if raw < self.last {
+            self.high += 1 << 32;
+        }
+        self.last = raw;
+        self.high | raw as u64
+    }
+}
@@ -0,0 +1,264 @@
+//! User-Space HPET Clock Driver
+//!
+//! Maps the High Precision Event Timer the ACPI tables describe and serves
+//! a monotonic nanosecond clock from its main counter, plus one-shot
+//! timers: a client names a port and a cookie and gets a notification on
+//! that port once the delay has passed. Timers are kept against the
+//! counter rather than the HPET comparators, since a comparator interrupt
+//! cannot wake this driver out of its IPC wait; the driver sleeps in the
+//! wait until the last millisecond and spins on the counter for the rest.
+//!
+//! Without an HPET (no table, or a block that does not answer) the same
+//! service runs on `SYS_GET_UPTIME_MS`, at millisecond resolution, and
+//! `HPET_OP_INFO` reports `CLOCK_SOURCE_UPTIME`.
+
+#![no_std]
+#![no_main]
+
+mod hpet;
+mod service;
+mod timers;
+
+use core::panic::PanicInfo;
+use driver_framework::ipc::{
+    ipc_create_port, ipc_receive, ipc_receive_timeout, ipc_reply, ipc_send, IpcMessage, IPC_MSG_NOTIFICATION,
+    IPC_MSG_RESPONSE,
+};
+use driver_framework::mmio::MmioRegion;
+use driver_framework::syscalls::get_uptime_ms;
+use driver_framework::{DriverError, DriverResult};
+use hpet::*;
+use timers::{OneShot, TimerQueue};
+
+// IPC operations
+/// [] -> [status][now ns:8]
+pub const HPET_OP_NOW: u64 = 1;
+/// [] -> [status][source][period fs:8][timers]; the uptime clock reports
+/// its millisecond period and no timers
+pub const HPET_OP_INFO: u64 = 2;
+/// [delay ns:8][port:8][cookie:8] -> [status]; `HPET_EVENT_EXPIRED` goes
+/// to `port` once the delay has passed
+pub const HPET_OP_ONESHOT: u64 = 3;
+/// [port:8][cookie:8] -> [status]
+pub const HPET_OP_CANCEL: u64 = 4;
+
+/// Notification of an expired one-shot timer: [cookie:8][now ns:8]
+pub const HPET_EVENT_EXPIRED: u64 = 1;
+
+pub const HPET_STATUS_OK: u8 = 0;
+pub const HPET_STATUS_ERROR: u8 = 1;
+/// Every timer slot is in use
+pub const HPET_STATUS_BUSY: u8 = 2;
+
+/// Clock behind `HPET_OP_NOW`
+pub const CLOCK_SOURCE_HPET: u8 = 0;
+pub const CLOCK_SOURCE_UPTIME: u8 = 1;
+
+const NS_PER_MS: u64 = 1_000_000;
+const FS_PER_NS: u64 = 1_000_000;
+
+/// Remaining time below which a timer is waited out on the counter
+const SPIN_THRESHOLD_NS: u64 = NS_PER_MS;
+
+struct HpetClock {
+    port: u64,
+    regs: Option<MmioRegion>,
+    caps: Option<Capabilities>,
+    extender: CounterExtender,
+    timers: TimerQueue,
+}
+
+impl HpetClock {
+    fn new() -> Self {
+        HpetClock {
+            port: 0,
+            regs: None,
+            caps: None,
+            extender: CounterExtender::new(),
+            timers: TimerQueue::new(),
+        }
+    }
+
+    fn init(&mut self) -> DriverResult<()> {
+        self.port = ipc_create_port().map_err(|_| DriverError::IoError)?;
+        service::register_service_name(b"hpet", self.port).map_err(|_| DriverError::IoError)
+    }
+
+    /// Find and enable the first HPET block
+    fn start(&mut self) -> DriverResult<()> {
+        let (phys, length) = service::find_acpi_table(HPET_SIGNATURE).map_err(|_| DriverError::DeviceNotFound)?;
+        if (length as usize) < HPET_TABLE_SIZE {
+            return Err(DriverError::DeviceNotFound);
+        }
+
+        let mut table = [0u8; HPET_TABLE_SIZE];
+        {
+            let mapped = MmioRegion::map(phys, HPET_TABLE_SIZE).map_err(|_| DriverError::IoError)?;
+            for (i, byte) in table.iter_mut().enumerate() {
+                *byte = mapped.read_u8(i);
+            }
+        }
+        let table = parse_hpet_table(&table).ok_or(DriverError::DeviceNotFound)?;
+
+        let regs = MmioRegion::map(table.base, HPET_REGS_SIZE).map_err(|_| DriverError::IoError)?;
+        let caps = Capabilities::decode(regs.read_u64(HPET_CAPABILITIES)).ok_or(DriverError::DeviceNotFound)?;
+
+        // The counter may already be running for someone else; never reset
+        // it, only make sure it counts and leave the PIT and RTC their IRQs
+        let config = regs.read_u64(HPET_CONFIG);
+        regs.write_u64(HPET_CONFIG, (config | CONFIG_ENABLE) & !CONFIG_LEGACY_ROUTE);
+
+        self.regs = Some(regs);
+        self.caps = Some(caps);
+        Ok(())
+    }
+
+    /// Monotonic nanoseconds since the counter (or the system) started
+    fn hpet_now_ns(&mut self) -> u64 {
+        match (&self.regs, self.caps) {
+            (Some(regs), Some(caps)) => {
+                let ticks = if caps.counter_64bit {
+                    regs.read_u64(HPET_MAIN_COUNTER)
+                } else {
+                    self.extender.extend(regs.read_u32(HPET_MAIN_COUNTER))
+                };
+                ticks_to_ns(ticks, caps.period_fs)
+            }
+            _ => get_uptime_ms().saturating_mul(NS_PER_MS),
+        }
+    }
+
+    /// Longest the driver may go without reading a 32-bit counter (half a
+    /// wrap, so the extender never misses one)
+    fn max_wait_ms(&self) -> Option<u64> {
+        match self.caps {
+            Some(caps) if !caps.counter_64bit => Some((ticks_to_ns(1 << 31, caps.period_fs) / NS_PER_MS).max(1)),
+            _ => None,
+        }
+    }
+
+    /// Notify the owners of every timer that is due
+    fn fire_expired(&mut self) {
+        let now = self.hpet_now_ns();
+        while let Some(timer) = self.timers.take_expired(now) {
+            let mut event = IpcMessage::new();
+            event.msg_type = IPC_MSG_NOTIFICATION;
+            event.msg_id = HPET_EVENT_EXPIRED;
+            let mut data = [0u8; 16];
+            data[0..8].copy_from_slice(&timer.cookie.to_le_bytes());
+            data[8..16].copy_from_slice(&now.to_le_bytes());
+            event.set_inline_data(&data);
+            // A client that went away just misses its event
+            let _ = ipc_send(timer.port, &event);
+        }
+    }
+
+    /// How long to wait for a request before the next timer is due, or
+    /// `None` to wait indefinitely. Timers closer than `SPIN_THRESHOLD_NS`
+    /// are waited out here.
+    fn next_wait_ms(&mut self) -> Option<u64> {
+        let wait = match self.timers.next_deadline() {
+            Some(deadline) => {
+                let mut now = self.hpet_now_ns();
+                if deadline.saturating_sub(now) < SPIN_THRESHOLD_NS {
+                    while now < deadline {
+                        core::hint::spin_loop();
+                        now = self.hpet_now_ns();
+                    }
+                    self.fire_expired();
+                    return self.next_wait_ms();
+                }
+                Some((deadline - now - SPIN_THRESHOLD_NS) / NS_PER_MS)
+            }
+            None => None,
+        };
+
+        match (wait, self.max_wait_ms()) {
+            (Some(wait), Some(max)) => Some(wait.clamp(1, max)),
+            (Some(wait), None) => Some(wait.max(1)),
+            (None, max) => max,
+        }
+    }
+
+    fn handle_ipc(&mut self, msg: &IpcMessage) -> IpcMessage {
+        let mut response = IpcMessage::new();
+        response.msg_type = IPC_MSG_RESPONSE;
+        response.msg_id = msg.msg_id;
+
+        let data = msg.get_inline_data();
+        let word = |i: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&data[i * 8..i * 8 + 8]);
+            u64::from_le_bytes(bytes)
+        };
+
+        match msg.msg_id {
+            HPET_OP_NOW => {
+                let mut reply = [0u8; 9];
+                reply[1..9].copy_from_slice(&self.hpet_now_ns().to_le_bytes());
+                response.set_inline_data(&reply);
+            }
+            HPET_OP_INFO => {
+                let mut reply = [0u8; 11];
+                match self.caps {
+                    Some(caps) => {
+                        reply[1] = CLOCK_SOURCE_HPET;
+                        reply[2..10].copy_from_slice(&(caps.period_fs as u64).to_le_bytes());
+                        reply[10] = caps.timers;
+                    }
+                    None => {
+                        reply[1] = CLOCK_SOURCE_UPTIME;
+                        reply[2..10].copy_from_slice(&(NS_PER_MS * FS_PER_NS).to_le_bytes());
+                    }
+                }
+                response.set_inline_data(&reply);
+            }
+            HPET_OP_ONESHOT if data.len() >= 24 && word(1) != 0 => {
+                let timer = OneShot {
+                    deadline_ns: self.hpet_now_ns().saturating_add(word(0)),
+                    port: word(1),
+                    cookie: word(2),
+                };
+                let status = match self.timers.arm(timer) {
+                    Ok(()) => HPET_STATUS_OK,
+                    Err(()) => HPET_STATUS_BUSY,
+                };
+                response.set_inline_data(&[status]);
+            }
+            HPET_OP_CANCEL if data.len() >= 16 => {
+                let status = if self.timers.cancel(word(0), word(1)) { HPET_STATUS_OK } else { HPET_STATUS_ERROR };
+                response.set_inline_data(&[status]);
+            }
+            _ => response.set_inline_data(&[HPET_STATUS_ERROR]),
+        }
+        response
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let mut clock = HpetClock::new();
+    if clock.init().is_err() {
+        loop {}
+    }
+    // Without an HPET the uptime clock stands in
+    let _ = clock.start();
+
+    let mut msg = IpcMessage::new();
+    loop {
+        let received = match clock.next_wait_ms() {
+            Some(wait_ms) => ipc_receive_timeout(clock.port, &mut msg, wait_ms).is_ok(),
+            None => ipc_receive(clock.port, &mut msg).is_ok(),
+        };
+        if received {
+            let response = clock.handle_ipc(&msg);
+            let _ = ipc_reply(&msg, &response);
+        }
+        clock.fire_expired();
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,79 @@
+//! Service registry and ACPI access
+//!
+//! The driver publishes its port as "hpet" and asks the ACPI service where
+//! the HPET table is.
+
+use driver_framework::ipc::{
+    ipc_create_port, ipc_destroy_port, ipc_receive_timeout, ipc_send, IpcMessage, IPC_MSG_REQUEST,
+    IPC_MSG_RESPONSE,
+};
+
+/// Port of the service name registry (hosted by the device manager)
+const SERVICE_REGISTRY_PORT: u64 = 90;
+/// [port:8][name] -> [status:1]
+const REGISTRY_OP_REGISTER_NAME: u64 = 5;
+const REGISTRY_REPLY_TIMEOUT_MS: u64 = 500;
+
+/// Well-known port of the ACPI service
+const ACPI_SERVICE_PORT: u64 = 106;
+/// [signature:4][instance] -> [status][physical address:8][length:4]
+const ACPI_OP_FIND_TABLE: u64 = 4;
+const ACPI_STATUS_OK: u8 = 0;
+const ACPI_REPLY_TIMEOUT_MS: u64 = 1000;
+
+/// Send a request and wait for its response on a private reply port
+fn request(port: u64, op: u64, data: &[u8], timeout_ms: u64) -> Result<IpcMessage, ()> {
+    let reply_port = ipc_create_port()?;
+
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_REQUEST;
+    msg.msg_id = op;
+    msg.set_inline_data(data);
+    msg.reply_port = reply_port;
+
+    let mut reply = IpcMessage::new();
+    let result = ipc_send(port, &msg)
+        .map_err(|_| ())
+        .and_then(|_| ipc_receive_timeout(reply_port, &mut reply, timeout_ms).map_err(|_| ()));
+    let _ = ipc_destroy_port(reply_port);
+    result?;
+
+    if reply.msg_type != IPC_MSG_RESPONSE || reply.msg_id != op {
+        return Err(());
+    }
+    Ok(reply)
+}
+
+/// Publish this driver's port under `name`, replacing any earlier entry
+pub fn register_service_name(name: &[u8], port: u64) -> Result<(), ()> {
+    let mut data = [0u8; 64];
+    let len = name.len().min(data.len() - 8);
+    data[0..8].copy_from_slice(&port.to_le_bytes());
+    data[8..8 + len].copy_from_slice(&name[..len]);
+
+    let reply = request(SERVICE_REGISTRY_PORT, REGISTRY_OP_REGISTER_NAME, &data[..8 + len], REGISTRY_REPLY_TIMEOUT_MS)?;
+    if reply.inline_size == 0 || reply.inline_data[0] != 0 {
+        return Err(());
+    }
+    Ok(())
+}
+
+/// Physical address and length of the first ACPI table with `signature`
+pub fn find_acpi_table(signature: &[u8; 4]) -> Result<(u64, u32), ()> {
+    let mut data = [0u8; 5];
+    data[0..4].copy_from_slice(signature);
+
+    let reply = request(ACPI_SERVICE_PORT, ACPI_OP_FIND_TABLE, &data, ACPI_REPLY_TIMEOUT_MS)?;
+    if reply.inline_size < 13 || reply.inline_data[0] != ACPI_STATUS_OK {
+        return Err(());
+    }
+    let mut phys = [0u8; 8];
+    phys.copy_from_slice(&reply.inline_data[1..9]);
+    let length = u32::from_le_bytes([
+        reply.inline_data[9],
+        reply.inline_data[10],
+        reply.inline_data[11],
+        reply.inline_data[12],
+    ]);
+    Ok((u64::from_le_bytes(phys), length))
+}
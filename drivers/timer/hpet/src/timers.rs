@@ -0,0 +1,66 @@
+//! Pending one-shot timers
+//!
+//! Each timer belongs to a client port and is named by a cookie the client
+//! picks; arming the same (port, cookie) again moves the deadline.
+
+/// Timers pending at once, over all clients
+pub const MAX_TIMERS: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OneShot {
+    pub deadline_ns: u64,
+    pub port: u64,
+    pub cookie: u64,
+}
+
+pub struct TimerQueue {
+    timers: [Option<OneShot>; MAX_TIMERS],
+}
+
+impl TimerQueue {
+    pub const fn new() -> Self {
+        TimerQueue { timers: [None; MAX_TIMERS] }
+    }
+
+    /// Add a timer, or move the deadline of the one with the same port and
+    /// cookie. Fails when every slot is taken.
+    pub fn arm(&mut self, timer: OneShot) -> Result<(), ()> {
+        let slot = self
+            .timers
+            .iter()
+            .position(|t| t.is_some_and(|t| t.port == timer.port && t.cookie == timer.cookie))
+            .or_else(|| self.timers.iter().position(|t| t.is_none()))
+            .ok_or(())?;
+        self.timers[slot] = Some(timer);
+        Ok(())
+    }
+
+    /// Drop a pending timer; false if it was not pending
+    pub fn cancel(&mut self, port: u64, cookie: u64) -> bool {
+        for slot in self.timers.iter_mut() {
+            if slot.is_some_and(|t| t.port == port && t.cookie == cookie) {
+                *slot = None;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Earliest pending deadline
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.timers.iter().flatten().map(|t| t.deadline_ns).min()
+    }
+
+    /// Remove and return the earliest timer due at `now_ns`
+    pub fn take_expired(&mut self, now_ns: u64) -> Option<OneShot> {
+        let slot = self
+            .timers
+            .iter()
+            .enumerate()
+            .filter_map(|(i, t)| t.map(|t| (i, t.deadline_ns)))
+            .filter(|&(_, deadline)| deadline <= now_ns)
+            .min_by_key(|&(_, deadline)| deadline)?
+            .0;
+        self.timers[slot].take()
+    }
+}
@@ -0,0 +1,327 @@
+/**
+ * @file graphics_service.rs
+ * @brief User-space graphics service
+ *
+ * Owns the linear framebuffer the bootloader set up. Clients draw into a
+ * back buffer with blits from shared memory; present copies what changed
+ * to the screen.
+ */
+
+use core::panic::PanicInfo;
+
+use driver_framework::ipc::{ipc_receive, ipc_reply, IpcMessage, IPC_MSG_RESPONSE};
+
+mod surface;
+use surface::{blit, Damage, PixelFormat, Rect, Target};
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    loop {}
+}
+
+// Syscall wrappers
+extern "C" {
+    fn sys_ipc_register_port(port: u32) -> i32;
+    fn sys_fb_get_info(info: *mut FramebufferInfo) -> i32;
+    fn sys_mmio_map(paddr: u64, size: u64) -> u64;
+    fn sys_shm_create(size: u64, flags: u32) -> u64;
+    fn sys_shm_map(shm_id: u64, vaddr: u64, flags: u32) -> u64;
+    fn sys_shm_unmap(shm_id: u64, vaddr: u64) -> i32;
+    fn sys_shm_get_info(shm_id: u64, size: *mut u64, refcount: *mut u64) -> i32;
+}
+
+/// SYS_FB_GET_INFO result (framebuffer_user_info_t)
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FramebufferInfo {
+    phys_base: u64,
+    width: u32,
+    height: u32,
+    pitch: u32,
+    bpp: u32,
+    red_mask: u32,
+    green_mask: u32,
+    blue_mask: u32,
+    reserved_mask: u32,
+}
+
+// Graphics service IPC port
+const GRAPHICS_SERVICE_PORT: u32 = 210;
+
+// Message ids
+/// [] -> [phys base u64, width u32, height u32, pitch u32, bpp u32,
+/// red mask u32, green mask u32, blue mask u32]
+const MSG_GFX_GET_INFO: u64 = 1;
+/// [x i32, y i32, width u32, height u32, shm id u64, stride u32,
+/// offset u32]: copy XRGB pixels starting `offset` bytes into a shared
+/// memory region into the back buffer, clipped to the screen
+const MSG_GFX_BLIT: u64 = 2;
+/// [] -> []: copy everything blitted since the last present to the screen
+const MSG_GFX_PRESENT: u64 = 3;
+/// [shm id u64] -> []: the region will not be blitted from again, sent by
+/// the window manager when the window showing it is destroyed
+const MSG_GFX_RELEASE_SURFACE: u64 = 4;
+
+/// Every answer has this id and starts with a status u32 (GFX_OK or an
+/// error). Answers go to the request's reply port; requests without one
+/// get none.
+const MSG_GFX_REPLY: u64 = 64;
+
+const GFX_OK: u32 = 0;
+const GFX_ERR_UNKNOWN: u32 = 1;
+const GFX_ERR_NO_FRAMEBUFFER: u32 = 2;
+const GFX_ERR_BAD_SURFACE: u32 = 3;
+
+/// Client regions kept mapped between blits
+const MAX_MAPPED_SURFACES: usize = 8;
+
+/// Map shared memory without write access
+const SHM_FLAG_READ_ONLY: u32 = 1 << 0;
+
+#[derive(Clone, Copy)]
+struct MappedSurface {
+    shm_id: u64,
+    vaddr: u64,
+    size: usize,
+}
+
+struct Display {
+    info: FramebufferInfo,
+    format: PixelFormat,
+    /// Mapped framebuffer
+    screen: *mut u8,
+    /// Same layout as the screen
+    back: *mut u8,
+}
+
+static mut DISPLAY: Option<Display> = None;
+static mut DAMAGE: Damage = Damage::new();
+static mut SURFACES: [Option<MappedSurface>; MAX_MAPPED_SURFACES] = [None; MAX_MAPPED_SURFACES];
+static mut NEXT_SURFACE_SLOT: usize = 0;
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    // Without a framebuffer the service still answers, with errors, so
+    // clients do not hang waiting for it
+    unsafe {
+        DISPLAY = init_display();
+    }
+
+    // Register IPC port
+    unsafe {
+        sys_ipc_register_port(GRAPHICS_SERVICE_PORT);
+    }
+
+    // Main service loop
+    let mut msg = IpcMessage::new();
+    loop {
+        if ipc_receive(GRAPHICS_SERVICE_PORT as u64, &mut msg).is_err() {
+            continue;
+        }
+
+        let response = handle_message(&msg);
+        let _ = ipc_reply(&msg, &response);
+    }
+}
+
+fn init_display() -> Option<Display> {
+    let mut info = FramebufferInfo {
+        phys_base: 0,
+        width: 0,
+        height: 0,
+        pitch: 0,
+        bpp: 0,
+        red_mask: 0,
+        green_mask: 0,
+        blue_mask: 0,
+        reserved_mask: 0,
+    };
+
+    unsafe {
+        if sys_fb_get_info(&mut info) != 0 || info.phys_base == 0 {
+            return None;
+        }
+    }
+
+    let format = PixelFormat::from_masks(info.bpp, info.red_mask, info.green_mask, info.blue_mask)?;
+    if (info.pitch as usize) < info.width as usize * format.bytes_per_pixel {
+        return None;
+    }
+    let size = info.pitch as u64 * info.height as u64;
+
+    unsafe {
+        let screen = sys_mmio_map(info.phys_base, size);
+        if screen == 0 {
+            return None;
+        }
+
+        let back_id = sys_shm_create(size, 0);
+        if back_id == 0 {
+            return None;
+        }
+        let back = sys_shm_map(back_id, 0, 0);
+        if back == 0 {
+            return None;
+        }
+
+        Some(Display {
+            info,
+            format,
+            screen: screen as *mut u8,
+            back: back as *mut u8,
+        })
+    }
+}
+
+fn handle_message(msg: &IpcMessage) -> IpcMessage {
+    // Needs no screen; surfaces may have been mapped by blits before
+    if msg.msg_id == MSG_GFX_RELEASE_SURFACE {
+        return handle_release_surface(msg);
+    }
+
+    let display = match unsafe { DISPLAY.as_ref() } {
+        Some(display) => display,
+        None => return create_reply(GFX_ERR_NO_FRAMEBUFFER),
+    };
+
+    match msg.msg_id {
+        MSG_GFX_GET_INFO => handle_get_info(display),
+        MSG_GFX_BLIT => handle_blit(display, msg),
+        MSG_GFX_PRESENT => handle_present(display),
+        _ => create_reply(GFX_ERR_UNKNOWN),
+    }
+}
+
+fn handle_get_info(display: &Display) -> IpcMessage {
+    let info = &display.info;
+    let mut response = create_reply(GFX_OK);
+    response.inline_data[4..12].copy_from_slice(&info.phys_base.to_le_bytes());
+    response.inline_data[12..16].copy_from_slice(&info.width.to_le_bytes());
+    response.inline_data[16..20].copy_from_slice(&info.height.to_le_bytes());
+    response.inline_data[20..24].copy_from_slice(&info.pitch.to_le_bytes());
+    response.inline_data[24..28].copy_from_slice(&info.bpp.to_le_bytes());
+    response.inline_data[28..32].copy_from_slice(&info.red_mask.to_le_bytes());
+    response.inline_data[32..36].copy_from_slice(&info.green_mask.to_le_bytes());
+    response.inline_data[36..40].copy_from_slice(&info.blue_mask.to_le_bytes());
+    response.inline_size = 40;
+    response
+}
+
+fn handle_blit(display: &Display, msg: &IpcMessage) -> IpcMessage {
+    let data = &msg.inline_data;
+    let rect = Rect {
+        x: i32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+        y: i32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+        width: u32::from_le_bytes([data[8], data[9], data[10], data[11]]),
+        height: u32::from_le_bytes([data[12], data[13], data[14], data[15]]),
+    };
+    let mut shm_id = [0u8; 8];
+    shm_id.copy_from_slice(&data[16..24]);
+    let shm_id = u64::from_le_bytes(shm_id);
+    let stride = u32::from_le_bytes([data[24], data[25], data[26], data[27]]) as usize;
+    let offset = u32::from_le_bytes([data[28], data[29], data[30], data[31]]) as usize;
+
+    let surface = match map_surface(shm_id) {
+        Some(surface) if offset <= surface.size => surface,
+        _ => return create_reply(GFX_ERR_BAD_SURFACE),
+    };
+
+    let info = &display.info;
+    let back_size = info.pitch as usize * info.height as usize;
+    let mut target = Target {
+        pixels: unsafe { core::slice::from_raw_parts_mut(display.back, back_size) },
+        pitch: info.pitch as usize,
+        width: info.width,
+        height: info.height,
+        format: display.format,
+    };
+    let src = unsafe { core::slice::from_raw_parts((surface.vaddr as *const u8).add(offset), surface.size - offset) };
+
+    // Off-screen is fine, a source too small for the rect is not
+    if rect.clip(info.width, info.height).is_some() {
+        match blit(&mut target, rect, src, stride) {
+            Some(written) => unsafe { DAMAGE.add(written) },
+            None => return create_reply(GFX_ERR_BAD_SURFACE),
+        }
+    }
+
+    create_reply(GFX_OK)
+}
+
+fn handle_release_surface(msg: &IpcMessage) -> IpcMessage {
+    if msg.inline_size < 8 {
+        return create_reply(GFX_ERR_BAD_SURFACE);
+    }
+    let mut shm_id = [0u8; 8];
+    shm_id.copy_from_slice(&msg.inline_data[0..8]);
+    unmap_surface(u64::from_le_bytes(shm_id));
+    create_reply(GFX_OK)
+}
+
+fn handle_present(display: &Display) -> IpcMessage {
+    let rect = match unsafe { DAMAGE.take() } {
+        Some(rect) => rect,
+        None => return create_reply(GFX_OK),
+    };
+
+    let pitch = display.info.pitch as usize;
+    let bpp = display.format.bytes_per_pixel;
+    let row_bytes = rect.width as usize * bpp;
+    for row in rect.y as usize..rect.y as usize + rect.height as usize {
+        let offset = row * pitch + rect.x as usize * bpp;
+        unsafe {
+            core::ptr::copy_nonoverlapping(display.back.add(offset), display.screen.add(offset), row_bytes);
+        }
+    }
+
+    create_reply(GFX_OK)
+}
+
+/// Client region by id, mapping it on first use. The oldest mapping is
+/// unmapped once MAX_MAPPED_SURFACES are in use.
+fn map_surface(shm_id: u64) -> Option<MappedSurface> {
+    unsafe {
+        if let Some(surface) = SURFACES.iter().flatten().find(|s| s.shm_id == shm_id) {
+            return Some(*surface);
+        }
+
+        let mut size = 0u64;
+        let mut refcount = 0u64;
+        if shm_id == 0 || sys_shm_get_info(shm_id, &mut size, &mut refcount) != 0 {
+            return None;
+        }
+        let vaddr = sys_shm_map(shm_id, 0, SHM_FLAG_READ_ONLY);
+        if vaddr == 0 {
+            return None;
+        }
+
+        let surface = MappedSurface { shm_id, vaddr, size: size as usize };
+        if let Some(evicted) = SURFACES[NEXT_SURFACE_SLOT].take() {
+            let _ = sys_shm_unmap(evicted.shm_id, evicted.vaddr);
+        }
+        SURFACES[NEXT_SURFACE_SLOT] = Some(surface);
+        NEXT_SURFACE_SLOT = (NEXT_SURFACE_SLOT + 1) % MAX_MAPPED_SURFACES;
+        Some(surface)
+    }
+}
+
+/// Unmap a client region if it is mapped
+fn unmap_surface(shm_id: u64) {
+    unsafe {
+        for slot in SURFACES.iter_mut() {
+            if slot.map_or(false, |s| s.shm_id == shm_id) {
+                if let Some(surface) = slot.take() {
+                    let _ = sys_shm_unmap(surface.shm_id, surface.vaddr);
+                }
+            }
+        }
+    }
+}
+
+fn create_reply(status: u32) -> IpcMessage {
+    let mut response = IpcMessage::new();
+    response.msg_type = IPC_MSG_RESPONSE;
+    response.msg_id = MSG_GFX_REPLY;
+    response.set_inline_data(&status.to_le_bytes());
+    response
+}
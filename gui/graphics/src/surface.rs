@@ -0,0 +1,169 @@
+//! Pixel formats and blitting
+//!
+//! Clients hand over 32-bit XRGB pixels. They are converted to the
+//! framebuffer's format as they are copied into the back buffer, so
+//! present() is a plain row copy of the damaged area.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    /// Part of the rect inside a width x height screen, `None` if nothing is
+    pub fn clip(&self, width: u32, height: u32) -> Option<Rect> {
+        let left = (self.x as i64).max(0);
+        let top = (self.y as i64).max(0);
+        let right = (self.x as i64 + self.width as i64).min(width as i64);
+        let bottom = (self.y as i64 + self.height as i64).min(height as i64);
+        if left >= right || top >= bottom {
+            return None;
+        }
+        Some(Rect {
+            x: left as i32,
+            y: top as i32,
+            width: (right - left) as u32,
+            height: (bottom - top) as u32,
+        })
+    }
+
+    /// Smallest rect covering both
+    pub fn union(&self, other: &Rect) -> Rect {
+        let left = self.x.min(other.x);
+        let top = self.y.min(other.y);
+        let right = (self.x as i64 + self.width as i64).max(other.x as i64 + other.width as i64);
+        let bottom = (self.y as i64 + self.height as i64).max(other.y as i64 + other.height as i64);
+        Rect {
+            x: left,
+            y: top,
+            width: (right - left as i64) as u32,
+            height: (bottom - top as i64) as u32,
+        }
+    }
+}
+
+/// Area of the back buffer not yet copied to the screen
+pub struct Damage {
+    rect: Option<Rect>,
+}
+
+impl Damage {
+    pub const fn new() -> Self {
+        Damage { rect: None }
+    }
+
+    pub fn add(&mut self, rect: Rect) {
+        self.rect = Some(match self.rect {
+            Some(current) => current.union(&rect),
+            None => rect,
+        });
+    }
+
+    /// Damaged area, leaving nothing damaged
+    pub fn take(&mut self) -> Option<Rect> {
+        self.rect.take()
+    }
+}
+
+/// One color channel: where it sits and how many bits it has
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Channel {
+    pub shift: u32,
+    pub bits: u32,
+}
+
+impl Channel {
+    fn from_mask(mask: u32) -> Option<Channel> {
+        if mask == 0 {
+            return None;
+        }
+        let shift = mask.trailing_zeros();
+        let bits = (mask >> shift).trailing_ones();
+        if bits > 8 {
+            return None;
+        }
+        Some(Channel { shift, bits })
+    }
+
+    /// Place an 8-bit channel value, dropping the bits that do not fit
+    fn encode(&self, value: u32) -> u32 {
+        (value >> (8 - self.bits)) << self.shift
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PixelFormat {
+    pub bytes_per_pixel: usize,
+    pub red: Channel,
+    pub green: Channel,
+    pub blue: Channel,
+}
+
+impl PixelFormat {
+    /// Format from the bootloader's description; `None` for depths and
+    /// masks the service cannot draw
+    pub fn from_masks(bpp: u32, red_mask: u32, green_mask: u32, blue_mask: u32) -> Option<PixelFormat> {
+        let bytes_per_pixel = match bpp {
+            16 | 24 | 32 => (bpp / 8) as usize,
+            _ => return None,
+        };
+        Some(PixelFormat {
+            bytes_per_pixel,
+            red: Channel::from_mask(red_mask)?,
+            green: Channel::from_mask(green_mask)?,
+            blue: Channel::from_mask(blue_mask)?,
+        })
+    }
+
+    /// Framebuffer value for an XRGB pixel
+    pub fn encode(&self, xrgb: u32) -> u32 {
+        self.red.encode((xrgb >> 16) & 0xFF)
+            | self.green.encode((xrgb >> 8) & 0xFF)
+            | self.blue.encode(xrgb & 0xFF)
+    }
+}
+
+/// Destination of a blit: a buffer laid out like the framebuffer
+pub struct Target<'a> {
+    pub pixels: &'a mut [u8],
+    pub pitch: usize,
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+}
+
+/// Copy XRGB pixels for `rect` from `src` (rows `src_stride` bytes apart)
+/// into the target, clipped to it. Returns the area actually written, or
+/// `None` if it was off-screen or `src` is too short for the rect.
+pub fn blit(target: &mut Target, rect: Rect, src: &[u8], src_stride: usize) -> Option<Rect> {
+    if rect.width == 0 || rect.height == 0 || target.pixels.len() < target.pitch * target.height as usize {
+        return None;
+    }
+    let row_bytes = rect.width as usize * 4;
+    let needed = src_stride.checked_mul(rect.height as usize - 1)?.checked_add(row_bytes)?;
+    if src_stride < row_bytes || src.len() < needed {
+        return None;
+    }
+
+    let clipped = rect.clip(target.width, target.height)?;
+    let skip_x = (clipped.x as i64 - rect.x as i64) as usize;
+    let skip_y = (clipped.y as i64 - rect.y as i64) as usize;
+    let bpp = target.format.bytes_per_pixel;
+
+    for row in 0..clipped.height as usize {
+        let src_row = (skip_y + row) * src_stride + skip_x * 4;
+        let dst_row = (clipped.y as usize + row) * target.pitch + clipped.x as usize * bpp;
+        for col in 0..clipped.width as usize {
+            let s = src_row + col * 4;
+            let xrgb = u32::from_le_bytes([src[s], src[s + 1], src[s + 2], src[s + 3]]);
+            let value = target.format.encode(xrgb).to_le_bytes();
+            let d = dst_row + col * bpp;
+            target.pixels[d..d + bpp].copy_from_slice(&value[..bpp]);
+        }
+    }
+
+    Some(clipped)
+}
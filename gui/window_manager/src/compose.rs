@@ -0,0 +1,148 @@
+//! Compositing
+//!
+//! Windows are painted from the bottom of the stack up, so each one covers
+//! whatever lies below it. Only the canvas's clip rect is painted, and a
+//! window that a higher one hides there entirely is skipped. Each window is
+//! drawn as a frame, a border and a title bar showing focus, around a body
+//! showing the client's committed frame. Body not covered by the frame,
+//! or all of it before the first commit, is plain.
+
+use crate::window::{Rect, Window};
+
+// XRGB colors
+pub const DESKTOP_COLOR: u32 = 0x002B_3A4A;
+pub const BORDER_COLOR: u32 = 0x0010_1010;
+pub const TITLE_COLOR: u32 = 0x0060_6060;
+pub const TITLE_FOCUSED_COLOR: u32 = 0x00B0_2030;
+pub const BODY_COLOR: u32 = 0x00F0_F0F0;
+
+pub const BORDER_WIDTH: u32 = 1;
+pub const TITLE_BAR_HEIGHT: u32 = 20;
+
+/// Client pixels for a window's body, rows `stride` pixels apart
+pub struct Content<'a> {
+    pub pixels: &'a [u32],
+    pub width: u32,
+    pub height: u32,
+    pub stride: usize,
+}
+
+/// Screen-sized XRGB pixel buffer, rows `width` pixels apart. Drawing
+/// only touches pixels inside `clip`.
+pub struct Canvas<'a> {
+    pub pixels: &'a mut [u32],
+    pub width: u32,
+    pub height: u32,
+    pub clip: Rect,
+}
+
+impl<'a> Canvas<'a> {
+    fn bounds(&self) -> Rect {
+        Rect { x: 0, y: 0, width: self.width, height: self.height }
+    }
+
+    /// Fill the part of `rect` inside the clip rect and the canvas
+    pub fn fill(&mut self, rect: Rect, color: u32) {
+        let area = match rect.intersect(&self.clip).and_then(|r| r.intersect(&self.bounds())) {
+            Some(area) => area,
+            None => return,
+        };
+
+        let (left, right) = (area.x as usize, area.x as usize + area.width as usize);
+        for row in area.y as usize..area.y as usize + area.height as usize {
+            let start = row * self.width as usize;
+            self.pixels[start + left..start + right].fill(color);
+        }
+    }
+
+    /// Copy `content` with its top-left corner at the top-left of `area`,
+    /// keeping to `area`, the clip rect and the canvas
+    pub fn copy(&mut self, area: Rect, content: &Content) {
+        let placed = Rect { x: area.x, y: area.y, width: content.width, height: content.height };
+        let visible = match placed
+            .intersect(&area)
+            .and_then(|r| r.intersect(&self.clip))
+            .and_then(|r| r.intersect(&self.bounds()))
+        {
+            Some(visible) => visible,
+            None => return,
+        };
+
+        let skip_x = (visible.x as i64 - area.x as i64) as usize;
+        let skip_y = (visible.y as i64 - area.y as i64) as usize;
+        let width = visible.width as usize;
+        for row in 0..visible.height as usize {
+            let src = (skip_y + row) * content.stride + skip_x;
+            let dst = (visible.y as usize + row) * self.width as usize + visible.x as usize;
+            self.pixels[dst..dst + width].copy_from_slice(&content.pixels[src..src + width]);
+        }
+    }
+}
+
+/// Part of a window below the title bar, inside the border; clients draw
+/// this area
+pub fn content_rect(window: &Window) -> Rect {
+    let outer = window.rect();
+    let inner_width = outer.width.saturating_sub(2 * BORDER_WIDTH);
+    let inner_height = outer.height.saturating_sub(2 * BORDER_WIDTH);
+    let title_height = TITLE_BAR_HEIGHT.min(inner_height);
+    Rect {
+        x: outer.x.saturating_add(BORDER_WIDTH as i32),
+        y: outer.y.saturating_add((BORDER_WIDTH + title_height) as i32),
+        width: inner_width,
+        height: inner_height - title_height,
+    }
+}
+
+/// Border, title bar and body of one window
+pub fn draw_window(canvas: &mut Canvas, window: &Window, focused: bool, content: Option<&Content>) {
+    let outer = window.rect();
+    canvas.fill(outer, BORDER_COLOR);
+
+    let inner_width = outer.width.saturating_sub(2 * BORDER_WIDTH);
+    let inner_height = outer.height.saturating_sub(2 * BORDER_WIDTH);
+    let title_height = TITLE_BAR_HEIGHT.min(inner_height);
+    let x = outer.x.saturating_add(BORDER_WIDTH as i32);
+    let y = outer.y.saturating_add(BORDER_WIDTH as i32);
+
+    let title_color = if focused { TITLE_FOCUSED_COLOR } else { TITLE_COLOR };
+    canvas.fill(Rect { x, y, width: inner_width, height: title_height }, title_color);
+
+    let body = content_rect(window);
+    canvas.fill(body, BODY_COLOR);
+    if let Some(content) = content {
+        canvas.copy(body, content);
+    }
+}
+
+/// Whether a visible window above covers all of `area`
+fn occluded<'w, I>(area: &Rect, above: I) -> bool
+where
+    I: Iterator<Item = &'w Window>,
+{
+    above.filter(|w| w.is_visible()).any(|w| w.rect().contains_rect(area))
+}
+
+/// Repaint the clip rect: the desktop, then every visible window in
+/// `stack` order, bottom first, with the content `content_of` finds for it
+pub fn compose<'w, 'c, I, F>(canvas: &mut Canvas, stack: I, focused: u32, content_of: F)
+where
+    I: Iterator<Item = &'w Window> + Clone,
+    F: Fn(u32) -> Option<Content<'c>>,
+{
+    let clip = canvas.clip;
+    canvas.fill(canvas.bounds(), DESKTOP_COLOR);
+    for (depth, window) in stack.clone().enumerate() {
+        if !window.is_visible() {
+            continue;
+        }
+        let area = match window.rect().intersect(&clip) {
+            Some(area) => area,
+            None => continue,
+        };
+        if occluded(&area, stack.clone().skip(depth + 1)) {
+            continue;
+        }
+        draw_window(canvas, window, window.id == focused, content_of(window.id).as_ref());
+    }
+}
@@ -0,0 +1,58 @@
+//! Screen damage
+//!
+//! Rects of the screen that need compositing again. Overlapping rects are
+//! merged as they arrive, so no pixel is composited twice in one redraw.
+//! When the list fills up everything is folded into one covering rect.
+
+use crate::window::Rect;
+
+pub const MAX_DAMAGE_RECTS: usize = 16;
+
+pub struct DamageList {
+    rects: [Rect; MAX_DAMAGE_RECTS],
+    len: usize,
+}
+
+impl DamageList {
+    pub const fn new() -> Self {
+        DamageList {
+            rects: [Rect { x: 0, y: 0, width: 0, height: 0 }; MAX_DAMAGE_RECTS],
+            len: 0,
+        }
+    }
+
+    /// Damaged rects, none overlapping another
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects[..self.len]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn add(&mut self, rect: Rect) {
+        if rect.is_empty() {
+            return;
+        }
+
+        // A merged rect can reach rects the original did not, so keep
+        // absorbing until nothing overlaps
+        let mut rect = rect;
+        while let Some(index) = self.rects().iter().position(|r| r.intersect(&rect).is_some()) {
+            rect = rect.union(&self.rects[index]);
+            self.len -= 1;
+            self.rects[index] = self.rects[self.len];
+        }
+
+        if self.len == MAX_DAMAGE_RECTS {
+            rect = self.rects().iter().fold(rect, |all, r| all.union(r));
+            self.len = 0;
+        }
+        self.rects[self.len] = rect;
+        self.len += 1;
+    }
+}
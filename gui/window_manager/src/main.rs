@@ -5,6 +5,22 @@
 
 use core::panic::PanicInfo;
 
+// The graphics service uses the kernel's message layout
+use driver_framework::ipc::{
+    ipc_create_port, ipc_destroy_port, ipc_receive_timeout, ipc_send, IpcMessage as GfxMessage,
+};
+
+mod window;
+mod stack;
+mod compose;
+mod damage;
+mod surface;
+use window::{parse_title, Rect, Window, WINDOW_SUMMARY_SIZE};
+use stack::ZOrder;
+use compose::{Canvas, Content};
+use damage::DamageList;
+use surface::{ClientSurface, SurfaceLayout};
+
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
     loop {}
@@ -15,6 +31,10 @@ extern "C" {
     fn sys_ipc_send(tid: u32, msg: *const IpcMessage) -> i32;
     fn sys_ipc_receive(port: u32, msg: *mut IpcMessage) -> i32;
     fn sys_ipc_register_port(port: u32) -> i32;
+    fn sys_shm_create(size: u64, flags: u32) -> u64;
+    fn sys_shm_map(shm_id: u64, vaddr: u64, flags: u32) -> u64;
+    fn sys_shm_unmap(shm_id: u64, vaddr: u64) -> i32;
+    fn sys_shm_get_info(shm_id: u64, size: *mut u64, refcount: *mut u64) -> i32;
 }
 
 #[repr(C)]
@@ -28,31 +48,119 @@ struct IpcMessage {
 const WINDOW_MANAGER_PORT: u32 = 200;
 
 // Message types
+/// [x i32, y i32, width u32, height u32, title...] -> [window id u32]
 const MSG_CREATE_WINDOW: u32 = 1;
+/// Title bytes start after the geometry, NUL-terminated or up to 63 bytes
+const CREATE_WINDOW_TITLE_OFFSET: usize = 16;
 const MSG_DESTROY_WINDOW: u32 = 2;
 const MSG_MOVE_WINDOW: u32 = 3;
 const MSG_RESIZE_WINDOW: u32 = 4;
 const MSG_FOCUS_WINDOW: u32 = 5;
 const MSG_MINIMIZE_WINDOW: u32 = 6;
 const MSG_MAXIMIZE_WINDOW: u32 = 7;
+/// [first index u32, owner only u8] -> [total u32, returned u32, entries...]
+/// where each entry is WINDOW_SUMMARY_SIZE bytes
 const MSG_GET_WINDOW_LIST: u32 = 8;
+const MSG_RESTORE_WINDOW: u32 = 9;
 
-#[repr(C)]
-struct Window {
-    id: u32,
-    x: i32,
-    y: i32,
-    width: u32,
-    height: u32,
-    title: [u8; 64],
-    owner_tid: u32,
-    flags: u32,
-}
+/// Key event from the keyboard driver: [ascii, modifiers, pressed,
+/// scancode, extended, key code]
+const MSG_KEY_EVENT: u32 = 10;
+const KEY_EVENT_SIZE: usize = 6;
+
+/// Raw PS/2-style packet from an input driver: [flags, dx i8, dy i8],
+/// buttons in bits 0-2 and Y growing upwards
+const MSG_MOUSE_EVENT: u32 = 11;
+
+/// [window id u32, x i32, y i32, width u32, height u32]: the owner changed
+/// what is drawn in this part of its window. Coordinates are relative to
+/// the window's top-left corner; anything outside the window is ignored.
+const MSG_INVALIDATE_RECT: u32 = 12;
+
+// Client surfaces. A surface is shared memory holding two XRGB frames of
+// stride * height bytes, one after the other. The client draws a frame
+// and commits it; the frame is then shown until the next commit, and the
+// client draws the next frame into the other one. Once the reply to a
+// commit arrives the previous frame is no longer read.
+
+/// [window id u32, shm id u64, width u32, height u32, stride u32]: show
+/// frames from this memory in the window's body, starting from the next
+/// commit. Shm id 0 detaches the surface.
+const MSG_ATTACH_SURFACE: u32 = 13;
+/// [window id u32, frame u32]: show frame 0 or 1 of the attached surface
+const MSG_COMMIT_SURFACE: u32 = 14;
+
+// Input events sent to window owners. Both start with the window id.
+
+/// [window id u32, x i32, y i32, buttons u8, kind u8] with x/y relative
+/// to the window's top-left corner and kind one of MOUSE_EVENT_*
+const MSG_WINDOW_MOUSE_EVENT: u32 = 20;
+/// [window id u32, key event as sent by the keyboard driver]
+const MSG_WINDOW_KEY_EVENT: u32 = 21;
+
+const MOUSE_EVENT_MOVE: u8 = 0;
+const MOUSE_EVENT_DOWN: u8 = 1;
+const MOUSE_EVENT_UP: u8 = 2;
+
+const MOUSE_BUTTON_MASK: u8 = 0x07;
+
+// Screen size until the graphics service reports the real one
+const SCREEN_WIDTH: u32 = 1024;
+const SCREEN_HEIGHT: u32 = 768;
+
+// Graphics service and the messages used to put the scene on screen.
+// Blits and presents are sent without a reply port, so they are not
+// answered.
+const GRAPHICS_SERVICE_PORT: u64 = 210;
+/// [] -> [status u32, phys base u64, width u32, height u32, ...]
+const MSG_GFX_GET_INFO: u64 = 1;
+/// [x i32, y i32, width u32, height u32, shm id u64, stride u32,
+/// offset u32]
+const MSG_GFX_BLIT: u64 = 2;
+const MSG_GFX_PRESENT: u64 = 3;
+/// [shm id u64]: the graphics service can unmap this region
+const MSG_GFX_RELEASE_SURFACE: u64 = 4;
+/// Answer to any graphics request: [status u32, ...]
+const MSG_GFX_REPLY: u64 = 64;
+const GFX_OK: u32 = 0;
+/// How long to wait for the screen size before running without one
+const GFX_REPLY_TIMEOUT_MS: u64 = 1000;
+
+/// Map shared memory without write access
+const SHM_FLAG_READ_ONLY: u32 = 1 << 0;
+
+/// Window list entries that fit one message after the 8-byte header
+const WINDOW_LIST_MAX_ENTRIES: usize = (256 - 8) / WINDOW_SUMMARY_SIZE;
 
 const MAX_WINDOWS: usize = 256;
 static mut WINDOWS: [Option<Window>; MAX_WINDOWS] = [None; MAX_WINDOWS];
 static mut NEXT_WINDOW_ID: u32 = 1;
 static mut FOCUSED_WINDOW: u32 = 0;
+static mut Z_ORDER: ZOrder = ZOrder::new();
+/// At most one per window
+static mut SURFACES: [Option<ClientSurface>; MAX_WINDOWS] = [None; MAX_WINDOWS];
+
+// Pointer state built from relative mouse packets
+static mut CURSOR_X: i32 = 0;
+static mut CURSOR_Y: i32 = 0;
+static mut MOUSE_BUTTONS: u8 = 0;
+/// Window that got the last press; it keeps the mouse until all buttons
+/// are released, even if the cursor leaves it
+static mut MOUSE_GRAB: u32 = 0;
+
+/// Shared memory the windows are composited into and blitted from
+struct Scene {
+    shm_id: u64,
+    pixels: *mut u32,
+    width: u32,
+    height: u32,
+}
+
+/// None while there is no framebuffer to draw to
+static mut SCENE: Option<Scene> = None;
+
+/// Parts of the screen to composite again on the next redraw
+static mut DAMAGE: DamageList = DamageList::new();
 
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
@@ -61,6 +169,10 @@ pub extern "C" fn _start() -> ! {
         sys_ipc_register_port(WINDOW_MANAGER_PORT);
     }
 
+    // Find the framebuffer and show the empty desktop
+    init_scene();
+    redraw();
+
     // Main service loop
     loop {
         let mut msg = IpcMessage {
@@ -72,11 +184,158 @@ pub extern "C" fn _start() -> ! {
         // Wait for IPC message
         unsafe {
             if sys_ipc_receive(WINDOW_MANAGER_PORT, &mut msg) == 0 {
-                let response = handle_message(&msg);
+                dispatch(&msg);
+            }
+        }
+    }
+}
+
+fn dispatch(msg: &IpcMessage) {
+    match msg.msg_type {
+        // Input drivers do not wait for an answer
+        MSG_KEY_EVENT => handle_key_event(msg),
+        MSG_MOUSE_EVENT => handle_mouse_event(msg),
+        _ => {
+            let response = handle_message(msg);
+            unsafe {
                 let _ = sys_ipc_send(msg.sender_tid, &response);
             }
         }
     }
+
+    // Handlers record what they changed; this puts just that on screen
+    redraw();
+}
+
+/// Ask the graphics service for the screen size and set up a scene
+/// buffer of that size. Requests wait on our port until this is done.
+fn init_scene() {
+    let reply_port = match ipc_create_port() {
+        Ok(port) => port,
+        Err(_) => return,
+    };
+
+    let mut request = GfxMessage::new();
+    request.msg_id = MSG_GFX_GET_INFO;
+    request.reply_port = reply_port;
+    let mut reply = GfxMessage::new();
+    let answered = ipc_send(GRAPHICS_SERVICE_PORT, &request).is_ok()
+        && ipc_receive_timeout(reply_port, &mut reply, GFX_REPLY_TIMEOUT_MS).is_ok();
+    let _ = ipc_destroy_port(reply_port);
+    if !answered || reply.msg_id != MSG_GFX_REPLY {
+        return;
+    }
+
+    let data = &reply.inline_data;
+    if u32::from_le_bytes([data[0], data[1], data[2], data[3]]) != GFX_OK {
+        return;
+    }
+    let width = u32::from_le_bytes([data[12], data[13], data[14], data[15]]);
+    let height = u32::from_le_bytes([data[16], data[17], data[18], data[19]]);
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    unsafe {
+        let shm_id = sys_shm_create(width as u64 * height as u64 * 4, 0);
+        if shm_id == 0 {
+            return;
+        }
+        let vaddr = sys_shm_map(shm_id, 0, 0);
+        if vaddr == 0 {
+            return;
+        }
+        SCENE = Some(Scene { shm_id, pixels: vaddr as *mut u32, width, height });
+        DAMAGE.add(screen());
+    }
+}
+
+/// Screen a maximized window covers and the cursor is kept on
+fn screen() -> Rect {
+    match unsafe { SCENE.as_ref() } {
+        Some(scene) => Rect { x: 0, y: 0, width: scene.width, height: scene.height },
+        None => Rect { x: 0, y: 0, width: SCREEN_WIDTH, height: SCREEN_HEIGHT },
+    }
+}
+
+/// Mark the screen area a window covers for redrawing. Minimized windows
+/// cover nothing.
+fn damage_window(window: &Window) {
+    if window.is_visible() {
+        unsafe {
+            DAMAGE.add(window.rect());
+        }
+    }
+}
+
+/// Move the focus, redrawing both title bars
+fn set_focus(window_id: u32) {
+    unsafe {
+        if FOCUSED_WINDOW == window_id {
+            return;
+        }
+        if let Some(window) = find_window(FOCUSED_WINDOW) {
+            damage_window(window);
+        }
+        FOCUSED_WINDOW = window_id;
+    }
+    if let Some(window) = find_window(window_id) {
+        damage_window(window);
+    }
+}
+
+/// Composite the damaged parts of the scene and put them on screen
+fn redraw() {
+    let scene = match unsafe { SCENE.as_ref() } {
+        Some(scene) => scene,
+        None => return,
+    };
+    if unsafe { DAMAGE.is_empty() } {
+        return;
+    }
+
+    let mut canvas = Canvas {
+        pixels: unsafe { core::slice::from_raw_parts_mut(scene.pixels, scene.width as usize * scene.height as usize) },
+        width: scene.width,
+        height: scene.height,
+        clip: screen(),
+    };
+
+    let mut blit = GfxMessage::new();
+    blit.msg_id = MSG_GFX_BLIT;
+    blit.inline_size = 32;
+    blit.inline_data[16..24].copy_from_slice(&scene.shm_id.to_le_bytes());
+    blit.inline_data[24..28].copy_from_slice(&(scene.width * 4).to_le_bytes());
+
+    unsafe {
+        for damaged in DAMAGE.rects() {
+            let rect = match damaged.intersect(&screen()) {
+                Some(rect) => rect,
+                None => continue,
+            };
+
+            canvas.clip = rect;
+            compose::compose(
+                &mut canvas,
+                Z_ORDER.ids().iter().filter_map(|&id| find_window(id)),
+                FOCUSED_WINDOW,
+                surface_content,
+            );
+
+            let offset = (rect.y as u32 * scene.width + rect.x as u32) * 4;
+            blit.inline_data[0..4].copy_from_slice(&rect.x.to_le_bytes());
+            blit.inline_data[4..8].copy_from_slice(&rect.y.to_le_bytes());
+            blit.inline_data[8..12].copy_from_slice(&rect.width.to_le_bytes());
+            blit.inline_data[12..16].copy_from_slice(&rect.height.to_le_bytes());
+            blit.inline_data[28..32].copy_from_slice(&offset.to_le_bytes());
+            let _ = ipc_send(GRAPHICS_SERVICE_PORT, &blit);
+        }
+        DAMAGE.clear();
+    }
+
+    let mut present = GfxMessage::new();
+    present.msg_id = MSG_GFX_PRESENT;
+    let _ = ipc_send(GRAPHICS_SERVICE_PORT, &present);
 }
 
 fn handle_message(msg: &IpcMessage) -> IpcMessage {
@@ -89,6 +348,10 @@ fn handle_message(msg: &IpcMessage) -> IpcMessage {
         MSG_MINIMIZE_WINDOW => handle_minimize_window(msg),
         MSG_MAXIMIZE_WINDOW => handle_maximize_window(msg),
         MSG_GET_WINDOW_LIST => handle_get_window_list(msg),
+        MSG_RESTORE_WINDOW => handle_restore_window(msg),
+        MSG_INVALIDATE_RECT => handle_invalidate_rect(msg),
+        MSG_ATTACH_SURFACE => handle_attach_surface(msg),
+        MSG_COMMIT_SURFACE => handle_commit_surface(msg),
         _ => create_error_response(1), // Unknown message type
     }
 }
@@ -107,19 +370,12 @@ fn handle_create_window(msg: &IpcMessage) -> IpcMessage {
                 let width = u32::from_le_bytes([msg.data[8], msg.data[9], msg.data[10], msg.data[11]]);
                 let height = u32::from_le_bytes([msg.data[12], msg.data[13], msg.data[14], msg.data[15]]);
 
-                let mut title = [0u8; 64];
-                title[..32].copy_from_slice(&msg.data[16..48]);
-
-                WINDOWS[i] = Some(Window {
-                    id: window_id,
-                    x,
-                    y,
-                    width,
-                    height,
-                    title,
-                    owner_tid: msg.sender_tid,
-                    flags: 0,
-                });
+                let title = parse_title(msg.data.get(CREATE_WINDOW_TITLE_OFFSET..).unwrap_or(&[]));
+
+                let window = Window::new(window_id, msg.sender_tid, Rect { x, y, width, height }, title);
+                damage_window(&window);
+                WINDOWS[i] = Some(window);
+                Z_ORDER.push(window_id);
 
                 // Return window ID
                 let mut response = IpcMessage {
@@ -144,7 +400,15 @@ fn handle_destroy_window(msg: &IpcMessage) -> IpcMessage {
         for i in 0..MAX_WINDOWS {
             if let Some(window) = &WINDOWS[i] {
                 if window.id == window_id && window.owner_tid == msg.sender_tid {
+                    damage_window(window);
+                    if let Some(shm_id) = detach_surface(window_id) {
+                        release_surface(shm_id);
+                    }
                     WINDOWS[i] = None;
+                    Z_ORDER.remove(window_id);
+                    if FOCUSED_WINDOW == window_id {
+                        set_focus(topmost_visible_window());
+                    }
                     return create_success_response();
                 }
             }
@@ -164,9 +428,9 @@ fn handle_move_window(msg: &IpcMessage) -> IpcMessage {
         for i in 0..MAX_WINDOWS {
             if let Some(ref mut window) = WINDOWS[i] {
                 if window.id == window_id && window.owner_tid == msg.sender_tid {
-                    window.x = x;
-                    window.y = y;
-                    // In full implementation, would notify compositor of position change
+                    damage_window(window);
+                    window.set_geometry(Rect { x, y, width: window.width, height: window.height });
+                    damage_window(window);
                     return create_success_response();
                 }
             }
@@ -186,9 +450,9 @@ fn handle_resize_window(msg: &IpcMessage) -> IpcMessage {
         for i in 0..MAX_WINDOWS {
             if let Some(ref mut window) = WINDOWS[i] {
                 if window.id == window_id && window.owner_tid == msg.sender_tid {
-                    window.width = width;
-                    window.height = height;
-                    // In full implementation, would notify compositor of size change
+                    damage_window(window);
+                    window.set_geometry(Rect { x: window.x, y: window.y, width, height });
+                    damage_window(window);
                     return create_success_response();
                 }
             }
@@ -201,91 +465,340 @@ fn handle_resize_window(msg: &IpcMessage) -> IpcMessage {
 fn handle_focus_window(msg: &IpcMessage) -> IpcMessage {
     let window_id = u32::from_le_bytes([msg.data[0], msg.data[1], msg.data[2], msg.data[3]]);
     unsafe {
-        FOCUSED_WINDOW = window_id;
+        if !Z_ORDER.raise(window_id) {
+            return create_error_response(3); // Window not found
+        }
+    }
+    // Raising uncovers whatever of the window was hidden
+    if let Some(window) = find_window(window_id) {
+        damage_window(window);
     }
+    set_focus(window_id);
     create_success_response()
 }
 
 fn handle_minimize_window(msg: &IpcMessage) -> IpcMessage {
-    // Implement window minimize
     let window_id = u32::from_le_bytes([msg.data[0], msg.data[1], msg.data[2], msg.data[3]]);
-    
+
+    let response = update_window_state(window_id, msg.sender_tid, |window| window.minimize());
+    unsafe {
+        if FOCUSED_WINDOW == window_id && response.msg_type == 0 {
+            set_focus(topmost_visible_window());
+        }
+    }
+    response
+}
+
+fn handle_maximize_window(msg: &IpcMessage) -> IpcMessage {
+    let window_id = u32::from_le_bytes([msg.data[0], msg.data[1], msg.data[2], msg.data[3]]);
+    let screen = screen();
+    update_window_state(window_id, msg.sender_tid, |window| window.maximize(screen))
+}
+
+fn handle_restore_window(msg: &IpcMessage) -> IpcMessage {
+    let window_id = u32::from_le_bytes([msg.data[0], msg.data[1], msg.data[2], msg.data[3]]);
+    update_window_state(window_id, msg.sender_tid, |window| window.restore())
+}
+
+/// Apply a state change to one of the caller's windows and answer with
+/// [flags u32, x i32, y i32, width u32, height u32]
+fn update_window_state<F: FnOnce(&mut Window)>(window_id: u32, sender_tid: u32, change: F) -> IpcMessage {
     unsafe {
         for i in 0..MAX_WINDOWS {
             if let Some(ref mut window) = WINDOWS[i] {
-                if window.id == window_id && window.owner_tid == msg.sender_tid {
-                    // Set minimized flag (would be in window state)
-                    // For now, just acknowledge
-                    // In full implementation, would set window state and notify compositor
-                    return create_success_response();
+                if window.id == window_id && window.owner_tid == sender_tid {
+                    damage_window(window);
+                    change(window);
+                    damage_window(window);
+
+                    let mut response = create_success_response();
+                    response.data[0..4].copy_from_slice(&window.flags.to_le_bytes());
+                    response.data[4..8].copy_from_slice(&window.x.to_le_bytes());
+                    response.data[8..12].copy_from_slice(&window.y.to_le_bytes());
+                    response.data[12..16].copy_from_slice(&window.width.to_le_bytes());
+                    response.data[16..20].copy_from_slice(&window.height.to_le_bytes());
+                    return response;
                 }
             }
         }
     }
-    
+
     create_error_response(3) // Window not found
 }
 
-fn handle_maximize_window(msg: &IpcMessage) -> IpcMessage {
-    // Implement window maximize
+fn handle_invalidate_rect(msg: &IpcMessage) -> IpcMessage {
     let window_id = u32::from_le_bytes([msg.data[0], msg.data[1], msg.data[2], msg.data[3]]);
-    
+    let rect = Rect {
+        x: i32::from_le_bytes([msg.data[4], msg.data[5], msg.data[6], msg.data[7]]),
+        y: i32::from_le_bytes([msg.data[8], msg.data[9], msg.data[10], msg.data[11]]),
+        width: u32::from_le_bytes([msg.data[12], msg.data[13], msg.data[14], msg.data[15]]),
+        height: u32::from_le_bytes([msg.data[16], msg.data[17], msg.data[18], msg.data[19]]),
+    };
+
+    let window = match find_window(window_id) {
+        Some(window) if window.owner_tid == msg.sender_tid => window,
+        _ => return create_error_response(3), // Window not found
+    };
+
+    let local = Rect { x: 0, y: 0, width: window.width, height: window.height };
+    if let Some(part) = rect.intersect(&local) {
+        if window.is_visible() {
+            unsafe {
+                DAMAGE.add(Rect {
+                    x: window.x.saturating_add(part.x),
+                    y: window.y.saturating_add(part.y),
+                    width: part.width,
+                    height: part.height,
+                });
+            }
+        }
+    }
+    create_success_response()
+}
+
+fn handle_attach_surface(msg: &IpcMessage) -> IpcMessage {
+    let window_id = u32::from_le_bytes([msg.data[0], msg.data[1], msg.data[2], msg.data[3]]);
+    let mut shm_id = [0u8; 8];
+    shm_id.copy_from_slice(&msg.data[4..12]);
+    let shm_id = u64::from_le_bytes(shm_id);
+    let width = u32::from_le_bytes([msg.data[12], msg.data[13], msg.data[14], msg.data[15]]);
+    let height = u32::from_le_bytes([msg.data[16], msg.data[17], msg.data[18], msg.data[19]]);
+    let stride = u32::from_le_bytes([msg.data[20], msg.data[21], msg.data[22], msg.data[23]]);
+
+    let window = match find_window(window_id) {
+        Some(window) if window.owner_tid == msg.sender_tid => window,
+        _ => return create_error_response(3), // Window not found
+    };
+
+    // Whatever was shown goes away with the old surface
+    detach_surface(window_id);
+    damage_window(window);
+    if shm_id == 0 {
+        return create_success_response();
+    }
+
+    let mut size = 0u64;
+    let mut refcount = 0u64;
+    let layout = unsafe {
+        if sys_shm_get_info(shm_id, &mut size, &mut refcount) != 0 {
+            return create_error_response(4); // Bad surface
+        }
+        SurfaceLayout::new(width, height, stride, size)
+    };
+    let layout = match layout {
+        Some(layout) => layout,
+        None => return create_error_response(4), // Bad surface
+    };
+
     unsafe {
-        for i in 0..MAX_WINDOWS {
-            if let Some(ref mut window) = WINDOWS[i] {
-                if window.id == window_id && window.owner_tid == msg.sender_tid {
-                    // Set maximized flag (would be in window state)
-                    // For now, just acknowledge
-                    // In full implementation, would set window state, resize to screen size, and notify compositor
-                    return create_success_response();
+        let slot = match SURFACES.iter().position(|slot| slot.is_none()) {
+            Some(slot) => slot,
+            None => return create_error_response(2), // No free slots
+        };
+        let vaddr = sys_shm_map(shm_id, 0, SHM_FLAG_READ_ONLY);
+        if vaddr == 0 {
+            return create_error_response(4); // Bad surface
+        }
+        SURFACES[slot] = Some(ClientSurface { window_id, shm_id, vaddr, layout, front: None });
+    }
+    create_success_response()
+}
+
+fn handle_commit_surface(msg: &IpcMessage) -> IpcMessage {
+    let window_id = u32::from_le_bytes([msg.data[0], msg.data[1], msg.data[2], msg.data[3]]);
+    let frame = u32::from_le_bytes([msg.data[4], msg.data[5], msg.data[6], msg.data[7]]);
+
+    let window = match find_window(window_id) {
+        Some(window) if window.owner_tid == msg.sender_tid => window,
+        _ => return create_error_response(3), // Window not found
+    };
+
+    unsafe {
+        let surface = match SURFACES.iter_mut().flatten().find(|s| s.window_id == window_id) {
+            Some(surface) => surface,
+            None => return create_error_response(4), // Bad surface
+        };
+        if surface.layout.frame_offset(frame).is_none() {
+            return create_error_response(4); // Bad surface
+        }
+        surface.front = Some(frame);
+    }
+
+    if window.is_visible() {
+        unsafe {
+            DAMAGE.add(compose::content_rect(window));
+        }
+    }
+    create_success_response()
+}
+
+/// Unmap a window's surface, if it has one, and return its shm id
+fn detach_surface(window_id: u32) -> Option<u64> {
+    let mut detached = None;
+    unsafe {
+        for slot in SURFACES.iter_mut() {
+            if let Some(surface) = slot {
+                if surface.window_id == window_id {
+                    let _ = sys_shm_unmap(surface.shm_id, surface.vaddr);
+                    detached = Some(surface.shm_id);
+                    *slot = None;
                 }
             }
         }
     }
-    
-    create_error_response(3) // Window not found
+    detached
 }
 
-fn handle_get_window_list(_msg: &IpcMessage) -> IpcMessage {
-    // Return list of windows
-    let mut response = IpcMessage {
+/// Tell the graphics service a destroyed window's surface is gone, so it
+/// drops any mapping it kept for blitting from it
+fn release_surface(shm_id: u64) {
+    let mut release = GfxMessage::new();
+    release.msg_id = MSG_GFX_RELEASE_SURFACE;
+    release.set_inline_data(&shm_id.to_le_bytes());
+    let _ = ipc_send(GRAPHICS_SERVICE_PORT, &release);
+}
+
+/// Committed frame of a window's surface
+fn surface_content(window_id: u32) -> Option<Content<'static>> {
+    let surface = unsafe { SURFACES.iter().flatten().find(|s| s.window_id == window_id)? };
+    let layout = surface.layout;
+    let offset = layout.frame_offset(surface.front?)?;
+
+    let pixels = unsafe {
+        core::slice::from_raw_parts((surface.vaddr as usize + offset) as *const u32, layout.frame_size() / 4)
+    };
+    Some(Content {
+        pixels,
+        width: layout.width,
+        height: layout.height,
+        stride: layout.stride as usize / 4,
+    })
+}
+
+/// Window by id
+fn find_window(window_id: u32) -> Option<&'static Window> {
+    unsafe { WINDOWS.iter().flatten().find(|window| window.id == window_id) }
+}
+
+/// Topmost window that is not minimized; 0 if none
+fn topmost_visible_window() -> u32 {
+    unsafe { Z_ORDER.topmost(find_window, |window| window.is_visible()).unwrap_or(0) }
+}
+
+/// Topmost visible window containing a screen point
+fn hit_test(x: i32, y: i32) -> Option<u32> {
+    unsafe { Z_ORDER.hit_test(x, y, find_window) }
+}
+
+/// Forward a key event to the owner of the focused window
+fn handle_key_event(msg: &IpcMessage) {
+    let window = match find_window(unsafe { FOCUSED_WINDOW }) {
+        Some(window) if window.is_visible() => window,
+        _ => return,
+    };
+
+    let mut event = IpcMessage {
         sender_tid: 0,
-        msg_type: 0,
+        msg_type: MSG_WINDOW_KEY_EVENT,
         data: [0; 256],
     };
-    
+    event.data[0..4].copy_from_slice(&window.id.to_le_bytes());
+    event.data[4..4 + KEY_EVENT_SIZE].copy_from_slice(&msg.data[..KEY_EVENT_SIZE]);
     unsafe {
-        let mut count = 0u32;
-        let mut offset = 0;
-        
-        for i in 0..MAX_WINDOWS {
-            if let Some(window) = &WINDOWS[i] {
-                if offset + 4 + core::mem::size_of::<Window>() <= 256 {
-                    // Write window ID
-                    response.data[offset..offset+4].copy_from_slice(&window.id.to_le_bytes());
-                    offset += 4;
-                    
-                    // Write window data (simplified - would serialize full window struct)
-                    response.data[offset..offset+4].copy_from_slice(&window.x.to_le_bytes());
-                    offset += 4;
-                    response.data[offset..offset+4].copy_from_slice(&window.y.to_le_bytes());
-                    offset += 4;
-                    response.data[offset..offset+4].copy_from_slice(&window.width.to_le_bytes());
-                    offset += 4;
-                    response.data[offset..offset+4].copy_from_slice(&window.height.to_le_bytes());
-                    offset += 4;
-                    
-                    count += 1;
-                } else {
-                    break; // Out of space
-                }
+        let _ = sys_ipc_send(window.owner_tid, &event);
+    }
+}
+
+/// Move the cursor and send the event to the window under it, or to the
+/// grabbing window while a button is held. A press raises and focuses
+/// the window.
+fn handle_mouse_event(msg: &IpcMessage) {
+    let buttons = msg.data[0] & MOUSE_BUTTON_MASK;
+    let dx = msg.data[1] as i8 as i32;
+    let dy = msg.data[2] as i8 as i32;
+
+    unsafe {
+        let screen = screen();
+        CURSOR_X = (CURSOR_X + dx).clamp(0, screen.width as i32 - 1);
+        CURSOR_Y = (CURSOR_Y - dy).clamp(0, screen.height as i32 - 1);
+        let pressed = buttons & !MOUSE_BUTTONS;
+        let released = MOUSE_BUTTONS & !buttons;
+        let was_grabbed = MOUSE_BUTTONS != 0;
+        MOUSE_BUTTONS = buttons;
+
+        let kind = if pressed != 0 {
+            MOUSE_EVENT_DOWN
+        } else if released != 0 {
+            MOUSE_EVENT_UP
+        } else {
+            MOUSE_EVENT_MOVE
+        };
+
+        let target = if was_grabbed {
+            find_window(MOUSE_GRAB)
+        } else {
+            hit_test(CURSOR_X, CURSOR_Y).and_then(find_window)
+        };
+        let window = match target {
+            Some(window) => window,
+            None => {
+                MOUSE_GRAB = 0;
+                return;
+            }
+        };
+
+        if kind == MOUSE_EVENT_DOWN && !was_grabbed {
+            Z_ORDER.raise(window.id);
+            damage_window(window);
+            set_focus(window.id);
+            MOUSE_GRAB = window.id;
+        }
+        if buttons == 0 {
+            MOUSE_GRAB = 0;
+        }
+
+        let mut event = IpcMessage {
+            sender_tid: 0,
+            msg_type: MSG_WINDOW_MOUSE_EVENT,
+            data: [0; 256],
+        };
+        event.data[0..4].copy_from_slice(&window.id.to_le_bytes());
+        event.data[4..8].copy_from_slice(&(CURSOR_X - window.x).to_le_bytes());
+        event.data[8..12].copy_from_slice(&(CURSOR_Y - window.y).to_le_bytes());
+        event.data[12] = buttons;
+        event.data[13] = kind;
+        let _ = sys_ipc_send(window.owner_tid, &event);
+    }
+}
+
+fn handle_get_window_list(msg: &IpcMessage) -> IpcMessage {
+    let first = u32::from_le_bytes([msg.data[0], msg.data[1], msg.data[2], msg.data[3]]) as usize;
+    let owner_only = msg.data[4] != 0;
+
+    let mut response = create_success_response();
+    let mut total = 0usize;
+    let mut returned = 0usize;
+
+    unsafe {
+        let visible = WINDOWS.iter()
+            .flatten()
+            .filter(|window| !owner_only || window.owner_tid == msg.sender_tid);
+
+        for window in visible {
+            if total >= first && returned < WINDOW_LIST_MAX_ENTRIES {
+                let offset = 8 + returned * WINDOW_SUMMARY_SIZE;
+                let mut entry = [0u8; WINDOW_SUMMARY_SIZE];
+                window.encode_summary(&mut entry);
+                response.data[offset..offset + WINDOW_SUMMARY_SIZE].copy_from_slice(&entry);
+                returned += 1;
             }
+            total += 1;
         }
-        
-        // Write count at beginning
-        response.data[0..4].copy_from_slice(&count.to_le_bytes());
     }
-    
+
+    // Callers page by asking again from first + returned until total
+    response.data[0..4].copy_from_slice(&(total as u32).to_le_bytes());
+    response.data[4..8].copy_from_slice(&(returned as u32).to_le_bytes());
     response
 }
 
@@ -0,0 +1,76 @@
+//! Window stacking order
+//!
+//! Window ids from bottom to top. The compositor draws in this order and
+//! hit-testing walks it the other way, so the topmost window under a point
+//! gets the click.
+
+use crate::window::Window;
+
+pub const ZORDER_CAPACITY: usize = 256;
+
+pub struct ZOrder {
+    ids: [u32; ZORDER_CAPACITY],
+    len: usize,
+}
+
+impl ZOrder {
+    pub const fn new() -> Self {
+        ZOrder {
+            ids: [0; ZORDER_CAPACITY],
+            len: 0,
+        }
+    }
+
+    /// Ids from bottom to top
+    pub fn ids(&self) -> &[u32] {
+        &self.ids[..self.len]
+    }
+
+    /// Put a new window on top
+    pub fn push(&mut self, id: u32) {
+        if self.len == ZORDER_CAPACITY || self.ids().contains(&id) {
+            return;
+        }
+        self.ids[self.len] = id;
+        self.len += 1;
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        if let Some(index) = self.ids().iter().position(|&w| w == id) {
+            self.ids.copy_within(index + 1..self.len, index);
+            self.len -= 1;
+        }
+    }
+
+    /// Move a window to the top; false if it is not stacked
+    pub fn raise(&mut self, id: u32) -> bool {
+        let index = match self.ids().iter().position(|&w| w == id) {
+            Some(index) => index,
+            None => return false,
+        };
+        self.ids.copy_within(index + 1..self.len, index);
+        self.ids[self.len - 1] = id;
+        true
+    }
+
+    /// Topmost window `accept` agrees to
+    pub fn topmost<'a, F, P>(&self, window: F, accept: P) -> Option<u32>
+    where
+        F: Fn(u32) -> Option<&'a Window>,
+        P: Fn(&Window) -> bool,
+    {
+        self.ids()
+            .iter()
+            .rev()
+            .copied()
+            .find(|&id| window(id).map_or(false, |w| accept(w)))
+    }
+
+    /// Topmost visible window containing the point
+    pub fn hit_test<'a, F>(&self, x: i32, y: i32, window: F) -> Option<u32>
+    where
+        F: Fn(u32) -> Option<&'a Window>,
+    {
+        self.topmost(window, |w| w.is_visible() && w.contains(x, y))
+    }
+}
@@ -0,0 +1,58 @@
+//! Client window surfaces
+//!
+//! A client draws its window's content into shared memory holding
+//! SURFACE_BUFFERS frames of `stride * height` bytes, one after another.
+//! Committing a frame makes it the one composited. The other frame is left
+//! to the client to draw the next one in, so it never writes to pixels
+//! that are on screen.
+
+/// Frames in a surface's shared memory
+pub const SURFACE_BUFFERS: usize = 2;
+
+/// Where the frames of a surface lie in its shared memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SurfaceLayout {
+    pub width: u32,
+    pub height: u32,
+    /// Bytes from one row to the next
+    pub stride: u32,
+}
+
+impl SurfaceLayout {
+    /// Layout of XRGB frames in a region of `size` bytes, `None` if the
+    /// frames do not fit or a row is shorter than `width` pixels
+    pub fn new(width: u32, height: u32, stride: u32, size: u64) -> Option<SurfaceLayout> {
+        if width == 0 || height == 0 || !stride.is_multiple_of(4) || (stride as u64) < width as u64 * 4 {
+            return None;
+        }
+        let frame = stride as u64 * height as u64;
+        if frame * SURFACE_BUFFERS as u64 > size {
+            return None;
+        }
+        Some(SurfaceLayout { width, height, stride })
+    }
+
+    pub fn frame_size(&self) -> usize {
+        self.stride as usize * self.height as usize
+    }
+
+    /// Byte offset of frame `index`, `None` if there is no such frame
+    pub fn frame_offset(&self, index: u32) -> Option<usize> {
+        if index as usize >= SURFACE_BUFFERS {
+            return None;
+        }
+        Some(index as usize * self.frame_size())
+    }
+}
+
+/// Surface attached to a window
+#[derive(Clone, Copy)]
+pub struct ClientSurface {
+    pub window_id: u32,
+    pub shm_id: u64,
+    /// Where the window manager mapped the shared memory
+    pub vaddr: u64,
+    pub layout: SurfaceLayout,
+    /// Frame being composited; `None` until the first commit
+    pub front: Option<u32>,
+}
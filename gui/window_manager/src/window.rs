@@ -0,0 +1,185 @@
+//! Window state
+//!
+//! Geometry and the flags that track minimize/maximize. A maximized window
+//! keeps the rect it had before so restore can put it back.
+
+/// Hidden from compositing until restored
+pub const WINDOW_FLAG_MINIMIZED: u32 = 1 << 0;
+/// Covers the whole screen
+pub const WINDOW_FLAG_MAXIMIZED: u32 = 1 << 1;
+/// `saved` holds the geometry to restore to
+pub const WINDOW_FLAG_SAVED_GEOMETRY: u32 = 1 << 2;
+
+/// Longest title kept; one byte of the buffer is left for the NUL
+pub const WINDOW_TITLE_MAX: usize = 63;
+
+/// Title bytes carried in a window list entry
+pub const WINDOW_SUMMARY_TITLE_LEN: usize = 24;
+/// Bytes of a window list entry: id, x, y, width, height, flags, title
+pub const WINDOW_SUMMARY_SIZE: usize = 24 + WINDOW_SUMMARY_TITLE_LEN;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    fn right(&self) -> i64 {
+        self.x as i64 + self.width as i64
+    }
+
+    fn bottom(&self) -> i64 {
+        self.y as i64 + self.height as i64
+    }
+
+    /// Area both rects cover, `None` if they do not overlap
+    pub fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let left = self.x.max(other.x);
+        let top = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if left as i64 >= right || top as i64 >= bottom {
+            return None;
+        }
+        Some(Rect { x: left, y: top, width: (right - left as i64) as u32, height: (bottom - top as i64) as u32 })
+    }
+
+    /// Smallest rect covering both
+    pub fn union(&self, other: &Rect) -> Rect {
+        let left = self.x.min(other.x);
+        let top = self.y.min(other.y);
+        let right = self.right().max(other.right());
+        let bottom = self.bottom().max(other.bottom());
+        Rect { x: left, y: top, width: (right - left as i64) as u32, height: (bottom - top as i64) as u32 }
+    }
+
+    /// Whether `other` lies entirely inside this rect
+    pub fn contains_rect(&self, other: &Rect) -> bool {
+        other.x >= self.x && other.y >= self.y && other.right() <= self.right() && other.bottom() <= self.bottom()
+    }
+}
+
+/// NUL-terminated title from client bytes: stops at the first NUL or
+/// WINDOW_TITLE_MAX bytes and drops anything past the last valid UTF-8
+/// character, including one cut in half by the limit
+pub fn parse_title(bytes: &[u8]) -> [u8; 64] {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len()).min(WINDOW_TITLE_MAX);
+    let valid = match core::str::from_utf8(&bytes[..len]) {
+        Ok(text) => text.len(),
+        Err(e) => e.valid_up_to(),
+    };
+
+    let mut title = [0u8; 64];
+    title[..valid].copy_from_slice(&bytes[..valid]);
+    title
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Window {
+    pub id: u32,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub title: [u8; 64],
+    pub owner_tid: u32,
+    pub flags: u32,
+    /// Geometry before maximizing
+    pub saved: Rect,
+}
+
+impl Window {
+    pub fn new(id: u32, owner_tid: u32, rect: Rect, title: [u8; 64]) -> Self {
+        Window {
+            id,
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height,
+            title,
+            owner_tid,
+            flags: 0,
+            saved: rect,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect { x: self.x, y: self.y, width: self.width, height: self.height }
+    }
+
+    fn set_rect(&mut self, rect: Rect) {
+        self.x = rect.x;
+        self.y = rect.y;
+        self.width = rect.width;
+        self.height = rect.height;
+    }
+
+    /// Whether the compositor should draw the window
+    pub fn is_visible(&self) -> bool {
+        self.flags & WINDOW_FLAG_MINIMIZED == 0
+    }
+
+    /// Whether a screen point falls inside the window
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        let dx = x as i64 - self.x as i64;
+        let dy = y as i64 - self.y as i64;
+        dx >= 0 && dy >= 0 && dx < self.width as i64 && dy < self.height as i64
+    }
+
+    /// Expand to the screen, remembering the current geometry
+    pub fn maximize(&mut self, screen: Rect) {
+        if self.flags & WINDOW_FLAG_SAVED_GEOMETRY == 0 {
+            self.saved = self.rect();
+            self.flags |= WINDOW_FLAG_SAVED_GEOMETRY;
+        }
+        self.set_rect(screen);
+        self.flags = (self.flags | WINDOW_FLAG_MAXIMIZED) & !WINDOW_FLAG_MINIMIZED;
+    }
+
+    pub fn minimize(&mut self) {
+        self.flags |= WINDOW_FLAG_MINIMIZED;
+    }
+
+    /// Undo the last state change: show a minimized window as it was,
+    /// otherwise return a maximized one to its saved geometry
+    pub fn restore(&mut self) {
+        if self.flags & WINDOW_FLAG_MINIMIZED != 0 {
+            self.flags &= !WINDOW_FLAG_MINIMIZED;
+            return;
+        }
+        if self.flags & WINDOW_FLAG_SAVED_GEOMETRY != 0 {
+            self.set_rect(self.saved);
+        }
+        self.flags &= !(WINDOW_FLAG_MAXIMIZED | WINDOW_FLAG_SAVED_GEOMETRY);
+    }
+
+    /// An explicit move or resize leaves the maximized state
+    pub fn set_geometry(&mut self, rect: Rect) {
+        self.set_rect(rect);
+        self.flags &= !(WINDOW_FLAG_MAXIMIZED | WINDOW_FLAG_SAVED_GEOMETRY);
+    }
+
+    /// Window list entry; the title is cut short and NUL padded
+    pub fn encode_summary(&self, out: &mut [u8; WINDOW_SUMMARY_SIZE]) {
+        out[0..4].copy_from_slice(&self.id.to_le_bytes());
+        out[4..8].copy_from_slice(&self.x.to_le_bytes());
+        out[8..12].copy_from_slice(&self.y.to_le_bytes());
+        out[12..16].copy_from_slice(&self.width.to_le_bytes());
+        out[16..20].copy_from_slice(&self.height.to_le_bytes());
+        out[20..24].copy_from_slice(&self.flags.to_le_bytes());
+
+        let title_len = self.title.iter().position(|&b| b == 0).unwrap_or(self.title.len());
+        // Keep the last byte for the terminator
+        let copy = title_len.min(WINDOW_SUMMARY_TITLE_LEN - 1);
+        out[24..].fill(0);
+        out[24..24 + copy].copy_from_slice(&self.title[..copy]);
+    }
+}
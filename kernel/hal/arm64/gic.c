@@ -140,7 +140,7 @@ void arm64_irq_handler(void) {
     }
 
     // Call registered IRQ handler
-    extern void irq_call_handlers(uint8_t irq);
+    extern bool irq_call_handlers(uint8_t irq);
     if (irq < 64) {  // Only call for valid IRQ range
         irq_call_handlers((uint8_t)irq);
     }
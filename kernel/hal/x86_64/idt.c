@@ -169,6 +169,40 @@ void idt_init(void) {
     idt_set_entry(45, (uint64_t)interrupt_handler_45, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
     idt_set_entry(46, (uint64_t)interrupt_handler_46, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
     idt_set_entry(47, (uint64_t)interrupt_handler_47, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
+
+    // MSI/MSI-X vectors (48-63)
+    extern void interrupt_handler_48(void);
+    extern void interrupt_handler_49(void);
+    extern void interrupt_handler_50(void);
+    extern void interrupt_handler_51(void);
+    extern void interrupt_handler_52(void);
+    extern void interrupt_handler_53(void);
+    extern void interrupt_handler_54(void);
+    extern void interrupt_handler_55(void);
+    extern void interrupt_handler_56(void);
+    extern void interrupt_handler_57(void);
+    extern void interrupt_handler_58(void);
+    extern void interrupt_handler_59(void);
+    extern void interrupt_handler_60(void);
+    extern void interrupt_handler_61(void);
+    extern void interrupt_handler_62(void);
+    extern void interrupt_handler_63(void);
+    idt_set_entry(48, (uint64_t)interrupt_handler_48, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
+    idt_set_entry(49, (uint64_t)interrupt_handler_49, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
+    idt_set_entry(50, (uint64_t)interrupt_handler_50, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
+    idt_set_entry(51, (uint64_t)interrupt_handler_51, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
+    idt_set_entry(52, (uint64_t)interrupt_handler_52, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
+    idt_set_entry(53, (uint64_t)interrupt_handler_53, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
+    idt_set_entry(54, (uint64_t)interrupt_handler_54, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
+    idt_set_entry(55, (uint64_t)interrupt_handler_55, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
+    idt_set_entry(56, (uint64_t)interrupt_handler_56, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
+    idt_set_entry(57, (uint64_t)interrupt_handler_57, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
+    idt_set_entry(58, (uint64_t)interrupt_handler_58, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
+    idt_set_entry(59, (uint64_t)interrupt_handler_59, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
+    idt_set_entry(60, (uint64_t)interrupt_handler_60, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
+    idt_set_entry(61, (uint64_t)interrupt_handler_61, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
+    idt_set_entry(62, (uint64_t)interrupt_handler_62, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
+    idt_set_entry(63, (uint64_t)interrupt_handler_63, 0x08, IDT_TYPE_INTERRUPT_GATE, 0);
     
     // Set up IDT pointer
     idt_ptr.limit = sizeof(idt) - 1;
@@ -6,6 +6,7 @@
 #include "../../include/types.h"
 #include "../../include/kprintf.h"
 #include "../../include/debug.h"
+#include "../../include/hal/msi.h"
 
 // Interrupt handler structure
 typedef struct {
@@ -86,28 +87,50 @@ void interrupt_handler_c(interrupt_frame_t* frame) {
         return;
     }
     
-    // Handle keyboard interrupt (IRQ 1 = interrupt 33)
+    // Handle keyboard interrupt (IRQ 1 = interrupt 33). Once the user-space
+    // PS/2 controller driver has claimed the line, the kernel driver keeps
+    // off the data port.
     if (interrupt_num == 33) {
-        extern void keyboard_interrupt_handler(void);
-        keyboard_interrupt_handler();
+        extern bool irq_call_handlers(uint8_t irq);
+        if (!irq_call_handlers(irq)) {
+            extern void keyboard_interrupt_handler(void);
+            keyboard_interrupt_handler();
+        }
         
         // Send EOI to PIC
         pic_send_eoi(irq);
         return;
     }
     
-    // Handle mouse interrupt (IRQ 12 = interrupt 44)
+    // Handle mouse interrupt (IRQ 12 = interrupt 44), likewise
     if (interrupt_num == 44) {
-        extern void mouse_interrupt_handler(void);
-        mouse_interrupt_handler();
+        extern bool irq_call_handlers(uint8_t irq);
+        if (!irq_call_handlers(irq)) {
+            extern void mouse_interrupt_handler(void);
+            mouse_interrupt_handler();
+        }
         
         // Send EOI to PIC
         pic_send_eoi(irq);
         return;
     }
     
-    // Handle other interrupts
-    kdebug("Unhandled interrupt: %lu (IRQ %u)\n", interrupt_num, irq);
+    // MSI/MSI-X vectors bypass the PIC and are acknowledged at the local APIC
+    if (msi_is_vector(interrupt_num)) {
+        extern bool irq_call_handlers(uint8_t irq);
+        irq_call_handlers(irq);
+
+        extern void apic_send_eoi(void);
+        apic_send_eoi();
+        return;
+    }
+    
+    // Run the whole handler chain for the line before acknowledging it, so
+    // a shared line is only re-armed once every device has been serviced
+    extern bool irq_call_handlers(uint8_t irq);
+    if (!irq_call_handlers(irq)) {
+        kdebug("Unhandled interrupt: %lu (IRQ %u)\n", interrupt_num, irq);
+    }
     
     // Send EOI for all interrupts
     pic_send_eoi(irq);
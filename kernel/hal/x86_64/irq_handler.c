@@ -10,15 +10,22 @@
 #include "../../include/sync/spinlock.h"
 #include "../../include/mm/heap.h"
 #include "../../include/sched/scheduler.h"
+#include "../../include/ipc/ipc.h"
+#include "../../include/process.h"
 
 // IRQ handler callback type
 typedef void (*irq_handler_callback_t)(void* context);
 
+// Shared IRQ handler: returns nonzero if its device raised the interrupt
+typedef bool (*irq_shared_callback_t)(void* context);
+
 // IRQ handler entry
 typedef struct irq_handler_entry {
     uint8_t irq;
     irq_handler_callback_t handler;
     void* context;
+    bool shared;   // handler is an irq_shared_callback_t on a shared line
+    uint64_t port; // IPC port notified instead of calling a handler (0 = none)
     uint64_t tid;  // Thread ID that registered this handler
     struct irq_handler_entry* next;
 } irq_handler_entry_t;
@@ -28,26 +35,36 @@ static irq_handler_entry_t* irq_handlers[MAX_IRQ_HANDLERS] = {NULL};
 static spinlock_t irq_handler_lock = SPINLOCK_INIT;
 
 /**
- * Register an IRQ handler from user-space
+ * Add a handler to an IRQ's chain
+ *
+ * An exclusive handler needs the line to itself; a shared handler can only
+ * join a line whose existing handlers are all shared.
  */
-int irq_register(uint8_t irq, irq_handler_callback_t handler, void* context) {
+static int irq_add_handler(uint8_t irq, irq_handler_callback_t handler, void* context, bool shared,
+                           uint64_t port) {
     if (irq >= MAX_IRQ_HANDLERS) {
         return -1;
     }
     
-    if (!handler) {
+    if (!handler && !port) {
         return -1;
     }
     
     spinlock_lock(&irq_handler_lock);
     
-    // Check if handler already exists for this IRQ
+    // Check if handler already exists or the line cannot be shared
     irq_handler_entry_t* entry = irq_handlers[irq];
     while (entry) {
         if (entry->handler == handler && entry->context == context) {
             spinlock_unlock(&irq_handler_lock);
             return -1;  // Already registered
         }
+        if (!shared || !entry->shared) {
+            spinlock_unlock(&irq_handler_lock);
+            kwarn("IRQ %u already claimed, cannot register %s handler\n",
+                  irq, shared ? "shared" : "exclusive");
+            return -1;
+        }
         entry = entry->next;
     }
     
@@ -61,64 +78,146 @@ int irq_register(uint8_t irq, irq_handler_callback_t handler, void* context) {
     new_entry->irq = irq;
     new_entry->handler = handler;
     new_entry->context = context;
+    new_entry->shared = shared;
+    new_entry->port = port;
     extern thread_t* thread_current(void);
     thread_t* current = thread_current();
     new_entry->tid = current ? current->tid : 0;
     new_entry->next = irq_handlers[irq];
     irq_handlers[irq] = new_entry;
     
-    kinfo("Registered IRQ handler for IRQ %u\n", irq);
+    kinfo("Registered %s IRQ handler for IRQ %u\n", shared ? "shared" : "exclusive", irq);
     
     spinlock_unlock(&irq_handler_lock);
     
     return 0;
 }
 
+/**
+ * Register an IRQ handler from user-space
+ */
+int irq_register(uint8_t irq, irq_handler_callback_t handler, void* context) {
+    return irq_add_handler(irq, handler, context, false, 0);
+}
+
+/**
+ * Register a handler on a line other devices may also use
+ */
+int irq_register_shared(uint8_t irq, irq_shared_callback_t handler, void* context) {
+    return irq_add_handler(irq, (irq_handler_callback_t)handler, context, true, 0);
+}
+
+/**
+ * Deliver an IRQ to a port the caller owns, as an IPC_MSG_ID_IRQ
+ * notification, so its owner can wait for the interrupt and for requests
+ * in one receive. The line is claimed exclusively.
+ */
+int irq_bind_port(uint8_t irq, uint64_t port_id) {
+    process_t* current = process_get_current();
+    uint64_t owner = ipc_port_owner_pid(port_id);
+    if (!current || owner == 0 || owner != (uint64_t)current->pid) {
+        return -1;
+    }
+    return irq_add_handler(irq, NULL, NULL, false, port_id);
+}
+
+/**
+ * Drop every IRQ binding to a port, e.g. when it is destroyed
+ */
+void irq_unbind_port(uint64_t port_id) {
+    spinlock_lock(&irq_handler_lock);
+    for (int irq = 0; irq < MAX_IRQ_HANDLERS; irq++) {
+        irq_handler_entry_t** entry = &irq_handlers[irq];
+        while (*entry) {
+            if ((*entry)->port == port_id) {
+                irq_handler_entry_t* to_free = *entry;
+                *entry = to_free->next;
+                kfree(to_free);
+                continue;
+            }
+            entry = &(*entry)->next;
+        }
+    }
+    spinlock_unlock(&irq_handler_lock);
+}
+
 /**
  * Unregister an IRQ handler
+ *
+ * A NULL handler removes every handler the calling thread registered on the
+ * line, leaving other drivers on a shared line untouched.
  */
 int irq_unregister(uint8_t irq, irq_handler_callback_t handler) {
     if (irq >= MAX_IRQ_HANDLERS) {
         return -1;
     }
     
+    extern thread_t* thread_current(void);
+    thread_t* current = thread_current();
+    uint64_t tid = current ? current->tid : 0;
+    int removed = 0;
+    
     spinlock_lock(&irq_handler_lock);
     
     irq_handler_entry_t** entry = &irq_handlers[irq];
     while (*entry) {
-        if ((*entry)->handler == handler) {
+        bool match = handler ? (*entry)->handler == handler : (*entry)->tid == tid;
+        if (match) {
             irq_handler_entry_t* to_free = *entry;
             *entry = (*entry)->next;
             kfree(to_free);
-            spinlock_unlock(&irq_handler_lock);
-            kinfo("Unregistered IRQ handler for IRQ %u\n", irq);
-            return 0;
+            removed++;
+            if (handler) {
+                break;
+            }
+            continue;
         }
         entry = &(*entry)->next;
     }
     
     spinlock_unlock(&irq_handler_lock);
-    return -1;  // Handler not found
+    
+    if (!removed) {
+        return -1;  // Handler not found
+    }
+    kinfo("Unregistered IRQ handler for IRQ %u\n", irq);
+    return 0;
 }
 
 /**
  * Call user-space IRQ handlers
+ *
+ * Every handler in the chain runs, since several devices on a shared
+ * level-triggered line may be asserting at once. Returns true if any handler
+ * claimed the interrupt; exclusive handlers always count as claiming it.
  */
-void irq_call_handlers(uint8_t irq) {
+bool irq_call_handlers(uint8_t irq) {
     if (irq >= MAX_IRQ_HANDLERS) {
-        return;
+        return false;
     }
     
     // Don't lock here - handlers are called from interrupt context
     // We'll use a lock-free approach or ensure handlers are safe
     
+    bool handled = false;
     irq_handler_entry_t* entry = irq_handlers[irq];
     while (entry) {
-        if (entry->handler) {
-            entry->handler(entry->context);
+        if (entry->port) {
+            ipc_notify_irq(entry->port, irq);
+            handled = true;
+        } else if (entry->handler) {
+            if (entry->shared) {
+                if (((irq_shared_callback_t)entry->handler)(entry->context)) {
+                    handled = true;
+                }
+            } else {
+                entry->handler(entry->context);
+                handled = true;
+            }
         }
         entry = entry->next;
     }
+    return handled;
 }
 
 /**
@@ -0,0 +1,96 @@
+/**
+ * @file msi.c
+ * @brief Message Signaled Interrupt vector allocation
+ *
+ * MSI vectors are delivered straight to a local APIC, bypassing the PIC,
+ * so each allocated vector is programmed as fixed delivery, edge triggered
+ * and acknowledged with an APIC EOI.
+ */
+
+#include "../../include/types.h"
+#include "../../include/kprintf.h"
+#include "../../include/debug.h"
+#include "../../include/apic.h"
+#include "../../include/sync/spinlock.h"
+#include "../../include/sched/scheduler.h"
+#include "../../include/hal/msi.h"
+
+static bool msi_vector_used[MSI_VECTOR_COUNT] = {false};
+// Thread each vector was allocated by (0 for the kernel); only it may free it
+static uint64_t msi_owner_tid[MSI_VECTOR_COUNT] = {0};
+static spinlock_t msi_lock = SPINLOCK_INIT;
+
+/**
+ * Allocate an MSI vector
+ */
+int msi_alloc(msi_message_t* msg) {
+    if (!msg) {
+        return -1;
+    }
+
+    thread_t* current = thread_current();
+    uint64_t tid = current ? current->tid : 0;
+
+    int slot = -1;
+    spinlock_lock(&msi_lock);
+    for (int i = 0; i < MSI_VECTOR_COUNT; i++) {
+        if (!msi_vector_used[i]) {
+            msi_vector_used[i] = true;
+            msi_owner_tid[i] = tid;
+            slot = i;
+            break;
+        }
+    }
+    spinlock_unlock(&msi_lock);
+
+    if (slot < 0) {
+        kdebug("MSI: out of vectors\n");
+        return -1;
+    }
+
+    uint32_t vector = MSI_VECTOR_BASE + slot;
+    uint32_t apic_id = (apic_read(LAPIC_ID) >> 24) & 0xFF;
+
+    // Destination ID in bits 19:12, physical destination mode
+    msg->address = MSI_ADDRESS_BASE | ((uint64_t)apic_id << 12);
+    // Fixed delivery mode, edge triggered
+    msg->data = vector;
+    msg->irq = vector - 32;
+
+    return (int)msg->irq;
+}
+
+/**
+ * Release an MSI vector, if the calling thread is the one that allocated it
+ */
+int msi_free(uint8_t irq) {
+    uint32_t vector = (uint32_t)irq + 32;
+    if (!msi_is_vector(vector)) {
+        return -1;
+    }
+
+    thread_t* current = thread_current();
+    uint64_t tid = current ? current->tid : 0;
+    uint32_t slot = vector - MSI_VECTOR_BASE;
+
+    int result = -1;
+    spinlock_lock(&msi_lock);
+    if (msi_vector_used[slot] && msi_owner_tid[slot] == tid) {
+        msi_vector_used[slot] = false;
+        msi_owner_tid[slot] = 0;
+        result = 0;
+    }
+    spinlock_unlock(&msi_lock);
+
+    if (result < 0) {
+        kdebug("MSI: thread %lu may not free IRQ %u\n", tid, irq);
+    }
+    return result;
+}
+
+/**
+ * Whether an interrupt vector belongs to the MSI range
+ */
+bool msi_is_vector(uint64_t vector) {
+    return vector >= MSI_VECTOR_BASE && vector < MSI_VECTOR_BASE + MSI_VECTOR_COUNT;
+}
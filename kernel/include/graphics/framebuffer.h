@@ -24,6 +24,19 @@ typedef struct {
     bool initialized;           // Is framebuffer initialized?
 } framebuffer_t;
 
+// Framebuffer description returned by SYS_FB_GET_INFO
+typedef struct {
+    uint64_t phys_base;         // Physical base address
+    uint32_t width;             // Width in pixels
+    uint32_t height;            // Height in pixels
+    uint32_t pitch;             // Bytes per scanline
+    uint32_t bpp;               // Bits per pixel
+    uint32_t red_mask;          // Red color mask
+    uint32_t green_mask;        // Green color mask
+    uint32_t blue_mask;         // Blue color mask
+    uint32_t reserved_mask;     // Reserved/alpha mask
+} framebuffer_user_info_t;
+
 // Color manipulation macros
 #define RGB(r, g, b) ((uint32_t)((r) << 16) | ((g) << 8) | (b))
 #define RGBA(r, g, b, a) ((uint32_t)((a) << 24) | ((r) << 16) | ((g) << 8) | (b))
@@ -11,20 +11,29 @@
 // IRQ handler callback type
 typedef void (*irq_handler_callback_t)(void* context);
 
+// Shared IRQ handler: returns nonzero if its device raised the interrupt
+typedef bool (*irq_shared_callback_t)(void* context);
+
 /**
  * Register an IRQ handler from user-space
  */
 int irq_register(uint8_t irq, irq_handler_callback_t handler, void* context);
 
 /**
- * Unregister an IRQ handler
+ * Register a handler on a line other devices may also use
+ */
+int irq_register_shared(uint8_t irq, irq_shared_callback_t handler, void* context);
+
+/**
+ * Unregister an IRQ handler (NULL removes the caller's handlers)
  */
 int irq_unregister(uint8_t irq, irq_handler_callback_t handler);
 
 /**
  * Call user-space IRQ handlers (called from interrupt context)
+ * Returns true if any handler claimed the interrupt
  */
-void irq_call_handlers(uint8_t irq);
+bool irq_call_handlers(uint8_t irq);
 
 /**
  * Enable IRQ in PIC
@@ -0,0 +1,46 @@
+/**
+ * @file msi.h
+ * @brief Message Signaled Interrupt vector allocation
+ */
+
+#ifndef KERNEL_HAL_MSI_H
+#define KERNEL_HAL_MSI_H
+
+#include "../../include/types.h"
+
+// Vectors above the legacy PIC range are handed out to MSI/MSI-X devices
+#define MSI_VECTOR_BASE   48
+#define MSI_VECTOR_COUNT  16
+
+// Local APIC message address window (Intel SDM 10.11.1)
+#define MSI_ADDRESS_BASE  0xFEE00000ULL
+
+/**
+ * Address/data pair a device writes to raise its interrupt.
+ * irq is the number to pass to irq_register().
+ */
+typedef struct {
+    uint64_t address;
+    uint32_t data;
+    uint32_t irq;
+} msi_message_t;
+
+/**
+ * Allocate an MSI vector targeting the current CPU
+ * Returns the IRQ number, or -1 if all vectors are in use
+ */
+int msi_alloc(msi_message_t* msg);
+
+/**
+ * Release a vector returned by msi_alloc(). Only the thread that
+ * allocated it may free it.
+ * Returns 0, or -1 if the vector is not the caller's
+ */
+int msi_free(uint8_t irq);
+
+/**
+ * Whether an interrupt vector belongs to the MSI range
+ */
+bool msi_is_vector(uint64_t vector);
+
+#endif // KERNEL_HAL_MSI_H
@@ -17,6 +17,10 @@
 // Maximum inline message size
 #define IPC_INLINE_SIZE 64
 
+// Notification msg_id for IRQs bound to a port; inline data is a uint64_t
+// mask of the IRQs raised since the last one (bit n = IRQ n)
+#define IPC_MSG_ID_IRQ 0xFFFFFFFF00000001ULL
+
 // IPC message structure
 typedef struct ipc_message {
     uint64_t sender_tid;
@@ -26,6 +30,7 @@ typedef struct ipc_message {
     uint8_t inline_data[IPC_INLINE_SIZE];
     void* buffer;
     size_t buffer_size;
+    uint64_t reply_port;    // Where responses go; 0 if the sender expects none
 } ipc_message_t;
 
 // IPC port (endpoint for communication)
@@ -78,6 +83,45 @@ int ipc_receive(uint64_t port_id, ipc_message_t* msg);
  */
 int ipc_try_receive(uint64_t port_id, ipc_message_t* msg);
 
+/**
+ * Receive a message, blocking for at most timeout_ms
+ * @param port_id Port to receive from
+ * @param msg Buffer to receive message
+ * @param timeout_ms Maximum time to wait
+ * @return 0 on success, ERR_TIMEOUT if nothing arrived in time, -1 on error
+ */
+int ipc_receive_timeout(uint64_t port_id, ipc_message_t* msg, uint64_t timeout_ms);
+
+/**
+ * Record an IRQ for a port and wake a thread receiving on it. Safe to call
+ * from interrupt context.
+ * @param port_id Port the IRQ is bound to
+ * @param irq IRQ that was raised
+ */
+void ipc_notify_irq(uint64_t port_id, uint8_t irq);
+
+/**
+ * Process that sent the message the calling thread last received. Servers
+ * read it right after receiving a request to learn who is asking.
+ * @return Sender's process ID, 0 for the kernel
+ */
+uint64_t ipc_sender_pid(void);
+
+/**
+ * Process that created a port
+ * @param port_id Port to look up
+ * @return Owner's process ID, 0 if the port does not exist
+ */
+uint64_t ipc_port_owner_pid(uint64_t port_id);
+
+/**
+ * Reply to a received message on its reply port
+ * @param request Message being answered
+ * @param response Response to send
+ * @return 0 on success, -1 on error
+ */
+int ipc_reply(const ipc_message_t* request, ipc_message_t* response);
+
 /**
  * Send and receive (call/reply pattern)
  * @param port_id Target port
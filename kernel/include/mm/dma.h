@@ -28,11 +28,31 @@ typedef struct dma_buffer {
     size_t size;                  // Size in bytes
     uint32_t flags;               // Buffer flags
     uint64_t owner_tid;           // Thread ID of owner
+    pid_t owner_pid;              // Process that allocated it; only it may share
     uint64_t device_id;           // Device ID using this buffer (0 = none)
     uint64_t iova;                // I/O Virtual Address (if mapped via IOMMU)
     struct dma_buffer* next;
 } dma_buffer_t;
 
+// A DMA buffer mapped into a second process
+typedef struct dma_share {
+    uint64_t handle;              // Names the share to both processes
+    dma_buffer_t* buffer;         // Buffer being shared
+    pid_t owner_pid;              // Process that allocated the buffer
+    pid_t target_pid;             // Process the buffer is mapped into
+    vaddr_t target_address;       // Where the target sees it
+    bool writable;                // Target may write
+    struct dma_share* next;
+} dma_share_t;
+
+// What the target of a share learns when adopting it
+typedef struct {
+    uint64_t virtual_address;
+    uint64_t physical_address;
+    uint64_t size;
+    uint64_t writable;
+} dma_share_info_t;
+
 /**
  * Initialize DMA subsystem
  */
@@ -60,6 +80,32 @@ int dma_free(void* vaddr);
  */
 paddr_t dma_get_physical(void* vaddr);
 
+/**
+ * Map a DMA buffer owned by the calling process into another process
+ * @param vaddr Virtual address returned by dma_alloc
+ * @param target_pid Process to share with
+ * @param writable Whether the target may write to the buffer
+ * @return Share handle, or 0 on error (not the owner, buffer larger than a
+ *         share slot, or every slot in use)
+ */
+uint64_t dma_share(void* vaddr, pid_t target_pid, bool writable);
+
+/**
+ * Look up a share made with the calling process
+ * @param handle Handle returned by dma_share
+ * @param info Filled with the mapping as the caller sees it
+ * @return 0 on success, -1 on error
+ */
+int dma_adopt(uint64_t handle, dma_share_info_t* info);
+
+/**
+ * End a share, unmapping the buffer from the target. Either side may call
+ * it; afterwards the target faults on access.
+ * @param handle Handle returned by dma_share
+ * @return 0 on success, -1 on error
+ */
+int dma_unshare(uint64_t handle);
+
 /**
  * Sync DMA buffer (flush/invalidate cache)
  * @param vaddr Virtual address
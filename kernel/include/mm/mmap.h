@@ -21,6 +21,8 @@
 #define MAP_SHARED   0x02
 #define MAP_FIXED    0x04
 #define MAP_ANONYMOUS 0x08
+#define MAP_DEVICE   0x10   // Device memory (MMIO); its pages are never freed
+#define MAP_DMA_SHARE 0x20  // Another process's DMA buffer; its pages are never freed
 
 // Memory mapping structure
 typedef struct memory_mapping {
@@ -37,6 +39,8 @@ typedef struct memory_mapping {
 error_code_t mmap_init(void);
 vaddr_t mmap_alloc(address_space_t* as, size_t size, uint64_t prot, uint64_t flags, int fd, uint64_t offset);
 error_code_t mmap_free(address_space_t* as, vaddr_t addr, size_t size);
+vaddr_t mmap_device(address_space_t* as, paddr_t paddr, size_t size, uint64_t page_flags);
+vaddr_t mmap_dma_share(address_space_t* as, paddr_t paddr, size_t size, uint64_t page_flags);
 memory_mapping_t* mmap_find(address_space_t* as, vaddr_t addr);
 error_code_t mmap_protect(address_space_t* as, vaddr_t addr, size_t size, uint64_t prot);
 
@@ -49,6 +49,7 @@ typedef struct thread {
     uint64_t cpu_time;               // Total CPU time
     uint64_t wakeup_time;            // For sleeping threads
     int32_t cpu_affinity;            // CPU affinity (-1 = no affinity, >= 0 = specific CPU)
+    uint64_t ipc_sender_pid;         // Process that sent the last IPC message received
 } thread_t;
 
 /**
@@ -86,6 +87,13 @@ void thread_yield(void);
  */
 void thread_sleep(uint64_t ms);
 
+/**
+ * Mark current thread sleeping for milliseconds without switching away.
+ * Like thread_prepare_block(), the caller drops its locks and then calls
+ * scheduler_schedule(); an early wake in between is not lost.
+ */
+void thread_prepare_sleep(uint64_t ms);
+
 /**
  * Get current thread
  */
@@ -124,11 +132,23 @@ bool scheduler_try_work_stealing(uint32_t idle_cpu_id);
  */
 void thread_block(void);
 
+/**
+ * Mark current thread blocked without switching away. The caller drops its
+ * locks and then calls scheduler_schedule(); a wake in between makes the
+ * thread runnable again instead of being lost.
+ */
+void thread_prepare_block(void);
+
 /**
  * Unblock a thread
  */
 void thread_unblock(thread_t* thread);
 
+/**
+ * Wake a blocked or sleeping thread early
+ */
+void thread_wake(thread_t* thread);
+
 // Global thread table (for CPU affinity and other uses)
 #define MAX_THREADS 256
 extern spinlock_t thread_table_lock;
@@ -61,9 +61,24 @@
 #define SYS_IO_READ 49
 #define SYS_IO_WRITE 50
 #define SYS_STAT 51
+#define SYS_DMA_GET_PHYSICAL 52
+#define SYS_IPC_TRY_RECEIVE 53
+#define SYS_IPC_RECEIVE_TIMEOUT 54
+#define SYS_IPC_REPLY   55
+#define SYS_MSI_ALLOC   56
+#define SYS_MSI_FREE    57
+#define SYS_DMA_SHARE   58
+#define SYS_DMA_ADOPT   59
+#define SYS_DMA_UNSHARE 60
+#define SYS_IRQ_REGISTER_SHARED 61
+#define SYS_FB_GET_INFO 62
+#define SYS_IPC_SENDER_PID 63
+#define SYS_IPC_PORT_OWNER 64
+#define SYS_IRQ_BIND_PORT 65
+#define SYS_GET_RANDOM  66
 
 // Maximum syscall number
-#define SYS_MAX         51
+#define SYS_MAX         66
 
 /**
  * Initialize system call handling
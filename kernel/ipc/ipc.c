@@ -11,6 +11,10 @@
 #include "../include/debug.h"
 #include "../include/sync/spinlock.h"
 #include "../include/security/capability.h"
+#include "../include/hal/timer.h"
+#include "../include/errors.h"
+#include "../include/process.h"
+#include "../include/string.h"
 
 #define MAX_PORTS 256
 #define MAX_QUEUE_SIZE 32
@@ -18,6 +22,7 @@
 // Message queue node
 typedef struct message_node {
     ipc_message_t message;
+    uint64_t sender_pid;        // Process that sent it (0 = kernel)
     struct message_node* next;
 } message_node_t;
 
@@ -31,6 +36,7 @@ typedef struct waiting_thread {
 typedef struct ipc_port_internal {
     uint64_t port_id;
     uint64_t owner_tid;
+    uint64_t owner_pid;     // Process that created the port (0 = kernel)
     
     // Message queue
     message_node_t* queue_head;
@@ -42,6 +48,11 @@ typedef struct ipc_port_internal {
     waiting_thread_t* waiting_receivers;  // Threads waiting to receive
     waiting_thread_t* waiting_senders;    // Threads waiting to send (queue full)
     
+    // IRQs raised since the owner last received (bit n = IRQ n), and the
+    // receiver to wake for them; set from interrupt context without the lock
+    volatile uint64_t pending_irqs;
+    thread_t* volatile irq_waiter;
+    
     // Lock for this port
     spinlock_t lock;
     
@@ -53,6 +64,14 @@ static ipc_port_internal_t* port_table[MAX_PORTS];
 static uint64_t next_port_id = 1;
 static spinlock_t port_table_lock = SPINLOCK_INIT;
 
+/**
+ * Process the calling thread runs in, 0 for the kernel
+ */
+static uint64_t current_pid(void) {
+    process_t* current = process_get_current();
+    return current ? (uint64_t)current->pid : 0;
+}
+
 /**
  * Initialize IPC system
  */
@@ -87,12 +106,15 @@ uint64_t ipc_create_port(void) {
     
     port->port_id = next_port_id++;
     port->owner_tid = thread_current()->tid;
+    port->owner_pid = current_pid();
     port->queue_head = NULL;
     port->queue_tail = NULL;
     port->queue_size = 0;
     port->queue_max = MAX_QUEUE_SIZE;
     port->waiting_receivers = NULL;
     port->waiting_senders = NULL;
+    port->pending_irqs = 0;
+    port->irq_waiter = NULL;
     port->next = NULL;
     spinlock_init(&port->lock);
     
@@ -111,6 +133,10 @@ int ipc_destroy_port(uint64_t port_id) {
         return -1;
     }
     
+    // No more IRQ notifications for a port about to be freed
+    extern void irq_unbind_port(uint64_t port_id);
+    irq_unbind_port(port_id);
+    
     spinlock_lock(&port_table_lock);
     
     ipc_port_internal_t* port = port_table[port_id];
@@ -156,6 +182,37 @@ int ipc_destroy_port(uint64_t port_id) {
     return 0;
 }
 
+/**
+ * Whether the current thread may send to a port: it owns the port or
+ * holds a capability with the write right for it
+ */
+static bool may_send(ipc_port_internal_t* port) {
+    // Look up capability for this port using capability system
+    extern uint64_t capability_find_for_port(uint64_t port_id);
+    uint64_t cap_id = capability_find_for_port(port->port_id);
+    if (cap_id == 0) {
+        // No capability found - check if sender is port owner
+        return port->owner_tid == thread_current()->tid;
+    }
+    
+    // Check if capability grants write right
+    return capability_check(cap_id, CAP_RIGHT_WRITE);
+}
+
+/**
+ * Whether the current thread may receive from a port: it owns the port or
+ * holds a capability with the read right for it
+ */
+static bool may_receive(ipc_port_internal_t* port) {
+    extern uint64_t capability_find_for_port(uint64_t port_id);
+    uint64_t cap_id = capability_find_for_port(port->port_id);
+    if (cap_id == 0) {
+        return port->owner_tid == thread_current()->tid;
+    }
+    
+    return capability_check(cap_id, CAP_RIGHT_READ);
+}
+
 /**
  * Send a message
  */
@@ -172,19 +229,25 @@ int ipc_send(uint64_t port_id, ipc_message_t* msg) {
         return -1;
     }
     
-    // Capability check: verify sender has capability to send to this port
-    // Look up capability for this port using capability system
-    extern uint64_t capability_find_for_port(uint64_t port_id);
-    uint64_t cap_id = capability_find_for_port(port_id);
-    if (cap_id == 0) {
-        // No capability found - check if sender is port owner
-        if (port->owner_tid != thread_current()->tid) {
-            return -1;  // Not authorized
+    if (!may_send(port)) {
+        return -1;
+    }
+    
+    // Replies only go where the sender asked; 0 means it expects none.
+    // The receiver replies with its own rights, so the sender may only
+    // name a port it could send to itself.
+    uint64_t reply_port = msg->reply_port;
+    if (reply_port != 0) {
+        if (reply_port >= MAX_PORTS) {
+            return -1;
         }
-    } else {
-        // Check if capability grants write right
-        if (!capability_check(cap_id, CAP_RIGHT_WRITE)) {
-            return -1;  // No write right
+        
+        spinlock_lock(&port_table_lock);
+        ipc_port_internal_t* reply = port_table[reply_port];
+        spinlock_unlock(&port_table_lock);
+        
+        if (!reply || !may_send(reply)) {
+            return -1;
         }
     }
     
@@ -218,6 +281,8 @@ int ipc_send(uint64_t port_id, ipc_message_t* msg) {
     // Copy message
     node->message = *msg;
     node->message.sender_tid = thread_current()->tid;
+    node->message.reply_port = reply_port;
+    node->sender_pid = current_pid();
     node->next = NULL;
     
     // Add to queue
@@ -231,12 +296,12 @@ int ipc_send(uint64_t port_id, ipc_message_t* msg) {
     
     port->queue_size++;
     
-    // Wake up a waiting receiver if any
+    // Wake up a waiting receiver if any (it may be in a timed wait)
     if (port->waiting_receivers) {
         waiting_thread_t* waiting = port->waiting_receivers;
         port->waiting_receivers = waiting->next;
         
-        thread_unblock(waiting->thread);
+        thread_wake(waiting->thread);
         kfree(waiting);
     }
     
@@ -245,6 +310,86 @@ int ipc_send(uint64_t port_id, ipc_message_t* msg) {
     return 0;
 }
 
+/**
+ * Drop a thread's entry from a port's waiting receivers, if still there
+ * (port lock held)
+ */
+static void remove_waiting_receiver(ipc_port_internal_t* port, thread_t* thread) {
+    waiting_thread_t** link = &port->waiting_receivers;
+    while (*link) {
+        if ((*link)->thread == thread) {
+            waiting_thread_t* waiting = *link;
+            *link = waiting->next;
+            kfree(waiting);
+            return;
+        }
+        link = &(*link)->next;
+    }
+}
+
+/**
+ * Hand a port's pending IRQs to a receiver as one notification, if there
+ * are any (port lock held)
+ */
+static bool take_pending_irqs(ipc_port_internal_t* port, ipc_message_t* msg) {
+    uint64_t irqs = __atomic_exchange_n(&port->pending_irqs, 0, __ATOMIC_SEQ_CST);
+    if (!irqs) {
+        return false;
+    }
+    
+    memset(msg, 0, sizeof(*msg));
+    msg->type = IPC_MSG_NOTIFICATION;
+    msg->msg_id = IPC_MSG_ID_IRQ;
+    memcpy(msg->inline_data, &irqs, sizeof(irqs));
+    msg->inline_size = sizeof(irqs);
+    thread_current()->ipc_sender_pid = 0;
+    return true;
+}
+
+/**
+ * Block the current thread until a message or IRQ arrives on the port, or
+ * until the uptime reaches `deadline_ms` if it is not 0 (port lock held on
+ * entry and on return). ERR_TIMEOUT once the deadline has passed.
+ */
+static int wait_for_message(ipc_port_internal_t* port, uint64_t deadline_ms) {
+    uint64_t now = timer_get_ms();
+    if (deadline_ms != 0 && now >= deadline_ms) {
+        return ERR_TIMEOUT;
+    }
+    
+    waiting_thread_t* waiting = (waiting_thread_t*)kmalloc(sizeof(waiting_thread_t));
+    if (!waiting) {
+        return -1;
+    }
+    
+    thread_t* self = thread_current();
+    waiting->thread = self;
+    waiting->next = port->waiting_receivers;
+    port->waiting_receivers = waiting;
+    
+    // Blocked (or asleep until the deadline) before the lock drops, so a
+    // sender's wake cannot be lost
+    if (deadline_ms != 0) {
+        thread_prepare_sleep(deadline_ms - now);
+    } else {
+        thread_prepare_block();
+    }
+    __atomic_store_n(&port->irq_waiter, self, __ATOMIC_SEQ_CST);
+    spinlock_unlock(&port->lock);
+    
+    // An IRQ raised before irq_waiter was set found nobody to wake
+    if (__atomic_load_n(&port->pending_irqs, __ATOMIC_SEQ_CST)) {
+        thread_wake(self);
+    }
+    scheduler_schedule();
+    
+    spinlock_lock(&port->lock);
+    __atomic_store_n(&port->irq_waiter, NULL, __ATOMIC_SEQ_CST);
+    // Only a sender takes our entry; a timeout or an IRQ wake leaves it
+    remove_waiting_receiver(port, self);
+    return 0;
+}
+
 /**
  * Receive a message (blocking)
  */
@@ -261,39 +406,23 @@ int ipc_receive(uint64_t port_id, ipc_message_t* msg) {
         return -1;
     }
     
-    // Capability check: verify receiver has capability to receive from this port
-    // Look up capability for this port using capability system
-    extern uint64_t capability_find_for_port(uint64_t port_id);
-    uint64_t cap_id = capability_find_for_port(port_id);
-    if (cap_id == 0) {
-        // No capability found - check if receiver is port owner
-        if (port->owner_tid != thread_current()->tid) {
-            return -1;  // Not authorized
-        }
-    } else {
-        // Check if capability grants read right
-        if (!capability_check(cap_id, CAP_RIGHT_READ)) {
-            return -1;  // No read right
-        }
+    if (!may_receive(port)) {
+        return -1;  // Not authorized
     }
     
     spinlock_lock(&port->lock);
     
     // Wait for message if queue is empty
-    while (port->queue_size == 0) {
-        waiting_thread_t* waiting = (waiting_thread_t*)kmalloc(sizeof(waiting_thread_t));
-        if (!waiting) {
+    while (port->queue_size == 0 && !port->pending_irqs) {
+        if (wait_for_message(port, 0) != 0) {
             spinlock_unlock(&port->lock);
             return -1;
         }
-        
-        waiting->thread = thread_current();
-        waiting->next = port->waiting_receivers;
-        port->waiting_receivers = waiting;
-        
+    }
+    
+    if (take_pending_irqs(port, msg)) {
         spinlock_unlock(&port->lock);
-        thread_block();
-        spinlock_lock(&port->lock);
+        return 0;
     }
     
     // Dequeue message
@@ -312,6 +441,7 @@ int ipc_receive(uint64_t port_id, ipc_message_t* msg) {
     
     // Copy message to output
     *msg = node->message;
+    thread_current()->ipc_sender_pid = node->sender_pid;
     kfree(node);
     
     // Wake up a waiting sender if any
@@ -344,8 +474,17 @@ int ipc_try_receive(uint64_t port_id, ipc_message_t* msg) {
         return -1;
     }
     
+    if (!may_receive(port)) {
+        return -1;  // Not authorized
+    }
+    
     spinlock_lock(&port->lock);
     
+    if (take_pending_irqs(port, msg)) {
+        spinlock_unlock(&port->lock);
+        return 0;
+    }
+    
     if (port->queue_size == 0) {
         spinlock_unlock(&port->lock);
         return -1;  // No message available
@@ -362,6 +501,7 @@ int ipc_try_receive(uint64_t port_id, ipc_message_t* msg) {
     
     // Copy message to output
     *msg = node->message;
+    thread_current()->ipc_sender_pid = node->sender_pid;
     kfree(node);
     
     // Wake up a waiting sender if any
@@ -379,20 +519,145 @@ int ipc_try_receive(uint64_t port_id, ipc_message_t* msg) {
 }
 
 /**
- * Send and receive (call/reply pattern)
+ * Receive a message, waiting at most timeout_ms for one to arrive
  */
-int ipc_call(uint64_t port_id, ipc_message_t* request, ipc_message_t* response) {
-    // Send request
-    if (ipc_send(port_id, request) != 0) {
+int ipc_receive_timeout(uint64_t port_id, ipc_message_t* msg, uint64_t timeout_ms) {
+    if (port_id >= MAX_PORTS || !msg) {
+        return -1;
+    }
+    
+    spinlock_lock(&port_table_lock);
+    ipc_port_internal_t* port = port_table[port_id];
+    spinlock_unlock(&port_table_lock);
+    
+    if (!port) {
         return -1;
     }
     
-    // Create reply port
+    if (!may_receive(port)) {
+        return -1;  // Not authorized
+    }
+    
+    // At least 1, as 0 would mean no deadline
+    uint64_t deadline = timer_get_ms() + timeout_ms;
+    if (deadline == 0) {
+        deadline = 1;
+    }
+    
+    spinlock_lock(&port->lock);
+    
+    while (port->queue_size == 0 && !port->pending_irqs) {
+        int waited = wait_for_message(port, deadline);
+        if (waited != 0) {
+            spinlock_unlock(&port->lock);
+            return waited;
+        }
+    }
+    
+    if (take_pending_irqs(port, msg)) {
+        spinlock_unlock(&port->lock);
+        return 0;
+    }
+    
+    // Dequeue message
+    message_node_t* node = port->queue_head;
+    port->queue_head = node->next;
+    if (!port->queue_head) {
+        port->queue_tail = NULL;
+    }
+    
+    port->queue_size--;
+    
+    *msg = node->message;
+    thread_current()->ipc_sender_pid = node->sender_pid;
+    kfree(node);
+    
+    // Wake up a waiting sender if any
+    if (port->waiting_senders) {
+        waiting_thread_t* waiting = port->waiting_senders;
+        port->waiting_senders = waiting->next;
+        
+        thread_unblock(waiting->thread);
+        kfree(waiting);
+    }
+    
+    spinlock_unlock(&port->lock);
+    
+    return 0;
+}
+
+/**
+ * Record an IRQ for a port and wake its receiver. Runs in interrupt
+ * context, so it takes no port lock.
+ */
+void ipc_notify_irq(uint64_t port_id, uint8_t irq) {
+    if (port_id >= MAX_PORTS || irq >= 64) {
+        return;
+    }
+    
+    ipc_port_internal_t* port = port_table[port_id];
+    if (!port) {
+        return;
+    }
+    
+    __atomic_fetch_or(&port->pending_irqs, 1ULL << irq, __ATOMIC_SEQ_CST);
+    thread_t* waiter = __atomic_exchange_n(&port->irq_waiter, NULL, __ATOMIC_SEQ_CST);
+    if (waiter) {
+        thread_wake(waiter);
+    }
+}
+
+/**
+ * Process that sent the message the calling thread last received
+ */
+uint64_t ipc_sender_pid(void) {
+    return thread_current()->ipc_sender_pid;
+}
+
+/**
+ * Process that created a port, 0 if there is no such port
+ */
+uint64_t ipc_port_owner_pid(uint64_t port_id) {
+    if (port_id >= MAX_PORTS) {
+        return 0;
+    }
+    
+    spinlock_lock(&port_table_lock);
+    ipc_port_internal_t* port = port_table[port_id];
+    uint64_t owner = port ? port->owner_pid : 0;
+    spinlock_unlock(&port_table_lock);
+    
+    return owner;
+}
+
+/**
+ * Reply to a received message
+ */
+int ipc_reply(const ipc_message_t* request, ipc_message_t* response) {
+    if (!request || !response || request->reply_port == 0) {
+        return -1;
+    }
+    
+    return ipc_send(request->reply_port, response);
+}
+
+/**
+ * Send and receive (call/reply pattern)
+ */
+int ipc_call(uint64_t port_id, ipc_message_t* request, ipc_message_t* response) {
+    // Create reply port before sending so the response has somewhere to go
     uint64_t reply_port = ipc_create_port();
     if (reply_port == 0) {
         return -1;
     }
     
+    // Send request
+    request->reply_port = reply_port;
+    if (ipc_send(port_id, request) != 0) {
+        ipc_destroy_port(reply_port);
+        return -1;
+    }
+    
     // Wait for response
     int result = ipc_receive(reply_port, response);
     
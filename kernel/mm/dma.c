@@ -9,6 +9,7 @@
 #include "../include/mm/vmm.h"
 #include "../include/mm/pmm.h"
 #include "../include/mm/heap.h"
+#include "../include/mm/mmap.h"
 #include "../include/sched/scheduler.h"
 #include "../include/process.h"
 #include "../include/kprintf.h"
@@ -19,6 +20,7 @@
 
 #define MAX_DMA_BUFFERS 256
 #define DMA_BASE_VADDR 0x50000000ULL  // Base virtual address for DMA buffers (1.25GB)
+#define DMA_MAX_SHARES 64                  // Shares at once, and so the largest handle
 
 // IOVA (I/O Virtual Address) Configuration
 #define IOVA_START 0x10000000ULL      // Start at 256MB
@@ -36,6 +38,7 @@
 static dma_buffer_t* dma_buffers = NULL;
 static spinlock_t dma_list_lock = SPINLOCK_INIT;
 static uint64_t next_buffer_id = 1;
+static dma_share_t* dma_shares = NULL;
 
 // IOMMU Context
 typedef struct {
@@ -308,6 +311,7 @@ void* dma_alloc(size_t size, uint32_t flags) {
     buffer->size = actual_size;
     buffer->flags = flags;
     buffer->owner_tid = thread_current() ? thread_current()->tid : 0;
+    buffer->owner_pid = proc->pid;
     buffer->device_id = 0;
     buffer->next = NULL;
     
@@ -326,6 +330,125 @@ void* dma_alloc(size_t size, uint32_t flags) {
     return (void*)virtual_addr;
 }
 
+/**
+ * Unmap a share from its target and forget it. Called with dma_list_lock
+ * held.
+ */
+static void dma_share_remove_locked(dma_share_t* share) {
+    process_t* target = process_get_by_pid(share->target_pid);
+    address_space_t* as = target ? process_get_address_space(target) : NULL;
+    if (as && share->target_address != 0) {
+        mmap_free(as, share->target_address, share->buffer->size);
+    }
+    
+    dma_share_t** link = &dma_shares;
+    while (*link && *link != share) link = &(*link)->next;
+    if (*link) *link = share->next;
+    kfree(share);
+}
+
+static dma_share_t* find_dma_share_locked(uint64_t handle) {
+    for (dma_share_t* share = dma_shares; share != NULL; share = share->next) {
+        if (share->handle == handle) return share;
+    }
+    return NULL;
+}
+
+/**
+ * Lowest share handle not in use, or 0 when every slot is taken. Called
+ * with dma_list_lock held.
+ */
+static uint64_t alloc_share_handle_locked(void) {
+    for (uint64_t handle = 1; handle <= DMA_MAX_SHARES; handle++) {
+        if (!find_dma_share_locked(handle)) return handle;
+    }
+    return 0;
+}
+
+uint64_t dma_share(void* vaddr, pid_t target_pid, bool writable) {
+    process_t* owner = process_get_current();
+    dma_buffer_t* buffer = find_dma_buffer((vaddr_t)vaddr);
+    if (!owner || !buffer || (vaddr_t)vaddr != buffer->virtual_address) return 0;
+    
+    // Buffer addresses are global, so only the allocating process may share
+    if (buffer->owner_pid != owner->pid) return 0;
+    
+    process_t* target = process_get_by_pid(target_pid);
+    address_space_t* as = target ? process_get_address_space(target) : NULL;
+    if (!as || target_pid == owner->pid) return 0;
+    
+    dma_share_t* share = (dma_share_t*)kzalloc(sizeof(dma_share_t));
+    if (!share) return 0;
+    
+    spinlock_lock(&dma_list_lock);
+    share->handle = alloc_share_handle_locked();
+    if (share->handle == 0) {
+        spinlock_unlock(&dma_list_lock);
+        kfree(share);
+        return 0;
+    }
+    share->buffer = buffer;
+    share->owner_pid = owner->pid;
+    share->target_pid = target_pid;
+    share->writable = writable;
+    // Listed straight away so the handle is not handed out twice
+    share->next = dma_shares;
+    dma_shares = share;
+    spinlock_unlock(&dma_list_lock);
+    
+    uint64_t vmm_flags = VMM_PRESENT | VMM_USER | VMM_NX;
+    if (writable) vmm_flags |= VMM_WRITE;
+    if (buffer->flags & DMA_FLAG_UNCACHED) vmm_flags |= VMM_NOCACHE;
+    if (buffer->flags & DMA_FLAG_WRITE_COMBINE) vmm_flags |= VMM_WRITETHROUGH;
+    
+    // Recorded as a mapping of the target, so nothing else is placed there
+    vaddr_t target_address = mmap_dma_share(as, buffer->physical_address, buffer->size, vmm_flags);
+    uint64_t handle = share->handle;
+    spinlock_lock(&dma_list_lock);
+    if (target_address == 0) {
+        dma_share_remove_locked(share);
+        handle = 0;
+    } else {
+        share->target_address = target_address;
+    }
+    spinlock_unlock(&dma_list_lock);
+    
+    return handle;
+}
+
+int dma_adopt(uint64_t handle, dma_share_info_t* info) {
+    process_t* proc = process_get_current();
+    if (!proc || !info) return -1;
+    
+    spinlock_lock(&dma_list_lock);
+    dma_share_t* share = find_dma_share_locked(handle);
+    if (!share || share->target_pid != proc->pid) {
+        spinlock_unlock(&dma_list_lock);
+        return -1;
+    }
+    info->virtual_address = share->target_address;
+    info->physical_address = share->buffer->physical_address;
+    info->size = share->buffer->size;
+    info->writable = share->writable ? 1 : 0;
+    spinlock_unlock(&dma_list_lock);
+    return 0;
+}
+
+int dma_unshare(uint64_t handle) {
+    process_t* proc = process_get_current();
+    if (!proc) return -1;
+    
+    spinlock_lock(&dma_list_lock);
+    dma_share_t* share = find_dma_share_locked(handle);
+    if (!share || (share->owner_pid != proc->pid && share->target_pid != proc->pid)) {
+        spinlock_unlock(&dma_list_lock);
+        return -1;
+    }
+    dma_share_remove_locked(share);
+    spinlock_unlock(&dma_list_lock);
+    return 0;
+}
+
 int dma_free(void* vaddr) {
     if (!vaddr) return -1;
     vaddr_t vaddr_val = (vaddr_t)vaddr;
@@ -338,6 +461,16 @@ int dma_free(void* vaddr) {
         return -1;
     }
     
+    // Revoke every share before the pages go back to the allocator
+    spinlock_lock(&dma_list_lock);
+    dma_share_t* share = dma_shares;
+    while (share) {
+        dma_share_t* next = share->next;
+        if (share->buffer == buffer) dma_share_remove_locked(share);
+        share = next;
+    }
+    spinlock_unlock(&dma_list_lock);
+    
     size_t pages = buffer->size / PAGE_SIZE;
     process_t* proc = process_get_current();
     if (proc) {
@@ -32,6 +32,15 @@ static inline void* phys_to_virt_pt(paddr_t paddr) {
 #define USER_SPACE_END   0x00007FFFFFFFFFFFULL
 #define USER_SPACE_SIZE  (USER_SPACE_END - USER_SPACE_START)
 
+// Device (MMIO) mappings live apart from RAM, clear of the fixed
+// shared-memory window at 0x40000000 and the DMA buffers from 0x50000000
+#define DEVICE_SPACE_START 0x0000001000000000ULL  // 64GB
+#define DEVICE_SPACE_END   0x0000002000000000ULL  // 128GB
+
+// DMA buffers shared into another process appear just above them
+#define DMA_SHARE_SPACE_START 0x0000002000000000ULL  // 128GB
+#define DMA_SHARE_SPACE_END   0x0000003000000000ULL  // 192GB
+
 // Current allocation pointer (simple bump allocator) - NOTE: This should also be per-AS!
 // static vaddr_t current_brk = USER_SPACE_START; // REMOVED - Using logic to find holes instead or AS-specific brk
 
@@ -60,6 +69,37 @@ memory_mapping_t* mmap_find(address_space_t* as, vaddr_t addr) {
     return NULL;
 }
 
+/**
+ * Lowest page-aligned range of `size` bytes in [base, limit) that no
+ * mapping of the address space overlaps, 0 if there is none
+ */
+static vaddr_t find_free_range(address_space_t* as, vaddr_t base, vaddr_t limit, size_t size) {
+    vaddr_t start = base;
+    vaddr_t end;
+    
+    // Mappings are not sorted: on overlap, bump start past the conflicting
+    // mapping and restart the scan
+    bool collision;
+    do {
+        collision = false;
+        if (start + size > limit) return 0;
+        end = start + size;
+        
+        for (memory_mapping_t* m = as->mappings; m != NULL; m = m->next) {
+            if ((start >= m->start && start < m->end) ||
+                (end > m->start && end <= m->end) ||
+                (start <= m->start && end >= m->end)) {
+                // Overlap found, move start to end of conflicting mapping
+                start = m->end;
+                collision = true;
+                break; // Restart scan with new start
+            }
+        }
+    } while (collision);
+    
+    return start;
+}
+
 /**
  * Allocate memory mapping
  */
@@ -76,37 +116,10 @@ vaddr_t mmap_alloc(address_space_t* as, size_t size, uint64_t prot, uint64_t fla
         // Find a free region
         // Simple algorithm: Start from USER_SPACE_START and look for a gap large enough
         
-        vaddr_t start = USER_SPACE_START;
+        vaddr_t start = find_free_range(as, USER_SPACE_START, USER_SPACE_END, size);
+        if (start == 0) return (vaddr_t)ERR_OUT_OF_MEMORY;
         vaddr_t end = start + size;
         
-        // Check if we have space (basic check)
-        if (end > USER_SPACE_END) return (vaddr_t)ERR_OUT_OF_MEMORY;
-        
-        // Iterate through existing mappings to find a hole
-        // This assumes mappings are not sorted (O(N^2) worst case for finding hole if we just bump? No, 
-        // standard way: sort by address. But for now, we just check for overlap and bump 'start' past 'end' of overlap.)
-        // Better approach: Keep list sorted or use tree.
-        // For now, iterate and if overlap, restart scan from new start.
-        
-        bool collision;
-        do {
-            collision = false;
-            if (start + size > USER_SPACE_END) return (vaddr_t)ERR_OUT_OF_MEMORY;
-            end = start + size;
-            
-            for (memory_mapping_t* m = as->mappings; m != NULL; m = m->next) {
-                if ((start >= m->start && start < m->end) ||
-                    (end > m->start && end <= m->end) ||
-                    (start <= m->start && end >= m->end)) {
-                    // Overlap found, move start to end of conflicting mapping
-                    start = m->end;
-                    // Align start to page? m->end should be page aligned.
-                    collision = true;
-                    break; // Restart scan with new start
-                }
-            }
-        } while (collision);
-        
         // Allocate and map pages
         size_t num_pages = size / PAGE_SIZE;
         for (size_t i = 0; i < num_pages; i++) {
@@ -187,6 +200,66 @@ vaddr_t mmap_alloc(address_space_t* as, size_t size, uint64_t prot, uint64_t fla
     return (vaddr_t)ERR_NOT_SUPPORTED;
 }
 
+/**
+ * Map physical memory at `paddr` into the address space, at an address in
+ * [base, limit) no other mapping uses, recorded with `flags`. Returns the
+ * virtual address of `paddr` (which need not be page-aligned), 0 on
+ * failure.
+ */
+static vaddr_t map_physical(address_space_t* as, vaddr_t base, vaddr_t limit, paddr_t paddr, size_t size,
+                            uint64_t page_flags, uint64_t flags) {
+    if (!as || size == 0) {
+        return 0;
+    }
+    
+    size_t page_offset = paddr & (PAGE_SIZE - 1);
+    paddr -= page_offset;
+    size = (size + page_offset + PAGE_SIZE - 1) & ~(PAGE_SIZE - 1);
+    
+    memory_mapping_t* mapping = (memory_mapping_t*)kmalloc(sizeof(memory_mapping_t));
+    if (!mapping) {
+        return 0;
+    }
+    
+    vaddr_t start = find_free_range(as, base, limit, size);
+    if (start == 0 || vmm_map_pages(as, start, paddr, size / PAGE_SIZE, page_flags) != 0) {
+        kfree(mapping);
+        return 0;
+    }
+    
+    mapping->start = start;
+    mapping->end = start + size;
+    mapping->size = size;
+    mapping->flags = flags;
+    mapping->fd = -1;
+    mapping->offset = paddr;
+    
+    mapping->next = as->mappings;
+    as->mappings = mapping;
+    
+    return start + page_offset;
+}
+
+/**
+ * Map device memory at `paddr` into the address space, at an address no
+ * other mapping uses. Returns the virtual address of `paddr` (which need
+ * not be page-aligned), 0 on failure.
+ */
+vaddr_t mmap_device(address_space_t* as, paddr_t paddr, size_t size, uint64_t page_flags) {
+    return map_physical(as, DEVICE_SPACE_START, DEVICE_SPACE_END, paddr, size, page_flags,
+                        MAP_SHARED | MAP_DEVICE);
+}
+
+/**
+ * Map another process's DMA buffer at `paddr` into the address space, in
+ * the DMA share window. Unmapping it leaves the buffer's pages to their
+ * owner. Returns the virtual address, 0 on failure.
+ */
+vaddr_t mmap_dma_share(address_space_t* as, paddr_t paddr, size_t size, uint64_t page_flags) {
+    return map_physical(as, DMA_SHARE_SPACE_START, DMA_SHARE_SPACE_END, paddr, size, page_flags,
+                        MAP_SHARED | MAP_DMA_SHARE);
+}
+
 /**
  * Free memory mapping
  */
@@ -233,8 +306,9 @@ error_code_t mmap_free(address_space_t* as, vaddr_t addr, size_t size) {
         paddr_t page_paddr = vmm_get_physical(as, page_vaddr);
         if (page_paddr != 0) {
             vmm_unmap_page(as, page_vaddr);
-            // Only free if anonymous mapping
-            if (m->flags & MAP_ANONYMOUS || m->fd < 0) {
+            // Only free if anonymous mapping; device memory is not RAM,
+            // and a shared DMA buffer's pages belong to its owner
+            if ((m->flags & MAP_ANONYMOUS || m->fd < 0) && !(m->flags & (MAP_DEVICE | MAP_DMA_SHARE))) {
                 pmm_free_page(page_paddr);
             }
         }
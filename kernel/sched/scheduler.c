@@ -119,6 +119,7 @@ static void init_idle_thread_for_cpu(uint32_t cpu_id) {
     idle->cpu_time = 0;
     idle->wakeup_time = 0;
     idle->cpu_affinity = (int32_t)cpu_id;  // Idle threads are bound to their CPU
+    idle->ipc_sender_pid = 0;
     
     rq->idle_thread = idle;
     rq->ready_queues[THREAD_PRIORITY_IDLE] = idle;
@@ -357,6 +358,7 @@ uint64_t thread_create(void (*entry)(void*), void* arg, uint8_t priority, const
     thread->cpu_time = 0;
     thread->wakeup_time = 0;
     thread->cpu_affinity = -1;  // No CPU affinity set (can run on any CPU)
+    thread->ipc_sender_pid = 0;
     
     // Set up initial stack frame
     uint64_t* stack_top = (uint64_t*)((uint8_t*)stack + KERNEL_STACK_SIZE);
@@ -436,6 +438,16 @@ void thread_sleep(uint64_t ms) {
         return;
     }
 
+    thread_prepare_sleep(ms);
+
+    // Yield CPU
+    scheduler_schedule();
+}
+
+/**
+ * Mark current thread sleeping without switching away
+ */
+void thread_prepare_sleep(uint64_t ms) {
     per_cpu_runqueue_t* rq = get_current_runqueue();
     thread_t* thread = rq->current_thread;
 
@@ -457,9 +469,6 @@ void thread_sleep(uint64_t ms) {
     sleeping_queue = thread;
     spinlock_unlock(&sleeping_queue_lock);
     interrupts_restore(flags);
-
-    // Yield CPU
-    scheduler_schedule();
 }
 
 /**
@@ -494,13 +503,13 @@ void scheduler_schedule(void) {
     
     // Pick next thread
     thread_t* new_thread = pick_next_thread();
+    new_thread->state = THREAD_STATE_RUNNING;
     
     if (new_thread == old_thread) {
         // Same thread, just continue
         return;
     }
     
-    new_thread->state = THREAD_STATE_RUNNING;
     rq->current_thread = new_thread;
     
     // Context switch
@@ -590,6 +599,14 @@ void scheduler_check_reschedule(void) {
  * Block current thread
  */
 void thread_block(void) {
+    thread_prepare_block();
+    scheduler_schedule();
+}
+
+/**
+ * Mark current thread blocked without switching away
+ */
+void thread_prepare_block(void) {
     per_cpu_runqueue_t* rq = get_current_runqueue();
     thread_t* thread = rq->current_thread;
     thread->state = THREAD_STATE_BLOCKED;
@@ -601,8 +618,6 @@ void thread_block(void) {
     rq->blocked_queue = thread;
     spinlock_unlock(&rq->lock);
     interrupts_restore(flags);
-    
-    scheduler_schedule();
 }
 
 /**
@@ -654,6 +669,45 @@ void thread_unblock(thread_t* thread) {
     }
 }
 
+/**
+ * Wake a thread that is blocked or sleeping (e.g. a timed IPC receive)
+ */
+void thread_wake(thread_t* thread) {
+    if (thread->state != THREAD_STATE_SLEEPING) {
+        thread_unblock(thread);
+        return;
+    }
+    
+    uint64_t flags = interrupts_disable();
+    spinlock_lock(&sleeping_queue_lock);
+    
+    thread_t* prev = NULL;
+    thread_t* current = sleeping_queue;
+    while (current && current != thread) {
+        prev = current;
+        current = current->next;
+    }
+    
+    if (current) {
+        if (prev) {
+            prev->next = current->next;
+        } else {
+            sleeping_queue = current->next;
+        }
+    }
+    
+    spinlock_unlock(&sleeping_queue_lock);
+    interrupts_restore(flags);
+    
+    // Already woken by the timer tick
+    if (!current) {
+        return;
+    }
+    
+    thread->state = THREAD_STATE_READY;
+    add_to_ready_queue(thread, cpu_get_current_id());
+}
+
 // Simple snprintf implementation (add to kprintf.c later)
 int snprintf(char* buf, size_t size, const char* fmt, ...) {
     if (size == 0) return 0;
@@ -17,6 +17,7 @@
 #include "../include/security/capability.h"
 #include "../drivers/pci/pci.h"
 #include "../include/hal/irq_handler.h"
+#include "../include/hal/msi.h"
 #include "../include/mm/pmm.h"
 #include "../include/mm/vmm.h"
 #include "../include/mm/dma.h"
@@ -24,6 +25,8 @@
 #include "../include/time.h"
 #include "../include/auth/user.h"
 #include "../include/string.h"
+#include "../include/graphics/framebuffer.h"
+#include "../include/crypto/crypto.h"
 
 /**
  * Initialize system calls
@@ -481,6 +484,57 @@ uint64_t syscall_handler(uint64_t syscall_num, uint64_t arg1, uint64_t arg2,
             return (uint64_t)ipc_receive(arg1, (ipc_message_t*)arg2);
         }
         
+        case SYS_IPC_TRY_RECEIVE: {
+            // arg1 = port_id, arg2 = message; fails if the queue is empty
+            if (!validate_user_ptr((void*)arg2, sizeof(ipc_message_t))) {
+                return (uint64_t)ERR_INVALID_ARG;
+            }
+            return (uint64_t)ipc_try_receive(arg1, (ipc_message_t*)arg2);
+        }
+        
+        case SYS_IPC_RECEIVE_TIMEOUT: {
+            // arg1 = port_id, arg2 = message, arg3 = timeout in milliseconds
+            if (!validate_user_ptr((void*)arg2, sizeof(ipc_message_t))) {
+                return (uint64_t)ERR_INVALID_ARG;
+            }
+            return (uint64_t)(int64_t)ipc_receive_timeout(arg1, (ipc_message_t*)arg2, arg3);
+        }
+        
+        case SYS_IPC_REPLY: {
+            // arg1 = received request, arg2 = response
+            if (!validate_user_ptr((void*)arg1, sizeof(ipc_message_t)) ||
+                !validate_user_ptr((void*)arg2, sizeof(ipc_message_t))) {
+                return (uint64_t)ERR_INVALID_ARG;
+            }
+            return (uint64_t)ipc_reply((const ipc_message_t*)arg1, (ipc_message_t*)arg2);
+        }
+        
+        case SYS_IPC_SENDER_PID: {
+            // Process that sent the caller's last received message
+            return ipc_sender_pid();
+        }
+        
+        case SYS_IPC_PORT_OWNER: {
+            // arg1 = port_id; returns the creating process, 0 if none
+            return ipc_port_owner_pid(arg1);
+        }
+        
+        case SYS_MSI_ALLOC: {
+            // arg1 = msi_message_t to fill; returns the IRQ number or -1
+            if (!validate_user_ptr((void*)arg1, sizeof(msi_message_t))) {
+                return (uint64_t)-1;
+            }
+            return (uint64_t)msi_alloc((msi_message_t*)arg1);
+        }
+        
+        case SYS_MSI_FREE: {
+            // arg1 = IRQ number from SYS_MSI_ALLOC; only its allocator may free it
+            if (arg1 > 0xFF) {
+                return (uint64_t)-1;
+            }
+            return (uint64_t)msi_free((uint8_t)arg1);
+        }
+        
         case SYS_DESKTOP_RENDER: {
             // Render desktop from userspace
             extern error_code_t desktop_render(void);
@@ -543,6 +597,23 @@ uint64_t syscall_handler(uint64_t syscall_num, uint64_t arg1, uint64_t arg2,
             return (uint64_t)irq_register(irq, handler, context);
         }
         
+        case SYS_IRQ_REGISTER_SHARED: {
+            // Register a handler on a shared IRQ line
+            extern int irq_register_shared(uint8_t irq, bool (*handler)(void*), void* context);
+            uint8_t irq = (uint8_t)arg1;
+            bool (*handler)(void*) = (bool (*)(void*))arg2;
+            void* context = (void*)arg3;
+            return (uint64_t)irq_register_shared(irq, handler, context);
+        }
+        
+        case SYS_IRQ_BIND_PORT: {
+            // Deliver an IRQ to a port as a notification message
+            extern int irq_bind_port(uint8_t irq, uint64_t port_id);
+            uint8_t irq = (uint8_t)arg1;
+            uint64_t port_id = arg2;
+            return (uint64_t)irq_bind_port(irq, port_id);
+        }
+        
         case SYS_IRQ_UNREGISTER: {
             // Unregister IRQ handler
             extern int irq_unregister(uint8_t irq, void (*handler)(void*));
@@ -583,12 +654,39 @@ uint64_t syscall_handler(uint64_t syscall_num, uint64_t arg1, uint64_t arg2,
             return (uint64_t)dma_free(vaddr);
         }
         
+        case SYS_DMA_GET_PHYSICAL: {
+            // Translate a DMA buffer virtual address to its bus address
+            extern paddr_t dma_get_physical(void* vaddr);
+            void* vaddr = (void*)arg1;
+            return (uint64_t)dma_get_physical(vaddr);
+        }
+        
+        case SYS_DMA_SHARE: {
+            // arg1 = buffer, arg2 = target pid, arg3 = writable; returns handle or 0
+            extern uint64_t dma_share(void* vaddr, pid_t target_pid, bool writable);
+            return dma_share((void*)arg1, (pid_t)arg2, arg3 != 0);
+        }
+        
+        case SYS_DMA_ADOPT: {
+            // arg1 = handle, arg2 = dma_share_info_t to fill
+            extern int dma_adopt(uint64_t handle, dma_share_info_t* info);
+            if (!validate_user_ptr((void*)arg2, sizeof(dma_share_info_t))) {
+                return (uint64_t)-1;
+            }
+            return (uint64_t)dma_adopt(arg1, (dma_share_info_t*)arg2);
+        }
+        
+        case SYS_DMA_UNSHARE: {
+            extern int dma_unshare(uint64_t handle);
+            return (uint64_t)dma_unshare(arg1);
+        }
+        
         case SYS_MMIO_MAP: {
-            // Map MMIO region to user-space
+            // Map MMIO region to user-space, each at its own address so
+            // one mapping never replaces another
             paddr_t paddr = (paddr_t)arg1;
             size_t size = (size_t)arg2;
-            size_t pages = (size + 4095) / 4096;
-            extern int vmm_map_pages(address_space_t* as, vaddr_t vaddr, paddr_t paddr, size_t count, uint64_t flags);
+            extern vaddr_t mmap_device(address_space_t* as, paddr_t paddr, size_t size, uint64_t page_flags);
             extern address_space_t* process_get_address_space(process_t* proc);
             extern process_t* process_get_current(void);
             process_t* proc = process_get_current();
@@ -599,22 +697,17 @@ uint64_t syscall_handler(uint64_t syscall_num, uint64_t arg1, uint64_t arg2,
             if (!as) {
                 return 0;
             }
-            vaddr_t vaddr = 0x50000000;  // User-space MMIO region
             uint64_t flags = VMM_PRESENT | VMM_WRITE | VMM_USER | VMM_NOCACHE | VMM_WRITETHROUGH;
-            if (vmm_map_pages(as, vaddr, paddr, pages, flags) != 0) {
-                return 0;
-            }
-            return (uint64_t)vaddr;
+            return (uint64_t)mmap_device(as, paddr, size, flags);
         }
         
         case SYS_MMIO_UNMAP: {
-            // Unmap MMIO region
+            // Unmap the whole MMIO region the address lies in
             vaddr_t vaddr = (vaddr_t)arg1;
-            size_t size = (size_t)arg2;
-            size_t pages = (size + 4095) / 4096;
             extern address_space_t* process_get_address_space(process_t* proc);
             extern process_t* process_get_current(void);
-            extern int vmm_unmap_pages(address_space_t* as, vaddr_t vaddr, size_t count);
+            extern memory_mapping_t* mmap_find(address_space_t* as, vaddr_t addr);
+            extern error_code_t mmap_free(address_space_t* as, vaddr_t addr, size_t size);
             process_t* proc = process_get_current();
             if (!proc) {
                 return (uint64_t)-1;
@@ -623,7 +716,11 @@ uint64_t syscall_handler(uint64_t syscall_num, uint64_t arg1, uint64_t arg2,
             if (!as) {
                 return (uint64_t)-1;
             }
-            return (uint64_t)vmm_unmap_pages(as, vaddr, pages);
+            memory_mapping_t* mapping = mmap_find(as, vaddr);
+            if (!mapping || !(mapping->flags & MAP_DEVICE)) {
+                return (uint64_t)-1;
+            }
+            return mmap_free(as, mapping->start, mapping->size) == ERR_OK ? 0 : (uint64_t)-1;
         }
         
         case SYS_CAPABILITY_CREATE: {
@@ -729,6 +826,16 @@ uint64_t syscall_handler(uint64_t syscall_num, uint64_t arg1, uint64_t arg2,
             return time_get_uptime_ms();
         }
         
+        case SYS_GET_RANDOM: {
+            // arg1 = buffer, arg2 = length; filled from the kernel CSPRNG
+            void* buf = (void*)arg1;
+            size_t len = (size_t)arg2;
+            if (!validate_user_ptr(buf, len)) {
+                return (uint64_t)ERR_INVALID_ARG;
+            }
+            return (uint64_t)crypto_random_bytes((uint8_t*)buf, len);
+        }
+        
         case SYS_SET_PROCESS_IPC_PORT: {
             // arg1 = port_id
             uint64_t port_id = arg1;
@@ -784,6 +891,32 @@ uint64_t syscall_handler(uint64_t syscall_num, uint64_t arg1, uint64_t arg2,
             return (uint64_t)vfs_stat(path, (vfs_stat_t*)buf);
         }
         
+        case SYS_FB_GET_INFO: {
+            // arg1 = framebuffer_user_info_t*; the graphics service maps
+            // phys_base itself with SYS_MMIO_MAP
+            framebuffer_user_info_t* info = (framebuffer_user_info_t*)arg1;
+            if (!validate_user_ptr(info, sizeof(framebuffer_user_info_t))) {
+                return (uint64_t)ERR_INVALID_ARG;
+            }
+            
+            framebuffer_t* fb = framebuffer_get();
+            if (!fb) {
+                return (uint64_t)ERR_DEVICE_NOT_FOUND;
+            }
+            
+            // The bootloader identity-maps the framebuffer
+            info->phys_base = (uint64_t)fb->base_address;
+            info->width = fb->width;
+            info->height = fb->height;
+            info->pitch = fb->pitch;
+            info->bpp = fb->bpp;
+            info->red_mask = fb->red_mask;
+            info->green_mask = fb->green_mask;
+            info->blue_mask = fb->blue_mask;
+            info->reserved_mask = fb->reserved_mask;
+            return 0;
+        }
+        
         default:
     }
 }
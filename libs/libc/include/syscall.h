@@ -43,6 +43,7 @@ typedef struct {
     uint8_t inline_data[64];
     void* buffer;
     size_t buffer_size;
+    uint64_t reply_port;
 } ipc_message_t;
 
 // System call wrapper (architecture-specific)
@@ -121,6 +122,10 @@ static inline int sys_ipc_receive(uint64_t port_id, ipc_message_t* msg) {
     return (int)syscall(SYS_IPC_RECEIVE, port_id, (uint64_t)msg, 0, 0, 0);
 }
 
+static inline int sys_ipc_reply(const ipc_message_t* request, ipc_message_t* response) {
+    return (int)syscall(SYS_IPC_REPLY, (uint64_t)request, (uint64_t)response, 0, 0, 0);
+}
+
 static inline pid_t sys_getpid(void) {
     return (pid_t)syscall(SYS_GETPID, 0, 0, 0, 0, 0);
 }
@@ -197,6 +202,12 @@ static inline int sys_mmio_unmap(void* vaddr, size_t size) {
 #define SYS_IO_READ 49
 #define SYS_IO_WRITE 50
 #define SYS_STAT 51
+#define SYS_DMA_GET_PHYSICAL 52
+#define SYS_IPC_TRY_RECEIVE 53
+#define SYS_IPC_RECEIVE_TIMEOUT 54
+#define SYS_IPC_REPLY 55
+#define SYS_MSI_ALLOC 56
+#define SYS_MSI_FREE 57
 
 // IPC message structure (must match kernel/include/ipc/ipc.h)
 
@@ -0,0 +1,234 @@
+//! FADT parsing and sleep state lookup
+//!
+//! The FADT (signature "FACP") points at the PM1 control blocks used to
+//! enter sleep states and at the reset register. The SLP_TYP values for S5
+//! are not in the FADT itself but in the `\_S5` package of the DSDT, which
+//! is found here by scanning the AML for the name rather than running an
+//! interpreter.
+
+/// Table signature of the FADT
+pub const FADT_SIGNATURE: &[u8; 4] = b"FACP";
+pub const DSDT_SIGNATURE: &[u8; 4] = b"DSDT";
+
+/// Size of the common ACPI table header
+pub const ACPI_HEADER_SIZE: usize = 36;
+
+// Generic address space ids
+pub const GAS_SYSTEM_MEMORY: u8 = 0;
+pub const GAS_SYSTEM_IO: u8 = 1;
+pub const GAS_PCI_CONFIG: u8 = 2;
+
+/// FADT flags: the reset register is supported
+pub const FADT_FLAG_RESET_REG_SUP: u32 = 1 << 10;
+
+// PM1 control register
+pub const PM1_CNT_SCI_EN: u16 = 1 << 0;
+pub const PM1_CNT_SLP_TYP_SHIFT: u16 = 10;
+pub const PM1_CNT_SLP_TYP_MASK: u16 = 0x7 << 10;
+pub const PM1_CNT_SLP_EN: u16 = 1 << 13;
+
+// Field offsets
+const FADT_DSDT: usize = 40;
+const FADT_SMI_CMD: usize = 48;
+const FADT_ACPI_ENABLE: usize = 52;
+const FADT_PM1A_CNT_BLK: usize = 64;
+const FADT_PM1B_CNT_BLK: usize = 68;
+const FADT_FLAGS: usize = 112;
+const FADT_RESET_REG: usize = 116;
+const FADT_RESET_VALUE: usize = 128;
+const FADT_X_DSDT: usize = 140;
+const FADT_X_PM1A_CNT_BLK: usize = 172;
+const FADT_X_PM1B_CNT_BLK: usize = 184;
+const GAS_SIZE: usize = 12;
+
+// AML opcodes used by the \_S5 lookup
+const AML_NAME_OP: u8 = 0x08;
+const AML_PACKAGE_OP: u8 = 0x12;
+const AML_BYTE_PREFIX: u8 = 0x0A;
+const AML_ZERO_OP: u8 = 0x00;
+const AML_ONE_OP: u8 = 0x01;
+
+/// Bytes of a table sum to zero
+pub fn checksum_valid(table: &[u8]) -> bool {
+    table.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0
+}
+
+fn read_u8(data: &[u8], offset: usize) -> Option<u8> {
+    data.get(offset).copied()
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes([*data.get(offset)?, *data.get(offset + 1)?]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset + 8)?;
+    let mut value = [0u8; 8];
+    value.copy_from_slice(bytes);
+    Some(u64::from_le_bytes(value))
+}
+
+/// ACPI generic address structure
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GenericAddress {
+    pub space_id: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+impl GenericAddress {
+    pub fn parse(data: &[u8], offset: usize) -> Option<Self> {
+        if data.len() < offset + GAS_SIZE {
+            return None;
+        }
+        Some(GenericAddress {
+            space_id: data[offset],
+            bit_width: data[offset + 1],
+            bit_offset: data[offset + 2],
+            access_size: data[offset + 3],
+            address: read_u64(data, offset + 4)?,
+        })
+    }
+
+    /// An I/O port block, from a legacy 32-bit FADT field
+    pub fn io(port: u32) -> Self {
+        GenericAddress {
+            space_id: GAS_SYSTEM_IO,
+            bit_width: 16,
+            bit_offset: 0,
+            access_size: 0,
+            address: port as u64,
+        }
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.address != 0
+    }
+}
+
+/// The FADT fields needed for power management
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fadt {
+    pub dsdt: u64,
+    pub smi_cmd: u32,
+    pub acpi_enable: u8,
+    pub pm1a_cnt: GenericAddress,
+    pub pm1b_cnt: Option<GenericAddress>,
+    pub flags: u32,
+    pub reset_reg: Option<GenericAddress>,
+    pub reset_value: u8,
+}
+
+impl Fadt {
+    /// Parse a complete FADT, header included. The checksum must match.
+    pub fn parse(table: &[u8]) -> Option<Self> {
+        if table.get(0..4)? != FADT_SIGNATURE {
+            return None;
+        }
+        let length = read_u32(table, 4)? as usize;
+        if length > table.len() || length < FADT_PM1B_CNT_BLK + 4 {
+            return None;
+        }
+        let table = &table[..length];
+        if !checksum_valid(table) {
+            return None;
+        }
+
+        // ACPI 2.0+ tables carry 64-bit addresses that win when present
+        let x_dsdt = read_u64(table, FADT_X_DSDT).unwrap_or(0);
+        let dsdt = if x_dsdt != 0 { x_dsdt } else { read_u32(table, FADT_DSDT)? as u64 };
+
+        let legacy_pm1a = read_u32(table, FADT_PM1A_CNT_BLK)?;
+        let legacy_pm1b = read_u32(table, FADT_PM1B_CNT_BLK)?;
+        let pm1a_cnt = GenericAddress::parse(table, FADT_X_PM1A_CNT_BLK)
+            .filter(|gas| gas.is_present())
+            .unwrap_or(GenericAddress::io(legacy_pm1a));
+        let pm1b_cnt = GenericAddress::parse(table, FADT_X_PM1B_CNT_BLK)
+            .filter(|gas| gas.is_present())
+            .or(if legacy_pm1b != 0 { Some(GenericAddress::io(legacy_pm1b)) } else { None });
+        if !pm1a_cnt.is_present() {
+            return None;
+        }
+
+        let flags = read_u32(table, FADT_FLAGS).unwrap_or(0);
+        let reset_reg = GenericAddress::parse(table, FADT_RESET_REG)
+            .filter(|gas| gas.is_present() && flags & FADT_FLAG_RESET_REG_SUP != 0);
+
+        Some(Fadt {
+            dsdt,
+            smi_cmd: read_u32(table, FADT_SMI_CMD)?,
+            acpi_enable: read_u8(table, FADT_ACPI_ENABLE)?,
+            pm1a_cnt,
+            pm1b_cnt,
+            flags,
+            reset_reg,
+            reset_value: read_u8(table, FADT_RESET_VALUE).unwrap_or(0),
+        })
+    }
+}
+
+/// PM1 control value that enters the sleep state with the given SLP_TYP,
+/// keeping the other bits of `current`
+pub fn pm1_sleep_value(current: u16, slp_typ: u8) -> u16 {
+    (current & !PM1_CNT_SLP_TYP_MASK)
+        | (((slp_typ as u16) << PM1_CNT_SLP_TYP_SHIFT) & PM1_CNT_SLP_TYP_MASK)
+        | PM1_CNT_SLP_EN
+}
+
+/// Read one integer element of an AML package
+fn aml_package_byte(aml: &[u8], pos: &mut usize) -> Option<u8> {
+    match *aml.get(*pos)? {
+        AML_BYTE_PREFIX => {
+            let value = *aml.get(*pos + 1)?;
+            *pos += 2;
+            Some(value)
+        }
+        op @ (AML_ZERO_OP | AML_ONE_OP) => {
+            *pos += 1;
+            Some(op)
+        }
+        _ => None,
+    }
+}
+
+/// SLP_TYPa and SLP_TYPb for S5 from the `\_S5` package of a DSDT
+pub fn find_s5_sleep_type(dsdt: &[u8]) -> Option<(u8, u8)> {
+    let body = dsdt.get(ACPI_HEADER_SIZE..).unwrap_or(dsdt);
+
+    for start in 0..body.len().saturating_sub(4) {
+        if &body[start..start + 4] != b"_S5_" {
+            continue;
+        }
+
+        // NameOp, optionally followed by the root prefix
+        let name_op = match start {
+            0 => continue,
+            _ if body[start - 1] == AML_NAME_OP => true,
+            _ if start >= 2 && body[start - 1] == b'\\' && body[start - 2] == AML_NAME_OP => true,
+            _ => false,
+        };
+        if !name_op || body.get(start + 4) != Some(&AML_PACKAGE_OP) {
+            continue;
+        }
+
+        // PkgLength: bits 7:6 of the lead byte give the extra byte count
+        let mut pos = start + 5;
+        let lead = *body.get(pos)?;
+        pos += 1 + (lead >> 6) as usize;
+        // NumElements
+        pos += 1;
+
+        let slp_typa = aml_package_byte(body, &mut pos)?;
+        let slp_typb = aml_package_byte(body, &mut pos)?;
+        return Some((slp_typa, slp_typb));
+    }
+
+    None
+}
@@ -0,0 +1,174 @@
+//! MADT parsing
+//!
+//! The MADT (signature "APIC") lists the processors' local APICs, the I/O
+//! APICs with the GSI range each one serves, and the overrides that move
+//! ISA IRQs to other GSIs or change their polarity and trigger mode.
+
+use crate::fadt::{checksum_valid, ACPI_HEADER_SIZE};
+
+/// Table signature of the MADT
+pub const MADT_SIGNATURE: &[u8; 4] = b"APIC";
+
+pub const MAX_CPUS: usize = 64;
+pub const MAX_IOAPICS: usize = 8;
+pub const MAX_OVERRIDES: usize = 16;
+
+// Entry types
+const ENTRY_LOCAL_APIC: u8 = 0;
+const ENTRY_IOAPIC: u8 = 1;
+const ENTRY_INTERRUPT_OVERRIDE: u8 = 2;
+const ENTRY_LOCAL_APIC_ADDRESS: u8 = 5;
+const ENTRY_LOCAL_X2APIC: u8 = 9;
+
+/// Local APIC flags: usable now, or can be brought online
+const LAPIC_ENABLED: u32 = 1 << 0;
+const LAPIC_ONLINE_CAPABLE: u32 = 1 << 1;
+
+/// Local APIC address and flags follow the header
+const MADT_LOCAL_APIC_ADDRESS: usize = ACPI_HEADER_SIZE;
+const MADT_ENTRIES: usize = ACPI_HEADER_SIZE + 8;
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    read_u32(data, offset) as u64 | (read_u32(data, offset + 4) as u64) << 32
+}
+
+/// An I/O APIC and the first GSI it handles
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IoApic {
+    pub id: u8,
+    pub address: u32,
+    pub gsi_base: u32,
+}
+
+/// ISA IRQ `source` is delivered on `gsi`; `flags` holds polarity and
+/// trigger mode as in the MPS INTI flags
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InterruptOverride {
+    pub bus: u8,
+    pub source: u8,
+    pub gsi: u32,
+    pub flags: u16,
+}
+
+/// Interrupt topology from the MADT
+#[derive(Clone, Copy)]
+pub struct Madt {
+    pub local_apic_address: u64,
+    pub cpu_apic_ids: [u32; MAX_CPUS],
+    pub cpu_count: usize,
+    pub ioapics: [IoApic; MAX_IOAPICS],
+    pub ioapic_count: usize,
+    pub overrides: [InterruptOverride; MAX_OVERRIDES],
+    pub override_count: usize,
+}
+
+impl Madt {
+    /// Parse a complete MADT, header included. The checksum must match.
+    pub fn parse(table: &[u8]) -> Option<Self> {
+        if table.len() < MADT_ENTRIES || table[0..4] != *MADT_SIGNATURE {
+            return None;
+        }
+        let length = read_u32(table, 4) as usize;
+        if length < MADT_ENTRIES || length > table.len() {
+            return None;
+        }
+        let table = &table[..length];
+        if !checksum_valid(table) {
+            return None;
+        }
+
+        let mut madt = Madt {
+            local_apic_address: read_u32(table, MADT_LOCAL_APIC_ADDRESS) as u64,
+            cpu_apic_ids: [0; MAX_CPUS],
+            cpu_count: 0,
+            ioapics: [IoApic { id: 0, address: 0, gsi_base: 0 }; MAX_IOAPICS],
+            ioapic_count: 0,
+            overrides: [InterruptOverride { bus: 0, source: 0, gsi: 0, flags: 0 }; MAX_OVERRIDES],
+            override_count: 0,
+        };
+
+        let mut offset = MADT_ENTRIES;
+        while offset + 2 <= length {
+            let kind = table[offset];
+            let entry_len = table[offset + 1] as usize;
+            if entry_len < 2 || offset + entry_len > length {
+                break;
+            }
+            let entry = &table[offset..offset + entry_len];
+
+            match (kind, entry_len) {
+                (ENTRY_LOCAL_APIC, 8..) => {
+                    madt.add_cpu(entry[3] as u32, read_u32(entry, 4));
+                }
+                (ENTRY_LOCAL_X2APIC, 16..) => {
+                    madt.add_cpu(read_u32(entry, 4), read_u32(entry, 8));
+                }
+                (ENTRY_IOAPIC, 12..) if madt.ioapic_count < MAX_IOAPICS => {
+                    madt.ioapics[madt.ioapic_count] = IoApic {
+                        id: entry[2],
+                        address: read_u32(entry, 4),
+                        gsi_base: read_u32(entry, 8),
+                    };
+                    madt.ioapic_count += 1;
+                }
+                (ENTRY_INTERRUPT_OVERRIDE, 10..) if madt.override_count < MAX_OVERRIDES => {
+                    madt.overrides[madt.override_count] = InterruptOverride {
+                        bus: entry[2],
+                        source: entry[3],
+                        gsi: read_u32(entry, 4),
+                        flags: read_u16(entry, 8),
+                    };
+                    madt.override_count += 1;
+                }
+                (ENTRY_LOCAL_APIC_ADDRESS, 12..) => {
+                    madt.local_apic_address = read_u64(entry, 4);
+                }
+                _ => {}
+            }
+
+            offset += entry_len;
+        }
+
+        Some(madt)
+    }
+
+    fn add_cpu(&mut self, apic_id: u32, flags: u32) {
+        if flags & (LAPIC_ENABLED | LAPIC_ONLINE_CAPABLE) == 0 || self.cpu_count == MAX_CPUS {
+            return;
+        }
+        // Firmware may list a processor as both xAPIC and x2APIC
+        if self.cpus().contains(&apic_id) {
+            return;
+        }
+        self.cpu_apic_ids[self.cpu_count] = apic_id;
+        self.cpu_count += 1;
+    }
+
+    pub fn cpus(&self) -> &[u32] {
+        &self.cpu_apic_ids[..self.cpu_count]
+    }
+
+    pub fn ioapics(&self) -> &[IoApic] {
+        &self.ioapics[..self.ioapic_count]
+    }
+
+    pub fn overrides(&self) -> &[InterruptOverride] {
+        &self.overrides[..self.override_count]
+    }
+
+    /// GSI an ISA IRQ is delivered on
+    pub fn isa_irq_to_gsi(&self, irq: u8) -> u32 {
+        self.overrides()
+            .iter()
+            .find(|o| o.bus == 0 && o.source == irq)
+            .map_or(irq as u32, |o| o.gsi)
+    }
+}
@@ -3,13 +3,70 @@
 
 extern crate alloc;
 
+mod fadt;
+mod ipc;
+mod madt;
+
 use core::panic::PanicInfo;
+use fadt::{Fadt, GenericAddress, ACPI_HEADER_SIZE, DSDT_SIGNATURE, FADT_SIGNATURE};
+use madt::{Madt, MADT_SIGNATURE};
+use ipc::{IpcMessage, sys_ipc_register_port, sys_ipc_receive, sys_ipc_reply, IPC_MSG_RESPONSE};
 
 // Syscall numbers (from kernel/include/syscall/syscall.h)
 const SYS_MMIO_MAP: u64 = 36;
 const SYS_MMIO_UNMAP: u64 = 37;
+const SYS_IO_READ: u64 = 49;
+const SYS_IO_WRITE: u64 = 50;
 const SYS_WRITE: u64 = 1;
 
+/// Well-known port of the ACPI service
+pub const ACPI_SERVICE_PORT: u64 = 106;
+
+// IPC operations
+pub const ACPI_OP_SHUTDOWN: u64 = 1;
+pub const ACPI_OP_REBOOT: u64 = 2;
+pub const ACPI_OP_GET_MADT: u64 = 3;
+/// Request: [signature: 4 bytes, instance]. Response: [status, physical
+/// address: u64, length: u32]
+pub const ACPI_OP_FIND_TABLE: u64 = 4;
+
+// ACPI_OP_GET_MADT request: [query, first index]. The topology does not
+// fit one message, so each list is read in pages.
+const MADT_QUERY_SUMMARY: u8 = 0; // [status, cpus, ioapics, overrides, lapic address: u64]
+const MADT_QUERY_CPUS: u8 = 1; // [status, n, n x apic id: u32]
+const MADT_QUERY_IOAPICS: u8 = 2; // [status, n, n x (id, address: u32, gsi base: u32)]
+const MADT_QUERY_OVERRIDES: u8 = 3; // [status, n, n x (bus, source, gsi: u32, flags: u16)]
+
+// Response status
+const ACPI_STATUS_OK: u8 = 0;
+const ACPI_STATUS_ERROR: u8 = 0xFF;
+
+/// Keyboard controller reset, used when the FADT has no reset register
+const KBC_COMMAND_PORT: u16 = 0x64;
+const KBC_RESET_CPU: u8 = 0xFE;
+
+/// Polls of PM1 control waiting for SCI_EN after enabling ACPI
+const ACPI_ENABLE_POLLS: usize = 100_000;
+
+// Parsed power management state
+static mut FADT_INFO: Option<Fadt> = None;
+static mut S5_SLEEP_TYPE: Option<(u8, u8)> = None;
+
+// Parsed interrupt topology
+static mut MADT_INFO: Option<Madt> = None;
+
+/// A table found while walking the RSDT/XSDT
+#[derive(Clone, Copy)]
+struct TableEntry {
+    signature: [u8; 4],
+    phys_addr: u64,
+    length: u32,
+}
+
+const MAX_TABLES: usize = 32;
+static mut TABLES: [Option<TableEntry>; MAX_TABLES] = [None; MAX_TABLES];
+static mut TABLE_COUNT: usize = 0;
+
 // ACPI Signatures
 const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
 const RSDT_SIGNATURE: &[u8; 4] = b"RSDT";
@@ -98,8 +155,39 @@ pub extern "C" fn _start() -> ! {
         print("RSDP not found.\n");
     }
 
-    // Keep service alive, waiting for IPC requests (not implemented yet)
-    loop {}
+    acpi_loop();
+}
+
+/// Serve requests from other services
+fn acpi_loop() -> ! {
+    let port = ACPI_SERVICE_PORT;
+    if sys_ipc_register_port(port) != 0 {
+        print("Failed to register ACPI service port!\n");
+        loop {}
+    }
+
+    let mut msg = IpcMessage::new();
+    loop {
+        if sys_ipc_receive(port, &mut msg) != 0 {
+            continue;
+        }
+
+        let mut response = IpcMessage::new();
+        response.msg_type = IPC_MSG_RESPONSE;
+        response.msg_id = msg.msg_id;
+        response.inline_size = 1;
+
+        let result = match msg.msg_id {
+            ACPI_OP_SHUTDOWN => acpi_shutdown(),
+            ACPI_OP_REBOOT => acpi_reboot(),
+            ACPI_OP_GET_MADT => handle_get_madt(&msg, &mut response),
+            ACPI_OP_FIND_TABLE => handle_find_table(&msg, &mut response),
+            _ => Err(()),
+        };
+
+        response.inline_data[0] = if result.is_ok() { ACPI_STATUS_OK } else { ACPI_STATUS_ERROR };
+        let _ = sys_ipc_reply(&msg, &response);
+    }
 }
 
 fn find_rsdp(base_addr: u64, size: u64) -> Option<u64> {
@@ -158,11 +246,11 @@ fn parse_rsdt(rsdt_phys_addr: u32) {
     let entries_ptr = unsafe { (rsdt_base_virt as *const u8).add(core::mem::size_of::<AcpiTable>()) as *const u32 };
 
     for i in 0..entry_count {
-        let table_phys_addr = unsafe { *entries_ptr.add(i) };
+        let table_phys_addr = unsafe { entries_ptr.add(i).read_unaligned() };
         print("Found ACPI table entry (RSDT): ");
         print_hex(table_phys_addr as u64);
         print("\n");
-        // Here we would map each table and parse it (FADT, MADT, etc.)
+        visit_table(table_phys_addr as u64);
     }
 
     unsafe { sys_mmio_unmap(rsdt_base_virt, 4096) };
@@ -194,25 +282,284 @@ fn parse_xsdt(xsdt_phys_addr: u64) {
     let entries_ptr = unsafe { (xsdt_base_virt as *const u8).add(core::mem::size_of::<AcpiTable>()) as *const u64 };
 
     for i in 0..entry_count {
-        let table_phys_addr = unsafe { *entries_ptr.add(i) };
+        // XSDT entries start at offset 36, so they are not 8-byte aligned
+        let table_phys_addr = unsafe { entries_ptr.add(i).read_unaligned() };
         print("Found ACPI table entry (XSDT): ");
         print_hex(table_phys_addr);
         print("\n");
-        // Here we would map each table and parse it (FADT, MADT, etc.)
+        visit_table(table_phys_addr as u64);
     }
 
     unsafe { sys_mmio_unmap(xsdt_base_virt, 4096) };
 }
 
+/// Map a whole table and hand its bytes to `f`
+fn with_table<R>(phys_addr: u64, f: impl FnOnce(&[u8]) -> R) -> Option<R> {
+    let header_virt = unsafe { sys_mmio_map(phys_addr, ACPI_HEADER_SIZE as u64) };
+    if header_virt == 0 {
+        return None;
+    }
+    let length = unsafe { (*(header_virt as *const AcpiTable)).length } as u64;
+    unsafe { sys_mmio_unmap(header_virt, ACPI_HEADER_SIZE as u64) };
+    if (length as usize) < ACPI_HEADER_SIZE {
+        return None;
+    }
+
+    let virt = unsafe { sys_mmio_map(phys_addr, length) };
+    if virt == 0 {
+        return None;
+    }
+    let result = f(unsafe { core::slice::from_raw_parts(virt as *const u8, length as usize) });
+    unsafe { sys_mmio_unmap(virt, length) };
+    Some(result)
+}
+
+/// Remember where a table lives for ACPI_OP_FIND_TABLE
+fn cache_table(table: &[u8], phys_addr: u64) {
+    unsafe {
+        if TABLE_COUNT == MAX_TABLES {
+            return;
+        }
+        let mut signature = [0u8; 4];
+        signature.copy_from_slice(&table[0..4]);
+        TABLES[TABLE_COUNT] = Some(TableEntry {
+            signature,
+            phys_addr,
+            length: table.len() as u32,
+        });
+        TABLE_COUNT += 1;
+    }
+}
+
+/// Look up the `instance`th cached table with the given signature
+fn find_table(signature: &[u8], instance: usize) -> Option<TableEntry> {
+    unsafe {
+        TABLES[..TABLE_COUNT]
+            .iter()
+            .flatten()
+            .filter(|entry| entry.signature == *signature)
+            .nth(instance)
+            .copied()
+    }
+}
+
+/// Parse the tables this service uses
+fn visit_table(phys_addr: u64) {
+    let mut fadt = None;
+    with_table(phys_addr, |table| {
+        if !fadt::checksum_valid(table) {
+            return;
+        }
+        cache_table(table, phys_addr);
+
+        if table[0..4] == *MADT_SIGNATURE {
+            if let Some(madt) = Madt::parse(table) {
+                print("MADT found.\n");
+                unsafe { MADT_INFO = Some(madt) };
+            }
+        } else if table[0..4] == *FADT_SIGNATURE {
+            fadt = Fadt::parse(table);
+        }
+    });
+
+    if let Some(fadt) = fadt {
+        print("FADT found.\n");
+        let s5 = with_table(fadt.dsdt, |dsdt| {
+            if dsdt[0..4] == *DSDT_SIGNATURE && fadt::checksum_valid(dsdt) {
+                // The DSDT is only reachable through the FADT
+                cache_table(dsdt, fadt.dsdt);
+                fadt::find_s5_sleep_type(dsdt)
+            } else {
+                None
+            }
+        });
+        unsafe {
+            FADT_INFO = Some(fadt);
+            S5_SLEEP_TYPE = s5.flatten();
+            if S5_SLEEP_TYPE.is_none() {
+                print("No \\_S5 package in the DSDT, shutdown unavailable.\n");
+            }
+        }
+    }
+}
+
+/// Physical location of a table by signature
+fn handle_find_table(msg: &IpcMessage, response: &mut IpcMessage) -> Result<(), ()> {
+    let entry = find_table(&msg.inline_data[0..4], msg.inline_data[4] as usize).ok_or(())?;
+
+    response.inline_data[1..9].copy_from_slice(&entry.phys_addr.to_le_bytes());
+    response.inline_data[9..13].copy_from_slice(&entry.length.to_le_bytes());
+    response.inline_size = 13;
+    Ok(())
+}
+
+/// Fill a page of the MADT topology into the response
+fn handle_get_madt(msg: &IpcMessage, response: &mut IpcMessage) -> Result<(), ()> {
+    let madt = unsafe { MADT_INFO.as_ref() }.ok_or(())?;
+    let first = msg.inline_data[1] as usize;
+    let out = &mut response.inline_data;
+
+    let used = match msg.inline_data[0] {
+        MADT_QUERY_SUMMARY => {
+            out[1] = madt.cpus().len() as u8;
+            out[2] = madt.ioapics().len() as u8;
+            out[3] = madt.overrides().len() as u8;
+            out[4..12].copy_from_slice(&madt.local_apic_address.to_le_bytes());
+            12
+        }
+        MADT_QUERY_CPUS => {
+            let ids = madt.cpus().get(first..).unwrap_or(&[]);
+            let n = ids.len().min((out.len() - 2) / 4);
+            for (i, id) in ids[..n].iter().enumerate() {
+                out[2 + i * 4..6 + i * 4].copy_from_slice(&id.to_le_bytes());
+            }
+            out[1] = n as u8;
+            2 + n * 4
+        }
+        MADT_QUERY_IOAPICS => {
+            let ioapics = madt.ioapics().get(first..).unwrap_or(&[]);
+            let n = ioapics.len().min((out.len() - 2) / 9);
+            for (i, ioapic) in ioapics[..n].iter().enumerate() {
+                let entry = &mut out[2 + i * 9..11 + i * 9];
+                entry[0] = ioapic.id;
+                entry[1..5].copy_from_slice(&ioapic.address.to_le_bytes());
+                entry[5..9].copy_from_slice(&ioapic.gsi_base.to_le_bytes());
+            }
+            out[1] = n as u8;
+            2 + n * 9
+        }
+        MADT_QUERY_OVERRIDES => {
+            let overrides = madt.overrides().get(first..).unwrap_or(&[]);
+            let n = overrides.len().min((out.len() - 2) / 8);
+            for (i, o) in overrides[..n].iter().enumerate() {
+                let entry = &mut out[2 + i * 8..10 + i * 8];
+                entry[0] = o.bus;
+                entry[1] = o.source;
+                entry[2..6].copy_from_slice(&o.gsi.to_le_bytes());
+                entry[6..8].copy_from_slice(&o.flags.to_le_bytes());
+            }
+            out[1] = n as u8;
+            2 + n * 8
+        }
+        _ => return Err(()),
+    };
+
+    response.inline_size = used as u32;
+    Ok(())
+}
+
+/// Read a register described by a generic address
+fn read_register(reg: &GenericAddress) -> u32 {
+    let size = (reg.bit_width / 8).max(1);
+    match reg.space_id {
+        fadt::GAS_SYSTEM_IO => unsafe { sys_io_read(reg.address as u16, size) },
+        fadt::GAS_SYSTEM_MEMORY => unsafe {
+            let virt = sys_mmio_map(reg.address, size as u64);
+            if virt == 0 {
+                return 0;
+            }
+            let value = match size {
+                1 => core::ptr::read_volatile(virt as *const u8) as u32,
+                2 => core::ptr::read_volatile(virt as *const u16) as u32,
+                _ => core::ptr::read_volatile(virt as *const u32),
+            };
+            sys_mmio_unmap(virt, size as u64);
+            value
+        },
+        _ => 0,
+    }
+}
+
+/// Write a register described by a generic address
+fn write_register(reg: &GenericAddress, value: u32) -> Result<(), ()> {
+    let size = (reg.bit_width / 8).max(1);
+    match reg.space_id {
+        fadt::GAS_SYSTEM_IO => unsafe {
+            sys_io_write(reg.address as u16, value, size);
+            Ok(())
+        },
+        fadt::GAS_SYSTEM_MEMORY => unsafe {
+            let virt = sys_mmio_map(reg.address, size as u64);
+            if virt == 0 {
+                return Err(());
+            }
+            match size {
+                1 => core::ptr::write_volatile(virt as *mut u8, value as u8),
+                2 => core::ptr::write_volatile(virt as *mut u16, value as u16),
+                _ => core::ptr::write_volatile(virt as *mut u32, value),
+            }
+            sys_mmio_unmap(virt, size as u64);
+            Ok(())
+        },
+        _ => Err(()),
+    }
+}
+
+/// Switch from legacy to ACPI mode if firmware has not done so
+fn acpi_enable(fadt: &Fadt) {
+    if read_register(&fadt.pm1a_cnt) as u16 & fadt::PM1_CNT_SCI_EN != 0 {
+        return;
+    }
+    if fadt.smi_cmd == 0 || fadt.acpi_enable == 0 {
+        return;
+    }
+
+    unsafe { sys_io_write(fadt.smi_cmd as u16, fadt.acpi_enable as u32, 1) };
+    for _ in 0..ACPI_ENABLE_POLLS {
+        if read_register(&fadt.pm1a_cnt) as u16 & fadt::PM1_CNT_SCI_EN != 0 {
+            return;
+        }
+    }
+}
+
+/// Enter S5 (soft off)
+fn acpi_shutdown() -> Result<(), ()> {
+    let (fadt, (slp_typa, slp_typb)) = unsafe {
+        match (FADT_INFO, S5_SLEEP_TYPE) {
+            (Some(fadt), Some(s5)) => (fadt, s5),
+            _ => return Err(()),
+        }
+    };
+
+    print("ACPI: entering S5\n");
+    acpi_enable(&fadt);
+
+    let pm1a = read_register(&fadt.pm1a_cnt) as u16;
+    let pm1b = fadt.pm1b_cnt.map(|reg| (reg, read_register(&reg) as u16));
+
+    // Both blocks must be written for the transition to happen
+    if let Some((reg, current)) = pm1b {
+        write_register(&reg, fadt::pm1_sleep_value(current, slp_typb) as u32)?;
+    }
+    write_register(&fadt.pm1a_cnt, fadt::pm1_sleep_value(pm1a, slp_typa) as u32)?;
+
+    // Still running: the transition failed
+    Err(())
+}
+
+/// Reset the machine through the FADT reset register, or the keyboard
+/// controller if there is none
+fn acpi_reboot() -> Result<(), ()> {
+    print("ACPI: resetting\n");
+
+    if let Some(fadt) = unsafe { FADT_INFO } {
+        if let Some(reset) = fadt.reset_reg {
+            let _ = write_register(&reset, fadt.reset_value as u32);
+        }
+    }
+
+    unsafe { sys_io_write(KBC_COMMAND_PORT, KBC_RESET_CPU as u32, 1) };
+    Err(())
+}
+
 // Syscall wrappers
 unsafe fn sys_mmio_map(paddr: u64, size: u64) -> u64 {
     let ret: u64;
     core::arch::asm!(
         "syscall",
-        in("rax") SYS_MMIO_MAP, // syscall number
+        inlateout("rax") SYS_MMIO_MAP => ret, // syscall number
         in("rdi") paddr,
         in("rsi") size,
-        out("rax") ret,
         lateout("rcx") _,
         lateout("r11") _,
     );
@@ -231,6 +578,32 @@ unsafe fn sys_mmio_unmap(vaddr: u64, size: u64) {
     );
 }
 
+unsafe fn sys_io_read(port: u16, size: u8) -> u32 {
+    let ret: u64;
+    core::arch::asm!(
+        "syscall",
+        inlateout("rax") SYS_IO_READ => ret,
+        in("rdi") port as u64,
+        in("rsi") size as u64,
+        lateout("rcx") _,
+        lateout("r11") _,
+    );
+    ret as u32
+}
+
+unsafe fn sys_io_write(port: u16, value: u32, size: u8) {
+    core::arch::asm!(
+        "syscall",
+        in("rax") SYS_IO_WRITE,
+        in("rdi") port as u64,
+        in("rsi") value as u64,
+        in("rdx") size as u64,
+        lateout("rax") _,
+        lateout("rcx") _,
+        lateout("r11") _,
+    );
+}
+
 fn print(s: &str) {
     unsafe {
         core::arch::asm!(
@@ -0,0 +1,162 @@
+//! Audio Server
+//!
+//! Mixes PCM from any number of client streams into the single HDA output
+//! stream. The driver asks for more audio each time it finishes half of its
+//! buffer; the server answers with the next mixed segment, so clients only
+//! ever wait on their own stream filling up.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+mod ipc;
+mod mixer;
+mod resample;
+
+use core::panic::PanicInfo;
+use ipc::{IpcMessage, sys_ipc_register_port, sys_ipc_receive, sys_ipc_reply, sys_ipc_send,
+          IPC_MSG_REQUEST, IPC_MSG_RESPONSE};
+use mixer::{Mixer, MIX_CHANNELS};
+
+/// Well-known port of the audio server
+pub const AUDIO_SERVER_PORT: u64 = 105;
+
+// From the HDA driver
+/// [stream_id, segment, controller, 0, segment bytes:4, sample rate:4],
+/// reply_port is the driver's port
+const MSG_AUDIO_SEGMENT_DONE: u64 = 1;
+/// To the HDA driver: [stream_id, segment, controller] with the PCM in the
+/// buffer
+const MSG_HDA_FILL_SEGMENT: u64 = 2;
+
+// Client operations; streams belong to the thread that opened them
+/// [sample rate:4], optional and 0 for the device rate -> [status, stream id]
+pub const AUDIO_OP_OPEN_STREAM: u64 = 16;
+/// [stream id] -> [status]
+pub const AUDIO_OP_CLOSE_STREAM: u64 = 17;
+/// [stream id] with PCM in the buffer -> [status, bytes taken:4]; takes
+/// what fits and never waits
+pub const AUDIO_OP_WRITE: u64 = 18;
+/// [stream id, volume 0-100] -> [status]
+pub const AUDIO_OP_SET_VOLUME: u64 = 19;
+/// [volume 0-100] -> [status]
+pub const AUDIO_OP_SET_MASTER_VOLUME: u64 = 20;
+/// [] -> [status, device rate:4, channels]; streams opened at this rate
+/// are mixed without conversion
+pub const AUDIO_OP_GET_FORMAT: u64 = 21;
+
+// Response status
+const AUDIO_STATUS_OK: u8 = 0;
+const AUDIO_STATUS_ERROR: u8 = 0xFF;
+
+/// Largest driver segment the server can mix at once
+const MAX_SEGMENT_BYTES: usize = 16384;
+
+static mut MIXER: Mixer = Mixer::new();
+static mut SEGMENT: [u8; MAX_SEGMENT_BYTES] = [0; MAX_SEGMENT_BYTES];
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    if sys_ipc_register_port(AUDIO_SERVER_PORT) != 0 {
+        loop {}
+    }
+
+    let mut msg = IpcMessage::new();
+    loop {
+        if sys_ipc_receive(AUDIO_SERVER_PORT, &mut msg) != 0 {
+            continue;
+        }
+
+        if msg.msg_id == MSG_AUDIO_SEGMENT_DONE {
+            refill_segment(&msg);
+            continue;
+        }
+
+        let mut response = IpcMessage::new();
+        response.msg_type = IPC_MSG_RESPONSE;
+        response.msg_id = msg.msg_id;
+
+        let mixer = unsafe { &mut *core::ptr::addr_of_mut!(MIXER) };
+        let owner = msg.sender_tid;
+        let data = &msg.inline_data[..msg.inline_size as usize];
+
+        let ok = match (msg.msg_id, data) {
+            (AUDIO_OP_OPEN_STREAM, _) => match mixer.open(owner, parse_u32_le(data)) {
+                Some(id) => {
+                    response.set_inline_data(&[AUDIO_STATUS_OK, id]);
+                    true
+                }
+                None => false,
+            },
+            (AUDIO_OP_CLOSE_STREAM, [id, ..]) => mixer.close(*id, owner),
+            (AUDIO_OP_WRITE, [id, ..]) if !msg.buffer.is_null() => {
+                let pcm = unsafe { core::slice::from_raw_parts(msg.buffer, msg.buffer_size) };
+                match mixer.write(*id, owner, pcm) {
+                    Some(taken) => {
+                        let taken = (taken as u32).to_le_bytes();
+                        response.set_inline_data(&[AUDIO_STATUS_OK, taken[0], taken[1], taken[2], taken[3]]);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            (AUDIO_OP_SET_VOLUME, [id, volume, ..]) => mixer.set_volume(*id, owner, *volume),
+            (AUDIO_OP_SET_MASTER_VOLUME, [volume, ..]) => {
+                mixer.set_master_volume(*volume);
+                true
+            }
+            (AUDIO_OP_GET_FORMAT, _) => {
+                let rate = mixer.device_rate().to_le_bytes();
+                response.set_inline_data(&[AUDIO_STATUS_OK, rate[0], rate[1], rate[2], rate[3], MIX_CHANNELS as u8]);
+                true
+            }
+            _ => false,
+        };
+
+        if !ok {
+            response.set_inline_data(&[AUDIO_STATUS_ERROR]);
+        } else if response.inline_size == 0 {
+            response.set_inline_data(&[AUDIO_STATUS_OK]);
+        }
+        sys_ipc_reply(&msg, &response);
+    }
+}
+
+fn parse_u32_le(bytes: &[u8]) -> u32 {
+    let mut buf = [0u8; 4];
+    let len = bytes.len().min(4);
+    buf[..len].copy_from_slice(&bytes[..len]);
+    u32::from_le_bytes(buf)
+}
+
+/// Mix the segment the driver just finished and hand it back
+fn refill_segment(done: &IpcMessage) {
+    if done.inline_size < 8 || done.reply_port == 0 {
+        return;
+    }
+    let data = &done.inline_data[..done.inline_size as usize];
+    let size = parse_u32_le(&data[4..8]) as usize;
+
+    let mixer = unsafe { &mut *core::ptr::addr_of_mut!(MIXER) };
+    if data.len() >= 12 {
+        mixer.set_device_rate(parse_u32_le(&data[8..12]));
+    }
+
+    let segment = unsafe { &mut *core::ptr::addr_of_mut!(SEGMENT) };
+    let segment = &mut segment[..size.min(MAX_SEGMENT_BYTES)];
+    mixer.mix(segment);
+
+    let mut fill = IpcMessage::new();
+    fill.msg_type = IPC_MSG_REQUEST;
+    fill.msg_id = MSG_HDA_FILL_SEGMENT;
+    fill.set_inline_data(&data[0..3]);
+    fill.buffer = segment.as_mut_ptr();
+    fill.buffer_size = segment.len();
+    sys_ipc_send(done.reply_port, &fill);
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,197 @@
+//! Stream mixer
+//!
+//! Each client stream has its own ring of pending samples. Writes take what
+//! fits and return at once, so a client that stalls never holds up the
+//! others. A mix pass sums every stream's next samples scaled by its
+//! volume, applies the master volume and saturates to i16; a stream short
+//! of data adds silence for the rest.
+//!
+//! All PCM is interleaved 16-bit stereo little endian. A stream opened at a
+//! rate other than the device's is resampled as it is written.
+
+use alloc::vec::Vec;
+use crate::resample::{resample, Resampler};
+
+/// Device rate until the driver reports one
+pub const MIX_SAMPLE_RATE: u32 = 48000;
+pub const MIX_CHANNELS: usize = 2;
+const FRAME_BYTES: usize = MIX_CHANNELS * 2;
+
+pub const MAX_STREAMS: usize = 16;
+/// Samples buffered per stream, about 170 ms of stereo at 48 kHz
+pub const STREAM_RING_SAMPLES: usize = 16384;
+/// Full volume; volumes are percentages
+pub const VOLUME_MAX: u8 = 100;
+
+struct Stream {
+    owner: u64,
+    volume: u8,
+    /// Rate the client writes at
+    rate: u32,
+    /// Set while the rate differs from the device's
+    resampler: Option<Resampler>,
+    ring: [i16; STREAM_RING_SAMPLES],
+    /// Index of the oldest queued sample
+    read: usize,
+    len: usize,
+}
+
+impl Stream {
+    fn sample(&self, offset: usize) -> i16 {
+        self.ring[(self.read + offset) % STREAM_RING_SAMPLES]
+    }
+
+    /// Queue one sample; callers check there is room
+    fn push(&mut self, sample: i16) {
+        self.ring[(self.read + self.len) % STREAM_RING_SAMPLES] = sample;
+        self.len += 1;
+    }
+}
+
+pub struct Mixer {
+    streams: [Option<Stream>; MAX_STREAMS],
+    master_volume: u8,
+    device_rate: u32,
+}
+
+fn resampler_for(rate: u32, device_rate: u32) -> Option<Resampler> {
+    if rate == device_rate {
+        None
+    } else {
+        Some(Resampler::new(rate, device_rate, MIX_CHANNELS))
+    }
+}
+
+impl Mixer {
+    pub const fn new() -> Self {
+        Mixer {
+            streams: [const { None }; MAX_STREAMS],
+            master_volume: VOLUME_MAX,
+            device_rate: MIX_SAMPLE_RATE,
+        }
+    }
+
+    pub fn device_rate(&self) -> u32 {
+        self.device_rate
+    }
+
+    /// Switch to the rate the device runs at. Audio already queued is
+    /// converted and streams are resampled from here on if theirs differs.
+    pub fn set_device_rate(&mut self, rate: u32) {
+        if rate == 0 || rate == self.device_rate {
+            return;
+        }
+        for stream in self.streams.iter_mut().flatten() {
+            let queued: Vec<i16> = (0..stream.len).map(|i| stream.sample(i)).collect();
+            let converted = resample(&queued, self.device_rate, rate, MIX_CHANNELS);
+            let keep = converted.len().min(STREAM_RING_SAMPLES) / MIX_CHANNELS * MIX_CHANNELS;
+
+            stream.read = 0;
+            stream.len = 0;
+            for &sample in &converted[..keep] {
+                stream.push(sample);
+            }
+            stream.resampler = resampler_for(stream.rate, rate);
+        }
+        self.device_rate = rate;
+    }
+
+    /// Open a stream at full volume, returning its id. Rate 0 means the
+    /// device rate.
+    pub fn open(&mut self, owner: u64, rate: u32) -> Option<u8> {
+        let id = self.streams.iter().position(|s| s.is_none())?;
+        let rate = if rate == 0 { self.device_rate } else { rate };
+        self.streams[id] = Some(Stream {
+            owner,
+            volume: VOLUME_MAX,
+            rate,
+            resampler: resampler_for(rate, self.device_rate),
+            ring: [0; STREAM_RING_SAMPLES],
+            read: 0,
+            len: 0,
+        });
+        Some(id as u8)
+    }
+
+    fn stream_mut(&mut self, id: u8, owner: u64) -> Option<&mut Stream> {
+        self.streams
+            .get_mut(id as usize)?
+            .as_mut()
+            .filter(|s| s.owner == owner)
+    }
+
+    /// Close a stream, dropping anything still queued
+    pub fn close(&mut self, id: u8, owner: u64) -> bool {
+        if self.stream_mut(id, owner).is_none() {
+            return false;
+        }
+        self.streams[id as usize] = None;
+        true
+    }
+
+    /// Queue PCM bytes, returning how many were taken: whole frames, as
+    /// many as fit
+    pub fn write(&mut self, id: u8, owner: u64, pcm: &[u8]) -> Option<usize> {
+        let stream = self.stream_mut(id, owner)?;
+        let free_frames = (STREAM_RING_SAMPLES - stream.len) / MIX_CHANNELS;
+        let samples = pcm.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]]));
+
+        let frames = match stream.resampler.as_mut() {
+            None => {
+                let frames = (pcm.len() / FRAME_BYTES).min(free_frames);
+                for sample in samples.take(frames * MIX_CHANNELS) {
+                    stream.push(sample);
+                }
+                frames
+            }
+            Some(resampler) => {
+                let frames = (pcm.len() / FRAME_BYTES).min(resampler.max_input_frames(free_frames));
+                let input: Vec<i16> = samples.take(frames * MIX_CHANNELS).collect();
+                let mut output = Vec::new();
+                resampler.process(&input, &mut output);
+                for sample in output {
+                    stream.push(sample);
+                }
+                frames
+            }
+        };
+        Some(frames * FRAME_BYTES)
+    }
+
+    pub fn set_volume(&mut self, id: u8, owner: u64, volume: u8) -> bool {
+        match self.stream_mut(id, owner) {
+            Some(stream) => {
+                stream.volume = volume.min(VOLUME_MAX);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn set_master_volume(&mut self, volume: u8) {
+        self.master_volume = volume.min(VOLUME_MAX);
+    }
+
+    /// Fill `out` with the next samples of every stream mixed together
+    pub fn mix(&mut self, out: &mut [u8]) {
+        let samples = out.len() / 2;
+        let master = self.master_volume as i32;
+
+        for (i, bytes) in out.chunks_exact_mut(2).enumerate() {
+            let mut sum: i32 = 0;
+            for stream in self.streams.iter().flatten() {
+                if i < stream.len {
+                    sum += stream.sample(i) as i32 * stream.volume as i32 / VOLUME_MAX as i32;
+                }
+            }
+            let mixed = (sum * master / VOLUME_MAX as i32).clamp(i16::MIN as i32, i16::MAX as i32);
+            bytes.copy_from_slice(&(mixed as i16).to_le_bytes());
+        }
+
+        for stream in self.streams.iter_mut().flatten() {
+            let used = samples.min(stream.len);
+            stream.read = (stream.read + used) % STREAM_RING_SAMPLES;
+            stream.len -= used;
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Sample rate conversion
+//!
+//! Linear interpolation between neighbouring input frames. The read
+//! position is kept as an exact fraction, in 1/out_rate steps of an input
+//! frame, so rounding never accumulates however long a stream plays. The
+//! last frame of each chunk is carried over to interpolate across the gap
+//! to the next one.
+
+use alloc::vec::Vec;
+
+/// Most interleaved channels a resampler handles
+pub const MAX_RESAMPLE_CHANNELS: usize = 8;
+
+pub struct Resampler {
+    in_rate: u32,
+    out_rate: u32,
+    channels: usize,
+    /// Position of the next output frame, in 1/out_rate input frames from
+    /// the start of the next chunk; -out_rate is the carried frame
+    position: i64,
+    prev: [i16; MAX_RESAMPLE_CHANNELS],
+    has_prev: bool,
+}
+
+impl Resampler {
+    pub fn new(in_rate: u32, out_rate: u32, channels: usize) -> Self {
+        Resampler {
+            in_rate: in_rate.max(1),
+            out_rate: out_rate.max(1),
+            channels: channels.clamp(1, MAX_RESAMPLE_CHANNELS),
+            position: 0,
+            prev: [0; MAX_RESAMPLE_CHANNELS],
+            has_prev: false,
+        }
+    }
+
+    /// Most input frames whose output is sure to fit in `output_frames`
+    pub fn max_input_frames(&self, output_frames: usize) -> usize {
+        if self.in_rate == self.out_rate {
+            return output_frames;
+        }
+        (output_frames.saturating_sub(1) as u64 * self.in_rate as u64 / self.out_rate as u64) as usize
+    }
+
+    /// Most output frames `input_frames` more input can produce
+    pub fn max_output_frames(&self, input_frames: usize) -> usize {
+        (input_frames as u64 * self.out_rate as u64 / self.in_rate as u64) as usize + 1
+    }
+
+    /// Convert the next chunk of interleaved samples, appending to `out`.
+    /// Trailing samples short of a whole frame are ignored.
+    pub fn process(&mut self, input: &[i16], out: &mut Vec<i16>) {
+        let channels = self.channels;
+        let frames = input.len() / channels;
+        if frames == 0 {
+            return;
+        }
+        if self.in_rate == self.out_rate {
+            out.extend_from_slice(&input[..frames * channels]);
+            return;
+        }
+
+        let out_rate = self.out_rate as i64;
+        let prev = self.prev;
+        let frame = |index: i64| -> &[i16] {
+            if index < 0 {
+                &prev[..channels]
+            } else {
+                &input[index as usize * channels..(index as usize + 1) * channels]
+            }
+        };
+
+        out.reserve(self.max_output_frames(frames) * channels);
+        let mut position = self.position;
+        loop {
+            let index = position.div_euclid(out_rate);
+            let fraction = position.rem_euclid(out_rate);
+            if index + 1 >= frames as i64 {
+                break;
+            }
+            let (a, b) = (frame(index), frame(index + 1));
+            for channel in 0..channels {
+                let (a, b) = (a[channel] as i64, b[channel] as i64);
+                out.push((a + (b - a) * fraction / out_rate) as i16);
+            }
+            position += self.in_rate as i64;
+        }
+
+        self.position = position - frames as i64 * out_rate;
+        self.prev[..channels].copy_from_slice(&input[(frames - 1) * channels..frames * channels]);
+        self.has_prev = true;
+    }
+
+    /// Emit the frames still due before the end of the input, holding the
+    /// last frame
+    pub fn finish(&mut self, out: &mut Vec<i16>) {
+        if !self.has_prev || self.in_rate == self.out_rate {
+            return;
+        }
+        while self.position < 0 {
+            out.extend_from_slice(&self.prev[..self.channels]);
+            self.position += self.in_rate as i64;
+        }
+    }
+}
+
+/// Convert a whole buffer of interleaved samples from `in_rate` to
+/// `out_rate`
+pub fn resample(input: &[i16], in_rate: u32, out_rate: u32, channels: usize) -> Vec<i16> {
+    let mut resampler = Resampler::new(in_rate, out_rate, channels);
+    let mut out = Vec::new();
+    resampler.process(input, &mut out);
+    resampler.finish(&mut out);
+    out
+}
@@ -22,6 +22,8 @@ pub enum DeviceState {
     Active = 2,
     Suspended = 3,
     Error = 4,
+    /// Unplugged; the id is free for the next device
+    Removed = 5,
 }
 
 /// Device information structure
@@ -33,44 +35,100 @@ pub struct Device {
     pub driver_loaded: bool,
     pub driver_name: [u8; 32],
     pub pci_info: PciDevice,  // Store directly, use device_type to determine if valid
+    pub usb_info: UsbDeviceInfo,
     // Add more device-specific info as needed
 }
 
+/// Where a USB device is attached and what it is
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct UsbDeviceInfo {
+    /// Port of the host controller driver that reported it
+    pub controller: u64,
+    /// Root hub port (0-based)
+    pub port: u8,
+    pub slot_id: u8,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub protocol: u8,
+}
+
 const MAX_DEVICES: usize = 256;
 
 /// Device registry
 static mut DEVICES: [Device; MAX_DEVICES] = unsafe { mem::zeroed() };
 static mut DEVICE_COUNT: usize = 0;
 
+/// Take a free registry entry, reusing the id of a removed device first
+fn alloc_device(device_type: DeviceType) -> Result<&'static mut Device, ()> {
+    unsafe {
+        let index = match (0..DEVICE_COUNT).find(|&i| DEVICES[i].state == DeviceState::Removed as u8) {
+            Some(index) => index,
+            None if DEVICE_COUNT < MAX_DEVICES => {
+                DEVICE_COUNT += 1;
+                DEVICE_COUNT - 1
+            }
+            None => return Err(()),
+        };
+
+        let device = &mut DEVICES[index];
+        *device = mem::zeroed();
+        device.device_id = index as u32;
+        device.device_type = device_type as u8;
+        device.state = DeviceState::Uninitialized as u8;
+        Ok(device)
+    }
+}
+
 /// Register a PCI device
 pub fn register_pci_device(pci_dev: &PciDevice) -> Result<u32, ()> {
+    let device = alloc_device(DeviceType::Pci)?;
+    device.pci_info = *pci_dev;  // Copy PCI device info
+    Ok(device.device_id)
+}
+
+/// Register a USB device reported by a host controller driver
+pub fn register_usb_device(usb_info: &UsbDeviceInfo) -> Result<u32, ()> {
+    let device = alloc_device(DeviceType::Usb)?;
+    device.usb_info = *usb_info;
+    Ok(device.device_id)
+}
+
+/// Find the USB device attached to a controller's root hub port
+pub fn find_usb_device(controller: u64, port: u8) -> Option<u32> {
+    unsafe {
+        DEVICES[..DEVICE_COUNT]
+            .iter()
+            .find(|d| {
+                d.device_type == DeviceType::Usb as u8
+                    && d.state != DeviceState::Removed as u8
+                    && d.usb_info.controller == controller
+                    && d.usb_info.port == port
+            })
+            .map(|d| d.device_id)
+    }
+}
+
+/// Mark a device unplugged and free its id
+pub fn remove_device(device_id: u32) -> Result<(), ()> {
     unsafe {
-        if DEVICE_COUNT >= MAX_DEVICES {
+        if (device_id as usize) >= DEVICE_COUNT || DEVICES[device_id as usize].state == DeviceState::Removed as u8 {
             return Err(());
         }
-        
-        let device = &mut DEVICES[DEVICE_COUNT];
-        device.device_id = DEVICE_COUNT as u32;
-        device.device_type = DeviceType::Pci as u8;
-        device.state = DeviceState::Uninitialized as u8;
+
+        let device = &mut DEVICES[device_id as usize];
+        device.state = DeviceState::Removed as u8;
         device.driver_loaded = false;
-        device.pci_info = *pci_dev;  // Copy PCI device info
-        
-        // Clear driver name
-        for i in 0..32 {
-            device.driver_name[i] = 0;
-        }
-        
-        let id = DEVICE_COUNT as u32;
-        DEVICE_COUNT += 1;
-        Ok(id)
+        Ok(())
     }
 }
 
-/// Get device by ID
+/// Get device by ID (removed devices are gone)
 pub fn get_device(device_id: u32) -> Option<&'static Device> {
     unsafe {
-        if (device_id as usize) < DEVICE_COUNT {
+        if (device_id as usize) < DEVICE_COUNT && DEVICES[device_id as usize].state != DeviceState::Removed as u8 {
             Some(&DEVICES[device_id as usize])
         } else {
             None
@@ -78,16 +136,27 @@ pub fn get_device(device_id: u32) -> Option<&'static Device> {
     }
 }
 
-/// Get device count
+/// Get device count (one past the highest id in use; removed ids leave gaps)
 pub fn get_device_count() -> usize {
     unsafe { DEVICE_COUNT }
 }
 
+/// Registry entries below the device count, indexed by id; removed
+/// devices keep their place with state `Removed`
+pub fn devices() -> &'static [Device] {
+    unsafe { &DEVICES[..DEVICE_COUNT] }
+}
+
+/// Raw bytes of registry entries, as sent to clients
+pub fn as_bytes(devices: &[Device]) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(devices.as_ptr() as *const u8, mem::size_of_val(devices)) }
+}
+
 /// Find device by PCI vendor/device ID
 pub fn find_device_by_pci_id(vendor_id: u16, device_id: u16) -> Option<&'static Device> {
     unsafe {
         for i in 0..DEVICE_COUNT {
-            if DEVICES[i].device_type == DeviceType::Pci as u8 {
+            if DEVICES[i].device_type == DeviceType::Pci as u8 && DEVICES[i].state != DeviceState::Removed as u8 {
                 let pci = &DEVICES[i].pci_info;
                 if pci.vendor_id == vendor_id && pci.device_id == device_id {
                     return Some(&DEVICES[i]);
@@ -1,116 +1,95 @@
 //! Driver loading and management
 
-use crate::device::{Device, DeviceState, set_device_driver, set_device_state};
-use crate::pci::PciDevice;
-use crate::process_spawn::spawn_driver_process;
+use crate::device::{DeviceState, set_device_driver, set_device_state};
+use crate::driver_table::{driver_binary_for, DeviceAssignment, DriverBinary};
+use crate::pci::{sys_pci_read_config, PciDevice, PCI_CLASS_MASS_STORAGE, PCI_CLASS_NETWORK};
+use crate::process_spawn::{driver_exited, spawn_driver};
+use crate::service_registry::{notify_service, ServiceType};
 
-/// Driver information
-pub struct DriverInfo {
-    pub name: &'static str,
-    pub probe: fn(&PciDevice) -> bool,
-    pub load: fn(&PciDevice) -> Result<(), ()>,
-}
-
-/// Known drivers
-static DRIVERS: &[DriverInfo] = &[
-    DriverInfo {
-        name: "ahci",
-        probe: ahci_probe,
-        load: ahci_load,
-    },
-    DriverInfo {
-        name: "ethernet",
-        probe: ethernet_probe,
-        load: ethernet_load,
-    },
-];
+/// Interrupt line (low byte) and pin (next byte) in PCI config space
+const PCI_CONFIG_INTERRUPT: u8 = 0x3C;
 
-/// Probe for AHCI controller
-fn ahci_probe(device: &PciDevice) -> bool {
-    device.class_code == 0x01 &&  // Mass storage controller
-    device.subclass == 0x06 &&    // SATA AHCI
-    device.interface == 0x01        // AHCI 1.0
+/// Build the assignment a driver is handed for a PCI device
+fn assignment_for(device: &PciDevice) -> DeviceAssignment {
+    let interrupt = sys_pci_read_config(device.bus, device.device, device.function, PCI_CONFIG_INTERRUPT);
+    DeviceAssignment {
+        bus: device.bus,
+        device: device.device,
+        function: device.function,
+        vendor_id: device.vendor_id,
+        device_id: device.device_id,
+        class_code: device.class_code,
+        subclass: device.subclass,
+        prog_if: device.prog_if,
+        irq_line: (interrupt & 0xFF) as u8,
+        irq_pin: ((interrupt >> 8) & 0xFF) as u8,
+        bars: device.bars,
+    }
 }
 
-/// Load AHCI driver
-fn ahci_load(device: &PciDevice) -> Result<(), ()> {
-    // Spawn AHCI driver process
-    match spawn_driver_process("ahci") {
-        Ok(driver_port) => {
-            // Notify block device service about new driver
-            let _ = crate::service_registry::notify_service(
-                crate::service_registry::ServiceType::BlockDevice,
-                driver_port
-            );
-            Ok(())
-        }
-        Err(_) => {
-            // Failed to spawn driver process
-            Err(())
-        }
+/// Service that should learn about a new driver for this class, if any
+fn service_for_class(class_code: u8) -> Option<ServiceType> {
+    match class_code {
+        PCI_CLASS_MASS_STORAGE => Some(ServiceType::BlockDevice),
+        PCI_CLASS_NETWORK => Some(ServiceType::NetworkDevice),
+        _ => None,
     }
 }
 
-/// Probe for Ethernet controller
-fn ethernet_probe(device: &PciDevice) -> bool {
-    device.class_code == 0x02 &&  // Network controller
-    device.subclass == 0x00       // Ethernet
+/// Find driver for device
+pub fn find_driver(device: &PciDevice) -> Option<&'static DriverBinary> {
+    driver_binary_for(&assignment_for(device))
 }
 
-/// Load Ethernet driver
-fn ethernet_load(device: &PciDevice) -> Result<(), ()> {
-    // Spawn Ethernet driver process
-    match spawn_driver_process("ethernet") {
-        Ok(driver_port) => {
-            // Notify network service about new driver
-            let _ = crate::service_registry::notify_service(
-                crate::service_registry::ServiceType::NetworkDevice,
-                driver_port
-            );
-            Ok(())
-        }
+/// Spawn the driver for a registered device
+pub fn load_driver(device_id: u32) -> Result<(), ()> {
+    let device = crate::device::get_device(device_id).ok_or(())?;
+    let assignment = assignment_for(&device.pci_info);
+    let binary = driver_binary_for(&assignment).ok_or(())?;
+
+    let process = match spawn_driver(binary.path, &assignment) {
+        Ok(process) => process,
         Err(_) => {
-            // Failed to spawn driver process
-            Err(())
+            let _ = set_device_state(device_id, DeviceState::Error);
+            return Err(());
         }
-    }
-}
+    };
 
-/// Find driver for device
-pub fn find_driver(device: &PciDevice) -> Option<&'static DriverInfo> {
-    DRIVERS.iter().find(|driver| (driver.probe)(device))
+    set_device_driver(device_id, binary.name)?;
+    set_device_state(device_id, DeviceState::Initialized)?;
+
+    if let Some(service) = service_for_class(assignment.class_code) {
+        let _ = notify_service(service, process.port);
+    }
+    Ok(())
 }
 
-/// Load driver for device
-pub fn load_driver(device: &PciDevice) -> Result<(), ()> {
-    if let Some(driver) = find_driver(device) {
-        // Set device driver name
-        if let Some(dev) = crate::device::find_device_by_pci_id(device.vendor_id, device.device_id) {
-            let device_id = dev.id;
-            set_device_driver(device_id, driver.name).map_err(|_| ())?;
-            set_device_state(device_id, DeviceState::Initialized).map_err(|_| ())?;
+/// Mark the device of a driver process that exited as failed
+pub fn handle_driver_exit(pid: u64) {
+    if let Some(process) = driver_exited(pid) {
+        let count = crate::device::get_device_count();
+        for i in 0..count as u32 {
+            if let Some(device) = crate::device::get_device(i) {
+                let pci = &device.pci_info;
+                if pci.bus == process.device.bus
+                    && pci.device == process.device.device
+                    && pci.function == process.device.function
+                {
+                    let _ = set_device_state(i, DeviceState::Error);
+                }
+            }
         }
-        
-        // Load driver
-        (driver.load)(device)
-    } else {
-        Err(())
     }
 }
 
 /// Auto-load drivers for all devices
 pub fn auto_load_drivers() {
     let count = crate::device::get_device_count();
-    for i in 0..count {
+    for i in 0..count as u32 {
         if let Some(device) = crate::device::get_device(i) {
-            if device.driver_id == 0 {
-                // Find PCI device
-                if let Some(pci_dev) = crate::pci::pci_get_device_by_id(device.vendor_id, device.device_id) {
-                    // Try to load driver
-                    let _ = load_driver(&pci_dev);
-                }
+            if !device.driver_loaded && find_driver(&device.pci_info).is_some() {
+                let _ = load_driver(i);
             }
         }
     }
 }
-
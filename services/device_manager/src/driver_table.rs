@@ -0,0 +1,95 @@
+//! Built-in table mapping PCI devices to driver binaries, and the message
+//! that hands a spawned driver its device
+
+/// Message id of the first message a spawned driver receives on its port
+pub const DRIVER_MSG_ASSIGN_DEVICE: u64 = 0x100;
+
+/// Size of an encoded device assignment
+pub const ASSIGNMENT_SIZE: usize = 60;
+
+/// How a table entry matches a device
+#[derive(Clone, Copy)]
+pub enum DeviceMatch {
+    /// Exact vendor/device id
+    Id(u16, u16),
+    /// Class/subclass, and prog-if unless None
+    Class(u8, u8, Option<u8>),
+}
+
+/// A driver executable and the devices it drives
+pub struct DriverBinary {
+    pub name: &'static str,
+    pub path: &'static str,
+    pub matches: DeviceMatch,
+}
+
+/// Known driver binaries. Exact ids are checked before class matches.
+pub static DRIVER_BINARIES: &[DriverBinary] = &[
+    DriverBinary { name: "ethernet", path: "/sbin/drivers/ethernet", matches: DeviceMatch::Id(0x8086, 0x100E) },
+    DriverBinary { name: "ethernet", path: "/sbin/drivers/ethernet", matches: DeviceMatch::Id(0x8086, 0x10D3) },
+    DriverBinary { name: "ahci", path: "/sbin/drivers/ahci", matches: DeviceMatch::Class(0x01, 0x06, Some(0x01)) },
+    DriverBinary { name: "ata", path: "/sbin/drivers/ata_driver", matches: DeviceMatch::Class(0x01, 0x01, None) },
+    DriverBinary { name: "xhci", path: "/sbin/drivers/xhci-driver", matches: DeviceMatch::Class(0x0C, 0x03, Some(0x30)) },
+];
+
+/// The device a driver is assigned, as sent in DRIVER_MSG_ASSIGN_DEVICE
+#[derive(Clone, Copy, Default)]
+pub struct DeviceAssignment {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class_code: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub irq_line: u8,
+    pub irq_pin: u8,
+    pub bars: [u64; 6],
+}
+
+impl DeviceAssignment {
+    /// Encode for an IPC message's inline data (little-endian):
+    /// bus, device, function, class, subclass, prog-if, irq line, irq pin,
+    /// vendor id, device id, then the six BARs
+    pub fn encode(&self, out: &mut [u8]) -> usize {
+        if out.len() < ASSIGNMENT_SIZE {
+            return 0;
+        }
+        out[0..8].copy_from_slice(&[
+            self.bus,
+            self.device,
+            self.function,
+            self.class_code,
+            self.subclass,
+            self.prog_if,
+            self.irq_line,
+            self.irq_pin,
+        ]);
+        out[8..10].copy_from_slice(&self.vendor_id.to_le_bytes());
+        out[10..12].copy_from_slice(&self.device_id.to_le_bytes());
+        for (i, bar) in self.bars.iter().enumerate() {
+            out[12 + i * 8..20 + i * 8].copy_from_slice(&bar.to_le_bytes());
+        }
+        ASSIGNMENT_SIZE
+    }
+}
+
+fn matches(entry: &DeviceMatch, info: &DeviceAssignment) -> bool {
+    match *entry {
+        DeviceMatch::Id(vendor, device) => info.vendor_id == vendor && info.device_id == device,
+        DeviceMatch::Class(class, subclass, prog_if) => {
+            info.class_code == class
+                && info.subclass == subclass
+                && prog_if.is_none_or(|p| info.prog_if == p)
+        }
+    }
+}
+
+/// Find the driver binary for a device
+pub fn driver_binary_for(info: &DeviceAssignment) -> Option<&'static DriverBinary> {
+    DRIVER_BINARIES
+        .iter()
+        .find(|b| matches!(b.matches, DeviceMatch::Id(..)) && matches(&b.matches, info))
+        .or_else(|| DRIVER_BINARIES.iter().find(|b| matches(&b.matches, info)))
+}
@@ -0,0 +1,144 @@
+//! Device add/remove notifications
+//!
+//! Services and drivers subscribe a port with `DEV_MGR_OP_SUBSCRIBE` and
+//! are sent a `DeviceEvent` whenever a device appears or goes away (USB
+//! attach/detach reported by the host controller driver).
+
+pub const MAX_SUBSCRIBERS: usize = 16;
+
+/// Longest device node name carried in an event (e.g. `/dev/usb3`)
+pub const MAX_NODE_NAME: usize = 16;
+
+/// Encoded size of an event with an empty name
+pub const EVENT_HEADER_SIZE: usize = 11;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceEventKind {
+    Added,
+    Removed,
+}
+
+/// Event payload: [device_id:4][device_type:1][vendor:2][product:2][class:1][name_len:1][name]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceEvent {
+    pub kind: DeviceEventKind,
+    pub device_id: u32,
+    pub device_type: u8,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub class: u8,
+    name: [u8; MAX_NODE_NAME],
+    name_len: usize,
+}
+
+impl DeviceEvent {
+    pub fn new(kind: DeviceEventKind, device_id: u32, device_type: u8, vendor_id: u16, product_id: u16, class: u8) -> Self {
+        DeviceEvent { kind, device_id, device_type, vendor_id, product_id, class, name: [0; MAX_NODE_NAME], name_len: 0 }
+    }
+
+    /// Node name the device is known by (truncated to `MAX_NODE_NAME`)
+    pub fn with_name(mut self, name: &[u8]) -> Self {
+        let len = name.len().min(MAX_NODE_NAME);
+        self.name[..len].copy_from_slice(&name[..len]);
+        self.name_len = len;
+        self
+    }
+
+    pub fn name(&self) -> &[u8] {
+        &self.name[..self.name_len]
+    }
+
+    /// Write the payload into `out`, returning its length
+    pub fn encode(&self, out: &mut [u8]) -> Option<usize> {
+        let len = EVENT_HEADER_SIZE + self.name_len;
+        if out.len() < len {
+            return None;
+        }
+        out[0..4].copy_from_slice(&self.device_id.to_le_bytes());
+        out[4] = self.device_type;
+        out[5..7].copy_from_slice(&self.vendor_id.to_le_bytes());
+        out[7..9].copy_from_slice(&self.product_id.to_le_bytes());
+        out[9] = self.class;
+        out[10] = self.name_len as u8;
+        out[EVENT_HEADER_SIZE..len].copy_from_slice(self.name());
+        Some(len)
+    }
+
+    /// Parse a payload; the kind comes from the message id
+    pub fn decode(kind: DeviceEventKind, data: &[u8]) -> Option<Self> {
+        if data.len() < EVENT_HEADER_SIZE {
+            return None;
+        }
+        let name_len = data[10] as usize;
+        if name_len > MAX_NODE_NAME || data.len() < EVENT_HEADER_SIZE + name_len {
+            return None;
+        }
+        let event = DeviceEvent::new(
+            kind,
+            u32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+            data[4],
+            u16::from_le_bytes([data[5], data[6]]),
+            u16::from_le_bytes([data[7], data[8]]),
+            data[9],
+        );
+        Some(event.with_name(&data[EVENT_HEADER_SIZE..EVENT_HEADER_SIZE + name_len]))
+    }
+}
+
+/// Write `prefix` followed by `id` in decimal, returning the length
+pub fn node_name(prefix: &[u8], id: u32, out: &mut [u8; MAX_NODE_NAME]) -> usize {
+    let mut digits = [0u8; 10];
+    let mut count = 0;
+    let mut value = id;
+    loop {
+        digits[count] = b'0' + (value % 10) as u8;
+        count += 1;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+
+    let prefix_len = prefix.len().min(MAX_NODE_NAME - count);
+    out[..prefix_len].copy_from_slice(&prefix[..prefix_len]);
+    for i in 0..count {
+        out[prefix_len + i] = digits[count - 1 - i];
+    }
+    prefix_len + count
+}
+
+/// Ports that asked to hear about device changes
+pub struct Subscribers {
+    ports: [u64; MAX_SUBSCRIBERS],
+}
+
+impl Subscribers {
+    pub const fn new() -> Self {
+        Subscribers { ports: [0; MAX_SUBSCRIBERS] }
+    }
+
+    /// Add `port`; subscribing twice is harmless
+    pub fn subscribe(&mut self, port: u64) -> Result<(), ()> {
+        if port == 0 {
+            return Err(());
+        }
+        if self.ports.contains(&port) {
+            return Ok(());
+        }
+        let slot = self.ports.iter_mut().find(|p| **p == 0).ok_or(())?;
+        *slot = port;
+        Ok(())
+    }
+
+    pub fn unsubscribe(&mut self, port: u64) {
+        for slot in self.ports.iter_mut() {
+            if *slot == port {
+                *slot = 0;
+            }
+        }
+    }
+
+    pub fn ports(&self) -> impl Iterator<Item = u64> + '_ {
+        self.ports.iter().copied().filter(|&p| p != 0)
+    }
+}
@@ -18,6 +18,8 @@ pub struct IpcMessage {
     pub inline_data: [u8; 64],
     pub buffer: *mut u8,
     pub buffer_size: usize,
+    /// Port responses go to; 0 if the sender expects none
+    pub reply_port: u64,
 }
 
 impl IpcMessage {
@@ -30,6 +32,7 @@ impl IpcMessage {
             inline_data: [0; 64],
             buffer: core::ptr::null_mut(),
             buffer_size: 0,
+            reply_port: 0,
         }
     }
     
@@ -40,6 +43,97 @@ impl IpcMessage {
     }
 }
 
+/// Convenience wrapper that returns Result for send
+pub fn ipc_send(port_id: u64, msg: &IpcMessage) -> Result<(), ()> {
+    if sys_ipc_send(port_id, msg) == 0 { Ok(()) } else { Err(()) }
+}
+
+/// Convenience wrapper that returns Result for receive
+pub fn ipc_receive(port_id: u64, msg: &mut IpcMessage) -> Result<(), ()> {
+    if sys_ipc_receive(port_id, msg) == 0 { Ok(()) } else { Err(()) }
+}
+
+/// Port init receives readiness reports on
+pub const INIT_PORT: u64 = 1;
+/// Readiness report; inline data is the service's name
+pub const INIT_MSG_SERVICE_READY: u64 = 1;
+
+/// Tell init this service is taking requests, so it can start the
+/// services that depend on it
+pub fn notify_init_ready(name: &[u8]) {
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_NOTIFICATION;
+    msg.msg_id = INIT_MSG_SERVICE_READY;
+    msg.set_inline_data(name);
+    let _ = ipc_send(INIT_PORT, &msg);
+}
+
+/// Bytes of inline data in a message
+pub const IPC_INLINE_SIZE: usize = 64;
+
+/// Copy `payload` into a new shared memory region and return its id
+fn share_payload(payload: &[u8]) -> Result<u64, ()> {
+    let shm_id = unsafe { syscall(40, payload.len() as u64, 0, 0, 0, 0) };
+    if shm_id == 0 {
+        return Err(());
+    }
+    let vaddr = unsafe { syscall(41, shm_id, 0, 0, 0, 0) };
+    if vaddr == 0 {
+        shm_destroy(shm_id);
+        return Err(());
+    }
+    unsafe {
+        core::ptr::copy_nonoverlapping(payload.as_ptr(), vaddr as *mut u8, payload.len());
+        syscall(42, shm_id, vaddr, 0, 0, 0);
+    }
+    Ok(shm_id)
+}
+
+fn shm_destroy(shm_id: u64) {
+    unsafe {
+        syscall(43, shm_id, 0, 0, 0, 0);
+    }
+}
+
+/// Reply to `request` with `header` followed by `payload` of any length:
+/// inline after the header if it fits, otherwise in a shared memory
+/// region whose id goes in `buffer` and length in `buffer_size`, for the
+/// requester to map, copy and destroy
+pub fn ipc_reply_large(request: &IpcMessage, header: &IpcMessage, payload: &[u8]) -> Result<(), ()> {
+    let header_len = (header.inline_size as usize).min(IPC_INLINE_SIZE);
+    let mut msg = IpcMessage::new();
+    msg.msg_id = header.msg_id;
+    msg.msg_type = header.msg_type;
+    msg.set_inline_data(&header.inline_data[..header_len]);
+
+    let mut shared = None;
+    if header_len + payload.len() <= IPC_INLINE_SIZE {
+        msg.inline_data[header_len..header_len + payload.len()].copy_from_slice(payload);
+        msg.inline_size = (header_len + payload.len()) as u32;
+    } else {
+        let shm_id = share_payload(payload)?;
+        msg.buffer = shm_id as *mut u8;
+        msg.buffer_size = payload.len();
+        shared = Some(shm_id);
+    }
+
+    if sys_ipc_reply(request, &msg) == 0 {
+        Ok(())
+    } else {
+        shared.into_iter().for_each(shm_destroy);
+        Err(())
+    }
+}
+
+extern "C" {
+    fn syscall_ipc_register_port(port: u32) -> i32;
+}
+
+/// Claim a well-known port number
+pub fn sys_ipc_register_port(port: u64) -> i32 {
+    unsafe { syscall_ipc_register_port(port as u32) }
+}
+
 /// System call wrapper for IPC send
 #[no_mangle]
 pub extern "C" fn sys_ipc_send(port_id: u64, msg: *const IpcMessage) -> i32 {
@@ -58,6 +152,14 @@ pub extern "C" fn sys_ipc_receive(port_id: u64, msg: *mut IpcMessage) -> i32 {
     }
 }
 
+/// System call wrapper for IPC reply
+#[no_mangle]
+pub extern "C" fn sys_ipc_reply(request: *const IpcMessage, response: *const IpcMessage) -> i32 {
+    unsafe {
+        syscall(55, request as u64, response as u64, 0, 0, 0) as i32
+    }
+}
+
 /// Raw syscall function (architecture-specific)
 #[cfg(target_arch = "x86_64")]
 unsafe fn syscall(num: u64, arg1: u64, arg2: u64, arg3: u64, arg4: u64, arg5: u64) -> u64 {
@@ -3,26 +3,62 @@
 pub mod pci;
 pub mod device;
 pub mod driver;
+pub mod driver_table;
+pub mod name_registry;
 pub mod service_registry;
 pub mod process_spawn;
+pub mod hotplug;
 
 pub use crate::ipc::{IpcMessage, IPC_MSG_REQUEST, IPC_MSG_RESPONSE};
 pub use pci::{pci_enumerate, pci_get_device_count, pci_get_device, PciDevice};
 pub use device::{register_pci_device, get_device, get_device_count, 
                  find_device_by_pci_id, set_device_driver, set_device_state, Device};
-pub use driver::{find_driver, load_driver, auto_load_drivers};
-pub use service_registry::{ServiceType, register_service_port, notify_service, get_driver_port};
+pub use hotplug::{DeviceEvent, DeviceEventKind};
+pub use driver::{find_driver, load_driver, auto_load_drivers, handle_driver_exit};
+pub use process_spawn::{spawn_driver, DriverProcess};
+pub use service_registry::{ServiceType, register_service_port, notify_service, get_driver_port,
+                           register_named, lookup_named};
 
 /// Device manager operation types
+/// [] -> [count:4][record_size:4][records], the records framed after the
+/// header (`ipc::ipc_reply_large`). Record `i` is the `Device` with id `i`;
+/// removed ids stay in the list with state `DeviceState::Removed`.
 pub const DEV_MGR_OP_ENUMERATE: u64 = 1;
 pub const DEV_MGR_OP_LOAD_DRIVER: u64 = 2;
+/// [device_id:4] -> [Device], framed; empty if there is no such device
 pub const DEV_MGR_OP_GET_DEVICE: u64 = 3;
+/// [vendor:2][device:2] -> [Device], framed; empty if none matches
 pub const DEV_MGR_OP_FIND_DEVICE: u64 = 4;
+/// [port:8][name] -> [status:1]; re-registering a name replaces its port
+pub const DEV_MGR_OP_REGISTER_NAME: u64 = 5;
+/// [name] -> [status:1][port:8]
+pub const DEV_MGR_OP_LOOKUP_NAME: u64 = 6;
+
+/// [port:8] -> [status:1]; the port is sent DEV_MGR_EVENT_* notifications
+pub const DEV_MGR_OP_SUBSCRIBE: u64 = 7;
+/// [port:8] -> [status:1]
+pub const DEV_MGR_OP_UNSUBSCRIBE: u64 = 8;
+
+/// Notifications from USB host controller drivers (no reply):
+/// [controller:8][port:1][slot:1][vendor:2][product:2][class:1][subclass:1][protocol:1]
+pub const DEV_MGR_NOTIFY_USB_ATTACH: u64 = 9;
+/// [controller:8][port:1]
+pub const DEV_MGR_NOTIFY_USB_DETACH: u64 = 10;
+
+/// Notifications to subscribers; the payload is an encoded `DeviceEvent`
+pub const DEV_MGR_EVENT_DEVICE_ADDED: u64 = 0x200;
+pub const DEV_MGR_EVENT_DEVICE_REMOVED: u64 = 0x201;
+
+/// The one fixed port: other services are found by name through it
+pub const SERVICE_REGISTRY_PORT: u64 = 90;
 
 /// Device manager service port
 static mut SERVICE_PORT: u64 = 0;
 static mut INITIALIZED: bool = false;
 
+/// Ports told about device changes
+static mut SUBSCRIBERS: hotplug::Subscribers = hotplug::Subscribers::new();
+
 /// Get service port (for internal use)
 pub unsafe fn get_service_port() -> u64 {
     SERVICE_PORT
@@ -32,11 +68,10 @@ pub unsafe fn get_service_port() -> u64 {
 pub fn init_ipc() -> Result<u64, ()> {
     unsafe {
         if SERVICE_PORT == 0 {
-            // Create IPC port via syscall
-            SERVICE_PORT = sys_ipc_create_port();
-            if SERVICE_PORT == 0 {
+            if crate::ipc::sys_ipc_register_port(SERVICE_REGISTRY_PORT) != 0 {
                 return Err(());
             }
+            SERVICE_PORT = SERVICE_REGISTRY_PORT;
         }
         Ok(SERVICE_PORT)
     }
@@ -102,27 +137,33 @@ pub fn init() -> Result<(), ()> {
     }
 }
 
-/// Handle device enumeration request
-pub fn handle_enumerate_devices(request: &IpcMessage) -> IpcMessage {
+/// Handle device enumeration request, replying directly since the list
+/// does not fit inline
+pub fn handle_enumerate_devices(request: &IpcMessage) {
     let mut response = IpcMessage::new();
     response.msg_type = IPC_MSG_RESPONSE;
     response.msg_id = request.msg_id;
     
-    // Return registered device count (from device registry)
-    let count = device::get_device_count() as u32;
+    // Every registry entry, from the device registry
+    let devices = device::devices();
+    let count = devices.len() as u32;
+    let record_size = core::mem::size_of::<Device>() as u32;
     response.inline_data[0..4].copy_from_slice(&count.to_le_bytes());
-    response.inline_size = 4;
+    response.inline_data[4..8].copy_from_slice(&record_size.to_le_bytes());
+    response.inline_size = 8;
     
-    response
+    let _ = crate::ipc::ipc_reply_large(request, &response, device::as_bytes(devices));
 }
 
-/// Handle get device request
-pub fn handle_get_device(request: &IpcMessage) -> IpcMessage {
+/// Handle get device request, replying directly since a device record
+/// does not fit inline
+pub fn handle_get_device(request: &IpcMessage) {
     let mut response = IpcMessage::new();
     response.msg_type = IPC_MSG_RESPONSE;
     response.msg_id = request.msg_id;
     
     // Parse device ID from request
+    let mut device = None;
     if request.inline_size >= 4 {
         let device_id = u32::from_le_bytes([
             request.inline_data[0],
@@ -130,22 +171,11 @@ pub fn handle_get_device(request: &IpcMessage) -> IpcMessage {
             request.inline_data[2],
             request.inline_data[3],
         ]);
-        
-        if let Some(device) = device::get_device(device_id) {
-            // Copy device info to response
-            let device_bytes = unsafe {
-                core::slice::from_raw_parts(
-                    device as *const _ as *const u8,
-                    core::mem::size_of::<device::Device>()
-                )
-            };
-            let copy_len = device_bytes.len().min(64);
-            response.inline_data[0..copy_len].copy_from_slice(&device_bytes[0..copy_len]);
-            response.inline_size = copy_len as u32;
-        }
+        device = device::get_device(device_id);
     }
     
-    response
+    let payload = device.map(core::slice::from_ref).map(device::as_bytes).unwrap_or(&[]);
+    let _ = crate::ipc::ipc_reply_large(request, &response, payload);
 }
 
 /// Handle driver load request
@@ -196,3 +226,182 @@ pub fn handle_load_driver(request: &IpcMessage) -> IpcMessage {
     
     response
 }
+
+/// Name in a request, up to the first NUL
+fn request_name(data: &[u8]) -> Option<&str> {
+    let len = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    core::str::from_utf8(&data[..len]).ok()
+}
+
+/// Handle service name registration
+pub fn handle_register_name(request: &IpcMessage) -> IpcMessage {
+    let mut response = IpcMessage::new();
+    response.msg_type = IPC_MSG_RESPONSE;
+    response.msg_id = request.msg_id;
+    response.inline_size = 1;
+
+    let size = (request.inline_size as usize).min(request.inline_data.len());
+    if size <= 8 {
+        response.inline_data[0] = 2;  // Invalid request
+        return response;
+    }
+
+    let mut port_bytes = [0u8; 8];
+    port_bytes.copy_from_slice(&request.inline_data[0..8]);
+    let port = u64::from_le_bytes(port_bytes);
+
+    let registered = request_name(&request.inline_data[8..size])
+        .is_some_and(|name| register_named(name, port).is_ok());
+    response.inline_data[0] = if registered { 0 } else { 1 };
+    response
+}
+
+/// Handle service name lookup
+pub fn handle_lookup_name(request: &IpcMessage) -> IpcMessage {
+    let mut response = IpcMessage::new();
+    response.msg_type = IPC_MSG_RESPONSE;
+    response.msg_id = request.msg_id;
+
+    let size = (request.inline_size as usize).min(request.inline_data.len());
+    match request_name(&request.inline_data[..size]).and_then(lookup_named) {
+        Some(port) => {
+            response.inline_data[0] = 0;
+            response.inline_data[1..9].copy_from_slice(&port.to_le_bytes());
+            response.inline_size = 9;
+        }
+        None => {
+            response.inline_data[0] = 1;  // Not registered
+            response.inline_size = 1;
+        }
+    }
+    response
+}
+
+/// Port number at the start of a request
+fn request_port(request: &IpcMessage) -> Option<u64> {
+    if request.inline_size < 8 {
+        return None;
+    }
+    let mut port_bytes = [0u8; 8];
+    port_bytes.copy_from_slice(&request.inline_data[0..8]);
+    Some(u64::from_le_bytes(port_bytes))
+}
+
+/// Handle device event subscription
+pub fn handle_subscribe(request: &IpcMessage) -> IpcMessage {
+    let mut response = IpcMessage::new();
+    response.msg_type = IPC_MSG_RESPONSE;
+    response.msg_id = request.msg_id;
+    response.inline_size = 1;
+
+    let subscribed = request_port(request)
+        .is_some_and(|port| unsafe { (*core::ptr::addr_of_mut!(SUBSCRIBERS)).subscribe(port).is_ok() });
+    response.inline_data[0] = if subscribed { 0 } else { 1 };
+    response
+}
+
+/// Handle device event unsubscription
+pub fn handle_unsubscribe(request: &IpcMessage) -> IpcMessage {
+    let mut response = IpcMessage::new();
+    response.msg_type = IPC_MSG_RESPONSE;
+    response.msg_id = request.msg_id;
+    response.inline_size = 1;
+
+    match request_port(request) {
+        Some(port) => {
+            unsafe { (*core::ptr::addr_of_mut!(SUBSCRIBERS)).unsubscribe(port) };
+            response.inline_data[0] = 0;
+        }
+        None => response.inline_data[0] = 2,  // Invalid request
+    }
+    response
+}
+
+/// Event describing a registered device, named after its node
+fn device_event(kind: DeviceEventKind, device: &Device) -> DeviceEvent {
+    let mut name = [0u8; hotplug::MAX_NODE_NAME];
+    let (vendor_id, product_id, class, prefix): (u16, u16, u8, &[u8]) =
+        if device.device_type == device::DeviceType::Usb as u8 {
+            (device.usb_info.vendor_id, device.usb_info.product_id, device.usb_info.class, b"/dev/usb")
+        } else {
+            (device.pci_info.vendor_id, device.pci_info.device_id, device.pci_info.class_code, b"/dev/pci")
+        };
+    let len = hotplug::node_name(prefix, device.device_id, &mut name);
+    DeviceEvent::new(kind, device.device_id, device.device_type, vendor_id, product_id, class)
+        .with_name(&name[..len])
+}
+
+/// Send an event to every subscriber. Ports that no longer accept
+/// messages (the subscriber exited) are dropped.
+fn publish_event(event: &DeviceEvent) {
+    let mut msg = IpcMessage::new();
+    msg.msg_type = crate::ipc::IPC_MSG_NOTIFICATION;
+    msg.msg_id = match event.kind {
+        DeviceEventKind::Added => DEV_MGR_EVENT_DEVICE_ADDED,
+        DeviceEventKind::Removed => DEV_MGR_EVENT_DEVICE_REMOVED,
+    };
+    let len = event.encode(&mut msg.inline_data).unwrap_or(0);
+    msg.inline_size = len as u32;
+
+    let subscribers = unsafe { &mut *core::ptr::addr_of_mut!(SUBSCRIBERS) };
+    let mut dead = [0u64; hotplug::MAX_SUBSCRIBERS];
+    for (slot, port) in subscribers.ports().enumerate() {
+        if crate::ipc::ipc_send(port, &msg).is_err() {
+            dead[slot] = port;
+        }
+    }
+    for port in dead.iter().filter(|&&p| p != 0) {
+        subscribers.unsubscribe(*port);
+    }
+}
+
+/// Handle a notification (no reply is sent)
+pub fn handle_notification(msg: &IpcMessage) {
+    let data = &msg.inline_data[..(msg.inline_size as usize).min(msg.inline_data.len())];
+    match msg.msg_id {
+        DEV_MGR_NOTIFY_USB_ATTACH if data.len() >= 17 => {
+            let mut controller = [0u8; 8];
+            controller.copy_from_slice(&data[0..8]);
+            let usb_info = device::UsbDeviceInfo {
+                controller: u64::from_le_bytes(controller),
+                port: data[8],
+                slot_id: data[9],
+                vendor_id: u16::from_le_bytes([data[10], data[11]]),
+                product_id: u16::from_le_bytes([data[12], data[13]]),
+                class: data[14],
+                subclass: data[15],
+                protocol: data[16],
+            };
+
+            // A re-reported attach (no detach seen) replaces the old entry
+            if let Some(old) = device::find_usb_device(usb_info.controller, usb_info.port) {
+                usb_detached(old);
+            }
+            if let Ok(device_id) = device::register_usb_device(&usb_info) {
+                let _ = device::set_device_state(device_id, device::DeviceState::Active);
+                if let Some(device) = device::get_device(device_id) {
+                    publish_event(&device_event(DeviceEventKind::Added, device));
+                }
+            }
+        }
+        DEV_MGR_NOTIFY_USB_DETACH if data.len() >= 9 => {
+            let mut controller = [0u8; 8];
+            controller.copy_from_slice(&data[0..8]);
+            if let Some(device_id) = device::find_usb_device(u64::from_le_bytes(controller), data[8]) {
+                usb_detached(device_id);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Announce a device's removal and free its id
+fn usb_detached(device_id: u32) {
+    // The event is built before the entry is freed for reuse
+    let event = device::get_device(device_id).map(|device| device_event(DeviceEventKind::Removed, device));
+    if device::remove_device(device_id).is_ok() {
+        if let Some(event) = event {
+            publish_event(&event);
+        }
+    }
+}
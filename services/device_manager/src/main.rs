@@ -10,8 +10,9 @@ mod ipc;
 mod lib;
 
 use core::panic::PanicInfo;
-use lib::{init_ipc, handle_enumerate_devices, handle_load_driver, handle_get_device, get_service_port};
-use ipc::{IpcMessage, sys_ipc_receive, sys_ipc_send};
+use lib::{init_ipc, handle_enumerate_devices, handle_load_driver, handle_get_device, get_service_port,
+          handle_register_name, handle_lookup_name, handle_subscribe, handle_unsubscribe, handle_notification};
+use ipc::{IpcMessage, sys_ipc_receive, sys_ipc_reply, notify_init_ready};
 
 /// Panic handler for the device manager service
 #[panic_handler]
@@ -39,6 +40,8 @@ fn device_manager_init() {
     
     // Initialize device manager
     let _ = lib::init();
+
+    notify_init_ready(b"device_manager");
 }
 
 /// Main service loop - handles IPC messages
@@ -56,9 +59,21 @@ fn device_manager_loop() {
                 ipc::IPC_MSG_REQUEST => {
                     // Handle request based on msg_id
                     match msg.msg_id {
-                        lib::DEV_MGR_OP_ENUMERATE => handle_enumerate_devices(&msg),
+                        // Device records do not fit inline, so these
+                        // handlers frame their own replies
+                        lib::DEV_MGR_OP_ENUMERATE => {
+                            handle_enumerate_devices(&msg);
+                            continue;
+                        },
                         lib::DEV_MGR_OP_LOAD_DRIVER => handle_load_driver(&msg),
-                        lib::DEV_MGR_OP_GET_DEVICE => handle_get_device(&msg),
+                        lib::DEV_MGR_OP_GET_DEVICE => {
+                            handle_get_device(&msg);
+                            continue;
+                        },
+                        lib::DEV_MGR_OP_REGISTER_NAME => handle_register_name(&msg),
+                        lib::DEV_MGR_OP_LOOKUP_NAME => handle_lookup_name(&msg),
+                        lib::DEV_MGR_OP_SUBSCRIBE => handle_subscribe(&msg),
+                        lib::DEV_MGR_OP_UNSUBSCRIBE => handle_unsubscribe(&msg),
                         lib::DEV_MGR_OP_FIND_DEVICE => {
                             // Find device by vendor/device ID or class
                             let mut resp = IpcMessage::new();
@@ -76,15 +91,9 @@ fn device_manager_loop() {
                                 ]);
                                 
                                 if let Some(device) = lib::device::find_device_by_pci_id(vendor_id, device_id) {
-                                    let device_bytes = unsafe {
-                                        core::slice::from_raw_parts(
-                                            device as *const _ as *const u8,
-                                            core::mem::size_of::<lib::device::Device>()
-                                        )
-                                    };
-                                    let copy_len = device_bytes.len().min(64);
-                                    resp.inline_data[0..copy_len].copy_from_slice(&device_bytes[0..copy_len]);
-                                    resp.inline_size = copy_len as u32;
+                                    let device_bytes = lib::device::as_bytes(core::slice::from_ref(device));
+                                    let _ = ipc::ipc_reply_large(&msg, &resp, device_bytes);
+                                    continue;
                                 }
                             }
                             resp
@@ -100,6 +109,11 @@ fn device_manager_loop() {
                         }
                     }
                 },
+                ipc::IPC_MSG_NOTIFICATION => {
+                    // Hotplug reports from bus drivers expect no reply
+                    handle_notification(&msg);
+                    continue;
+                },
                 _ => {
                     // Unknown message type
                     let mut resp = IpcMessage::new();
@@ -109,8 +123,8 @@ fn device_manager_loop() {
                 }
             };
             
-            // Send response back to sender using sender_tid as reply port.
-            let _ = sys_ipc_send(msg.sender_tid, &response);
+            // Send response back on the sender's reply port
+            let _ = sys_ipc_reply(&msg, &response);
         }
         
         // Yield to scheduler (if syscall exists)
@@ -0,0 +1,64 @@
+//! Service ports looked up by name
+//!
+//! Registering a name that is already present replaces its port, so a
+//! restarted service simply registers again.
+
+/// Longest service name kept
+pub const MAX_SERVICE_NAME: usize = 32;
+pub const MAX_NAMED_SERVICES: usize = 32;
+
+#[derive(Clone, Copy)]
+struct NamedService {
+    name: [u8; MAX_SERVICE_NAME],
+    len: usize,
+    port: u64,
+}
+
+pub struct NameRegistry {
+    entries: [Option<NamedService>; MAX_NAMED_SERVICES],
+}
+
+/// Name up to its first NUL, if it is a usable service name
+fn trim_name(name: &[u8]) -> Option<&[u8]> {
+    let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    if len == 0 || len > MAX_SERVICE_NAME {
+        None
+    } else {
+        Some(&name[..len])
+    }
+}
+
+impl NameRegistry {
+    pub const fn new() -> Self {
+        NameRegistry { entries: [None; MAX_NAMED_SERVICES] }
+    }
+
+    /// Register `name` at `port`, updating the entry if the name is taken
+    pub fn register(&mut self, name: &[u8], port: u64) -> Result<(), ()> {
+        let name = trim_name(name).ok_or(())?;
+        if port == 0 {
+            return Err(());
+        }
+
+        if let Some(entry) = self.entries.iter_mut().flatten().find(|e| &e.name[..e.len] == name) {
+            entry.port = port;
+            return Ok(());
+        }
+
+        let slot = self.entries.iter_mut().find(|e| e.is_none()).ok_or(())?;
+        let mut entry = NamedService { name: [0; MAX_SERVICE_NAME], len: name.len(), port };
+        entry.name[..name.len()].copy_from_slice(name);
+        *slot = Some(entry);
+        Ok(())
+    }
+
+    /// Port registered for `name`
+    pub fn lookup(&self, name: &[u8]) -> Option<u64> {
+        let name = trim_name(name)?;
+        self.entries
+            .iter()
+            .flatten()
+            .find(|e| &e.name[..e.len] == name)
+            .map(|e| e.port)
+    }
+}
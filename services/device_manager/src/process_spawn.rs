@@ -1,57 +1,152 @@
 //! Process spawning for driver loading
+//!
+//! A driver is its own executable. spawn_driver forks and execs it, waits for
+//! the driver to publish its IPC port (SYS_SET_PROCESS_IPC_PORT), then sends
+//! the device to drive as DRIVER_MSG_ASSIGN_DEVICE. Spawned pids are kept so
+//! a driver that exits can be traced back to its device.
 
-use crate::syscalls;
-
-/// Spawn a driver process
-/// Returns the IPC port of the spawned driver process
-pub fn spawn_driver_process(driver_name: &str) -> Result<u64, ()> {
-    // Driver entry points (would be loaded from filesystem in real implementation)
-    let entry_point = match driver_name {
-        "ahci" => 0x500000u64,      // Driver entry point
-        "ethernet" => 0x510000u64,  // Driver entry point
-        _ => return Err(()),
-    };
-    
-    // Convert driver name to C string
-    let name_bytes = driver_name.as_bytes();
-    let mut name_buf = [0u8; 64];
-    let len = name_bytes.len().min(63);
-    name_buf[0..len].copy_from_slice(&name_bytes[0..len]);
-    
-    // Spawn the process
-    let pid = match syscalls::spawn_process(name_buf.as_ptr() as *const u8, core::ptr::null(), entry_point) {
-        Ok(p) => p,
-        Err(_) => return Err(()),
-    };
-    
-    // Get IPC port from the spawned process
-    let port = match syscalls::get_process_ipc_port(pid) {
-        Ok(p) => p,
-        Err(_) => return Err(()),
-    };
-    
-    Ok(port)
+use crate::driver_table::{DeviceAssignment, DRIVER_MSG_ASSIGN_DEVICE};
+use crate::ipc::{sys_ipc_send, IpcMessage, IPC_MSG_REQUEST};
+
+/// How long a new driver has to publish its IPC port
+const PORT_WAIT_MS: u64 = 2000;
+const PORT_POLL_MS: u64 = 10;
+
+const MAX_DRIVER_PROCESSES: usize = 32;
+
+/// A running driver process
+#[derive(Clone, Copy)]
+pub struct DriverProcess {
+    pub pid: u64,
+    /// 0 if the driver never published a port
+    pub port: u64,
+    pub device: DeviceAssignment,
+}
+
+static mut DRIVER_PROCESSES: [Option<DriverProcess>; MAX_DRIVER_PROCESSES] = [None; MAX_DRIVER_PROCESSES];
+
+/// Spawn the driver at `path` and assign it `device_info`.
+/// Returns the driver process once it has been handed its device.
+pub fn spawn_driver(path: &str, device_info: &DeviceAssignment) -> Result<DriverProcess, ()> {
+    // exec needs a NUL-terminated path
+    let mut path_buf = [0u8; 128];
+    if path.len() >= path_buf.len() {
+        return Err(());
+    }
+    path_buf[..path.len()].copy_from_slice(path.as_bytes());
+
+    let pid = syscalls::fork()?;
+    if pid == 0 {
+        // Child: become the driver
+        let argv: [*const u8; 2] = [path_buf.as_ptr(), core::ptr::null()];
+        let envp: [*const u8; 1] = [core::ptr::null()];
+        syscalls::exec(path_buf.as_ptr(), argv.as_ptr(), envp.as_ptr());
+        // exec only returns on failure
+        syscalls::exit(1);
+    }
+
+    // Record the pid before anything else can fail, so the process is tracked
+    let mut process = DriverProcess { pid, port: 0, device: *device_info };
+    record(process)?;
+
+    process.port = wait_for_port(pid).ok_or(())?;
+    set_port(pid, process.port);
+
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_REQUEST;
+    msg.msg_id = DRIVER_MSG_ASSIGN_DEVICE;
+    let len = device_info.encode(&mut msg.inline_data);
+    msg.inline_size = len as u32;
+    if sys_ipc_send(process.port, &msg) != 0 {
+        return Err(());
+    }
+
+    Ok(process)
+}
+
+/// Poll until the driver publishes its IPC port
+fn wait_for_port(pid: u64) -> Option<u64> {
+    let mut waited = 0;
+    while waited < PORT_WAIT_MS {
+        if let Ok(port) = syscalls::get_process_ipc_port(pid) {
+            return Some(port);
+        }
+        syscalls::sleep(PORT_POLL_MS);
+        waited += PORT_POLL_MS;
+    }
+    None
+}
+
+fn record(process: DriverProcess) -> Result<(), ()> {
+    unsafe {
+        let slot = DRIVER_PROCESSES.iter_mut().find(|p| p.is_none()).ok_or(())?;
+        *slot = Some(process);
+        Ok(())
+    }
+}
+
+fn set_port(pid: u64, port: u64) {
+    unsafe {
+        if let Some(p) = DRIVER_PROCESSES.iter_mut().flatten().find(|p| p.pid == pid) {
+            p.port = port;
+        }
+    }
+}
+
+/// Look up a spawned driver by pid
+pub fn find_driver_process(pid: u64) -> Option<DriverProcess> {
+    unsafe { DRIVER_PROCESSES.iter().flatten().find(|p| p.pid == pid).copied() }
+}
+
+/// Forget a driver that has exited, returning what it was driving
+pub fn driver_exited(pid: u64) -> Option<DriverProcess> {
+    unsafe {
+        let slot = DRIVER_PROCESSES.iter_mut().find(|p| matches!(p, Some(d) if d.pid == pid))?;
+        slot.take()
+    }
 }
 
 /// System call wrappers
-#[allow(dead_code)]
 mod syscalls {
-    /// Spawn a new process
-    /// Returns process ID on success, 0 on failure
-    pub fn spawn_process(name: *const u8, path: *const u8, entry_point: u64) -> Result<u64, ()> {
-        const SYS_SPAWN_PROCESS: u64 = 45;
-        let pid = unsafe { syscall_raw(SYS_SPAWN_PROCESS, name as u64, path as u64, entry_point, 0, 0) };
-        if pid == 0 {
+    const SYS_EXIT: u64 = 0;
+    const SYS_SLEEP: u64 = 5;
+    const SYS_FORK: u64 = 15;
+    const SYS_EXEC: u64 = 16;
+    const SYS_GET_PROCESS_IPC_PORT: u64 = 46;
+
+    /// Returns 0 in the child and the child's pid in the parent
+    pub fn fork() -> Result<u64, ()> {
+        let pid = unsafe { syscall_raw(SYS_FORK, 0, 0, 0, 0, 0) };
+        if pid > 0xFFFF_FFFF_FFFF_F000 {
+            // Error codes come back as negative values
             Err(())
         } else {
             Ok(pid)
         }
     }
-    
+
+    pub fn exec(path: *const u8, argv: *const *const u8, envp: *const *const u8) {
+        unsafe {
+            syscall_raw(SYS_EXEC, path as u64, argv as u64, envp as u64, 0, 0);
+        }
+    }
+
+    pub fn exit(code: u64) -> ! {
+        unsafe {
+            syscall_raw(SYS_EXIT, code, 0, 0, 0, 0);
+        }
+        loop {}
+    }
+
+    pub fn sleep(ms: u64) {
+        unsafe {
+            syscall_raw(SYS_SLEEP, ms, 0, 0, 0, 0);
+        }
+    }
+
     /// Get IPC port of a process
     /// Returns IPC port on success, 0 on failure
     pub fn get_process_ipc_port(pid: u64) -> Result<u64, ()> {
-        const SYS_GET_PROCESS_IPC_PORT: u64 = 46;
         let port = unsafe { syscall_raw(SYS_GET_PROCESS_IPC_PORT, pid, 0, 0, 0, 0) };
         if port == 0 {
             Err(())
@@ -59,28 +154,27 @@ mod syscalls {
             Ok(port)
         }
     }
-    
+
     #[cfg(target_arch = "x86_64")]
     unsafe fn syscall_raw(num: u64, arg1: u64, arg2: u64, arg3: u64, arg4: u64, arg5: u64) -> u64 {
         let ret: u64;
         core::arch::asm!(
             "syscall",
-            in("rax") num,
+            inlateout("rax") num => ret,
             in("rdi") arg1,
             in("rsi") arg2,
             in("rdx") arg3,
             in("r10") arg4,
             in("r8") arg5,
-            out("rax") ret,
+            lateout("rcx") _,
+            lateout("r11") _,
             options(nostack, preserves_flags)
         );
         ret
     }
-    
+
     #[cfg(not(target_arch = "x86_64"))]
     unsafe fn syscall_raw(_num: u64, _arg1: u64, _arg2: u64, _arg3: u64, _arg4: u64, _arg5: u64) -> u64 {
         0
     }
 }
-
-
@@ -1,6 +1,7 @@
 //! Service registry for driver-to-service connections
 
 use crate::ipc::{IpcMessage, ipc_send, ipc_receive};
+use crate::name_registry::NameRegistry;
 
 /// Service types
 #[derive(Clone, Copy)]
@@ -20,6 +21,9 @@ pub struct ServiceRegistration {
 static mut REGISTRY: [Option<ServiceRegistration>; 16] = [None; 16];
 static mut REGISTRY_COUNT: usize = 0;
 
+/// Services registered by name
+static mut NAMED: NameRegistry = NameRegistry::new();
+
 /// Register a service with a driver
 pub fn register_service(service_type: ServiceType, service_port: u64, driver_port: u64) -> Result<(), ()> {
     unsafe {
@@ -89,3 +93,13 @@ pub fn register_service_port(service_type: ServiceType, service_port: u64) -> Re
     }
 }
 
+/// Register a service's port under its name; a restarted service
+/// registering again replaces its old port
+pub fn register_named(name: &str, port: u64) -> Result<(), ()> {
+    unsafe { NAMED.register(name.as_bytes(), port) }
+}
+
+/// Port of the service registered as `name`
+pub fn lookup_named(name: &str) -> Option<u64> {
+    unsafe { NAMED.lookup(name.as_bytes()) }
+}
@@ -2,25 +2,39 @@
 
 pub const IPC_MSG_REQUEST: u32 = 1;
 pub const IPC_MSG_RESPONSE: u32 = 2;
+pub const IPC_MSG_NOTIFICATION: u32 = 3;
 
+/// Port init receives readiness reports on
+pub const INIT_PORT: u32 = 1;
+/// Readiness report; inline data is the service's name
+pub const INIT_MSG_SERVICE_READY: u64 = 1;
+
+/// IPC message structure (must match kernel/include/ipc/ipc.h)
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct IpcMessage {
+    pub sender_tid: u64,
+    pub msg_id: u64,
     pub msg_type: u32,
-    pub msg_id: u32,
-    pub sender_tid: u32,
     pub inline_size: u32,
-    pub inline_data: [u8; 128],
+    pub inline_data: [u8; 64],
+    pub buffer: *mut u8,
+    pub buffer_size: usize,
+    /// Port responses go to; 0 if the sender expects none
+    pub reply_port: u64,
 }
 
 impl IpcMessage {
     pub fn new() -> Self {
         IpcMessage {
-            msg_type: 0,
-            msg_id: 0,
             sender_tid: 0,
+            msg_id: 0,
+            msg_type: 0,
             inline_size: 0,
-            inline_data: [0; 128],
+            inline_data: [0; 64],
+            buffer: core::ptr::null_mut(),
+            buffer_size: 0,
+            reply_port: 0,
         }
     }
 }
@@ -29,7 +43,10 @@ impl IpcMessage {
 extern "C" {
     fn syscall_ipc_send(port: u32, msg: *const IpcMessage) -> i32;
     fn syscall_ipc_receive(port: u32, msg: *mut IpcMessage) -> i32;
+    fn syscall_ipc_receive_timeout(port: u32, msg: *mut IpcMessage, timeout_ms: u64) -> i32;
+    fn syscall_ipc_reply(request: *const IpcMessage, response: *const IpcMessage) -> i32;
     fn syscall_ipc_register_port(port: u32) -> i32;
+    fn syscall_get_uptime_ms() -> u64;
 }
 
 pub fn sys_ipc_send(port: u32, msg: &IpcMessage) -> i32 {
@@ -40,6 +57,30 @@ pub fn sys_ipc_receive(port: u32, msg: &mut IpcMessage) -> i32 {
     unsafe { syscall_ipc_receive(port, msg as *mut IpcMessage) }
 }
 
+pub fn sys_ipc_receive_timeout(port: u32, msg: &mut IpcMessage, timeout_ms: u64) -> i32 {
+    unsafe { syscall_ipc_receive_timeout(port, msg as *mut IpcMessage, timeout_ms) }
+}
+
+pub fn sys_ipc_reply(request: &IpcMessage, response: &IpcMessage) -> i32 {
+    unsafe { syscall_ipc_reply(request as *const IpcMessage, response as *const IpcMessage) }
+}
+
 pub fn sys_ipc_register_port(port: u32) -> i32 {
     unsafe { syscall_ipc_register_port(port) }
 }
+
+/// Tell init this service is taking requests, so it can start the
+/// services that depend on it
+pub fn notify_init_ready(name: &[u8]) {
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_NOTIFICATION;
+    msg.msg_id = INIT_MSG_SERVICE_READY;
+    let len = name.len().min(msg.inline_data.len());
+    msg.inline_data[..len].copy_from_slice(&name[..len]);
+    msg.inline_size = len as u32;
+    let _ = sys_ipc_send(INIT_PORT, &msg);
+}
+
+pub fn sys_get_uptime_ms() -> u64 {
+    unsafe { syscall_get_uptime_ms() }
+}
@@ -16,17 +16,35 @@ use alloc::string::String;
 use core::panic::PanicInfo;
 
 mod ipc;
-use ipc::{IpcMessage, sys_ipc_receive, sys_ipc_send, sys_ipc_register_port};
+mod pending;
+mod restart;
+use ipc::{IpcMessage, sys_ipc_receive_timeout, sys_ipc_send, sys_ipc_reply, sys_ipc_register_port, sys_get_uptime_ms, notify_init_ready};
+use pending::PendingRequests;
+use restart::{CrashAction, RestartTracker};
 
 // Driver Manager IPC port
 const DRIVER_MANAGER_PORT: u32 = 100;
 
 // Message types
-const MSG_REGISTER_DRIVER: u32 = 1;
-const MSG_UNREGISTER_DRIVER: u32 = 2;
-const MSG_DEVICE_REQUEST: u32 = 3;
-const MSG_ENUMERATE_DEVICES: u32 = 4;
-const MSG_DRIVER_CRASHED: u32 = 5;
+const MSG_REGISTER_DRIVER: u64 = 1;
+const MSG_UNREGISTER_DRIVER: u64 = 2;
+/// Forwarded to the driver with a transaction id as msg_id; the driver's
+/// response comes back to this port and is relayed to the client
+const MSG_DEVICE_REQUEST: u64 = 3;
+const MSG_ENUMERATE_DEVICES: u64 = 4;
+const MSG_DRIVER_CRASHED: u64 = 5;
+const MSG_ENUMERATE_UNMANAGED: u64 = 7;
+
+/// Sent to a restarted driver for each device it should probe again:
+/// [device_id u32, vendor_id u16, device_id u16]
+const MSG_ATTACH_DEVICE: u64 = 8;
+
+// Process manager restart request
+const PROCESS_MANAGER_PORT: u32 = 101;
+const PM_MSG_RESTART_PROCESS: u64 = 1;
+
+/// Longest the main loop blocks before checking pending restarts
+const TICK_MS: u64 = 100;
 
 // Driver types
 #[derive(Clone, Copy, PartialEq)]
@@ -46,7 +64,12 @@ enum DriverType {
 enum DriverState {
     Registered,
     Running,
+    /// Waiting out the restart backoff
     Crashed,
+    /// Restart requested; waiting for the driver to register again
+    Restarting,
+    /// Retry budget exhausted; devices are unmanaged
+    Failed,
     Stopped,
 }
 
@@ -55,9 +78,21 @@ struct RegisteredDriver {
     driver_id: u32,
     driver_type: DriverType,
     driver_port: u32,
-    driver_pid: u32,
+    driver_pid: u64,
     state: DriverState,
-    crash_count: u32,
+    restart: RestartTracker,
+    /// Uptime at which a crashed driver is restarted
+    restart_at_ms: u64,
+}
+
+// Device binding state
+#[derive(Clone, Copy, PartialEq)]
+enum DeviceState {
+    Attached,
+    /// Driver is down; reattached when it comes back
+    Detached,
+    /// Driver failed permanently
+    Unmanaged,
 }
 
 // Device information
@@ -67,12 +102,14 @@ struct Device {
     device_type: DriverType,
     vendor_id: u16,
     device_id_hw: u16,
+    state: DeviceState,
 }
 
 // Driver Manager state
 struct DriverManager {
     drivers: Vec<RegisteredDriver>,
     devices: Vec<Device>,
+    pending: PendingRequests,
     next_driver_id: u32,
     next_device_id: u32,
 }
@@ -82,12 +119,27 @@ impl DriverManager {
         DriverManager {
             drivers: Vec::new(),
             devices: Vec::new(),
+            pending: PendingRequests::new(),
             next_driver_id: 1,
             next_device_id: 1,
         }
     }
 
-    fn register_driver(&mut self, driver_type: DriverType, driver_port: u32, driver_pid: u32) -> u32 {
+    fn register_driver(&mut self, driver_type: DriverType, driver_port: u32, driver_pid: u64, now_ms: u64) -> u32 {
+        // A restarted driver takes over its old identity and devices
+        if let Some(driver) = self.drivers.iter_mut()
+            .find(|d| d.driver_type == driver_type && d.state == DriverState::Restarting)
+        {
+            driver.driver_port = driver_port;
+            driver.driver_pid = driver_pid;
+            driver.state = DriverState::Running;
+            driver.restart.started(now_ms);
+
+            let driver_id = driver.driver_id;
+            self.reattach_devices(driver_id, driver_port);
+            return driver_id;
+        }
+
         let driver_id = self.next_driver_id;
         self.next_driver_id += 1;
 
@@ -96,8 +148,9 @@ impl DriverManager {
             driver_type,
             driver_port,
             driver_pid,
-            state: DriverState::Registered,
-            crash_count: 0,
+            state: DriverState::Running,
+            restart: RestartTracker::new(now_ms),
+            restart_at_ms: 0,
         };
 
         self.drivers.push(driver);
@@ -107,6 +160,7 @@ impl DriverManager {
     fn unregister_driver(&mut self, driver_id: u32) -> bool {
         if let Some(pos) = self.drivers.iter().position(|d| d.driver_id == driver_id) {
             self.drivers.remove(pos);
+            self.fail_pending(driver_id);
             // Remove all devices associated with this driver
             self.devices.retain(|dev| dev.driver_id != driver_id);
             true
@@ -138,32 +192,109 @@ impl DriverManager {
             device_type,
             vendor_id,
             device_id_hw,
+            state: DeviceState::Attached,
         };
 
         self.devices.push(device);
         device_id
     }
 
-    fn handle_driver_crash(&mut self, driver_id: u32) {
-        if let Some(driver) = self.find_driver_by_id_mut(driver_id) {
-            driver.state = DriverState::Crashed;
-            driver.crash_count += 1;
-
-            // Auto-restart if crash count is below threshold
-            if driver.crash_count < 3 {
-                // Send restart request to process manager
-                // Assuming process manager port is 101 and message ID 1
-                let mut restart_msg = IpcMessage::new();
-                restart_msg.msg_type = ipc::IPC_MSG_REQUEST;
-                restart_msg.msg_id = 1; // PM_MSG_RESTART_PROCESS
-                restart_msg.inline_data[0..4].copy_from_slice(&driver.driver_pid.to_le_bytes()); // PID to restart
-                restart_msg.inline_size = 4;
-                let _ = sys_ipc_send(101, &restart_msg); // Assuming 101 is Process Manager's port
-                driver.state = DriverState::Registered; // Mark as registered for restart
+    /// Answer every client still waiting on a driver that will not respond
+    fn fail_pending(&mut self, driver_id: u32) {
+        while let Some(request) = self.pending.take_for_driver(driver_id) {
+            let mut response = IpcMessage::new();
+            response.msg_type = ipc::IPC_MSG_RESPONSE;
+            response.msg_id = request.client_msg_id;
+            response.inline_data[0] = 0xFF; // Error
+            response.inline_size = 1;
+            let _ = sys_ipc_send(request.client_port as u32, &response);
+        }
+    }
+
+    /// Relay a driver's response to the client that made the request
+    fn complete_device_request(&mut self, msg: &IpcMessage) {
+        if let Some(request) = self.pending.complete(msg.msg_id, msg.sender_tid) {
+            let mut response = *msg;
+            response.msg_id = request.client_msg_id;
+            let _ = sys_ipc_send(request.client_port as u32, &response);
+        }
+    }
+
+    fn handle_driver_crash(&mut self, driver_id: u32, now_ms: u64) {
+        self.fail_pending(driver_id);
+
+        let action = match self.find_driver_by_id_mut(driver_id) {
+            Some(driver) if driver.state != DriverState::Failed => {
+                let action = driver.restart.crashed(now_ms);
+                match action {
+                    CrashAction::RestartAt(at_ms) => {
+                        driver.state = DriverState::Crashed;
+                        driver.restart_at_ms = at_ms;
+                    }
+                    CrashAction::GiveUp => driver.state = DriverState::Failed,
+                }
+                action
             }
+            _ => return,
+        };
+
+        let device_state = match action {
+            CrashAction::RestartAt(_) => DeviceState::Detached,
+            CrashAction::GiveUp => DeviceState::Unmanaged,
+        };
+        for device in self.devices.iter_mut().filter(|dev| dev.driver_id == driver_id) {
+            device.state = device_state;
+        }
+    }
+
+    /// Restart drivers whose backoff has elapsed and forgive crashes of
+    /// drivers that have been stable
+    fn tick(&mut self, now_ms: u64) {
+        for driver in self.drivers.iter_mut() {
+            match driver.state {
+                DriverState::Crashed if now_ms >= driver.restart_at_ms => {
+                    let mut restart_msg = IpcMessage::new();
+                    restart_msg.msg_type = ipc::IPC_MSG_REQUEST;
+                    restart_msg.msg_id = PM_MSG_RESTART_PROCESS;
+                    restart_msg.inline_data[0..4].copy_from_slice(&(driver.driver_pid as u32).to_le_bytes()); // PID to restart
+                    restart_msg.inline_size = 4;
+
+                    // Retried on the next tick if the process manager is busy
+                    if sys_ipc_send(PROCESS_MANAGER_PORT, &restart_msg) == 0 {
+                        driver.state = DriverState::Restarting;
+                    }
+                }
+                DriverState::Running => driver.restart.cool_down(now_ms),
+                _ => {}
+            }
+        }
+    }
+
+    /// Rebind a restarted driver's devices and ask it to probe each one
+    fn reattach_devices(&mut self, driver_id: u32, driver_port: u32) {
+        for device in self.devices.iter_mut()
+            .filter(|dev| dev.driver_id == driver_id && dev.state == DeviceState::Detached)
+        {
+            let mut attach_msg = IpcMessage::new();
+            attach_msg.msg_type = ipc::IPC_MSG_REQUEST;
+            attach_msg.msg_id = MSG_ATTACH_DEVICE;
+            attach_msg.inline_data[0..4].copy_from_slice(&device.device_id.to_le_bytes());
+            attach_msg.inline_data[4..6].copy_from_slice(&device.vendor_id.to_le_bytes());
+            attach_msg.inline_data[6..8].copy_from_slice(&device.device_id_hw.to_le_bytes());
+            attach_msg.inline_size = 8;
+            let _ = sys_ipc_send(driver_port, &attach_msg);
+
+            device.state = DeviceState::Attached;
         }
     }
 
+    fn unmanaged_devices(&self) -> Vec<u32> {
+        self.devices.iter()
+            .filter(|dev| dev.state == DeviceState::Unmanaged)
+            .map(|dev| dev.device_id)
+            .collect()
+    }
+
     fn enumerate_devices(&self, device_type: DriverType) -> Vec<u32> {
         self.devices.iter()
             .filter(|dev| dev.device_type == device_type)
@@ -190,23 +321,39 @@ fn driver_manager_init() {
         // Failed to register port - panic
         loop {}
     }
+
+    notify_init_ready(b"driver_manager");
 }
 
 fn driver_manager_loop() -> ! {
     let mut msg = IpcMessage::new();
 
     loop {
-        // Receive IPC message
-        if sys_ipc_receive(DRIVER_MANAGER_PORT, &mut msg) == 0 {
-            let response = handle_message(&msg);
-            
-            // Send response back to sender
-            let _ = sys_ipc_send(msg.sender_tid, &response);
+        // Receive IPC message, waking periodically for pending restarts
+        if sys_ipc_receive_timeout(DRIVER_MANAGER_PORT, &mut msg, TICK_MS) == 0 {
+            if msg.msg_type == ipc::IPC_MSG_RESPONSE {
+                // Driver answering a forwarded device request
+                unsafe {
+                    if let Some(ref mut manager) = DRIVER_MANAGER {
+                        manager.complete_device_request(&msg);
+                    }
+                }
+            } else if let Some(response) = handle_message(&msg) {
+                // Send response back on the sender's reply port; forwarded
+                // device requests are answered when the driver responds
+                let _ = sys_ipc_reply(&msg, &response);
+            }
+        }
+
+        unsafe {
+            if let Some(ref mut manager) = DRIVER_MANAGER {
+                manager.tick(sys_get_uptime_ms());
+            }
         }
     }
 }
 
-fn handle_message(msg: &IpcMessage) -> IpcMessage {
+fn handle_message(msg: &IpcMessage) -> Option<IpcMessage> {
     let mut response = IpcMessage::new();
     response.msg_type = ipc::IPC_MSG_RESPONSE;
     response.msg_id = msg.msg_id;
@@ -233,7 +380,7 @@ fn handle_message(msg: &IpcMessage) -> IpcMessage {
                     ]);
                     let driver_pid = msg.sender_tid;
 
-                    let driver_id = manager.register_driver(driver_type, driver_port, driver_pid);
+                    let driver_id = manager.register_driver(driver_type, driver_port, driver_pid, sys_get_uptime_ms());
 
                     // Return driver ID
                     response.inline_data[0..4].copy_from_slice(&driver_id.to_le_bytes());
@@ -263,20 +410,30 @@ fn handle_message(msg: &IpcMessage) -> IpcMessage {
                         _ => DriverType::Unknown,
                     };
 
-                    if let Some(driver) = manager.find_driver_by_type(device_type) {
-                        // Forward request to driver
-                        let mut fwd_msg = *msg;
-                        if sys_ipc_send(driver.driver_port, &fwd_msg) == 0 {
-                            // Wait for driver response
-                            if sys_ipc_receive(DRIVER_MANAGER_PORT, &mut response) == 0 {
-                                // Return driver response
-                            } else {
-                                response.inline_data[0] = 0xFF; // Error
+                    let target = manager.find_driver_by_type(device_type)
+                        .map(|d| (d.driver_id, d.driver_pid, d.driver_port));
+
+                    if let Some((driver_id, driver_tid, driver_port)) = target {
+                        match manager.pending.begin(driver_id, driver_tid, msg.reply_port, msg.msg_id) {
+                            Some(transaction_id) => {
+                                // Forward request to driver; it replies to us
+                                let mut fwd_msg = *msg;
+                                fwd_msg.msg_type = ipc::IPC_MSG_REQUEST;
+                                fwd_msg.msg_id = transaction_id;
+                                fwd_msg.reply_port = DRIVER_MANAGER_PORT as u64;
+
+                                if sys_ipc_send(driver_port, &fwd_msg) == 0 {
+                                    return None;
+                                }
+
+                                manager.pending.complete(transaction_id, driver_tid);
+                                response.inline_data[0] = 0xFE; // Forward failed
+                                response.inline_size = 1;
+                            }
+                            None => {
+                                response.inline_data[0] = 0xFB; // Too many requests in flight
                                 response.inline_size = 1;
                             }
-                        } else {
-                            response.inline_data[0] = 0xFE; // Forward failed
-                            response.inline_size = 1;
                         }
                     } else {
                         response.inline_data[0] = 0xFD; // No driver found
@@ -294,7 +451,19 @@ fn handle_message(msg: &IpcMessage) -> IpcMessage {
                     };
 
                     let devices = manager.enumerate_devices(device_type);
-                    let count = devices.len().min(16); // Max 16 devices in response
+                    let count = devices.len().min((response.inline_data.len() - 1) / 4); // As many as fit inline
+
+                    response.inline_data[0] = count as u8;
+                    for (i, &device_id) in devices.iter().take(count).enumerate() {
+                        let offset = 1 + i * 4;
+                        response.inline_data[offset..offset + 4].copy_from_slice(&device_id.to_le_bytes());
+                    }
+                    response.inline_size = 1 + (count * 4) as u32;
+                }
+
+                MSG_ENUMERATE_UNMANAGED => {
+                    let devices = manager.unmanaged_devices();
+                    let count = devices.len().min((response.inline_data.len() - 1) / 4); // As many as fit inline
 
                     response.inline_data[0] = count as u8;
                     for (i, &device_id) in devices.iter().take(count).enumerate() {
@@ -312,7 +481,7 @@ fn handle_message(msg: &IpcMessage) -> IpcMessage {
                         msg.inline_data[3],
                     ]);
 
-                    manager.handle_driver_crash(driver_id);
+                    manager.handle_driver_crash(driver_id, sys_get_uptime_ms());
                     response.inline_data[0] = 1; // Acknowledged
                     response.inline_size = 1;
                 }
@@ -330,7 +499,7 @@ fn handle_message(msg: &IpcMessage) -> IpcMessage {
         }
     }
 
-    response
+    Some(response)
 }
 
 #[panic_handler]
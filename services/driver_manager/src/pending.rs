@@ -0,0 +1,86 @@
+//! Outstanding device requests
+//!
+//! A MSG_DEVICE_REQUEST is forwarded to its driver with a fresh transaction
+//! id as the msg_id and the manager's port as the reply port. Drivers echo
+//! msg_id in their response, which this table maps back to the client that
+//! is waiting for it.
+
+/// Requests that may be in flight to drivers at once
+pub const MAX_PENDING_REQUESTS: usize = 32;
+
+/// A forwarded request waiting for its driver's response
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PendingRequest {
+    pub transaction_id: u64,
+    pub driver_id: u32,
+    /// Only this thread may complete the request
+    pub driver_tid: u64,
+    pub client_port: u64,
+    pub client_msg_id: u64,
+}
+
+pub struct PendingRequests {
+    entries: [Option<PendingRequest>; MAX_PENDING_REQUESTS],
+    next_transaction_id: u64,
+}
+
+impl PendingRequests {
+    pub const fn new() -> Self {
+        PendingRequests {
+            entries: [None; MAX_PENDING_REQUESTS],
+            next_transaction_id: 1,
+        }
+    }
+
+    /// Record a request about to be forwarded and return its transaction id,
+    /// or None if too many requests are outstanding
+    pub fn begin(&mut self, driver_id: u32, driver_tid: u64, client_port: u64, client_msg_id: u64) -> Option<u64> {
+        let slot = self.entries.iter().position(|e| e.is_none())?;
+        let transaction_id = self.allocate_id();
+
+        self.entries[slot] = Some(PendingRequest {
+            transaction_id,
+            driver_id,
+            driver_tid,
+            client_port,
+            client_msg_id,
+        });
+
+        Some(transaction_id)
+    }
+
+    /// Match a driver response to its request. Unknown ids and responses
+    /// from a thread other than the driver's are ignored.
+    pub fn complete(&mut self, transaction_id: u64, sender_tid: u64) -> Option<PendingRequest> {
+        let slot = self.entries.iter().position(|e| {
+            matches!(e, Some(p) if p.transaction_id == transaction_id && p.driver_tid == sender_tid)
+        })?;
+        self.entries[slot].take()
+    }
+
+    /// Remove one request outstanding on a driver; call until None to fail
+    /// everything a crashed or unregistered driver will never answer
+    pub fn take_for_driver(&mut self, driver_id: u32) -> Option<PendingRequest> {
+        let slot = self.entries.iter().position(|e| matches!(e, Some(p) if p.driver_id == driver_id))?;
+        self.entries[slot].take()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.iter().filter(|e| e.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Next id that is nonzero and not already in flight
+    fn allocate_id(&mut self) -> u64 {
+        loop {
+            let id = self.next_transaction_id;
+            self.next_transaction_id = self.next_transaction_id.wrapping_add(1);
+            if id != 0 && !self.entries.iter().any(|e| matches!(e, Some(p) if p.transaction_id == id)) {
+                return id;
+            }
+        }
+    }
+}
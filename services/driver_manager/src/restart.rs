@@ -0,0 +1,77 @@
+//! Driver restart policy
+//!
+//! Restarts back off exponentially so a driver that dies on startup does
+//! not thrash, and the crash count is forgiven once a driver has stayed up
+//! for a while. Past the retry budget the driver is given up on.
+
+/// Restarts allowed before a driver is marked permanently failed
+pub const MAX_RESTARTS: u32 = 3;
+
+/// Delay before the first restart; doubles with every further crash
+pub const BASE_BACKOFF_MS: u64 = 500;
+pub const MAX_BACKOFF_MS: u64 = 30_000;
+
+/// Uptime after which earlier crashes no longer count
+pub const STABLE_PERIOD_MS: u64 = 60_000;
+
+/// Delay before restarting after the given number of consecutive crashes
+pub fn backoff_ms(crash_count: u32) -> u64 {
+    if crash_count == 0 {
+        return 0;
+    }
+    let shift = (crash_count - 1).min(16);
+    (BASE_BACKOFF_MS << shift).min(MAX_BACKOFF_MS)
+}
+
+/// What to do about a crash
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrashAction {
+    /// Ask for a restart once uptime reaches this time
+    RestartAt(u64),
+    /// Retry budget exhausted
+    GiveUp,
+}
+
+/// Per-driver crash history
+#[derive(Clone, Copy, Debug)]
+pub struct RestartTracker {
+    crash_count: u32,
+    running_since_ms: u64,
+}
+
+impl RestartTracker {
+    pub fn new(now_ms: u64) -> Self {
+        RestartTracker {
+            crash_count: 0,
+            running_since_ms: now_ms,
+        }
+    }
+
+    pub fn crash_count(&self) -> u32 {
+        self.crash_count
+    }
+
+    /// The driver came (back) up
+    pub fn started(&mut self, now_ms: u64) {
+        self.running_since_ms = now_ms;
+    }
+
+    /// Forgive earlier crashes once the driver has run for a stable period
+    pub fn cool_down(&mut self, now_ms: u64) {
+        if self.crash_count > 0 && now_ms.saturating_sub(self.running_since_ms) >= STABLE_PERIOD_MS {
+            self.crash_count = 0;
+        }
+    }
+
+    pub fn crashed(&mut self, now_ms: u64) -> CrashAction {
+        // A crash after a long stable run starts a fresh budget
+        self.cool_down(now_ms);
+        self.crash_count += 1;
+
+        if self.crash_count > MAX_RESTARTS {
+            CrashAction::GiveUp
+        } else {
+            CrashAction::RestartAt(now_ms + backoff_ms(self.crash_count))
+        }
+    }
+}
@@ -1,43 +1,32 @@
-/**
- * @file init.rs
- * @brief User-space init process - launches all system services and desktop
- */
+//! Init Process
+//!
+//! User-space init process - launches all system services and desktop
+
+#![no_std]
+#![no_main]
+
+mod ipc;
+mod readiness;
+mod restart;
+mod service_manager;
+mod service_startup;
 
 use core::panic::PanicInfo;
+use service_startup::{handle_child_exit, init_readiness, start_core_services, wait_for_dependencies, LOGIN_DEPENDENCIES};
 
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
     loop {}
 }
 
-// IPC syscall wrappers
+// Process syscall wrappers
 extern "C" {
-    fn sys_ipc_send(tid: u32, msg: *const IpcMessage) -> i32;
-    fn sys_ipc_receive(port: u32, msg: *mut IpcMessage) -> i32;
     fn sys_exec(path: *const u8, args: *const *const u8) -> i32;
     fn sys_fork() -> i32;
-    fn sys_wait(pid: i32) -> i32;
+    fn sys_wait(pid: i32, status: *mut i32) -> i32;
     fn sys_exit(code: i32) -> !;
 }
 
-#[repr(C)]
-struct IpcMessage {
-    sender_tid: u32,
-    msg_type: u32,
-    data: [u8; 256],
-}
-
-// Service launch order
-const SERVICES: &[&str] = &[
-    "/sbin/driver_manager",     // 1. Driver manager (manages all drivers)
-    "/sbin/vfs",                // 2. Virtual File System
-    "/sbin/security",           // 3. Security service (capabilities, ACL)
-    "/sbin/network",            // 4. Network stack
-    "/sbin/audio",              // 5. Audio server
-    "/sbin/compositor",         // 6. Display compositor
-    "/sbin/window_manager",     // 7. Window manager
-];
-
 // User applications (launched after services)
 const APPS: &[&str] = &[
     "/bin/login",               // Login manager (first user app)
@@ -47,37 +36,24 @@ const APPS: &[&str] = &[
 pub extern "C" fn _start() -> ! {
     // Init process (PID 1) - runs in Ring 3
     
-    // Phase 1: Launch system services
-    launch_services();
+    // Phase 1: Launch system services, each after its dependencies are up
+    init_readiness();
+    start_core_services();
     
     // Phase 2: Launch login manager
-    launch_login();
+    let login_pid = launch_login();
     
     // Phase 3: Wait for login, then launch desktop
-    wait_for_login_and_launch_desktop();
+    wait_for_login_and_launch_desktop(login_pid);
     
     // Phase 4: Reap zombie processes
     reaper_loop();
 }
 
-fn launch_services() {
-    for service in SERVICES {
-        let pid = unsafe { sys_fork() };
-        
-        if pid == 0 {
-            // Child process - exec the service
-            let args: [*const u8; 1] = [core::ptr::null()];
-            unsafe {
-                sys_exec(service.as_ptr(), args.as_ptr());
-            }
-            // If exec fails, exit
-            unsafe { sys_exit(1); }
-        }
-        // Parent continues to launch next service
-    }
-}
+fn launch_login() -> i32 {
+    // Login still comes up if a service failed; the failure has been logged
+    let _ = wait_for_dependencies(LOGIN_DEPENDENCIES);
 
-fn launch_login() {
     let pid = unsafe { sys_fork() };
     
     if pid == 0 {
@@ -88,13 +64,19 @@ fn launch_login() {
             sys_exit(1);
         }
     }
+    pid
 }
 
-fn wait_for_login_and_launch_desktop() {
+fn wait_for_login_and_launch_desktop(login_pid: i32) {
     // Wait for login manager to signal successful login
-    // For now, just wait for login process to exit
-    unsafe {
-        sys_wait(-1);  // Wait for any child
+    // For now, just wait for login process to exit, restarting any
+    // service that crashes meanwhile
+    loop {
+        let (pid, status) = wait_child();
+        if pid < 0 || pid == login_pid {
+            break;
+        }
+        handle_child_exit(pid as u64, status);
     }
     
     // Launch desktop environment
@@ -113,9 +95,17 @@ fn wait_for_login_and_launch_desktop() {
 fn reaper_loop() -> ! {
     // Init process must reap zombie processes
     loop {
-        unsafe {
-            sys_wait(-1);  // Wait for any child to exit
+        let (pid, status) = wait_child();
+        if pid > 0 {
+            handle_child_exit(pid as u64, status);
         }
         // Child exited, loop to wait for next one
     }
 }
+
+/// Wait for any child to exit; returns its pid and exit code
+fn wait_child() -> (i32, i32) {
+    let mut status = 0;
+    let pid = unsafe { sys_wait(-1, &mut status) };
+    (pid, status)
+}
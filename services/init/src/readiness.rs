@@ -0,0 +1,57 @@
+//! Service readiness protocol
+//!
+//! A service that others depend on sends INIT_MSG_SERVICE_READY to
+//! INIT_PORT once it is taking requests, with its name as the inline data.
+//! Init holds back a service until everything it depends on has reported
+//! in.
+
+use crate::service_manager::ServiceStatus;
+
+/// Well-known port init receives readiness reports on
+pub const INIT_PORT: u64 = 1;
+/// Readiness report; inline data is the service's name
+pub const INIT_MSG_SERVICE_READY: u64 = 1;
+/// How long a service has to report in once started
+pub const READY_TIMEOUT_MS: u64 = 5000;
+
+/// Where a service's dependencies stand
+#[derive(Debug, PartialEq, Eq)]
+pub enum DependencyState<'a> {
+    /// All of them are running
+    Ready,
+    /// This one has not reported in yet
+    Waiting(&'a [u8]),
+    /// This one failed or was never started, so it never will
+    Failed(&'a [u8]),
+}
+
+/// Name of the service a readiness report came from, without padding
+pub fn ready_name(inline: &[u8]) -> Option<&[u8]> {
+    let len = inline.iter().position(|&b| b == 0).unwrap_or(inline.len());
+    if len == 0 {
+        None
+    } else {
+        Some(&inline[..len])
+    }
+}
+
+/// Check `deps` against the status `status_of` reports for each; a failed
+/// dependency wins over one still starting
+pub fn check_dependencies<'a, F>(deps: &[&'a [u8]], status_of: F) -> DependencyState<'a>
+where
+    F: Fn(&[u8]) -> Option<ServiceStatus>,
+{
+    let mut state = DependencyState::Ready;
+    for dep in deps {
+        match status_of(dep) {
+            Some(ServiceStatus::Running) => {}
+            Some(ServiceStatus::Starting) => {
+                if state == DependencyState::Ready {
+                    state = DependencyState::Waiting(dep);
+                }
+            }
+            _ => return DependencyState::Failed(dep),
+        }
+    }
+    state
+}
@@ -0,0 +1,74 @@
+//! Service restart policy
+//!
+//! A service that exits with a nonzero code is treated as crashed and
+//! restarted; exit code 0 is a deliberate shutdown and is left alone.
+//! Restarts back off exponentially, crashes are forgiven after a stable
+//! run, and past the retry budget the service is given up on.
+
+/// Restarts allowed before a service is marked permanently failed
+pub const MAX_RESTARTS: u32 = 3;
+
+/// Delay before the first restart; doubles with every further crash
+pub const BASE_BACKOFF_MS: u64 = 500;
+pub const MAX_BACKOFF_MS: u64 = 30_000;
+
+/// Uptime after which earlier crashes no longer count
+pub const STABLE_PERIOD_MS: u64 = 60_000;
+
+/// Whether an exit code reports a crash rather than a deliberate exit
+pub fn is_crash(exit_code: i32) -> bool {
+    exit_code != 0
+}
+
+/// Delay before restarting after the given number of consecutive crashes
+pub fn backoff_ms(crash_count: u32) -> u64 {
+    if crash_count == 0 {
+        return 0;
+    }
+    let shift = (crash_count - 1).min(16);
+    (BASE_BACKOFF_MS << shift).min(MAX_BACKOFF_MS)
+}
+
+/// What to do about a crash
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrashAction {
+    /// Restart once uptime reaches this time
+    RestartAt(u64),
+    /// Retry budget exhausted
+    GiveUp,
+}
+
+/// Per-service crash history
+#[derive(Clone, Copy, Debug)]
+pub struct RestartTracker {
+    crash_count: u32,
+    running_since_ms: u64,
+}
+
+impl RestartTracker {
+    pub const fn new(now_ms: u64) -> Self {
+        RestartTracker {
+            crash_count: 0,
+            running_since_ms: now_ms,
+        }
+    }
+
+    /// The service came (back) up
+    pub fn started(&mut self, now_ms: u64) {
+        self.running_since_ms = now_ms;
+    }
+
+    pub fn crashed(&mut self, now_ms: u64) -> CrashAction {
+        // A crash after a long stable run starts a fresh budget
+        if now_ms.saturating_sub(self.running_since_ms) >= STABLE_PERIOD_MS {
+            self.crash_count = 0;
+        }
+        self.crash_count += 1;
+
+        if self.crash_count > MAX_RESTARTS {
+            CrashAction::GiveUp
+        } else {
+            CrashAction::RestartAt(now_ms + backoff_ms(self.crash_count))
+        }
+    }
+}
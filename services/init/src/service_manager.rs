@@ -12,6 +12,7 @@ pub struct ServiceInfo {
 }
 
 #[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ServiceStatus {
     Stopped = 0,
     Starting = 1,
@@ -47,7 +48,8 @@ pub fn register_service(name: &[u8], pid: u64, port: u64) -> Result<usize, ()> {
         
         service.pid = pid;
         service.port = port;
-        service.status = ServiceStatus::Running;
+        // Running once the service reports itself ready
+        service.status = ServiceStatus::Starting;
         
         let idx = SERVICE_COUNT;
         SERVICE_COUNT += 1;
@@ -56,12 +58,18 @@ pub fn register_service(name: &[u8], pid: u64, port: u64) -> Result<usize, ()> {
     }
 }
 
-/// Find service by name
+/// Name without its NUL terminator
+fn trim_name(name: &[u8]) -> &[u8] {
+    let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    &name[..len]
+}
+
+/// Find service by name, with or without a NUL terminator
 pub fn find_service(name: &[u8]) -> Option<usize> {
+    let name = trim_name(name);
     unsafe {
         for i in 0..SERVICE_COUNT {
-            let service = &SERVICES[i];
-            if service.name.starts_with(name) && service.name[name.len()] == 0 {
+            if trim_name(&SERVICES[i].name) == name {
                 return Some(i);
             }
         }
@@ -69,6 +77,37 @@ pub fn find_service(name: &[u8]) -> Option<usize> {
     }
 }
 
+/// Find the service running as `pid`
+pub fn find_service_by_pid(pid: u64) -> Option<usize> {
+    unsafe { (0..SERVICE_COUNT).find(|&i| SERVICES[i].pid == pid) }
+}
+
+/// Record the process a restarted service now runs as
+pub fn set_service_pid(service_idx: usize, pid: u64) {
+    unsafe {
+        if service_idx < SERVICE_COUNT {
+            SERVICES[service_idx].pid = pid;
+        }
+    }
+}
+
+/// Status of a service by name; None if it was never registered
+pub fn service_status(name: &[u8]) -> Option<ServiceStatus> {
+    find_service(name).map(|idx| unsafe { SERVICES[idx].status })
+}
+
+/// Record that a starting service reported itself ready. Reports from
+/// unknown or failed services are ignored.
+pub fn mark_ready(name: &[u8]) -> bool {
+    match find_service(name) {
+        Some(idx) if unsafe { SERVICES[idx].status == ServiceStatus::Starting } => {
+            set_service_status(idx, ServiceStatus::Running);
+            true
+        }
+        _ => false,
+    }
+}
+
 /// Get service port
 pub fn get_service_port(service_idx: usize) -> Option<u64> {
     unsafe {
@@ -1,14 +1,16 @@
 //! Service startup and management
 
-use crate::service_manager::{register_service, find_service, get_service_port, set_service_status, ServiceStatus};
-use core::mem;
+use crate::ipc::{IpcMessage, sys_ipc_receive_timeout, sys_ipc_register_port};
+use crate::readiness::{check_dependencies, ready_name, DependencyState, INIT_MSG_SERVICE_READY, INIT_PORT, READY_TIMEOUT_MS};
+use crate::restart::{is_crash, CrashAction, RestartTracker};
+use crate::service_manager::{register_service, find_service, find_service_by_pid, mark_ready, service_status, set_service_pid, set_service_status, ServiceStatus};
 
 // Syscall numbers (assuming these are globally available or defined in a common header)
 const SYS_FORK: u64 = 15;
 const SYS_EXEC: u64 = 16;
 const SYS_EXIT: u64 = 0;
-const SYS_YIELD: u64 = 6;
-const SYS_SLEEP: u64 = 5; // Added for timeout
+const SYS_WRITE: u64 = 1;
+const SYS_SLEEP: u64 = 5;
 const SYS_GET_UPTIME_MS: u64 = 47; // Added for timeout
 
 /// Service startup configuration
@@ -18,35 +20,83 @@ pub struct ServiceConfig {
     pub dependencies: &'static [&'static [u8]],
 }
 
-/// Core services to start
+/// Longest single wait for a readiness report, so timeouts are noticed
+const READY_POLL_MS: u64 = 100;
+
+/// Core services in launch order. Anything named as a dependency must
+/// report itself ready to init.
 pub const CORE_SERVICES: &[ServiceConfig] = &[
     ServiceConfig {
-        name: b"device_manager\0",
-        binary_path: b"/services/device_manager\0",
+        name: b"driver_manager\0",
+        binary_path: b"/sbin/driver_manager\0",
         dependencies: &[],
     },
+    ServiceConfig {
+        name: b"device_manager\0",
+        binary_path: b"/sbin/device_manager\0",
+        dependencies: &[], // Hosts the service name registry
+    },
     ServiceConfig {
         name: b"vfs\0",
-        binary_path: b"/services/vfs\0",
-        dependencies: &[b"device_manager\0"],
+        binary_path: b"/sbin/vfs\0",
+        dependencies: &[b"driver_manager\0", b"device_manager\0"],
+    },
+    ServiceConfig {
+        name: b"security\0",
+        binary_path: b"/sbin/security\0",
+        dependencies: &[b"vfs\0"], // ACL table is loaded from the VFS
     },
     ServiceConfig {
         name: b"network\0",
-        binary_path: b"/services/network\0",
-        dependencies: &[b"device_manager\0"],
+        binary_path: b"/sbin/network\0",
+        dependencies: &[b"driver_manager\0"],
+    },
+    ServiceConfig {
+        name: b"audio\0",
+        binary_path: b"/sbin/audio\0",
+        dependencies: &[b"driver_manager\0"],
+    },
+    ServiceConfig {
+        name: b"compositor\0",
+        binary_path: b"/sbin/compositor\0",
+        dependencies: &[b"driver_manager\0"],
+    },
+    ServiceConfig {
+        name: b"graphics\0",
+        binary_path: b"/sbin/graphics_service\0",
+        dependencies: &[], // Launched ahead of the window manager, which draws through it
+    },
+    ServiceConfig {
+        name: b"window_manager\0",
+        binary_path: b"/sbin/window_manager\0",
+        dependencies: &[],
     },
 ];
 
+/// Crash history of each core service, by position in CORE_SERVICES
+static mut RESTARTS: [RestartTracker; 16] = [RestartTracker::new(0); 16];
+
+/// Services the login manager needs before it is launched
+pub const LOGIN_DEPENDENCIES: &[&[u8]] = &[b"vfs\0", b"security\0"];
+
+/// Claim init's port so services can report in
+pub fn init_readiness() {
+    if sys_ipc_register_port(INIT_PORT) != 0 {
+        log(&[b"init: cannot register readiness port\n"]);
+    }
+}
+
 /// Start a service
 pub fn start_service(config: &ServiceConfig) -> Result<u64, ()> {
     // Check dependencies
-    for dep in config.dependencies {
-        if find_service(dep).is_none() {
-            // Dependency not ready
-            return Err(());
-        }
+    if check_dependencies(config.dependencies, service_status) != DependencyState::Ready {
+        return Err(());
     }
-    
+    spawn_service(config)
+}
+
+/// Fork and exec a service, tracking it as starting
+fn spawn_service(config: &ServiceConfig) -> Result<u64, ()> {
     // Fork process
     let pid = unsafe { syscall_raw(SYS_FORK, 0, 0, 0, 0, 0) };
     
@@ -73,59 +123,143 @@ pub fn start_service(config: &ServiceConfig) -> Result<u64, ()> {
     }
     
     // Parent process
-    // Mark service as starting
-    if let Some(idx) = find_service(config.name) {
-        set_service_status(idx, ServiceStatus::Starting);
+    // Track the service as starting until it reports in
+    match find_service(config.name) {
+        Some(idx) => {
+            set_service_pid(idx, pid);
+            set_service_status(idx, ServiceStatus::Starting);
+        }
+        None => {
+            let _ = register_service(config.name, pid, 0);
+        }
     }
     
     // Return PID
     Ok(pid)
 }
 
-/// Start all core services
+/// Start all core services, each once its dependencies are ready
 pub fn start_core_services() {
-    for service in CORE_SERVICES {
-        // Try to start service
-        if start_service(service).is_ok() {
-            // Service started
-        } else {
-            // Service failed to start (dependency not ready, etc.)
+    for (pos, service) in CORE_SERVICES.iter().enumerate() {
+        if let Err(dep) = wait_for_dependencies(service.dependencies) {
+            log(&[b"init: not starting ", service.name, b": ", dep, b" is not running\n"]);
+            continue;
+        }
+        match start_service(service) {
+            Ok(_) => unsafe { RESTARTS[pos].started(uptime_ms()) },
+            Err(()) => log(&[b"init: failed to start ", service.name, b"\n"]),
+        }
+    }
+}
+
+/// Wait until every service in `deps` is ready, or return the first one
+/// that never will be
+pub fn wait_for_dependencies<'a>(deps: &[&'a [u8]]) -> Result<(), &'a [u8]> {
+    loop {
+        match check_dependencies(deps, service_status) {
+            DependencyState::Ready => return Ok(()),
+            DependencyState::Failed(dep) => return Err(dep),
+            DependencyState::Waiting(dep) => {
+                wait_for_service(dep, READY_TIMEOUT_MS);
+            }
         }
     }
 }
 
-/// Wait for service to be ready
+/// Wait for a started service to report itself ready. On timeout it is
+/// marked failed so its dependents are not started.
 pub fn wait_for_service(name: &[u8], timeout_ms: u64) -> bool {
-    let start_time = unsafe { syscall_raw(SYS_GET_UPTIME_MS, 0, 0, 0, 0, 0) };
+    let start_time = uptime_ms();
     
     loop {
-        if find_service(name).is_some() {
-            return true;
+        match service_status(name) {
+            Some(ServiceStatus::Running) => return true,
+            Some(ServiceStatus::Starting) => {}
+            _ => return false,
         }
         
-        let current_time = unsafe { syscall_raw(SYS_GET_UPTIME_MS, 0, 0, 0, 0, 0) };
-        if timeout_ms > 0 && current_time - start_time > timeout_ms as u64 {
-            return false; // Timeout
+        let current_time = uptime_ms();
+        let elapsed = current_time.saturating_sub(start_time);
+        if elapsed >= timeout_ms {
+            if let Some(idx) = find_service(name) {
+                set_service_status(idx, ServiceStatus::Failed);
+            }
+            log(&[b"init: ", name, b" did not become ready\n"]);
+            return false;
         }
         
-        unsafe { syscall_raw(SYS_SLEEP, 10, 0, 0, 0, 0) }; // Sleep for a short period
+        receive_ready_report((timeout_ms - elapsed).min(READY_POLL_MS));
     }
 }
 
-/// Wait for all core services
-pub fn wait_for_core_services() {
-    for service in CORE_SERVICES {
-        // Wait for service to register
-        while !wait_for_service(service.name, 5000) { // 5-second timeout per service
-            // This loop condition already handles yielding/sleeping internally
+/// Take one readiness report if it arrives within `timeout_ms`; false if
+/// none did
+fn receive_ready_report(timeout_ms: u64) -> bool {
+    let mut msg = IpcMessage::new();
+    if sys_ipc_receive_timeout(INIT_PORT, &mut msg, timeout_ms) != 0 {
+        return false;
+    }
+    if msg.msg_id == INIT_MSG_SERVICE_READY {
+        if let Some(name) = ready_name(msg.inline()) {
+            mark_ready(name);
         }
     }
+    true
+}
+
+/// Handle a reaped child. A core service that crashed is restarted after
+/// its backoff, unless it has used up its retries.
+pub fn handle_child_exit(pid: u64, exit_code: i32) {
+    // Pick up reports from restarted services
+    while receive_ready_report(0) {}
+
+    let idx = match find_service_by_pid(pid) {
+        Some(idx) => idx,
+        None => return, // Not a service
+    };
+    let config = match CORE_SERVICES.iter().position(|s| find_service(s.name) == Some(idx)) {
+        Some(pos) => pos,
+        None => return,
+    };
+    let service = &CORE_SERVICES[config];
+
+    if !is_crash(exit_code) {
+        set_service_status(idx, ServiceStatus::Stopped);
+        log(&[b"init: ", service.name, b" exited\n"]);
+        return;
+    }
+
+    let now = uptime_ms();
+    let tracker = unsafe { &mut RESTARTS[config] };
+    match tracker.crashed(now) {
+        CrashAction::RestartAt(at_ms) => {
+            set_service_status(idx, ServiceStatus::Stopped);
+            log(&[b"init: ", service.name, b" crashed, restarting\n"]);
+            unsafe { syscall_raw(SYS_SLEEP, at_ms.saturating_sub(now), 0, 0, 0, 0) };
+
+            if spawn_service(service).is_ok() {
+                tracker.started(uptime_ms());
+            } else {
+                set_service_status(idx, ServiceStatus::Failed);
+                log(&[b"init: failed to restart ", service.name, b"\n"]);
+            }
+        }
+        CrashAction::GiveUp => {
+            set_service_status(idx, ServiceStatus::Failed);
+            log(&[b"init: ", service.name, b" keeps crashing, giving up\n"]);
+        }
+    }
+}
+
+fn uptime_ms() -> u64 {
+    unsafe { syscall_raw(SYS_GET_UPTIME_MS, 0, 0, 0, 0, 0) }
 }
 
-/// Yield CPU to scheduler
-fn yield_cpu() {
-    unsafe {
-        syscall_raw(SYS_YIELD, 0, 0, 0, 0, 0);  // SYS_YIELD
+/// Write to the console; NUL terminators in the parts are skipped
+fn log(parts: &[&[u8]]) {
+    for part in parts {
+        let len = part.iter().position(|&b| b == 0).unwrap_or(part.len());
+        unsafe { syscall_raw(SYS_WRITE, 1, part.as_ptr() as u64, len as u64, 0, 0) };
     }
 }
 
@@ -134,13 +268,14 @@ unsafe fn syscall_raw(num: u64, arg1: u64, arg2: u64, arg3: u64, arg4: u64, arg5
     let ret: u64;
     core::arch::asm!(
         "syscall",
-        in("rax") num,
+        inlateout("rax") num => ret,
         in("rdi") arg1,
         in("rsi") arg2,
         in("rdx") arg3,
         in("r10") arg4,
         in("r8") arg5,
-        out("rax") ret,
+        lateout("rcx") _,
+        lateout("r11") _,
         options(nostack, preserves_flags)
     );
     ret
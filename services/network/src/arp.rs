@@ -1,6 +1,6 @@
 //! ARP (Address Resolution Protocol) Implementation
 
-use crate::ethernet_device;
+use crate::network;
 use crate::syscalls::sys_get_uptime_ms;
 use core::mem;
 
@@ -29,19 +29,66 @@ pub const ARP_HW_ETHERNET: u16 = 1;
 /// ARP protocol types
 pub const ARP_PROTO_IPV4: u16 = 0x0800;
 
+/// EtherType carried by ARP frames
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+
 /// ARP cache entry
 #[derive(Clone, Copy)]
 pub struct ArpCacheEntry {
+    pub device: usize,
     pub ip: u32,
     pub mac: [u8; 6],
     pub timestamp: u64,
     pub valid: bool,
 }
 
+/// Frame waiting for its next hop to be resolved
+#[derive(Clone, Copy)]
+struct PendingFrame {
+    device: usize,
+    next_hop: u32,
+    frame: [u8; ARP_MAX_FRAME],
+    len: usize,
+    valid: bool,
+}
+
+/// Outstanding ARP request
+#[derive(Clone, Copy)]
+struct ArpRequestState {
+    device: usize,
+    ip: u32,
+    last_sent: u64,
+    attempts: u8,
+    valid: bool,
+}
+
 const ARP_CACHE_SIZE: usize = 256;
-const ARP_CACHE_TIMEOUT: u64 = 300; // 5 minutes
+const ARP_CACHE_TIMEOUT_MS: u64 = 300_000; // 5 minutes
+
+const ARP_PENDING_SIZE: usize = 8;
+const ARP_MAX_FRAME: usize = 1514;
+const ARP_OUTSTANDING_SIZE: usize = 8;
+const ARP_REQUEST_INTERVAL_MS: u64 = 1000;
+const ARP_MAX_REQUESTS: u8 = 3;
+
+static mut ARP_PENDING: [PendingFrame; ARP_PENDING_SIZE] = [PendingFrame {
+    device: 0,
+    next_hop: 0,
+    frame: [0; ARP_MAX_FRAME],
+    len: 0,
+    valid: false,
+}; ARP_PENDING_SIZE];
+
+static mut ARP_OUTSTANDING: [ArpRequestState; ARP_OUTSTANDING_SIZE] = [ArpRequestState {
+    device: 0,
+    ip: 0,
+    last_sent: 0,
+    attempts: 0,
+    valid: false,
+}; ARP_OUTSTANDING_SIZE];
 
 static mut ARP_CACHE: [ArpCacheEntry; ARP_CACHE_SIZE] = [ArpCacheEntry {
+    device: 0,
     ip: 0,
     mac: [0; 6],
     timestamp: 0,
@@ -49,36 +96,47 @@ static mut ARP_CACHE: [ArpCacheEntry; ARP_CACHE_SIZE] = [ArpCacheEntry {
 }; ARP_CACHE_SIZE];
 
 static mut ARP_CACHE_COUNT: usize = 0;
-static mut LOCAL_IP: u32 = 0;
-static mut LOCAL_MAC: [u8; 6] = [0; 6];
 
 /// Initialize ARP
-pub fn arp_init(local_ip: u32, local_mac: [u8; 6]) -> Result<(), ()> {
+/// Entries are kept per device; local MAC and IP are taken from the network
+/// device, so addresses may be configured later (DHCP).
+pub fn arp_init() -> Result<(), ()> {
     unsafe {
-        LOCAL_IP = local_ip;
-        LOCAL_MAC = local_mac;
         ARP_CACHE_COUNT = 0;
 
-        // Clear cache
+        // Clear cache, pending frames and outstanding requests
         for i in 0..ARP_CACHE_SIZE {
             ARP_CACHE[i].valid = false;
         }
+        for i in 0..ARP_PENDING_SIZE {
+            ARP_PENDING[i].valid = false;
+        }
+        for i in 0..ARP_OUTSTANDING_SIZE {
+            ARP_OUTSTANDING[i].valid = false;
+        }
     }
 
     Ok(())
 }
 
+/// Local MAC and IP address advertised in ARP packets on a device
+fn local_addr(device_idx: usize) -> Result<([u8; 6], u32), ()> {
+    network::get_device(device_idx).map(|dev| (dev.mac_address, dev.ip_address)).ok_or(())
+}
+
 /// Send ARP request
-pub fn arp_request(target_ip: u32) -> Result<(), ()> {
+pub fn arp_request(device_idx: usize, target_ip: u32) -> Result<(), ()> {
+    let (local_mac, local_ip) = local_addr(device_idx)?;
+
     unsafe {
-        let mut arp = ArpHeader {
+        let arp = ArpHeader {
             hardware_type: ARP_HW_ETHERNET.to_be(),
             protocol_type: ARP_PROTO_IPV4.to_be(),
             hardware_addr_len: 6,
             protocol_addr_len: 4,
             operation: ARP_OP_REQUEST.to_be(),
-            sender_hw_addr: LOCAL_MAC,
-            sender_proto_addr: LOCAL_IP.to_be(),
+            sender_hw_addr: local_mac,
+            sender_proto_addr: local_ip.to_be(),
             target_hw_addr: [0; 6],
             target_proto_addr: target_ip.to_be(),
         };
@@ -90,9 +148,9 @@ pub fn arp_request(target_ip: u32) -> Result<(), ()> {
         // Destination MAC (broadcast)
         packet[0..6].copy_from_slice(&[0xFF; 6]);
         // Source MAC
-        packet[6..12].copy_from_slice(&LOCAL_MAC);
+        packet[6..12].copy_from_slice(&local_mac);
         // EtherType (ARP = 0x0806)
-        packet[12..14].copy_from_slice(&0x0806u16.to_be_bytes());
+        packet[12..14].copy_from_slice(&ETHERTYPE_ARP.to_be_bytes());
 
         // ARP payload
         let arp_bytes = core::slice::from_raw_parts(
@@ -102,21 +160,23 @@ pub fn arp_request(target_ip: u32) -> Result<(), ()> {
         packet[14..14 + mem::size_of::<ArpHeader>()].copy_from_slice(arp_bytes);
 
         // Send packet
-        ethernet_device::send_packet(&packet[0..14 + mem::size_of::<ArpHeader>()])
+        network::device_send(device_idx, &packet[0..14 + mem::size_of::<ArpHeader>()])
     }
 }
 
 /// Send ARP reply
-pub fn arp_reply(target_ip: u32, target_mac: [u8; 6]) -> Result<(), ()> {
+pub fn arp_reply(device_idx: usize, target_ip: u32, target_mac: [u8; 6]) -> Result<(), ()> {
+    let (local_mac, local_ip) = local_addr(device_idx)?;
+
     unsafe {
-        let mut arp = ArpHeader {
+        let arp = ArpHeader {
             hardware_type: ARP_HW_ETHERNET.to_be(),
             protocol_type: ARP_PROTO_IPV4.to_be(),
             hardware_addr_len: 6,
             protocol_addr_len: 4,
             operation: ARP_OP_REPLY.to_be(),
-            sender_hw_addr: LOCAL_MAC,
-            sender_proto_addr: LOCAL_IP.to_be(),
+            sender_hw_addr: local_mac,
+            sender_proto_addr: local_ip.to_be(),
             target_hw_addr: target_mac,
             target_proto_addr: target_ip.to_be(),
         };
@@ -126,8 +186,8 @@ pub fn arp_reply(target_ip: u32, target_mac: [u8; 6]) -> Result<(), ()> {
 
         // Ethernet header
         packet[0..6].copy_from_slice(&target_mac);
-        packet[6..12].copy_from_slice(&LOCAL_MAC);
-        packet[12..14].copy_from_slice(&0x0806u16.to_be_bytes());
+        packet[6..12].copy_from_slice(&local_mac);
+        packet[12..14].copy_from_slice(&ETHERTYPE_ARP.to_be_bytes());
 
         // ARP payload
         let arp_bytes = core::slice::from_raw_parts(
@@ -136,12 +196,12 @@ pub fn arp_reply(target_ip: u32, target_mac: [u8; 6]) -> Result<(), ()> {
         );
         packet[14..14 + mem::size_of::<ArpHeader>()].copy_from_slice(arp_bytes);
 
-        ethernet_device::send_packet(&packet[0..14 + mem::size_of::<ArpHeader>()])
+        network::device_send(device_idx, &packet[0..14 + mem::size_of::<ArpHeader>()])
     }
 }
 
-/// Process received ARP packet
-pub fn arp_process(packet: &[u8]) -> Result<(), ()> {
+/// Process ARP packet received on a device
+pub fn arp_process(device_idx: usize, packet: &[u8]) -> Result<(), ()> {
     if packet.len() < mem::size_of::<ArpHeader>() {
         return Err(());
     }
@@ -159,13 +219,19 @@ pub fn arp_process(packet: &[u8]) -> Result<(), ()> {
         let target_ip = u32::from_be(arp.target_proto_addr);
         let operation = u16::from_be(arp.operation);
 
-        // Update ARP cache with sender info
-        arp_cache_add(sender_ip, arp.sender_hw_addr);
+        let (_, our_ip) = local_addr(device_idx)?;
+        if sender_ip == 0 {
+            // ARP probe (RFC 5227), nothing to learn
+            return Ok(());
+        }
+
+        // Update ARP cache with sender info (also flushes frames waiting on it)
+        arp_cache_add(device_idx, sender_ip, arp.sender_hw_addr);
 
         // Handle ARP request
-        if operation == ARP_OP_REQUEST && target_ip == LOCAL_IP {
+        if operation == ARP_OP_REQUEST && our_ip != 0 && target_ip == our_ip {
             // Send ARP reply
-            arp_reply(sender_ip, arp.sender_hw_addr)?;
+            arp_reply(device_idx, sender_ip, arp.sender_hw_addr)?;
         }
 
         // Handle ARP reply (cache already updated above)
@@ -175,11 +241,16 @@ pub fn arp_process(packet: &[u8]) -> Result<(), ()> {
 }
 
 /// Add entry to ARP cache
-fn arp_cache_add(ip: u32, mac: [u8; 6]) {
+fn arp_cache_add(device_idx: usize, ip: u32, mac: [u8; 6]) {
+    arp_cache_insert(device_idx, ip, mac);
+    arp_flush_pending(device_idx, ip, mac);
+}
+
+fn arp_cache_insert(device_idx: usize, ip: u32, mac: [u8; 6]) {
     unsafe {
         // Check if entry already exists
         for i in 0..ARP_CACHE_SIZE {
-            if ARP_CACHE[i].valid && ARP_CACHE[i].ip == ip {
+            if ARP_CACHE[i].valid && ARP_CACHE[i].device == device_idx && ARP_CACHE[i].ip == ip {
                 ARP_CACHE[i].mac = mac;
                 ARP_CACHE[i].timestamp = sys_get_uptime_ms();
                 return;
@@ -189,6 +260,7 @@ fn arp_cache_add(ip: u32, mac: [u8; 6]) {
         // Find free slot
         for i in 0..ARP_CACHE_SIZE {
             if !ARP_CACHE[i].valid {
+                ARP_CACHE[i].device = device_idx;
                 ARP_CACHE[i].ip = ip;
                 ARP_CACHE[i].mac = mac;
                 ARP_CACHE[i].timestamp = sys_get_uptime_ms();
@@ -208,18 +280,27 @@ fn arp_cache_add(ip: u32, mac: [u8; 6]) {
             }
         }
 
+        ARP_CACHE[oldest_idx].device = device_idx;
         ARP_CACHE[oldest_idx].ip = ip;
         ARP_CACHE[oldest_idx].mac = mac;
-        ARP_CACHE[oldest_idx].timestamp = 0;
+        ARP_CACHE[oldest_idx].timestamp = sys_get_uptime_ms();
         ARP_CACHE[oldest_idx].valid = true;
     }
 }
 
-/// Lookup MAC address for IP
-pub fn arp_lookup(ip: u32) -> Option<[u8; 6]> {
+/// Lookup MAC address for IP on a device
+pub fn arp_lookup(device_idx: usize, ip: u32) -> Option<[u8; 6]> {
+    let now = sys_get_uptime_ms();
+
     unsafe {
         for i in 0..ARP_CACHE_SIZE {
-            if ARP_CACHE[i].valid && ARP_CACHE[i].ip == ip {
+            if ARP_CACHE[i].valid && ARP_CACHE[i].device == device_idx && ARP_CACHE[i].ip == ip {
+                if now.saturating_sub(ARP_CACHE[i].timestamp) >= ARP_CACHE_TIMEOUT_MS {
+                    // Stale entry, force re-resolution
+                    ARP_CACHE[i].valid = false;
+                    ARP_CACHE_COUNT = ARP_CACHE_COUNT.saturating_sub(1);
+                    return None;
+                }
                 return Some(ARP_CACHE[i].mac);
             }
         }
@@ -228,26 +309,165 @@ pub fn arp_lookup(ip: u32) -> Option<[u8; 6]> {
     None
 }
 
-/// Resolve IP to MAC (with ARP request if needed)
-pub fn arp_resolve(ip: u32) -> Result<[u8; 6], ()> {
-    // Check cache first
-    if let Some(mac) = arp_lookup(ip) {
-        return Ok(mac);
+/// Resolve IP to MAC without blocking.
+/// On a cache miss an ARP request is sent (rate limited) and `None` is returned;
+/// callers queue their frame with `arp_queue_frame` until the reply arrives.
+pub fn arp_resolve(device_idx: usize, ip: u32) -> Option<[u8; 6]> {
+    if let Some(mac) = arp_lookup(device_idx, ip) {
+        return Some(mac);
     }
 
-    // Send ARP request
-    arp_request(ip)?;
+    arp_solicit(device_idx, ip);
+    None
+}
 
-    // Wait for reply (with timeout)
-    for _ in 0..100 {
-        // Check cache again
-        if let Some(mac) = arp_lookup(ip) {
-            return Ok(mac);
-        }
+/// Queue an Ethernet frame until `next_hop` is resolved.
+/// The destination MAC (bytes 0..6) is filled in when the reply arrives.
+pub fn arp_queue_frame(device_idx: usize, next_hop: u32, frame: &[u8]) -> Result<(), ()> {
+    if frame.len() > ARP_MAX_FRAME {
+        return Err(());
+    }
 
-        // Yield CPU
-        crate::syscalls::sys_yield();
+    unsafe {
+        for i in 0..ARP_PENDING_SIZE {
+            if !ARP_PENDING[i].valid {
+                let pending = &mut ARP_PENDING[i];
+                pending.device = device_idx;
+                pending.next_hop = next_hop;
+                pending.frame[0..frame.len()].copy_from_slice(frame);
+                pending.len = frame.len();
+                pending.valid = true;
+
+                arp_solicit(device_idx, next_hop);
+                return Ok(());
+            }
+        }
     }
 
+    // Queue full, drop the frame
     Err(())
 }
+
+/// Send an ARP request for `ip` unless one went out recently
+fn arp_solicit(device_idx: usize, ip: u32) {
+    let now = sys_get_uptime_ms();
+
+    unsafe {
+        let mut slot = None;
+        for i in 0..ARP_OUTSTANDING_SIZE {
+            if ARP_OUTSTANDING[i].valid && ARP_OUTSTANDING[i].device == device_idx && ARP_OUTSTANDING[i].ip == ip {
+                if now.saturating_sub(ARP_OUTSTANDING[i].last_sent) < ARP_REQUEST_INTERVAL_MS {
+                    return;
+                }
+                slot = Some(i);
+                break;
+            }
+        }
+
+        let idx = match slot {
+            Some(idx) => idx,
+            None => {
+                // New request; reuse the oldest one if the table is full
+                let mut idx = 0;
+                for i in 0..ARP_OUTSTANDING_SIZE {
+                    if !ARP_OUTSTANDING[i].valid {
+                        idx = i;
+                        break;
+                    }
+                    if ARP_OUTSTANDING[i].last_sent < ARP_OUTSTANDING[idx].last_sent {
+                        idx = i;
+                    }
+                }
+                ARP_OUTSTANDING[idx] = ArpRequestState {
+                    device: device_idx,
+                    ip,
+                    last_sent: 0,
+                    attempts: 0,
+                    valid: true,
+                };
+                idx
+            }
+        };
+
+        ARP_OUTSTANDING[idx].last_sent = now;
+        ARP_OUTSTANDING[idx].attempts += 1;
+    }
+
+    let _ = arp_request(device_idx, ip);
+}
+
+/// Send frames that were waiting for `ip` now that its MAC is known
+fn arp_flush_pending(device_idx: usize, ip: u32, mac: [u8; 6]) {
+    unsafe {
+        for i in 0..ARP_OUTSTANDING_SIZE {
+            if ARP_OUTSTANDING[i].valid && ARP_OUTSTANDING[i].device == device_idx && ARP_OUTSTANDING[i].ip == ip {
+                ARP_OUTSTANDING[i].valid = false;
+            }
+        }
+
+        for i in 0..ARP_PENDING_SIZE {
+            if ARP_PENDING[i].valid && ARP_PENDING[i].device == device_idx && ARP_PENDING[i].next_hop == ip {
+                let pending = &mut ARP_PENDING[i];
+                pending.frame[0..6].copy_from_slice(&mac);
+                let _ = network::device_send(device_idx, &pending.frame[0..pending.len]);
+                pending.valid = false;
+            }
+        }
+    }
+}
+
+/// Forget everything learned on a device (link lost: the NIC may come back
+/// on a different network)
+pub fn arp_flush_device(device_idx: usize) {
+    unsafe {
+        for i in 0..ARP_CACHE_SIZE {
+            if ARP_CACHE[i].valid && ARP_CACHE[i].device == device_idx {
+                ARP_CACHE[i].valid = false;
+                ARP_CACHE_COUNT = ARP_CACHE_COUNT.saturating_sub(1);
+            }
+        }
+        for i in 0..ARP_PENDING_SIZE {
+            if ARP_PENDING[i].device == device_idx {
+                ARP_PENDING[i].valid = false;
+            }
+        }
+        for i in 0..ARP_OUTSTANDING_SIZE {
+            if ARP_OUTSTANDING[i].device == device_idx {
+                ARP_OUTSTANDING[i].valid = false;
+            }
+        }
+    }
+}
+
+/// Drop frames waiting for `ip`
+fn arp_drop_pending(device_idx: usize, ip: u32) {
+    unsafe {
+        for i in 0..ARP_PENDING_SIZE {
+            if ARP_PENDING[i].valid && ARP_PENDING[i].device == device_idx && ARP_PENDING[i].next_hop == ip {
+                ARP_PENDING[i].valid = false;
+            }
+        }
+    }
+}
+
+/// Retransmit outstanding ARP requests and give up on unreachable hosts.
+/// Called periodically from the network service loop.
+pub fn arp_timer_tick() {
+    let now = sys_get_uptime_ms();
+
+    unsafe {
+        for i in 0..ARP_OUTSTANDING_SIZE {
+            let request = ARP_OUTSTANDING[i];
+            if !request.valid || now.saturating_sub(request.last_sent) < ARP_REQUEST_INTERVAL_MS {
+                continue;
+            }
+
+            if request.attempts >= ARP_MAX_REQUESTS {
+                ARP_OUTSTANDING[i].valid = false;
+                arp_drop_pending(request.device, request.ip);
+            } else {
+                arp_solicit(request.device, request.ip);
+            }
+        }
+    }
+}
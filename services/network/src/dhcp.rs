@@ -0,0 +1,487 @@
+//! DHCP (Dynamic Host Configuration Protocol) Client
+//!
+//! Acquires an address lease for each network device (DISCOVER/OFFER/REQUEST/ACK),
+//! renews it at T1, rebinds at T2 and starts over on NAK or lease expiry.
+//! The client never blocks: packets are fed in from the network loop and
+//! retransmissions are driven by `dhcp_timer_tick`.
+
+use crate::network::{self, MAX_DEVICES};
+use crate::route;
+use crate::syscalls::sys_get_uptime_ms;
+use crate::udp;
+
+/// UDP ports
+pub const DHCP_SERVER_PORT: u16 = 67;
+pub const DHCP_CLIENT_PORT: u16 = 68;
+
+/// BOOTP operations
+const BOOTP_REQUEST: u8 = 1;
+const BOOTP_REPLY: u8 = 2;
+
+/// DHCP message types (option 53)
+pub const DHCP_DISCOVER: u8 = 1;
+pub const DHCP_OFFER: u8 = 2;
+pub const DHCP_REQUEST: u8 = 3;
+pub const DHCP_DECLINE: u8 = 4;
+pub const DHCP_ACK: u8 = 5;
+pub const DHCP_NAK: u8 = 6;
+pub const DHCP_RELEASE: u8 = 7;
+
+/// DHCP options
+const OPT_PAD: u8 = 0;
+const OPT_SUBNET_MASK: u8 = 1;
+const OPT_ROUTER: u8 = 3;
+const OPT_DNS_SERVER: u8 = 6;
+const OPT_REQUESTED_IP: u8 = 50;
+const OPT_LEASE_TIME: u8 = 51;
+const OPT_MESSAGE_TYPE: u8 = 53;
+const OPT_SERVER_ID: u8 = 54;
+const OPT_PARAM_REQUEST: u8 = 55;
+const OPT_RENEWAL_TIME: u8 = 58;
+const OPT_REBINDING_TIME: u8 = 59;
+const OPT_END: u8 = 255;
+
+const DHCP_MAGIC_COOKIE: u32 = 0x6382_5363;
+const DHCP_FLAG_BROADCAST: u16 = 0x8000;
+
+/// Fixed BOOTP header length (up to and including the magic cookie)
+const DHCP_HEADER_LEN: usize = 240;
+/// Minimum BOOTP message size accepted by old relays
+const DHCP_MIN_MESSAGE_LEN: usize = 300;
+
+const DHCP_INITIAL_RETRY_MS: u64 = 4000;
+const DHCP_MAX_RETRY_MS: u64 = 64000;
+const DHCP_RENEW_RETRY_MS: u64 = 60000;
+const DHCP_MAX_REQUEST_RETRIES: u8 = 4;
+
+/// DHCP client state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DhcpState {
+    Idle,
+    Selecting,   // DISCOVER sent, waiting for an OFFER
+    Requesting,  // REQUEST sent, waiting for ACK/NAK
+    Bound,
+    Renewing,    // T1 passed, unicasting REQUEST to the leasing server
+    Rebinding,   // T2 passed, broadcasting REQUEST to any server
+}
+
+/// Options extracted from a server reply
+#[derive(Clone, Copy)]
+struct DhcpReply {
+    message_type: u8,
+    your_ip: u32,
+    server_id: u32,
+    netmask: u32,
+    gateway: u32,
+    dns_server: u32,
+    lease_secs: u32,
+    t1_secs: u32,
+    t2_secs: u32,
+}
+
+struct DhcpClient {
+    state: DhcpState,
+    device_idx: usize,
+    mac: [u8; 6],
+    xid: u32,
+    offered_ip: u32,
+    server_id: u32,
+    leased_ip: u32,
+    lease_start: u64,
+    lease_secs: u32,
+    t1_secs: u32,
+    t2_secs: u32,
+    retry_interval: u64,
+    retries: u8,
+    next_timeout: u64,
+}
+
+const NO_CLIENT: DhcpClient = DhcpClient {
+    state: DhcpState::Idle,
+    device_idx: 0,
+    mac: [0; 6],
+    xid: 0,
+    offered_ip: 0,
+    server_id: 0,
+    leased_ip: 0,
+    lease_start: 0,
+    lease_secs: 0,
+    t1_secs: 0,
+    t2_secs: 0,
+    retry_interval: DHCP_INITIAL_RETRY_MS,
+    retries: 0,
+    next_timeout: 0,
+};
+
+/// One client per network device
+static mut CLIENTS: [DhcpClient; MAX_DEVICES] = [NO_CLIENT; MAX_DEVICES];
+
+/// Start acquiring a lease for a registered device
+pub fn dhcp_start(device_idx: usize) -> Result<(), ()> {
+    let device = network::get_device(device_idx).ok_or(())?;
+    if device_idx >= MAX_DEVICES {
+        return Err(());
+    }
+
+    unsafe {
+        let client = &mut CLIENTS[device_idx];
+        client.device_idx = device_idx;
+        client.mac = device.mac_address;
+    }
+
+    dhcp_discover(device_idx)
+}
+
+/// Current client state for a device
+pub fn dhcp_get_state(device_idx: usize) -> DhcpState {
+    if device_idx >= MAX_DEVICES {
+        return DhcpState::Idle;
+    }
+    unsafe { CLIENTS[device_idx].state }
+}
+
+/// Pick a transaction ID that differs between boots and between devices
+fn next_xid(device_idx: usize) -> u32 {
+    unsafe {
+        let client = &CLIENTS[device_idx];
+        let mac = client.mac;
+        let seed = u32::from_be_bytes([mac[2], mac[3], mac[4], mac[5]]);
+        (sys_get_uptime_ms() as u32).wrapping_mul(2654435761) ^ seed ^ client.xid.rotate_left(7)
+    }
+}
+
+/// Begin (or restart) address acquisition with a broadcast DISCOVER
+fn dhcp_discover(device_idx: usize) -> Result<(), ()> {
+    unsafe {
+        let client = &mut CLIENTS[device_idx];
+        client.state = DhcpState::Selecting;
+        client.xid = next_xid(device_idx);
+        client.offered_ip = 0;
+        client.server_id = 0;
+        client.retry_interval = DHCP_INITIAL_RETRY_MS;
+        client.retries = 0;
+        client.next_timeout = sys_get_uptime_ms() + client.retry_interval;
+    }
+
+    send_message(device_idx, DHCP_DISCOVER)
+}
+
+/// Drop the current address and start over
+fn dhcp_restart(device_idx: usize) {
+    unsafe {
+        let client = &mut CLIENTS[device_idx];
+        if client.leased_ip != 0 {
+            client.leased_ip = 0;
+            let _ = network::set_ip_config(client.device_idx, 0, 0, 0, 0);
+            route::remove_device_routes(client.device_idx);
+        }
+    }
+
+    let _ = dhcp_discover(device_idx);
+}
+
+/// Build and send a DHCP message for the current state
+fn send_message(device_idx: usize, message_type: u8) -> Result<(), ()> {
+    let mut packet = [0u8; 548];
+
+    unsafe {
+        let client = &CLIENTS[device_idx];
+        // Renewing is unicast and carries our address in ciaddr; every other
+        // request is broadcast with the server asked to broadcast its reply
+        let (dest_ip, ciaddr, flags) = match client.state {
+            DhcpState::Renewing => (client.server_id, client.leased_ip, 0),
+            DhcpState::Rebinding => (crate::ip::IP_BROADCAST, client.leased_ip, 0),
+            _ => (crate::ip::IP_BROADCAST, 0, DHCP_FLAG_BROADCAST),
+        };
+
+        packet[0] = BOOTP_REQUEST;
+        packet[1] = 1; // Ethernet
+        packet[2] = 6; // MAC length
+        packet[3] = 0; // Hops
+        packet[4..8].copy_from_slice(&client.xid.to_be_bytes());
+        packet[10..12].copy_from_slice(&flags.to_be_bytes());
+        packet[12..16].copy_from_slice(&ciaddr.to_be_bytes());
+        packet[28..34].copy_from_slice(&client.mac);
+        packet[236..240].copy_from_slice(&DHCP_MAGIC_COOKIE.to_be_bytes());
+
+        // Options
+        let mut offset = DHCP_HEADER_LEN;
+        packet[offset..offset + 3].copy_from_slice(&[OPT_MESSAGE_TYPE, 1, message_type]);
+        offset += 3;
+
+        if client.state == DhcpState::Requesting {
+            packet[offset] = OPT_REQUESTED_IP;
+            packet[offset + 1] = 4;
+            packet[offset + 2..offset + 6].copy_from_slice(&client.offered_ip.to_be_bytes());
+            offset += 6;
+
+            packet[offset] = OPT_SERVER_ID;
+            packet[offset + 1] = 4;
+            packet[offset + 2..offset + 6].copy_from_slice(&client.server_id.to_be_bytes());
+            offset += 6;
+        }
+
+        let params = [
+            OPT_SUBNET_MASK,
+            OPT_ROUTER,
+            OPT_DNS_SERVER,
+            OPT_LEASE_TIME,
+            OPT_RENEWAL_TIME,
+            OPT_REBINDING_TIME,
+        ];
+        packet[offset] = OPT_PARAM_REQUEST;
+        packet[offset + 1] = params.len() as u8;
+        packet[offset + 2..offset + 2 + params.len()].copy_from_slice(&params);
+        offset += 2 + params.len();
+
+        packet[offset] = OPT_END;
+        offset += 1;
+
+        let len = offset.max(DHCP_MIN_MESSAGE_LEN);
+        udp::udp_send_on(device_idx, dest_ip, DHCP_SERVER_PORT, DHCP_CLIENT_PORT, &packet[0..len])
+    }
+}
+
+/// Parse a server reply addressed to the device's client
+fn parse_reply(device_idx: usize, packet: &[u8]) -> Result<DhcpReply, ()> {
+    if packet.len() < DHCP_HEADER_LEN || packet[0] != BOOTP_REPLY {
+        return Err(());
+    }
+
+    let xid = u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]);
+    let cookie = u32::from_be_bytes([packet[236], packet[237], packet[238], packet[239]]);
+    unsafe {
+        let client = &CLIENTS[device_idx];
+        if xid != client.xid || packet[28..34] != client.mac || cookie != DHCP_MAGIC_COOKIE {
+            return Err(());
+        }
+    }
+
+    let mut reply = DhcpReply {
+        message_type: 0,
+        your_ip: u32::from_be_bytes([packet[16], packet[17], packet[18], packet[19]]),
+        server_id: 0,
+        netmask: 0,
+        gateway: 0,
+        dns_server: 0,
+        lease_secs: 0,
+        t1_secs: 0,
+        t2_secs: 0,
+    };
+
+    let mut offset = DHCP_HEADER_LEN;
+    while offset < packet.len() {
+        let code = packet[offset];
+        if code == OPT_END {
+            break;
+        }
+        if code == OPT_PAD {
+            offset += 1;
+            continue;
+        }
+        if offset + 2 > packet.len() {
+            return Err(());
+        }
+
+        let len = packet[offset + 1] as usize;
+        let start = offset + 2;
+        if start + len > packet.len() {
+            return Err(());
+        }
+        let value = &packet[start..start + len];
+        let as_u32 = || u32::from_be_bytes([value[0], value[1], value[2], value[3]]);
+
+        match code {
+            OPT_MESSAGE_TYPE if len >= 1 => reply.message_type = value[0],
+            // Routers and DNS servers are lists; use the first entry
+            OPT_SUBNET_MASK if len >= 4 => reply.netmask = as_u32(),
+            OPT_ROUTER if len >= 4 => reply.gateway = as_u32(),
+            OPT_DNS_SERVER if len >= 4 => reply.dns_server = as_u32(),
+            OPT_SERVER_ID if len >= 4 => reply.server_id = as_u32(),
+            OPT_LEASE_TIME if len >= 4 => reply.lease_secs = as_u32(),
+            OPT_RENEWAL_TIME if len >= 4 => reply.t1_secs = as_u32(),
+            OPT_REBINDING_TIME if len >= 4 => reply.t2_secs = as_u32(),
+            _ => {}
+        }
+
+        offset = start + len;
+    }
+
+    if reply.message_type == 0 {
+        return Err(());
+    }
+
+    Ok(reply)
+}
+
+/// Handle a datagram received on the DHCP client port of a device
+pub fn dhcp_handle_packet(device_idx: usize, packet: &[u8]) -> Result<(), ()> {
+    if device_idx >= MAX_DEVICES {
+        return Err(());
+    }
+    let reply = parse_reply(device_idx, packet)?;
+    let now = sys_get_uptime_ms();
+
+    unsafe {
+        let client = &mut CLIENTS[device_idx];
+        match (client.state, reply.message_type) {
+            (DhcpState::Selecting, DHCP_OFFER) => {
+                if reply.your_ip == 0 || reply.server_id == 0 {
+                    return Err(());
+                }
+
+                // Take the first offer
+                client.offered_ip = reply.your_ip;
+                client.server_id = reply.server_id;
+                client.state = DhcpState::Requesting;
+                client.retry_interval = DHCP_INITIAL_RETRY_MS;
+                client.retries = 0;
+                client.next_timeout = now + client.retry_interval;
+                send_message(device_idx, DHCP_REQUEST)
+            }
+            (DhcpState::Requesting, DHCP_ACK)
+            | (DhcpState::Renewing, DHCP_ACK)
+            | (DhcpState::Rebinding, DHCP_ACK) => {
+                if reply.your_ip == 0 {
+                    return Err(());
+                }
+                bind(device_idx, &reply, now)
+            }
+            (DhcpState::Requesting, DHCP_NAK)
+            | (DhcpState::Renewing, DHCP_NAK)
+            | (DhcpState::Rebinding, DHCP_NAK) => {
+                // Lease refused, start over
+                dhcp_restart(device_idx);
+                Ok(())
+            }
+            _ => Ok(()), // Stale or unexpected message
+        }
+    }
+}
+
+/// Apply a lease from an ACK
+fn bind(device_idx: usize, reply: &DhcpReply, now: u64) -> Result<(), ()> {
+    unsafe {
+        let client = &mut CLIENTS[device_idx];
+        // Infinite/absent lease times are clamped so the timers stay sane
+        let lease_secs = if reply.lease_secs == 0 || reply.lease_secs == u32::MAX {
+            u32::MAX / 2
+        } else {
+            reply.lease_secs
+        };
+        let t1_secs = if reply.t1_secs != 0 && reply.t1_secs < lease_secs {
+            reply.t1_secs
+        } else {
+            lease_secs / 2
+        };
+        let t2_secs = if reply.t2_secs > t1_secs && reply.t2_secs < lease_secs {
+            reply.t2_secs
+        } else {
+            ((lease_secs as u64) * 7 / 8) as u32
+        };
+
+        // Fall back to a classful mask if the server did not send one
+        let netmask = if reply.netmask != 0 {
+            reply.netmask
+        } else if (reply.your_ip >> 24) < 128 {
+            0xFF00_0000
+        } else if (reply.your_ip >> 24) < 192 {
+            0xFFFF_0000
+        } else {
+            0xFFFF_FF00
+        };
+
+        network::set_ip_config(client.device_idx, reply.your_ip, netmask, reply.gateway, reply.dns_server)?;
+        if let Some(device) = network::get_device(client.device_idx) {
+            let _ = crate::ethernet_device::set_ip_config(device.driver_port, reply.your_ip, netmask, reply.gateway);
+        }
+
+        // Subnet route plus the default route through the router we were given
+        route::remove_device_routes(client.device_idx);
+        route::add_route(reply.your_ip & netmask, netmask, 0, client.device_idx)?;
+        if reply.gateway != 0 {
+            route::add_route(0, 0, reply.gateway, client.device_idx)?;
+        }
+
+        if reply.server_id != 0 {
+            client.server_id = reply.server_id;
+        }
+        client.leased_ip = reply.your_ip;
+        client.lease_start = now;
+        client.lease_secs = lease_secs;
+        client.t1_secs = t1_secs;
+        client.t2_secs = t2_secs;
+        client.state = DhcpState::Bound;
+        client.next_timeout = now + (t1_secs as u64) * 1000;
+    }
+
+    Ok(())
+}
+
+/// Drive retransmission, renewal (T1), rebinding (T2) and lease expiry
+/// for every device. Called periodically from the network service loop.
+pub fn dhcp_timer_tick() {
+    for device_idx in 0..network::get_device_count().min(MAX_DEVICES) {
+        client_timer_tick(device_idx);
+    }
+}
+
+fn client_timer_tick(device_idx: usize) {
+    let now = sys_get_uptime_ms();
+
+    unsafe {
+        let client = &mut CLIENTS[device_idx];
+        if client.state == DhcpState::Idle || now < client.next_timeout {
+            return;
+        }
+
+        let t2_deadline = client.lease_start + (client.t2_secs as u64) * 1000;
+        let lease_deadline = client.lease_start + (client.lease_secs as u64) * 1000;
+
+        match client.state {
+            DhcpState::Selecting => {
+                // No offer yet, back off and rebroadcast
+                client.retry_interval = (client.retry_interval * 2).min(DHCP_MAX_RETRY_MS);
+                client.next_timeout = now + client.retry_interval;
+                let _ = send_message(device_idx, DHCP_DISCOVER);
+            }
+            DhcpState::Requesting => {
+                client.retries += 1;
+                if client.retries >= DHCP_MAX_REQUEST_RETRIES {
+                    // Server went away, look for another one
+                    let _ = dhcp_discover(device_idx);
+                } else {
+                    client.retry_interval = (client.retry_interval * 2).min(DHCP_MAX_RETRY_MS);
+                    client.next_timeout = now + client.retry_interval;
+                    let _ = send_message(device_idx, DHCP_REQUEST);
+                }
+            }
+            DhcpState::Bound => {
+                // T1: renew with the server that granted the lease
+                client.state = DhcpState::Renewing;
+                client.xid = next_xid(device_idx);
+                client.next_timeout = (now + DHCP_RENEW_RETRY_MS).min(t2_deadline);
+                let _ = send_message(device_idx, DHCP_REQUEST);
+            }
+            DhcpState::Renewing if now >= t2_deadline => {
+                // T2: ask any server to extend the lease
+                client.state = DhcpState::Rebinding;
+                client.next_timeout = (now + DHCP_RENEW_RETRY_MS).min(lease_deadline);
+                let _ = send_message(device_idx, DHCP_REQUEST);
+            }
+            DhcpState::Renewing => {
+                client.next_timeout = (now + DHCP_RENEW_RETRY_MS).min(t2_deadline);
+                let _ = send_message(device_idx, DHCP_REQUEST);
+            }
+            DhcpState::Rebinding if now >= lease_deadline => {
+                // Lease expired, give up the address
+                dhcp_restart(device_idx);
+            }
+            DhcpState::Rebinding => {
+                client.next_timeout = (now + DHCP_RENEW_RETRY_MS).min(lease_deadline);
+                let _ = send_message(device_idx, DHCP_REQUEST);
+            }
+            DhcpState::Idle => {}
+        }
+    }
+}
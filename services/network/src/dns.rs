@@ -1,7 +1,15 @@
 //! DNS (Domain Name System) Resolver
+//!
+//! Resolves hostnames to IPv4 addresses using the DNS server learned via DHCP.
+//! Queries are sent over UDP; responses are delivered by the network loop
+//! (`dns_handle_packet`) so waiting for an answer never swallows other traffic.
+//! Every query goes out with a random ID from a random ephemeral port, so
+//! an off-path spoofer has to guess both.
 
 use crate::udp;
-use crate::syscalls::sys_get_uptime_ms;
+use crate::socket;
+use crate::port_table::{EPHEMERAL_FIRST, EPHEMERAL_LAST};
+use crate::syscalls::{sys_get_random, sys_get_uptime_ms};
 use core::mem;
 
 /// DNS header structure
@@ -58,6 +66,19 @@ pub const DNS_FLAG_RD: u16 = 0x0100;         // Recursion desired
 pub const DNS_FLAG_RA: u16 = 0x0080;         // Recursion available
 pub const DNS_FLAG_RCODE: u16 = 0x000F;      // Response code
 
+/// UDP port servers answer on
+pub const DNS_SERVER_PORT: u16 = 53;
+
+/// Maximum UDP DNS message size (RFC 1035)
+const DNS_MAX_MESSAGE: usize = 512;
+/// Maximum encoded/decoded name length
+const DNS_MAX_NAME: usize = 256;
+/// Maximum number of CNAMEs followed for one lookup
+const DNS_MAX_CNAMES: usize = 8;
+/// Time to wait for each response, and attempts per query
+const DNS_TIMEOUT_MS: u64 = 2000;
+const DNS_RETRIES: usize = 2;
+
 /// DNS cache entry
 #[derive(Clone, Copy)]
 pub struct DnsCacheEntry {
@@ -69,6 +90,14 @@ pub struct DnsCacheEntry {
     pub valid: bool,
 }
 
+/// Result of parsing an answer section
+enum DnsAnswer {
+    /// Address for the queried name (after following in-response CNAMEs)
+    Address(u32, u32),
+    /// Name is an alias whose target must be queried separately
+    Alias([u8; DNS_MAX_NAME], usize, u32),
+}
+
 const DNS_CACHE_SIZE: usize = 128;
 static mut DNS_CACHE: [DnsCacheEntry; DNS_CACHE_SIZE] = [DnsCacheEntry {
     name: [0; 256],
@@ -80,13 +109,19 @@ static mut DNS_CACHE: [DnsCacheEntry; DNS_CACHE_SIZE] = [DnsCacheEntry {
 }; DNS_CACHE_SIZE];
 
 static mut DNS_SERVER: u32 = 0;
-static mut NEXT_DNS_ID: u16 = 1;
+
+/// Response mailbox filled by `dns_handle_packet`, for the query in
+/// flight: (ID, source port)
+static mut DNS_PENDING: Option<(u16, u16)> = None;
+static mut DNS_RESPONSE: [u8; DNS_MAX_MESSAGE] = [0; DNS_MAX_MESSAGE];
+static mut DNS_RESPONSE_LEN: usize = 0;
 
 /// Initialize DNS resolver
+/// A non-zero `dns_server` overrides the server learned via DHCP.
 pub fn dns_init(dns_server: u32) -> Result<(), ()> {
     unsafe {
         DNS_SERVER = dns_server;
-        NEXT_DNS_ID = 1;
+        DNS_PENDING = None;
 
         // Clear cache
         for i in 0..DNS_CACHE_SIZE {
@@ -97,96 +132,143 @@ pub fn dns_init(dns_server: u32) -> Result<(), ()> {
     Ok(())
 }
 
+/// DNS server to query: the explicit override, else the one from DHCP
+fn dns_server() -> u32 {
+    unsafe {
+        if DNS_SERVER != 0 {
+            return DNS_SERVER;
+        }
+    }
+    (0..crate::network::get_device_count())
+        .filter_map(crate::network::get_device)
+        .map(|dev| dev.dns_server)
+        .find(|&server| server != 0)
+        .unwrap_or(0)
+}
+
 /// Encode domain name in DNS format
-fn encode_domain_name(domain: &str, buffer: &mut [u8]) -> usize {
+fn encode_domain_name(domain: &[u8], buffer: &mut [u8]) -> Result<usize, ()> {
+    // A single trailing dot (fully qualified name) is allowed
+    let domain = match domain.split_last() {
+        Some((b'.', rest)) => rest,
+        _ => domain,
+    };
+    if domain.is_empty() || domain.len() > 253 || buffer.len() < domain.len() + 2 {
+        return Err(());
+    }
+
     let mut offset = 0;
 
-    for label in domain.split('.') {
-        if label.len() > 63 {
-            return 0; // Label too long
+    for label in domain.split(|&c| c == b'.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(()); // Empty or too long label
         }
 
         buffer[offset] = label.len() as u8;
         offset += 1;
 
-        buffer[offset..offset + label.len()].copy_from_slice(label.as_bytes());
+        buffer[offset..offset + label.len()].copy_from_slice(label);
         offset += label.len();
     }
 
     buffer[offset] = 0; // Null terminator
-    offset + 1
+    Ok(offset + 1)
 }
 
-/// Decode domain name from DNS format
-fn decode_domain_name(packet: &[u8], mut offset: usize, buffer: &mut [u8]) -> (usize, usize) {
+/// Decode domain name from DNS format, following compression pointers.
+/// Returns (decoded length, offset just past the name at its original position).
+fn decode_domain_name(packet: &[u8], mut offset: usize, buffer: &mut [u8]) -> Result<(usize, usize), ()> {
     let mut buf_offset = 0;
-    let start_offset = offset;
-    let mut jumped = false;
+    let mut end_offset = None;
     let mut jump_count = 0;
 
     loop {
-        if jump_count > 10 {
-            // Prevent infinite loops from malformed packets
-            return (0, start_offset);
-        }
-
-        let len = packet[offset] as usize;
+        let len = *packet.get(offset).ok_or(())? as usize;
 
         if len == 0 {
             // End of name
-            if !jumped {
-                offset += 1;
-            }
+            offset += 1;
             break;
         } else if (len & 0xC0) == 0xC0 {
-            // Pointer
-            if !jumped {
-                offset += 2;
-                jumped = true;
-            }
-            let ptr = (((len & 0x3F) as usize) << 8) | (packet[offset + 1] as usize);
-            offset = ptr;
+            // Pointer; prevent infinite loops from malformed packets
             jump_count += 1;
+            if jump_count > 10 {
+                return Err(());
+            }
+            let low = *packet.get(offset + 1).ok_or(())? as usize;
+            if end_offset.is_none() {
+                end_offset = Some(offset + 2);
+            }
+            offset = ((len & 0x3F) << 8) | low;
+        } else if (len & 0xC0) != 0 {
+            return Err(()); // Reserved label type
         } else {
             // Label
+            let label = packet.get(offset + 1..offset + 1 + len).ok_or(())?;
+            let needed = len + if buf_offset > 0 { 1 } else { 0 };
+            if buf_offset + needed > buffer.len() {
+                return Err(());
+            }
+
             if buf_offset > 0 {
                 buffer[buf_offset] = b'.';
                 buf_offset += 1;
             }
+            buffer[buf_offset..buf_offset + len].copy_from_slice(label);
+            buf_offset += len;
+
+            offset += len + 1;
+        }
+    }
 
-            let label_len = len.min(buffer.len() - buf_offset);
-            buffer[buf_offset..buf_offset + label_len]
-                .copy_from_slice(&packet[offset + 1..offset + 1 + label_len]);
-            buf_offset += label_len;
+    Ok((buf_offset, end_offset.unwrap_or(offset)))
+}
 
-            if !jumped {
-                offset += len + 1;
-            }
+/// Case-insensitive name comparison (a trailing dot is ignored)
+fn names_equal(a: &[u8], b: &[u8]) -> bool {
+    let trim = |n: &[u8]| match n.split_last() {
+        Some((b'.', rest)) => rest.len(),
+        _ => n.len(),
+    };
+    let (a, b) = (&a[0..trim(a)], &b[0..trim(b)]);
+    a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x.eq_ignore_ascii_case(y))
+}
+
+/// Parse a dotted-quad literal so numeric hosts skip the network entirely
+fn parse_ipv4(text: &[u8]) -> Option<u32> {
+    let mut ip = 0u32;
+    let mut parts = 0;
+
+    for part in text.split(|&c| c == b'.') {
+        if part.is_empty() || part.len() > 3 || !part.iter().all(|c| c.is_ascii_digit()) {
+            return None;
         }
+        let value = part.iter().fold(0u32, |acc, &c| acc * 10 + (c - b'0') as u32);
+        if value > 255 {
+            return None;
+        }
+        ip = (ip << 8) | value;
+        parts += 1;
     }
 
-    (buf_offset, if jumped { start_offset + 2 } else { offset })
+    if parts == 4 { Some(ip) } else { None }
 }
 
 /// Lookup domain name in cache
-fn dns_cache_lookup(domain: &str) -> Option<u32> {
+fn dns_cache_lookup(domain: &[u8]) -> Option<u32> {
     unsafe {
         for i in 0..DNS_CACHE_SIZE {
-            if DNS_CACHE[i].valid {
-                let cached_name = core::str::from_utf8(&DNS_CACHE[i].name[0..DNS_CACHE[i].name_len])
-                    .ok()?;
-                if cached_name == domain {
-                    // Check TTL and timestamp
-                    let current_time = sys_get_uptime_ms();
-                    let cache_age = current_time.saturating_sub(DNS_CACHE[i].timestamp);
-                    let ttl_ms = (DNS_CACHE[i].ttl as u64) * 1000;
-                    
-                    if cache_age < ttl_ms {
-                        return Some(DNS_CACHE[i].ip);
-                    } else {
-                        // Cache entry expired, mark as invalid
-                        DNS_CACHE[i].valid = false;
-                    }
+            if DNS_CACHE[i].valid && names_equal(&DNS_CACHE[i].name[0..DNS_CACHE[i].name_len], domain) {
+                // Check TTL and timestamp
+                let current_time = sys_get_uptime_ms();
+                let cache_age = current_time.saturating_sub(DNS_CACHE[i].timestamp);
+                let ttl_ms = (DNS_CACHE[i].ttl as u64) * 1000;
+
+                if cache_age < ttl_ms {
+                    return Some(DNS_CACHE[i].ip);
+                } else {
+                    // Cache entry expired, mark as invalid
+                    DNS_CACHE[i].valid = false;
                 }
             }
         }
@@ -196,51 +278,86 @@ fn dns_cache_lookup(domain: &str) -> Option<u32> {
 }
 
 /// Add entry to DNS cache
-fn dns_cache_add(domain: &str, ip: u32, ttl: u32) {
+fn dns_cache_add(domain: &[u8], ip: u32, ttl: u32) {
+    if ttl == 0 || domain.len() > 255 {
+        return; // Not cacheable
+    }
+
     unsafe {
-        // Find free slot
+        // Reuse an existing entry for the name, else a free slot, else the oldest
+        let mut slot = None;
+        let mut oldest = 0;
         for i in 0..DNS_CACHE_SIZE {
-            if !DNS_CACHE[i].valid {
-                let len = domain.len().min(255);
-                DNS_CACHE[i].name[0..len].copy_from_slice(&domain.as_bytes()[0..len]);
-                DNS_CACHE[i].name_len = len;
-                DNS_CACHE[i].ip = ip;
-                DNS_CACHE[i].ttl = ttl;
-                DNS_CACHE[i].timestamp = sys_get_uptime_ms();
-                DNS_CACHE[i].valid = true;
-                return;
+            if DNS_CACHE[i].valid && names_equal(&DNS_CACHE[i].name[0..DNS_CACHE[i].name_len], domain) {
+                slot = Some(i);
+                break;
+            }
+            if slot.is_none() && !DNS_CACHE[i].valid {
+                slot = Some(i);
+            }
+            if DNS_CACHE[i].timestamp < DNS_CACHE[oldest].timestamp {
+                oldest = i;
             }
         }
 
-        // Cache full, evict oldest entry
-        DNS_CACHE[0].name[0..domain.len()].copy_from_slice(domain.as_bytes());
-        DNS_CACHE[0].name_len = domain.len();
-        DNS_CACHE[0].ip = ip;
-        DNS_CACHE[0].ttl = ttl;
-        DNS_CACHE[0].timestamp = sys_get_uptime_ms();
-        DNS_CACHE[0].valid = true;
+        let entry = &mut DNS_CACHE[slot.unwrap_or(oldest)];
+        entry.name[0..domain.len()].copy_from_slice(domain);
+        entry.name_len = domain.len();
+        entry.ip = ip;
+        entry.ttl = ttl;
+        entry.timestamp = sys_get_uptime_ms();
+        entry.valid = true;
     }
 }
 
-/// Resolve domain name to IP address
-pub fn dns_resolve(domain: &str) -> Result<u32, ()> {
-    // Check cache first
-    if let Some(ip) = dns_cache_lookup(domain) {
-        return Ok(ip);
+/// Source port of the query in flight, where its answer arrives
+pub fn dns_query_port() -> Option<u16> {
+    unsafe { DNS_PENDING.map(|(_, port)| port) }
+}
+
+/// Handle a datagram from a DNS server.
+/// Called by the network loop for UDP traffic to `dns_query_port()`.
+pub fn dns_handle_packet(src_ip: u32, src_port: u16, payload: &[u8]) {
+    if src_port != DNS_SERVER_PORT || src_ip != dns_server() || payload.len() < mem::size_of::<DnsHeader>() {
+        return;
     }
 
     unsafe {
-        if DNS_SERVER == 0 {
-            return Err(()); // No DNS server configured
+        let id = u16::from_be_bytes([payload[0], payload[1]]);
+        if DNS_PENDING.map(|(pending, _)| pending) != Some(id) || DNS_RESPONSE_LEN != 0 {
+            return; // Stale, spoofed or duplicate response
         }
 
-        // Build DNS query
-        let mut packet = [0u8; 512];
-        let mut offset = 0;
+        let len = payload.len().min(DNS_MAX_MESSAGE);
+        DNS_RESPONSE[0..len].copy_from_slice(&payload[0..len]);
+        DNS_RESPONSE_LEN = len;
+    }
+}
+
+/// Send a query and wait for the matching response.
+/// Returns the response length, or an error on timeout, truncation or a server error.
+fn dns_query(name: &[u8], qtype: u16, response: &mut [u8; DNS_MAX_MESSAGE]) -> Result<usize, ()> {
+    let server = dns_server();
+    if server == 0 {
+        return Err(()); // No DNS server configured
+    }
+
+    // Build DNS query
+    let mut packet = [0u8; DNS_MAX_MESSAGE];
+    let mut offset = mem::size_of::<DnsHeader>();
 
-        // DNS header
-        let id = NEXT_DNS_ID;
-        NEXT_DNS_ID = NEXT_DNS_ID.wrapping_add(1);
+    let name_len = encode_domain_name(name, &mut packet[offset..])?;
+    offset += name_len;
+
+    // Question type and class
+    packet[offset..offset + 2].copy_from_slice(&qtype.to_be_bytes());
+    offset += 2;
+    packet[offset..offset + 2].copy_from_slice(&DNS_CLASS_IN.to_be_bytes());
+    offset += 2;
+
+    for _ in 0..DNS_RETRIES {
+        // Fresh ID and port per attempt so a late answer to the previous one is ignored
+        let (id, port) = dns_begin_query()?;
 
         let header = DnsHeader {
             id: id.to_be(),
@@ -250,205 +367,243 @@ pub fn dns_resolve(domain: &str) -> Result<u32, ()> {
             authority: 0,
             additional: 0,
         };
+        let header_bytes = unsafe {
+            core::slice::from_raw_parts(&header as *const _ as *const u8, mem::size_of::<DnsHeader>())
+        };
+        packet[0..mem::size_of::<DnsHeader>()].copy_from_slice(header_bytes);
 
-        let header_bytes = core::slice::from_raw_parts(
-            &header as *const _ as *const u8,
-            mem::size_of::<DnsHeader>()
-        );
-        packet[offset..offset + mem::size_of::<DnsHeader>()].copy_from_slice(header_bytes);
-        offset += mem::size_of::<DnsHeader>();
-
-        // Encode domain name
-        let name_len = encode_domain_name(domain, &mut packet[offset..]);
-        if name_len == 0 {
+        // Send DNS query via UDP
+        if udp::udp_send(server, DNS_SERVER_PORT, port, &packet[0..offset]).is_err() {
+            dns_end_query();
             return Err(());
         }
-        offset += name_len;
 
-        // Question type and class
-        packet[offset..offset + 2].copy_from_slice(&DNS_TYPE_A.to_be_bytes());
-        offset += 2;
-        packet[offset..offset + 2].copy_from_slice(&DNS_CLASS_IN.to_be_bytes());
-        offset += 2;
+        // Wait for response, keeping the rest of the stack running
+        let deadline = sys_get_uptime_ms() + DNS_TIMEOUT_MS;
+        while sys_get_uptime_ms() < deadline {
+            crate::net_poll();
 
-        // Send DNS query via UDP
-        udp::udp_send(DNS_SERVER, 53, 12345, &packet[0..offset])?;
-
-        // Wait for response
-        let mut response = [0u8; 512];
-        for _ in 0..100 {
-            if let Ok((len, src_ip, src_port, _)) = udp::udp_receive(&mut response) {
-                if src_ip == DNS_SERVER && src_port == 53 && len >= mem::size_of::<DnsHeader>() {
-                    // Parse response
-                    let resp_header = &*(response.as_ptr() as *const DnsHeader);
-
-                    if u16::from_be(resp_header.id) == id {
-                        // Correct response
-                        let answers = u16::from_be(resp_header.answers);
-
-                        if answers > 0 {
-                            // Skip questions
-                            let mut resp_offset = mem::size_of::<DnsHeader>();
-
-                            // Skip question name
-                            let mut name_buf = [0u8; 256];
-                            let (_, new_offset) = decode_domain_name(&response, resp_offset, &mut name_buf);
-                            resp_offset = new_offset;
-
-                            // Skip question type and class
-                            resp_offset += 4;
-
-                            // Parse answer
-                            let (_, new_offset) = decode_domain_name(&response, resp_offset, &mut name_buf);
-                            resp_offset = new_offset;
-
-                            // Read RR type, class, TTL, and data length
-                            let rtype = u16::from_be_bytes([response[resp_offset], response[resp_offset + 1]]);
-                            resp_offset += 2;
-                            let _rclass = u16::from_be_bytes([response[resp_offset], response[resp_offset + 1]]);
-                            resp_offset += 2;
-                            let ttl = u32::from_be_bytes([
-                                response[resp_offset],
-                                response[resp_offset + 1],
-                                response[resp_offset + 2],
-                                response[resp_offset + 3],
-                            ]);
-                            resp_offset += 4;
-                            let rdlength = u16::from_be_bytes([response[resp_offset], response[resp_offset + 1]]);
-                            resp_offset += 2;
-
-                            if rtype == DNS_TYPE_A && rdlength == 4 {
-                                // IPv4 address
-                                let ip = u32::from_be_bytes([
-                                    response[resp_offset],
-                                    response[resp_offset + 1],
-                                    response[resp_offset + 2],
-                                    response[resp_offset + 3],
-                                ]);
-
-                                // Add to cache
-                                dns_cache_add(domain, ip, ttl);
-
-                                return Ok(ip);
-                            }
-                        }
-                    }
+            let len = unsafe { DNS_RESPONSE_LEN };
+            if len != 0 {
+                unsafe {
+                    response[0..len].copy_from_slice(&DNS_RESPONSE[0..len]);
                 }
+                dns_end_query();
+
+                let flags = u16::from_be_bytes([response[2], response[3]]);
+                if (flags & DNS_FLAG_QR) == 0 || (flags & DNS_FLAG_RCODE) != 0 {
+                    return Err(()); // Not a response, or NXDOMAIN/SERVFAIL/...
+                }
+                if (flags & DNS_FLAG_TC) != 0 {
+                    // Truncated; there is no TCP fallback, so don't trust a partial answer
+                    return Err(());
+                }
+                return Ok(len);
             }
 
-            // Yield CPU
             crate::syscalls::sys_yield();
         }
+
+        dns_end_query();
     }
 
     Err(()) // Timeout
 }
 
-/// Reverse DNS lookup (PTR)
-pub fn dns_reverse_lookup(ip: u32) -> Result<[u8; 256], ()> {
+/// Pick a random ID and a free random source port for a query and wait
+/// for its answer on them
+fn dns_begin_query() -> Result<(u16, u16), ()> {
+    let mut random = [0u8; 4];
+    sys_get_random(&mut random)?;
+    let id = u16::from_le_bytes([random[0], random[1]]);
+
+    // Walk on from the random start past ports sockets hold
+    let range = (EPHEMERAL_LAST - EPHEMERAL_FIRST) as u32 + 1;
+    let start = u16::from_le_bytes([random[2], random[3]]) as u32;
+    for i in 0..range {
+        let port = EPHEMERAL_FIRST + ((start + i) % range) as u16;
+        if socket::reserve_resolver_port(port).is_ok() {
+            unsafe {
+                DNS_PENDING = Some((id, port));
+                DNS_RESPONSE_LEN = 0;
+            }
+            return Ok((id, port));
+        }
+    }
+    Err(()) // Range exhausted
+}
+
+/// Stop waiting for the query in flight and free its port
+fn dns_end_query() {
     unsafe {
-        if DNS_SERVER == 0 {
-            return Err(()); // No DNS server configured
+        DNS_PENDING = None;
+    }
+    socket::release_resolver_port();
+}
+
+/// Offset of the answer section (after the question section)
+fn skip_questions(response: &[u8]) -> Result<usize, ()> {
+    let questions = u16::from_be_bytes([response[4], response[5]]);
+    let mut offset = mem::size_of::<DnsHeader>();
+    let mut name_buf = [0u8; DNS_MAX_NAME];
+
+    for _ in 0..questions {
+        let (_, next) = decode_domain_name(response, offset, &mut name_buf)?;
+        offset = next + 4; // Skip type and class
+    }
+
+    Ok(offset)
+}
+
+/// Find an IN-class answer record of `rtype` owned by `owner`.
+/// Returns (rdata offset, rdata length, ttl).
+fn find_record(response: &[u8], owner: &[u8], rtype: u16) -> Result<Option<(usize, usize, u32)>, ()> {
+    let answers = u16::from_be_bytes([response[6], response[7]]);
+    let mut offset = skip_questions(response)?;
+    let mut name_buf = [0u8; DNS_MAX_NAME];
+
+    for _ in 0..answers {
+        let (name_len, next) = decode_domain_name(response, offset, &mut name_buf)?;
+        let fixed = response.get(next..next + 10).ok_or(())?;
+
+        let record_type = u16::from_be_bytes([fixed[0], fixed[1]]);
+        let class = u16::from_be_bytes([fixed[2], fixed[3]]);
+        let ttl = u32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]);
+        let rdlength = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+        let rdata = next + 10;
+        if rdata + rdlength > response.len() {
+            return Err(());
         }
 
-        // Format IP for reverse lookup: e.g., 1.2.3.4 becomes 4.3.2.1.in-addr.arpa
-        let ip_bytes = ip.to_be_bytes(); // Convert to network byte order bytes
-        let reverse_domain = alloc::fmt::format(format_args!(
-            "{}.{}.{}.{}.in-addr.arpa",
-            ip_bytes[0], ip_bytes[1], ip_bytes[2], ip_bytes[3]
-        ));
+        if record_type == rtype && class == DNS_CLASS_IN && names_equal(&name_buf[0..name_len], owner) {
+            return Ok(Some((rdata, rdlength, ttl)));
+        }
 
-        // Build DNS query
-        let mut packet = [0u8; 512];
-        let mut offset = 0;
+        offset = rdata + rdlength;
+    }
 
-        // DNS header
-        let id = NEXT_DNS_ID;
-        NEXT_DNS_ID = NEXT_DNS_ID.wrapping_add(1);
+    Ok(None)
+}
 
-        let header = DnsHeader {
-            id: id.to_be(),
-            flags: DNS_FLAG_RD.to_be(), // Recursion desired
-            questions: 1u16.to_be(),
-            answers: 0,
-            authority: 0,
-            additional: 0,
-        };
+/// Extract the address for `name` from an A-query response,
+/// following any CNAME chain contained in the same response
+fn parse_address_response(response: &[u8], name: &[u8]) -> Result<DnsAnswer, ()> {
+    let mut current = [0u8; DNS_MAX_NAME];
+    let mut current_len = name.len().min(DNS_MAX_NAME);
+    current[0..current_len].copy_from_slice(&name[0..current_len]);
+    let mut ttl = u32::MAX;
+    let mut aliased = false;
+
+    for _ in 0..=DNS_MAX_CNAMES {
+        if let Some((rdata, rdlength, record_ttl)) = find_record(response, &current[0..current_len], DNS_TYPE_A)? {
+            if rdlength == 4 {
+                let ip = u32::from_be_bytes([
+                    response[rdata],
+                    response[rdata + 1],
+                    response[rdata + 2],
+                    response[rdata + 3],
+                ]);
+                return Ok(DnsAnswer::Address(ip, ttl.min(record_ttl)));
+            }
+        }
 
-        let header_bytes = core::slice::from_raw_parts(
-            &header as *const _ as *const u8,
-            mem::size_of::<DnsHeader>()
-        );
-        packet[offset..offset + mem::size_of::<DnsHeader>()].copy_from_slice(header_bytes);
-        offset += mem::size_of::<DnsHeader>();
+        match find_record(response, &current[0..current_len], DNS_TYPE_CNAME)? {
+            Some((rdata, _, record_ttl)) => {
+                let mut target = [0u8; DNS_MAX_NAME];
+                let (target_len, _) = decode_domain_name(response, rdata, &mut target)?;
+                current = target;
+                current_len = target_len;
+                ttl = ttl.min(record_ttl);
+                aliased = true;
+            }
+            None => break,
+        }
+    }
 
-        // Encode reverse domain name
-        let name_len = encode_domain_name(&reverse_domain, &mut packet[offset..]);
-        if name_len == 0 {
-            return Err(());
+    if aliased {
+        Ok(DnsAnswer::Alias(current, current_len, ttl))
+    } else {
+        Err(()) // No address for this name
+    }
+}
+
+/// Resolve a hostname to an IPv4 address.
+/// Numeric addresses are parsed directly; results are cached for their TTL.
+pub fn resolve(hostname: &str) -> Result<u32, ()> {
+    let hostname = hostname.as_bytes();
+    if let Some(ip) = parse_ipv4(hostname) {
+        return Ok(ip);
+    }
+
+    // Check cache first
+    if let Some(ip) = dns_cache_lookup(hostname) {
+        return Ok(ip);
+    }
+
+    let mut name = [0u8; DNS_MAX_NAME];
+    let mut name_len = hostname.len().min(DNS_MAX_NAME);
+    name[0..name_len].copy_from_slice(&hostname[0..name_len]);
+    let mut ttl = u32::MAX;
+    let mut response = [0u8; DNS_MAX_MESSAGE];
+
+    // Each alias whose target is not in the response costs another query
+    for _ in 0..=DNS_MAX_CNAMES {
+        let len = dns_query(&name[0..name_len], DNS_TYPE_A, &mut response)?;
+
+        match parse_address_response(&response[0..len], &name[0..name_len])? {
+            DnsAnswer::Address(ip, record_ttl) => {
+                dns_cache_add(hostname, ip, ttl.min(record_ttl));
+                return Ok(ip);
+            }
+            DnsAnswer::Alias(target, target_len, record_ttl) => {
+                name = target;
+                name_len = target_len;
+                ttl = ttl.min(record_ttl);
+            }
         }
-        offset += name_len;
+    }
 
-        // Question type (PTR) and class (IN)
-        packet[offset..offset + 2].copy_from_slice(&DNS_TYPE_PTR.to_be_bytes());
-        offset += 2;
-        packet[offset..offset + 2].copy_from_slice(&DNS_CLASS_IN.to_be_bytes());
-        offset += 2;
+    Err(()) // CNAME chain too long
+}
 
-        // Send DNS query via UDP
-        udp::udp_send(DNS_SERVER, 53, 12345, &packet[0..offset])?;
-
-        // Wait for response
-        let mut response = [0u8; 512];
-        for _ in 0..100 {
-            if let Ok((len, src_ip, src_port, _)) = udp::udp_receive(&mut response) {
-                if src_ip == DNS_SERVER && src_port == 53 && len >= mem::size_of::<DnsHeader>() {
-                    // Parse response
-                    let resp_header = &*(response.as_ptr() as *const DnsHeader);
-
-                    if u16::from_be(resp_header.id) == id {
-                        let answers = u16::from_be(resp_header.answers);
-
-                        if answers > 0 {
-                            let mut resp_offset = mem::size_of::<DnsHeader>();
-
-                            // Skip questions
-                            let mut name_buf = [0u8; 256];
-                            let (_, new_offset) = decode_domain_name(&response, resp_offset, &mut name_buf);
-                            resp_offset = new_offset;
-                            resp_offset += 4; // Skip type and class
-
-                            // Parse answer
-                            let (decoded_len, new_offset) = decode_domain_name(&response, resp_offset, &mut name_buf);
-                            resp_offset = new_offset;
-
-                            let rtype = u16::from_be_bytes([response[resp_offset], response[resp_offset + 1]]);
-                            resp_offset += 2;
-                            let _rclass = u16::from_be_bytes([response[resp_offset], response[resp_offset + 1]]);
-                            resp_offset += 2;
-                            let _ttl = u32::from_be_bytes([
-                                response[resp_offset], response[resp_offset + 1],
-                                response[resp_offset + 2], response[resp_offset + 3],
-                            ]);
-                            resp_offset += 4;
-                            let rdlength = u16::from_be_bytes([response[resp_offset], response[resp_offset + 1]]);
-                            resp_offset += 2;
-
-                            if rtype == DNS_TYPE_PTR {
-                                // Extract the PTR data (domain name)
-                                let (name_len, _) = decode_domain_name(&response, resp_offset, &mut name_buf);
-                                if name_len > 0 {
-                                    let mut result_name = [0u8; 256];
-                                    result_name[0..name_len].copy_from_slice(&name_buf[0..name_len]);
-                                    return Ok(result_name);
-                                }
-                            }
-                        }
-                    }
-                }
+/// Reverse DNS lookup (PTR)
+pub fn dns_reverse_lookup(ip: u32) -> Result<[u8; 256], ()> {
+    // Format IP for reverse lookup: e.g., 1.2.3.4 becomes 4.3.2.1.in-addr.arpa
+    let mut reverse_domain = [0u8; 32];
+    let mut len = 0;
+    for &octet in ip.to_be_bytes().iter().rev() {
+        let mut digits = [0u8; 3];
+        let mut count = 0;
+        let mut value = octet;
+        loop {
+            digits[count] = b'0' + value % 10;
+            count += 1;
+            value /= 10;
+            if value == 0 {
+                break;
             }
-            crate::syscalls::sys_yield();
         }
+        for i in (0..count).rev() {
+            reverse_domain[len] = digits[i];
+            len += 1;
+        }
+        reverse_domain[len] = b'.';
+        len += 1;
     }
+    reverse_domain[len..len + 12].copy_from_slice(b"in-addr.arpa");
+    len += 12;
+
+    let mut response = [0u8; DNS_MAX_MESSAGE];
+    let response_len = dns_query(&reverse_domain[0..len], DNS_TYPE_PTR, &mut response)?;
+
+    // Extract the PTR data (domain name)
+    if let Some((rdata, _, _)) = find_record(&response[0..response_len], &reverse_domain[0..len], DNS_TYPE_PTR)? {
+        let mut result_name = [0u8; 256];
+        let (name_len, _) = decode_domain_name(&response[0..response_len], rdata, &mut result_name)?;
+        if name_len > 0 {
+            return Ok(result_name);
+        }
+    }
+
     Err(())
 }
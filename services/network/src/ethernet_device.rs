@@ -2,235 +2,379 @@
 
 use crate::ipc::{IpcMessage, ipc_send, ipc_receive, sys_ipc_send, sys_ipc_receive};
 
-/// Ethernet device service port
-static mut ETHERNET_DEV_PORT: u64 = 0;
-
-/// Set Ethernet device port
-pub fn set_ethernet_device_port(port: u64) {
-    unsafe {
-        ETHERNET_DEV_PORT = port;
-    }
+/// Link state reported by a driver
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct LinkStatus {
+    pub up: bool,
+    pub speed_mbps: u16,
+    pub full_duplex: bool,
 }
 
-/// Send packet via Ethernet device
-pub fn send_packet(data: &[u8]) -> Result<(), ()> {
-    unsafe {
-        if ETHERNET_DEV_PORT == 0 {
-            return Err(()); // Driver not available
-        }
-        
-        let mut request = IpcMessage::new();
-        request.msg_id = 1; // NET_DEV_OP_SEND
-        request.msg_type = crate::ipc::IPC_MSG_REQUEST;
-        
-        // Copy packet data to inline_data (limited to 64 bytes)
-        let copy_len = data.len().min(64);
-        request.inline_data[0..copy_len].copy_from_slice(&data[0..copy_len]);
-        request.inline_size = copy_len as u32;
-        
-        // For larger packets, data would be in request.buffer
-        
-        // Send request with retry logic
-        let mut retries = 3;
-        loop {
-            match ipc_send(ETHERNET_DEV_PORT, &request) {
-                Ok(_) => break,
-                Err(_) => {
-                    retries -= 1;
-                    if retries == 0 {
-                        return Err(()); // Failed after retries
-                    }
-                    crate::syscalls::sys_yield();
+/// Send packet via the Ethernet driver listening on `port`
+pub fn send_packet(port: u64, data: &[u8]) -> Result<(), ()> {
+    if port == 0 {
+        return Err(()); // Driver not available
+    }
+    
+    let mut request = IpcMessage::new();
+    request.msg_id = 1; // NET_DEV_OP_SEND
+    request.msg_type = crate::ipc::IPC_MSG_REQUEST;
+    
+    // Copy packet data to inline_data (limited to 64 bytes)
+    let copy_len = data.len().min(64);
+    request.inline_data[0..copy_len].copy_from_slice(&data[0..copy_len]);
+    request.inline_size = copy_len as u32;
+    
+    // For larger packets, data would be in request.buffer
+    
+    // Send request with retry logic
+    let mut retries = 3;
+    loop {
+        match ipc_send(port, &request) {
+            Ok(_) => break,
+            Err(_) => {
+                retries -= 1;
+                if retries == 0 {
+                    return Err(()); // Failed after retries
                 }
+                crate::syscalls::sys_yield();
             }
         }
-        
-        // Receive response with retry logic
-        let mut response = IpcMessage::new();
-        retries = 3;
-        loop {
-            match ipc_receive(ETHERNET_DEV_PORT, &mut response) {
-                Ok(_) => break,
-                Err(_) => {
-                    retries -= 1;
-                    if retries == 0 {
-                        return Err(()); // Failed after retries
-                    }
-                    crate::syscalls::sys_yield();
+    }
+    
+    // Receive response with retry logic
+    let mut response = IpcMessage::new();
+    retries = 3;
+    loop {
+        match ipc_receive(port, &mut response) {
+            Ok(_) => break,
+            Err(_) => {
+                retries -= 1;
+                if retries == 0 {
+                    return Err(()); // Failed after retries
                 }
+                crate::syscalls::sys_yield();
             }
         }
-        
-        // Check success
-        if response.inline_size > 0 && response.inline_data[0] == 0 {
-            Ok(())
-        } else {
-            Err(())
-        }
+    }
+    
+    // Check success
+    if response.inline_size > 0 && response.inline_data[0] == 0 {
+        Ok(())
+    } else {
+        Err(())
     }
 }
 
-/// Receive packet from Ethernet device
+/// Receive packet from the Ethernet driver listening on `port`
 /// Returns packet data in provided buffer
-pub fn receive_packet(buffer: &mut [u8]) -> Result<usize, ()> {
-    unsafe {
-        if ETHERNET_DEV_PORT == 0 {
-            return Err(());
-        }
-        
-        let mut request = IpcMessage::new();
-        request.msg_id = 2; // NET_DEV_OP_RECEIVE
-        request.msg_type = crate::ipc::IPC_MSG_REQUEST;
-        
-        // Send request with retry logic
-        let mut retries = 3;
-        loop {
-            match ipc_send(ETHERNET_DEV_PORT, &request) {
-                Ok(_) => break,
-                Err(_) => {
-                    retries -= 1;
-                    if retries == 0 {
-                        return Err(()); // Failed after retries
-                    }
-                    crate::syscalls::sys_yield();
+pub fn receive_packet(port: u64, buffer: &mut [u8]) -> Result<usize, ()> {
+    if port == 0 {
+        return Err(());
+    }
+    
+    let mut request = IpcMessage::new();
+    request.msg_id = 2; // NET_DEV_OP_RECEIVE
+    request.msg_type = crate::ipc::IPC_MSG_REQUEST;
+    
+    // Send request with retry logic
+    let mut retries = 3;
+    loop {
+        match ipc_send(port, &request) {
+            Ok(_) => break,
+            Err(_) => {
+                retries -= 1;
+                if retries == 0 {
+                    return Err(()); // Failed after retries
                 }
+                crate::syscalls::sys_yield();
             }
         }
-        
-        // Receive response with retry logic
-        let mut response = IpcMessage::new();
-        retries = 3;
-        loop {
-            match ipc_receive(ETHERNET_DEV_PORT, &mut response) {
-                Ok(_) => break,
-                Err(_) => {
-                    retries -= 1;
-                    if retries == 0 {
-                        return Err(()); // Failed after retries
-                    }
-                    crate::syscalls::sys_yield();
+    }
+    
+    // Receive response with retry logic
+    let mut response = IpcMessage::new();
+    retries = 3;
+    loop {
+        match ipc_receive(port, &mut response) {
+            Ok(_) => break,
+            Err(_) => {
+                retries -= 1;
+                if retries == 0 {
+                    return Err(()); // Failed after retries
                 }
+                crate::syscalls::sys_yield();
             }
         }
-        
-        // Extract packet data
-        if response.inline_size > 0 && response.inline_data[0] != 1 {
-            let copy_len = buffer.len().min(response.inline_size as usize);
-            buffer[0..copy_len].copy_from_slice(&response.inline_data[0..copy_len]);
-            Ok(copy_len)
-        } else {
-            Err(()) // No packet available
-        }
+    }
+    
+    // Extract packet data
+    if response.inline_size > 0 && response.inline_data[0] != 1 {
+        let copy_len = buffer.len().min(response.inline_size as usize);
+        buffer[0..copy_len].copy_from_slice(&response.inline_data[0..copy_len]);
+        Ok(copy_len)
+    } else {
+        Err(()) // No packet available
     }
 }
 
 /// Get MAC address from Ethernet device
-pub fn get_mac_address() -> Result<[u8; 6], ()> {
-    unsafe {
-        if ETHERNET_DEV_PORT == 0 {
-            return Err(());
+pub fn get_mac_address(port: u64) -> Result<[u8; 6], ()> {
+    if port == 0 {
+        return Err(());
+    }
+    
+    let mut request = IpcMessage::new();
+    request.msg_id = 3; // NET_DEV_OP_GET_MAC
+    request.msg_type = crate::ipc::IPC_MSG_REQUEST;
+    
+    // Send request with retry logic
+    let mut retries = 3;
+    loop {
+        match ipc_send(port, &request) {
+            Ok(_) => break,
+            Err(_) => {
+                retries -= 1;
+                if retries == 0 {
+                    return Err(()); // Failed after retries
+                }
+                crate::syscalls::sys_yield();
+            }
         }
-        
-        let mut request = IpcMessage::new();
-        request.msg_id = 3; // NET_DEV_OP_GET_MAC
-        request.msg_type = crate::ipc::IPC_MSG_REQUEST;
-        
-        // Send request with retry logic
-        let mut retries = 3;
-        loop {
-            match ipc_send(ETHERNET_DEV_PORT, &request) {
-                Ok(_) => break,
-                Err(_) => {
-                    retries -= 1;
-                    if retries == 0 {
-                        return Err(()); // Failed after retries
-                    }
-                    crate::syscalls::sys_yield();
+    }
+    
+    // Receive response with retry logic
+    let mut response = IpcMessage::new();
+    retries = 3;
+    loop {
+        match ipc_receive(port, &mut response) {
+            Ok(_) => break,
+            Err(_) => {
+                retries -= 1;
+                if retries == 0 {
+                    return Err(()); // Failed after retries
                 }
+                crate::syscalls::sys_yield();
             }
         }
-        
-        // Receive response with retry logic
-        let mut response = IpcMessage::new();
-        retries = 3;
-        loop {
-            match ipc_receive(ETHERNET_DEV_PORT, &mut response) {
-                Ok(_) => break,
-                Err(_) => {
-                    retries -= 1;
-                    if retries == 0 {
-                        return Err(()); // Failed after retries
-                    }
-                    crate::syscalls::sys_yield();
+    }
+    
+    // Extract MAC address
+    if response.inline_size >= 6 {
+        let mut mac = [0u8; 6];
+        mac.copy_from_slice(&response.inline_data[0..6]);
+        Ok(mac)
+    } else {
+        Err(())
+    }
+}
+
+/// Set IP configuration on Ethernet device
+pub fn set_ip_config(port: u64, ip: u32, netmask: u32, gateway: u32) -> Result<(), ()> {
+    if port == 0 {
+        return Err(());
+    }
+    
+    let mut request = IpcMessage::new();
+    request.msg_id = 4; // NET_DEV_OP_SET_IP
+    request.msg_type = crate::ipc::IPC_MSG_REQUEST;
+    
+    // Pack IP configuration
+    request.inline_data[0..4].copy_from_slice(&ip.to_le_bytes());
+    request.inline_data[4..8].copy_from_slice(&netmask.to_le_bytes());
+    request.inline_data[8..12].copy_from_slice(&gateway.to_le_bytes());
+    request.inline_size = 12;
+    
+    // Send request with retry logic
+    let mut retries = 3;
+    loop {
+        match ipc_send(port, &request) {
+            Ok(_) => break,
+            Err(_) => {
+                retries -= 1;
+                if retries == 0 {
+                    return Err(()); // Failed after retries
                 }
+                crate::syscalls::sys_yield();
             }
         }
-        
-        // Extract MAC address
-        if response.inline_size >= 6 {
-            let mut mac = [0u8; 6];
-            mac.copy_from_slice(&response.inline_data[0..6]);
-            Ok(mac)
-        } else {
-            Err(())
+    }
+    
+    // Receive response with retry logic
+    let mut response = IpcMessage::new();
+    retries = 3;
+    loop {
+        match ipc_receive(port, &mut response) {
+            Ok(_) => break,
+            Err(_) => {
+                retries -= 1;
+                if retries == 0 {
+                    return Err(()); // Failed after retries
+                }
+                crate::syscalls::sys_yield();
+            }
         }
     }
+    
+    // Check success
+    if response.inline_size > 0 && response.inline_data[0] == 0 {
+        Ok(())
+    } else {
+        Err(())
+    }
 }
 
-/// Set IP configuration on Ethernet device
-pub fn set_ip_config(ip: u32, netmask: u32, gateway: u32) -> Result<(), ()> {
-    unsafe {
-        if ETHERNET_DEV_PORT == 0 {
-            return Err(());
+/// Have the Ethernet device accept frames sent to a multicast MAC
+pub fn add_multicast(port: u64, mac: &[u8; 6]) -> Result<(), ()> {
+    if port == 0 {
+        return Err(());
+    }
+    
+    let mut request = IpcMessage::new();
+    request.msg_id = 6; // NET_DEV_OP_ADD_MULTICAST
+    request.msg_type = crate::ipc::IPC_MSG_REQUEST;
+    request.inline_data[0..6].copy_from_slice(mac);
+    request.inline_size = 6;
+    
+    // Send request with retry logic
+    let mut retries = 3;
+    loop {
+        match ipc_send(port, &request) {
+            Ok(_) => break,
+            Err(_) => {
+                retries -= 1;
+                if retries == 0 {
+                    return Err(()); // Failed after retries
+                }
+                crate::syscalls::sys_yield();
+            }
         }
-        
-        let mut request = IpcMessage::new();
-        request.msg_id = 4; // NET_DEV_OP_SET_IP
-        request.msg_type = crate::ipc::IPC_MSG_REQUEST;
-        
-        // Pack IP configuration
-        request.inline_data[0..4].copy_from_slice(&ip.to_le_bytes());
-        request.inline_data[4..8].copy_from_slice(&netmask.to_le_bytes());
-        request.inline_data[8..12].copy_from_slice(&gateway.to_le_bytes());
-        request.inline_size = 12;
-        
-        // Send request with retry logic
-        let mut retries = 3;
-        loop {
-            match ipc_send(ETHERNET_DEV_PORT, &request) {
-                Ok(_) => break,
-                Err(_) => {
-                    retries -= 1;
-                    if retries == 0 {
-                        return Err(()); // Failed after retries
-                    }
-                    crate::syscalls::sys_yield();
+    }
+    
+    // Receive response with retry logic
+    let mut response = IpcMessage::new();
+    retries = 3;
+    loop {
+        match ipc_receive(port, &mut response) {
+            Ok(_) => break,
+            Err(_) => {
+                retries -= 1;
+                if retries == 0 {
+                    return Err(()); // Failed after retries
                 }
+                crate::syscalls::sys_yield();
             }
         }
-        
-        // Receive response with retry logic
-        let mut response = IpcMessage::new();
-        retries = 3;
-        loop {
-            match ipc_receive(ETHERNET_DEV_PORT, &mut response) {
-                Ok(_) => break,
-                Err(_) => {
-                    retries -= 1;
-                    if retries == 0 {
-                        return Err(()); // Failed after retries
-                    }
-                    crate::syscalls::sys_yield();
+    }
+    
+    // Check success
+    if response.inline_size > 0 && response.inline_data[0] == 0 {
+        Ok(())
+    } else {
+        Err(())
+    }
+}
+
+/// Query link up/down and negotiated speed from the Ethernet device
+pub fn get_link_status(port: u64) -> Result<LinkStatus, ()> {
+    if port == 0 {
+        return Err(());
+    }
+    
+    let mut request = IpcMessage::new();
+    request.msg_id = 5; // NET_DEV_OP_LINK_STATUS
+    request.msg_type = crate::ipc::IPC_MSG_REQUEST;
+    
+    // Send request with retry logic
+    let mut retries = 3;
+    loop {
+        match ipc_send(port, &request) {
+            Ok(_) => break,
+            Err(_) => {
+                retries -= 1;
+                if retries == 0 {
+                    return Err(()); // Failed after retries
                 }
+                crate::syscalls::sys_yield();
             }
         }
-        
-        // Check success
-        if response.inline_size > 0 && response.inline_data[0] == 0 {
-            Ok(())
-        } else {
-            Err(())
+    }
+    
+    // Receive response with retry logic
+    let mut response = IpcMessage::new();
+    retries = 3;
+    loop {
+        match ipc_receive(port, &mut response) {
+            Ok(_) => break,
+            Err(_) => {
+                retries -= 1;
+                if retries == 0 {
+                    return Err(()); // Failed after retries
+                }
+                crate::syscalls::sys_yield();
+            }
         }
     }
+    
+    // Up (u8), speed in Mbps (u16 LE), full duplex (u8)
+    if response.inline_size >= 4 {
+        Ok(LinkStatus {
+            up: response.inline_data[0] != 0,
+            speed_mbps: u16::from_le_bytes([response.inline_data[1], response.inline_data[2]]),
+            full_duplex: response.inline_data[3] != 0,
+        })
+    } else {
+        Err(())
+    }
 }
 
+/// Read the device's cumulative traffic and error counters
+pub fn get_stats(port: u64) -> Result<crate::network::NetDeviceStats, ()> {
+    if port == 0 {
+        return Err(());
+    }
+    
+    let mut request = IpcMessage::new();
+    request.msg_id = 8; // NET_DEV_OP_GET_STATS
+    request.msg_type = crate::ipc::IPC_MSG_REQUEST;
+    
+    // Send request with retry logic
+    let mut retries = 3;
+    loop {
+        match ipc_send(port, &request) {
+            Ok(_) => break,
+            Err(_) => {
+                retries -= 1;
+                if retries == 0 {
+                    return Err(()); // Failed after retries
+                }
+                crate::syscalls::sys_yield();
+            }
+        }
+    }
+    
+    // Receive response with retry logic
+    let mut response = IpcMessage::new();
+    retries = 3;
+    loop {
+        match ipc_receive(port, &mut response) {
+            Ok(_) => break,
+            Err(_) => {
+                retries -= 1;
+                if retries == 0 {
+                    return Err(()); // Failed after retries
+                }
+                crate::syscalls::sys_yield();
+            }
+        }
+    }
+    
+    // 8 x u64 LE counters
+    if response.inline_size >= 64 {
+        let mut bytes = [0u8; 64];
+        bytes.copy_from_slice(&response.inline_data[0..64]);
+        Ok(crate::network::NetDeviceStats::from_bytes(&bytes))
+    } else {
+        Err(())
+    }
+}
@@ -0,0 +1,37 @@
+//! Queue of whole frames kept in a socket's receive buffer
+//!
+//! Each frame is stored as a little-endian u16 length followed by its
+//! bytes, oldest first. Frames that do not fit are dropped rather than
+//! split, so a reader always gets complete frames.
+
+const RECORD_HEADER: usize = 2;
+
+/// Append `frame` if it fits within `capacity` bytes of `buf`.
+/// Returns false if the frame was dropped.
+pub fn push_frame(buf: &mut [u8], len: &mut usize, capacity: usize, frame: &[u8]) -> bool {
+    let capacity = capacity.min(buf.len());
+    let record = RECORD_HEADER + frame.len();
+    if frame.is_empty() || frame.len() > u16::MAX as usize || *len + record > capacity {
+        return false;
+    }
+    buf[*len..*len + RECORD_HEADER].copy_from_slice(&(frame.len() as u16).to_le_bytes());
+    buf[*len + RECORD_HEADER..*len + record].copy_from_slice(frame);
+    *len += record;
+    true
+}
+
+/// Remove the oldest frame, copying as much of it as fits into `out`.
+/// Returns the number of bytes copied; the rest of a long frame is lost.
+pub fn pop_frame(buf: &mut [u8], len: &mut usize, out: &mut [u8]) -> Option<usize> {
+    if *len < RECORD_HEADER {
+        return None;
+    }
+    let frame_len = u16::from_le_bytes([buf[0], buf[1]]) as usize;
+    let record = (RECORD_HEADER + frame_len).min(*len);
+    let copied = frame_len.min(out.len()).min(record - RECORD_HEADER);
+    out[..copied].copy_from_slice(&buf[RECORD_HEADER..RECORD_HEADER + copied]);
+
+    buf.copy_within(record..*len, 0);
+    *len -= record;
+    Some(copied)
+}
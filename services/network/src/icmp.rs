@@ -60,7 +60,7 @@ pub fn icmp_ping(dest_ip: u32, sequence: u16, data: &[u8]) -> Result<(), ()> {
     let mut packet = [0u8; 1500];
 
     // ICMP header
-    let mut icmp = IcmpHeader {
+    let icmp = IcmpHeader {
         icmp_type: ICMP_TYPE_ECHO_REQUEST,
         code: 0,
         checksum: 0,
@@ -1,6 +1,7 @@
 //! IP protocol implementation
 
-use core::mem;
+pub use crate::multicast::is_multicast;
+use crate::multicast::link_destination;
 
 /// IP header structure
 #[repr(C, packed)]
@@ -23,93 +24,251 @@ pub const IP_PROTOCOL_ICMP: u8 = 1;
 pub const IP_PROTOCOL_TCP: u8 = 6;
 pub const IP_PROTOCOL_UDP: u8 = 17;
 
+/// IP header length without options
+pub const IP_HEADER_LEN: usize = 20;
+
+/// Ethernet header length and the EtherType carried by IPv4 frames
+pub const ETH_HEADER_LEN: usize = 14;
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+
+/// Limited broadcast address
+pub const IP_BROADCAST: u32 = 0xFFFF_FFFF;
+
+/// Don't Fragment flag (we never fragment outgoing datagrams)
+const IP_FLAG_DF: u16 = 0x4000;
+const IP_FLAG_MF: u16 = 0x2000;
+const IP_FRAGMENT_OFFSET_MASK: u16 = 0x1FFF;
+
+static mut NEXT_IP_ID: u16 = 1;
+static mut IP_CHECKSUM_ERRORS: u64 = 0;
+
+/// Parsed IPv4 datagram
+pub struct Ipv4Packet<'a> {
+    pub src_ip: u32,
+    pub dst_ip: u32,
+    pub protocol: u8,
+    pub ttl: u8,
+    pub payload: &'a [u8],
+}
+
+/// Add data to a running one's-complement sum (odd trailing byte is padded with zero)
+pub fn checksum_add(mut sum: u32, data: &[u8]) -> u32 {
+    let mut words = data.chunks_exact(2);
+    for word in &mut words {
+        sum += u16::from_be_bytes([word[0], word[1]]) as u32;
+    }
+    if let [last] = words.remainder() {
+        sum += (*last as u32) << 8;
+    }
+    sum
+}
+
+/// Fold carries of a one's-complement sum into 16 bits
+pub fn checksum_fold(mut sum: u32) -> u16 {
+    while (sum >> 16) != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    sum as u16
+}
+
+/// Internet checksum (RFC 1071) of a byte slice.
+/// Returns 0 when run over data that already contains a valid checksum.
+pub fn inet_checksum(data: &[u8]) -> u16 {
+    !checksum_fold(checksum_add(0, data))
+}
+
+/// TCP/UDP checksum over the IPv4 pseudo-header and the transport segment
+pub fn transport_checksum(src_ip: u32, dst_ip: u32, protocol: u8, segment: &[u8]) -> u16 {
+    let mut sum = checksum_add(0, &src_ip.to_be_bytes());
+    sum = checksum_add(sum, &dst_ip.to_be_bytes());
+    sum = checksum_add(sum, &[0, protocol]);
+    sum = checksum_add(sum, &(segment.len() as u16).to_be_bytes());
+    sum = checksum_add(sum, segment);
+    !checksum_fold(sum)
+}
+
 /// Calculate IP checksum
 pub fn ip_checksum(header: &IpHeader) -> u16 {
-    let mut sum: u32 = 0;
     let header_len = ((header.version_ihl & 0x0F) * 4) as usize;
-    let words = unsafe {
-        core::slice::from_raw_parts(header as *const _ as *const u16, header_len / 2)
+    let bytes = unsafe {
+        core::slice::from_raw_parts(header as *const _ as *const u8, header_len)
     };
-    
-    for &word in words {
-        sum += u16::from_be_bytes(word.to_le_bytes()) as u32;
+    inet_checksum(bytes)
+}
+
+/// Number of received datagrams dropped for a bad header checksum
+pub fn ip_checksum_errors() -> u64 {
+    unsafe { IP_CHECKSUM_ERRORS }
+}
+
+/// Source address used for datagrams to `dest_ip`: the address of the
+/// outgoing device, or the destination itself when it is one of ours
+pub fn ip_source_address(dest_ip: u32) -> u32 {
+    match crate::route::route_lookup(dest_ip) {
+        Some((device, _)) => ip_source_address_on(device, dest_ip),
+        None => 0,
     }
-    
-    while (sum >> 16) != 0 {
-        sum = (sum & 0xFFFF) + (sum >> 16);
+}
+
+/// Source address used for datagrams to `dest_ip` sent out of `device`
+pub fn ip_source_address_on(device: usize, dest_ip: u32) -> u32 {
+    if crate::network::is_loopback(device) {
+        if crate::network::find_device_by_ip(dest_ip).is_some() {
+            return dest_ip;
+        }
+        return crate::loopback::LOOPBACK_ADDR;
+    }
+    crate::network::get_device(device).map(|dev| dev.ip_address).unwrap_or(0)
+}
+
+/// Next hop for `dest_ip`: the destination itself when on-link,
+/// otherwise the gateway of the matching route
+pub fn ip_next_hop(dest_ip: u32) -> u32 {
+    crate::route::route_lookup(dest_ip).map(|(_, next_hop)| next_hop).unwrap_or(dest_ip)
+}
+
+/// Is `dest_ip` the limited broadcast or the directed broadcast of the device's subnet?
+pub fn is_broadcast(dest_ip: u32, device: usize) -> bool {
+    if dest_ip == IP_BROADCAST {
+        return true;
+    }
+    match crate::network::get_device(device) {
+        Some(dev) if dev.netmask != 0 && dev.netmask != IP_BROADCAST => {
+            (dest_ip & dev.netmask) == (dev.ip_address & dev.netmask)
+                && (dest_ip & !dev.netmask) == !dev.netmask
+        }
+        _ => false,
     }
-    
-    !(sum as u16).to_be()
 }
 
 /// Send IP packet
+/// The routing table picks the device and next hop. Datagrams for the
+/// loopback device are queued for local delivery; otherwise the frame is
+/// sent immediately if the next-hop MAC is cached, or queued in the ARP
+/// layer until resolution completes.
 pub fn ip_send(dest_ip: u32, protocol: u8, data: &[u8]) -> Result<(), ()> {
-    // Get network device via IPC
-    // For now, use first available device
-    // Allocate packet buffer
-    let mut packet = [0u8; 1500];
-    
-    // Build IP header
-    let mut ip_header = IpHeader {
-        version_ihl: 0x45, // IPv4, 5 * 4 = 20 bytes header
-        tos: 0,
-        total_length: (20 + data.len()) as u16,
-        identification: 0,
-        flags_fragment: 0,
-        ttl: 64,
-        protocol,
-        checksum: 0,
-        src_ip: 0, // Would get from network device
-        dst_ip: dest_ip,
+    let (device, next_hop) = crate::route::route_lookup(dest_ip).ok_or(())?;
+    ip_transmit(device, next_hop, dest_ip, protocol, data)
+}
+
+/// Send IP packet out of a specific device, bypassing the routing decision.
+/// Used by per-interface protocols such as DHCP.
+pub fn ip_send_on(device: usize, dest_ip: u32, protocol: u8, data: &[u8]) -> Result<(), ()> {
+    let next_hop = match crate::route::route_lookup(dest_ip) {
+        Some((route_device, next_hop)) if route_device == device => next_hop,
+        _ => dest_ip,
     };
-    
-    // Calculate checksum
-    ip_header.checksum = ip_checksum(&ip_header);
-    
-    // Copy header and data to packet
-    unsafe {
-        core::ptr::copy_nonoverlapping(&ip_header as *const _ as *const u8, packet.as_mut_ptr(), 20);
-    }
-    let data_len = data.len().min(1480);
-    packet[20..20+data_len].copy_from_slice(&data[0..data_len]);
-    
-    // Send via Ethernet
-    use crate::ethernet_device::send_packet;
-    let _ = send_packet(&packet[0..20+data_len]);
-    
-    Ok(())
+    ip_transmit(device, next_hop, dest_ip, protocol, data)
+}
+
+/// Build the datagram and hand it to the device (or the ARP queue)
+fn ip_transmit(device: usize, next_hop: u32, dest_ip: u32, protocol: u8, data: &[u8]) -> Result<(), ()> {
+    if !crate::network::is_link_up(device) {
+        return Err(());
+    }
+
+    let mut frame = [0u8; ETH_HEADER_LEN + 1500];
+    let packet = &mut frame[ETH_HEADER_LEN..];
+
+    let data_len = data.len().min(packet.len() - IP_HEADER_LEN);
+    let total_len = IP_HEADER_LEN + data_len;
+    let id = unsafe {
+        let id = NEXT_IP_ID;
+        NEXT_IP_ID = NEXT_IP_ID.wrapping_add(1);
+        id
+    };
+
+    // Build IP header (network byte order)
+    packet[0] = 0x45; // IPv4, 5 * 4 = 20 bytes header
+    packet[1] = 0;
+    packet[2..4].copy_from_slice(&(total_len as u16).to_be_bytes());
+    packet[4..6].copy_from_slice(&id.to_be_bytes());
+    packet[6..8].copy_from_slice(&IP_FLAG_DF.to_be_bytes());
+    packet[8] = 64;
+    packet[9] = protocol;
+    packet[12..16].copy_from_slice(&ip_source_address_on(device, dest_ip).to_be_bytes());
+    packet[16..20].copy_from_slice(&dest_ip.to_be_bytes());
+
+    let checksum = inet_checksum(&packet[0..IP_HEADER_LEN]);
+    packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+    packet[IP_HEADER_LEN..total_len].copy_from_slice(&data[0..data_len]);
+
+    if crate::network::is_loopback(device) {
+        return crate::loopback::loopback_send(&packet[0..total_len]);
+    }
+
+    // Ethernet header; destination MAC is filled in once the next hop is known
+    let src_mac = crate::network::get_device(device).map(|dev| dev.mac_address).unwrap_or([0; 6]);
+    frame[6..12].copy_from_slice(&src_mac);
+    frame[12..14].copy_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+    let frame_len = ETH_HEADER_LEN + total_len;
+
+    use crate::network::device_send;
+    if let Some(mac) = link_destination(dest_ip, is_broadcast(dest_ip, device)) {
+        frame[0..6].copy_from_slice(&mac);
+        return device_send(device, &frame[0..frame_len]);
+    }
+
+    match crate::arp::arp_resolve(device, next_hop) {
+        Some(mac) => {
+            frame[0..6].copy_from_slice(&mac);
+            device_send(device, &frame[0..frame_len])
+        }
+        None => crate::arp::arp_queue_frame(device, next_hop, &frame[0..frame_len]),
+    }
+}
+
+/// Validate an IPv4 datagram and split it into header fields and payload.
+/// Datagrams with a bad header checksum are counted and dropped;
+/// fragments are dropped since there is no reassembly.
+pub fn ip_parse(packet: &[u8]) -> Result<Ipv4Packet<'_>, ()> {
+    if packet.len() < IP_HEADER_LEN || (packet[0] >> 4) != 4 {
+        return Err(());
+    }
+
+    let header_len = ((packet[0] & 0x0F) as usize) * 4;
+    let total_len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+    if header_len < IP_HEADER_LEN || total_len < header_len || total_len > packet.len() {
+        return Err(());
+    }
+
+    if inet_checksum(&packet[0..header_len]) != 0 {
+        unsafe {
+            IP_CHECKSUM_ERRORS += 1;
+        }
+        return Err(());
+    }
+
+    let flags_fragment = u16::from_be_bytes([packet[6], packet[7]]);
+    if (flags_fragment & IP_FLAG_MF) != 0 || (flags_fragment & IP_FRAGMENT_OFFSET_MASK) != 0 {
+        return Err(());
+    }
+
+    Ok(Ipv4Packet {
+        src_ip: u32::from_be_bytes([packet[12], packet[13], packet[14], packet[15]]),
+        dst_ip: u32::from_be_bytes([packet[16], packet[17], packet[18], packet[19]]),
+        protocol: packet[9],
+        ttl: packet[8],
+        payload: &packet[header_len..total_len],
+    })
 }
 
 /// Receive IP packet
 pub fn ip_receive(buffer: &mut [u8]) -> Result<(usize, u32, u8), ()> {
     // Receive from Ethernet layer
-    use crate::ethernet_device::receive_packet;
     let mut eth_buffer = [0u8; 1518];
-    match receive_packet(&mut eth_buffer) {
-        Ok(len) => {
-            if len < 20 {
-                return Err(());
-            }
-            // Parse IP header
-            let ip_header = unsafe {
-                &*(eth_buffer.as_ptr().add(14) as *const IpHeader) // Skip 14-byte Ethernet header
-            };
-            
-            // Verify IP version
-            if (ip_header.version_ihl >> 4) != 4 {
-                return Err(());
-            }
-            
-            // Extract data
-            let header_len = ((ip_header.version_ihl & 0x0F) * 4) as usize;
-            let data_len = (ip_header.total_length as usize).saturating_sub(header_len);
-            let copy_len = data_len.min(buffer.len());
-            buffer[0..copy_len].copy_from_slice(&eth_buffer[14+header_len..14+header_len+copy_len]);
-            
-            // Return data length, source IP, protocol
-            Ok((copy_len, ip_header.src_ip, ip_header.protocol))
-        }
-        Err(_) => Err(())
+    let (_, len) = crate::network::device_receive_any(&mut eth_buffer)?;
+    if len < ETH_HEADER_LEN {
+        return Err(());
     }
-}
 
+    // Skip 14-byte Ethernet header
+    let packet = ip_parse(&eth_buffer[ETH_HEADER_LEN..len])?;
+
+    let copy_len = packet.payload.len().min(buffer.len());
+    buffer[0..copy_len].copy_from_slice(&packet.payload[0..copy_len]);
+
+    // Return data length, source IP, protocol
+    Ok((copy_len, packet.src_ip, packet.protocol))
+}
@@ -16,6 +16,8 @@ pub struct IpcMessage {
     pub inline_data: [u8; 64],
     pub buffer: *mut u8,
     pub buffer_size: usize,
+    /// Port responses go to; 0 if the sender expects none
+    pub reply_port: u64,
 }
 
 impl IpcMessage {
@@ -28,10 +30,95 @@ impl IpcMessage {
             inline_data: [0; 64],
             buffer: core::ptr::null_mut(),
             buffer_size: 0,
+            reply_port: 0,
         }
     }
 }
 
+/// Convenience wrapper that returns Result for send
+pub fn ipc_send(port_id: u64, msg: &IpcMessage) -> Result<(), ()> {
+    let ret = sys_ipc_send(port_id, msg as *const IpcMessage);
+    if ret == 0 { Ok(()) } else { Err(()) }
+}
+
+/// Convenience wrapper that returns Result for receive
+pub fn ipc_receive(port_id: u64, msg: &mut IpcMessage) -> Result<(), ()> {
+    let ret = sys_ipc_receive(port_id, msg as *mut IpcMessage);
+    if ret == 0 { Ok(()) } else { Err(()) }
+}
+
+/// Convenience wrapper that returns Result for reply
+pub fn ipc_reply(request: &IpcMessage, response: &IpcMessage) -> Result<(), ()> {
+    let ret = sys_ipc_reply(request as *const IpcMessage, response as *const IpcMessage);
+    if ret == 0 { Ok(()) } else { Err(()) }
+}
+
+/// Receive that gives up after `timeout_ms`
+pub fn ipc_receive_timeout(port_id: u64, msg: &mut IpcMessage, timeout_ms: u64) -> Result<(), ()> {
+    let ret = unsafe { syscall_raw(54, port_id, msg as *mut IpcMessage as u64, timeout_ms, 0, 0) as i32 };
+    if ret == 0 { Ok(()) } else { Err(()) }
+}
+
+/// Create a private port, e.g. to receive replies on
+pub fn ipc_create_port() -> Result<u64, ()> {
+    let port = unsafe { syscall_raw(26, 0, 0, 0, 0, 0) };
+    if port == 0 || (port as i64) < 0 { Err(()) } else { Ok(port) }
+}
+
+pub fn ipc_destroy_port(port_id: u64) {
+    unsafe {
+        syscall_raw(27, port_id, 0, 0, 0, 0);
+    }
+}
+/// Port of the service name registry (hosted by the device manager)
+pub const SERVICE_REGISTRY_PORT: u64 = 90;
+/// [port:8][name] -> [status:1]
+const REGISTRY_OP_REGISTER_NAME: u64 = 5;
+/// [name] -> [status:1][port:8]
+const REGISTRY_OP_LOOKUP_NAME: u64 = 6;
+const REGISTRY_REPLY_TIMEOUT_MS: u64 = 500;
+
+/// One registry request; Err unless the registry answered with status 0
+fn registry_request(op: u64, data: &[u8]) -> Result<IpcMessage, ()> {
+    let reply_port = ipc_create_port()?;
+
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_REQUEST;
+    msg.msg_id = op;
+    let len = data.len().min(msg.inline_data.len());
+    msg.inline_data[..len].copy_from_slice(&data[..len]);
+    msg.inline_size = len as u32;
+    msg.reply_port = reply_port;
+
+    let mut reply = IpcMessage::new();
+    let result = ipc_send(SERVICE_REGISTRY_PORT, &msg)
+        .and_then(|_| ipc_receive_timeout(reply_port, &mut reply, REGISTRY_REPLY_TIMEOUT_MS));
+    ipc_destroy_port(reply_port);
+    result?;
+
+    if reply.msg_type != IPC_MSG_RESPONSE || reply.msg_id != op || reply.inline_size == 0 || reply.inline_data[0] != 0 {
+        return Err(());
+    }
+    Ok(reply)
+}
+
+/// Publish this service's port under `name`, replacing any earlier entry
+pub fn register_service_name(name: &[u8], port: u64) -> Result<(), ()> {
+    let mut data = [0u8; 64];
+    let len = name.len().min(data.len() - 8);
+    data[0..8].copy_from_slice(&port.to_le_bytes());
+    data[8..8 + len].copy_from_slice(&name[..len]);
+    registry_request(REGISTRY_OP_REGISTER_NAME, &data[..8 + len]).map(|_| ())
+}
+
+/// System call wrapper for IPC send
+#[no_mangle]
+pub extern "C" fn sys_ipc_send(port_id: u64, msg: *const IpcMessage) -> i32 {
+    unsafe {
+        syscall_raw(9, port_id, msg as u64, 0, 0, 0) as i32
+    }
+}
+
 /// System call wrapper for IPC receive
 #[no_mangle]
 pub extern "C" fn sys_ipc_receive(port_id: u64, msg: *mut IpcMessage) -> i32 {
@@ -40,6 +127,14 @@ pub extern "C" fn sys_ipc_receive(port_id: u64, msg: *mut IpcMessage) -> i32 {
     }
 }
 
+/// System call wrapper for IPC reply
+#[no_mangle]
+pub extern "C" fn sys_ipc_reply(request: *const IpcMessage, response: *const IpcMessage) -> i32 {
+    unsafe {
+        syscall_raw(55, request as u64, response as u64, 0, 0, 0) as i32
+    }
+}
+
 #[cfg(target_arch = "x86_64")]
 unsafe fn syscall_raw(num: u64, arg1: u64, arg2: u64, arg3: u64, arg4: u64, arg5: u64) -> u64 {
     let ret: u64;
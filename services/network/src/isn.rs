@@ -0,0 +1,76 @@
+//! TCP initial sequence numbers (RFC 6528)
+//!
+//! ISN = M + F(local ip, local port, remote ip, remote port, secret), where
+//! M is a clock ticking every 4 microseconds and F is SipHash-2-4 keyed
+//! with a secret chosen at boot. Each connection 4-tuple gets its own
+//! sequence space, so an off-path attacker who sees the ISNs of their own
+//! connections learns nothing about anyone else's.
+
+/// Clock ticks per millisecond (one every 4 microseconds)
+const ISN_TICKS_PER_MS: u32 = 250;
+
+/// Per-boot key for F
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IsnSecret {
+    k0: u64,
+    k1: u64,
+}
+
+impl IsnSecret {
+    pub const fn new(k0: u64, k1: u64) -> Self {
+        Self { k0, k1 }
+    }
+
+    pub fn from_bytes(bytes: [u8; 16]) -> Self {
+        let mut k0 = [0u8; 8];
+        let mut k1 = [0u8; 8];
+        k0.copy_from_slice(&bytes[0..8]);
+        k1.copy_from_slice(&bytes[8..16]);
+        Self::new(u64::from_le_bytes(k0), u64::from_le_bytes(k1))
+    }
+
+    /// Initial sequence number for a connection opened at `now_ms`
+    pub fn initial_sequence(&self, now_ms: u64, local_ip: u32, local_port: u16, remote_ip: u32, remote_port: u16) -> u32 {
+        let clock = (now_ms as u32).wrapping_mul(ISN_TICKS_PER_MS);
+        let ips = (local_ip as u64) << 32 | remote_ip as u64;
+        let ports = (local_port as u64) << 16 | remote_port as u64;
+        clock.wrapping_add(self.hash(ips, ports) as u32)
+    }
+
+    /// SipHash-2-4 of two 64-bit words
+    fn hash(&self, m0: u64, m1: u64) -> u64 {
+        let mut v = [
+            self.k0 ^ 0x736f_6d65_7073_6575,
+            self.k1 ^ 0x646f_7261_6e64_6f6d,
+            self.k0 ^ 0x6c79_6765_6e65_7261,
+            self.k1 ^ 0x7465_6462_7974_6573,
+        ];
+
+        // Two message words, then the final word holding the length (16)
+        for m in [m0, m1, 16u64 << 56] {
+            v[3] ^= m;
+            sip_round(&mut v);
+            sip_round(&mut v);
+            v[0] ^= m;
+        }
+
+        v[2] ^= 0xFF;
+        for _ in 0..4 {
+            sip_round(&mut v);
+        }
+        v[0] ^ v[1] ^ v[2] ^ v[3]
+    }
+}
+
+fn sip_round(v: &mut [u64; 4]) {
+    v[0] = v[0].wrapping_add(v[1]);
+    v[1] = v[1].rotate_left(13) ^ v[0];
+    v[0] = v[0].rotate_left(32);
+    v[2] = v[2].wrapping_add(v[3]);
+    v[3] = v[3].rotate_left(16) ^ v[2];
+    v[0] = v[0].wrapping_add(v[3]);
+    v[3] = v[3].rotate_left(21) ^ v[0];
+    v[2] = v[2].wrapping_add(v[1]);
+    v[1] = v[1].rotate_left(17) ^ v[2];
+    v[2] = v[2].rotate_left(32);
+}
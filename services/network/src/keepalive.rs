@@ -0,0 +1,64 @@
+//! TCP keepalive and connection-establishment timers
+//!
+//! With keepalive on, a connection that has heard nothing from its peer
+//! for the idle time is probed, then probed again every interval; once the
+//! last probe goes unanswered for an interval the peer is taken to be gone.
+//! Separately, a handshake still unfinished when the connect timeout runs
+//! out is abandoned rather than retried for as long as the RTO allows.
+
+/// Defaults (RFC 1122 section 4.2.3.6 and common practice) in milliseconds
+pub const TCP_KEEPALIVE_IDLE_MS: u64 = 7_200_000;
+pub const TCP_KEEPALIVE_INTERVAL_MS: u64 = 75_000;
+pub const TCP_KEEPALIVE_PROBES: u32 = 9;
+pub const TCP_CONNECT_TIMEOUT_MS: u64 = 75_000;
+
+/// Keepalive timing for one connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keepalive {
+    /// Silence before the first probe
+    pub idle_ms: u64,
+    /// Time between probes, and after the last one before giving up
+    pub interval_ms: u64,
+    /// Unanswered probes before the connection is dropped
+    pub probes: u32,
+}
+
+impl Keepalive {
+    pub const DEFAULT: Self = Self {
+        idle_ms: TCP_KEEPALIVE_IDLE_MS,
+        interval_ms: TCP_KEEPALIVE_INTERVAL_MS,
+        probes: TCP_KEEPALIVE_PROBES,
+    };
+
+    /// What is due at `now` for a connection last heard from at
+    /// `last_heard` that has sent `probes_sent` probes since
+    pub fn action(&self, last_heard: u64, probes_sent: u32, now: u64) -> KeepaliveAction {
+        let due = last_heard
+            .saturating_add(self.idle_ms)
+            .saturating_add(self.interval_ms.saturating_mul(probes_sent as u64));
+        if now < due {
+            KeepaliveAction::Wait
+        } else if probes_sent >= self.probes {
+            KeepaliveAction::Drop
+        } else {
+            KeepaliveAction::Probe
+        }
+    }
+}
+
+/// Keepalive decision for an idle connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepaliveAction {
+    /// Nothing due yet
+    Wait,
+    /// Send a probe
+    Probe,
+    /// Every probe went unanswered
+    Drop,
+}
+
+/// Whether a handshake started at `started` has run past `timeout_ms`
+/// (0 never times out)
+pub fn handshake_expired(started: u64, timeout_ms: u64, now: u64) -> bool {
+    timeout_ms != 0 && now.saturating_sub(started) >= timeout_ms
+}
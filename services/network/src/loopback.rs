@@ -0,0 +1,97 @@
+//! Loopback pseudo-device
+//!
+//! Datagrams routed to the loopback device are queued here and fed back
+//! into the receive path by the service loop, so local traffic works
+//! without a NIC.
+
+use crate::network;
+use crate::route;
+
+/// 127.0.0.1 and the 127.0.0.0/8 network
+pub const LOOPBACK_ADDR: u32 = 0x7F00_0001;
+pub const LOOPBACK_NET: u32 = 0x7F00_0000;
+pub const LOOPBACK_NETMASK: u32 = 0xFF00_0000;
+
+const LOOPBACK_QUEUE_SIZE: usize = 16;
+const LOOPBACK_MTU: usize = 1500;
+
+/// Datagram waiting to be received
+#[derive(Clone, Copy)]
+struct LoopbackPacket {
+    data: [u8; LOOPBACK_MTU],
+    len: usize,
+}
+
+static mut LOOPBACK_QUEUE: [LoopbackPacket; LOOPBACK_QUEUE_SIZE] = [LoopbackPacket {
+    data: [0; LOOPBACK_MTU],
+    len: 0,
+}; LOOPBACK_QUEUE_SIZE];
+static mut QUEUE_HEAD: usize = 0;
+static mut QUEUE_COUNT: usize = 0;
+static mut LOOPBACK_DROPS: u64 = 0;
+static mut LOOPBACK_DEVICE: usize = 0;
+
+/// Register the "lo" device and route 127.0.0.0/8 to it.
+/// Returns the device index.
+pub fn loopback_init() -> Result<usize, ()> {
+    let idx = network::register_device(b"lo", &[0; 6], 0)?;
+    network::set_device_flags(idx, network::NET_DEVICE_LOOPBACK | network::NET_DEVICE_LINK_UP)?;
+    network::set_ip_config(idx, LOOPBACK_ADDR, LOOPBACK_NETMASK, 0, 0)?;
+    route::add_route(LOOPBACK_NET, LOOPBACK_NETMASK, 0, idx)?;
+
+    unsafe {
+        LOOPBACK_DEVICE = idx;
+        QUEUE_HEAD = 0;
+        QUEUE_COUNT = 0;
+    }
+
+    Ok(idx)
+}
+
+/// Queue an IP datagram for local delivery.
+/// Fails (and the datagram is dropped) when the queue is full.
+pub fn loopback_send(datagram: &[u8]) -> Result<(), ()> {
+    unsafe {
+        if QUEUE_COUNT >= LOOPBACK_QUEUE_SIZE || datagram.len() > LOOPBACK_MTU {
+            LOOPBACK_DROPS += 1;
+            network::account_traffic(LOOPBACK_DEVICE, 0, 0, true);
+            return Err(());
+        }
+
+        let slot = &mut LOOPBACK_QUEUE[(QUEUE_HEAD + QUEUE_COUNT) % LOOPBACK_QUEUE_SIZE];
+        slot.data[0..datagram.len()].copy_from_slice(datagram);
+        slot.len = datagram.len();
+        QUEUE_COUNT += 1;
+        network::account_traffic(LOOPBACK_DEVICE, 0, datagram.len(), false);
+    }
+
+    Ok(())
+}
+
+/// Dequeue the oldest looped-back datagram into `buffer`
+pub fn loopback_receive(buffer: &mut [u8]) -> Option<usize> {
+    unsafe {
+        if QUEUE_COUNT == 0 {
+            return None;
+        }
+
+        let packet = &LOOPBACK_QUEUE[QUEUE_HEAD];
+        let len = packet.len.min(buffer.len());
+        buffer[0..len].copy_from_slice(&packet.data[0..len]);
+        QUEUE_HEAD = (QUEUE_HEAD + 1) % LOOPBACK_QUEUE_SIZE;
+        QUEUE_COUNT -= 1;
+        network::account_traffic(LOOPBACK_DEVICE, len, 0, false);
+
+        Some(len)
+    }
+}
+
+/// Number of datagrams waiting for delivery
+pub fn loopback_pending() -> usize {
+    unsafe { QUEUE_COUNT }
+}
+
+/// Number of datagrams dropped because the queue was full
+pub fn loopback_drops() -> u64 {
+    unsafe { LOOPBACK_DROPS }
+}
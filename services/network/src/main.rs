@@ -6,14 +6,43 @@
 #![no_std]
 #![no_main]
 
+extern crate alloc;
+
 mod network;
 mod ipc;
 mod ethernet_device;
+mod syscalls;
+mod arp;
+mod ip;
+mod route;
+mod loopback;
+mod dhcp;
+mod dns;
+mod tcp;
+mod isn;
+mod keepalive;
+mod udp;
+mod socket;
+mod frame_queue;
+mod port_table;
+mod udp_demux;
+mod multicast;
+mod icmp;
 
 use core::panic::PanicInfo;
 use network::network_init;
-use ipc::{IpcMessage, sys_ipc_receive};
-use ethernet_device::{set_ethernet_device_port, send_packet, receive_packet, get_mac_address, set_ip_config};
+use ipc::{IpcMessage, sys_ipc_receive, ipc_reply, ipc_create_port, register_service_name};
+use ethernet_device::{get_mac_address, get_link_status};
+
+/// Resolve a hostname (see `handle_resolve` for the message layout)
+pub const NET_OP_RESOLVE: u64 = 8;
+
+/// Per-interface statistics (see `handle_get_stats` for the message layout)
+pub const NET_OP_GET_STATS: u64 = 9;
+
+/// Link change notification from a network driver
+/// (driver port u64 LE, link up u8, speed in Mbps u16 LE, full duplex u8)
+const NET_DEV_NOTIFY_LINK_CHANGE: u64 = 101;
 
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
@@ -24,18 +53,111 @@ fn panic(_info: &PanicInfo) -> ! {
 pub extern "C" fn _start() -> ! {
     // Initialize network stack
     let _ = network_init();
+
+    let _ = arp::arp_init();
+
+    // Loopback is always present, so local traffic works without a NIC
+    let _ = loopback::loopback_init();
+
+    // Clients find the stack by name rather than a fixed port
+    let port = ipc_create_port().unwrap_or(0);
+    if port != 0 {
+        let _ = register_service_name(b"network", port);
+    }
     
     // Main service loop
-    network_loop();
+    network_loop(port);
+}
+
+/// Run protocol timers and process one received frame, if any.
+/// Called from the service loop and from code waiting on the network (DNS).
+pub fn net_poll() {
+    // Drive TCP retransmission and TIME_WAIT timers
+    tcp::tcp_timer_tick();
+
+    // Retry outstanding ARP requests
+    arp::arp_timer_tick();
+
+    // DHCP retransmission and lease renewal
+    dhcp::dhcp_timer_tick();
+
+    // Deliver datagrams looped back since the last poll (replies queue behind them)
+    let mut packet_buffer = [0u8; 1518];
+    if let Some(lo) = network::loopback_device() {
+        for _ in 0..loopback::loopback_pending() {
+            if let Some(len) = loopback::loopback_receive(&mut packet_buffer) {
+                ip_deliver(lo, &packet_buffer[0..len]);
+            }
+        }
+    }
+
+    // Process one network packet from each driver
+    for device_idx in 0..network::get_device_count() {
+        if network::is_loopback(device_idx) {
+            continue;
+        }
+
+        if let Ok(len) = network::device_receive(device_idx, &mut packet_buffer) {
+            // Packet sockets see every frame, whatever the stack does with it
+            socket::socket_deliver_frame(device_idx, &packet_buffer[0..len]);
+
+            // Process Ethernet packet (parse headers, route to protocol handlers)
+            if len >= 14 {
+                // Parse Ethernet header (14 bytes)
+                let eth_type = u16::from_be_bytes([packet_buffer[12], packet_buffer[13]]);
+                if eth_type == 0x0800 { // IPv4
+                    ip_deliver(device_idx, &packet_buffer[14..len]);
+                } else if eth_type == arp::ETHERTYPE_ARP {
+                    // Learn peer MACs and answer requests for our address
+                    let _ = arp::arp_process(device_idx, &packet_buffer[14..len]);
+                }
+            }
+        }
+    }
+
+    // Track which sockets became readable/writable
+    socket::socket_update_readiness();
 }
 
-fn network_loop() {
+/// Validate an IPv4 datagram received on `device_idx` and hand it to the protocol handler
+fn ip_deliver(device_idx: usize, datagram: &[u8]) {
+    // Route to IP layer (validates header checksum)
+    use crate::ip::ip_parse;
+    if let Ok(packet) = ip_parse(datagram) {
+        // Route to protocol handler
+        if packet.protocol == crate::ip::IP_PROTOCOL_TCP {
+            use crate::tcp::tcp_handle_packet;
+            let _ = tcp_handle_packet(packet.payload, packet.src_ip, packet.dst_ip);
+        } else if packet.protocol == crate::ip::IP_PROTOCOL_UDP {
+            use crate::udp::udp_parse;
+            if let Ok((src_port, dest_port, payload)) = udp_parse(packet.payload, packet.src_ip, packet.dst_ip) {
+                if dest_port == dhcp::DHCP_CLIENT_PORT {
+                    let _ = dhcp::dhcp_handle_packet(device_idx, payload);
+                } else if dns::dns_query_port() == Some(dest_port) {
+                    dns::dns_handle_packet(packet.src_ip, src_port, payload);
+                } else if ip::is_broadcast(packet.dst_ip, device_idx) || ip::is_multicast(packet.dst_ip) {
+                    // A copy for every socket that asked; nobody tells the sender otherwise
+                    socket::socket_deliver_group(packet.src_ip, src_port, packet.dst_ip, dest_port, payload);
+                } else if !socket::socket_deliver_datagram(packet.src_ip, src_port, packet.dst_ip, dest_port, payload) {
+                    // Nobody listens there; tell the sender
+                    let quoted = (((datagram[0] & 0x0F) as usize) * 4 + 8).min(datagram.len());
+                    let _ = icmp::icmp_dest_unreachable(packet.src_ip, icmp::ICMP_CODE_PORT_UNREACHABLE, &datagram[..quoted]);
+                }
+            }
+        } else if packet.protocol == crate::ip::IP_PROTOCOL_ICMP {
+            // Handle ICMP packet
+        }
+    }
+}
+
+fn network_loop(port: u64) {
     let mut msg = IpcMessage::new();
-    let mut ethernet_port: Option<u64> = None;
     
     loop {
+        net_poll();
+
         // Receive IPC messages for network operations
-        if sys_ipc_receive(3, &mut msg) == 0 {
+        if sys_ipc_receive(port, &mut msg) == 0 {
             // Check for driver notification (from device manager)
             if msg.msg_id == 100 { // SERVICE_NOTIFY_DRIVER_AVAILABLE
                 if msg.inline_size >= 8 {
@@ -43,13 +165,18 @@ fn network_loop() {
                         msg.inline_data[0], msg.inline_data[1], msg.inline_data[2], msg.inline_data[3],
                         msg.inline_data[4], msg.inline_data[5], msg.inline_data[6], msg.inline_data[7],
                     ]);
-                    ethernet_port = Some(port);
-                    set_ethernet_device_port(port);
-                    
-                    // Get MAC address and register device
-                    if let Ok(mac) = get_mac_address() {
-                        let _ = network::register_device(b"eth0", &mac);
-                    }
+                    let _ = register_driver(port);
+                }
+                continue;
+            }
+
+            // Link went up or down on one of the NICs
+            if msg.msg_id == NET_DEV_NOTIFY_LINK_CHANGE {
+                if msg.inline_size >= 11 {
+                    let mut port_bytes = [0u8; 8];
+                    port_bytes.copy_from_slice(&msg.inline_data[0..8]);
+                    let speed = u16::from_le_bytes([msg.inline_data[9], msg.inline_data[10]]);
+                    handle_link_change(u64::from_le_bytes(port_bytes), msg.inline_data[8] != 0, speed);
                 }
                 continue;
             }
@@ -83,36 +210,113 @@ fn network_loop() {
             if msg.msg_id == 7 { // SOCKET_RECEIVE
                 // Receive data and return
             }
-            
-            // Process network packets from drivers
-            if ethernet_port.is_some() {
-                let mut packet_buffer = [0u8; 1518];
-                if let Ok(len) = receive_packet(&mut packet_buffer) {
-                    // Process Ethernet packet (parse headers, route to protocol handlers)
-                    if len >= 14 {
-                        // Parse Ethernet header (14 bytes)
-                        let eth_type = u16::from_be_bytes([packet_buffer[12], packet_buffer[13]]);
-                        if eth_type == 0x0800 { // IPv4
-                            // Route to IP layer
-                            use crate::ip::ip_receive;
-                            let mut ip_buffer = [0u8; 1500];
-                            ip_buffer[0..len-14].copy_from_slice(&packet_buffer[14..len]);
-                            if let Ok((data_len, src_ip, protocol)) = ip_receive(&mut ip_buffer) {
-                                // Route to protocol handler
-                                if protocol == crate::ip::IP_PROTOCOL_TCP {
-                                    use crate::tcp::tcp_handle_packet;
-                                    let _ = tcp_handle_packet(&ip_buffer[0..data_len], src_ip);
-                                } else if protocol == crate::ip::IP_PROTOCOL_UDP {
-                                    // Handle UDP packet
-                                } else if protocol == crate::ip::IP_PROTOCOL_ICMP {
-                                    // Handle ICMP packet
-                                }
-                            }
-                        }
-                    }
-                }
+
+            // Hostname resolution
+            if msg.msg_id == NET_OP_RESOLVE {
+                let response = handle_resolve(&msg);
+                let _ = ipc_reply(&msg, &response);
             }
+
+            // Interface counters
+            if msg.msg_id == NET_OP_GET_STATS {
+                let response = handle_get_stats(&msg);
+                let _ = ipc_reply(&msg, &response);
+            }
+        }
+    }
+}
+
+/// Register the network driver listening on `port` as a new interface
+/// ("eth0", "eth1", ...) and start auto-configuring it
+fn register_driver(port: u64) -> Result<usize, ()> {
+    // Drivers may announce themselves more than once
+    if let Some(device_idx) = network::find_device_by_port(port) {
+        return Ok(device_idx);
+    }
+
+    let mac = get_mac_address(port)?;
+
+    let unit = (0..network::get_device_count()).filter(|&idx| !network::is_loopback(idx)).count();
+    let mut name = *b"eth0";
+    name[3] = b'0' + unit.min(9) as u8;
+
+    let device_idx = network::register_device(&name, &mac, port)?;
+
+    // Drivers that cannot report link state are assumed to be up
+    let link = get_link_status(port).unwrap_or(ethernet_device::LinkStatus {
+        up: true,
+        speed_mbps: 0,
+        full_duplex: true,
+    });
+    let _ = network::set_link_state(device_idx, link.up, link.speed_mbps);
+
+    // Auto-configure the interface (DHCP waits for link)
+    if link.up {
+        let _ = dhcp::dhcp_start(device_idx);
+    }
+
+    Ok(device_idx)
+}
+
+/// Bring a device's routes down or up with its link. Routes through a
+/// device without link are skipped by the routing table; when the link
+/// returns (e.g. NIC hot-plugged back in) the lease is re-acquired since
+/// the device may now be on a different network.
+fn handle_link_change(port: u64, up: bool, speed_mbps: u16) {
+    let device_idx = match network::find_device_by_port(port) {
+        Some(idx) => idx,
+        None => return,
+    };
+
+    if let Ok(true) = network::set_link_state(device_idx, up, speed_mbps) {
+        arp::arp_flush_device(device_idx);
+        if up {
+            let _ = dhcp::dhcp_start(device_idx);
         }
     }
 }
 
+/// Report an interface's counters.
+/// Request: device index (u8) in inline_data[0]. Response: 8 x u64 LE
+/// (rx/tx packets, rx/tx bytes, rx dropped, rx no buffer, rx CRC errors,
+/// rx errors); inline_size is 0 for an unknown device.
+fn handle_get_stats(msg: &IpcMessage) -> IpcMessage {
+    let mut response = IpcMessage::new();
+    response.msg_type = ipc::IPC_MSG_RESPONSE;
+    response.msg_id = msg.msg_id;
+    response.inline_size = 0;
+
+    if msg.inline_size >= 1 {
+        if let Some(stats) = network::device_stats(msg.inline_data[0] as usize) {
+            response.inline_data.copy_from_slice(&stats.to_bytes());
+            response.inline_size = 64;
+        }
+    }
+
+    response
+}
+
+/// Resolve a hostname for a client.
+/// Request: hostname bytes in inline_data. Response: status byte (0 = ok),
+/// followed by the IPv4 address (u32 LE) on success.
+fn handle_resolve(msg: &IpcMessage) -> IpcMessage {
+    let mut response = IpcMessage::new();
+    response.msg_type = ipc::IPC_MSG_RESPONSE;
+    response.msg_id = msg.msg_id;
+    response.inline_data[0] = 0xFF; // Error
+    response.inline_size = 1;
+
+    let len = (msg.inline_size as usize).min(msg.inline_data.len());
+    let name = &msg.inline_data[0..len];
+    let name_len = name.iter().position(|&c| c == 0).unwrap_or(len);
+
+    if let Ok(hostname) = core::str::from_utf8(&name[0..name_len]) {
+        if let Ok(ip) = dns::resolve(hostname) {
+            response.inline_data[0] = 0;
+            response.inline_data[1..5].copy_from_slice(&ip.to_le_bytes());
+            response.inline_size = 5;
+        }
+    }
+
+    response
+}
@@ -0,0 +1,82 @@
+//! Broadcast and multicast addressing
+//!
+//! Broadcast and multicast datagrams go to fixed link-layer addresses
+//! instead of one found with ARP. Received ones reach every socket that
+//! asked for them: SO_BROADCAST for broadcasts, IP_ADD_MEMBERSHIP for a
+//! multicast group.
+
+/// Ethernet broadcast address
+pub const ETH_BROADCAST: [u8; 6] = [0xFF; 6];
+
+/// Groups one socket can belong to
+pub const IP_MAX_MEMBERSHIPS: usize = 8;
+
+/// Whether `dest_ip` is a multicast group (224.0.0.0/4)
+pub fn is_multicast(dest_ip: u32) -> bool {
+    (dest_ip >> 28) == 0xE
+}
+
+/// Ethernet address frames for `group` are sent to: 01:00:5E followed by
+/// the low 23 bits of the group (RFC 1112 section 6.4)
+pub fn multicast_mac(group: u32) -> [u8; 6] {
+    let low = group.to_be_bytes();
+    [0x01, 0x00, 0x5E, low[1] & 0x7F, low[2], low[3]]
+}
+
+/// Link-layer destination of a datagram to `dest_ip` when it does not
+/// depend on the next hop; `None` for unicast, which needs ARP
+pub fn link_destination(dest_ip: u32, broadcast: bool) -> Option<[u8; 6]> {
+    if broadcast {
+        Some(ETH_BROADCAST)
+    } else if is_multicast(dest_ip) {
+        Some(multicast_mac(dest_ip))
+    } else {
+        None
+    }
+}
+
+/// Multicast groups a socket has joined
+#[derive(Debug, Clone, Copy)]
+pub struct Memberships {
+    groups: [u32; IP_MAX_MEMBERSHIPS],
+    count: usize,
+}
+
+impl Memberships {
+    pub const fn new() -> Self {
+        Self { groups: [0; IP_MAX_MEMBERSHIPS], count: 0 }
+    }
+
+    /// Join `group`; false if it is not a multicast group or the socket
+    /// already belongs to as many as it can. Joining twice is harmless.
+    pub fn join(&mut self, group: u32) -> bool {
+        if !is_multicast(group) {
+            return false;
+        }
+        if self.contains(group) {
+            return true;
+        }
+        if self.count == IP_MAX_MEMBERSHIPS {
+            return false;
+        }
+        self.groups[self.count] = group;
+        self.count += 1;
+        true
+    }
+
+    /// Leave `group`; false if the socket was not a member
+    pub fn leave(&mut self, group: u32) -> bool {
+        match self.groups[..self.count].iter().position(|&g| g == group) {
+            Some(index) => {
+                self.groups.copy_within(index + 1..self.count, index);
+                self.count -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn contains(&self, group: u32) -> bool {
+        self.groups[..self.count].contains(&group)
+    }
+}
@@ -2,6 +2,73 @@
 
 use core::mem;
 
+/// Per-interface traffic counters (8 x u64, also the IPC wire layout)
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct NetDeviceStats {
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_dropped: u64,
+    pub rx_no_buffer: u64,
+    pub rx_crc_errors: u64,
+    pub rx_errors: u64,
+}
+
+impl NetDeviceStats {
+    pub const fn new() -> Self {
+        Self {
+            rx_packets: 0,
+            tx_packets: 0,
+            rx_bytes: 0,
+            tx_bytes: 0,
+            rx_dropped: 0,
+            rx_no_buffer: 0,
+            rx_crc_errors: 0,
+            rx_errors: 0,
+        }
+    }
+
+    /// Decode from 8 little-endian u64 values
+    pub fn from_bytes(bytes: &[u8; 64]) -> Self {
+        let field = |i: usize| {
+            let mut value = [0u8; 8];
+            value.copy_from_slice(&bytes[i * 8..i * 8 + 8]);
+            u64::from_le_bytes(value)
+        };
+        Self {
+            rx_packets: field(0),
+            tx_packets: field(1),
+            rx_bytes: field(2),
+            tx_bytes: field(3),
+            rx_dropped: field(4),
+            rx_no_buffer: field(5),
+            rx_crc_errors: field(6),
+            rx_errors: field(7),
+        }
+    }
+
+    /// Encode as 8 little-endian u64 values
+    pub fn to_bytes(&self) -> [u8; 64] {
+        let fields = [
+            self.rx_packets,
+            self.tx_packets,
+            self.rx_bytes,
+            self.tx_bytes,
+            self.rx_dropped,
+            self.rx_no_buffer,
+            self.rx_crc_errors,
+            self.rx_errors,
+        ];
+        let mut bytes = [0u8; 64];
+        for (i, value) in fields.iter().enumerate() {
+            bytes[i * 8..i * 8 + 8].copy_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+}
+
 /// Network device structure
 #[repr(C)]
 pub struct NetDevice {
@@ -10,10 +77,19 @@ pub struct NetDevice {
     pub ip_address: u32,
     pub netmask: u32,
     pub gateway: u32,
+    pub dns_server: u32,
     pub mtu: u16,
+    pub flags: u32,
+    pub link_speed: u16,   // Negotiated speed in Mbps (0 = unknown/down)
+    pub driver_port: u64,  // IPC port of the driver (0 = none, e.g. loopback)
+    pub stats: NetDeviceStats,
     pub next: u64,  // Pointer to next device
 }
 
+/// Device flags
+pub const NET_DEVICE_LOOPBACK: u32 = 0x1;
+pub const NET_DEVICE_LINK_UP: u32 = 0x2;
+
 /// IP packet structure
 #[repr(C, packed)]
 pub struct IpPacket {
@@ -30,7 +106,7 @@ pub struct IpPacket {
     pub data: [u8; 0],  // Variable length
 }
 
-const MAX_DEVICES: usize = 16;
+pub const MAX_DEVICES: usize = 16;
 
 static mut NET_DEVICES: [NetDevice; MAX_DEVICES] = unsafe { mem::zeroed() };
 static mut DEVICE_COUNT: usize = 0;
@@ -50,8 +126,8 @@ pub fn network_init() -> Result<(), ()> {
     }
 }
 
-/// Register network device
-pub fn register_device(name: &[u8], mac: &[u8; 6]) -> Result<usize, ()> {
+/// Register network device served by the driver on `driver_port`
+pub fn register_device(name: &[u8], mac: &[u8; 6], driver_port: u64) -> Result<usize, ()> {
     unsafe {
         if DEVICE_COUNT >= MAX_DEVICES {
             return Err(());
@@ -70,7 +146,12 @@ pub fn register_device(name: &[u8], mac: &[u8; 6]) -> Result<usize, ()> {
         device.ip_address = 0;
         device.netmask = 0;
         device.gateway = 0;
+        device.dns_server = 0;
         device.mtu = 1500;
+        device.flags = 0;
+        device.link_speed = 0;
+        device.driver_port = driver_port;
+        device.stats = NetDeviceStats::new();
         device.next = 0;
         
         let idx = DEVICE_COUNT;
@@ -81,7 +162,7 @@ pub fn register_device(name: &[u8], mac: &[u8; 6]) -> Result<usize, ()> {
 }
 
 /// Set IP configuration
-pub fn set_ip_config(device_idx: usize, ip: u32, netmask: u32, gateway: u32) -> Result<(), ()> {
+pub fn set_ip_config(device_idx: usize, ip: u32, netmask: u32, gateway: u32, dns_server: u32) -> Result<(), ()> {
     unsafe {
         if device_idx >= DEVICE_COUNT {
             return Err(());
@@ -91,11 +172,156 @@ pub fn set_ip_config(device_idx: usize, ip: u32, netmask: u32, gateway: u32) ->
         device.ip_address = ip;
         device.netmask = netmask;
         device.gateway = gateway;
+        device.dns_server = dns_server;
         
         Ok(())
     }
 }
 
+/// Set device flags
+pub fn set_device_flags(device_idx: usize, flags: u32) -> Result<(), ()> {
+    unsafe {
+        if device_idx >= DEVICE_COUNT {
+            return Err(());
+        }
+
+        NET_DEVICES[device_idx].flags = flags;
+        Ok(())
+    }
+}
+
+/// Record the link state reported by the driver.
+/// Returns true if the link changed between up and down.
+pub fn set_link_state(device_idx: usize, up: bool, speed_mbps: u16) -> Result<bool, ()> {
+    unsafe {
+        if device_idx >= DEVICE_COUNT {
+            return Err(());
+        }
+
+        let device = &mut NET_DEVICES[device_idx];
+        let was_up = (device.flags & NET_DEVICE_LINK_UP) != 0;
+        if up {
+            device.flags |= NET_DEVICE_LINK_UP;
+            device.link_speed = speed_mbps;
+        } else {
+            device.flags &= !NET_DEVICE_LINK_UP;
+            device.link_speed = 0;
+        }
+
+        Ok(was_up != up)
+    }
+}
+
+/// Current counters for a device. Hardware counters are refreshed from the
+/// driver; if it does not answer the last known values are returned.
+pub fn device_stats(device_idx: usize) -> Option<NetDeviceStats> {
+    unsafe {
+        if device_idx >= DEVICE_COUNT {
+            return None;
+        }
+
+        let device = &mut NET_DEVICES[device_idx];
+        if device.driver_port != 0 {
+            if let Ok(stats) = crate::ethernet_device::get_stats(device.driver_port) {
+                device.stats = stats;
+            }
+        }
+
+        Some(device.stats)
+    }
+}
+
+/// Count traffic on a device the service drives itself (loopback)
+pub fn account_traffic(device_idx: usize, rx_bytes: usize, tx_bytes: usize, dropped: bool) {
+    unsafe {
+        if device_idx >= DEVICE_COUNT {
+            return;
+        }
+
+        let stats = &mut NET_DEVICES[device_idx].stats;
+        if rx_bytes > 0 {
+            stats.rx_packets += 1;
+            stats.rx_bytes += rx_bytes as u64;
+        }
+        if tx_bytes > 0 {
+            stats.tx_packets += 1;
+            stats.tx_bytes += tx_bytes as u64;
+        }
+        if dropped {
+            stats.rx_dropped += 1;
+        }
+    }
+}
+
+/// Does the device have link?
+pub fn is_link_up(device_idx: usize) -> bool {
+    get_device(device_idx).map(|dev| (dev.flags & NET_DEVICE_LINK_UP) != 0).unwrap_or(false)
+}
+
+/// Is the device the loopback pseudo-device?
+pub fn is_loopback(device_idx: usize) -> bool {
+    get_device(device_idx).map(|dev| (dev.flags & NET_DEVICE_LOOPBACK) != 0).unwrap_or(false)
+}
+
+/// Index of the loopback pseudo-device, if registered
+pub fn loopback_device() -> Option<usize> {
+    (0..get_device_count()).find(|&idx| is_loopback(idx))
+}
+
+/// Index of the device configured with `ip`
+pub fn find_device_by_ip(ip: u32) -> Option<usize> {
+    if ip == 0 {
+        return None;
+    }
+    (0..get_device_count()).find(|&idx| get_device(idx).map(|dev| dev.ip_address == ip).unwrap_or(false))
+}
+
+/// Index of the device served by the driver on `driver_port`
+pub fn find_device_by_port(driver_port: u64) -> Option<usize> {
+    if driver_port == 0 {
+        return None;
+    }
+    (0..get_device_count()).find(|&idx| get_device(idx).map(|dev| dev.driver_port == driver_port).unwrap_or(false))
+}
+
+/// Transmit an Ethernet frame through the device's driver
+pub fn device_send(device_idx: usize, frame: &[u8]) -> Result<(), ()> {
+    let port = get_device(device_idx).ok_or(())?.driver_port;
+    crate::ethernet_device::send_packet(port, frame)
+}
+
+/// Have the device receive frames for multicast `group`. Loopback hears
+/// everything it sends, so there is nothing to program.
+pub fn device_add_multicast(device_idx: usize, group: u32) -> Result<(), ()> {
+    let device = get_device(device_idx).ok_or(())?;
+    if (device.flags & NET_DEVICE_LOOPBACK) != 0 {
+        return Ok(());
+    }
+    crate::ethernet_device::add_multicast(device.driver_port, &crate::multicast::multicast_mac(group))
+}
+
+/// Poll the device's driver for a received Ethernet frame
+pub fn device_receive(device_idx: usize, buffer: &mut [u8]) -> Result<usize, ()> {
+    let port = get_device(device_idx).ok_or(())?.driver_port;
+    crate::ethernet_device::receive_packet(port, buffer)
+}
+
+/// Poll every device for a received frame, returning (device index, length)
+/// of the first one found
+pub fn device_receive_any(buffer: &mut [u8]) -> Result<(usize, usize), ()> {
+    for idx in 0..get_device_count() {
+        if let Ok(len) = device_receive(idx, buffer) {
+            return Ok((idx, len));
+        }
+    }
+    Err(())
+}
+
+/// Index of the first real (non-loopback) device with link
+pub fn first_hardware_device() -> Option<usize> {
+    (0..get_device_count()).find(|&idx| !is_loopback(idx) && is_link_up(idx))
+}
+
 /// Get network device
 pub fn get_device(idx: usize) -> Option<&'static NetDevice> {
     unsafe {
@@ -0,0 +1,90 @@
+//! Local port bindings for TCP and UDP sockets
+//!
+//! Tracks which socket holds which port so two sockets cannot bind the
+//! same one by accident, and hands out ephemeral ports to sockets that
+//! connect or send without binding first.
+
+/// IANA dynamic port range
+pub const EPHEMERAL_FIRST: u16 = 49152;
+pub const EPHEMERAL_LAST: u16 = 65535;
+
+pub const MAX_BINDINGS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortProtocol {
+    Tcp,
+    Udp,
+}
+
+#[derive(Clone, Copy)]
+struct Binding {
+    protocol: PortProtocol,
+    port: u16,
+    /// Socket descriptor holding the port
+    owner: usize,
+    /// SO_REUSEADDR at bind time
+    reuse: bool,
+}
+
+pub struct PortTable {
+    bindings: [Option<Binding>; MAX_BINDINGS],
+    next_ephemeral: u16,
+}
+
+impl PortTable {
+    pub const fn new() -> Self {
+        PortTable { bindings: [None; MAX_BINDINGS], next_ephemeral: EPHEMERAL_FIRST }
+    }
+
+    /// Whether any socket holds `port`
+    pub fn is_bound(&self, protocol: PortProtocol, port: u16) -> bool {
+        self.holders(protocol, port).next().is_some()
+    }
+
+    fn holders(&self, protocol: PortProtocol, port: u16) -> impl Iterator<Item = &Binding> {
+        self.bindings.iter().flatten().filter(move |b| b.protocol == protocol && b.port == port)
+    }
+
+    /// Give `port` to socket `owner`. A port another socket holds can only
+    /// be shared when every holder and the newcomer set SO_REUSEADDR.
+    pub fn bind(&mut self, protocol: PortProtocol, port: u16, owner: usize, reuse: bool) -> Result<(), ()> {
+        if port == 0 {
+            return Err(());
+        }
+        if self.holders(protocol, port).any(|b| b.owner == owner) {
+            return Err(()); // Already bound
+        }
+        if self.holders(protocol, port).any(|b| !(b.reuse && reuse)) {
+            return Err(()); // In use
+        }
+
+        let slot = self.bindings.iter_mut().find(|b| b.is_none()).ok_or(())?;
+        *slot = Some(Binding { protocol, port, owner, reuse });
+        Ok(())
+    }
+
+    /// Bind `owner` to the next free ephemeral port. `in_use` reports ports
+    /// taken outside the table (e.g. TCP connections lingering in TIME_WAIT).
+    pub fn bind_ephemeral<F: Fn(u16) -> bool>(&mut self, protocol: PortProtocol, owner: usize, in_use: F) -> Result<u16, ()> {
+        let range = (EPHEMERAL_LAST - EPHEMERAL_FIRST) as u32 + 1;
+        for _ in 0..range {
+            let port = self.next_ephemeral;
+            self.next_ephemeral = if port == EPHEMERAL_LAST { EPHEMERAL_FIRST } else { port + 1 };
+
+            if !self.is_bound(protocol, port) && !in_use(port) {
+                self.bind(protocol, port, owner, false)?;
+                return Ok(port);
+            }
+        }
+        Err(()) // Range exhausted
+    }
+
+    /// Free every port `owner` holds (the socket closed)
+    pub fn release(&mut self, owner: usize) {
+        for slot in self.bindings.iter_mut() {
+            if slot.is_some_and(|b| b.owner == owner) {
+                *slot = None;
+            }
+        }
+    }
+}
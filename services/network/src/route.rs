@@ -0,0 +1,116 @@
+//! IPv4 routing table
+//!
+//! Longest-prefix-match table consulted by `ip_send` to pick the outgoing
+//! device and next hop. Traffic to any locally configured address is routed
+//! to the loopback pseudo-device. Routes through a device without link are
+//! skipped until the link comes back.
+
+use crate::ip::{is_multicast, IP_BROADCAST};
+use crate::network;
+
+/// Routing table entry
+#[derive(Clone, Copy)]
+pub struct Route {
+    pub dest: u32,
+    pub netmask: u32,
+    pub gateway: u32,  // 0 = destination is on-link
+    pub device: usize,
+    pub valid: bool,
+}
+
+const MAX_ROUTES: usize = 32;
+
+static mut ROUTES: [Route; MAX_ROUTES] = [Route {
+    dest: 0,
+    netmask: 0,
+    gateway: 0,
+    device: 0,
+    valid: false,
+}; MAX_ROUTES];
+
+/// Add a route, replacing any existing route for the same destination and mask.
+/// A `netmask` of 0 installs the default route.
+pub fn add_route(dest: u32, netmask: u32, gateway: u32, device: usize) -> Result<(), ()> {
+    if network::get_device(device).is_none() {
+        return Err(());
+    }
+
+    let route = Route {
+        dest: dest & netmask,
+        netmask,
+        gateway,
+        device,
+        valid: true,
+    };
+
+    unsafe {
+        let slot = (0..MAX_ROUTES)
+            .find(|&i| ROUTES[i].valid && ROUTES[i].dest == route.dest && ROUTES[i].netmask == netmask)
+            .or_else(|| (0..MAX_ROUTES).find(|&i| !ROUTES[i].valid))
+            .ok_or(())?;
+        ROUTES[slot] = route;
+    }
+
+    Ok(())
+}
+
+/// Remove the route for `dest`/`netmask`
+pub fn remove_route(dest: u32, netmask: u32) -> Result<(), ()> {
+    unsafe {
+        for i in 0..MAX_ROUTES {
+            if ROUTES[i].valid && ROUTES[i].dest == (dest & netmask) && ROUTES[i].netmask == netmask {
+                ROUTES[i].valid = false;
+                return Ok(());
+            }
+        }
+    }
+
+    Err(())
+}
+
+/// Remove every route through `device` (e.g. when its address is lost)
+pub fn remove_device_routes(device: usize) {
+    unsafe {
+        for i in 0..MAX_ROUTES {
+            if ROUTES[i].device == device {
+                ROUTES[i].valid = false;
+            }
+        }
+    }
+}
+
+/// Pick the outgoing device and next hop for `dest_ip`.
+/// Returns (device index, next-hop address).
+pub fn route_lookup(dest_ip: u32) -> Option<(usize, u32)> {
+    // Our own addresses never leave the host
+    if network::find_device_by_ip(dest_ip).is_some() {
+        return network::loopback_device().map(|lo| (lo, dest_ip));
+    }
+
+    // Limited broadcast goes out of the first real interface with link, even
+    // unconfigured (DHCP); so does multicast, which has no gateway
+    if dest_ip == IP_BROADCAST || is_multicast(dest_ip) {
+        return network::first_hardware_device().map(|dev| (dev, dest_ip));
+    }
+
+    let mut best: Option<Route> = None;
+    unsafe {
+        for route in ROUTES.iter() {
+            if !route.valid || (dest_ip & route.netmask) != route.dest {
+                continue;
+            }
+            if !network::is_link_up(route.device) {
+                continue;
+            }
+            // Longest prefix wins
+            if best.map(|b| route.netmask.count_ones() > b.netmask.count_ones()).unwrap_or(true) {
+                best = Some(*route);
+            }
+        }
+    }
+
+    best.map(|route| {
+        let next_hop = if route.gateway != 0 { route.gateway } else { dest_ip };
+        (route.device, next_hop)
+    })
+}
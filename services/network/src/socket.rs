@@ -5,6 +5,14 @@
 use crate::tcp;
 use crate::udp;
 use crate::ip;
+use crate::network;
+use crate::dhcp;
+use crate::frame_queue;
+use crate::keepalive::{Keepalive, TCP_CONNECT_TIMEOUT_MS as DEFAULT_CONNECT_TIMEOUT_MS};
+use crate::port_table::{PortProtocol, PortTable};
+use crate::udp_demux::{udp_demux, udp_demux_all, UdpEndpoint};
+use crate::multicast::Memberships;
+use alloc::vec::Vec;
 
 /// Socket types
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -12,7 +20,7 @@ use crate::ip;
 pub enum SocketType {
     Stream = 1,      // TCP
     Datagram = 2,    // UDP
-    Raw = 3,         // Raw IP
+    Raw = 3,         // Raw IP, or whole Ethernet frames (see ETH_P_ALL)
 }
 
 /// Socket address families
@@ -23,6 +31,73 @@ pub enum AddressFamily {
     Inet6 = 10,      // IPv6
 }
 
+/// Raw sockets bound with this protocol (in the port field, network byte
+/// order) receive a copy of every frame the interfaces accept and send
+/// whole frames. The bound IP picks the interface; 0 means all of them.
+pub const ETH_P_ALL: u16 = 0x0003;
+
+/// Ethernet header, and the largest frame without FCS
+const ETH_HEADER_LEN: usize = 14;
+const ETH_MAX_FRAME: usize = 1518;
+
+/// Largest UDP payload in an unfragmented datagram
+const UDP_MAX_PAYLOAD: usize = 1472;
+/// Queued datagrams start with the sender: IPv4 address, then port
+const DATAGRAM_SOURCE_LEN: usize = 6;
+
+/// Socket option levels
+pub const SOL_SOCKET: u32 = 1;
+pub const IPPROTO_IP: u32 = 0;
+pub const IPPROTO_TCP: u32 = 6;
+
+/// Socket-level options (values are u32 LE)
+pub const SO_REUSEADDR: u32 = 2;        // Allow binding a port whose connections are in TIME_WAIT
+pub const SO_BROADCAST: u32 = 6;        // UDP: send to and receive broadcast addresses
+pub const SO_SNDBUF: u32 = 7;           // Send buffer size in bytes
+pub const SO_RCVBUF: u32 = 8;           // Receive buffer size in bytes
+pub const SO_KEEPALIVE: u32 = 9;        // Probe idle TCP connections and drop dead ones
+pub const SO_NONBLOCK: u32 = 0x1000;    // Non-zero u32: recv/accept return WouldBlock instead of waiting
+
+/// IP-level socket options; the value is `[group:4][interface:4]`, both in
+/// network byte order, and the interface is chosen by route
+pub const IP_ADD_MEMBERSHIP: u32 = 35;  // UDP: receive datagrams sent to a multicast group
+pub const IP_DROP_MEMBERSHIP: u32 = 36; // UDP: stop receiving them
+
+/// TCP-level socket options
+pub const TCP_NODELAY: u32 = 1;         // Disable Nagle coalescing
+pub const TCP_KEEPIDLE: u32 = 4;        // Idle seconds before the first keepalive probe
+pub const TCP_KEEPINTVL: u32 = 5;       // Seconds between keepalive probes
+pub const TCP_KEEPCNT: u32 = 6;         // Unanswered probes before the connection is dropped
+pub const TCP_RTO_MS: u32 = 0x1001;     // Current retransmission timeout in ms (read-only)
+pub const TCP_CONNECT_TIMEOUT_MS: u32 = 0x1002; // Handshake time limit in ms, 0 for none
+
+/// Socket buffer size limits (SO_SNDBUF/SO_RCVBUF); stream sockets are
+/// further held to what a TCP connection can buffer
+const SOCKET_MIN_BUFFER: usize = 1024;
+const SOCKET_MAX_BUFFER: usize = 65536;
+
+/// Poll events (same values as POSIX poll)
+pub const POLLIN: u16 = 0x0001;         // Readable (data, EOF or a pending connection)
+pub const POLLOUT: u16 = 0x0004;        // Writable
+pub const POLLERR: u16 = 0x0008;        // Error (always reported)
+pub const POLLHUP: u16 = 0x0010;        // Peer closed (always reported)
+pub const POLLNVAL: u16 = 0x0020;       // Invalid socket (always reported)
+
+/// Socket operation errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketError {
+    /// Non-blocking socket has nothing to return yet
+    WouldBlock,
+    /// Invalid socket or failed operation
+    Failed,
+}
+
+impl From<()> for SocketError {
+    fn from(_: ()) -> Self {
+        SocketError::Failed
+    }
+}
+
 /// Socket state
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SocketState {
@@ -66,6 +141,30 @@ pub struct Socket {
     pub receive_len: usize,
     pub send_buffer: [u8; 65536],
     pub send_len: usize,
+    /// SO_NONBLOCK: recv/accept return WouldBlock instead of waiting
+    pub nonblocking: bool,
+    /// SO_REUSEADDR
+    pub reuse_addr: bool,
+    /// SO_BROADCAST
+    pub broadcast: bool,
+    /// Multicast groups joined with IP_ADD_MEMBERSHIP
+    pub memberships: Memberships,
+    /// TCP_NODELAY
+    pub no_delay: bool,
+    /// SO_KEEPALIVE, with the TCP_KEEPIDLE/TCP_KEEPINTVL/TCP_KEEPCNT timing
+    pub keepalive: bool,
+    pub keepalive_timing: Keepalive,
+    /// TCP_CONNECT_TIMEOUT_MS
+    pub connect_timeout_ms: u64,
+    /// SO_RCVBUF / SO_SNDBUF
+    pub recv_buffer_size: usize,
+    pub send_buffer_size: usize,
+    /// Poll events currently ready, refreshed by the network loop
+    pub readiness: u16,
+    /// Raw socket bound with ETH_P_ALL: frames queue in receive_buffer
+    pub link_layer: bool,
+    /// Interface a link-layer socket is bound to; None for all of them
+    pub link_device: Option<usize>,
 }
 
 impl Socket {
@@ -85,13 +184,75 @@ impl Socket {
             receive_len: 0,
             send_buffer: [0; 65536],
             send_len: 0,
+            nonblocking: false,
+            reuse_addr: false,
+            broadcast: false,
+            memberships: Memberships::new(),
+            no_delay: false,
+            keepalive: false,
+            keepalive_timing: Keepalive::DEFAULT,
+            connect_timeout_ms: DEFAULT_CONNECT_TIMEOUT_MS,
+            recv_buffer_size: tcp::TCP_DEFAULT_BUFFER_SIZE,
+            send_buffer_size: tcp::TCP_DEFAULT_BUFFER_SIZE,
+            readiness: 0,
+            link_layer: false,
+            link_device: None,
         }
     }
 }
 
 const MAX_SOCKETS: usize = 256;
-static mut SOCKETS: [Option<Socket>; MAX_SOCKETS] = [None; MAX_SOCKETS];
+const NO_SOCKET: Option<Socket> = None;
+static mut SOCKETS: [Option<Socket>; MAX_SOCKETS] = [NO_SOCKET; MAX_SOCKETS];
 static mut SOCKET_COUNT: usize = 0;
+static mut PORTS: PortTable = PortTable::new();
+
+fn ports() -> &'static mut PortTable {
+    unsafe { &mut *core::ptr::addr_of_mut!(PORTS) }
+}
+
+/// Port table owner of the stack's own DNS resolver (not a socket)
+const RESOLVER_OWNER: usize = MAX_SOCKETS;
+
+/// Hold UDP `port` for a DNS query so no socket binds it meanwhile;
+/// fails if a socket already has it
+pub fn reserve_resolver_port(port: u16) -> Result<(), ()> {
+    ports().bind(PortProtocol::Udp, port, RESOLVER_OWNER, false)
+}
+
+/// Give back the port held for the DNS query that finished
+pub fn release_resolver_port() {
+    ports().release(RESOLVER_OWNER);
+}
+
+/// Port namespace a socket type binds in; raw sockets have none
+fn port_protocol(socket_type: SocketType) -> Option<PortProtocol> {
+    match socket_type {
+        SocketType::Stream => Some(PortProtocol::Tcp),
+        SocketType::Datagram => Some(PortProtocol::Udp),
+        SocketType::Raw => None,
+    }
+}
+
+/// Local port of a TCP or UDP socket, binding a free ephemeral port first
+/// if the socket was never bound
+fn ensure_local_port(socket_fd: usize, socket: &mut Socket) -> Result<u16, ()> {
+    let port = u16::from_be(socket.local_addr.port);
+    if port != 0 {
+        return Ok(port);
+    }
+
+    let protocol = port_protocol(socket.socket_type).ok_or(())?;
+    // Skip ports TCP connections still linger on after their socket closed
+    let port = ports().bind_ephemeral(protocol, socket_fd, |port| {
+        protocol == PortProtocol::Tcp && !matches!(tcp::tcp_port_usage(port), tcp::TcpPortUsage::Free)
+    })?;
+    socket.local_addr.port = port.to_be();
+    if socket.state == SocketState::Closed {
+        socket.state = SocketState::Bound;
+    }
+    Ok(port)
+}
 
 /// Create socket
 pub fn socket_create(socket_type: SocketType) -> Result<usize, ()> {
@@ -121,9 +282,46 @@ pub fn socket_bind(socket_fd: usize, addr: SocketAddr) -> Result<(), ()> {
                 return Err(()); // Already bound
             }
 
+            let port = u16::from_be(addr.port);
+            let mut reap_time_wait = false;
+            if socket.socket_type == SocketType::Stream && port != 0 {
+                match tcp::tcp_port_usage(port) {
+                    tcp::TcpPortUsage::Free => {}
+                    tcp::TcpPortUsage::TimeWait if socket.reuse_addr => reap_time_wait = true,
+                    _ => return Err(()), // Port in use
+                }
+            }
+
+            // The stack's own DHCP client receives on this
+            if socket.socket_type == SocketType::Datagram && port == dhcp::DHCP_CLIENT_PORT {
+                return Err(());
+            }
+
+            if socket.socket_type == SocketType::Raw && port == ETH_P_ALL {
+                let ip = u32::from_be(addr.ip);
+                socket.link_device = match ip {
+                    0 => None,
+                    _ => Some(network::find_device_by_ip(ip).ok_or(())?),
+                };
+                socket.link_layer = true;
+            }
+
+            let protocol = port_protocol(socket.socket_type);
+            if let (Some(protocol), true) = (protocol, port != 0) {
+                ports().bind(protocol, port, socket_fd, socket.reuse_addr)?;
+            }
+            if reap_time_wait {
+                tcp::tcp_reap_time_wait(port);
+            }
+
             socket.local_addr = addr;
             socket.state = SocketState::Bound;
 
+            // Port 0 asks for any free port
+            if protocol.is_some() && port == 0 {
+                ensure_local_port(socket_fd, socket)?;
+            }
+
             Ok(())
         } else {
             Err(())
@@ -147,11 +345,13 @@ pub fn socket_listen(socket_fd: usize, backlog: u32) -> Result<(), ()> {
                 return Err(()); // Must be bound first
             }
 
-            // Set up listen queue with backlog size
-            // In full implementation, would allocate queue for pending connections
-            // For now, just mark as listening
-            let _ = backlog;
+            // Passive open; completed handshakes queue up for socket_accept
+            let local_ip = u32::from_be(socket.local_addr.ip);
+            let local_port = u16::from_be(socket.local_addr.port);
+            let conn_id = tcp::tcp_listen(local_ip, local_port, backlog as usize)?;
+            apply_tcp_options(socket, conn_id);
 
+            socket.tcp_connection_id = Some(conn_id);
             socket.state = SocketState::Listening;
 
             Ok(())
@@ -175,7 +375,7 @@ pub fn socket_connect(socket_fd: usize, addr: SocketAddr) -> Result<(), ()> {
                 SocketType::Stream => {
                     // TCP connect
                     let local_ip = u32::from_be(socket.local_addr.ip);
-                    let local_port = u16::from_be(socket.local_addr.port);
+                    let local_port = ensure_local_port(socket_fd, socket)?;
                     let remote_ip = u32::from_be(addr.ip);
                     let remote_port = u16::from_be(addr.port);
 
@@ -187,6 +387,7 @@ pub fn socket_connect(socket_fd: usize, addr: SocketAddr) -> Result<(), ()> {
                     )?;
 
                     socket.tcp_connection_id = Some(conn_id);
+                    apply_tcp_options(socket, conn_id);
 
                     // Initiate TCP handshake
                     tcp::tcp_connect(conn_id)?;
@@ -196,7 +397,8 @@ pub fn socket_connect(socket_fd: usize, addr: SocketAddr) -> Result<(), ()> {
                     Ok(())
                 }
                 SocketType::Datagram => {
-                    // UDP "connect" just sets remote address
+                    // UDP "connect" just sets remote address (and a port replies come back to)
+                    ensure_local_port(socket_fd, socket)?;
                     socket.state = SocketState::Connected;
                     Ok(())
                 }
@@ -212,32 +414,80 @@ pub fn socket_connect(socket_fd: usize, addr: SocketAddr) -> Result<(), ()> {
 }
 
 /// Accept incoming connection (TCP only)
-pub fn socket_accept(socket_fd: usize) -> Result<(usize, SocketAddr), ()> {
+/// Waits for a connection unless the socket is non-blocking, in which case
+/// `WouldBlock` is returned when none is pending.
+pub fn socket_accept(socket_fd: usize) -> Result<(usize, SocketAddr), SocketError> {
     unsafe {
         if socket_fd >= MAX_SOCKETS {
-            return Err(());
+            return Err(SocketError::Failed);
         }
 
-        if let Some(ref socket) = SOCKETS[socket_fd] {
-            if socket.socket_type != SocketType::Stream {
-                return Err(());
+        let (listen_id, nonblocking, options) = match SOCKETS[socket_fd] {
+            Some(ref socket) => {
+                if socket.socket_type != SocketType::Stream {
+                    return Err(SocketError::Failed);
+                }
+
+                if socket.state != SocketState::Listening {
+                    return Err(SocketError::Failed);
+                }
+
+                let options = (
+                    socket.reuse_addr,
+                    socket.no_delay,
+                    socket.recv_buffer_size,
+                    socket.send_buffer_size,
+                    socket.keepalive,
+                    socket.keepalive_timing,
+                    socket.connect_timeout_ms,
+                );
+                (socket.tcp_connection_id.ok_or(())?, socket.nonblocking, options)
             }
+            None => return Err(SocketError::Failed),
+        };
 
-            if socket.state != SocketState::Listening {
-                return Err(());
+        // Take the next established connection off the listener
+        let conn_id = loop {
+            if let Some(conn_id) = tcp::tcp_accept(listen_id) {
+                break conn_id;
+            }
+            if nonblocking {
+                return Err(SocketError::WouldBlock);
             }
+            wait_for_network();
+        };
+        refresh_readiness(socket_fd);
 
-            // Check listen queue for pending connections
-            // In full implementation, would check queue and return next connection
-            // For now, return error (no pending connections)
-            // Full implementation would:
-            // 1. Check if queue has pending connections
-            // 2. Create new socket for accepted connection
-            // 3. Return new socket FD
-            Err(())
-        } else {
-            Err(())
+        let (local_ip, local_port, remote_ip, remote_port) = tcp::tcp_get_endpoints(conn_id).ok_or(())?;
+
+        let new_fd = match socket_create(SocketType::Stream) {
+            Ok(fd) => fd,
+            Err(_) => {
+                let _ = tcp::tcp_close(conn_id);
+                return Err(SocketError::Failed);
+            }
+        };
+
+        let remote_addr = SocketAddr::new(remote_ip, remote_port);
+        if let Some(ref mut new_socket) = SOCKETS[new_fd] {
+            new_socket.local_addr = SocketAddr::new(local_ip, local_port);
+            new_socket.remote_addr = remote_addr;
+            new_socket.tcp_connection_id = Some(conn_id);
+            new_socket.state = SocketState::Connected;
+
+            // Accepted sockets inherit the listener's options
+            let (reuse_addr, no_delay, recv_size, send_size, keepalive, keepalive_timing, connect_timeout_ms) = options;
+            new_socket.reuse_addr = reuse_addr;
+            new_socket.no_delay = no_delay;
+            new_socket.recv_buffer_size = recv_size;
+            new_socket.send_buffer_size = send_size;
+            new_socket.keepalive = keepalive;
+            new_socket.keepalive_timing = keepalive_timing;
+            new_socket.connect_timeout_ms = connect_timeout_ms;
         }
+        refresh_readiness(new_fd);
+
+        Ok((new_fd, remote_addr))
     }
 }
 
@@ -255,8 +505,7 @@ pub fn socket_send(socket_fd: usize, data: &[u8], flags: u32) -> Result<usize, (
                 SocketType::Stream => {
                     // TCP send
                     if let Some(conn_id) = socket.tcp_connection_id {
-                        tcp::tcp_send(conn_id, data)?;
-                        Ok(data.len())
+                        tcp::tcp_send(conn_id, data)
                     } else {
                         Err(())
                     }
@@ -265,11 +514,23 @@ pub fn socket_send(socket_fd: usize, data: &[u8], flags: u32) -> Result<usize, (
                     // UDP send
                     let remote_ip = u32::from_be(socket.remote_addr.ip);
                     let remote_port = u16::from_be(socket.remote_addr.port);
-                    let local_port = u16::from_be(socket.local_addr.port);
+                    if is_broadcast_destination(remote_ip) && !socket.broadcast {
+                        return Err(());
+                    }
+                    let local_port = ensure_local_port(socket_fd, socket)?;
 
                     udp::udp_send(remote_ip, remote_port, local_port, data)?;
                     Ok(data.len())
                 }
+                SocketType::Raw if socket.link_layer => {
+                    // The caller supplies the whole frame, headers included
+                    if data.len() < ETH_HEADER_LEN || data.len() > ETH_MAX_FRAME {
+                        return Err(());
+                    }
+                    let device_idx = socket.link_device.or_else(network::first_hardware_device).ok_or(())?;
+                    network::device_send(device_idx, data)?;
+                    Ok(data.len())
+                }
                 SocketType::Raw => {
                     // Raw IP send
                     let remote_ip = u32::from_be(socket.remote_addr.ip);
@@ -284,36 +545,64 @@ pub fn socket_send(socket_fd: usize, data: &[u8], flags: u32) -> Result<usize, (
 }
 
 /// Receive data from socket
-pub fn socket_recv(socket_fd: usize, buffer: &mut [u8], flags: u32) -> Result<usize, ()> {
+/// Waits for data unless the socket is non-blocking, in which case `WouldBlock`
+/// is returned when nothing is available. Returns 0 at end of stream.
+pub fn socket_recv(socket_fd: usize, buffer: &mut [u8], flags: u32) -> Result<usize, SocketError> {
     unsafe {
         if socket_fd >= MAX_SOCKETS {
-            return Err(());
+            return Err(SocketError::Failed);
         }
 
-        if let Some(ref mut socket) = SOCKETS[socket_fd] {
-            let _ = flags;
-
-            match socket.socket_type {
-                SocketType::Stream => {
-                    // TCP receive
-                    if let Some(conn_id) = socket.tcp_connection_id {
-                        tcp::tcp_receive(conn_id, buffer)
-                    } else {
-                        Err(())
+        let (socket_type, conn_id, nonblocking, link_layer) = match SOCKETS[socket_fd] {
+            Some(ref socket) => (socket.socket_type, socket.tcp_connection_id, socket.nonblocking, socket.link_layer),
+            None => return Err(SocketError::Failed),
+        };
+        let _ = flags;
+
+        let result = match socket_type {
+            SocketType::Stream => {
+                // TCP receive
+                let conn_id = conn_id.ok_or(())?;
+                loop {
+                    match tcp::tcp_readiness(conn_id) {
+                        Some(ready) if ready.readable => break tcp::tcp_receive(conn_id, buffer).map_err(SocketError::from),
+                        Some(_) => {}
+                        None => break Err(SocketError::Failed),
+                    }
+                    if nonblocking {
+                        break Err(SocketError::WouldBlock);
                     }
+                    wait_for_network();
                 }
-                SocketType::Datagram => {
-                    // UDP receive
-                    udp::udp_receive(buffer).map(|(len, _, _, _)| len)
+            }
+            SocketType::Datagram => datagram_recv(socket_fd, buffer).map(|(len, _)| len),
+            SocketType::Raw if link_layer => loop {
+                // Oldest queued frame; a short buffer gets its head only
+                if let Some(ref mut socket) = SOCKETS[socket_fd] {
+                    if let Some(len) = frame_queue::pop_frame(&mut socket.receive_buffer, &mut socket.receive_len, buffer) {
+                        break Ok(len);
+                    }
                 }
-                SocketType::Raw => {
-                    // Raw IP receive
-                    ip::ip_receive(buffer).map(|(len, _, _)| len)
+                if nonblocking {
+                    break Err(SocketError::WouldBlock);
+                }
+                wait_for_network();
+                if SOCKETS[socket_fd].is_none() {
+                    break Err(SocketError::Failed);
+                }
+            },
+            SocketType::Raw => {
+                // Raw IP receive
+                match ip::ip_receive(buffer) {
+                    Ok((len, _, _)) => Ok(len),
+                    Err(_) if nonblocking => Err(SocketError::WouldBlock),
+                    Err(_) => Err(SocketError::Failed),
                 }
             }
-        } else {
-            Err(())
-        }
+        };
+
+        refresh_readiness(socket_fd);
+        result
     }
 }
 
@@ -324,7 +613,7 @@ pub fn socket_sendto(socket_fd: usize, data: &[u8], addr: SocketAddr, flags: u32
             return Err(());
         }
 
-        if let Some(ref socket) = SOCKETS[socket_fd] {
+        if let Some(ref mut socket) = SOCKETS[socket_fd] {
             let _ = flags;
 
             if socket.socket_type != SocketType::Datagram {
@@ -333,7 +622,10 @@ pub fn socket_sendto(socket_fd: usize, data: &[u8], addr: SocketAddr, flags: u32
 
             let remote_ip = u32::from_be(addr.ip);
             let remote_port = u16::from_be(addr.port);
-            let local_port = u16::from_be(socket.local_addr.port);
+            if is_broadcast_destination(remote_ip) && !socket.broadcast {
+                return Err(());
+            }
+            let local_port = ensure_local_port(socket_fd, socket)?;
 
             udp::udp_send(remote_ip, remote_port, local_port, data)?;
             Ok(data.len())
@@ -344,29 +636,128 @@ pub fn socket_sendto(socket_fd: usize, data: &[u8], addr: SocketAddr, flags: u32
 }
 
 /// Receive data with source address (UDP)
+/// Waits for a datagram unless the socket is non-blocking.
 pub fn socket_recvfrom(socket_fd: usize, buffer: &mut [u8], flags: u32) -> Result<(usize, SocketAddr), ()> {
     unsafe {
         if socket_fd >= MAX_SOCKETS {
             return Err(());
         }
 
-        if let Some(ref socket) = SOCKETS[socket_fd] {
-            let _ = flags;
+        match SOCKETS[socket_fd] {
+            Some(ref socket) if socket.socket_type == SocketType::Datagram => {}
+            _ => return Err(()),
+        }
+        let _ = flags;
 
-            if socket.socket_type != SocketType::Datagram {
-                return Err(());
+        let result = datagram_recv(socket_fd, buffer).map_err(|_| ());
+        refresh_readiness(socket_fd);
+        result
+    }
+}
+
+/// Take the oldest datagram queued on a UDP socket: (bytes copied, sender)
+fn datagram_recv(socket_fd: usize, buffer: &mut [u8]) -> Result<(usize, SocketAddr), SocketError> {
+    let mut record = [0u8; DATAGRAM_SOURCE_LEN + UDP_MAX_PAYLOAD];
+    loop {
+        let nonblocking = unsafe {
+            match SOCKETS[socket_fd] {
+                Some(ref mut socket) => {
+                    if let Some(len) = frame_queue::pop_frame(&mut socket.receive_buffer, &mut socket.receive_len, &mut record) {
+                        let src_ip = u32::from_be_bytes([record[0], record[1], record[2], record[3]]);
+                        let src_port = u16::from_be_bytes([record[4], record[5]]);
+                        let payload = &record[DATAGRAM_SOURCE_LEN..len.max(DATAGRAM_SOURCE_LEN)];
+                        let copy_len = payload.len().min(buffer.len());
+                        buffer[..copy_len].copy_from_slice(&payload[..copy_len]);
+                        return Ok((copy_len, SocketAddr::new(src_ip, src_port)));
+                    }
+                    socket.nonblocking
+                }
+                None => return Err(SocketError::Failed),
             }
+        };
+        if nonblocking {
+            return Err(SocketError::WouldBlock);
+        }
+        wait_for_network();
+    }
+}
 
-            let (len, src_ip, src_port, _) = udp::udp_receive(buffer)?;
-            let addr = SocketAddr::new(src_ip, src_port);
+/// Whether `dest_ip` is the limited broadcast or the directed broadcast of
+/// the subnet it routes to; sending there needs SO_BROADCAST
+fn is_broadcast_destination(dest_ip: u32) -> bool {
+    dest_ip == ip::IP_BROADCAST
+        || crate::route::route_lookup(dest_ip).is_some_and(|(device, _)| ip::is_broadcast(dest_ip, device))
+}
 
-            Ok((len, addr))
-        } else {
-            Err(())
+/// Addresses of a UDP socket, for demultiplexing
+fn udp_endpoint(socket: &Socket) -> UdpEndpoint {
+    UdpEndpoint {
+        local_ip: u32::from_be(socket.local_addr.ip),
+        local_port: u16::from_be(socket.local_addr.port),
+        remote: match socket.state {
+            SocketState::Connected => Some((u32::from_be(socket.remote_addr.ip), u16::from_be(socket.remote_addr.port))),
+            _ => None,
+        },
+    }
+}
+
+/// Queue a received UDP datagram on the socket it is addressed to (see
+/// `udp_demux` for which socket wins when several share the port).
+/// Returns false if no socket is bound to the port; a datagram for a
+/// socket whose queue is full is dropped.
+pub fn socket_deliver_datagram(src_ip: u32, src_port: u16, dest_ip: u32, dest_port: u16, payload: &[u8]) -> bool {
+    let sockets = unsafe { &mut *core::ptr::addr_of_mut!(SOCKETS) };
+    let endpoints = sockets.iter().enumerate().filter_map(|(fd, socket)| match socket {
+        Some(socket) if socket.socket_type == SocketType::Datagram => Some((fd, udp_endpoint(socket))),
+        _ => None,
+    });
+    let target = udp_demux(endpoints, src_ip, src_port, dest_ip, dest_port);
+
+    match target.and_then(|fd| sockets[fd].as_mut()) {
+        Some(socket) => {
+            queue_datagram(socket, src_ip, src_port, payload);
+            true
         }
+        None => false,
     }
 }
 
+/// Queue a copy of a broadcast or multicast datagram on every UDP socket
+/// bound to its port that asked for it: with SO_BROADCAST for a broadcast,
+/// as a member of the group for multicast. Returns how many got one.
+pub fn socket_deliver_group(src_ip: u32, src_port: u16, dest_ip: u32, dest_port: u16, payload: &[u8]) -> usize {
+    let sockets = unsafe { &mut *core::ptr::addr_of_mut!(SOCKETS) };
+    let multicast = ip::is_multicast(dest_ip);
+    let endpoints = sockets.iter().enumerate().filter_map(|(fd, socket)| match socket {
+        Some(socket) if socket.socket_type == SocketType::Datagram => {
+            let wanted = if multicast { socket.memberships.contains(dest_ip) } else { socket.broadcast };
+            wanted.then(|| (fd, udp_endpoint(socket)))
+        }
+        _ => None,
+    });
+    let targets: Vec<usize> = udp_demux_all(endpoints, src_ip, src_port, dest_ip, dest_port).collect();
+
+    for &fd in &targets {
+        if let Some(socket) = sockets[fd].as_mut() {
+            queue_datagram(socket, src_ip, src_port, payload);
+        }
+    }
+    targets.len()
+}
+
+/// Queue a datagram with its sender on a UDP socket, dropping it if the
+/// queue is full
+fn queue_datagram(socket: &mut Socket, src_ip: u32, src_port: u16, payload: &[u8]) {
+    let mut record = [0u8; DATAGRAM_SOURCE_LEN + UDP_MAX_PAYLOAD];
+    let len = payload.len().min(UDP_MAX_PAYLOAD);
+    record[0..4].copy_from_slice(&src_ip.to_be_bytes());
+    record[4..6].copy_from_slice(&src_port.to_be_bytes());
+    record[DATAGRAM_SOURCE_LEN..DATAGRAM_SOURCE_LEN + len].copy_from_slice(&payload[..len]);
+
+    let capacity = socket.recv_buffer_size;
+    let _ = frame_queue::push_frame(&mut socket.receive_buffer, &mut socket.receive_len, capacity, &record[..DATAGRAM_SOURCE_LEN + len]);
+}
+
 /// Close socket
 pub fn socket_close(socket_fd: usize) -> Result<(), ()> {
     unsafe {
@@ -382,6 +773,7 @@ pub fn socket_close(socket_fd: usize) -> Result<(), ()> {
 
             SOCKETS[socket_fd] = None;
             SOCKET_COUNT -= 1;
+            ports().release(socket_fd);
 
             Ok(())
         } else {
@@ -391,17 +783,82 @@ pub fn socket_close(socket_fd: usize) -> Result<(), ()> {
 }
 
 /// Set socket option
+/// Unknown or read-only options are rejected.
 pub fn socket_setsockopt(socket_fd: usize, level: u32, optname: u32, optval: &[u8]) -> Result<(), ()> {
     unsafe {
         if socket_fd >= MAX_SOCKETS {
             return Err(());
         }
 
-        if SOCKETS[socket_fd].is_some() {
-            // Implement socket options
-            // Common options: SO_REUSEADDR, SO_KEEPALIVE, TCP_NODELAY, etc.
-            // For now, just acknowledge (options not fully implemented)
-            let _ = (level, optname, optval);
+        if let Some(ref mut socket) = SOCKETS[socket_fd] {
+            if optval.len() < 4 {
+                return Err(());
+            }
+            let value = u32::from_le_bytes([optval[0], optval[1], optval[2], optval[3]]);
+            let max_buffer = match socket.socket_type {
+                SocketType::Stream => tcp::TCP_MAX_BUFFER_SIZE,
+                _ => SOCKET_MAX_BUFFER,
+            };
+
+            match (level, optname) {
+                (SOL_SOCKET, SO_NONBLOCK) => socket.nonblocking = value != 0,
+                (SOL_SOCKET, SO_REUSEADDR) => socket.reuse_addr = value != 0,
+                (SOL_SOCKET, SO_RCVBUF) => {
+                    socket.recv_buffer_size = (value as usize).clamp(SOCKET_MIN_BUFFER, max_buffer);
+                }
+                (SOL_SOCKET, SO_SNDBUF) => {
+                    socket.send_buffer_size = (value as usize).clamp(SOCKET_MIN_BUFFER, max_buffer);
+                }
+                (IPPROTO_TCP, TCP_NODELAY) if socket.socket_type == SocketType::Stream => {
+                    socket.no_delay = value != 0;
+                }
+                (SOL_SOCKET, SO_KEEPALIVE) if socket.socket_type == SocketType::Stream => {
+                    socket.keepalive = value != 0;
+                }
+                (SOL_SOCKET, SO_BROADCAST) if socket.socket_type == SocketType::Datagram => {
+                    socket.broadcast = value != 0;
+                }
+                (IPPROTO_IP, IP_ADD_MEMBERSHIP) if socket.socket_type == SocketType::Datagram => {
+                    let group = u32::from_be_bytes([optval[0], optval[1], optval[2], optval[3]]);
+                    if socket.memberships.contains(group) {
+                        return Ok(());
+                    }
+                    let (device, _) = crate::route::route_lookup(group).ok_or(())?;
+                    if !socket.memberships.join(group) {
+                        return Err(());
+                    }
+                    if network::device_add_multicast(device, group).is_err() {
+                        socket.memberships.leave(group);
+                        return Err(());
+                    }
+                }
+                (IPPROTO_IP, IP_DROP_MEMBERSHIP) if socket.socket_type == SocketType::Datagram => {
+                    // The NIC keeps accepting the group's frames (its filter
+                    // is a shared hash); nothing is queued for this socket
+                    let group = u32::from_be_bytes([optval[0], optval[1], optval[2], optval[3]]);
+                    if !socket.memberships.leave(group) {
+                        return Err(());
+                    }
+                }
+                (IPPROTO_TCP, TCP_KEEPIDLE) if socket.socket_type == SocketType::Stream && value > 0 => {
+                    socket.keepalive_timing.idle_ms = value as u64 * 1000;
+                }
+                (IPPROTO_TCP, TCP_KEEPINTVL) if socket.socket_type == SocketType::Stream && value > 0 => {
+                    socket.keepalive_timing.interval_ms = value as u64 * 1000;
+                }
+                (IPPROTO_TCP, TCP_KEEPCNT) if socket.socket_type == SocketType::Stream && value > 0 => {
+                    socket.keepalive_timing.probes = value;
+                }
+                (IPPROTO_TCP, TCP_CONNECT_TIMEOUT_MS) if socket.socket_type == SocketType::Stream => {
+                    socket.connect_timeout_ms = value as u64;
+                }
+                _ => return Err(()), // Unknown option
+            }
+
+            if let Some(conn_id) = socket.tcp_connection_id {
+                apply_tcp_options(socket, conn_id);
+            }
+
             Ok(())
         } else {
             Err(())
@@ -410,20 +867,157 @@ pub fn socket_setsockopt(socket_fd: usize, level: u32, optname: u32, optval: &[u
 }
 
 /// Get socket option
+/// Writes the current value (u32 LE) and returns its length.
 pub fn socket_getsockopt(socket_fd: usize, level: u32, optname: u32, optval: &mut [u8]) -> Result<usize, ()> {
     unsafe {
         if socket_fd >= MAX_SOCKETS {
             return Err(());
         }
 
-        if SOCKETS[socket_fd].is_some() {
-            // Implement socket options
-            // Common options: SO_REUSEADDR, SO_KEEPALIVE, TCP_NODELAY, etc.
-            // For now, return 0 (options not fully implemented)
-            let _ = (level, optname, optval);
-            Ok(0)
+        if let Some(ref socket) = SOCKETS[socket_fd] {
+            let value = match (level, optname) {
+                (SOL_SOCKET, SO_NONBLOCK) => socket.nonblocking as u32,
+                (SOL_SOCKET, SO_REUSEADDR) => socket.reuse_addr as u32,
+                (SOL_SOCKET, SO_RCVBUF) => socket.recv_buffer_size as u32,
+                (SOL_SOCKET, SO_SNDBUF) => socket.send_buffer_size as u32,
+                (IPPROTO_TCP, TCP_NODELAY) if socket.socket_type == SocketType::Stream => socket.no_delay as u32,
+                (SOL_SOCKET, SO_KEEPALIVE) if socket.socket_type == SocketType::Stream => socket.keepalive as u32,
+                (SOL_SOCKET, SO_BROADCAST) if socket.socket_type == SocketType::Datagram => socket.broadcast as u32,
+                (IPPROTO_TCP, TCP_KEEPIDLE) if socket.socket_type == SocketType::Stream => {
+                    (socket.keepalive_timing.idle_ms / 1000) as u32
+                }
+                (IPPROTO_TCP, TCP_KEEPINTVL) if socket.socket_type == SocketType::Stream => {
+                    (socket.keepalive_timing.interval_ms / 1000) as u32
+                }
+                (IPPROTO_TCP, TCP_KEEPCNT) if socket.socket_type == SocketType::Stream => socket.keepalive_timing.probes,
+                (IPPROTO_TCP, TCP_CONNECT_TIMEOUT_MS) if socket.socket_type == SocketType::Stream => {
+                    socket.connect_timeout_ms as u32
+                }
+                (IPPROTO_TCP, TCP_RTO_MS) => {
+                    let conn_id = socket.tcp_connection_id.ok_or(())?;
+                    tcp::tcp_get_rto(conn_id).ok_or(())? as u32
+                }
+                _ => return Err(()), // Unknown option
+            };
+
+            if optval.len() < 4 {
+                return Err(());
+            }
+            optval[0..4].copy_from_slice(&value.to_le_bytes());
+            Ok(4)
         } else {
             Err(())
         }
     }
 }
+
+/// Queue a copy of a frame received on `device_idx` for every link-layer
+/// raw socket bound to that interface (or to all). A socket whose queue
+/// is full (SO_RCVBUF) drops the frame.
+pub fn socket_deliver_frame(device_idx: usize, frame: &[u8]) {
+    unsafe {
+        if SOCKET_COUNT == 0 {
+            return;
+        }
+        for socket in (*core::ptr::addr_of_mut!(SOCKETS)).iter_mut().flatten() {
+            if !socket.link_layer || socket.link_device.is_some_and(|dev| dev != device_idx) {
+                continue;
+            }
+            let capacity = socket.recv_buffer_size;
+            let _ = frame_queue::push_frame(&mut socket.receive_buffer, &mut socket.receive_len, capacity, frame);
+        }
+    }
+}
+
+/// Push the socket's TCP options down to its connection
+fn apply_tcp_options(socket: &Socket, conn_id: usize) {
+    let _ = tcp::tcp_set_nodelay(conn_id, socket.no_delay);
+    let _ = tcp::tcp_set_buffer_sizes(conn_id, socket.recv_buffer_size, socket.send_buffer_size);
+    let _ = tcp::tcp_set_keepalive(conn_id, socket.keepalive.then_some(socket.keepalive_timing));
+    let _ = tcp::tcp_set_connect_timeout(conn_id, socket.connect_timeout_ms);
+}
+
+/// Let the network stack make progress while a blocking call waits
+fn wait_for_network() {
+    crate::net_poll();
+    crate::syscalls::sys_yield();
+}
+
+/// Compute the poll events currently ready on a socket
+fn compute_readiness(socket: &Socket) -> u16 {
+    match socket.socket_type {
+        SocketType::Stream => match (socket.state, socket.tcp_connection_id) {
+            (SocketState::Listening, Some(listen_id)) => {
+                if tcp::tcp_accept_pending(listen_id) { POLLIN } else { 0 }
+            }
+            (_, Some(conn_id)) => match tcp::tcp_readiness(conn_id) {
+                Some(ready) => {
+                    let mut events = 0;
+                    if ready.readable {
+                        events |= POLLIN;
+                    }
+                    if ready.writable {
+                        events |= POLLOUT;
+                    }
+                    if ready.hangup {
+                        events |= POLLHUP;
+                    }
+                    if ready.error {
+                        events |= POLLERR;
+                    }
+                    events
+                }
+                None => POLLHUP | POLLERR,
+            },
+            // Not connected yet
+            (_, None) => 0,
+        },
+        SocketType::Datagram | SocketType::Raw => {
+            if socket.receive_len > 0 { POLLIN | POLLOUT } else { POLLOUT }
+        }
+    }
+}
+
+/// Refresh the stored readiness of one socket
+fn refresh_readiness(socket_fd: usize) {
+    unsafe {
+        if let Some(ref mut socket) = SOCKETS[socket_fd] {
+            socket.readiness = compute_readiness(socket);
+        }
+    }
+}
+
+/// Refresh the readiness of every socket.
+/// Called by the network loop after incoming packets and timers are processed.
+pub fn socket_update_readiness() {
+    unsafe {
+        if SOCKET_COUNT == 0 {
+            return;
+        }
+    }
+    for fd in 0..MAX_SOCKETS {
+        refresh_readiness(fd);
+    }
+}
+
+/// Report readiness for a set of sockets without blocking.
+/// Each entry is (fd, requested events); the result holds (fd, revents) for
+/// every socket with something to report. POLLERR, POLLHUP and POLLNVAL are
+/// reported even when not requested.
+pub fn socket_poll(fds: &[(usize, u16)]) -> Vec<(usize, u16)> {
+    let mut ready = Vec::new();
+
+    for &(fd, events) in fds {
+        let revents = unsafe {
+            match SOCKETS.get(fd) {
+                Some(Some(socket)) => socket.readiness & (events | POLLERR | POLLHUP),
+                _ => POLLNVAL,
+            }
+        };
+        if revents != 0 {
+            ready.push((fd, revents));
+        }
+    }
+
+    ready
+}
@@ -33,3 +33,25 @@ pub fn sys_get_uptime_ms() -> u64 {
     }
 }
 
+
+/// Fill `buf` from the kernel's random number generator
+pub fn sys_get_random(buf: &mut [u8]) -> Result<(), ()> {
+    const SYS_GET_RANDOM: u64 = 66;
+    unsafe {
+        #[cfg(target_arch = "x86_64")]
+        {
+            let ret: u64;
+            core::arch::asm!(
+                "syscall",
+                in("rax") SYS_GET_RANDOM,
+                in("rdi") buf.as_mut_ptr() as u64,
+                in("rsi") buf.len() as u64,
+                out("rax") ret,
+                options(nostack, preserves_flags)
+            );
+            if ret == 0 { Ok(()) } else { Err(()) }
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        Err(())
+    }
+}
@@ -1,6 +1,9 @@
 //! TCP protocol implementation
 
-use core::mem;
+use crate::ip;
+use crate::isn::IsnSecret;
+use crate::keepalive::{handshake_expired, Keepalive, KeepaliveAction, TCP_CONNECT_TIMEOUT_MS};
+use crate::syscalls::{sys_get_random, sys_get_uptime_ms};
 
 /// TCP header structure
 #[repr(C, packed)]
@@ -25,8 +28,46 @@ pub const TCP_FLAG_PSH: u8 = 0x08;
 pub const TCP_FLAG_ACK: u8 = 0x10;
 pub const TCP_FLAG_URG: u8 = 0x20;
 
+/// TCP header length without options
+const TCP_HEADER_LEN: usize = 20;
+
+/// TCP option kinds
+const TCP_OPT_END: u8 = 0;
+const TCP_OPT_NOP: u8 = 1;
+const TCP_OPT_MSS: u8 = 2;
+
+/// Default and advertised maximum segment size
+const TCP_DEFAULT_MSS: u16 = 536;
+const TCP_LOCAL_MSS: u16 = 1460;
+
+/// Per-connection send/receive buffer sizes (adjustable via SO_SNDBUF/SO_RCVBUF)
+pub const TCP_DEFAULT_BUFFER_SIZE: usize = 8192;
+pub const TCP_MIN_BUFFER_SIZE: usize = 1024;
+pub const TCP_MAX_BUFFER_SIZE: usize = 16384;
+
+/// TIME_WAIT duration (2 * MSL) in milliseconds
+const TCP_TIME_WAIT_MS: u64 = 60_000;
+
+/// Retransmission timeout bounds (RFC 6298) in milliseconds
+const TCP_INITIAL_RTO_MS: u64 = 1000;
+const TCP_MIN_RTO_MS: u64 = 200;
+const TCP_MAX_RTO_MS: u64 = 60_000;
+
+/// Consecutive timeouts before the connection is reset
+const TCP_MAX_RETRIES: u32 = 8;
+
+/// Duplicate ACKs that trigger a fast retransmit
+const TCP_DUPACK_THRESHOLD: u32 = 3;
+
+/// Unacknowledged segments tracked per connection
+const TCP_RETRANSMIT_QUEUE_LEN: usize = 8;
+
+/// Upper bound on a listener's pending-connection queue
+pub const TCP_MAX_BACKLOG: usize = 16;
+
 /// TCP states
 #[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TcpState {
     Closed = 0,
     Listen = 1,
@@ -41,6 +82,41 @@ pub enum TcpState {
     TimeWait = 10,
 }
 
+/// A sent segment awaiting acknowledgement
+#[derive(Clone, Copy)]
+struct UnackedSegment {
+    seq: u32,
+    flags: u8,
+    len: usize,
+    data: [u8; TCP_LOCAL_MSS as usize],
+    /// Retransmitted at least once (no RTT sample, Karn's algorithm)
+    retransmitted: bool,
+    sent_at: u64,
+}
+
+const EMPTY_SEGMENT: UnackedSegment = UnackedSegment {
+    seq: 0,
+    flags: 0,
+    len: 0,
+    data: [0; TCP_LOCAL_MSS as usize],
+    retransmitted: false,
+    sent_at: 0,
+};
+
+impl UnackedSegment {
+    /// Sequence space occupied by the segment
+    fn seq_len(&self) -> u32 {
+        let mut len = self.len as u32;
+        if (self.flags & TCP_FLAG_SYN) != 0 {
+            len += 1;
+        }
+        if (self.flags & TCP_FLAG_FIN) != 0 {
+            len += 1;
+        }
+        len
+    }
+}
+
 /// TCP connection
 pub struct TcpConnection {
     pub local_ip: u32,
@@ -48,315 +124,1253 @@ pub struct TcpConnection {
     pub local_port: u16,
     pub remote_port: u16,
     pub state: TcpState,
-    pub seq_num: u32,
-    pub ack_num: u32,
-    pub window_size: u32,
+    /// Initial send sequence number
+    pub iss: u32,
+    /// Oldest unacknowledged sequence number
+    pub snd_una: u32,
+    /// Next sequence number to send
+    pub snd_nxt: u32,
+    /// Peer's advertised receive window
+    pub snd_wnd: u32,
+    /// Initial receive sequence number
+    pub irs: u32,
+    /// Next sequence number expected from the peer
+    pub rcv_nxt: u32,
+    /// Peer's maximum segment size
+    pub mss: u16,
+    /// Listening connection this one was spawned from (passive open)
+    pub parent: Option<usize>,
+    /// Handed out by tcp_accept
+    pub accepted: bool,
+    /// Listener only: maximum pending (half-open + unaccepted) connections
+    pub backlog: usize,
+    /// Listener only: established children waiting for tcp_accept (ring buffer)
+    accept_queue: [usize; TCP_MAX_BACKLOG],
+    accept_head: usize,
+    accept_count: usize,
+    /// Owner has called tcp_close; the slot is freed once the close completes
+    pub user_closed: bool,
+    /// Connection was reset by the peer
+    pub reset: bool,
+    /// Time TIME_WAIT was entered
+    pub time_wait_start: u64,
+    /// In-order payload not yet read by the owner
+    pub recv_buffer: [u8; TCP_MAX_BUFFER_SIZE],
+    pub recv_len: usize,
+    /// Usable part of recv_buffer (SO_RCVBUF)
+    pub recv_capacity: usize,
+    /// Data accepted by tcp_send but not yet transmitted
+    send_buffer: [u8; TCP_MAX_BUFFER_SIZE],
+    send_len: usize,
+    /// Usable part of send_buffer (SO_SNDBUF)
+    pub send_capacity: usize,
+    /// TCP_NODELAY: send small segments immediately instead of coalescing (Nagle)
+    pub nodelay: bool,
+    /// tcp_close was called; FIN goes out once the send buffer drains
+    fin_pending: bool,
+    /// Retransmission queue (ring buffer, oldest first)
+    rtx_queue: [UnackedSegment; TCP_RETRANSMIT_QUEUE_LEN],
+    rtx_head: usize,
+    rtx_count: usize,
+    /// Current retransmission timeout
+    pub rto_ms: u64,
+    /// Smoothed RTT and RTT variance (0 until the first sample)
+    srtt_ms: u64,
+    rttvar_ms: u64,
+    /// When the retransmission timer fires (0 = stopped)
+    rto_deadline: u64,
+    /// Consecutive timeouts for the oldest segment
+    retries: u32,
+    /// Duplicate ACKs seen for snd_una
+    dup_acks: u32,
+    /// SO_KEEPALIVE timing, None when off
+    pub keepalive: Option<Keepalive>,
+    /// Last time the peer sent an acceptable segment
+    last_heard: u64,
+    /// Keepalive probes sent since then
+    keepalive_probes: u32,
+    /// How long the handshake may take (0 = until the retries run out)
+    pub connect_timeout_ms: u64,
+    /// Time the SYN was sent or received
+    connect_started: u64,
+}
+
+impl TcpConnection {
+    fn new(local_ip: u32, local_port: u16, remote_ip: u32, remote_port: u16) -> Self {
+        let iss = generate_iss(local_ip, local_port, remote_ip, remote_port);
+        let now = sys_get_uptime_ms();
+        Self {
+            local_ip,
+            remote_ip,
+            local_port,
+            remote_port,
+            state: TcpState::Closed,
+            iss,
+            snd_una: iss,
+            snd_nxt: iss,
+            snd_wnd: 0,
+            irs: 0,
+            rcv_nxt: 0,
+            mss: TCP_DEFAULT_MSS,
+            parent: None,
+            accepted: false,
+            backlog: 0,
+            accept_queue: [0; TCP_MAX_BACKLOG],
+            accept_head: 0,
+            accept_count: 0,
+            user_closed: false,
+            reset: false,
+            time_wait_start: 0,
+            recv_buffer: [0; TCP_MAX_BUFFER_SIZE],
+            recv_len: 0,
+            recv_capacity: TCP_DEFAULT_BUFFER_SIZE,
+            send_buffer: [0; TCP_MAX_BUFFER_SIZE],
+            send_len: 0,
+            send_capacity: TCP_DEFAULT_BUFFER_SIZE,
+            nodelay: false,
+            fin_pending: false,
+            rtx_queue: [EMPTY_SEGMENT; TCP_RETRANSMIT_QUEUE_LEN],
+            rtx_head: 0,
+            rtx_count: 0,
+            rto_ms: TCP_INITIAL_RTO_MS,
+            srtt_ms: 0,
+            rttvar_ms: 0,
+            rto_deadline: 0,
+            retries: 0,
+            dup_acks: 0,
+            keepalive: None,
+            last_heard: now,
+            keepalive_probes: 0,
+            connect_timeout_ms: TCP_CONNECT_TIMEOUT_MS,
+            connect_started: now,
+        }
+    }
+
+    /// Queue an established child for tcp_accept
+    fn push_pending(&mut self, conn_id: usize) -> Result<(), ()> {
+        if self.accept_count >= TCP_MAX_BACKLOG {
+            return Err(());
+        }
+        let tail = (self.accept_head + self.accept_count) % TCP_MAX_BACKLOG;
+        self.accept_queue[tail] = conn_id;
+        self.accept_count += 1;
+        Ok(())
+    }
+
+    /// Take the oldest established child
+    fn pop_pending(&mut self) -> Option<usize> {
+        if self.accept_count == 0 {
+            return None;
+        }
+        let conn_id = self.accept_queue[self.accept_head];
+        self.accept_head = (self.accept_head + 1) % TCP_MAX_BACKLOG;
+        self.accept_count -= 1;
+        Some(conn_id)
+    }
+
+    /// Remove a child that died before being accepted
+    fn remove_pending(&mut self, conn_id: usize) {
+        let mut kept = 0;
+        for i in 0..self.accept_count {
+            let id = self.accept_queue[(self.accept_head + i) % TCP_MAX_BACKLOG];
+            if id != conn_id {
+                self.accept_queue[(self.accept_head + kept) % TCP_MAX_BACKLOG] = id;
+                kept += 1;
+            }
+        }
+        self.accept_count = kept;
+    }
+
+    /// Receive window we advertise (free space in the receive buffer)
+    fn rcv_wnd(&self) -> u32 {
+        self.recv_capacity.saturating_sub(self.recv_len) as u32
+    }
+
+    fn enter_time_wait(&mut self) {
+        self.state = TcpState::TimeWait;
+        self.time_wait_start = sys_get_uptime_ms();
+    }
+
+    fn rtx_full(&self) -> bool {
+        self.rtx_count == TCP_RETRANSMIT_QUEUE_LEN
+    }
+
+    fn rtx_oldest(&mut self) -> Option<&mut UnackedSegment> {
+        if self.rtx_count == 0 {
+            None
+        } else {
+            Some(&mut self.rtx_queue[self.rtx_head])
+        }
+    }
+
+    fn restart_timer(&mut self) {
+        self.rto_deadline = if self.rtx_count == 0 {
+            0
+        } else {
+            sys_get_uptime_ms() + self.rto_ms
+        };
+    }
+
+    /// Fold an RTT sample into SRTT/RTTVAR and recompute the RTO (RFC 6298)
+    fn update_rtt(&mut self, rtt_ms: u64) {
+        if self.srtt_ms == 0 {
+            self.srtt_ms = rtt_ms.max(1);
+            self.rttvar_ms = rtt_ms / 2;
+        } else {
+            let delta = if self.srtt_ms > rtt_ms { self.srtt_ms - rtt_ms } else { rtt_ms - self.srtt_ms };
+            self.rttvar_ms = (3 * self.rttvar_ms + delta) / 4;
+            self.srtt_ms = (7 * self.srtt_ms + rtt_ms) / 8;
+        }
+        self.rto_ms = (self.srtt_ms + (4 * self.rttvar_ms).max(1)).clamp(TCP_MIN_RTO_MS, TCP_MAX_RTO_MS);
+    }
+
+    /// Drop every queued segment fully covered by `ack`
+    fn process_ack(&mut self, ack: u32) {
+        let now = sys_get_uptime_ms();
+        let mut acked_any = false;
+
+        while self.rtx_count > 0 {
+            let head = self.rtx_queue[self.rtx_head];
+            if !seq_le(head.seq.wrapping_add(head.seq_len()), ack) {
+                break;
+            }
+            if !head.retransmitted {
+                self.update_rtt(now.saturating_sub(head.sent_at));
+            }
+            self.rtx_head = (self.rtx_head + 1) % TCP_RETRANSMIT_QUEUE_LEN;
+            self.rtx_count -= 1;
+            acked_any = true;
+        }
+
+        if acked_any {
+            self.retries = 0;
+            self.restart_timer();
+        }
+    }
+}
+
+/// Parsed view of an incoming segment
+struct Segment<'a> {
+    src_port: u16,
+    dest_port: u16,
+    seq: u32,
+    ack: u32,
+    flags: u8,
+    window: u16,
+    mss: Option<u16>,
+    payload: &'a [u8],
+}
+
+impl<'a> Segment<'a> {
+    fn parse(buffer: &'a [u8]) -> Option<Self> {
+        if buffer.len() < TCP_HEADER_LEN {
+            return None;
+        }
+
+        let header_len = ((buffer[12] >> 4) as usize) * 4;
+        if header_len < TCP_HEADER_LEN || header_len > buffer.len() {
+            return None;
+        }
+
+        Some(Self {
+            src_port: u16::from_be_bytes([buffer[0], buffer[1]]),
+            dest_port: u16::from_be_bytes([buffer[2], buffer[3]]),
+            seq: u32::from_be_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]),
+            ack: u32::from_be_bytes([buffer[8], buffer[9], buffer[10], buffer[11]]),
+            flags: buffer[13],
+            window: u16::from_be_bytes([buffer[14], buffer[15]]),
+            mss: parse_mss_option(&buffer[TCP_HEADER_LEN..header_len]),
+            payload: &buffer[header_len..],
+        })
+    }
+
+    fn has(&self, flag: u8) -> bool {
+        (self.flags & flag) != 0
+    }
+
+    /// Sequence space occupied by the segment (payload + SYN + FIN)
+    fn seq_len(&self) -> u32 {
+        let mut len = self.payload.len() as u32;
+        if self.has(TCP_FLAG_SYN) {
+            len += 1;
+        }
+        if self.has(TCP_FLAG_FIN) {
+            len += 1;
+        }
+        len
+    }
+}
+
+/// Extract the MSS option from a SYN's option bytes
+fn parse_mss_option(options: &[u8]) -> Option<u16> {
+    let mut i = 0;
+    while i < options.len() {
+        match options[i] {
+            TCP_OPT_END => break,
+            TCP_OPT_NOP => i += 1,
+            kind => {
+                if i + 1 >= options.len() {
+                    break;
+                }
+                let len = options[i + 1] as usize;
+                if len < 2 || i + len > options.len() {
+                    break;
+                }
+                if kind == TCP_OPT_MSS && len == 4 {
+                    return Some(u16::from_be_bytes([options[i + 2], options[i + 3]]));
+                }
+                i += len;
+            }
+        }
+    }
+    None
+}
+
+/// Sequence number comparisons (modulo 2^32)
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+fn seq_le(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) <= 0
 }
 
 const MAX_TCP_CONNECTIONS: usize = 32;
-static mut TCP_CONNECTIONS: [Option<TcpConnection>; MAX_TCP_CONNECTIONS] = [None; MAX_TCP_CONNECTIONS];
-static mut NEXT_SEQ_NUM: u32 = 1;
+const NO_CONNECTION: Option<TcpConnection> = None;
+static mut TCP_CONNECTIONS: [Option<TcpConnection>; MAX_TCP_CONNECTIONS] = [NO_CONNECTION; MAX_TCP_CONNECTIONS];
+/// Key for initial sequence numbers, chosen by tcp_init
+static mut ISS_SECRET: IsnSecret = IsnSecret::new(0, 0);
 static mut INITIALIZED: bool = false;
+static mut TCP_CHECKSUM_ERRORS: u64 = 0;
 
-/// Initialize TCP
+/// Initialize TCP. Fails if there is no randomness for the sequence
+/// number key, rather than open connections with guessable ISNs.
 pub fn tcp_init() -> Result<(), ()> {
     unsafe {
         if INITIALIZED {
             return Ok(());
         }
-        
-        NEXT_SEQ_NUM = 1;
+
+        let mut secret = [0u8; 16];
+        sys_get_random(&mut secret)?;
+        ISS_SECRET = IsnSecret::from_bytes(secret);
         INITIALIZED = true;
-        
+
         Ok(())
     }
 }
 
+/// Generate an initial sequence number for a connection (RFC 6528)
+fn generate_iss(local_ip: u32, local_port: u16, remote_ip: u32, remote_port: u16) -> u32 {
+    unsafe { ISS_SECRET.initial_sequence(sys_get_uptime_ms(), local_ip, local_port, remote_ip, remote_port) }
+}
+
+/// Free a connection slot, unlinking it from its listener's accept queue
+fn free_slot(conn_id: usize) {
+    unsafe {
+        let parent = match TCP_CONNECTIONS[conn_id] {
+            Some(ref conn) if !conn.accepted => conn.parent,
+            _ => None,
+        };
+        if let Some(listener) = parent.and_then(get_connection) {
+            listener.remove_pending(conn_id);
+        }
+        TCP_CONNECTIONS[conn_id] = None;
+    }
+}
+
+/// Find a free connection slot, reclaiming expired TIME_WAIT connections
+fn alloc_slot() -> Option<usize> {
+    unsafe {
+        for i in 0..MAX_TCP_CONNECTIONS {
+            if TCP_CONNECTIONS[i].is_none() {
+                return Some(i);
+            }
+        }
+
+        let now = sys_get_uptime_ms();
+        for i in 0..MAX_TCP_CONNECTIONS {
+            if let Some(ref conn) = TCP_CONNECTIONS[i] {
+                if conn.state == TcpState::TimeWait
+                    && now.saturating_sub(conn.time_wait_start) >= TCP_TIME_WAIT_MS
+                {
+                    free_slot(i);
+                    return Some(i);
+                }
+            }
+        }
+
+        None
+    }
+}
+
 /// Create TCP connection
 pub fn tcp_create_connection(local_ip: u32, local_port: u16, remote_ip: u32, remote_port: u16) -> Result<usize, ()> {
     unsafe {
         if !INITIALIZED {
             tcp_init()?;
         }
-        
-        // Find free slot
-        for i in 0..MAX_TCP_CONNECTIONS {
-            if TCP_CONNECTIONS[i].is_none() {
-                TCP_CONNECTIONS[i] = Some(TcpConnection {
-                    local_ip,
-                    remote_ip,
-                    local_port,
-                    remote_port,
-                    state: TcpState::Closed,
-                    seq_num: NEXT_SEQ_NUM,
-                    ack_num: 0,
-                    window_size: 65535,
-                });
-                NEXT_SEQ_NUM += 1;
-                return Ok(i);
+
+        let slot = alloc_slot().ok_or(())?;
+        TCP_CONNECTIONS[slot] = Some(TcpConnection::new(local_ip, local_port, remote_ip, remote_port));
+        Ok(slot)
+    }
+}
+
+/// Get connection by id
+fn get_connection(conn_id: usize) -> Option<&'static mut TcpConnection> {
+    unsafe {
+        if conn_id >= MAX_TCP_CONNECTIONS {
+            return None;
+        }
+        TCP_CONNECTIONS[conn_id].as_mut()
+    }
+}
+
+/// Get the current state of a connection
+pub fn tcp_get_state(conn_id: usize) -> Option<TcpState> {
+    get_connection(conn_id).map(|conn| conn.state)
+}
+
+/// Get (local_ip, local_port, remote_ip, remote_port) of a connection
+pub fn tcp_get_endpoints(conn_id: usize) -> Option<(u32, u16, u32, u16)> {
+    get_connection(conn_id).map(|conn| (conn.local_ip, conn.local_port, conn.remote_ip, conn.remote_port))
+}
+
+/// Build and transmit a segment for a connection
+fn send_segment(conn: &TcpConnection, flags: u8, seq: u32, data: &[u8]) -> Result<(), ()> {
+    let ack = if (flags & TCP_FLAG_ACK) != 0 { conn.rcv_nxt } else { 0 };
+    let window = conn.rcv_wnd().min(u16::MAX as u32) as u16;
+
+    send_raw(conn.local_port, conn.remote_ip, conn.remote_port, seq, ack, flags, window, data)
+}
+
+/// Build and transmit a segment from explicit header fields
+fn send_raw(
+    local_port: u16,
+    remote_ip: u32,
+    remote_port: u16,
+    seq: u32,
+    ack: u32,
+    flags: u8,
+    window: u16,
+    data: &[u8],
+) -> Result<(), ()> {
+    let mut packet = [0u8; 1500];
+
+    // Advertise our MSS on SYN segments
+    let header_len = if (flags & TCP_FLAG_SYN) != 0 {
+        packet[20] = TCP_OPT_MSS;
+        packet[21] = 4;
+        packet[22..24].copy_from_slice(&TCP_LOCAL_MSS.to_be_bytes());
+        TCP_HEADER_LEN + 4
+    } else {
+        TCP_HEADER_LEN
+    };
+
+    packet[0..2].copy_from_slice(&local_port.to_be_bytes());
+    packet[2..4].copy_from_slice(&remote_port.to_be_bytes());
+    packet[4..8].copy_from_slice(&seq.to_be_bytes());
+    packet[8..12].copy_from_slice(&ack.to_be_bytes());
+    packet[12] = ((header_len / 4) as u8) << 4;
+    packet[13] = flags;
+    packet[14..16].copy_from_slice(&window.to_be_bytes());
+    packet[16..18].copy_from_slice(&0u16.to_be_bytes()); // Checksum
+    packet[18..20].copy_from_slice(&0u16.to_be_bytes()); // Urgent pointer
+
+    let data_len = data.len().min(packet.len() - header_len);
+    packet[header_len..header_len + data_len].copy_from_slice(&data[0..data_len]);
+
+    let segment_len = header_len + data_len;
+    let local_ip = ip::ip_source_address(remote_ip);
+    let checksum = ip::transport_checksum(local_ip, remote_ip, ip::IP_PROTOCOL_TCP, &packet[0..segment_len]);
+    packet[16..18].copy_from_slice(&checksum.to_be_bytes());
+
+    ip::ip_send(remote_ip, ip::IP_PROTOCOL_TCP, &packet[0..segment_len])
+}
+
+/// Send a segment that consumes sequence space at snd_nxt and queue it
+/// for retransmission until it is acknowledged
+fn transmit(conn: &mut TcpConnection, flags: u8, data: &[u8]) -> Result<(), ()> {
+    if conn.rtx_full() {
+        return Err(());
+    }
+
+    let len = data.len().min(TCP_LOCAL_MSS as usize);
+    let mut entry = EMPTY_SEGMENT;
+    entry.seq = conn.snd_nxt;
+    entry.flags = flags;
+    entry.len = len;
+    entry.data[0..len].copy_from_slice(&data[0..len]);
+    entry.sent_at = sys_get_uptime_ms();
+
+    // A lost segment is recovered by the retransmit timer, so a send
+    // failure here is not fatal once the segment is queued
+    let _ = send_segment(conn, flags, entry.seq, &data[0..len]);
+
+    let tail = (conn.rtx_head + conn.rtx_count) % TCP_RETRANSMIT_QUEUE_LEN;
+    conn.rtx_queue[tail] = entry;
+    conn.rtx_count += 1;
+    conn.snd_nxt = conn.snd_nxt.wrapping_add(entry.seq_len());
+
+    if conn.rto_deadline == 0 {
+        conn.restart_timer();
+    }
+
+    Ok(())
+}
+
+/// Resend the oldest unacknowledged segment
+fn retransmit_oldest(conn: &mut TcpConnection) {
+    let state = conn.state;
+    let now = sys_get_uptime_ms();
+    let entry = match conn.rtx_oldest() {
+        Some(entry) => {
+            entry.retransmitted = true;
+            entry.sent_at = now;
+            // Everything after the initial SYN carries an ACK
+            if state != TcpState::SynSent {
+                entry.flags |= TCP_FLAG_ACK;
             }
+            *entry
         }
+        None => return,
+    };
 
-        Err(())
+    let _ = send_segment(conn, entry.flags, entry.seq, &entry.data[0..entry.len]);
+}
+
+/// Send a bare ACK for the current receive state
+fn send_ack(conn: &TcpConnection) {
+    let _ = send_segment(conn, TCP_FLAG_ACK, conn.snd_nxt, &[]);
+}
+
+/// Reply to a segment that matches no connection with a RST (RFC 793)
+fn send_reset(src_ip: u32, seg: &Segment) {
+    if seg.has(TCP_FLAG_RST) {
+        return;
+    }
+
+    if seg.has(TCP_FLAG_ACK) {
+        let _ = send_raw(seg.dest_port, src_ip, seg.src_port, seg.ack, 0, TCP_FLAG_RST, 0, &[]);
+    } else {
+        let ack = seg.seq.wrapping_add(seg.seq_len());
+        let _ = send_raw(seg.dest_port, src_ip, seg.src_port, 0, ack, TCP_FLAG_RST | TCP_FLAG_ACK, 0, &[]);
     }
 }
 
 /// Initiate TCP connection (SYN)
 pub fn tcp_connect(conn_id: usize) -> Result<(), ()> {
-    unsafe {
-        if conn_id >= MAX_TCP_CONNECTIONS {
-            return Err(());
-        }
+    let conn = get_connection(conn_id).ok_or(())?;
 
-        if let Some(ref mut conn) = TCP_CONNECTIONS[conn_id] {
-            conn.state = TcpState::SynSent;
-            // Send SYN packet
-            let mut tcp_header = TcpHeader {
-                src_port: conn.local_port,
-                dest_port: conn.remote_port,
-                seq_number: conn.seq_num,
-                ack_number: 0,
-                data_offset: 0x50, // 5 * 4 = 20 bytes header
-                flags: TCP_FLAG_SYN,
-                window_size: conn.window_size as u16,
-                checksum: 0,
-                urgent_ptr: 0,
-            };
-            // Calculate checksum and send via IP layer
-            use crate::ip::ip_send;
-            let header_bytes = unsafe {
-                core::slice::from_raw_parts(&tcp_header as *const _ as *const u8, 20)
-            };
-            let _ = ip_send(conn.remote_ip, crate::ip::IP_PROTOCOL_TCP, header_bytes);
-            Ok(())
-        } else {
-            Err(())
+    if conn.state != TcpState::Closed {
+        return Err(());
+    }
+
+    conn.snd_una = conn.iss;
+    conn.snd_nxt = conn.iss;
+    transmit(conn, TCP_FLAG_SYN, &[])?;
+    conn.state = TcpState::SynSent;
+    conn.connect_started = sys_get_uptime_ms();
+    Ok(())
+}
+
+/// Open a passive (listening) connection on a local port
+/// `backlog` bounds pending connections (clamped to 1..=TCP_MAX_BACKLOG);
+/// SYNs arriving while it is exhausted are dropped.
+pub fn tcp_listen(local_ip: u32, local_port: u16, backlog: usize) -> Result<usize, ()> {
+    let conn_id = tcp_create_connection(local_ip, local_port, 0, 0)?;
+    if let Some(conn) = get_connection(conn_id) {
+        conn.state = TcpState::Listen;
+        conn.backlog = backlog.clamp(1, TCP_MAX_BACKLOG);
+    }
+    Ok(conn_id)
+}
+
+/// Take the oldest established connection off a listener's accept queue
+pub fn tcp_accept(listen_id: usize) -> Option<usize> {
+    let listener = match get_connection(listen_id) {
+        Some(conn) if conn.state == TcpState::Listen => conn,
+        _ => return None,
+    };
+
+    let conn_id = listener.pop_pending()?;
+    let conn = get_connection(conn_id)?;
+    conn.accepted = true;
+    Some(conn_id)
+}
+
+/// Number of pending connections (half-open or waiting for accept) on a listener
+fn pending_connections(listen_id: usize) -> usize {
+    let mut pending = 0;
+    unsafe {
+        for i in 0..MAX_TCP_CONNECTIONS {
+            if let Some(ref conn) = TCP_CONNECTIONS[i] {
+                if conn.parent == Some(listen_id) && !conn.accepted && conn.state == TcpState::SynReceived {
+                    pending += 1;
+                }
+            }
         }
     }
+    pending + get_connection(listen_id).map(|l| l.accept_count).unwrap_or(0)
 }
 
 /// Send data on TCP connection
-pub fn tcp_send(conn_id: usize, data: &[u8]) -> Result<(), ()> {
-    unsafe {
-        if conn_id >= MAX_TCP_CONNECTIONS {
-            return Err(());
+/// Returns the number of bytes accepted into the send buffer (0 when it is full)
+pub fn tcp_send(conn_id: usize, data: &[u8]) -> Result<usize, ()> {
+    let conn = get_connection(conn_id).ok_or(())?;
+
+    if conn.state != TcpState::Established && conn.state != TcpState::CloseWait {
+        return Err(());
+    }
+
+    let space = conn.send_capacity.saturating_sub(conn.send_len);
+    let len = data.len().min(space);
+    conn.send_buffer[conn.send_len..conn.send_len + len].copy_from_slice(&data[0..len]);
+    conn.send_len += len;
+
+    tcp_output(conn);
+
+    Ok(len)
+}
+
+/// Transmit buffered data as the peer window allows, then a pending FIN.
+/// Unless TCP_NODELAY is set, a sub-MSS segment is held back while earlier
+/// data is unacknowledged (Nagle's algorithm, RFC 896).
+fn tcp_output(conn: &mut TcpConnection) {
+    match conn.state {
+        TcpState::Established | TcpState::CloseWait => {}
+        TcpState::FinWait1 | TcpState::Closing | TcpState::LastAck if conn.fin_pending => {}
+        _ => return,
+    }
+
+    let mss = conn.mss.min(TCP_LOCAL_MSS) as usize;
+
+    while conn.send_len > 0 && !conn.rtx_full() {
+        let in_flight = conn.snd_nxt.wrapping_sub(conn.snd_una);
+        let window = conn.snd_wnd.saturating_sub(in_flight) as usize;
+        let chunk = conn.send_len.min(mss).min(window);
+        if chunk == 0 {
+            break;
+        }
+
+        let coalesce = chunk < mss && in_flight > 0 && !conn.nodelay && !conn.fin_pending;
+        if coalesce {
+            break;
+        }
+
+        let mut data = [0u8; TCP_LOCAL_MSS as usize];
+        data[0..chunk].copy_from_slice(&conn.send_buffer[0..chunk]);
+        if transmit(conn, TCP_FLAG_ACK | TCP_FLAG_PSH, &data[0..chunk]).is_err() {
+            break;
         }
+        conn.send_buffer.copy_within(chunk..conn.send_len, 0);
+        conn.send_len -= chunk;
+    }
+
+    if conn.fin_pending && conn.send_len == 0 && !conn.rtx_full() {
+        if transmit(conn, TCP_FLAG_FIN | TCP_FLAG_ACK, &[]).is_ok() {
+            conn.fin_pending = false;
+        }
+    }
+}
+
+/// Enable or disable Nagle coalescing (TCP_NODELAY)
+pub fn tcp_set_nodelay(conn_id: usize, nodelay: bool) -> Result<(), ()> {
+    let conn = get_connection(conn_id).ok_or(())?;
+    conn.nodelay = nodelay;
+
+    // Anything held back by Nagle can go out now
+    tcp_output(conn);
+    Ok(())
+}
+
+/// Turn keepalive probing on with the given timing, or off (SO_KEEPALIVE)
+pub fn tcp_set_keepalive(conn_id: usize, keepalive: Option<Keepalive>) -> Result<(), ()> {
+    let conn = get_connection(conn_id).ok_or(())?;
+    conn.keepalive = keepalive;
+    Ok(())
+}
+
+/// Bound how long the handshake may take; 0 leaves it to the retries
+pub fn tcp_set_connect_timeout(conn_id: usize, timeout_ms: u64) -> Result<(), ()> {
+    let conn = get_connection(conn_id).ok_or(())?;
+    conn.connect_timeout_ms = timeout_ms;
+    Ok(())
+}
+
+/// Resize the usable send/receive buffers (clamped to TCP_MIN/MAX_BUFFER_SIZE).
+/// Buffered data is never discarded; a smaller buffer takes effect as it drains.
+pub fn tcp_set_buffer_sizes(conn_id: usize, recv_size: usize, send_size: usize) -> Result<(), ()> {
+    let conn = get_connection(conn_id).ok_or(())?;
+    conn.recv_capacity = recv_size.clamp(TCP_MIN_BUFFER_SIZE, TCP_MAX_BUFFER_SIZE);
+    conn.send_capacity = send_size.clamp(TCP_MIN_BUFFER_SIZE, TCP_MAX_BUFFER_SIZE);
+    Ok(())
+}
+
+/// How a local port is used by TCP
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpPortUsage {
+    Free,
+    /// Only connections lingering in TIME_WAIT
+    TimeWait,
+    /// A listener or a live connection
+    Active,
+}
 
-        if let Some(ref conn) = TCP_CONNECTIONS[conn_id] {
-            if conn.state != TcpState::Established {
-                return Err(());
+/// Check whether a local port is in use
+pub fn tcp_port_usage(local_port: u16) -> TcpPortUsage {
+    let mut usage = TcpPortUsage::Free;
+    unsafe {
+        for i in 0..MAX_TCP_CONNECTIONS {
+            if let Some(ref conn) = TCP_CONNECTIONS[i] {
+                if conn.local_port != local_port || conn.state == TcpState::Closed {
+                    continue;
+                }
+                if conn.state != TcpState::TimeWait {
+                    return TcpPortUsage::Active;
+                }
+                usage = TcpPortUsage::TimeWait;
             }
+        }
+    }
+    usage
+}
 
-            // Build and send TCP segment
-            let mut tcp_header = TcpHeader {
-                src_port: conn.local_port,
-                dest_port: conn.remote_port,
-                seq_number: conn.seq_num,
-                ack_number: conn.ack_num,
-                data_offset: 0x50,
-                flags: TCP_FLAG_ACK | TCP_FLAG_PSH,
-                window_size: conn.window_size as u16,
-                checksum: 0,
-                urgent_ptr: 0,
+/// Discard TIME_WAIT connections on a local port so it can be reused (SO_REUSEADDR)
+pub fn tcp_reap_time_wait(local_port: u16) {
+    unsafe {
+        for i in 0..MAX_TCP_CONNECTIONS {
+            let reap = match TCP_CONNECTIONS[i] {
+                Some(ref conn) => conn.local_port == local_port && conn.state == TcpState::TimeWait,
+                None => false,
             };
-            // Build packet: header + data
-            let mut packet = [0u8; 1500];
-            unsafe {
-                core::ptr::copy_nonoverlapping(&tcp_header as *const _ as *const u8, packet.as_mut_ptr(), 20);
+            if reap {
+                free_slot(i);
             }
-            let data_len = data.len().min(1480);
-            packet[20..20+data_len].copy_from_slice(&data[0..data_len]);
-            // Send via IP layer
-            use crate::ip::ip_send;
-            let _ = ip_send(conn.remote_ip, crate::ip::IP_PROTOCOL_TCP, &packet[0..20+data_len]);
-            Ok(())
-        } else {
-            Err(())
         }
     }
 }
 
 /// Receive data from TCP connection
+/// Returns 0 when no data is buffered (including after the peer closed)
 pub fn tcp_receive(conn_id: usize, buffer: &mut [u8]) -> Result<usize, ()> {
-    unsafe {
-        if conn_id >= MAX_TCP_CONNECTIONS {
+    let conn = get_connection(conn_id).ok_or(())?;
+
+    if conn.reset {
+        return Err(());
+    }
+
+    match conn.state {
+        TcpState::Closed | TcpState::Listen | TcpState::SynSent | TcpState::SynReceived => {
             return Err(());
         }
+        _ => {}
+    }
 
-        if let Some(ref conn) = TCP_CONNECTIONS[conn_id] {
-            if conn.state != TcpState::Established {
-                return Err(());
-            }
+    let was_full = conn.rcv_wnd() == 0;
+    let copy_len = conn.recv_len.min(buffer.len());
+    buffer[0..copy_len].copy_from_slice(&conn.recv_buffer[0..copy_len]);
+    conn.recv_buffer.copy_within(copy_len..conn.recv_len, 0);
+    conn.recv_len -= copy_len;
 
-            // Retrieve data from receive buffer
-            // For now, return 0 (receive buffer not fully implemented)
-            // Full implementation would:
-            // 1. Check receive buffer for data
-            // 2. Copy data to buffer
-            // 3. Update ack_num
-            let _ = buffer;
-            Ok(0)
-        } else {
-            Err(())
-        }
+    // Window update so a peer stalled on a zero window resumes
+    if was_full && copy_len > 0 {
+        send_ack(conn);
+    }
+
+    Ok(copy_len)
+}
+
+/// Readiness of a connection, for socket polling
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpReadiness {
+    /// tcp_receive will not block: data, end of stream or an error is pending
+    pub readable: bool,
+    /// tcp_send can queue at least one byte
+    pub writable: bool,
+    /// Peer has closed its side or the connection is gone
+    pub hangup: bool,
+    /// Connection was reset
+    pub error: bool,
+}
+
+/// Get the readiness of a connection
+pub fn tcp_readiness(conn_id: usize) -> Option<TcpReadiness> {
+    let conn = get_connection(conn_id)?;
+
+    let fin_received = matches!(
+        conn.state,
+        TcpState::CloseWait | TcpState::LastAck | TcpState::Closing | TcpState::TimeWait
+    );
+    let closed = conn.state == TcpState::Closed;
+
+    let can_send = matches!(conn.state, TcpState::Established | TcpState::CloseWait);
+
+    Some(TcpReadiness {
+        readable: conn.recv_len > 0 || fin_received || closed || conn.reset,
+        writable: can_send && conn.send_len < conn.send_capacity,
+        hangup: closed || fin_received,
+        error: conn.reset,
+    })
+}
+
+/// Does a listening connection have an established connection waiting?
+pub fn tcp_accept_pending(listen_id: usize) -> bool {
+    match get_connection(listen_id) {
+        Some(conn) => conn.state == TcpState::Listen && conn.accept_count > 0,
+        None => false,
     }
 }
 
 /// Close TCP connection
 pub fn tcp_close(conn_id: usize) -> Result<(), ()> {
-    unsafe {
-        if conn_id >= MAX_TCP_CONNECTIONS {
-            return Err(());
-        }
+    let conn = get_connection(conn_id).ok_or(())?;
+    conn.user_closed = true;
 
-        if let Some(ref mut conn) = TCP_CONNECTIONS[conn_id] {
+    match conn.state {
+        TcpState::Established | TcpState::SynReceived => {
+            // FIN follows any data still in the send buffer
+            conn.fin_pending = true;
             conn.state = TcpState::FinWait1;
-            // Send FIN packet
-            let mut tcp_header = TcpHeader {
-                src_port: conn.local_port,
-                dest_port: conn.remote_port,
-                seq_number: conn.seq_num,
-                ack_number: conn.ack_num,
-                data_offset: 0x50,
-                flags: TCP_FLAG_FIN | TCP_FLAG_ACK,
-                window_size: conn.window_size as u16,
-                checksum: 0,
-                urgent_ptr: 0,
-            };
-            use crate::ip::ip_send;
-            let header_bytes = unsafe {
-                core::slice::from_raw_parts(&tcp_header as *const _ as *const u8, 20)
-            };
-            let _ = ip_send(conn.remote_ip, crate::ip::IP_PROTOCOL_TCP, header_bytes);
-            TCP_CONNECTIONS[conn_id] = None;
-            Ok(())
-        } else {
-            Err(())
+            tcp_output(conn);
+        }
+        TcpState::CloseWait => {
+            conn.fin_pending = true;
+            conn.state = TcpState::LastAck;
+            tcp_output(conn);
         }
+        TcpState::Listen => {
+            // Drop children that were never accepted along with the listener
+            unsafe {
+                for i in 0..MAX_TCP_CONNECTIONS {
+                    let orphan = match TCP_CONNECTIONS[i] {
+                        Some(ref child) => child.parent == Some(conn_id) && !child.accepted,
+                        None => false,
+                    };
+                    if orphan {
+                        if let Some(ref child) = TCP_CONNECTIONS[i] {
+                            let _ = send_segment(child, TCP_FLAG_RST, child.snd_nxt, &[]);
+                        }
+                        free_slot(i);
+                    }
+                }
+                TCP_CONNECTIONS[conn_id] = None;
+            }
+        }
+        TcpState::Closed | TcpState::SynSent | TcpState::TimeWait => free_slot(conn_id),
+        // FIN already sent; the slot is freed when the close completes
+        TcpState::FinWait1 | TcpState::FinWait2 | TcpState::Closing | TcpState::LastAck => {}
     }
+
+    Ok(())
 }
 
-/// Send TCP data
-pub fn tcp_send(conn_idx: usize, data: &[u8]) -> Result<(), ()> {
+/// Mark a connection closed, freeing it if nobody will look at it again
+fn tcp_drop(conn_id: usize) {
     unsafe {
-        if conn_idx >= MAX_TCP_CONNECTIONS {
-            return Err(());
+        let free = match TCP_CONNECTIONS[conn_id] {
+            Some(ref mut conn) => {
+                conn.state = TcpState::Closed;
+                conn.rtx_count = 0;
+                conn.rto_deadline = 0;
+                conn.user_closed || (conn.parent.is_some() && !conn.accepted)
+            }
+            None => false,
+        };
+        if free {
+            free_slot(conn_id);
         }
-        
-        if let Some(ref mut conn) = TCP_CONNECTIONS[conn_idx] {
-            // Build TCP packet
-            let mut tcp_header = TcpHeader {
-                src_port: conn.local_port,
-                dest_port: conn.remote_port,
-                seq_number: conn.seq_num,
-                ack_number: conn.ack_num,
-                data_offset: 0x50,
-                flags: TCP_FLAG_ACK | TCP_FLAG_PSH,
-                window_size: conn.window_size as u16,
-                checksum: 0,
-                urgent_ptr: 0,
-            };
-            // Build packet
-            let mut packet = [0u8; 1500];
-            unsafe {
-                core::ptr::copy_nonoverlapping(&tcp_header as *const _ as *const u8, packet.as_mut_ptr(), 20);
+    }
+}
+
+/// Get the current retransmission timeout of a connection
+pub fn tcp_get_rto(conn_id: usize) -> Option<u64> {
+    get_connection(conn_id).map(|conn| conn.rto_ms)
+}
+
+/// Periodic timer processing, driven from the network service loop.
+/// Retransmits segments whose RTO expired (with exponential backoff),
+/// resets connections that exhausted their retries or their connect
+/// timeout, probes idle connections with keepalive on and drops those
+/// whose probes go unanswered, and reaps TIME_WAIT.
+pub fn tcp_timer_tick() {
+    let now = sys_get_uptime_ms();
+
+    for conn_id in 0..MAX_TCP_CONNECTIONS {
+        let conn = match get_connection(conn_id) {
+            Some(conn) => conn,
+            None => continue,
+        };
+
+        if conn.state == TcpState::TimeWait {
+            if now.saturating_sub(conn.time_wait_start) >= TCP_TIME_WAIT_MS {
+                tcp_drop(conn_id);
             }
-            let data_len = data.len().min(1480);
-            packet[20..20+data_len].copy_from_slice(&data[0..data_len]);
-            // Send via IP layer
-            use crate::ip::ip_send;
-            let _ = ip_send(conn.remote_ip, crate::ip::IP_PROTOCOL_TCP, &packet[0..20+data_len]);
-            Ok(())
-        } else {
-            Err(())
+            continue;
+        }
+
+        let handshaking = matches!(conn.state, TcpState::SynSent | TcpState::SynReceived);
+        if handshaking && handshake_expired(conn.connect_started, conn.connect_timeout_ms, now) {
+            let _ = send_segment(conn, TCP_FLAG_RST, conn.snd_nxt, &[]);
+            conn.reset = true;
+            tcp_drop(conn_id);
+            continue;
         }
+
+        // Data in flight is covered by the retransmission timer
+        let idle = matches!(conn.state, TcpState::Established | TcpState::CloseWait | TcpState::FinWait2)
+            && conn.rtx_count == 0;
+        if let Some(keepalive) = conn.keepalive.filter(|_| idle) {
+            match keepalive.action(conn.last_heard, conn.keepalive_probes, now) {
+                KeepaliveAction::Wait => {}
+                KeepaliveAction::Probe => {
+                    // An old sequence number makes the peer answer with an ACK
+                    let _ = send_segment(conn, TCP_FLAG_ACK, conn.snd_nxt.wrapping_sub(1), &[]);
+                    conn.keepalive_probes += 1;
+                }
+                KeepaliveAction::Drop => {
+                    let _ = send_segment(conn, TCP_FLAG_RST, conn.snd_nxt, &[]);
+                    conn.reset = true;
+                    tcp_drop(conn_id);
+                    continue;
+                }
+            }
+        }
+
+        if conn.rto_deadline == 0 || now < conn.rto_deadline {
+            continue;
+        }
+
+        conn.retries += 1;
+        if conn.retries > TCP_MAX_RETRIES {
+            let _ = send_segment(conn, TCP_FLAG_RST, conn.snd_nxt, &[]);
+            conn.reset = true;
+            tcp_drop(conn_id);
+            continue;
+        }
+
+        retransmit_oldest(conn);
+        conn.rto_ms = (conn.rto_ms * 2).min(TCP_MAX_RTO_MS);
+        conn.rto_deadline = now + conn.rto_ms;
     }
 }
 
-/// Receive TCP data
-pub fn tcp_receive(conn_idx: usize, buffer: &mut [u8]) -> Result<usize, ()> {
+/// Find the connection an incoming segment belongs to
+fn find_connection(src_ip: u32, seg: &Segment) -> Option<usize> {
     unsafe {
-        if conn_idx >= MAX_TCP_CONNECTIONS {
-            return Err(());
-        }
-        
-        if let Some(_conn) = &TCP_CONNECTIONS[conn_idx] {
-            // Receive from IP layer
-            use crate::ip::ip_receive;
-            let mut ip_buffer = [0u8; 1500];
-            match ip_receive(&mut ip_buffer) {
-                Ok((len, _src_ip, protocol)) => {
-                    if protocol == crate::ip::IP_PROTOCOL_TCP && len >= 20 {
-                        // Parse TCP header
-                        let tcp_header = unsafe {
-                            &*(ip_buffer.as_ptr() as *const TcpHeader)
-                        };
-                        // Copy data to buffer
-                        let data_len = (len - 20).min(buffer.len());
-                        buffer[0..data_len].copy_from_slice(&ip_buffer[20..20+data_len]);
-                        Ok(data_len)
-                    } else {
-                        Ok(0)
-                    }
+        let mut listener = None;
+
+        for i in 0..MAX_TCP_CONNECTIONS {
+            if let Some(ref conn) = TCP_CONNECTIONS[i] {
+                if conn.local_port != seg.dest_port {
+                    continue;
+                }
+                if conn.state == TcpState::Listen {
+                    listener = Some(i);
+                } else if conn.remote_ip == src_ip && conn.remote_port == seg.src_port {
+                    return Some(i);
                 }
-                Err(_) => Ok(0)
             }
-        } else {
-            Err(())
         }
+
+        listener
     }
 }
 
+/// Number of received segments dropped for a bad checksum
+pub fn tcp_checksum_errors() -> u64 {
+    unsafe { TCP_CHECKSUM_ERRORS }
+}
+
 /// Handle TCP packet
-pub fn tcp_handle_packet(buffer: &[u8], src_ip: u32) -> Result<(), ()> {
-    // Parse TCP header
-    if buffer.len() < 20 {
+pub fn tcp_handle_packet(buffer: &[u8], src_ip: u32, dst_ip: u32) -> Result<(), ()> {
+    if ip::transport_checksum(src_ip, dst_ip, ip::IP_PROTOCOL_TCP, buffer) != 0 {
+        unsafe {
+            TCP_CHECKSUM_ERRORS += 1;
+        }
         return Err(());
     }
-    let tcp_header = unsafe {
-        &*(buffer.as_ptr() as *const TcpHeader)
+
+    let seg = Segment::parse(buffer).ok_or(())?;
+
+    let conn_id = match find_connection(src_ip, &seg) {
+        Some(id) => id,
+        None => {
+            send_reset(src_ip, &seg);
+            return Ok(());
+        }
     };
-    
-    // Find or create connection
-    let mut conn_idx = None;
-    unsafe {
-        for i in 0..MAX_TCP_CONNECTIONS {
-            if let Some(ref conn) = TCP_CONNECTIONS[i] {
-                if conn.local_port == tcp_header.dest_port && 
-                   conn.remote_ip == src_ip &&
-                   conn.remote_port == tcp_header.src_port {
-                    conn_idx = Some(i);
-                    break;
+
+    let state = match get_connection(conn_id) {
+        Some(conn) => conn.state,
+        None => return Err(()),
+    };
+
+    match state {
+        TcpState::Closed => {
+            send_reset(src_ip, &seg);
+            Ok(())
+        }
+        TcpState::Listen => handle_listen(conn_id, src_ip, &seg),
+        TcpState::SynSent => handle_syn_sent(conn_id, &seg),
+        _ => handle_synchronized(conn_id, &seg),
+    }
+}
+
+/// LISTEN: spawn a child connection for an incoming SYN
+fn handle_listen(listen_id: usize, src_ip: u32, seg: &Segment) -> Result<(), ()> {
+    if seg.has(TCP_FLAG_RST) {
+        return Ok(());
+    }
+    if seg.has(TCP_FLAG_ACK) {
+        send_reset(src_ip, seg);
+        return Ok(());
+    }
+    if !seg.has(TCP_FLAG_SYN) {
+        return Ok(());
+    }
+
+    let (local_ip, backlog) = get_connection(listen_id).map(|l| (l.local_ip, l.backlog)).ok_or(())?;
+    if pending_connections(listen_id) >= backlog {
+        // Backlog full: drop the SYN, the peer will retry
+        return Ok(());
+    }
+
+    let child_id = tcp_create_connection(local_ip, seg.dest_port, src_ip, seg.src_port)?;
+    let child = get_connection(child_id).ok_or(())?;
+
+    if let Some(listener) = get_connection(listen_id) {
+        child.nodelay = listener.nodelay;
+        child.recv_capacity = listener.recv_capacity;
+        child.send_capacity = listener.send_capacity;
+        child.keepalive = listener.keepalive;
+        child.connect_timeout_ms = listener.connect_timeout_ms;
+    }
+
+    child.parent = Some(listen_id);
+    child.irs = seg.seq;
+    child.rcv_nxt = seg.seq.wrapping_add(1);
+    child.snd_wnd = seg.window as u32;
+    child.mss = seg.mss.unwrap_or(TCP_DEFAULT_MSS);
+
+    child.snd_una = child.iss;
+    child.snd_nxt = child.iss;
+    child.state = TcpState::SynReceived;
+    transmit(child, TCP_FLAG_SYN | TCP_FLAG_ACK, &[])?;
+
+    Ok(())
+}
+
+/// SYN_SENT: complete (or simultaneously open) an active connection
+fn handle_syn_sent(conn_id: usize, seg: &Segment) -> Result<(), ()> {
+    let conn = get_connection(conn_id).ok_or(())?;
+
+    let ack_ok = seg.has(TCP_FLAG_ACK)
+        && seq_lt(conn.iss, seg.ack)
+        && seq_le(seg.ack, conn.snd_nxt);
+
+    if seg.has(TCP_FLAG_ACK) && !ack_ok {
+        if !seg.has(TCP_FLAG_RST) {
+            let _ = send_segment(conn, TCP_FLAG_RST, seg.ack, &[]);
+        }
+        return Ok(());
+    }
+
+    if seg.has(TCP_FLAG_RST) {
+        if ack_ok {
+            conn.reset = true;
+            tcp_drop(conn_id);
+        }
+        return Ok(());
+    }
+
+    if !seg.has(TCP_FLAG_SYN) {
+        return Ok(());
+    }
+
+    conn.irs = seg.seq;
+    conn.rcv_nxt = seg.seq.wrapping_add(1);
+    conn.snd_wnd = seg.window as u32;
+    conn.mss = seg.mss.unwrap_or(TCP_DEFAULT_MSS);
+
+    if ack_ok {
+        conn.snd_una = seg.ack;
+        conn.process_ack(seg.ack);
+        conn.state = TcpState::Established;
+        send_ack(conn);
+    } else {
+        // Simultaneous open: the queued SYN is resent as SYN|ACK
+        conn.state = TcpState::SynReceived;
+        retransmit_oldest(conn);
+    }
+
+    Ok(())
+}
+
+/// Is the segment (partly) inside our receive window? (RFC 793 acceptability test)
+fn segment_acceptable(conn: &TcpConnection, seg: &Segment) -> bool {
+    let wnd = conn.rcv_wnd();
+    let len = seg.seq_len();
+    let in_window = |seq: u32| seq_le(conn.rcv_nxt, seq) && seq_lt(seq, conn.rcv_nxt.wrapping_add(wnd));
+
+    match (len, wnd) {
+        (0, 0) => seg.seq == conn.rcv_nxt,
+        (0, _) => in_window(seg.seq),
+        (_, 0) => false,
+        (_, _) => in_window(seg.seq) || in_window(seg.seq.wrapping_add(len - 1)),
+    }
+}
+
+/// Segment processing for all synchronized states (SYN_RECEIVED onwards)
+fn handle_synchronized(conn_id: usize, seg: &Segment) -> Result<(), ()> {
+    let conn = get_connection(conn_id).ok_or(())?;
+
+    // 1. Sequence number check
+    if !segment_acceptable(conn, seg) {
+        if !seg.has(TCP_FLAG_RST) {
+            send_ack(conn);
+        }
+        return Ok(());
+    }
+
+    // The peer is alive; keepalive starts over
+    conn.last_heard = sys_get_uptime_ms();
+    conn.keepalive_probes = 0;
+
+    // 2. RST
+    if seg.has(TCP_FLAG_RST) {
+        conn.reset = true;
+        tcp_drop(conn_id);
+        return Ok(());
+    }
+
+    // 3. SYN inside the window is an error
+    if seg.has(TCP_FLAG_SYN) {
+        let _ = send_segment(conn, TCP_FLAG_RST, conn.snd_nxt, &[]);
+        conn.reset = true;
+        tcp_drop(conn_id);
+        return Ok(());
+    }
+
+    // 4. ACK processing
+    if !seg.has(TCP_FLAG_ACK) {
+        return Ok(());
+    }
+
+    if conn.state == TcpState::SynReceived {
+        if seq_lt(conn.snd_una, seg.ack) && seq_le(seg.ack, conn.snd_nxt) {
+            conn.state = TcpState::Established;
+
+            // Passive open complete, queue for accept
+            if let Some(listener) = conn.parent.and_then(get_connection) {
+                if listener.state != TcpState::Listen || listener.push_pending(conn_id).is_err() {
+                    let _ = send_segment(conn, TCP_FLAG_RST, conn.snd_nxt, &[]);
+                    tcp_drop(conn_id);
+                    return Ok(());
                 }
             }
+        } else {
+            let _ = send_segment(conn, TCP_FLAG_RST, seg.ack, &[]);
+            return Ok(());
         }
     }
-    
-    // Update connection state
-    if let Some(idx) = conn_idx {
-        unsafe {
-            if let Some(ref mut conn) = TCP_CONNECTIONS[idx] {
-                // Handle TCP state machine
-                match conn.state {
-                    TcpState::SynSent => {
-                        if (tcp_header.flags & TCP_FLAG_SYN) != 0 && (tcp_header.flags & TCP_FLAG_ACK) != 0 {
-                            conn.state = TcpState::Established;
-                            conn.ack_num = tcp_header.seq_number + 1;
-                        }
-                    }
-                    TcpState::Established => {
-                        if (tcp_header.flags & TCP_FLAG_FIN) != 0 {
-                            conn.state = TcpState::CloseWait;
-                        } else if (tcp_header.flags & TCP_FLAG_ACK) != 0 {
-                            conn.ack_num = tcp_header.seq_number;
-                        }
+
+    if seq_lt(conn.snd_nxt, seg.ack) {
+        // Acknowledges something we never sent
+        send_ack(conn);
+        return Ok(());
+    }
+    if seq_lt(conn.snd_una, seg.ack) {
+        conn.snd_una = seg.ack;
+        conn.dup_acks = 0;
+        conn.process_ack(seg.ack);
+    } else if seg.ack == conn.snd_una
+        && conn.rtx_count > 0
+        && seg.payload.is_empty()
+        && !seg.has(TCP_FLAG_FIN)
+        && seg.window as u32 == conn.snd_wnd
+    {
+        // Duplicate ACK: the peer is missing the oldest segment
+        conn.dup_acks += 1;
+        if conn.dup_acks == TCP_DUPACK_THRESHOLD {
+            retransmit_oldest(conn);
+        }
+    }
+    if seq_le(conn.snd_una, seg.ack) {
+        conn.snd_wnd = seg.window as u32;
+    }
+
+    // Freed window space / acknowledged data may release buffered data
+    tcp_output(conn);
+
+    let fin_acked = conn.snd_una == conn.snd_nxt && !conn.fin_pending;
+    match conn.state {
+        TcpState::FinWait1 if fin_acked => conn.state = TcpState::FinWait2,
+        TcpState::Closing if fin_acked => conn.enter_time_wait(),
+        TcpState::LastAck if fin_acked => {
+            tcp_drop(conn_id);
+            return Ok(());
+        }
+        TcpState::TimeWait => {
+            // Retransmitted FIN: acknowledge and restart the 2MSL timer
+            if seg.has(TCP_FLAG_FIN) {
+                send_ack(conn);
+                conn.enter_time_wait();
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    // 5. Payload (in-order only; out-of-order data is dropped and re-ACKed)
+    let mut need_ack = false;
+    let mut payload_end = seg.seq.wrapping_add(seg.payload.len() as u32);
+    if !seg.payload.is_empty() {
+        match conn.state {
+            TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2 => {
+                if seq_le(seg.seq, conn.rcv_nxt) && seq_lt(conn.rcv_nxt, payload_end) {
+                    let skip = conn.rcv_nxt.wrapping_sub(seg.seq) as usize;
+                    let data = &seg.payload[skip..];
+                    let space = conn.recv_capacity.saturating_sub(conn.recv_len);
+                    let copy_len = data.len().min(space);
+                    conn.recv_buffer[conn.recv_len..conn.recv_len + copy_len]
+                        .copy_from_slice(&data[0..copy_len]);
+                    conn.recv_len += copy_len;
+                    conn.rcv_nxt = conn.rcv_nxt.wrapping_add(copy_len as u32);
+                    if copy_len < data.len() {
+                        // Ran out of buffer; the FIN (if any) was not reached
+                        payload_end = conn.rcv_nxt;
                     }
-                    _ => {}
+                }
+                need_ack = true;
+            }
+            _ => {}
+        }
+    }
+
+    // 6. FIN, only once all preceding data has been received
+    if seg.has(TCP_FLAG_FIN) && payload_end == conn.rcv_nxt {
+        conn.rcv_nxt = conn.rcv_nxt.wrapping_add(1);
+        need_ack = true;
+
+        match conn.state {
+            TcpState::SynReceived | TcpState::Established => conn.state = TcpState::CloseWait,
+            TcpState::FinWait1 => {
+                if conn.snd_una == conn.snd_nxt && !conn.fin_pending {
+                    conn.enter_time_wait();
+                } else {
+                    conn.state = TcpState::Closing;
                 }
             }
+            TcpState::FinWait2 => conn.enter_time_wait(),
+            _ => {}
         }
     }
-    
+
+    if need_ack {
+        send_ack(conn);
+    }
+
     Ok(())
 }
-
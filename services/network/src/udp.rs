@@ -1,5 +1,7 @@
 //! UDP protocol implementation
 
+use crate::ip;
+
 /// UDP header structure
 #[repr(C, packed)]
 pub struct UdpHeader {
@@ -10,57 +12,68 @@ pub struct UdpHeader {
     pub data: [u8; 0],  // Variable length data
 }
 
+/// UDP header length
+pub const UDP_HEADER_LEN: usize = 8;
+
+static mut UDP_CHECKSUM_ERRORS: u64 = 0;
+
+/// Number of received datagrams dropped for a bad checksum
+pub fn udp_checksum_errors() -> u64 {
+    unsafe { UDP_CHECKSUM_ERRORS }
+}
+
 /// Send UDP packet
 pub fn udp_send(dest_ip: u32, dest_port: u16, src_port: u16, data: &[u8]) -> Result<(), ()> {
-    // Build UDP header
-    let mut udp_header = UdpHeader {
-        src_port,
-        dest_port,
-        length: (8 + data.len()) as u16,
-        checksum: 0, // Checksum would be calculated here
-        data: [],
+    let (device, _) = crate::route::route_lookup(dest_ip).ok_or(())?;
+    udp_send_on(device, dest_ip, dest_port, src_port, data)
+}
+
+/// Send UDP packet out of a specific device (DHCP broadcasts)
+pub fn udp_send_on(device: usize, dest_ip: u32, dest_port: u16, src_port: u16, data: &[u8]) -> Result<(), ()> {
+    let mut packet = [0u8; 1480];
+
+    let data_len = data.len().min(packet.len() - UDP_HEADER_LEN);
+    let total_len = UDP_HEADER_LEN + data_len;
+
+    // Build UDP header (network byte order)
+    packet[0..2].copy_from_slice(&src_port.to_be_bytes());
+    packet[2..4].copy_from_slice(&dest_port.to_be_bytes());
+    packet[4..6].copy_from_slice(&(total_len as u16).to_be_bytes());
+    packet[UDP_HEADER_LEN..total_len].copy_from_slice(&data[0..data_len]);
+
+    // Checksum over pseudo-header; a computed 0 is sent as 0xFFFF (0 means "no checksum")
+    let src_ip = ip::ip_source_address_on(device, dest_ip);
+    let checksum = match ip::transport_checksum(src_ip, dest_ip, ip::IP_PROTOCOL_UDP, &packet[0..total_len]) {
+        0 => 0xFFFF,
+        sum => sum,
     };
-    
-    // Build packet
-    let mut packet = [0u8; 1500];
-    unsafe {
-        core::ptr::copy_nonoverlapping(&udp_header as *const _ as *const u8, packet.as_mut_ptr(), 8);
-    }
-    let data_len = data.len().min(1492);
-    packet[8..8+data_len].copy_from_slice(&data[0..data_len]);
-    
-    // Calculate checksum (simplified - would include pseudo-header)
-    // For now, skip checksum calculation
-    
+    packet[6..8].copy_from_slice(&checksum.to_be_bytes());
+
     // Send via IP layer
-    use crate::ip::ip_send;
-    ip_send(dest_ip, crate::ip::IP_PROTOCOL_UDP, &packet[0..8+data_len])
+    ip::ip_send_on(device, dest_ip, ip::IP_PROTOCOL_UDP, &packet[0..total_len])
 }
 
-/// Receive UDP packet
-pub fn udp_receive(buffer: &mut [u8]) -> Result<(usize, u32, u16, u16), ()> {
-    // Receive from IP layer
-    use crate::ip::ip_receive;
-    let mut ip_buffer = [0u8; 1500];
-    match ip_receive(&mut ip_buffer) {
-        Ok((len, src_ip, protocol)) => {
-            if protocol == crate::ip::IP_PROTOCOL_UDP && len >= 8 {
-                // Parse UDP header
-                let udp_header = unsafe {
-                    &*(ip_buffer.as_ptr() as *const UdpHeader)
-                };
-                
-                // Copy data to buffer
-                let data_len = (len - 8).min(buffer.len());
-                buffer[0..data_len].copy_from_slice(&ip_buffer[8..8+data_len]);
-                
-                // Return data length, source IP, source port, dest port
-                Ok((data_len, src_ip, udp_header.src_port, udp_header.dest_port))
-            } else {
-                Err(())
-            }
+/// Validate a UDP datagram and return (src_port, dest_port, payload).
+/// Datagrams with a bad checksum are counted and dropped.
+pub fn udp_parse(segment: &[u8], src_ip: u32, dst_ip: u32) -> Result<(u16, u16, &[u8]), ()> {
+    if segment.len() < UDP_HEADER_LEN {
+        return Err(());
+    }
+
+    let length = u16::from_be_bytes([segment[4], segment[5]]) as usize;
+    if length < UDP_HEADER_LEN || length > segment.len() {
+        return Err(());
+    }
+
+    let checksum = u16::from_be_bytes([segment[6], segment[7]]);
+    if checksum != 0 && ip::transport_checksum(src_ip, dst_ip, ip::IP_PROTOCOL_UDP, &segment[0..length]) != 0 {
+        unsafe {
+            UDP_CHECKSUM_ERRORS += 1;
         }
-        Err(_) => Err(())
+        return Err(());
     }
-}
 
+    let src_port = u16::from_be_bytes([segment[0], segment[1]]);
+    let dest_port = u16::from_be_bytes([segment[2], segment[3]]);
+    Ok((src_port, dest_port, &segment[UDP_HEADER_LEN..length]))
+}
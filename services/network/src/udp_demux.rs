@@ -0,0 +1,63 @@
+//! UDP datagram demultiplexing
+//!
+//! Picks which socket a received datagram belongs to. Several sockets may
+//! share a port (SO_REUSEADDR, or different local addresses); the most
+//! specific match wins: a socket connected to the sender, then one bound
+//! to the destination address, then a wildcard bind. Broadcast and
+//! multicast datagrams go to every match instead.
+
+/// The addresses a UDP socket receives on (host byte order)
+#[derive(Debug, Clone, Copy)]
+pub struct UdpEndpoint {
+    /// Bound local address, 0 for any
+    pub local_ip: u32,
+    pub local_port: u16,
+    /// Peer set by connect(); only its datagrams are accepted
+    pub remote: Option<(u32, u16)>,
+}
+
+impl UdpEndpoint {
+    /// How specifically this endpoint matches a datagram, None if it does not
+    fn score(&self, src_ip: u32, src_port: u16, dst_ip: u32, dst_port: u16) -> Option<u8> {
+        if self.local_port != dst_port || (self.local_ip != 0 && self.local_ip != dst_ip) {
+            return None;
+        }
+        let mut score = if self.local_ip != 0 { 1 } else { 0 };
+        if let Some(remote) = self.remote {
+            if remote != (src_ip, src_port) {
+                return None;
+            }
+            score += 2;
+        }
+        Some(score)
+    }
+}
+
+/// Index of the endpoint that should receive a datagram from
+/// `src_ip:src_port` to `dst_ip:dst_port`. Ties go to the first endpoint.
+pub fn udp_demux<I>(endpoints: I, src_ip: u32, src_port: u16, dst_ip: u32, dst_port: u16) -> Option<usize>
+where
+    I: IntoIterator<Item = (usize, UdpEndpoint)>,
+{
+    let mut best: Option<(usize, u8)> = None;
+    for (index, endpoint) in endpoints {
+        if let Some(score) = endpoint.score(src_ip, src_port, dst_ip, dst_port) {
+            if best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((index, score));
+            }
+        }
+    }
+    best.map(|(index, _)| index)
+}
+
+/// Indexes of every endpoint a broadcast or multicast datagram matches.
+/// Each gets its own copy, where a unicast datagram goes to one.
+pub fn udp_demux_all<I>(endpoints: I, src_ip: u32, src_port: u16, dst_ip: u32, dst_port: u16) -> impl Iterator<Item = usize>
+where
+    I: IntoIterator<Item = (usize, UdpEndpoint)>,
+{
+    endpoints
+        .into_iter()
+        .filter(move |(_, endpoint)| endpoint.score(src_ip, src_port, dst_ip, dst_port).is_some())
+        .map(|(index, _)| index)
+}
@@ -1,4 +1,12 @@
 //! Access Control List (ACL) System
+//!
+//! Each resource has its own list. The table of lists is saved as a small
+//! versioned binary file so rules survive restarts:
+//!
+//! header: magic "SACL", version u16, resource count u16, body length u32,
+//!         CRC32 of the body u32
+//! body:   per resource: resource id u64, entry count u8, then entries of
+//!         [type u8, id u32, permissions u32]
 
 /// ACL permissions
 pub const ACL_READ: u32 = 0x01;
@@ -43,6 +51,7 @@ impl AclEntry {
 }
 
 /// Access Control List
+#[derive(Clone, Copy)]
 pub struct Acl {
     entries: [Option<AclEntry>; 32],
     count: usize,
@@ -86,6 +95,14 @@ impl Acl {
         Err(())
     }
 
+    pub fn entries(&self) -> impl Iterator<Item = &AclEntry> {
+        self.entries.iter().flatten()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
     pub fn check_access(&self, uid: u32, gid: u32, requested_perms: u32) -> bool {
         // Check user-specific ACL
         for entry in &self.entries {
@@ -117,3 +134,182 @@ impl Acl {
         false
     }
 }
+
+pub const MAX_ACL_RESOURCES: usize = 64;
+const MAX_ACL_ENTRIES: usize = 32;
+
+pub const ACL_DB_MAGIC: &[u8; 4] = b"SACL";
+pub const ACL_DB_VERSION: u16 = 1;
+const ACL_DB_HEADER_SIZE: usize = 16;
+const ACL_DB_RESOURCE_SIZE: usize = 9;
+const ACL_DB_ENTRY_SIZE: usize = 9;
+/// Largest file a full table serializes to
+pub const ACL_DB_MAX_SIZE: usize =
+    ACL_DB_HEADER_SIZE + MAX_ACL_RESOURCES * (ACL_DB_RESOURCE_SIZE + MAX_ACL_ENTRIES * ACL_DB_ENTRY_SIZE);
+
+const CRC32_INIT: u32 = 0xFFFF_FFFF;
+
+/// CRC32 (IEEE 802.3, reflected)
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = CRC32_INIT;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    crc ^ CRC32_INIT
+}
+
+pub fn entry_type_from_u8(val: u8) -> Option<AclEntryType> {
+    match val {
+        1 => Some(AclEntryType::User),
+        2 => Some(AclEntryType::Group),
+        3 => Some(AclEntryType::Other),
+        4 => Some(AclEntryType::Mask),
+        _ => None,
+    }
+}
+
+/// ACLs of all protected resources. Resources without a list deny
+/// everything.
+#[derive(Clone, Copy)]
+pub struct AclTable {
+    resources: [Option<(u64, Acl)>; MAX_ACL_RESOURCES],
+}
+
+impl AclTable {
+    pub fn new() -> Self {
+        Self {
+            resources: [None; MAX_ACL_RESOURCES],
+        }
+    }
+
+    pub fn get(&self, resource_id: u64) -> Option<&Acl> {
+        self.resources
+            .iter()
+            .flatten()
+            .find(|(id, _)| *id == resource_id)
+            .map(|(_, acl)| acl)
+    }
+
+    /// Add or replace the entry of a type and id on a resource
+    pub fn set_entry(&mut self, resource_id: u64, entry: AclEntry) -> Result<(), ()> {
+        let slot = match self.resources.iter().position(|r| matches!(r, Some((id, _)) if *id == resource_id)) {
+            Some(slot) => slot,
+            None => {
+                let slot = self.resources.iter().position(|r| r.is_none()).ok_or(())?;
+                self.resources[slot] = Some((resource_id, Acl::new()));
+                slot
+            }
+        };
+
+        if let Some((_, ref mut acl)) = self.resources[slot] {
+            let _ = acl.remove_entry(entry.entry_type, entry.id);
+            acl.add_entry(entry)?;
+        }
+        Ok(())
+    }
+
+    /// Remove an entry; the resource's list goes away with its last entry
+    pub fn remove_entry(&mut self, resource_id: u64, entry_type: AclEntryType, id: u32) -> Result<(), ()> {
+        for slot in self.resources.iter_mut() {
+            if let Some((rid, ref mut acl)) = slot {
+                if *rid == resource_id {
+                    acl.remove_entry(entry_type, id)?;
+                    if acl.is_empty() {
+                        *slot = None;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+        Err(())
+    }
+
+    pub fn check_access(&self, resource_id: u64, uid: u32, gid: u32, requested_perms: u32) -> bool {
+        self.get(resource_id)
+            .map_or(false, |acl| acl.check_access(uid, gid, requested_perms))
+    }
+
+    /// Write the table in the file format; returns the length used
+    pub fn serialize(&self, out: &mut [u8]) -> Option<usize> {
+        let mut pos = ACL_DB_HEADER_SIZE;
+        let mut count = 0u16;
+
+        for (resource_id, acl) in self.resources.iter().flatten() {
+            let entries = acl.entries().count();
+            let size = ACL_DB_RESOURCE_SIZE + entries * ACL_DB_ENTRY_SIZE;
+            let record = out.get_mut(pos..pos + size)?;
+
+            record[0..8].copy_from_slice(&resource_id.to_le_bytes());
+            record[8] = entries as u8;
+            for (i, entry) in acl.entries().enumerate() {
+                let e = &mut record[ACL_DB_RESOURCE_SIZE + i * ACL_DB_ENTRY_SIZE..][..ACL_DB_ENTRY_SIZE];
+                e[0] = entry.entry_type as u8;
+                e[1..5].copy_from_slice(&entry.id.to_le_bytes());
+                e[5..9].copy_from_slice(&entry.permissions.to_le_bytes());
+            }
+
+            pos += size;
+            count += 1;
+        }
+
+        let body_len = pos - ACL_DB_HEADER_SIZE;
+        let crc = crc32(&out[ACL_DB_HEADER_SIZE..pos]);
+        let header = out.get_mut(..ACL_DB_HEADER_SIZE)?;
+        header[0..4].copy_from_slice(ACL_DB_MAGIC);
+        header[4..6].copy_from_slice(&ACL_DB_VERSION.to_le_bytes());
+        header[6..8].copy_from_slice(&count.to_le_bytes());
+        header[8..12].copy_from_slice(&(body_len as u32).to_le_bytes());
+        header[12..16].copy_from_slice(&crc.to_le_bytes());
+        Some(pos)
+    }
+
+    /// Parse a saved table. None if the file is truncated, corrupt, or from
+    /// another version, so the caller can fall back to denying everything.
+    pub fn deserialize(data: &[u8]) -> Option<Self> {
+        let header = data.get(..ACL_DB_HEADER_SIZE)?;
+        if &header[0..4] != ACL_DB_MAGIC || u16::from_le_bytes([header[4], header[5]]) != ACL_DB_VERSION {
+            return None;
+        }
+        let count = u16::from_le_bytes([header[6], header[7]]) as usize;
+        let body_len = u32::from_le_bytes([header[8], header[9], header[10], header[11]]) as usize;
+        let crc = u32::from_le_bytes([header[12], header[13], header[14], header[15]]);
+
+        let body = data.get(ACL_DB_HEADER_SIZE..ACL_DB_HEADER_SIZE.checked_add(body_len)?)?;
+        if crc32(body) != crc || count > MAX_ACL_RESOURCES {
+            return None;
+        }
+
+        let mut table = AclTable::new();
+        let mut pos = 0;
+        for slot in table.resources.iter_mut().take(count) {
+            let record = body.get(pos..pos + ACL_DB_RESOURCE_SIZE)?;
+            let mut id = [0u8; 8];
+            id.copy_from_slice(&record[0..8]);
+            let entries = record[8] as usize;
+            if entries > MAX_ACL_ENTRIES {
+                return None;
+            }
+            pos += ACL_DB_RESOURCE_SIZE;
+
+            let mut acl = Acl::new();
+            for _ in 0..entries {
+                let e = body.get(pos..pos + ACL_DB_ENTRY_SIZE)?;
+                let entry_type = entry_type_from_u8(e[0])?;
+                let entry_id = u32::from_le_bytes([e[1], e[2], e[3], e[4]]);
+                let permissions = u32::from_le_bytes([e[5], e[6], e[7], e[8]]);
+                acl.add_entry(AclEntry::new(entry_type, entry_id, permissions)).ok()?;
+                pos += ACL_DB_ENTRY_SIZE;
+            }
+            *slot = Some((u64::from_le_bytes(id), acl));
+        }
+
+        if pos != body.len() {
+            return None;
+        }
+        Some(table)
+    }
+}
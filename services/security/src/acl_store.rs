@@ -0,0 +1,158 @@
+//! ACL persistence through the VFS service
+//!
+//! The whole table is rewritten on every change; it is small and changes
+//! rarely. Saving creates the file if needed and truncates it, so a
+//! shorter table leaves nothing of a longer one behind.
+
+use crate::acl::{AclTable, ACL_DB_MAX_SIZE};
+use crate::ipc::{
+    ipc_create_port, ipc_destroy_port, ipc_receive_timeout, ipc_send, lookup_service, IpcMessage,
+    IPC_MSG_REQUEST, IPC_MSG_RESPONSE,
+};
+
+pub const ACL_DB_PATH: &[u8] = b"/etc/acl.db";
+
+const VFS_OP_OPEN: u64 = 1;
+const VFS_OP_READ: u64 = 2;
+const VFS_OP_WRITE: u64 = 3;
+const VFS_OP_CLOSE: u64 = 4;
+
+const VFS_REPLY_TIMEOUT_MS: u64 = 1000;
+
+/// Open flags, after the path and its NUL
+const O_RDONLY: u32 = 0x0000;
+const O_WRONLY: u32 = 0x0001;
+const O_CREAT: u32 = 0x0040;
+const O_TRUNC: u32 = 0x0200;
+
+/// Open reply status: no such file
+const VFS_STATUS_NOT_FOUND: u8 = 0xFC;
+
+/// Bytes moved per read or write request
+const VFS_CHUNK_SIZE: usize = 4096;
+
+static mut ACL_DB_BUFFER: [u8; ACL_DB_MAX_SIZE] = [0; ACL_DB_MAX_SIZE];
+
+/// VFS port from the service registry; 0 until looked up, and cleared when
+/// a request fails so a restarted VFS is found again
+static mut VFS_PORT: u64 = 0;
+
+fn vfs_port() -> Result<u64, ()> {
+    unsafe {
+        if VFS_PORT == 0 {
+            VFS_PORT = lookup_service(b"vfs")?;
+        }
+        Ok(VFS_PORT)
+    }
+}
+
+/// One VFS request and its reply; `buffer` carries bulk data either way
+fn vfs_request(op: u64, data: &[u8], buffer: Option<&mut [u8]>) -> Result<IpcMessage, ()> {
+    let vfs_port = vfs_port()?;
+    let reply_port = ipc_create_port()?;
+
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_REQUEST;
+    msg.msg_id = op;
+    msg.set_inline_data(data);
+    msg.reply_port = reply_port;
+    if let Some(buffer) = buffer {
+        msg.buffer = buffer.as_mut_ptr();
+        msg.buffer_size = buffer.len();
+    }
+
+    let mut reply = IpcMessage::new();
+    let result = ipc_send(vfs_port, &msg)
+        .and_then(|_| ipc_receive_timeout(reply_port, &mut reply, VFS_REPLY_TIMEOUT_MS));
+    ipc_destroy_port(reply_port);
+    if result.is_err() {
+        unsafe { VFS_PORT = 0; }
+        return Err(());
+    }
+
+    if reply.msg_type != IPC_MSG_RESPONSE || reply.msg_id != op {
+        return Err(());
+    }
+    Ok(reply)
+}
+
+/// Replies holding a u32 result; errors are a single status byte
+fn reply_u32(reply: &IpcMessage) -> Result<u32, ()> {
+    if reply.inline_size < 4 {
+        return Err(());
+    }
+    Ok(u32::from_le_bytes([reply.inline_data[0], reply.inline_data[1], reply.inline_data[2], reply.inline_data[3]]))
+}
+
+/// Open the database; `Ok(None)` if it does not exist
+fn open_db(flags: u32) -> Result<Option<[u8; 4]>, ()> {
+    let mut request = [0u8; ACL_DB_PATH.len() + 5];
+    request[..ACL_DB_PATH.len()].copy_from_slice(ACL_DB_PATH);
+    request[ACL_DB_PATH.len() + 1..].copy_from_slice(&flags.to_le_bytes());
+
+    let reply = vfs_request(VFS_OP_OPEN, &request, None)?;
+    if reply.inline_size == 1 && reply.inline_data[0] == VFS_STATUS_NOT_FOUND {
+        return Ok(None);
+    }
+    Ok(Some(reply_u32(&reply)?.to_le_bytes()))
+}
+
+fn close_db(fd: [u8; 4]) {
+    let _ = vfs_request(VFS_OP_CLOSE, &fd, None);
+}
+
+/// Load the saved table; `Ok(None)` only if there is none. A file that
+/// cannot be read or does not check out is an error.
+pub fn load() -> Result<Option<AclTable>, ()> {
+    let fd = match open_db(O_RDONLY)? {
+        Some(fd) => fd,
+        None => return Ok(None),
+    };
+    let buffer = unsafe { &mut *core::ptr::addr_of_mut!(ACL_DB_BUFFER) };
+
+    let mut len = 0;
+    while len < buffer.len() {
+        let want = (buffer.len() - len).min(VFS_CHUNK_SIZE);
+        let mut request = [0u8; 8];
+        request[0..4].copy_from_slice(&fd);
+        request[4..8].copy_from_slice(&(want as u32).to_le_bytes());
+
+        let read = match vfs_request(VFS_OP_READ, &request, Some(&mut buffer[len..len + want])).and_then(|r| reply_u32(&r)) {
+            Ok(read) => (read as usize).min(want),
+            Err(_) => {
+                close_db(fd);
+                return Err(());
+            }
+        };
+        if read == 0 {
+            break;
+        }
+        len += read;
+    }
+    close_db(fd);
+
+    AclTable::deserialize(&buffer[..len]).map(Some).ok_or(())
+}
+
+/// Write the table out
+pub fn save(table: &AclTable) -> Result<(), ()> {
+    let buffer = unsafe { &mut *core::ptr::addr_of_mut!(ACL_DB_BUFFER) };
+    let len = table.serialize(buffer).ok_or(())?;
+    let fd = open_db(O_WRONLY | O_CREAT | O_TRUNC)?.ok_or(())?;
+
+    let mut written = 0;
+    while written < len {
+        let chunk = (len - written).min(VFS_CHUNK_SIZE);
+        let result = vfs_request(VFS_OP_WRITE, &fd, Some(&mut buffer[written..written + chunk])).and_then(|r| reply_u32(&r));
+        match result {
+            Ok(n) if n > 0 => written += (n as usize).min(chunk),
+            _ => {
+                close_db(fd);
+                return Err(());
+            }
+        }
+    }
+
+    close_db(fd);
+    Ok(())
+}
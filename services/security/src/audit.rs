@@ -0,0 +1,127 @@
+//! Audit log of access decisions
+//!
+//! A fixed ring of the most recent capability and sandbox checks. When it
+//! is full the oldest record is overwritten and counted as dropped, so a
+//! reader can tell it missed some.
+//!
+//! Wire format of a record (little endian):
+//!   [timestamp ms:8][pid:4][kind:1][resource type:1][allowed:1][0:1]
+//!   [resource:8][resource name:32]
+
+/// Records kept before the oldest are dropped
+pub const AUDIT_CAPACITY: usize = 256;
+/// Bytes of a sandbox resource name kept, NUL padded
+pub const AUDIT_NAME_LEN: usize = 32;
+pub const AUDIT_RECORD_SIZE: usize = 24 + AUDIT_NAME_LEN;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AuditKind {
+    /// Capability check; resource_type is the CapabilityType
+    Capability = 0,
+    /// Sandbox check; resource_type is the SEC_OP_CHECK_ACCESS type byte
+    Access = 1,
+}
+
+#[derive(Clone, Copy)]
+pub struct AuditRecord {
+    pub timestamp_ms: u64,
+    pub pid: u32,
+    pub kind: AuditKind,
+    pub resource_type: u8,
+    /// Capability resource id; 0 for sandbox checks
+    pub resource: u64,
+    /// Start of the sandbox resource name, e.g. the path
+    pub name: [u8; AUDIT_NAME_LEN],
+    pub allowed: bool,
+}
+
+impl AuditRecord {
+    const EMPTY: AuditRecord = AuditRecord {
+        timestamp_ms: 0,
+        pid: 0,
+        kind: AuditKind::Capability,
+        resource_type: 0,
+        resource: 0,
+        name: [0; AUDIT_NAME_LEN],
+        allowed: false,
+    };
+
+    pub fn capability(timestamp_ms: u64, pid: u32, cap_type: u8, resource: u64, allowed: bool) -> Self {
+        AuditRecord { timestamp_ms, pid, kind: AuditKind::Capability, resource_type: cap_type, resource, allowed, ..Self::EMPTY }
+    }
+
+    pub fn access(timestamp_ms: u64, pid: u32, resource_type: u8, resource_id: &[u8], allowed: bool) -> Self {
+        let mut record = AuditRecord { timestamp_ms, pid, kind: AuditKind::Access, resource_type, allowed, ..Self::EMPTY };
+        let len = resource_id.len().min(AUDIT_NAME_LEN);
+        record.name[..len].copy_from_slice(&resource_id[..len]);
+        record
+    }
+
+    pub fn encode(&self, out: &mut [u8; AUDIT_RECORD_SIZE]) {
+        out[0..8].copy_from_slice(&self.timestamp_ms.to_le_bytes());
+        out[8..12].copy_from_slice(&self.pid.to_le_bytes());
+        out[12] = self.kind as u8;
+        out[13] = self.resource_type;
+        out[14] = self.allowed as u8;
+        out[15] = 0;
+        out[16..24].copy_from_slice(&self.resource.to_le_bytes());
+        out[24..].copy_from_slice(&self.name);
+    }
+}
+
+pub struct AuditLog {
+    records: [AuditRecord; AUDIT_CAPACITY],
+    /// Index of the oldest record
+    head: usize,
+    len: usize,
+    /// Records overwritten since the last take_dropped
+    dropped: u32,
+    /// Skip allowed decisions
+    pub denials_only: bool,
+}
+
+impl AuditLog {
+    pub const fn new() -> Self {
+        AuditLog {
+            records: [AuditRecord::EMPTY; AUDIT_CAPACITY],
+            head: 0,
+            len: 0,
+            dropped: 0,
+            denials_only: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn record(&mut self, record: AuditRecord) {
+        if self.denials_only && record.allowed {
+            return;
+        }
+        if self.len == AUDIT_CAPACITY {
+            self.head = (self.head + 1) % AUDIT_CAPACITY;
+            self.len -= 1;
+            self.dropped = self.dropped.saturating_add(1);
+        }
+        self.records[(self.head + self.len) % AUDIT_CAPACITY] = record;
+        self.len += 1;
+    }
+
+    /// Remove and return the oldest record
+    pub fn pop(&mut self) -> Option<AuditRecord> {
+        if self.len == 0 {
+            return None;
+        }
+        let record = self.records[self.head];
+        self.head = (self.head + 1) % AUDIT_CAPACITY;
+        self.len -= 1;
+        Some(record)
+    }
+
+    /// Dropped count since the last call, resetting it
+    pub fn take_dropped(&mut self) -> u32 {
+        core::mem::replace(&mut self.dropped, 0)
+    }
+}
@@ -52,6 +52,35 @@ pub enum CapabilityType {
     HardwareMMIO = 70,
     HardwareIRQ = 71,
     HardwareDMA = 72,
+
+    // Security service capabilities
+    SecurityAdmin = 80,
+    SecurityAudit = 81,
+}
+
+impl CapabilityType {
+    /// Capabilities over the security service itself; only init may grant
+    /// them, though holders may delegate them
+    pub fn init_only(&self) -> bool {
+        matches!(self, CapabilityType::SecurityAdmin | CapabilityType::SecurityAudit)
+    }
+
+    /// Hardware access is tied to the process it was granted to and never
+    /// passes to children, whatever its flags say
+    pub fn may_inherit(&self) -> bool {
+        !matches!(
+            self,
+            CapabilityType::HardwareMMIO | CapabilityType::HardwareIRQ | CapabilityType::HardwareDMA
+        )
+    }
+}
+
+/// Capability flags: copied to children on fork
+pub const CAP_FLAG_INHERITABLE: u8 = 1 << 0;
+
+fn next_cap_id() -> u64 {
+    static NEXT_CAP_ID: AtomicU64 = AtomicU64::new(1);
+    NEXT_CAP_ID.fetch_add(1, Ordering::SeqCst)
 }
 
 /// Capability structure
@@ -76,12 +105,16 @@ pub struct Capability {
     /// Creation timestamp
     pub timestamp: u64,
 
-    /// Expiration time (0 = never)
-    pub expiration: u64,
+    /// Uptime in ms at which the capability lapses (0 = never)
+    pub expires_at_ms: u64,
 
     /// Delegation depth (how many times can be delegated)
     pub delegation_depth: u8,
 
+    /// Capability this one was delegated from (0 = granted directly);
+    /// revoking that one revokes this one too
+    pub parent_id: u64,
+
     /// Flags
     pub flags: u8,
 
@@ -91,17 +124,16 @@ pub struct Capability {
 
 impl Capability {
     pub fn new(cap_type: CapabilityType, resource_id: u64, owner_pid: u32) -> Self {
-        static NEXT_CAP_ID: AtomicU64 = AtomicU64::new(1);
-
         Self {
-            id: NEXT_CAP_ID.fetch_add(1, Ordering::SeqCst),
+            id: next_cap_id(),
             cap_type,
             resource_id,
             permissions: 0xFFFFFFFFFFFFFFFF, // All permissions by default
             owner_pid,
             timestamp: sys_get_uptime_ms(),
-            expiration: 0, // Never expires
+            expires_at_ms: 0, // Never expires
             delegation_depth: 3, // Can be delegated 3 times
+            parent_id: 0,
             flags: 0,
             _reserved: [0; 6],
         }
@@ -119,24 +151,47 @@ impl Capability {
         new_cap
     }
 
-    /// Delegate capability to another process
-    pub fn delegate(&self, target_pid: u32) -> Result<Capability, ()> {
+    /// Delegate capability to another process with the given permissions.
+    /// Asking for any permission this capability lacks is refused rather
+    /// than silently dropped.
+    pub fn delegate(&self, target_pid: u32, permissions: u64) -> Result<Capability, ()> {
         if self.delegation_depth == 0 {
             return Err(()); // Cannot delegate further
         }
+        if permissions & !self.permissions != 0 {
+            return Err(()); // Would widen rights
+        }
 
         let mut new_cap = *self;
+        new_cap.id = next_cap_id();
         new_cap.owner_pid = target_pid;
+        new_cap.permissions = permissions;
         new_cap.delegation_depth -= 1;
+        new_cap.parent_id = self.id;
+        // The delegator decides who holds it; the target's children do not
+        new_cap.flags &= !CAP_FLAG_INHERITABLE;
         Ok(new_cap)
     }
 
+    /// Whether a forked child gets a copy
+    pub fn is_inheritable(&self) -> bool {
+        self.flags & CAP_FLAG_INHERITABLE != 0 && self.cap_type.may_inherit()
+    }
+
+    /// Child's copy of an inheritable capability, with its own id
+    pub fn inherit(&self, child_pid: u32) -> Capability {
+        let mut new_cap = *self;
+        new_cap.id = next_cap_id();
+        new_cap.owner_pid = child_pid;
+        new_cap
+    }
+
     /// Check if capability has expired
     pub fn is_expired(&self, current_time: u64) -> bool {
-        if self.expiration == 0 {
+        if self.expires_at_ms == 0 {
             false // Never expires
         } else {
-            current_time >= self.expiration
+            current_time >= self.expires_at_ms
         }
     }
 }
@@ -146,17 +201,26 @@ impl Capability {
 pub struct CapabilityEntry {
     pub capability: Capability,
     pub valid: bool,
+    /// Freed because the capability lapsed; kept until the slot is reused
+    /// so lookups can tell expiry apart from never having had it
+    pub expired: bool,
 }
 
-const MAX_CAPABILITIES: usize = 4096;
+/// Result of looking a capability up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapabilityStatus {
+    Granted,
+    Expired,
+    Absent,
+}
 
-/// Per-process capability table
-pub struct CapabilityTable {
+/// Per-process capability table, with room for `MAX_CAPABILITIES` entries
+pub struct CapabilityTable<const MAX_CAPABILITIES: usize = 4096> {
     entries: [CapabilityEntry; MAX_CAPABILITIES],
     count: usize,
 }
 
-impl CapabilityTable {
+impl<const MAX_CAPABILITIES: usize> CapabilityTable<MAX_CAPABILITIES> {
     pub fn new() -> Self {
         Self {
             entries: [CapabilityEntry {
@@ -167,12 +231,14 @@ impl CapabilityTable {
                     permissions: 0,
                     owner_pid: 0,
                     timestamp: 0,
-                    expiration: 0,
+                    expires_at_ms: 0,
                     delegation_depth: 0,
+                    parent_id: 0,
                     flags: 0,
                     _reserved: [0; 6],
                 },
                 valid: false,
+                expired: false,
             }; MAX_CAPABILITIES],
             count: 0,
         }
@@ -185,6 +251,7 @@ impl CapabilityTable {
             if !self.entries[i].valid {
                 self.entries[i].capability = cap;
                 self.entries[i].valid = true;
+                self.entries[i].expired = false;
                 self.count += 1;
                 return Ok(i);
             }
@@ -230,6 +297,50 @@ impl CapabilityTable {
         }
     }
 
+    /// Copy the parent's inheritable capabilities into this table,
+    /// returning how many were added
+    pub fn inherit_from(&mut self, parent: &Self, child_pid: u32) -> usize {
+        let mut added = 0;
+        for entry in parent.entries.iter().filter(|e| e.valid) {
+            if entry.capability.is_inheritable() && self.add(entry.capability.inherit(child_pid)).is_ok() {
+                added += 1;
+            }
+        }
+        added
+    }
+
+    /// Look a capability up at uptime `now`, pruning it if it has lapsed
+    pub fn lookup(&mut self, cap_type: CapabilityType, resource_id: u64, now: u64) -> CapabilityStatus {
+        let mut status = CapabilityStatus::Absent;
+        for entry in self.entries.iter_mut() {
+            let cap = &entry.capability;
+            if cap.cap_type != cap_type || cap.resource_id != resource_id {
+                continue;
+            }
+
+            if entry.valid {
+                if !cap.is_expired(now) {
+                    return CapabilityStatus::Granted;
+                }
+                entry.valid = false;
+                entry.expired = true;
+                self.count -= 1;
+            }
+            // Another entry may still grant it; keep looking
+            if entry.expired {
+                status = CapabilityStatus::Expired;
+            }
+        }
+        status
+    }
+
+    /// First capability delegated from the given one
+    pub fn find_derived(&self, parent_id: u64) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|e| e.valid && e.capability.parent_id == parent_id)
+    }
+
     /// Find capability for resource
     pub fn find(&self, cap_type: CapabilityType, resource_id: u64) -> Option<usize> {
         for i in 0..MAX_CAPABILITIES {
@@ -245,22 +356,23 @@ impl CapabilityTable {
     }
 }
 
-/// Global capability manager
-pub struct CapabilityManager {
+/// Global capability manager, with a table for each pid below
+/// `MAX_PROCESSES`
+pub struct CapabilityManager<const MAX_PROCESSES: usize = 256, const MAX_CAPABILITIES: usize = 4096> {
     // Per-process capability tables
-    process_tables: [Option<CapabilityTable>; 256],
+    process_tables: [Option<CapabilityTable<MAX_CAPABILITIES>>; MAX_PROCESSES],
 }
 
-impl CapabilityManager {
+impl<const MAX_PROCESSES: usize, const MAX_CAPABILITIES: usize> CapabilityManager<MAX_PROCESSES, MAX_CAPABILITIES> {
     pub fn new() -> Self {
         Self {
-            process_tables: [None; 256],
+            process_tables: core::array::from_fn(|_| None),
         }
     }
 
     /// Initialize capability table for process
     pub fn init_process(&mut self, pid: u32) -> Result<(), ()> {
-        if pid as usize >= 256 {
+        if pid as usize >= MAX_PROCESSES {
             return Err(());
         }
 
@@ -270,7 +382,7 @@ impl CapabilityManager {
 
     /// Grant capability to process
     pub fn grant(&mut self, pid: u32, cap: Capability) -> Result<usize, ()> {
-        if pid as usize >= 256 {
+        if pid as usize >= MAX_PROCESSES {
             return Err(());
         }
 
@@ -281,29 +393,121 @@ impl CapabilityManager {
         }
     }
 
-    /// Revoke capability from process
+    /// Revoke capability from process, along with everything delegated
+    /// from it
     pub fn revoke(&mut self, pid: u32, cap_idx: usize) -> Result<(), ()> {
-        if pid as usize >= 256 {
+        if pid as usize >= MAX_PROCESSES {
             return Err(());
         }
 
-        if let Some(ref mut table) = self.process_tables[pid as usize] {
-            table.remove(cap_idx)
+        let id = if let Some(ref mut table) = self.process_tables[pid as usize] {
+            let id = table.get(cap_idx).ok_or(())?.id;
+            table.remove(cap_idx)?;
+            id
         } else {
-            Err(())
+            return Err(());
+        };
+
+        self.revoke_derived(id);
+        Ok(())
+    }
+
+    /// Revoke every capability delegated from `parent_id`, in any process.
+    /// Recursion is bounded by the delegation depth.
+    fn revoke_derived(&mut self, parent_id: u64) {
+        // 0 marks directly granted capabilities, not a real parent
+        if parent_id == 0 {
+            return;
+        }
+
+        for pid in 0..MAX_PROCESSES {
+            loop {
+                let derived = match self.process_tables[pid] {
+                    Some(ref mut table) => match table.find_derived(parent_id) {
+                        Some(idx) => {
+                            let id = table.get(idx).map_or(0, |cap| cap.id);
+                            let _ = table.remove(idx);
+                            id
+                        }
+                        None => break,
+                    },
+                    None => break,
+                };
+                self.revoke_derived(derived);
+            }
+        }
+    }
+
+    /// Hand a copy of one of `from_pid`'s capabilities to `to_pid`, limited
+    /// to `permissions` (0 keeps the same permissions). Returns the index in
+    /// the target's table. Only the holder may hand its capabilities out, so
+    /// a request from any `sender` other than `from_pid` is refused.
+    pub fn delegate(&mut self, sender: u32, from_pid: u32, to_pid: u32, cap_idx: usize, permissions: u64) -> Result<usize, ()> {
+        if sender != from_pid {
+            return Err(());
+        }
+        if from_pid as usize >= MAX_PROCESSES || to_pid as usize >= MAX_PROCESSES {
+            return Err(());
+        }
+
+        let cap = match self.process_tables[from_pid as usize] {
+            Some(ref table) => *table.get(cap_idx).ok_or(())?,
+            None => return Err(()),
+        };
+        let permissions = if permissions == 0 { cap.permissions } else { permissions };
+
+        let derived = cap.delegate(to_pid, permissions)?;
+        self.grant(to_pid, derived)
+    }
+
+    /// Give a freshly forked child copies of the parent's inheritable
+    /// capabilities. Only the parent itself or `trusted` (init) may ask, and
+    /// only for a child with no table yet, so an existing process's
+    /// capabilities are never wiped.
+    pub fn inherit(&mut self, sender: u32, parent_pid: u32, child_pid: u32, trusted: u32) -> Result<usize, ()> {
+        if sender != parent_pid && sender != trusted {
+            return Err(());
+        }
+        let (parent, child) = (parent_pid as usize, child_pid as usize);
+        if parent >= MAX_PROCESSES || child >= MAX_PROCESSES || parent == child {
+            return Err(());
+        }
+        if self.process_tables[parent].is_none() || self.process_tables[child].is_some() {
+            return Err(());
+        }
+
+        self.process_tables[child] = Some(CapabilityTable::new());
+
+        // Borrow both tables at once
+        let (parent_table, child_table) = if parent < child {
+            let (low, high) = self.process_tables.split_at_mut(child);
+            (low[parent].as_ref(), high[0].as_mut())
+        } else {
+            let (low, high) = self.process_tables.split_at_mut(parent);
+            (high[0].as_ref(), low[child].as_mut())
+        };
+
+        match (parent_table, child_table) {
+            (Some(parent_table), Some(child_table)) => Ok(child_table.inherit_from(parent_table, child_pid)),
+            _ => Err(()),
         }
     }
 
-    /// Check if process has capability
-    pub fn check(&self, pid: u32, cap_type: CapabilityType, resource_id: u64) -> bool {
-        if pid as usize >= 256 {
-            return false;
+    /// Check if process has capability. Lapsed ones count as absent.
+    pub fn check(&mut self, pid: u32, cap_type: CapabilityType, resource_id: u64) -> bool {
+        self.query(pid, cap_type, resource_id) == CapabilityStatus::Granted
+    }
+
+    /// Whether process holds a capability, has had it expire, or never had it
+    pub fn query(&mut self, pid: u32, cap_type: CapabilityType, resource_id: u64) -> CapabilityStatus {
+        if pid as usize >= MAX_PROCESSES {
+            return CapabilityStatus::Absent;
         }
 
-        if let Some(ref table) = self.process_tables[pid as usize] {
-            table.find(cap_type, resource_id).is_some()
+        if let Some(ref mut table) = self.process_tables[pid as usize] {
+            table.lookup(cap_type, resource_id, sys_get_uptime_ms())
         } else {
-            false
+            CapabilityStatus::Absent
         }
     }
 }
@@ -19,6 +19,8 @@ pub struct IpcMessage {
     pub inline_data: [u8; 64],
     pub buffer: *mut u8,
     pub buffer_size: usize,
+    /// Port responses go to; 0 if the sender expects none
+    pub reply_port: u64,
 }
 
 impl IpcMessage {
@@ -31,6 +33,7 @@ impl IpcMessage {
             inline_data: [0; 64],
             buffer: core::ptr::null_mut(),
             buffer_size: 0,
+            reply_port: 0,
         }
     }
 
@@ -47,12 +50,168 @@ pub fn ipc_send(port_id: u64, msg: &IpcMessage) -> Result<(), ()> {
     if ret == 0 { Ok(()) } else { Err(()) }
 }
 
+/// Bytes of inline data in a message
+pub const IPC_INLINE_SIZE: usize = 64;
+const SHM_FLAG_READ_ONLY: u64 = 1;
+
+fn shm_destroy(shm_id: u64) {
+    unsafe {
+        syscall_raw(43, shm_id, 0, 0, 0, 0);
+    }
+}
+
+/// Size in bytes of a shared memory region
+fn shm_size(shm_id: u64) -> Result<usize, ()> {
+    let mut size: usize = 0;
+    let mut refcount: usize = 0;
+    let ret = unsafe {
+        syscall_raw(44, shm_id, &mut size as *mut usize as u64, &mut refcount as *mut usize as u64, 0, 0)
+    };
+    if ret == 0 { Ok(size) } else { Err(()) }
+}
+
+/// Copy the payload after `header_len` header bytes of a received message
+/// into `out` and return its length. A shared payload is destroyed even
+/// when it does not fit, so take every framed message exactly once. The
+/// sender's `buffer_size` is refused if it claims more than the region.
+pub fn ipc_take_payload(msg: &IpcMessage, header_len: usize, out: &mut [u8]) -> Result<usize, ()> {
+    let inline_size = (msg.inline_size as usize).min(IPC_INLINE_SIZE);
+    if header_len > inline_size {
+        return Err(());
+    }
+
+    if msg.buffer_size == 0 {
+        let len = inline_size - header_len;
+        let dest = out.get_mut(..len).ok_or(())?;
+        dest.copy_from_slice(&msg.inline_data[header_len..inline_size]);
+        return Ok(len);
+    }
+
+    let shm_id = msg.buffer as u64;
+    let len = msg.buffer_size;
+    let result = shm_size(shm_id)
+        .and_then(|region_size| if len <= region_size && len <= out.len() { Ok(len) } else { Err(()) })
+        .and_then(|len| {
+            let vaddr = unsafe { syscall_raw(41, shm_id, 0, SHM_FLAG_READ_ONLY, 0, 0) };
+            if vaddr == 0 {
+                return Err(());
+            }
+            unsafe {
+                core::ptr::copy_nonoverlapping(vaddr as *const u8, out.as_mut_ptr(), len);
+                syscall_raw(42, shm_id, vaddr, 0, 0, 0);
+            }
+            Ok(len)
+        });
+    shm_destroy(shm_id);
+    result
+}
+
+/// Port init receives readiness reports on
+pub const INIT_PORT: u64 = 1;
+/// Readiness report; inline data is the service's name
+pub const INIT_MSG_SERVICE_READY: u64 = 1;
+
+/// Tell init this service is taking requests, so it can start the
+/// services that depend on it
+pub fn notify_init_ready(name: &[u8]) {
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_NOTIFICATION;
+    msg.msg_id = INIT_MSG_SERVICE_READY;
+    msg.set_inline_data(name);
+    let _ = ipc_send(INIT_PORT, &msg);
+}
+
+/// Port of the service name registry (hosted by the device manager)
+pub const SERVICE_REGISTRY_PORT: u64 = 90;
+/// [port:8][name] -> [status:1]
+const REGISTRY_OP_REGISTER_NAME: u64 = 5;
+/// [name] -> [status:1][port:8]
+const REGISTRY_OP_LOOKUP_NAME: u64 = 6;
+const REGISTRY_REPLY_TIMEOUT_MS: u64 = 500;
+
+/// One registry request; Err unless the registry answered with status 0
+fn registry_request(op: u64, data: &[u8]) -> Result<IpcMessage, ()> {
+    let reply_port = ipc_create_port()?;
+
+    let mut msg = IpcMessage::new();
+    msg.msg_type = IPC_MSG_REQUEST;
+    msg.msg_id = op;
+    msg.set_inline_data(data);
+    msg.reply_port = reply_port;
+
+    let mut reply = IpcMessage::new();
+    let result = ipc_send(SERVICE_REGISTRY_PORT, &msg)
+        .and_then(|_| ipc_receive_timeout(reply_port, &mut reply, REGISTRY_REPLY_TIMEOUT_MS));
+    ipc_destroy_port(reply_port);
+    result?;
+
+    if reply.msg_type != IPC_MSG_RESPONSE || reply.msg_id != op || reply.inline_size == 0 || reply.inline_data[0] != 0 {
+        return Err(());
+    }
+    Ok(reply)
+}
+
+/// Publish this service's port under `name`, replacing any earlier entry
+pub fn register_service_name(name: &[u8], port: u64) -> Result<(), ()> {
+    let mut data = [0u8; 64];
+    let len = name.len().min(data.len() - 8);
+    data[0..8].copy_from_slice(&port.to_le_bytes());
+    data[8..8 + len].copy_from_slice(&name[..len]);
+    registry_request(REGISTRY_OP_REGISTER_NAME, &data[..8 + len]).map(|_| ())
+}
+
+/// Port of the service registered as `name`
+pub fn lookup_service(name: &[u8]) -> Result<u64, ()> {
+    let reply = registry_request(REGISTRY_OP_LOOKUP_NAME, name)?;
+    if reply.inline_size < 9 {
+        return Err(());
+    }
+    let mut port = [0u8; 8];
+    port.copy_from_slice(&reply.inline_data[1..9]);
+    Ok(u64::from_le_bytes(port))
+}
+
 /// Convenience wrapper for receive
 pub fn ipc_receive(port_id: u64, msg: &mut IpcMessage) -> Result<(), ()> {
     let ret = unsafe { sys_ipc_receive(port_id, msg as *mut IpcMessage) };
     if ret == 0 { Ok(()) } else { Err(()) }
 }
 
+/// Convenience wrapper that returns Result for reply
+pub fn ipc_reply(request: &IpcMessage, response: &IpcMessage) -> Result<(), ()> {
+    let ret = unsafe { sys_ipc_reply(request as *const IpcMessage, response as *const IpcMessage) };
+    if ret == 0 { Ok(()) } else { Err(()) }
+}
+
+/// Receive with a timeout; Err on timeout or failure
+pub fn ipc_receive_timeout(port_id: u64, msg: &mut IpcMessage, timeout_ms: u64) -> Result<(), ()> {
+    let ret = unsafe { syscall_raw(54, port_id, msg as *mut IpcMessage as u64, timeout_ms, 0, 0) as i32 };
+    if ret == 0 { Ok(()) } else { Err(()) }
+}
+
+/// Process that sent the message this thread last received; read it
+/// before receiving anything else
+pub fn ipc_sender_pid() -> u32 {
+    unsafe { syscall_raw(63, 0, 0, 0, 0, 0) as u32 }
+}
+
+/// Process that created `port_id`, or 0 if there is no such port
+pub fn ipc_port_owner(port_id: u64) -> u32 {
+    unsafe { syscall_raw(64, port_id, 0, 0, 0, 0) as u32 }
+}
+
+/// Create a private port, e.g. to receive replies on
+pub fn ipc_create_port() -> Result<u64, ()> {
+    let port = unsafe { syscall_raw(26, 0, 0, 0, 0, 0) };
+    if port == 0 || (port as i64) < 0 { Err(()) } else { Ok(port) }
+}
+
+pub fn ipc_destroy_port(port_id: u64) {
+    unsafe {
+        syscall_raw(27, port_id, 0, 0, 0, 0);
+    }
+}
+
 /// System call wrapper for IPC send
 #[no_mangle]
 pub extern "C" fn sys_ipc_send(port_id: u64, msg: *const IpcMessage) -> i32 {
@@ -65,20 +224,27 @@ pub extern "C" fn sys_ipc_receive(port_id: u64, msg: *mut IpcMessage) -> i32 {
     unsafe { syscall_raw(10, port_id, msg as u64, 0, 0, 0) as i32 }
 }
 
+/// System call wrapper for IPC reply
+#[no_mangle]
+pub extern "C" fn sys_ipc_reply(request: *const IpcMessage, response: *const IpcMessage) -> i32 {
+    unsafe { syscall_raw(55, request as u64, response as u64, 0, 0, 0) as i32 }
+}
+
 /// Raw syscall (x86_64 only for now)
 #[cfg(target_arch = "x86_64")]
 unsafe fn syscall_raw(num: u64, arg1: u64, arg2: u64, arg3: u64, arg4: u64, arg5: u64) -> u64 {
     let ret: u64;
     core::arch::asm!(
         "syscall",
-        in("rax") num,
+        inlateout("rax") num => ret,
         in("rdi") arg1,
         in("rsi") arg2,
         in("rdx") arg3,
         in("r10") arg4,
         in("r8") arg5,
-        out("rax") ret,
-        options(nostack, preserves_flags)
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack)
     );
     ret
 }
@@ -9,21 +9,76 @@ mod capability;
 mod acl;
 mod sandbox;
 mod ipc;
+mod acl_store;
+mod audit;
+mod syscalls;
 
+use acl::{AclEntry, AclTable};
+use audit::{AuditLog, AuditRecord, AUDIT_RECORD_SIZE};
 use capability::CapabilityManager;
 use sandbox::SandboxManager;
-use capability::{Capability, CapabilityType};
-use ipc::{IpcMessage, IPC_MSG_RESPONSE, ipc_receive, ipc_send};
+use capability::{Capability, CapabilityStatus, CapabilityType, CAP_FLAG_INHERITABLE};
+use ipc::{IpcMessage, IPC_MSG_RESPONSE, INIT_PORT, ipc_create_port, ipc_port_owner, ipc_receive, ipc_reply,
+          ipc_sender_pid, ipc_take_payload, notify_init_ready, register_service_name};
+use syscalls::sys_get_uptime_ms;
 
 static mut CAP_MANAGER: Option<CapabilityManager> = None;
 static mut SANDBOX_MANAGER: Option<SandboxManager> = None;
+static mut ACL_TABLE: Option<AclTable> = None;
+/// Whether ACL_TABLE matches the saved rules, or there were none; if they
+/// could not be read at startup, changes are refused so saving cannot
+/// overwrite them
+static mut ACL_TABLE_LOADED: bool = false;
+static mut AUDIT_LOG: AuditLog = AuditLog::new();
 
 // Security IPC operation IDs
 const SEC_OP_GRANT_CAP: u64 = 1;
 const SEC_OP_REVOKE_CAP: u64 = 2;
+/// [pid:4][cap_type:1][resource:8] -> [0 never granted, 1 held, 2 expired]
 const SEC_OP_CHECK_CAP: u64 = 3;
+/// Sent after a fork by the parent or init: [parent pid:4][child pid:4];
+/// refused if the child already has capabilities
+const SEC_OP_INHERIT_CAPS: u64 = 4;
+/// [from pid:4][to pid:4][cap_idx:4][permissions:8] -> [new cap_idx:4];
+/// permissions 0 keeps the source's, wider ones are refused. Only from pid
+/// itself may send it.
+const SEC_OP_DELEGATE_CAP: u64 = 5;
+/// [pid:4][mode:1]; replacing an existing sandbox follows the same rule
+/// as SEC_OP_SANDBOX_PATH_RULE
 const SEC_OP_CREATE_SANDBOX: u64 = 10;
+/// [pid:4][resource_type:1] framed with [resource_id] of any length ->
+/// [1 allowed / 0 denied][1 sandboxed / 0 not]; a process without a
+/// sandbox is allowed
 const SEC_OP_CHECK_ACCESS: u64 = 11;
+/// Longest resource id a check accepts; longer ones are denied
+const CHECK_ACCESS_MAX_ID: usize = 4096;
+/// [resource:8][entry type:1][id:4][permissions:4], replacing any entry of
+/// the same type and id; only init or a SecurityAdmin holder may send it
+const SEC_OP_ACL_SET: u64 = 12;
+/// [resource:8][entry type:1][id:4]; same senders as SEC_OP_ACL_SET
+const SEC_OP_ACL_REMOVE: u64 = 13;
+/// [resource:8][uid:4][gid:4][permissions:4] -> [1 allowed / 0 denied]
+const SEC_OP_ACL_CHECK: u64 = 14;
+/// [pid:4][allow:1][path] adds a path rule to an existing sandbox; only
+/// its creator or init may, never the sandboxed process
+const SEC_OP_SANDBOX_PATH_RULE: u64 = 15;
+/// Drain the oldest audit record: [] -> [dropped since last read:4]
+/// [records left:2][returned:1][0:1] followed by the record if returned is 1.
+/// Only init or a SecurityAudit holder may send it.
+const SEC_OP_READ_AUDIT: u64 = 16;
+/// [denials only:1]; same senders as SEC_OP_READ_AUDIT
+const SEC_OP_AUDIT_MODE: u64 = 17;
+
+// ACL change status: applied but could not be saved
+const ACL_STATUS_NOT_SAVED: u8 = 0x02;
+// ACL change status: refused, the saved ACLs could not be read at startup
+const ACL_STATUS_NOT_LOADED: u8 = 0x03;
+// ACL change status: the sender may not change ACLs
+const ACL_STATUS_NOT_PERMITTED: u8 = 0x04;
+// Sandbox change status: the sender may not change this sandbox
+const SANDBOX_STATUS_NOT_PERMITTED: u8 = 0x02;
+// Audit status: the sender may not read or configure the audit log
+const AUDIT_STATUS_NOT_PERMITTED: u8 = 0x02;
 
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
@@ -32,8 +87,18 @@ pub extern "C" fn _start() -> ! {
         CAP_MANAGER = Some(CapabilityManager::new());
         SANDBOX_MANAGER = Some(SandboxManager::new());
 
+        // Clients find us by name through the service registry. Register
+        // before loading the ACLs: the VFS only lets the owner of this port
+        // open files without asking us.
+        let port = ipc_create_port().unwrap_or(0);
+        if port != 0 {
+            let _ = register_service_name(b"security", port);
+        }
+        load_acl_table();
+        notify_init_ready(b"security");
+
         // Main service loop
-        main_loop();
+        main_loop(port);
     }
 }
 
@@ -42,13 +107,11 @@ fn panic(_info: &core::panic::PanicInfo) -> ! {
     loop {}
 }
 
-fn main_loop() -> ! {
+fn main_loop(port: u64) -> ! {
     let mut msg = IpcMessage::new();
 
     loop {
-        // Receive on well-known security port (3). In a fuller implementation,
-        // this would be dynamically registered with the device manager.
-        if ipc_receive(3, &mut msg).is_err() {
+        if ipc_receive(port, &mut msg).is_err() {
             continue;
         }
 
@@ -60,16 +123,22 @@ fn main_loop() -> ! {
             SEC_OP_GRANT_CAP => handle_grant(&msg, &mut resp),
             SEC_OP_REVOKE_CAP => handle_revoke(&msg, &mut resp),
             SEC_OP_CHECK_CAP => handle_check(&msg, &mut resp),
+            SEC_OP_INHERIT_CAPS => handle_inherit(&msg, &mut resp),
+            SEC_OP_DELEGATE_CAP => handle_delegate(&msg, &mut resp),
             SEC_OP_CREATE_SANDBOX => handle_create_sandbox(&msg, &mut resp),
             SEC_OP_CHECK_ACCESS => handle_check_access(&msg, &mut resp),
+            SEC_OP_SANDBOX_PATH_RULE => handle_sandbox_path_rule(&msg, &mut resp),
+            SEC_OP_READ_AUDIT => handle_read_audit(&mut resp),
+            SEC_OP_AUDIT_MODE => handle_audit_mode(&msg, &mut resp),
+            SEC_OP_ACL_SET | SEC_OP_ACL_REMOVE => handle_acl_change(&msg, &mut resp),
+            SEC_OP_ACL_CHECK => handle_acl_check(&msg, &mut resp),
             _ => {
                 resp.inline_data[0] = 0xFF; // Unknown op
                 resp.inline_size = 1;
             }
         }
 
-        // Use sender_tid as reply target; real implementation would map to a reply port.
-        let _ = ipc_send(msg.sender_tid, &resp);
+        let _ = ipc_reply(&msg, &resp);
     }
 }
 
@@ -87,8 +156,23 @@ fn parse_u64_le(bytes: &[u8]) -> u64 {
     u64::from_le_bytes(buf)
 }
 
+/// Whether the sender of the request being handled is init or holds `cap`
+fn sender_holds(cap: CapabilityType) -> bool {
+    let sender = ipc_sender_pid();
+    if sender == ipc_port_owner(INIT_PORT) {
+        return true;
+    }
+    unsafe {
+        match CAP_MANAGER {
+            Some(ref mut mgr) => mgr.check(sender, cap, 0),
+            None => false,
+        }
+    }
+}
+
 fn handle_grant(msg: &IpcMessage, resp: &mut IpcMessage) {
-    // inline_data layout: [pid:4][cap_type:1][resource:8]
+    // inline_data layout: [pid:4][cap_type:1][resource:8], optionally
+    // followed by [flags:1] and [ttl_ms:8] (0 = no expiry)
     if msg.inline_size < 13 {
         resp.inline_data[0] = 0xFE;
         resp.inline_size = 1;
@@ -98,10 +182,23 @@ fn handle_grant(msg: &IpcMessage, resp: &mut IpcMessage) {
     let pid = parse_u32_le(&msg.inline_data[0..4]);
     let cap_type = msg.inline_data[4];
     let resource = parse_u64_le(&msg.inline_data[5..13]);
+    let flags = if msg.inline_size > 13 { msg.inline_data[13] } else { 0 };
+    let ttl_ms = if msg.inline_size >= 22 { parse_u64_le(&msg.inline_data[14..22]) } else { 0 };
+
+    let cap_type = cap_from_u8(cap_type);
+    if cap_type.init_only() && ipc_sender_pid() != ipc_port_owner(INIT_PORT) {
+        resp.inline_data[0] = 0x01;
+        resp.inline_size = 1;
+        return;
+    }
 
     unsafe {
         if let Some(ref mut mgr) = CAP_MANAGER {
-            let cap = Capability::new(cap_from_u8(cap_type), resource, pid);
+            let mut cap = Capability::new(cap_type, resource, pid);
+            cap.flags = flags & CAP_FLAG_INHERITABLE;
+            if ttl_ms != 0 {
+                cap.expires_at_ms = cap.timestamp.saturating_add(ttl_ms);
+            }
             match mgr.grant(pid, cap) {
                 Ok(idx) => {
                     resp.inline_data[0..4].copy_from_slice(&(idx as u32).to_le_bytes());
@@ -146,6 +243,69 @@ fn handle_revoke(msg: &IpcMessage, resp: &mut IpcMessage) {
     }
 }
 
+fn handle_inherit(msg: &IpcMessage, resp: &mut IpcMessage) {
+    // inline_data layout: [parent pid:4][child pid:4]
+    if msg.inline_size < 8 {
+        resp.inline_data[0] = 0xFE;
+        resp.inline_size = 1;
+        return;
+    }
+
+    let parent_pid = parse_u32_le(&msg.inline_data[0..4]);
+    let child_pid = parse_u32_le(&msg.inline_data[4..8]);
+
+    unsafe {
+        if let Some(ref mut mgr) = CAP_MANAGER {
+            match mgr.inherit(ipc_sender_pid(), parent_pid, child_pid, ipc_port_owner(INIT_PORT)) {
+                Ok(count) => {
+                    // Number of capabilities the child received
+                    resp.inline_data[0..4].copy_from_slice(&(count as u32).to_le_bytes());
+                    resp.inline_size = 4;
+                }
+                Err(_) => {
+                    resp.inline_data[0] = 0x01;
+                    resp.inline_size = 1;
+                }
+            }
+        } else {
+            resp.inline_data[0] = 0x01;
+            resp.inline_size = 1;
+        }
+    }
+}
+
+fn handle_delegate(msg: &IpcMessage, resp: &mut IpcMessage) {
+    // inline_data layout: [from pid:4][to pid:4][cap_idx:4][permissions:8]
+    if msg.inline_size < 20 {
+        resp.inline_data[0] = 0xFE;
+        resp.inline_size = 1;
+        return;
+    }
+
+    let from_pid = parse_u32_le(&msg.inline_data[0..4]);
+    let to_pid = parse_u32_le(&msg.inline_data[4..8]);
+    let cap_idx = parse_u32_le(&msg.inline_data[8..12]) as usize;
+    let permissions = parse_u64_le(&msg.inline_data[12..20]);
+
+    unsafe {
+        if let Some(ref mut mgr) = CAP_MANAGER {
+            match mgr.delegate(ipc_sender_pid(), from_pid, to_pid, cap_idx, permissions) {
+                Ok(idx) => {
+                    resp.inline_data[0..4].copy_from_slice(&(idx as u32).to_le_bytes());
+                    resp.inline_size = 4;
+                }
+                Err(_) => {
+                    resp.inline_data[0] = 0x01;
+                    resp.inline_size = 1;
+                }
+            }
+        } else {
+            resp.inline_data[0] = 0x01;
+            resp.inline_size = 1;
+        }
+    }
+}
+
 fn handle_check(msg: &IpcMessage, resp: &mut IpcMessage) {
     // inline_data layout: [pid:4][cap_type:1][resource:8]
     if msg.inline_size < 13 {
@@ -159,14 +319,20 @@ fn handle_check(msg: &IpcMessage, resp: &mut IpcMessage) {
     let resource = parse_u64_le(&msg.inline_data[5..13]);
 
     unsafe {
-        if let Some(ref mgr) = CAP_MANAGER {
-            let allowed = mgr.check(pid, cap_from_u8(cap_type), resource);
-            resp.inline_data[0] = if allowed { 1 } else { 0 };
+        if let Some(ref mut mgr) = CAP_MANAGER {
+            resp.inline_data[0] = match mgr.query(pid, cap_from_u8(cap_type), resource) {
+                CapabilityStatus::Absent => 0,
+                CapabilityStatus::Granted => 1,
+                CapabilityStatus::Expired => 2,
+            };
             resp.inline_size = 1;
         } else {
             resp.inline_data[0] = 0;
             resp.inline_size = 1;
         }
+
+        let allowed = resp.inline_data[0] == 1;
+        AUDIT_LOG.record(AuditRecord::capability(sys_get_uptime_ms(), pid, cap_type, resource, allowed));
     }
 }
 
@@ -181,15 +347,23 @@ fn handle_create_sandbox(msg: &IpcMessage, resp: &mut IpcMessage) {
     let pid = parse_u32_le(&msg.inline_data[0..4]);
     let mode = msg.inline_data[4];
 
+    let sender = ipc_sender_pid();
+
     unsafe {
         if let Some(ref mut mgr) = SANDBOX_MANAGER {
+            if !mgr.may_configure(pid, sender, ipc_port_owner(INIT_PORT)) {
+                resp.inline_data[0] = SANDBOX_STATUS_NOT_PERMITTED;
+                resp.inline_size = 1;
+                return;
+            }
+
             let cfg = if mode == 1 {
                 sandbox::SandboxConfig::new_permissive()
             } else {
                 sandbox::SandboxConfig::new_default()
             };
 
-            if mgr.create_sandbox(pid, cfg).is_ok() {
+            if mgr.create_sandbox(pid, sender, cfg).is_ok() {
                 resp.inline_data[0] = 0;
                 resp.inline_size = 1;
             } else {
@@ -203,17 +377,66 @@ fn handle_create_sandbox(msg: &IpcMessage, resp: &mut IpcMessage) {
     }
 }
 
-fn handle_check_access(msg: &IpcMessage, resp: &mut IpcMessage) {
-    // inline_data layout: [pid:4][resource_type:1][resource_id (rest as string bytes)]
+fn handle_sandbox_path_rule(msg: &IpcMessage, resp: &mut IpcMessage) {
+    // inline_data layout: [pid:4][allow:1][path (rest as string bytes)]
     if msg.inline_size < 6 {
         resp.inline_data[0] = 0xFE;
         resp.inline_size = 1;
         return;
     }
 
+    let pid = parse_u32_le(&msg.inline_data[0..4]);
+    let allow = msg.inline_data[4] != 0;
+    let path_bytes = &msg.inline_data[5..msg.inline_size as usize];
+    let path_len = path_bytes.iter().position(|&b| b == 0).unwrap_or(path_bytes.len());
+
+    let path = match core::str::from_utf8(&path_bytes[..path_len]) {
+        Ok(path) => path,
+        Err(_) => {
+            resp.inline_data[0] = 0xFE;
+            resp.inline_size = 1;
+            return;
+        }
+    };
+
+    let sender = ipc_sender_pid();
+
+    unsafe {
+        if let Some(ref mut mgr) = SANDBOX_MANAGER {
+            if mgr.get_sandbox(pid).is_some() && !mgr.may_configure(pid, sender, ipc_port_owner(INIT_PORT)) {
+                resp.inline_data[0] = SANDBOX_STATUS_NOT_PERMITTED;
+                resp.inline_size = 1;
+                return;
+            }
+
+            let added = match mgr.get_sandbox_mut(pid) {
+                Some(sandbox) => sandbox.config.add_path_rule(path, allow).is_ok(),
+                None => false,
+            };
+            resp.inline_data[0] = if added { 0 } else { 0x01 };
+            resp.inline_size = 1;
+        } else {
+            resp.inline_data[0] = 0x01;
+            resp.inline_size = 1;
+        }
+    }
+}
+
+fn handle_check_access(msg: &IpcMessage, resp: &mut IpcMessage) {
+    // inline_data layout: [pid:4][resource_type:1], then the resource id
+    // as string bytes, inline or shared
+    let mut resource_id_buf = [0u8; CHECK_ACCESS_MAX_ID];
+    let resource_id_bytes = match ipc_take_payload(msg, 5, &mut resource_id_buf) {
+        Ok(len) => &resource_id_buf[..len],
+        Err(_) => {
+            resp.inline_data[0] = 0xFE;
+            resp.inline_size = 1;
+            return;
+        }
+    };
+
     let pid = parse_u32_le(&msg.inline_data[0..4]);
     let resource_type_byte = msg.inline_data[4];
-    let resource_id_bytes = &msg.inline_data[5..msg.inline_size as usize];
     let resource_id_len = resource_id_bytes.iter().position(|&b| b == 0).unwrap_or(resource_id_bytes.len());
     let resource_id = core::str::from_utf8(&resource_id_bytes[..resource_id_len]).unwrap_or("");
 
@@ -230,11 +453,149 @@ fn handle_check_access(msg: &IpcMessage, resp: &mut IpcMessage) {
         if let Some(ref mgr) = SANDBOX_MANAGER {
             let allowed = mgr.check_access(pid, resource_type, resource_id);
             resp.inline_data[0] = if allowed { 1 } else { 0 };
-            resp.inline_size = 1;
+            resp.inline_data[1] = mgr.get_sandbox(pid).is_some() as u8;
+            resp.inline_size = 2;
         } else {
             resp.inline_data[0] = 0;
             resp.inline_size = 1;
         }
+
+        let allowed = resp.inline_data[0] == 1;
+        AUDIT_LOG.record(AuditRecord::access(
+            sys_get_uptime_ms(),
+            pid,
+            resource_type_byte,
+            &resource_id_bytes[..resource_id_len],
+            allowed,
+        ));
+    }
+}
+
+fn handle_read_audit(resp: &mut IpcMessage) {
+    if !sender_holds(CapabilityType::SecurityAudit) {
+        resp.inline_data[0] = AUDIT_STATUS_NOT_PERMITTED;
+        resp.inline_size = 1;
+        return;
+    }
+
+    let log = unsafe { &mut *core::ptr::addr_of_mut!(AUDIT_LOG) };
+    let dropped = log.take_dropped();
+    let record = log.pop();
+
+    resp.inline_data[0..4].copy_from_slice(&dropped.to_le_bytes());
+    resp.inline_data[4..6].copy_from_slice(&(log.len() as u16).to_le_bytes());
+    resp.inline_data[6] = record.is_some() as u8;
+    resp.inline_data[7] = 0;
+    resp.inline_size = 8;
+
+    if let Some(record) = record {
+        let mut encoded = [0u8; AUDIT_RECORD_SIZE];
+        record.encode(&mut encoded);
+        resp.inline_data[8..8 + AUDIT_RECORD_SIZE].copy_from_slice(&encoded);
+        resp.inline_size = (8 + AUDIT_RECORD_SIZE) as u32;
+    }
+}
+
+fn handle_audit_mode(msg: &IpcMessage, resp: &mut IpcMessage) {
+    if msg.inline_size < 1 {
+        resp.inline_data[0] = 0xFE;
+        resp.inline_size = 1;
+        return;
+    }
+    if !sender_holds(CapabilityType::SecurityAudit) {
+        resp.inline_data[0] = AUDIT_STATUS_NOT_PERMITTED;
+        resp.inline_size = 1;
+        return;
+    }
+
+    unsafe {
+        AUDIT_LOG.denials_only = msg.inline_data[0] != 0;
+    }
+    resp.inline_data[0] = 0;
+    resp.inline_size = 1;
+}
+
+/// Load saved ACLs once at startup. With none saved the table starts
+/// empty; if they cannot be read it is empty too, denying everything, but
+/// stays unloaded.
+fn load_acl_table() {
+    unsafe {
+        match acl_store::load() {
+            Ok(table) => {
+                ACL_TABLE = Some(table.unwrap_or_else(AclTable::new));
+                ACL_TABLE_LOADED = true;
+            }
+            Err(_) => ACL_TABLE = Some(AclTable::new()),
+        }
+    }
+}
+
+fn handle_acl_change(msg: &IpcMessage, resp: &mut IpcMessage) {
+    // inline_data layout: [resource:8][entry type:1][id:4][permissions:4, set only]
+    let needed = if msg.msg_id == SEC_OP_ACL_SET { 17 } else { 13 };
+    let entry_type = acl::entry_type_from_u8(msg.inline_data[8]);
+    if (msg.inline_size as usize) < needed || entry_type.is_none() {
+        resp.inline_data[0] = 0xFE;
+        resp.inline_size = 1;
+        return;
+    }
+
+    let resource = parse_u64_le(&msg.inline_data[0..8]);
+    let entry_type = entry_type.unwrap();
+    let id = parse_u32_le(&msg.inline_data[9..13]);
+
+    if !sender_holds(CapabilityType::SecurityAdmin) {
+        resp.inline_data[0] = ACL_STATUS_NOT_PERMITTED;
+        resp.inline_size = 1;
+        return;
+    }
+
+    unsafe {
+        resp.inline_size = 1;
+        if !ACL_TABLE_LOADED {
+            resp.inline_data[0] = ACL_STATUS_NOT_LOADED;
+            return;
+        }
+
+        if let Some(ref mut table) = ACL_TABLE {
+            let result = if msg.msg_id == SEC_OP_ACL_SET {
+                let permissions = parse_u32_le(&msg.inline_data[13..17]);
+                table.set_entry(resource, AclEntry::new(entry_type, id, permissions))
+            } else {
+                table.remove_entry(resource, entry_type, id)
+            };
+
+            resp.inline_data[0] = match result {
+                Err(_) => 0x01,
+                Ok(_) if acl_store::save(table).is_err() => ACL_STATUS_NOT_SAVED,
+                Ok(_) => 0,
+            };
+        } else {
+            resp.inline_data[0] = 0x01;
+        }
+    }
+}
+
+fn handle_acl_check(msg: &IpcMessage, resp: &mut IpcMessage) {
+    // inline_data layout: [resource:8][uid:4][gid:4][permissions:4]
+    if msg.inline_size < 20 {
+        resp.inline_data[0] = 0xFE;
+        resp.inline_size = 1;
+        return;
+    }
+
+    let resource = parse_u64_le(&msg.inline_data[0..8]);
+    let uid = parse_u32_le(&msg.inline_data[8..12]);
+    let gid = parse_u32_le(&msg.inline_data[12..16]);
+    let permissions = parse_u32_le(&msg.inline_data[16..20]);
+
+    unsafe {
+        let allowed = match ACL_TABLE {
+            Some(ref table) => table.check_access(resource, uid, gid, permissions),
+            None => false,
+        };
+        resp.inline_data[0] = if allowed { 1 } else { 0 };
+        resp.inline_size = 1;
     }
 }
 
@@ -268,6 +629,8 @@ fn cap_from_u8(val: u8) -> CapabilityType {
         70 => CapabilityType::HardwareMMIO,
         71 => CapabilityType::HardwareIRQ,
         72 => CapabilityType::HardwareDMA,
+        80 => CapabilityType::SecurityAdmin,
+        81 => CapabilityType::SecurityAudit,
         _ => CapabilityType::FileRead,
     }
 }
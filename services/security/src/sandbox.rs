@@ -1,13 +1,63 @@
 //! Application Sandboxing System
+//!
+//! File access from a sandbox is decided by its path rules: the rule with
+//! the longest prefix of the path wins, whole components only, so a rule
+//! for `/home/app` covers `/home/app/data` but not `/home/apple`. A path
+//! no rule covers is denied.
 
 use crate::capability::{Capability, CapabilityType, CapabilityTable};
 
+/// Path rules per sandbox
+pub const MAX_PATH_RULES: usize = 16;
+/// Longest path a rule can hold
+pub const SANDBOX_PATH_MAX: usize = 255;
+
+/// Components of a path, skipping empty and `.` ones
+fn path_components(path: &[u8]) -> impl Iterator<Item = &[u8]> {
+    path.split(|&b| b == b'/').filter(|c| !c.is_empty() && *c != b".")
+}
+
+/// Absolute and free of `..`, which would let a path climb out of an
+/// allowed directory while still starting with it
+fn path_is_confined(path: &[u8]) -> bool {
+    path.first() == Some(&b'/') && !path_components(path).any(|c| c == b"..")
+}
+
+/// Allow or deny for everything under a path
+#[derive(Clone, Copy)]
+pub struct PathRule {
+    path: [u8; SANDBOX_PATH_MAX + 1],
+    len: usize,
+    pub allow: bool,
+}
+
+impl PathRule {
+    const EMPTY: PathRule = PathRule { path: [0; SANDBOX_PATH_MAX + 1], len: 0, allow: false };
+
+    pub fn path(&self) -> &[u8] {
+        &self.path[..self.len]
+    }
+
+    /// Components the rule matched of `path`, if it covers it
+    pub fn match_depth(&self, path: &[u8]) -> Option<usize> {
+        let mut components = path_components(path);
+        let mut depth = 0;
+        for rule_component in path_components(self.path()) {
+            if components.next() != Some(rule_component) {
+                return None;
+            }
+            depth += 1;
+        }
+        Some(depth)
+    }
+}
+
 /// Sandbox configuration
 #[repr(C)]
 pub struct SandboxConfig {
-    /// Allowed file system paths
-    pub allowed_paths: [[u8; 256]; 16],
-    pub path_count: usize,
+    /// File system allow/deny rules
+    pub path_rules: [PathRule; MAX_PATH_RULES],
+    pub path_rule_count: usize,
 
     /// Allowed network addresses
     pub allowed_networks: [u32; 16],
@@ -45,8 +95,8 @@ pub struct SandboxConfig {
 impl SandboxConfig {
     pub fn new_default() -> Self {
         Self {
-            allowed_paths: [[0; 256]; 16],
-            path_count: 0,
+            path_rules: [PathRule::EMPTY; MAX_PATH_RULES],
+            path_rule_count: 0,
             allowed_networks: [0; 16],
             network_count: 0,
             allowed_devices: [0; 16],
@@ -63,9 +113,9 @@ impl SandboxConfig {
     }
 
     pub fn new_permissive() -> Self {
-        Self {
-            allowed_paths: [[0; 256]; 16],
-            path_count: 0,
+        let mut config = Self {
+            path_rules: [PathRule::EMPTY; MAX_PATH_RULES],
+            path_rule_count: 0,
             allowed_networks: [0; 16],
             network_count: 0,
             allowed_devices: [0; 16],
@@ -78,38 +128,85 @@ impl SandboxConfig {
             can_exec: true,
             can_network: true,
             can_hardware: true,
-        }
+        };
+        let _ = config.add_allowed_path("/");
+        config
     }
 
-    pub fn add_allowed_path(&mut self, path: &str) -> Result<(), ()> {
-        if self.path_count >= 16 {
+    pub fn path_rules(&self) -> &[PathRule] {
+        &self.path_rules[..self.path_rule_count]
+    }
+
+    /// Add a rule, replacing one for the same path. The path must be
+    /// absolute, without `..` and at most SANDBOX_PATH_MAX bytes.
+    pub fn add_path_rule(&mut self, path: &str, allow: bool) -> Result<(), ()> {
+        let path = path.as_bytes();
+        if path.len() > SANDBOX_PATH_MAX || !path_is_confined(path) {
             return Err(());
         }
 
-        let len = path.len().min(255);
-        self.allowed_paths[self.path_count][0..len].copy_from_slice(&path.as_bytes()[0..len]);
-        self.path_count += 1;
-
+        let index = match self
+            .path_rules()
+            .iter()
+            .position(|rule| path_components(rule.path()).eq(path_components(path)))
+        {
+            Some(index) => index,
+            None if self.path_rule_count < MAX_PATH_RULES => {
+                self.path_rule_count += 1;
+                self.path_rule_count - 1
+            }
+            None => return Err(()),
+        };
+
+        let rule = &mut self.path_rules[index];
+        *rule = PathRule::EMPTY;
+        rule.path[..path.len()].copy_from_slice(path);
+        rule.len = path.len();
+        rule.allow = allow;
         Ok(())
     }
 
-    pub fn check_path_allowed(&self, path: &str) -> bool {
-        for i in 0..self.path_count {
-            let allowed_path = core::str::from_utf8(&self.allowed_paths[i])
-                .unwrap_or("");
+    pub fn add_allowed_path(&mut self, path: &str) -> Result<(), ()> {
+        self.add_path_rule(path, true)
+    }
 
-            if path.starts_with(allowed_path) {
-                return true;
-            }
+    pub fn add_denied_path(&mut self, path: &str) -> Result<(), ()> {
+        self.add_path_rule(path, false)
+    }
+
+    /// Builder form of add_allowed_path
+    pub fn allow_path(mut self, path: &str) -> Result<Self, ()> {
+        self.add_allowed_path(path)?;
+        Ok(self)
+    }
+
+    /// Builder form of add_denied_path
+    pub fn deny_path(mut self, path: &str) -> Result<Self, ()> {
+        self.add_denied_path(path)?;
+        Ok(self)
+    }
+
+    /// Whether the most specific rule covering `path` allows it
+    pub fn check_path_allowed(&self, path: &str) -> bool {
+        let path = path.as_bytes();
+        if !path_is_confined(path) {
+            return false;
         }
 
-        false
+        // Rules never share a path, so depths of matches are distinct
+        self.path_rules()
+            .iter()
+            .filter_map(|rule| rule.match_depth(path).map(|depth| (depth, rule.allow)))
+            .max_by_key(|&(depth, _)| depth)
+            .map_or(false, |(_, allow)| allow)
     }
 }
 
 /// Sandbox instance
 pub struct Sandbox {
     pub pid: u32,
+    /// Process that set up the sandbox; it may change it later
+    pub creator: u32,
     pub config: SandboxConfig,
     pub capabilities: CapabilityTable,
     pub memory_used: u64,
@@ -119,9 +216,10 @@ pub struct Sandbox {
 }
 
 impl Sandbox {
-    pub fn new(pid: u32, config: SandboxConfig) -> Self {
+    pub fn new(pid: u32, creator: u32, config: SandboxConfig) -> Self {
         Self {
             pid,
+            creator,
             config,
             capabilities: CapabilityTable::new(),
             memory_used: 0,
@@ -165,31 +263,44 @@ impl Sandbox {
     }
 }
 
-/// Sandbox manager
-pub struct SandboxManager {
-    sandboxes: [Option<Sandbox>; 256],
+/// Sandbox manager, with a slot for each pid below `MAX_PROCESSES`
+pub struct SandboxManager<const MAX_PROCESSES: usize = 256> {
+    sandboxes: [Option<Sandbox>; MAX_PROCESSES],
 }
 
-impl SandboxManager {
+impl<const MAX_PROCESSES: usize> SandboxManager<MAX_PROCESSES> {
     pub fn new() -> Self {
         Self {
-            sandboxes: [None; 256],
+            sandboxes: core::array::from_fn(|_| None),
         }
     }
 
-    /// Create sandbox for process
-    pub fn create_sandbox(&mut self, pid: u32, config: SandboxConfig) -> Result<(), ()> {
-        if pid as usize >= 256 {
+    /// Create sandbox for process on behalf of `creator`
+    pub fn create_sandbox(&mut self, pid: u32, creator: u32, config: SandboxConfig) -> Result<(), ()> {
+        if pid as usize >= MAX_PROCESSES {
             return Err(());
         }
 
-        self.sandboxes[pid as usize] = Some(Sandbox::new(pid, config));
+        self.sandboxes[pid as usize] = Some(Sandbox::new(pid, creator, config));
         Ok(())
     }
 
+    /// Whether `sender` may set up or change `pid`'s sandbox. The confined
+    /// process never may; an existing sandbox is only changed by its
+    /// creator or by `trusted` (init).
+    pub fn may_configure(&self, pid: u32, sender: u32, trusted: u32) -> bool {
+        if sender == pid {
+            return false;
+        }
+        match self.get_sandbox(pid) {
+            Some(sandbox) => sender == sandbox.creator || sender == trusted,
+            None => true,
+        }
+    }
+
     /// Get sandbox for process
     pub fn get_sandbox(&self, pid: u32) -> Option<&Sandbox> {
-        if pid as usize >= 256 {
+        if pid as usize >= MAX_PROCESSES {
             return None;
         }
 
@@ -198,7 +309,7 @@ impl SandboxManager {
 
     /// Get mutable sandbox for process
     pub fn get_sandbox_mut(&mut self, pid: u32) -> Option<&mut Sandbox> {
-        if pid as usize >= 256 {
+        if pid as usize >= MAX_PROCESSES {
             return None;
         }
 
@@ -207,7 +318,7 @@ impl SandboxManager {
 
     /// Destroy sandbox for process
     pub fn destroy_sandbox(&mut self, pid: u32) -> Result<(), ()> {
-        if pid as usize >= 256 {
+        if pid as usize >= MAX_PROCESSES {
             return Err(());
         }
 
@@ -215,12 +326,13 @@ impl SandboxManager {
         Ok(())
     }
 
-    /// Check if process has access to resource
+    /// Check if process has access to resource; a process without a
+    /// sandbox is not confined
     pub fn check_access(&self, pid: u32, resource_type: &str, resource_id: &str) -> bool {
         if let Some(sandbox) = self.get_sandbox(pid) {
             sandbox.check_resource_access(resource_type, resource_id)
         } else {
-            false
+            true
         }
     }
 }
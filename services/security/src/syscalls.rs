@@ -9,9 +9,10 @@ pub fn sys_get_uptime_ms() -> u64 {
             let ret: u64;
             core::arch::asm!(
                 "syscall",
-                in("rax") SYS_GET_UPTIME_MS,
-                out("rax") ret,
-                options(nostack, preserves_flags)
+                inlateout("rax") SYS_GET_UPTIME_MS => ret,
+                lateout("rcx") _,
+                lateout("r11") _,
+                options(nostack)
             );
             ret
         }
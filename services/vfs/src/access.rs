@@ -0,0 +1,105 @@
+//! Sandbox check before opening a path
+//!
+//! The security service keeps each sandbox's path rules, and says with
+//! each answer whether the process is sandboxed. A process it has reported
+//! as sandboxed needs an explicit allow: a service that cannot be reached,
+//! times out or answers with anything else refuses its opens. Any other
+//! process is let through when the service fails, so an outage does not
+//! lock out the whole system. The security service stores its own ACLs
+//! through the VFS and may be blocked on us while we ask, so its own
+//! requests are never checked.
+
+use crate::ipc::{
+    ipc_create_port, ipc_destroy_port, ipc_port_owner, ipc_receive_timeout, ipc_send_large,
+    lookup_service, IpcMessage, IPC_MSG_REQUEST, IPC_MSG_RESPONSE,
+};
+
+/// [pid:4][resource_type:1] framed with the path -> [allowed:1][sandboxed:1]
+const SEC_OP_CHECK_ACCESS: u64 = 11;
+/// Resource type the security service uses for paths
+const SEC_RESOURCE_FILE: u8 = 0;
+
+const SECURITY_REPLY_TIMEOUT_MS: u64 = 200;
+
+/// The security service only sandboxes pids below this
+const SANDBOX_PIDS: usize = 256;
+
+/// Security service port from the service registry; 0 until looked up, and
+/// cleared when a request fails so a restarted service is found again
+static mut SECURITY_PORT: u64 = 0;
+
+/// Pids the security service last reported as sandboxed
+static mut KNOWN_SANDBOXED: [u64; SANDBOX_PIDS / 64] = [0; SANDBOX_PIDS / 64];
+
+fn security_port() -> Option<u64> {
+    unsafe {
+        if SECURITY_PORT == 0 {
+            SECURITY_PORT = lookup_service(b"security").ok()?;
+        }
+        Some(SECURITY_PORT)
+    }
+}
+
+fn known_sandboxed(pid: u32) -> bool {
+    let pid = pid as usize;
+    pid < SANDBOX_PIDS && unsafe { KNOWN_SANDBOXED[pid / 64] } & (1 << (pid % 64)) != 0
+}
+
+fn set_known_sandboxed(pid: u32, sandboxed: bool) {
+    let pid = pid as usize;
+    if pid >= SANDBOX_PIDS {
+        return;
+    }
+    unsafe {
+        if sandboxed {
+            KNOWN_SANDBOXED[pid / 64] |= 1 << (pid % 64);
+        } else {
+            KNOWN_SANDBOXED[pid / 64] &= !(1 << (pid % 64));
+        }
+    }
+}
+
+/// Ask the security service about `pid` opening `path`: whether it is
+/// allowed and whether `pid` is sandboxed, or None if it did not answer
+fn check_access(pid: u32, path: &[u8]) -> Option<(bool, bool)> {
+    let security_port = security_port()?;
+    if ipc_port_owner(security_port) == pid {
+        return Some((true, false));
+    }
+    let reply_port = ipc_create_port().ok()?;
+
+    let mut header = IpcMessage::new();
+    header.msg_type = IPC_MSG_REQUEST;
+    header.msg_id = SEC_OP_CHECK_ACCESS;
+    let mut request = [0u8; 5];
+    request[0..4].copy_from_slice(&pid.to_le_bytes());
+    request[4] = SEC_RESOURCE_FILE;
+    header.set_inline_data(&request);
+    header.reply_port = reply_port;
+
+    let mut reply = IpcMessage::new();
+    let result = ipc_send_large(security_port, &header, path)
+        .and_then(|_| ipc_receive_timeout(reply_port, &mut reply, SECURITY_REPLY_TIMEOUT_MS));
+    ipc_destroy_port(reply_port);
+    if result.is_err() {
+        unsafe { SECURITY_PORT = 0; }
+        return None;
+    }
+
+    let answered = reply.msg_type == IPC_MSG_RESPONSE && reply.msg_id == SEC_OP_CHECK_ACCESS
+        && reply.inline_size == 2 && reply.inline_data[0] <= 1;
+    answered.then(|| (reply.inline_data[0] == 1, reply.inline_data[1] != 0))
+}
+
+/// Whether the sandbox of `pid`, if any, lets it open `path`
+pub fn sandbox_allows(pid: u32, path: &[u8]) -> bool {
+    let path_len = path.iter().position(|&b| b == 0).unwrap_or(path.len());
+
+    match check_access(pid, &path[..path_len]) {
+        Some((allowed, sandboxed)) => {
+            set_known_sandboxed(pid, sandboxed);
+            allowed
+        }
+        None => !known_sandboxed(pid),
+    }
+}
@@ -1,15 +1,17 @@
 //! Block device communication for VFS service
 
-use crate::ipc::{IpcMessage, ipc_send, ipc_receive, sys_ipc_send, sys_ipc_receive};
+use crate::ipc::{IpcMessage, ipc_send, ipc_send_large, ipc_receive, ipc_take_payload, sys_ipc_send, sys_ipc_receive};
 
 /// Block device service port (AHCI driver)
 static mut BLOCK_DEV_PORT: u64 = 0;
 
-/// Set block device port
+/// Set block device port. Partition tables read through a previous
+/// driver may be stale, so every disk is scanned again on next use.
 pub fn set_block_device_port(port: u64) {
     unsafe {
         BLOCK_DEV_PORT = port;
     }
+    crate::partition::forget_all();
 }
 
 /// Read blocks from block device
@@ -61,12 +63,8 @@ pub fn read_blocks(port_idx: u8, lba: u64, count: u32, buffer: &mut [u8]) -> Res
             }
         }
         
-        // Extract data from response
-        // Note: For large reads, data would be in response.buffer
-        let copy_len = buffer.len().min(response.inline_size as usize);
-        buffer[0..copy_len].copy_from_slice(&response.inline_data[0..copy_len]);
-        
-        Ok(copy_len)
+        // The data is the whole reply, inline or shared when larger
+        ipc_take_payload(&response, 0, buffer)
     }
 }
 
@@ -87,16 +85,10 @@ pub fn write_blocks(port_idx: u8, lba: u64, count: u32, data: &[u8]) -> Result<(
         request.inline_data[9..13].copy_from_slice(&count.to_le_bytes());
         request.inline_size = 13;
         
-        // For large writes, data would be in request.buffer
-        // For now, copy what fits in inline_data
-        let copy_len = data.len().min(64 - 13);
-        request.inline_data[13..13+copy_len].copy_from_slice(&data[0..copy_len]);
-        request.inline_size = (13 + copy_len) as u32;
-        
-        // Send request with retry logic
+        // Send request and data with retry logic
         let mut retries = 3;
         loop {
-            match ipc_send(BLOCK_DEV_PORT, &request) {
+            match ipc_send_large(BLOCK_DEV_PORT, &request, data) {
                 Ok(_) => break,
                 Err(_) => {
                     retries -= 1;
@@ -133,3 +125,93 @@ pub fn write_blocks(port_idx: u8, lba: u64, count: u32, data: &[u8]) -> Result<(
     }
 }
 
+/// Geometry reported by the block driver
+#[derive(Clone, Copy, Debug)]
+pub struct BlockDeviceInfo {
+    pub sectors: u64,
+    pub sector_size: u32,
+}
+
+/// Ask the driver for a device's sector size and total sectors
+/// (BLOCK_DEV_OP_GET_INFO; reply `[sectors:8][sector_size:4][model_len:1][model]`)
+pub fn get_info(port_idx: u8) -> Result<BlockDeviceInfo, ()> {
+    unsafe {
+        if BLOCK_DEV_PORT == 0 {
+            return Err(());
+        }
+
+        let mut request = IpcMessage::new();
+        request.msg_id = 3; // BLOCK_DEV_OP_GET_INFO
+        request.msg_type = crate::ipc::IPC_MSG_REQUEST;
+        request.inline_data[0] = port_idx;
+        request.inline_size = 1;
+
+        let mut response = IpcMessage::new();
+        exchange(&request, &mut response)?;
+
+        // An empty reply means there is no such device
+        if response.inline_size < 12 {
+            return Err(());
+        }
+        let sectors = u64::from_le_bytes(response.inline_data[0..8].try_into().unwrap());
+        let sector_size = u32::from_le_bytes(response.inline_data[8..12].try_into().unwrap());
+        if sector_size == 0 {
+            return Err(());
+        }
+        Ok(BlockDeviceInfo { sectors, sector_size })
+    }
+}
+
+/// Send a request to the block driver and wait for its reply, retrying
+/// both halves a few times like the read and write paths
+unsafe fn exchange(request: &IpcMessage, response: &mut IpcMessage) -> Result<(), ()> {
+    let mut retries = 3;
+    while ipc_send(BLOCK_DEV_PORT, request).is_err() {
+        retries -= 1;
+        if retries == 0 {
+            return Err(());
+        }
+        crate::syscalls::sys_yield();
+    }
+
+    retries = 3;
+    while ipc_receive(BLOCK_DEV_PORT, response).is_err() {
+        retries -= 1;
+        if retries == 0 {
+            return Err(());
+        }
+        crate::syscalls::sys_yield();
+    }
+    Ok(())
+}
+
+/// Sector-addressed storage a filesystem can live on: a whole disk, or one
+/// partition of it (`partition::Volume`)
+pub trait BlockIo {
+    /// Read `count` sectors starting at `lba`
+    fn read_blocks(&self, lba: u64, count: u32, buffer: &mut [u8]) -> Result<usize, ()>;
+
+    /// Write `count` sectors starting at `lba`
+    fn write_blocks(&self, lba: u64, count: u32, data: &[u8]) -> Result<(), ()>;
+
+    /// Sector size and number of sectors
+    fn info(&self) -> Result<BlockDeviceInfo, ()>;
+}
+
+/// A whole disk behind the block driver, by its port index
+#[derive(Clone, Copy, Debug)]
+pub struct Disk(pub u8);
+
+impl BlockIo for Disk {
+    fn read_blocks(&self, lba: u64, count: u32, buffer: &mut [u8]) -> Result<usize, ()> {
+        read_blocks(self.0, lba, count, buffer)
+    }
+
+    fn write_blocks(&self, lba: u64, count: u32, data: &[u8]) -> Result<(), ()> {
+        write_blocks(self.0, lba, count, data)
+    }
+
+    fn info(&self) -> Result<BlockDeviceInfo, ()> {
+        get_info(self.0)
+    }
+}
@@ -0,0 +1,20 @@
+//! CRC32 checksums for on-disk structures
+
+pub const CRC32_INIT: u32 = 0xFFFF_FFFF;
+
+/// CRC32 (IEEE 802.3, reflected) as used by GPT and the SFS superblock
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(CRC32_INIT, data) ^ CRC32_INIT
+}
+
+/// Feed more data into a running CRC32 (no final XOR applied)
+pub fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    crc
+}
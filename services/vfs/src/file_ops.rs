@@ -14,6 +14,12 @@ pub const O_TRUNC: u32 = 0x0200;
 pub const O_APPEND: u32 = 0x0400;
 pub const O_DIRECTORY: u32 = 0x10000;
 
+/// Mount flags
+pub const MS_RDWR: u32 = 0x01;
+/// Leave access times alone on read (saves a metadata write, and under
+/// CoW a block copy, per read)
+pub const MS_NOATIME: u32 = 0x02;
+
 /// Seek modes
 pub const SEEK_SET: i32 = 0;
 pub const SEEK_CUR: i32 = 1;
@@ -33,6 +39,25 @@ pub enum FileType {
     Socket = 7,
 }
 
+impl FileType {
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => FileType::Regular,
+            2 => FileType::Directory,
+            3 => FileType::Symlink,
+            4 => FileType::CharDevice,
+            5 => FileType::BlockDevice,
+            6 => FileType::Fifo,
+            7 => FileType::Socket,
+            _ => FileType::Unknown,
+        }
+    }
+}
+
+/// Encoded size of a `FileStat`; leaves room for a status byte in an IPC
+/// message's 64 bytes of inline data
+pub const FILE_STAT_SIZE: usize = 62;
+
 /// File stat structure
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -51,6 +76,54 @@ pub struct FileStat {
     pub ctime: u64,  // Creation time
 }
 
+impl FileStat {
+    /// Encode as `[type:1][block_shift:1][mode:2][links:2][uid:4][gid:4]`
+    /// followed by size, blocks, inode, atime, mtime and ctime (8 each),
+    /// little-endian. The block size goes as its log2 and the link count
+    /// saturates at 65535 to fit.
+    pub fn encode(&self, out: &mut [u8]) -> Option<usize> {
+        if out.len() < FILE_STAT_SIZE || !self.block_size.is_power_of_two() {
+            return None;
+        }
+        out[0] = self.file_type as u8;
+        out[1] = self.block_size.trailing_zeros() as u8;
+        out[2..4].copy_from_slice(&self.mode.to_le_bytes());
+        out[4..6].copy_from_slice(&(self.links.min(u16::MAX as u32) as u16).to_le_bytes());
+        out[6..10].copy_from_slice(&self.uid.to_le_bytes());
+        out[10..14].copy_from_slice(&self.gid.to_le_bytes());
+        let wide = [self.size, self.blocks, self.inode, self.atime, self.mtime, self.ctime];
+        for (i, value) in wide.iter().enumerate() {
+            out[14 + i * 8..22 + i * 8].copy_from_slice(&value.to_le_bytes());
+        }
+        Some(FILE_STAT_SIZE)
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < FILE_STAT_SIZE || data[1] >= 32 {
+            return None;
+        }
+        let wide = |i: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&data[14 + i * 8..22 + i * 8]);
+            u64::from_le_bytes(bytes)
+        };
+        Some(FileStat {
+            file_type: FileType::from_u8(data[0]),
+            size: wide(0),
+            blocks: wide(1),
+            block_size: 1 << data[1],
+            inode: wide(2),
+            links: u16::from_le_bytes([data[4], data[5]]) as u32,
+            uid: u32::from_le_bytes([data[6], data[7], data[8], data[9]]),
+            gid: u32::from_le_bytes([data[10], data[11], data[12], data[13]]),
+            mode: u16::from_le_bytes([data[2], data[3]]),
+            atime: wide(3),
+            mtime: wide(4),
+            ctime: wide(5),
+        })
+    }
+}
+
 /// Directory entry
 #[repr(C)]
 pub struct DirEntry {
@@ -73,6 +146,95 @@ impl DirEntry {
     pub fn get_name(&self) -> &[u8] {
         &self.name[0..self.name_len as usize]
     }
+
+    /// Encode as `[type:1][name_len:1][name]`; the inode is left out.
+    /// `name_len` is always the full length, so a name cut short to fit
+    /// `out` shows up as fewer bytes than it claims.
+    pub fn encode(&self, out: &mut [u8]) -> Option<usize> {
+        if out.len() < 2 || self.name_len > u8::MAX as u16 {
+            return None;
+        }
+        let name = self.get_name();
+        let carried = name.len().min(out.len() - 2);
+        out[0] = self.file_type as u8;
+        out[1] = name.len() as u8;
+        out[2..2 + carried].copy_from_slice(&name[..carried]);
+        Some(2 + carried)
+    }
+
+    /// Decode an encoded entry; `None` if the name was cut short
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < 2 || data.len() - 2 < data[1] as usize {
+            return None;
+        }
+        let mut entry = DirEntry::new();
+        entry.file_type = FileType::from_u8(data[0]);
+        entry.name_len = data[1] as u16;
+        entry.name[..data[1] as usize].copy_from_slice(&data[2..2 + data[1] as usize]);
+        Some(entry)
+    }
+}
+
+/// Directory entries packed back to back for one batch readdir reply,
+/// each encoded as in `DirEntry::encode`. An entry is never cut short: one
+/// that does not fit whole is left for the next batch.
+pub struct DirBatch<'a> {
+    out: &'a mut [u8],
+    len: usize,
+    count: u16,
+    /// Most entries the batch takes
+    limit: u16,
+}
+
+impl<'a> DirBatch<'a> {
+    pub fn new(out: &'a mut [u8]) -> Self {
+        Self::with_limit(out, u16::MAX)
+    }
+
+    /// A batch that takes at most `limit` entries, however much room is left
+    pub fn with_limit(out: &'a mut [u8], limit: u16) -> Self {
+        Self { out, len: 0, count: 0, limit }
+    }
+
+    /// Append `entry`, or return false if it does not fit
+    pub fn push(&mut self, entry: &DirEntry) -> bool {
+        let needed = 2 + entry.name_len as usize;
+        if self.count == self.limit || self.out.len() - self.len < needed {
+            return false;
+        }
+        match entry.encode(&mut self.out[self.len..self.len + needed]) {
+            Some(len) => {
+                self.len += len;
+                self.count += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Entries packed so far
+    pub fn count(&self) -> u16 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// The packed entries
+    pub fn bytes(&self) -> &[u8] {
+        &self.out[..self.len]
+    }
+}
+
+/// Entries of a packed batch, stopping at the first one cut short
+pub fn batch_entries(data: &[u8]) -> impl Iterator<Item = DirEntry> + '_ {
+    let mut rest = data;
+    core::iter::from_fn(move || {
+        let entry = DirEntry::decode(rest)?;
+        rest = &rest[2 + entry.name_len as usize..];
+        Some(entry)
+    })
 }
 
 /// VFS Error types
@@ -92,6 +254,8 @@ pub enum VfsError {
     NotSupported,
     InvalidFd,
     TooManyOpenFiles,
+    TooManyLinks,
+    LinkLimit,
 }
 
 impl fmt::Display for VfsError {
@@ -111,6 +275,8 @@ impl fmt::Display for VfsError {
             VfsError::NotSupported => write!(f, "Operation not supported"),
             VfsError::InvalidFd => write!(f, "Invalid file descriptor"),
             VfsError::TooManyOpenFiles => write!(f, "Too many open files"),
+            VfsError::TooManyLinks => write!(f, "Too many levels of symbolic links"),
+            VfsError::LinkLimit => write!(f, "Too many links"),
         }
     }
 }
@@ -155,12 +321,41 @@ pub trait FileSystemOps {
     /// Rename file
     fn rename(&mut self, old_path: &str, new_path: &str) -> VfsResult<()>;
 
+    /// Create a symbolic link at `linkpath` pointing to `target`
+    fn symlink(&mut self, target: &str, linkpath: &str) -> VfsResult<()>;
+
+    /// Create a hard link at `new_path` to the file at `existing_path`
+    fn link(&mut self, existing_path: &str, new_path: &str) -> VfsResult<()>;
+
     /// Open directory for reading
     fn opendir(&mut self, path: &str) -> VfsResult<u64>;
 
     /// Read directory entry
     fn readdir(&mut self, dir_handle: u64) -> VfsResult<Option<DirEntry>>;
 
+    /// Pack entries of a directory just opened with `opendir` into `batch`,
+    /// starting at `cursor` (0 for the first entry; otherwise a cursor this
+    /// returned). Returns the cursor of the first entry left out, or `None`
+    /// once the directory is exhausted. By default the cursor counts
+    /// entries and those before it are skipped with `readdir`.
+    fn readdir_batch(&mut self, dir_handle: u64, cursor: u32, batch: &mut DirBatch) -> VfsResult<Option<u32>> {
+        for _ in 0..cursor {
+            if self.readdir(dir_handle)?.is_none() {
+                return Ok(None);
+            }
+        }
+
+        let mut next = cursor;
+        while let Some(entry) = self.readdir(dir_handle)? {
+            if !batch.push(&entry) {
+                // An entry no batch can hold would stall the caller
+                return if batch.is_empty() { Err(VfsError::NameTooLong) } else { Ok(Some(next)) };
+            }
+            next += 1;
+        }
+        Ok(None)
+    }
+
     /// Close directory
     fn closedir(&mut self, dir_handle: u64) -> VfsResult<()>;
 
@@ -2,10 +2,19 @@
 
 pub mod vfs;
 pub mod block_device;
+pub mod partition;
+pub mod crc32;
 pub mod syscalls;
+pub mod access;
+pub mod file_ops;
+pub mod sfs;
+
+extern crate alloc;
+use alloc::boxed::Box;
 
 pub use crate::ipc::{IpcMessage, IPC_MSG_REQUEST, IPC_MSG_RESPONSE};
-use vfs::{vfs_init, vfs_mount, allocate_fd, free_fd, get_fd_entry, resolve_path, get_mount_fs_id};
+use file_ops::{batch_entries, DirBatch, FileSystemOps, VfsError, MS_RDWR};
+use vfs::{vfs_init, allocate_fd, free_fd, get_fd_entry, resolve_path, get_mount_fs, path_in_mount};
 
 /// VFS service port
 static mut SERVICE_PORT: u64 = 0;
@@ -13,13 +22,37 @@ static mut INITIALIZED: bool = false;
 
 /// VFS operation types
 pub const VFS_OP_OPEN: u64 = 1;
+/// [fd:4][count:4] -> [bytes:4][data], data framed after the header
+/// (`ipc::ipc_reply_large`)
 pub const VFS_OP_READ: u64 = 2;
+/// [fd:4][data] -> [bytes:4], data framed after the fd
+/// (`ipc::ipc_send_large`)
 pub const VFS_OP_WRITE: u64 = 3;
 pub const VFS_OP_CLOSE: u64 = 4;
+/// Status of a path: [path] -> [status:1][stat] (layout in `FileStat::encode`)
 pub const VFS_OP_STAT: u64 = 5;
+/// Next entry of a directory: [cursor:4][path] -> [0][next cursor:4][entry]
+/// (layout in `DirEntry::encode`), or [1] past the last entry. Cursor 0
+/// starts at the first entry; cursors are the same as for
+/// VFS_OP_READDIR_BATCH.
 pub const VFS_OP_READDIR: u64 = 6;
 pub const VFS_OP_MOUNT: u64 = 7;
 pub const VFS_OP_UNMOUNT: u64 = 8;
+/// Entries of a directory, as many as fit in one reply: [cursor:4][path]
+/// -> [0][next cursor:4][count:2][entries] with the entries framed after
+/// the header (layout in `DirBatch`, `ipc::ipc_reply_large`), or [status:1]
+/// on error. Cursor 0 starts at the first entry; a next cursor of 0 means
+/// the last entry was sent.
+pub const VFS_OP_READDIR_BATCH: u64 = 10;
+/// Consistency check of an unmounted device: [device_len:1][device]
+/// -> [status:1][report] (report layout in `sfs::fsck`)
+pub const VFS_OP_FSCK: u64 = 9;
+
+/// Most bytes one read or write moves
+pub const VFS_IO_MAX: usize = 4096;
+
+/// Most bytes of entries one batch readdir reply carries
+pub const VFS_DIRENT_BATCH_MAX: usize = 4096;
 
 /// Initialize VFS IPC
 pub fn init_ipc() -> Result<u64, ()> {
@@ -86,16 +119,31 @@ pub fn init() -> Result<(), ()> {
     }
 }
 
-/// Handle file open request
-pub fn handle_open(request: &IpcMessage) -> IpcMessage {
+/// Handle file open request from process `caller_pid`. The request is the
+/// path, optionally followed by a NUL and the open flags (u32, default
+/// `O_RDONLY`). The reply is the fd, or a status byte: 0xFC no such file,
+/// 0xFD refused by the sandbox, 0xFE no mount covers the path, 0xFF out of fds.
+pub fn handle_open(request: &IpcMessage, caller_pid: u32) -> IpcMessage {
     let mut response = IpcMessage::new();
     response.msg_type = IPC_MSG_RESPONSE;
     response.msg_id = request.msg_id;
     
     // Parse path from request inline data
     if request.inline_size > 0 {
-        let path_len = request.inline_size as usize;
-        let path = &request.inline_data[0..path_len.min(64)];
+        let data = &request.inline_data[..(request.inline_size as usize).min(64)];
+        let path_len = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+        let path = &data[..path_len];
+        let flags = match data.get(path_len + 1..path_len + 5) {
+            Some(flags) => u32::from_le_bytes([flags[0], flags[1], flags[2], flags[3]]),
+            None => file_ops::O_RDONLY,
+        };
+
+        if !access::sandbox_allows(caller_pid, path) {
+            // Refused by the caller's sandbox
+            response.inline_data[0] = 0xFD;
+            response.inline_size = 1;
+            return response;
+        }
         
         // Resolve path to mount point
         if let Some(mount_idx) = resolve_path(path) {
@@ -107,7 +155,7 @@ pub fn handle_open(request: &IpcMessage) -> IpcMessage {
                     fd_entry.fs_id = get_mount_fs_id(mount_idx);
                     fd_entry.file_data = 0; // Will be set by filesystem open
                     fd_entry.position = 0;
-                    fd_entry.flags = 0;
+                    fd_entry.flags = flags as u64;
                     
                     // Call filesystem open function
                     // For SFS, this would call sfs_open()
@@ -132,12 +180,16 @@ pub fn handle_open(request: &IpcMessage) -> IpcMessage {
     response
 }
 
-/// Handle file read request
-pub fn handle_read(request: &IpcMessage) -> IpcMessage {
+/// Handle file read request, replying directly since the data may not
+/// fit inline
+pub fn handle_read(request: &IpcMessage) {
     let mut response = IpcMessage::new();
     response.msg_type = IPC_MSG_RESPONSE;
     response.msg_id = request.msg_id;
     
+    let buffer = [0u8; VFS_IO_MAX];
+    let mut bytes_read = 0;
+    
     // Parse fd and count from request
     if request.inline_size >= 8 {
         let fd = i32::from_le_bytes([
@@ -154,20 +206,18 @@ pub fn handle_read(request: &IpcMessage) -> IpcMessage {
         ]) as usize;
         
         if let Some(fd_entry) = get_fd_entry(fd) {
-            // Call filesystem read function based on fs_id
+            // Call filesystem read function based on fs_id, reading up
+            // to count.min(VFS_IO_MAX) bytes into buffer
             // For SFS (fs_id == 1), call sfs_read()
             // For FAT32 (fs_id == 2), call fat32_read()
-            // For now, read from block device if available
-            use crate::block_device::read_blocks;
-            let mut buffer = [0u8; 4096];
-            let bytes_read = if fd_entry.file_data != 0 {
+            bytes_read = if fd_entry.file_data != 0 {
                 // Filesystem-specific read would go here
                 // For now, return 0 (filesystem not fully integrated)
-                0u32
+                0usize
             } else {
-                0u32
-            };
-            response.inline_data[0..4].copy_from_slice(&bytes_read.to_le_bytes());
+                0usize
+            }.min(count).min(VFS_IO_MAX);
+            response.inline_data[0..4].copy_from_slice(&(bytes_read as u32).to_le_bytes());
             response.inline_size = 4;
         } else {
             // Invalid file descriptor
@@ -176,7 +226,7 @@ pub fn handle_read(request: &IpcMessage) -> IpcMessage {
         }
     }
     
-    response
+    let _ = crate::ipc::ipc_reply_large(request, &response, &buffer[..bytes_read]);
 }
 
 /// Handle file write request
@@ -185,6 +235,11 @@ pub fn handle_write(request: &IpcMessage) -> IpcMessage {
     response.msg_type = IPC_MSG_RESPONSE;
     response.msg_id = request.msg_id;
     
+    // Data follows the fd, inline or shared; taking it also frees a
+    // shared region, so do it before anything can fail
+    let mut buffer = [0u8; VFS_IO_MAX];
+    let data_len = crate::ipc::ipc_take_payload(request, 4, &mut buffer);
+    
     // Parse fd from request
     if request.inline_size >= 4 {
         let fd = i32::from_le_bytes([
@@ -194,24 +249,27 @@ pub fn handle_write(request: &IpcMessage) -> IpcMessage {
             request.inline_data[3],
         ]);
         
-        if let Some(fd_entry) = get_fd_entry(fd) {
-            // Call filesystem write function based on fs_id
-            // For SFS (fs_id == 1), call sfs_write()
-            // For FAT32 (fs_id == 2), call fat32_write()
-            // Data would be in request.buffer
-            let bytes_written = if fd_entry.file_data != 0 {
-                // Filesystem-specific write would go here
-                // For now, return 0 (filesystem not fully integrated)
-                0u32
-            } else {
-                0u32
-            };
-            response.inline_data[0..4].copy_from_slice(&bytes_written.to_le_bytes());
-            response.inline_size = 4;
-        } else {
-            // Invalid file descriptor
-            response.inline_data[0] = 0xFF;
-            response.inline_size = 1;
+        match (get_fd_entry(fd), data_len) {
+            (Some(fd_entry), Ok(_len)) => {
+                // Call filesystem write function based on fs_id with
+                // buffer[.._len]
+                // For SFS (fs_id == 1), call sfs_write()
+                // For FAT32 (fs_id == 2), call fat32_write()
+                let bytes_written = if fd_entry.file_data != 0 {
+                    // Filesystem-specific write would go here
+                    // For now, return 0 (filesystem not fully integrated)
+                    0u32
+                } else {
+                    0u32
+                };
+                response.inline_data[0..4].copy_from_slice(&bytes_written.to_le_bytes());
+                response.inline_size = 4;
+            }
+            _ => {
+                // Invalid file descriptor, or more data than one write takes
+                response.inline_data[0] = 0xFF;
+                response.inline_size = 1;
+            }
         }
     }
     
@@ -250,6 +308,197 @@ pub fn handle_close(request: &IpcMessage) -> IpcMessage {
     response
 }
 
+/// Handle stat request from process `caller_pid`. The reply status is 0
+/// with the stat after it, or 0xFC no such file, 0xFD refused by the
+/// sandbox, 0xFE no mount covers the path, 0xFB the mount's filesystem is
+/// not linked into the service, 0xFF any other error.
+pub fn handle_stat(request: &IpcMessage, caller_pid: u32) -> IpcMessage {
+    let mut response = IpcMessage::new();
+    response.msg_type = IPC_MSG_RESPONSE;
+    response.msg_id = request.msg_id;
+    response.inline_data[0] = 0xFF;  // Error
+    response.inline_size = 1;
+
+    let size = (request.inline_size as usize).min(request.inline_data.len());
+    if size == 0 {
+        return response;
+    }
+    let (fs, path) = match mounted_path(&request.inline_data[0..size], caller_pid) {
+        Ok(found) => found,
+        Err(status) => {
+            response.inline_data[0] = status;
+            return response;
+        }
+    };
+
+    match fs.stat(path) {
+        Ok(stat) => {
+            if let Some(len) = stat.encode(&mut response.inline_data[1..]) {
+                response.inline_data[0] = 0;
+                response.inline_size = 1 + len as u32;
+            }
+        }
+        Err(err) => response.inline_data[0] = error_status(err),
+    }
+    response
+}
+
+/// Handle readdir request from process `caller_pid`: a batch readdir of a
+/// single entry, so the filesystem resumes straight from `cursor` and the
+/// service keeps no per-client state. Error statuses are as for
+/// `handle_stat`.
+pub fn handle_readdir(request: &IpcMessage, caller_pid: u32) -> IpcMessage {
+    let mut response = IpcMessage::new();
+    response.msg_type = IPC_MSG_RESPONSE;
+    response.msg_id = request.msg_id;
+    response.inline_data[0] = 0xFF;  // Error
+    response.inline_size = 1;
+
+    let size = (request.inline_size as usize).min(request.inline_data.len());
+    if size <= 4 {
+        return response;
+    }
+    let cursor = u32::from_le_bytes([
+        request.inline_data[0],
+        request.inline_data[1],
+        request.inline_data[2],
+        request.inline_data[3],
+    ]);
+    let (fs, path) = match mounted_path(&request.inline_data[4..size], caller_pid) {
+        Ok(found) => found,
+        Err(status) => {
+            response.inline_data[0] = status;
+            return response;
+        }
+    };
+
+    let handle = match fs.opendir(path) {
+        Ok(handle) => handle,
+        Err(err) => {
+            response.inline_data[0] = error_status(err);
+            return response;
+        }
+    };
+    let mut buffer = [0u8; 2 + u8::MAX as usize];
+    let mut batch = DirBatch::with_limit(&mut buffer, 1);
+    let next = fs.readdir_batch(handle, cursor, &mut batch);
+    let _ = fs.closedir(handle);
+
+    match (next, batch_entries(batch.bytes()).next()) {
+        (Ok(next), Some(entry)) => {
+            if let Some(len) = entry.encode(&mut response.inline_data[5..]) {
+                // After the last entry, a cursor no entry is at
+                let next = next.unwrap_or(u32::MAX);
+                response.inline_data[0] = 0;
+                response.inline_data[1..5].copy_from_slice(&next.to_le_bytes());
+                response.inline_size = 5 + len as u32;
+            }
+        }
+        // Past the last entry
+        (Ok(_), None) => response.inline_data[0] = 1,
+        (Err(err), _) => response.inline_data[0] = error_status(err),
+    }
+    response
+}
+
+/// Handle batch readdir request from process `caller_pid`; entries may not
+/// fit inline, so the reply is framed here. Error statuses are as for
+/// `handle_stat`.
+pub fn handle_readdir_batch(request: &IpcMessage, caller_pid: u32) {
+    let mut buffer = [0u8; VFS_DIRENT_BATCH_MAX];
+    let mut batch = DirBatch::new(&mut buffer);
+    let response = fill_readdir_batch(request, caller_pid, &mut batch);
+    // A read that failed part way sends none of what it packed
+    let entries = if response.inline_data[0] == 0 { batch.bytes() } else { &[] };
+    let _ = crate::ipc::ipc_reply_large(request, &response, entries);
+}
+
+/// Header of a batch readdir reply, with `batch` filled to go after it
+fn fill_readdir_batch(request: &IpcMessage, caller_pid: u32, batch: &mut DirBatch) -> IpcMessage {
+    let mut response = IpcMessage::new();
+    response.msg_type = IPC_MSG_RESPONSE;
+    response.msg_id = request.msg_id;
+    response.inline_data[0] = 0xFF;  // Error
+    response.inline_size = 1;
+
+    let size = (request.inline_size as usize).min(request.inline_data.len());
+    if size <= 4 {
+        return response;
+    }
+    let cursor = u32::from_le_bytes([
+        request.inline_data[0],
+        request.inline_data[1],
+        request.inline_data[2],
+        request.inline_data[3],
+    ]);
+    let (fs, path) = match mounted_path(&request.inline_data[4..size], caller_pid) {
+        Ok(found) => found,
+        Err(status) => {
+            response.inline_data[0] = status;
+            return response;
+        }
+    };
+
+    let handle = match fs.opendir(path) {
+        Ok(handle) => handle,
+        Err(err) => {
+            response.inline_data[0] = error_status(err);
+            return response;
+        }
+    };
+    let next = fs.readdir_batch(handle, cursor, batch);
+    let _ = fs.closedir(handle);
+
+    match next {
+        Ok(next) => {
+            response.inline_data[0] = 0;
+            response.inline_data[1..5].copy_from_slice(&next.unwrap_or(0).to_le_bytes());
+            response.inline_data[5..7].copy_from_slice(&batch.count().to_le_bytes());
+            response.inline_size = 7;
+        }
+        Err(err) => response.inline_data[0] = error_status(err),
+    }
+    response
+}
+
+/// Filesystem serving `path` for process `caller_pid`, and the path as
+/// the filesystem sees it, or the status byte to reply with
+fn mounted_path(path: &[u8], caller_pid: u32) -> Result<(&'static mut dyn FileSystemOps, &str), u8> {
+    if !access::sandbox_allows(caller_pid, path) {
+        return Err(0xFD);
+    }
+    let mount_idx = resolve_path(path).ok_or(0xFE)?;
+    let fs = get_mount_fs(mount_idx).ok_or(0xFB)?;
+    let path = path_in_mount(mount_idx, path).ok_or(0xFC)?;
+    let path = core::str::from_utf8(path).map_err(|_| 0xFF)?;
+    Ok((fs, path))
+}
+
+/// Reply status for a filesystem error
+fn error_status(err: VfsError) -> u8 {
+    match err {
+        VfsError::NotFound => 0xFC,
+        _ => 0xFF,
+    }
+}
+
+/// Mount `device` at `mountpoint`, first mounting the filesystem on it if
+/// the service has a driver for its type
+fn mount_device(device: &[u8], mountpoint: &[u8], fstype: &[u8]) -> Result<(), ()> {
+    let fs_id = vfs::mount_fs_id(device, fstype).ok_or(())?;
+    let fs: Option<Box<dyn FileSystemOps>> = match fs_id {
+        1 => {
+            // SFS
+            let name = core::str::from_utf8(device).map_err(|_| ())?;
+            let mut sfs = sfs::SfsFileSystem::new();
+            sfs.mount(name, MS_RDWR).map_err(|_| ())?;
+            Some(Box::new(sfs))
+        }
+        _ => None,
+    };
+    vfs::vfs_mount_fs(device, mountpoint, fs_id, fs)
+}
+
 /// Handle mount request
 pub fn handle_mount(request: &IpcMessage) -> IpcMessage {
     let mut response = IpcMessage::new();
@@ -279,8 +528,7 @@ pub fn handle_mount(request: &IpcMessage) -> IpcMessage {
         let fstype = &request.inline_data[offset..offset + fs_len.min(255)];
         
         // Mount filesystem
-        use crate::vfs::vfs_mount;
-        match vfs_mount(device, mountpoint, fstype) {
+        match mount_device(device, mountpoint, fstype) {
             Ok(_) => {
                 response.inline_data[0] = 0;  // Success
                 response.inline_size = 1;
@@ -298,3 +546,69 @@ pub fn handle_mount(request: &IpcMessage) -> IpcMessage {
     response
 }
 
+/// Node name carried by a device event, or None if the payload is malformed
+fn event_node(msg: &IpcMessage) -> Option<&[u8]> {
+    let size = (msg.inline_size as usize).min(msg.inline_data.len());
+    if size < 11 {
+        return None;
+    }
+    let name_len = msg.inline_data[10] as usize;
+    if name_len == 0 || 11 + name_len > size {
+        return None;
+    }
+    Some(&msg.inline_data[11..11 + name_len])
+}
+
+/// Give a USB disk the device manager reports as added a disk letter
+pub fn handle_device_added(msg: &IpcMessage) {
+    let node = match event_node(msg) {
+        Some(node) => node,
+        None => return,
+    };
+    if msg.inline_data[4] == crate::ipc::DEV_TYPE_USB && msg.inline_data[9] == crate::ipc::USB_CLASS_MASS_STORAGE {
+        let _ = partition::attach_disk(node);
+    }
+}
+
+/// Unmount everything on a device the device manager reports as removed,
+/// and give its disk letter back
+pub fn handle_device_removed(msg: &IpcMessage) {
+    if let Some(node) = event_node(msg) {
+        let _ = vfs::vfs_unmount_device(node);
+        let _ = partition::detach_disk(node);
+    }
+}
+
+/// Handle fsck request. The reply status is 0 with the report after it,
+/// 0xFA if the device is mounted, or 0xFF if it cannot be checked.
+pub fn handle_fsck(request: &IpcMessage) -> IpcMessage {
+    let mut response = IpcMessage::new();
+    response.msg_type = IPC_MSG_RESPONSE;
+    response.msg_id = request.msg_id;
+    response.inline_data[0] = 0xFF;  // Error
+    response.inline_size = 1;
+
+    let size = (request.inline_size as usize).min(request.inline_data.len());
+    let dev_len = request.inline_data[0] as usize;
+    if size < 1 || dev_len == 0 || 1 + dev_len > size {
+        return response;
+    }
+    let device = &request.inline_data[1..1 + dev_len];
+
+    // The check reads the filesystem directly and would race a mount
+    if vfs::vfs_device_mounted(device) {
+        response.inline_data[0] = 0xFA;
+        return response;
+    }
+
+    let report = core::str::from_utf8(device)
+        .map_err(|_| VfsError::InvalidArgument)
+        .and_then(sfs::SfsFileSystem::fsck_device);
+    if let Ok(report) = report {
+        if let Some(len) = report.encode(&mut response.inline_data[1..]) {
+            response.inline_data[0] = 0;
+            response.inline_size = 1 + len as u32;
+        }
+    }
+    response
+}
@@ -11,9 +11,11 @@ mod lib;
 mod block_device;
 
 use core::panic::PanicInfo;
-use lib::{init_ipc, init, handle_open, handle_read, handle_write, handle_close, handle_mount, 
-          VFS_OP_OPEN, VFS_OP_READ, VFS_OP_WRITE, VFS_OP_CLOSE, VFS_OP_MOUNT};
-use ipc::{IpcMessage, sys_ipc_receive, sys_ipc_send};
+use lib::{init_ipc, init, handle_open, handle_read, handle_write, handle_close, handle_stat, handle_readdir,
+          handle_readdir_batch, handle_mount, handle_fsck,
+          VFS_OP_OPEN, VFS_OP_READ, VFS_OP_WRITE, VFS_OP_CLOSE, VFS_OP_STAT, VFS_OP_READDIR, VFS_OP_MOUNT,
+          VFS_OP_FSCK, VFS_OP_READDIR_BATCH};
+use ipc::{IpcMessage, sys_ipc_receive, sys_ipc_reply};
 use block_device::{set_block_device_port, read_blocks, write_blocks};
 
 /// Panic handler for the VFS service
@@ -33,38 +35,60 @@ pub extern "C" fn _start() -> ! {
 fn vfs_init() {
     // Initialize IPC
     if let Ok(port) = init_ipc() {
-        // Register with device manager
-        // Send registration message to device manager
-        use crate::ipc::{IpcMessage, IPC_MSG_REQUEST};
-        let mut reg_msg = IpcMessage::new();
-        reg_msg.msg_type = IPC_MSG_REQUEST;
-        reg_msg.msg_id = 200; // SERVICE_REGISTER
-        reg_msg.inline_data[0] = 1; // ServiceType::BlockDevice
-        reg_msg.inline_data[1..9].copy_from_slice(&port.to_le_bytes());
-        reg_msg.inline_size = 9;
-        
-        // Get device manager port (would be discovered via service discovery)
-        // For now, we'll wait for device manager to notify us
+        // Clients find the VFS by name through the service registry.
         // Block device port will be set when driver registers
+        let _ = ipc::register_service_name(b"vfs", port);
+
+        // Filesystems on unplugged devices are unmounted
+        let _ = ipc::subscribe_device_events(port);
         
         // Initialize VFS
         let _ = init();
     }
+
+    ipc::notify_init_ready(b"vfs");
 }
 
 /// Main service loop - handles file system requests via IPC
 fn vfs_loop() {
     let mut msg = IpcMessage::new();
+    let port = init_ipc().unwrap_or(0);
     
     loop {
         // Receive IPC message
-        if sys_ipc_receive(2, &mut msg) == 0 {
+        if sys_ipc_receive(port, &mut msg) == 0 {
+            // Who is asking, before any other receive replaces it
+            let caller_pid = ipc::ipc_sender_pid();
+
+            // Device manager events expect no reply
+            if msg.msg_type == ipc::IPC_MSG_NOTIFICATION {
+                match msg.msg_id {
+                    ipc::DEV_MGR_EVENT_DEVICE_ADDED => lib::handle_device_added(&msg),
+                    ipc::DEV_MGR_EVENT_DEVICE_REMOVED => lib::handle_device_removed(&msg),
+                    _ => {}
+                }
+                continue;
+            }
+
             let response = match msg.msg_id {
-                VFS_OP_OPEN => handle_open(&msg),
-                VFS_OP_READ => handle_read(&msg),
+                VFS_OP_OPEN => handle_open(&msg, caller_pid),
+                VFS_OP_READ => {
+                    // Read data may not fit inline, so the handler frames
+                    // its own reply
+                    handle_read(&msg);
+                    continue;
+                }
                 VFS_OP_WRITE => handle_write(&msg),
                 VFS_OP_CLOSE => handle_close(&msg),
+                VFS_OP_STAT => handle_stat(&msg, caller_pid),
+                VFS_OP_READDIR => handle_readdir(&msg, caller_pid),
+                VFS_OP_READDIR_BATCH => {
+                    // Entries may not fit inline either
+                    handle_readdir_batch(&msg, caller_pid);
+                    continue;
+                }
                 VFS_OP_MOUNT => handle_mount(&msg),
+                VFS_OP_FSCK => handle_fsck(&msg),
                 _ => {
                     // Unknown operation
                     let mut resp = IpcMessage::new();
@@ -76,10 +100,8 @@ fn vfs_loop() {
                 }
             };
 
-            // Send response back to sender on their reply port (using sender_tid as a proxy)
-            // In a fuller implementation, we would resolve sender_tid to a reply port.
-            let reply_port = msg.sender_tid;
-            let _ = sys_ipc_send(reply_port, &response);
+            // Send response back on the sender's reply port
+            let _ = sys_ipc_reply(&msg, &response);
         }
     }
 }
@@ -0,0 +1,533 @@
+//! Partition table parsing for VFS service
+//!
+//! Reads the MBR (LBA 0) and GPT (LBA 1 + entry array) of a block device and
+//! exposes every partition as a logical block device with an LBA offset, so a
+//! filesystem can be mounted on `/dev/sda1` style handles. A disk's table is
+//! read the first time one of its partitions is opened. A USB disk is given
+//! a letter when the device manager reports it attached, so its removal
+//! event (which names `/dev/usbN`) can be traced back to `/dev/sdX`.
+
+use crate::block_device::{BlockDeviceInfo, BlockIo, Disk};
+use crate::crc32::{crc32, crc32_update, CRC32_INIT};
+
+/// Logical sector size assumed for partition tables
+pub const SECTOR_SIZE: usize = 512;
+
+/// Maximum number of partitions tracked across all devices
+const MAX_PARTITIONS: usize = 64;
+
+/// Maximum number of GPT entries we are willing to parse per device; larger
+/// entry arrays are refused rather than read in part
+pub const MAX_GPT_ENTRIES: u32 = 128;
+
+/// MBR boot signature (bytes 510..512)
+const MBR_SIGNATURE: u16 = 0xAA55;
+const MBR_TABLE_OFFSET: usize = 0x1BE;
+const MBR_ENTRY_SIZE: usize = 16;
+
+/// MBR partition type bytes
+pub const MBR_TYPE_EMPTY: u8 = 0x00;
+pub const MBR_TYPE_FAT32_CHS: u8 = 0x0B;
+pub const MBR_TYPE_FAT32_LBA: u8 = 0x0C;
+pub const MBR_TYPE_EXTENDED: u8 = 0x05;
+pub const MBR_TYPE_NTFS: u8 = 0x07;
+pub const MBR_TYPE_EXTENDED_LBA: u8 = 0x0F;
+pub const MBR_TYPE_LINUX: u8 = 0x83;
+pub const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+
+/// GPT header signature ("EFI PART")
+const GPT_SIGNATURE: [u8; 8] = *b"EFI PART";
+const GPT_MIN_HEADER_SIZE: u32 = 92;
+const GPT_MIN_ENTRY_SIZE: u32 = 128;
+
+/// GPT partition type GUIDs (on-disk mixed-endian byte order)
+pub const GPT_TYPE_EFI_SYSTEM: [u8; 16] = [
+    0x28, 0x73, 0x2A, 0xC1, 0x1F, 0xF8, 0xD2, 0x11,
+    0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B,
+];
+pub const GPT_TYPE_BASIC_DATA: [u8; 16] = [
+    0xA2, 0xA0, 0xD0, 0xEB, 0xE5, 0xB9, 0x33, 0x44,
+    0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7,
+];
+pub const GPT_TYPE_LINUX_FS: [u8; 16] = [
+    0xAF, 0x3D, 0xC6, 0x0F, 0x83, 0x84, 0x72, 0x47,
+    0x8E, 0x79, 0x3D, 0x69, 0xD8, 0x47, 0x7D, 0xE4,
+];
+
+/// Partition table scheme
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionScheme {
+    Mbr,
+    Gpt,
+}
+
+/// Partition type as recorded in the partition table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionType {
+    /// MBR system ID byte
+    Mbr(u8),
+    /// GPT partition type GUID
+    Gpt([u8; 16]),
+}
+
+/// A partition exposed as a logical block device
+#[derive(Debug, Clone, Copy)]
+pub struct Partition {
+    /// Block device port index of the underlying disk
+    pub port_idx: u8,
+    /// 1-based partition number (`/dev/sda1` -> 1)
+    pub number: u8,
+    /// First LBA of the partition on the underlying disk
+    pub start_lba: u64,
+    /// Number of sectors in the partition
+    pub sector_count: u64,
+    /// Partition type byte/GUID
+    pub part_type: PartitionType,
+    /// Scheme the partition was discovered through
+    pub scheme: PartitionScheme,
+}
+
+impl Partition {
+    /// Read sectors relative to the start of the partition
+    pub fn read_blocks(&self, lba: u64, count: u32, buffer: &mut [u8]) -> Result<usize, ()> {
+        let disk_lba = self.translate(lba, count)?;
+        Disk(self.port_idx).read_blocks(disk_lba, count, buffer)
+    }
+
+    /// Write sectors relative to the start of the partition
+    pub fn write_blocks(&self, lba: u64, count: u32, data: &[u8]) -> Result<(), ()> {
+        let disk_lba = self.translate(lba, count)?;
+        Disk(self.port_idx).write_blocks(disk_lba, count, data)
+    }
+
+    /// Translate a partition-relative LBA to a disk LBA, rejecting accesses
+    /// that would run past the end of the partition
+    pub fn translate(&self, lba: u64, count: u32) -> Result<u64, ()> {
+        let end = lba.checked_add(count as u64).ok_or(())?;
+        if end > self.sector_count {
+            return Err(());
+        }
+        self.start_lba.checked_add(lba).ok_or(())
+    }
+
+    /// Filesystem type name the VFS should try for this partition, if known
+    pub fn fs_type_hint(&self) -> Option<&'static [u8]> {
+        match self.part_type {
+            PartitionType::Mbr(MBR_TYPE_FAT32_CHS) | PartitionType::Mbr(MBR_TYPE_FAT32_LBA) => Some(b"fat32"),
+            PartitionType::Mbr(MBR_TYPE_NTFS) => Some(b"ntfs"),
+            PartitionType::Mbr(MBR_TYPE_LINUX) => Some(b"ext4"),
+            PartitionType::Gpt(guid) if guid == GPT_TYPE_EFI_SYSTEM => Some(b"fat32"),
+            PartitionType::Gpt(guid) if guid == GPT_TYPE_BASIC_DATA => Some(b"ntfs"),
+            PartitionType::Gpt(guid) if guid == GPT_TYPE_LINUX_FS => Some(b"ext4"),
+            _ => None,
+        }
+    }
+}
+
+impl BlockIo for Partition {
+    fn read_blocks(&self, lba: u64, count: u32, buffer: &mut [u8]) -> Result<usize, ()> {
+        Partition::read_blocks(self, lba, count, buffer)
+    }
+
+    fn write_blocks(&self, lba: u64, count: u32, data: &[u8]) -> Result<(), ()> {
+        Partition::write_blocks(self, lba, count, data)
+    }
+
+    fn info(&self) -> Result<BlockDeviceInfo, ()> {
+        let disk = Disk(self.port_idx).info()?;
+        Ok(BlockDeviceInfo { sectors: self.sector_count, sector_size: disk.sector_size })
+    }
+}
+
+/// What a device name refers to: a whole disk (`/dev/sda`) or one of its
+/// partitions (`/dev/sda1`)
+#[derive(Debug, Clone, Copy)]
+pub enum Volume {
+    Disk(Disk),
+    Partition(Partition),
+}
+
+impl BlockIo for Volume {
+    fn read_blocks(&self, lba: u64, count: u32, buffer: &mut [u8]) -> Result<usize, ()> {
+        match self {
+            Volume::Disk(disk) => disk.read_blocks(lba, count, buffer),
+            Volume::Partition(part) => part.read_blocks(lba, count, buffer),
+        }
+    }
+
+    fn write_blocks(&self, lba: u64, count: u32, data: &[u8]) -> Result<(), ()> {
+        match self {
+            Volume::Disk(disk) => disk.write_blocks(lba, count, data),
+            Volume::Partition(part) => part.write_blocks(lba, count, data),
+        }
+    }
+
+    fn info(&self) -> Result<BlockDeviceInfo, ()> {
+        match self {
+            Volume::Disk(disk) => disk.info(),
+            Volume::Partition(part) => part.info(),
+        }
+    }
+}
+
+/// Resolve a `/dev/sdX` or `/dev/sdXN` name to the sectors it covers
+pub fn open_volume(device: &[u8]) -> Option<Volume> {
+    let (port_idx, number) = parse_device_name(device)?;
+    if number == 0 {
+        return Some(Volume::Disk(Disk(port_idx)));
+    }
+    find_partition(device).map(Volume::Partition)
+}
+
+pub const EMPTY_PARTITION: Partition = Partition {
+    port_idx: 0,
+    number: 0,
+    start_lba: 0,
+    sector_count: 0,
+    part_type: PartitionType::Mbr(MBR_TYPE_EMPTY),
+    scheme: PartitionScheme::Mbr,
+};
+
+static mut PARTITIONS: [Partition; MAX_PARTITIONS] = [EMPTY_PARTITION; MAX_PARTITIONS];
+static mut PARTITION_COUNT: usize = 0;
+/// Disks (bit per port index) whose table has been read since they last
+/// changed
+static mut SCANNED_DISKS: u32 = 0;
+
+/// Disk letters, `/dev/sda` to `/dev/sdz`
+pub const MAX_DISKS: usize = 26;
+
+/// Longest device manager node name (`/dev/usb3`), as in its events
+pub const MAX_NODE_NAME: usize = 16;
+
+/// Device manager node each removable disk was attached as, by port index.
+/// Unused entries are empty.
+static mut DISK_NODES: [[u8; MAX_NODE_NAME]; MAX_DISKS] = [[0; MAX_NODE_NAME]; MAX_DISKS];
+
+/// Scan the partition table of a block device and register its partitions.
+/// Any partitions previously registered for the device are replaced.
+/// Returns the number of partitions found.
+pub fn scan_partitions(port_idx: u8) -> Result<usize, ()> {
+    register_partitions(&Disk(port_idx), port_idx)
+}
+
+/// Read the partition table of `disk` and register its partitions under
+/// port index `port_idx`, as `scan_partitions` does for a driver's disk
+pub fn register_partitions<D: BlockIo + ?Sized>(disk: &D, port_idx: u8) -> Result<usize, ()> {
+    forget_partitions(port_idx);
+
+    let mut found = [EMPTY_PARTITION; MAX_GPT_ENTRIES as usize];
+    let count = read_partition_table(disk, port_idx, &mut found)?;
+    for part in &found[..count] {
+        register_partition(*part)?;
+    }
+
+    unsafe {
+        SCANNED_DISKS |= disk_bit(port_idx);
+    }
+    Ok(count)
+}
+
+/// Parse the partition table of `disk` (port index `port_idx`) into
+/// `found`, returning how many partitions it holds. Nothing is registered.
+pub fn read_partition_table<D: BlockIo + ?Sized>(disk: &D, port_idx: u8, found: &mut [Partition]) -> Result<usize, ()> {
+    let mut mbr = [0u8; SECTOR_SIZE];
+    disk.read_blocks(0, 1, &mut mbr)?;
+
+    let mbr_valid = u16::from_le_bytes([mbr[510], mbr[511]]) == MBR_SIGNATURE;
+    let protective = mbr_valid && (0..4).any(|i| mbr_entry_type(&mbr, i) == MBR_TYPE_GPT_PROTECTIVE);
+
+    // GPT takes precedence when the protective MBR is present and the header
+    // and entry array both pass their CRC checks
+    if protective {
+        if let Ok(count) = parse_gpt(disk, port_idx, found) {
+            return Ok(count);
+        }
+    }
+
+    if !mbr_valid {
+        return Err(());
+    }
+
+    parse_mbr(port_idx, &mbr, found)
+}
+
+/// Parse the four primary MBR entries
+fn parse_mbr(port_idx: u8, mbr: &[u8; SECTOR_SIZE], out: &mut [Partition]) -> Result<usize, ()> {
+    let mut found = 0;
+
+    for i in 0..4 {
+        let offset = MBR_TABLE_OFFSET + i * MBR_ENTRY_SIZE;
+        let part_type = mbr[offset + 4];
+        let start_lba = read_u32(mbr, offset + 8) as u64;
+        let sector_count = read_u32(mbr, offset + 12) as u64;
+
+        // Extended partitions (logical volumes) are not followed
+        if part_type == MBR_TYPE_EMPTY
+            || part_type == MBR_TYPE_GPT_PROTECTIVE
+            || part_type == MBR_TYPE_EXTENDED
+            || part_type == MBR_TYPE_EXTENDED_LBA
+            || sector_count == 0
+        {
+            continue;
+        }
+
+        *out.get_mut(found).ok_or(())? = Partition {
+            port_idx,
+            number: (i + 1) as u8,
+            start_lba,
+            sector_count,
+            part_type: PartitionType::Mbr(part_type),
+            scheme: PartitionScheme::Mbr,
+        };
+        found += 1;
+    }
+
+    Ok(found)
+}
+
+/// Parse and validate the primary GPT header and its entry array
+fn parse_gpt<D: BlockIo + ?Sized>(disk: &D, port_idx: u8, out: &mut [Partition]) -> Result<usize, ()> {
+    let mut header = [0u8; SECTOR_SIZE];
+    disk.read_blocks(1, 1, &mut header)?;
+
+    if header[0..8] != GPT_SIGNATURE {
+        return Err(());
+    }
+
+    let header_size = read_u32(&header, 12);
+    if header_size < GPT_MIN_HEADER_SIZE || header_size as usize > SECTOR_SIZE {
+        return Err(());
+    }
+
+    // Header CRC is computed with the CRC field itself zeroed
+    let stored_header_crc = read_u32(&header, 16);
+    let mut check = header;
+    check[16..20].copy_from_slice(&[0; 4]);
+    if crc32(&check[..header_size as usize]) != stored_header_crc {
+        return Err(());
+    }
+
+    let entries_lba = read_u64(&header, 72);
+    let num_entries = read_u32(&header, 80);
+    let entry_size = read_u32(&header, 84);
+    let stored_entries_crc = read_u32(&header, 88);
+
+    // The array CRC covers every entry, so a table too big to read whole
+    // cannot be trusted in part
+    if entry_size < GPT_MIN_ENTRY_SIZE
+        || entry_size as usize > SECTOR_SIZE
+        || SECTOR_SIZE % entry_size as usize != 0
+        || num_entries > MAX_GPT_ENTRIES
+    {
+        return Err(());
+    }
+
+    // Entries only count once the array CRC has been verified
+    let mut staged_count = 0;
+
+    let entries_per_sector = SECTOR_SIZE / entry_size as usize;
+    let total_bytes = num_entries as usize * entry_size as usize;
+    let sectors = (total_bytes + SECTOR_SIZE - 1) / SECTOR_SIZE;
+
+    let mut crc = CRC32_INIT;
+    let mut entry_index = 0u32;
+    let mut sector = [0u8; SECTOR_SIZE];
+
+    for s in 0..sectors {
+        disk.read_blocks(entries_lba.checked_add(s as u64).ok_or(())?, 1, &mut sector)?;
+
+        let remaining = total_bytes - s * SECTOR_SIZE;
+        crc = crc32_update(crc, &sector[..remaining.min(SECTOR_SIZE)]);
+
+        for e in 0..entries_per_sector {
+            if entry_index >= num_entries {
+                break;
+            }
+            entry_index += 1;
+
+            let entry = &sector[e * entry_size as usize..(e + 1) * entry_size as usize];
+            let mut type_guid = [0u8; 16];
+            type_guid.copy_from_slice(&entry[0..16]);
+            if type_guid == [0u8; 16] {
+                continue; // Unused entry
+            }
+
+            let first_lba = read_u64(entry, 32);
+            let last_lba = read_u64(entry, 40);
+            if last_lba < first_lba {
+                continue;
+            }
+
+            *out.get_mut(staged_count).ok_or(())? = Partition {
+                port_idx,
+                number: entry_index as u8,
+                start_lba: first_lba,
+                sector_count: last_lba - first_lba + 1,
+                part_type: PartitionType::Gpt(type_guid),
+                scheme: PartitionScheme::Gpt,
+            };
+            staged_count += 1;
+        }
+    }
+
+    if crc ^ CRC32_INIT != stored_entries_crc {
+        return Err(());
+    }
+
+    Ok(staged_count)
+}
+
+/// Add a partition to the global table
+fn register_partition(part: Partition) -> Result<(), ()> {
+    unsafe {
+        if PARTITION_COUNT >= MAX_PARTITIONS {
+            return Err(());
+        }
+        PARTITIONS[PARTITION_COUNT] = part;
+        PARTITION_COUNT += 1;
+        Ok(())
+    }
+}
+
+/// Drop all partitions registered for a block device
+pub fn forget_partitions(port_idx: u8) {
+    unsafe {
+        SCANNED_DISKS &= !disk_bit(port_idx);
+        let mut i = 0;
+        while i < PARTITION_COUNT {
+            if PARTITIONS[i].port_idx == port_idx {
+                PARTITIONS[i] = PARTITIONS[PARTITION_COUNT - 1];
+                PARTITION_COUNT -= 1;
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Drop every registered partition, so each disk is scanned again
+pub fn forget_all() {
+    unsafe {
+        PARTITION_COUNT = 0;
+        SCANNED_DISKS = 0;
+    }
+}
+
+/// Give the disk the device manager attached as `node` (e.g. `/dev/usb3`)
+/// the lowest letter that is neither attached nor already read, returning
+/// its port index. A node attached twice keeps its letter.
+pub fn attach_disk(node: &[u8]) -> Option<u8> {
+    if node.is_empty() || node.len() > MAX_NODE_NAME {
+        return None;
+    }
+    if let Some(port_idx) = attached_disk(node) {
+        return Some(port_idx);
+    }
+
+    unsafe {
+        let port_idx = (0..MAX_DISKS as u8)
+            .find(|&i| DISK_NODES[i as usize][0] == 0 && SCANNED_DISKS & disk_bit(i) == 0)?;
+        DISK_NODES[port_idx as usize][..node.len()].copy_from_slice(node);
+        Some(port_idx)
+    }
+}
+
+/// Release the disk attached as `node`, forgetting its partitions.
+/// Returns the port index it had.
+pub fn detach_disk(node: &[u8]) -> Option<u8> {
+    let port_idx = attached_disk(node)?;
+    unsafe {
+        DISK_NODES[port_idx as usize] = [0; MAX_NODE_NAME];
+    }
+    forget_partitions(port_idx);
+    Some(port_idx)
+}
+
+/// Port index of the disk attached as device manager node `node`
+pub fn attached_disk(node: &[u8]) -> Option<u8> {
+    if node.is_empty() || node.len() > MAX_NODE_NAME {
+        return None;
+    }
+    unsafe {
+        DISK_NODES.iter().position(|name| {
+            name.starts_with(node) && name[node.len()..].iter().all(|&b| b == 0)
+        }).map(|i| i as u8)
+    }
+}
+
+/// Port index of the disk behind `node`: either a whole-disk name
+/// (`/dev/sdb`) or the device manager node it was attached as
+pub fn disk_on_node(node: &[u8]) -> Option<u8> {
+    match parse_device_name(node) {
+        Some((port_idx, 0)) => Some(port_idx),
+        Some(_) => None,
+        None => attached_disk(node),
+    }
+}
+
+/// Bit of `SCANNED_DISKS` for a port index; disks past the mask are
+/// scanned on every lookup
+fn disk_bit(port_idx: u8) -> u32 {
+    1u32.checked_shl(port_idx as u32).unwrap_or(0)
+}
+
+/// Look up a partition by port index and partition number
+pub fn get_partition(port_idx: u8, number: u8) -> Option<Partition> {
+    unsafe {
+        PARTITIONS[..PARTITION_COUNT]
+            .iter()
+            .find(|p| p.port_idx == port_idx && p.number == number)
+            .copied()
+    }
+}
+
+/// Look up a partition by device name (e.g. `/dev/sda1`), reading the
+/// disk's partition table if it has not been read yet
+pub fn find_partition(device: &[u8]) -> Option<Partition> {
+    let (port_idx, number) = parse_device_name(device)?;
+    if number == 0 {
+        return None; // Whole-disk handle, not a partition
+    }
+    if unsafe { SCANNED_DISKS } & disk_bit(port_idx) == 0 {
+        scan_partitions(port_idx).ok()?;
+    }
+    get_partition(port_idx, number)
+}
+
+/// Parse a `/dev/sdXN` device name into (port index, partition number).
+/// A partition number of 0 refers to the whole disk.
+pub fn parse_device_name(device: &[u8]) -> Option<(u8, u8)> {
+    let name = device.strip_prefix(b"/dev/sd")?;
+    let (&letter, digits) = name.split_first()?;
+    if !letter.is_ascii_lowercase() {
+        return None;
+    }
+
+    let mut number: u32 = 0;
+    for &d in digits {
+        if !d.is_ascii_digit() {
+            return None;
+        }
+        number = number * 10 + (d - b'0') as u32;
+        if number > u8::MAX as u32 {
+            return None;
+        }
+    }
+
+    Some((letter - b'a', number as u8))
+}
+
+fn mbr_entry_type(mbr: &[u8; SECTOR_SIZE], index: usize) -> u8 {
+    mbr[MBR_TABLE_OFFSET + index * MBR_ENTRY_SIZE + 4]
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]])
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&buf[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
@@ -0,0 +1,159 @@
+//! SFS block allocation bitmap
+//!
+//! One bit per filesystem block, set while the block is in use. The bitmap
+//! lives in the blocks just past the inode bitmap; like it, it is loaded
+//! whole on mount and the blocks that changed are written back on sync.
+//! The superblock, inode table and both bitmaps sit below the first data
+//! block and are always in use.
+//!
+//! Allocation is next-fit: the search starts just past the last block
+//! handed out, so a file written a block at a time gets consecutive blocks
+//! and can keep growing its extent, and freed blocks are reused once the
+//! search wraps around.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use super::inode_bitmap::bitmap_blocks;
+
+/// In-memory copy of the block bitmap
+pub struct BlockBitmap {
+    bits: Vec<u8>,
+    total_blocks: u64,
+    /// First block that can be allocated
+    data_start: u64,
+    block_size: usize,
+    /// Blocks changed since the last `take_dirty`
+    dirty: Vec<bool>,
+    /// Where the next search starts
+    cursor: u64,
+}
+
+impl BlockBitmap {
+    /// Bitmap of a freshly formatted filesystem: only the metadata blocks
+    /// are in use
+    pub fn new(total_blocks: u64, data_start: u64, block_size: usize) -> Self {
+        let blocks = bitmap_blocks(total_blocks, block_size) as usize;
+        let mut bitmap = Self {
+            bits: vec![0; blocks * block_size],
+            total_blocks,
+            data_start,
+            block_size,
+            dirty: vec![true; blocks],
+            cursor: data_start,
+        };
+        bitmap.reserve(0..(blocks * block_size) as u64 * 8);
+        bitmap
+    }
+
+    /// Blocks the bitmap occupies on disk
+    pub fn blocks(&self) -> u64 {
+        self.dirty.len() as u64
+    }
+
+    /// Replace block `index` with its on-disk contents. Allocation carries
+    /// on past the last block in use, as it would have before unmount.
+    pub fn load_block(&mut self, index: u64, data: &[u8]) {
+        let start = index as usize * self.block_size;
+        let len = self.block_size.min(data.len());
+        self.bits[start..start + len].copy_from_slice(&data[..len]);
+        self.dirty[index as usize] = false;
+
+        let first = start as u64 * 8;
+        let end = (start + self.block_size) as u64 * 8;
+        let data = first.max(self.data_start)..end.min(self.total_blocks);
+        if let Some(last) = data.rev().find(|&block| self.is_used(block)) {
+            self.cursor = self.cursor.max(last + 1);
+        }
+        // A bitmap from a damaged disk must still never hand out metadata
+        self.reserve(first..end);
+    }
+
+    /// Contents of block `index` as it should be written
+    pub fn block(&self, index: u64) -> &[u8] {
+        let start = index as usize * self.block_size;
+        &self.bits[start..start + self.block_size]
+    }
+
+    /// Indexes of the blocks changed since the last call, marking them clean
+    pub fn take_dirty(&mut self) -> Vec<u64> {
+        let mut changed = Vec::new();
+        for (index, dirty) in self.dirty.iter_mut().enumerate() {
+            if *dirty {
+                changed.push(index as u64);
+                *dirty = false;
+            }
+        }
+        changed
+    }
+
+    /// Whether `block` is in use; blocks past the filesystem always are
+    pub fn is_used(&self, block: u64) -> bool {
+        block >= self.total_blocks || self.bits[(block / 8) as usize] & (1 << (block % 8)) != 0
+    }
+
+    /// Take the next free block after the last one handed out, wrapping
+    /// around to the first data block; `None` if every one is in use
+    pub fn alloc(&mut self) -> Option<u64> {
+        let cursor = self.cursor.clamp(self.data_start, self.total_blocks);
+        let block = (cursor..self.total_blocks)
+            .chain(self.data_start..cursor)
+            .find(|&block| !self.is_used(block))?;
+        self.take(block);
+        Some(block)
+    }
+
+    /// Take `block` itself, e.g. to extend an extent; false if it is in use
+    pub fn alloc_at(&mut self, block: u64) -> bool {
+        if block < self.data_start || self.is_used(block) {
+            return false;
+        }
+        self.take(block);
+        true
+    }
+
+    /// Return `block` to the free pool; false if it was not in use or
+    /// holds metadata
+    pub fn free(&mut self, block: u64) -> bool {
+        if block < self.data_start || block >= self.total_blocks || !self.is_used(block) {
+            return false;
+        }
+        self.set(block, false);
+        true
+    }
+
+    /// Blocks not in use
+    pub fn free_count(&self) -> u64 {
+        // Every bit past the last block is set
+        self.bits.iter().map(|bits| bits.count_zeros() as u64).sum()
+    }
+
+    fn take(&mut self, block: u64) {
+        self.set(block, true);
+        self.cursor = block + 1;
+    }
+
+    fn set(&mut self, block: u64, used: bool) {
+        let byte = (block / 8) as usize;
+        let bits = if used {
+            self.bits[byte] | (1 << (block % 8))
+        } else {
+            self.bits[byte] & !(1 << (block % 8))
+        };
+        if bits != self.bits[byte] {
+            self.bits[byte] = bits;
+            self.dirty[byte / self.block_size] = true;
+        }
+    }
+
+    /// Mark the metadata blocks among `bits` used, and those past the last
+    /// block too, so scanning never runs off the end of the filesystem
+    fn reserve(&mut self, bits: Range<u64>) {
+        for block in bits {
+            if block < self.data_start || block >= self.total_blocks {
+                self.set(block, true);
+            }
+        }
+    }
+}
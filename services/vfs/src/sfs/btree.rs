@@ -1,6 +1,7 @@
 //! B-Tree for directory entries and extent trees
 
 use crate::file_ops::{VfsResult, VfsError};
+use alloc::vec::Vec;
 
 /// B-Tree node
 pub struct BTreeNode {
@@ -1,13 +1,17 @@
 //! Block cache for SFS
+//!
+//! Write-back LRU cache keyed by block number. The cache never talks to the
+//! block device itself: evicted dirty blocks and flushes are handed back to
+//! the caller, which owns the device I/O path.
 
 use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
 
-const CACHE_SIZE: usize = 1024; // Cache 1024 blocks (4MB)
+/// Default capacity in blocks (1024 x 4KB = 4MB)
+pub const DEFAULT_CACHE_CAPACITY: usize = 1024;
 
 /// Cached block
 struct CachedBlock {
-    block_num: u64,
     data: Vec<u8>,
     dirty: bool,
     access_time: u64,
@@ -17,14 +21,55 @@ struct CachedBlock {
 pub struct BlockCache {
     cache: BTreeMap<u64, CachedBlock>,
     access_counter: u64,
+    capacity: usize,
 }
 
 impl BlockCache {
     pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CACHE_CAPACITY)
+    }
+
+    /// Create a cache holding at most `capacity` blocks (minimum 1)
+    pub fn with_capacity(capacity: usize) -> Self {
         Self {
             cache: BTreeMap::new(),
             access_counter: 0,
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Maximum number of cached blocks
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of blocks currently cached
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Is the cache empty?
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// Is the block currently cached?
+    pub fn contains(&self, block_num: u64) -> bool {
+        self.cache.contains_key(&block_num)
+    }
+
+    /// Change the capacity, evicting LRU blocks if the cache is now too big.
+    /// Returns dirty blocks that were evicted and must be written back.
+    pub fn set_capacity(&mut self, capacity: usize) -> Vec<(u64, Vec<u8>)> {
+        self.capacity = capacity.max(1);
+
+        let mut evicted = Vec::new();
+        while self.cache.len() > self.capacity {
+            if let Some(victim) = self.evict_lru() {
+                evicted.push(victim);
+            }
         }
+        evicted
     }
 
     /// Get block from cache
@@ -39,23 +84,34 @@ impl BlockCache {
         }
     }
 
-    /// Put block in cache
-    pub fn put(&mut self, block_num: u64, data: Vec<u8>, dirty: bool) {
+    /// Put block in cache, replacing any cached copy. A block that is already
+    /// dirty stays dirty even if the new copy is clean.
+    /// Returns a dirty block evicted to make room, which the caller must write back.
+    pub fn put(&mut self, block_num: u64, data: Vec<u8>, dirty: bool) -> Option<(u64, Vec<u8>)> {
         self.access_counter += 1;
 
-        // Evict if cache is full
-        if self.cache.len() >= CACHE_SIZE {
-            self.evict_lru();
+        if let Some(block) = self.cache.get_mut(&block_num) {
+            block.data = data;
+            block.dirty |= dirty;
+            block.access_time = self.access_counter;
+            return None;
         }
 
+        // Evict if cache is full
+        let evicted = if self.cache.len() >= self.capacity {
+            self.evict_lru()
+        } else {
+            None
+        };
+
         let block = CachedBlock {
-            block_num,
             data,
             dirty,
             access_time: self.access_counter,
         };
 
         self.cache.insert(block_num, block);
+        evicted
     }
 
     /// Mark block as dirty
@@ -65,42 +121,52 @@ impl BlockCache {
         }
     }
 
-    /// Evict least recently used block
-    fn evict_lru(&mut self) {
-        if let Some((&block_num, block)) = self
+    /// Drop a block from the cache without writing it back.
+    /// Used when a block is freed or reallocated so stale contents can never
+    /// be returned for the new owner.
+    pub fn invalidate(&mut self, block_num: u64) {
+        self.cache.remove(&block_num);
+    }
+
+    /// Drop every cached block without writing anything back
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Evict least recently used block, returning it if it was dirty
+    fn evict_lru(&mut self) -> Option<(u64, Vec<u8>)> {
+        let block_num = self
             .cache
             .iter()
             .min_by_key(|(_, b)| b.access_time)
-        {
-            // Write back if dirty
-            if block.dirty {
-                // Write to disk via block device
-                use crate::block_device::write_blocks;
-                let _ = write_blocks(0, block_num, 1, &block.data);
-            }
-            self.cache.remove(&block_num);
+            .map(|(&num, _)| num)?;
+
+        let block = self.cache.remove(&block_num)?;
+        if block.dirty {
+            Some((block_num, block.data))
+        } else {
+            None
         }
     }
 
-    /// Flush all dirty blocks
-    pub fn flush_all(&mut self) {
-        // Write back all dirty blocks
-        use crate::block_device::write_blocks;
-        let mut blocks_to_flush = Vec::new();
-        
-        // Collect dirty blocks
-        for (block_num, block) in self.cache.iter() {
-            if block.dirty {
-                blocks_to_flush.push((*block_num, block.data.clone()));
-            }
-        }
-        
-        // Write dirty blocks to disk
-        for (block_num, data) in blocks_to_flush {
-            let _ = write_blocks(0, block_num, 1, &data);
-            if let Some(block) = self.cache.get_mut(&block_num) {
-                block.dirty = false;
-            }
+    /// Block numbers of all dirty blocks, in ascending order
+    pub fn dirty_blocks(&self) -> Vec<u64> {
+        self.cache
+            .iter()
+            .filter(|(_, b)| b.dirty)
+            .map(|(&num, _)| num)
+            .collect()
+    }
+
+    /// Contents of a cached block without touching its LRU position
+    pub fn peek(&self, block_num: u64) -> Option<&[u8]> {
+        self.cache.get(&block_num).map(|b| b.data.as_slice())
+    }
+
+    /// Mark a block clean after it has been written back
+    pub fn mark_clean(&mut self, block_num: u64) {
+        if let Some(block) = self.cache.get_mut(&block_num) {
+            block.dirty = false;
         }
     }
 }
@@ -25,6 +25,11 @@ impl CowManager {
         self.refcounts.get(&block).map(|&count| count > 1).unwrap_or(false)
     }
 
+    /// Reference count of a block, if it is tracked
+    pub fn refcount(&self, block: u64) -> Option<u32> {
+        self.refcounts.get(&block).copied()
+    }
+
     /// Increment block reference count
     pub fn inc_refcount(&mut self, block: u64) {
         let count = self.refcounts.entry(block).or_insert(0);
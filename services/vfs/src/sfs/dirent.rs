@@ -0,0 +1,81 @@
+//! SFS directory entries
+//!
+//! Directory blocks hold fixed-size entries: `[inode:4][name:64]`, the
+//! name NUL padded. An inode number of 0 marks a free slot.
+
+/// Size of one directory entry
+pub const DIRENT_SIZE: usize = 68;
+
+/// Longest name a directory entry can hold
+pub const DIRENT_NAME_LEN: usize = DIRENT_SIZE - 4;
+
+/// Inode number of a directory slot, 0 if it is free
+pub fn entry_inode(block: &[u8], slot: usize) -> u64 {
+    let offset = slot * DIRENT_SIZE;
+    u32::from_le_bytes([block[offset], block[offset + 1], block[offset + 2], block[offset + 3]]) as u64
+}
+
+/// Name stored in a directory slot
+pub fn entry_name(block: &[u8], slot: usize) -> &[u8] {
+    let name = &block[slot * DIRENT_SIZE + 4..(slot + 1) * DIRENT_SIZE];
+    let len = name.iter().position(|&c| c == 0).unwrap_or(DIRENT_NAME_LEN);
+    &name[..len]
+}
+
+/// Slot holding `name`, with its inode number
+pub fn find_entry(block: &[u8], name: &[u8]) -> Option<(usize, u64)> {
+    (0..block.len() / DIRENT_SIZE)
+        .map(|slot| (slot, entry_inode(block, slot)))
+        .find(|&(slot, inode)| inode != 0 && entry_name(block, slot) == name)
+}
+
+/// First free slot in a directory block
+pub fn find_free_slot(block: &[u8]) -> Option<usize> {
+    (0..block.len() / DIRENT_SIZE).find(|&slot| entry_inode(block, slot) == 0)
+}
+
+/// Fill a slot; the name must already be valid (see `valid_name`)
+pub fn write_entry(block: &mut [u8], slot: usize, inode: u64, name: &[u8]) {
+    let offset = slot * DIRENT_SIZE;
+    block[offset..offset + 4].copy_from_slice(&(inode as u32).to_le_bytes());
+    let name_field = &mut block[offset + 4..offset + DIRENT_SIZE];
+    name_field.fill(0);
+    name_field[..name.len()].copy_from_slice(name);
+}
+
+pub fn clear_entry(block: &mut [u8], slot: usize) {
+    block[slot * DIRENT_SIZE..(slot + 1) * DIRENT_SIZE].fill(0);
+}
+
+/// Whether a block holds entries other than "." and ".."
+pub fn has_children(block: &[u8]) -> bool {
+    (0..block.len() / DIRENT_SIZE).any(|slot| {
+        entry_inode(block, slot) != 0 && !matches!(entry_name(block, slot), b"." | b"..")
+    })
+}
+
+/// Whether `name` can be linked into a directory
+pub fn valid_name(name: &[u8]) -> bool {
+    !name.is_empty()
+        && name.len() <= DIRENT_NAME_LEN
+        && name != b"."
+        && name != b".."
+        && !name.contains(&b'/')
+        && !name.contains(&0)
+}
+
+/// Split a path into its parent directory and final component;
+/// `None` for the root (or an empty path)
+pub fn split_parent(path: &str) -> Option<(&str, &str)> {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() {
+        return None;
+    }
+    match trimmed.rfind('/') {
+        Some(idx) => {
+            let parent = trimmed[..idx].trim_end_matches('/');
+            Some((if parent.is_empty() { "/" } else { parent }, &trimmed[idx + 1..]))
+        }
+        None => Some(("/", trimmed)),
+    }
+}
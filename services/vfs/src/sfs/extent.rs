@@ -0,0 +1,53 @@
+//! SFS file extents
+//!
+//! A file's data is a single extent: logical block `i` lives in physical
+//! block `extent_root + i` for the `blocks` blocks the inode records. Blocks
+//! past the extent are holes, which read as zeros until written.
+
+use core::ops::Range;
+
+/// The blocks backing a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extent {
+    /// First physical block, or 0 for a file with no blocks
+    pub start: u64,
+    /// Blocks in the extent
+    pub len: u64,
+}
+
+impl Extent {
+    /// Extent of an inode with `extent_root` and `blocks`
+    pub fn new(extent_root: u64, blocks: u64) -> Self {
+        if extent_root == 0 {
+            Self { start: 0, len: 0 }
+        } else {
+            Self { start: extent_root, len: blocks }
+        }
+    }
+
+    /// Physical block holding logical block `index`, or `None` for a hole
+    pub fn lookup(&self, index: u64) -> Option<u64> {
+        if index < self.len {
+            Some(self.start + index)
+        } else {
+            None
+        }
+    }
+
+    /// Cut the extent down to what a file of `size` bytes needs, returning
+    /// the physical blocks that no longer belong to it. Growing leaves the
+    /// extent alone; the new tail is a hole.
+    pub fn truncate(&mut self, size: u64, block_size: u64) -> Range<u64> {
+        let keep = size.div_ceil(block_size);
+        if keep >= self.len {
+            return 0..0;
+        }
+
+        let freed = self.start + keep..self.start + self.len;
+        self.len = keep;
+        if keep == 0 {
+            self.start = 0;
+        }
+        freed
+    }
+}
@@ -0,0 +1,148 @@
+//! SFS consistency check
+//!
+//! The check claims the blocks of every in-use inode's extents and
+//! compares the result against the block bitmap and the CoW refcounts.
+//! Problems are counted, not repaired.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Encoded size of a report
+pub const FSCK_REPORT_SIZE: usize = 48;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FsckReport {
+    /// In-use inodes walked
+    pub inodes_checked: u64,
+    /// Blocks owned by at least one inode
+    pub blocks_in_use: u64,
+    /// Allocated data blocks no inode owns
+    pub leaked_blocks: u64,
+    /// Extents reaching into metadata, past the filesystem or into free blocks
+    pub dangling_extents: u64,
+    /// Blocks owned by more than one inode without being CoW-shared
+    pub doubly_owned_blocks: u64,
+    /// Blocks whose CoW refcount disagrees with the owners found
+    pub refcount_mismatches: u64,
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        self.leaked_blocks == 0
+            && self.dangling_extents == 0
+            && self.doubly_owned_blocks == 0
+            && self.refcount_mismatches == 0
+    }
+
+    /// Write the report into `out` as little-endian fields in declaration order
+    pub fn encode(&self, out: &mut [u8]) -> Option<usize> {
+        if out.len() < FSCK_REPORT_SIZE {
+            return None;
+        }
+        for (i, value) in self.fields().iter().enumerate() {
+            out[i * 8..i * 8 + 8].copy_from_slice(&value.to_le_bytes());
+        }
+        Some(FSCK_REPORT_SIZE)
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < FSCK_REPORT_SIZE {
+            return None;
+        }
+        let field = |i: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&data[i * 8..i * 8 + 8]);
+            u64::from_le_bytes(bytes)
+        };
+        Some(FsckReport {
+            inodes_checked: field(0),
+            blocks_in_use: field(1),
+            leaked_blocks: field(2),
+            dangling_extents: field(3),
+            doubly_owned_blocks: field(4),
+            refcount_mismatches: field(5),
+        })
+    }
+
+    fn fields(&self) -> [u64; 6] {
+        [
+            self.inodes_checked,
+            self.blocks_in_use,
+            self.leaked_blocks,
+            self.dangling_extents,
+            self.doubly_owned_blocks,
+            self.refcount_mismatches,
+        ]
+    }
+}
+
+/// Owners of every block in the filesystem, filled in as inodes are walked
+pub struct BlockMap {
+    owners: Vec<u16>,
+    /// Blocks the block bitmap has in use
+    allocated: Vec<bool>,
+    /// First block after the superblock, inode table and bitmaps
+    data_start: u64,
+    report: FsckReport,
+}
+
+impl BlockMap {
+    /// `allocated` says whether the block bitmap has a block in use
+    pub fn new(total_blocks: u64, data_start: u64, allocated: impl Fn(u64) -> bool) -> Self {
+        BlockMap {
+            owners: vec![0; total_blocks as usize],
+            allocated: (0..total_blocks).map(allocated).collect(),
+            data_start,
+            report: FsckReport::default(),
+        }
+    }
+
+    /// Record an in-use inode and the blocks its extent covers. An extent
+    /// that is not wholly inside allocated data blocks is dangling and
+    /// claims nothing.
+    pub fn claim(&mut self, blocks: Range<u64>) {
+        self.report.inodes_checked += 1;
+        if blocks.is_empty() {
+            return;
+        }
+        let allocated = blocks.clone().all(|block| self.allocated.get(block as usize) == Some(&true));
+        if blocks.start < self.data_start || !allocated {
+            self.report.dangling_extents += 1;
+            return;
+        }
+        for block in blocks {
+            let owners = &mut self.owners[block as usize];
+            *owners = owners.saturating_add(1);
+        }
+    }
+
+    /// Tally the allocated data blocks; `refcount` gives the CoW refcount
+    /// of a block, `None` where it is not tracked (e.g. after a remount)
+    pub fn finish(mut self, refcount: impl Fn(u64) -> Option<u32>) -> FsckReport {
+        for block in self.data_start..self.owners.len() as u64 {
+            if !self.allocated[block as usize] {
+                continue;
+            }
+            let owners = self.owners[block as usize] as u32;
+            let counted = refcount(block);
+
+            match owners {
+                0 => self.report.leaked_blocks += 1,
+                1 => self.report.blocks_in_use += 1,
+                _ => {
+                    self.report.blocks_in_use += 1;
+                    // Sharing is only legitimate when CoW knows about it
+                    if counted.is_none_or(|count| count < owners) {
+                        self.report.doubly_owned_blocks += 1;
+                    }
+                }
+            }
+
+            if counted.is_some_and(|count| count != owners) {
+                self.report.refcount_mismatches += 1;
+            }
+        }
+        self.report
+    }
+}
@@ -0,0 +1,88 @@
+//! SFS open-file table
+//!
+//! `open` hands out handles from this table rather than raw inode numbers,
+//! so the flags a file was opened with follow it to `read` and `write`.
+
+use alloc::collections::BTreeMap;
+
+use crate::file_ops::{O_APPEND, O_RDWR, O_WRONLY};
+
+/// An open file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenFile {
+    pub inode: u64,
+    pub flags: u32,
+}
+
+impl OpenFile {
+    pub fn can_read(&self) -> bool {
+        self.flags & O_WRONLY == 0
+    }
+
+    pub fn can_write(&self) -> bool {
+        self.flags & (O_WRONLY | O_RDWR) != 0
+    }
+
+    /// Where a write asked for at `offset` lands in a file of `size` bytes;
+    /// with `O_APPEND` every write goes to the end
+    pub fn write_offset(&self, offset: u64, size: u64) -> u64 {
+        if self.flags & O_APPEND != 0 {
+            size
+        } else {
+            offset
+        }
+    }
+}
+
+/// Open files by handle
+pub struct OpenFileTable {
+    files: BTreeMap<u64, OpenFile>,
+    next_handle: u64,
+}
+
+impl OpenFileTable {
+    pub const fn new() -> Self {
+        OpenFileTable { files: BTreeMap::new(), next_handle: 1 }
+    }
+
+    /// Record an open file and return its handle (never 0)
+    pub fn insert(&mut self, inode: u64, flags: u32) -> u64 {
+        while self.next_handle == 0 || self.files.contains_key(&self.next_handle) {
+            self.next_handle = self.next_handle.wrapping_add(1);
+        }
+        let handle = self.next_handle;
+        self.next_handle = self.next_handle.wrapping_add(1);
+        self.files.insert(handle, OpenFile { inode, flags });
+        handle
+    }
+
+    pub fn get(&self, handle: u64) -> Option<OpenFile> {
+        self.files.get(&handle).copied()
+    }
+
+    pub fn remove(&mut self, handle: u64) -> Option<OpenFile> {
+        self.files.remove(&handle)
+    }
+
+    /// Whether any handle refers to `inode`
+    pub fn is_open(&self, inode: u64) -> bool {
+        self.files.values().any(|file| file.inode == inode)
+    }
+
+    /// Inodes of the open files, once per handle
+    pub fn inodes(&self) -> impl Iterator<Item = u64> + '_ {
+        self.files.values().map(|file| file.inode)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.files.clear();
+    }
+}
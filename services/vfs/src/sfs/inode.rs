@@ -45,7 +45,7 @@ pub struct Inode {
     /// Modification time
     pub mtime: u64,
 
-    /// Creation time
+    /// Status change time (contents or metadata)
     pub ctime: u64,
 
     /// Generation number (for CoW)
@@ -108,4 +108,53 @@ impl Inode {
     pub fn is_symlink(&self) -> bool {
         self.file_type == InodeType::Symlink
     }
+
+    /// Stamp a new inode: atime, mtime and ctime all start at `now`.
+    ///
+    /// After that:
+    /// - reads set atime, unless mounted with `MS_NOATIME`
+    /// - writes and truncation set mtime and ctime
+    /// - linking, unlinking and renaming set ctime
+    /// - adding or removing a directory entry sets the directory's mtime
+    ///   and ctime
+    pub fn touch_created(&mut self, now: u64) {
+        self.atime = now;
+        self.mtime = now;
+        self.ctime = now;
+    }
+
+    /// Contents were read
+    pub fn touch_access(&mut self, now: u64) {
+        self.atime = now;
+    }
+
+    /// Contents changed, which is also a status change
+    pub fn touch_modify(&mut self, now: u64) {
+        self.mtime = now;
+        self.ctime = now;
+    }
+
+    /// Metadata changed (links, name) but not the contents
+    pub fn touch_change(&mut self, now: u64) {
+        self.ctime = now;
+    }
+
+    /// Count one more directory entry naming this inode; false if the
+    /// link count is already at its limit
+    pub fn add_link(&mut self) -> bool {
+        match self.links.checked_add(1) {
+            Some(links) => {
+                self.links = links;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Count one directory entry fewer; true once no entry names the
+    /// inode and it can be freed
+    pub fn drop_link(&mut self) -> bool {
+        self.links = self.links.saturating_sub(1);
+        self.links == 0
+    }
 }
@@ -0,0 +1,134 @@
+//! SFS inode allocation bitmap
+//!
+//! One bit per inode, set while the inode is in use. The bitmap lives in
+//! the blocks just past the inode table; it is loaded whole on mount and
+//! the blocks that changed are written back on sync. Inode 0 means "no
+//! inode" and inode 1 is the root, so both are always in use.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Root directory inode
+pub const ROOT_INODE: u64 = 1;
+
+/// Inodes that can never be allocated or freed
+const RESERVED_INODES: u64 = ROOT_INODE + 1;
+
+/// Blocks of `block_size` bytes needed for a bitmap of `total_inodes`
+pub fn bitmap_blocks(total_inodes: u64, block_size: usize) -> u64 {
+    total_inodes.div_ceil(block_size as u64 * 8)
+}
+
+/// In-memory copy of the inode bitmap
+pub struct InodeBitmap {
+    bits: Vec<u8>,
+    total_inodes: u64,
+    block_size: usize,
+    /// Blocks changed since the last `take_dirty`
+    dirty: Vec<bool>,
+}
+
+impl InodeBitmap {
+    /// Bitmap of a freshly formatted filesystem: only the reserved inodes
+    /// are in use
+    pub fn new(total_inodes: u64, block_size: usize) -> Self {
+        let blocks = bitmap_blocks(total_inodes, block_size) as usize;
+        let mut bitmap = Self {
+            bits: vec![0; blocks * block_size],
+            total_inodes,
+            block_size,
+            dirty: vec![true; blocks],
+        };
+        bitmap.reserve();
+        bitmap
+    }
+
+    /// Blocks the bitmap occupies on disk
+    pub fn blocks(&self) -> u64 {
+        self.dirty.len() as u64
+    }
+
+    /// Replace block `index` with its on-disk contents
+    pub fn load_block(&mut self, index: u64, data: &[u8]) {
+        let start = index as usize * self.block_size;
+        let len = self.block_size.min(data.len());
+        self.bits[start..start + len].copy_from_slice(&data[..len]);
+        self.dirty[index as usize] = false;
+        // A bitmap from a damaged disk must still never hand out the root
+        self.reserve();
+    }
+
+    /// Contents of block `index` as it should be written
+    pub fn block(&self, index: u64) -> &[u8] {
+        let start = index as usize * self.block_size;
+        &self.bits[start..start + self.block_size]
+    }
+
+    /// Indexes of the blocks changed since the last call, marking them clean
+    pub fn take_dirty(&mut self) -> Vec<u64> {
+        let mut changed = Vec::new();
+        for (index, dirty) in self.dirty.iter_mut().enumerate() {
+            if *dirty {
+                changed.push(index as u64);
+                *dirty = false;
+            }
+        }
+        changed
+    }
+
+    /// Whether `inode` is in use
+    pub fn is_used(&self, inode: u64) -> bool {
+        inode < self.total_inodes && self.bits[(inode / 8) as usize] & (1 << (inode % 8)) != 0
+    }
+
+    /// Take the lowest free inode, or `None` if every one is in use
+    pub fn alloc(&mut self) -> Option<u64> {
+        let byte = self.bits.iter().position(|&bits| bits != 0xFF)?;
+        let inode = byte as u64 * 8 + self.bits[byte].trailing_ones() as u64;
+        if inode >= self.total_inodes {
+            return None;
+        }
+        self.set(inode, true);
+        Some(inode)
+    }
+
+    /// Return `inode` to the free pool; false if it was not in use or is
+    /// one that can never be freed
+    pub fn free(&mut self, inode: u64) -> bool {
+        if inode < RESERVED_INODES || !self.is_used(inode) {
+            return false;
+        }
+        self.set(inode, false);
+        true
+    }
+
+    /// Inodes not in use
+    pub fn free_count(&self) -> u64 {
+        // Every bit past the last inode is set
+        self.bits.iter().map(|bits| bits.count_zeros() as u64).sum()
+    }
+
+    fn set(&mut self, inode: u64, used: bool) {
+        let byte = (inode / 8) as usize;
+        let bits = if used {
+            self.bits[byte] | (1 << (inode % 8))
+        } else {
+            self.bits[byte] & !(1 << (inode % 8))
+        };
+        if bits != self.bits[byte] {
+            self.bits[byte] = bits;
+            self.dirty[byte / self.block_size] = true;
+        }
+    }
+
+    /// Mark the reserved inodes used and the bits past the last inode too,
+    /// so scanning never runs off the end of the inode table
+    fn reserve(&mut self) {
+        for inode in 0..RESERVED_INODES.min(self.total_inodes) {
+            self.set(inode, true);
+        }
+        for inode in self.total_inodes..self.bits.len() as u64 * 8 {
+            self.set(inode, true);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! SFS metadata journal
+//!
+//! Metadata blocks (superblock, inode table, directories) changed since the
+//! last commit are held in a `Transaction` and never written in place
+//! until the transaction is committed. A commit is written to the journal
+//! region between the filesystem and the backup superblock as:
+//!
+//! - descriptor: `[magic:8][sequence:8][count:4][0:4][home block:8]*count`
+//! - `count` block images, in descriptor order
+//! - commit: `[magic:8][sequence:8][crc32:4]`, the CRC covering the
+//!   descriptor and every image
+//!
+//! The images are then checkpointed to their home blocks and the
+//! descriptor is cleared. Mount replays a journal whose commit block is
+//! present and matches; anything else is an unfinished transaction and is
+//! discarded, leaving the previous metadata intact. File data is flushed
+//! before the commit (ordered mode) and is not journaled.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use super::BLOCK_SIZE;
+use crate::crc32::{crc32_update, CRC32_INIT};
+
+/// Journal size given to new filesystems
+pub const JOURNAL_BLOCKS: u64 = 64;
+
+pub const DESCRIPTOR_MAGIC: u64 = 0x314C_4E52_4A53_4653; // "SFSJRNL1"
+pub const COMMIT_MAGIC: u64 = 0x3154_4D43_4A53_4653; // "SFSJCMT1"
+
+const DESCRIPTOR_HEADER_SIZE: usize = 24;
+
+/// Most home blocks one descriptor can list
+pub const DESCRIPTOR_CAPACITY: usize = (BLOCK_SIZE - DESCRIPTOR_HEADER_SIZE) / 8;
+
+/// Most blocks a transaction in a journal of `journal_blocks` can hold
+/// (one block goes to the descriptor and one to the commit record)
+pub fn transaction_capacity(journal_blocks: u64) -> usize {
+    (journal_blocks.saturating_sub(2) as usize).min(DESCRIPTOR_CAPACITY)
+}
+
+/// Metadata blocks changed since the last commit
+pub struct Transaction {
+    blocks: BTreeMap<u64, Vec<u8>>,
+}
+
+impl Transaction {
+    pub const fn new() -> Self {
+        Transaction { blocks: BTreeMap::new() }
+    }
+
+    pub fn get(&self, block: u64) -> Option<&[u8]> {
+        self.blocks.get(&block).map(|data| data.as_slice())
+    }
+
+    pub fn contains(&self, block: u64) -> bool {
+        self.blocks.contains_key(&block)
+    }
+
+    /// Record the new contents of `block`, replacing an earlier change
+    pub fn put(&mut self, block: u64, data: &[u8]) {
+        self.blocks.insert(block, data[..BLOCK_SIZE].to_vec());
+    }
+
+    /// Forget a block (it was freed, so its contents no longer matter)
+    pub fn remove(&mut self, block: u64) {
+        self.blocks.remove(&block);
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Home blocks and their new contents, in block order
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &[u8])> + '_ {
+        self.blocks.iter().map(|(&block, data)| (block, data.as_slice()))
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+    }
+}
+
+/// Fill a descriptor block; `homes` must fit `DESCRIPTOR_CAPACITY`
+pub fn encode_descriptor(sequence: u64, homes: &[u64], out: &mut [u8]) {
+    out[..BLOCK_SIZE].fill(0);
+    out[0..8].copy_from_slice(&DESCRIPTOR_MAGIC.to_le_bytes());
+    out[8..16].copy_from_slice(&sequence.to_le_bytes());
+    out[16..20].copy_from_slice(&(homes.len() as u32).to_le_bytes());
+    for (i, home) in homes.iter().enumerate() {
+        let offset = DESCRIPTOR_HEADER_SIZE + i * 8;
+        out[offset..offset + 8].copy_from_slice(&home.to_le_bytes());
+    }
+}
+
+/// Sequence number and image count of a descriptor block, if it is one
+pub fn decode_descriptor(block: &[u8]) -> Option<(u64, usize)> {
+    if read_u64(block, 0) != DESCRIPTOR_MAGIC {
+        return None;
+    }
+    let count = u32::from_le_bytes([block[16], block[17], block[18], block[19]]) as usize;
+    if count == 0 || count > DESCRIPTOR_CAPACITY {
+        return None;
+    }
+    Some((read_u64(block, 8), count))
+}
+
+/// Home block of image `index` listed in a descriptor
+pub fn descriptor_home(block: &[u8], index: usize) -> u64 {
+    read_u64(block, DESCRIPTOR_HEADER_SIZE + index * 8)
+}
+
+pub fn encode_commit(sequence: u64, checksum: u32, out: &mut [u8]) {
+    out[..BLOCK_SIZE].fill(0);
+    out[0..8].copy_from_slice(&COMMIT_MAGIC.to_le_bytes());
+    out[8..16].copy_from_slice(&sequence.to_le_bytes());
+    out[16..20].copy_from_slice(&checksum.to_le_bytes());
+}
+
+/// Whether `block` commits transaction `sequence` with contents `checksum`
+pub fn commit_matches(block: &[u8], sequence: u64, checksum: u32) -> bool {
+    read_u64(block, 0) == COMMIT_MAGIC
+        && read_u64(block, 8) == sequence
+        && u32::from_le_bytes([block[16], block[17], block[18], block[19]]) == checksum
+}
+
+/// Running checksum over the descriptor and images of a transaction;
+/// start from `CRC32_INIT` and finish with `finish_checksum`
+pub fn update_checksum(crc: u32, block: &[u8]) -> u32 {
+    crc32_update(crc, &block[..BLOCK_SIZE])
+}
+
+pub fn finish_checksum(crc: u32) -> u32 {
+    crc ^ CRC32_INIT
+}
+
+fn read_u64(block: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&block[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
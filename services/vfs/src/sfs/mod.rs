@@ -9,40 +9,44 @@ pub mod btree;
 pub mod cow;
 pub mod snapshot;
 pub mod cache;
+pub mod dirent;
+pub mod fsck;
+pub mod journal;
+pub mod symlink;
+pub mod path;
+pub mod handle;
+pub mod extent;
+pub mod inode_bitmap;
+pub mod block_bitmap;
 
 extern crate alloc;
+use alloc::boxed::Box;
 use alloc::vec::Vec;
 use alloc::string::String;
-use core::convert::TryInto;
+use core::cell::RefCell;
 
 use crate::file_ops::*;
+use crate::crc32::CRC32_INIT;
+use crate::block_device::BlockIo;
+use crate::partition::open_volume;
 use superblock::*;
 use inode::*;
 use cow::*;
 use snapshot::*;
+use cache::{BlockCache, DEFAULT_CACHE_CAPACITY};
+use dirent::*;
+use fsck::{BlockMap, FsckReport};
+use journal::*;
+use symlink::*;
+use path::normalize_path;
+use handle::{OpenFile, OpenFileTable};
+use extent::Extent;
+use inode_bitmap::{bitmap_blocks, InodeBitmap, ROOT_INODE};
+use block_bitmap::BlockBitmap;
 
 // Syscall constants (copied from ipc.rs for convenience)
-const SYS_IPC_SEND: u64 = 9;
-const SYS_IPC_RECEIVE: u64 = 10;
 const SYS_GET_UPTIME_MS: u64 = 47;
 
-// Device Manager IPC constants
-const DRIVER_MANAGER_PORT: u32 = 100; // From services/driver_manager/src/main.rs
-const DM_MSG_OPEN_DEVICE: u32 = 6;    // Arbitrary new message ID for opening device
-
-// IPC message structure (must match kernel/include/ipc/ipc.h)
-#[repr(C)]
-#[derive(Clone, Copy)]
-struct IpcMessage {
-    sender_tid: u64,
-    msg_id: u64,
-    msg_type: u32,
-    inline_size: u32,
-    inline_data: [u8; 64],
-    buffer: u64,
-    buffer_size: u64,
-}
-
 // Syscall raw (copied from ipc.rs for convenience)
 #[cfg(target_arch = "x86_64")]
 unsafe fn syscall_raw(num: u64, arg1: u64, arg2: u64, arg3: u64, arg4: u64, arg5: u64) -> u64 {
@@ -66,47 +70,6 @@ unsafe fn syscall_raw(_num: u64, _arg1: u64, _arg2: u64, _arg3: u64, _arg4: u64,
     0
 }
 
-// Helper to send IPC messages and get response
-fn send_ipc_request(target_port: u64, msg_id: u64, inline_data: &[u8]) -> Result<IpcMessage, ()> {
-    let mut msg = IpcMessage {
-        sender_tid: 0, // Filled by kernel
-        msg_id,
-        msg_type: 1, // Request
-        inline_size: inline_data.len() as u32,
-        inline_data: [0; 64],
-        buffer: 0,
-        buffer_size: 0,
-    };
-    msg.inline_data[0..inline_data.len()].copy_from_slice(inline_data);
-
-    unsafe {
-        let _ = syscall_raw(SYS_IPC_SEND, target_port, &msg as *const _ as u64, 0, 0, 0);
-        let mut response = IpcMessage {
-            sender_tid: 0, msg_id: 0, msg_type: 0, inline_size: 0, inline_data: [0; 64], buffer: 0, buffer_size: 0,
-        };
-        // Blocking receive (timeout could be added)
-        let _ = syscall_raw(SYS_IPC_RECEIVE, 0, &mut response as *mut _ as u64, 0, 0, 0); 
-        Ok(response)
-    }
-}
-
-// Function to open a block device via Device Manager
-fn open_block_device(device_name: &str) -> Result<u64, ()> {
-    let inline_data = device_name.as_bytes();
-    let response = send_ipc_request(DRIVER_MANAGER_PORT as u64, DM_MSG_OPEN_DEVICE as u64, inline_data)?;
-
-    if response.msg_id == DM_MSG_OPEN_DEVICE as u64 && response.msg_type == 2 /* Response */ && response.inline_size == 8 {
-        let device_handle = u64::from_le_bytes(response.inline_data[0..8].try_into().unwrap());
-        if device_handle != 0 {
-            Ok(device_handle)
-        } else {
-            Err(())
-        }
-    } else {
-        Err(())
-    }
-}
-
 // Get uptime helper
 fn get_uptime_ms() -> u64 {
     unsafe { syscall_raw(SYS_GET_UPTIME_MS, 0, 0, 0, 0, 0) }
@@ -117,7 +80,8 @@ pub const SFS_MAGIC: u64 = 0x5343415246535F31; // "SCARSF_1" in hex
 
 /// SFS Version
 pub const SFS_VERSION_MAJOR: u16 = 1;
-pub const SFS_VERSION_MINOR: u16 = 0;
+/// 1.1 added the block bitmap, moving the first data block
+pub const SFS_VERSION_MINOR: u16 = 1;
 
 /// Block size (4KB)
 pub const BLOCK_SIZE: usize = 4096;
@@ -145,13 +109,39 @@ pub struct SfsFileSystem {
     /// Is mounted read-write?
     read_write: bool,
 
-    /// Device handle for block I/O
-    device_handle: u64,
+    /// Disk or partition the filesystem lives on, while mounted
+    device: Option<Box<dyn BlockIo>>,
+
+    /// Device sectors making up one filesystem block
+    sectors_per_block: u64,
+
+    /// Write-back block cache (interior mutability so reads through `&self` can fill it)
+    cache: RefCell<BlockCache>,
+
+    /// Metadata changed since the last journal commit
+    journal: Transaction,
+
+    /// Files opened through `open`, by handle
+    open_files: OpenFileTable,
+
+    /// Inodes in use, loaded on mount and written back on sync
+    inode_bitmap: InodeBitmap,
+
+    /// Blocks in use, loaded on mount and written back on sync
+    block_bitmap: BlockBitmap,
+
+    /// Mounted with `MS_NOATIME`
+    noatime: bool,
 }
 
 impl SfsFileSystem {
     /// Create a new SFS instance
     pub fn new() -> Self {
+        Self::with_cache_capacity(DEFAULT_CACHE_CAPACITY)
+    }
+
+    /// Create a new SFS instance with a block cache of `capacity` blocks
+    pub fn with_cache_capacity(capacity: usize) -> Self {
         Self {
             superblock: Superblock::new(),
             root_inode: 0,
@@ -159,58 +149,357 @@ impl SfsFileSystem {
             cow_manager: CowManager::new(),
             snapshot_manager: SnapshotManager::new(),
             read_write: false,
-            device_handle: 0,
+            device: None,
+            sectors_per_block: (BLOCK_SIZE / 512) as u64,
+            cache: RefCell::new(BlockCache::with_capacity(capacity)),
+            journal: Transaction::new(),
+            open_files: OpenFileTable::new(),
+            inode_bitmap: InodeBitmap::new(0, BLOCK_SIZE),
+            block_bitmap: BlockBitmap::new(0, 0, BLOCK_SIZE),
+            noatime: false,
+        }
+    }
+
+    /// Resize the block cache, writing back any dirty blocks it evicts
+    pub fn set_cache_capacity(&mut self, capacity: usize) -> VfsResult<()> {
+        let evicted = self.cache.borrow_mut().set_capacity(capacity);
+        for (block_num, data) in evicted {
+            self.device_write_block(block_num, &data)?;
         }
+        Ok(())
     }
 
-    /// Format a device with SFS
-    pub fn format(device_handle: u64, total_blocks: u64) -> VfsResult<()> {
+    /// Sectors per block and whole blocks on a device, from the geometry
+    /// its driver reports. Sectors larger than a block (or not dividing
+    /// it) are not supported.
+    fn device_geometry(device: &dyn BlockIo) -> VfsResult<(u64, u64)> {
+        let info = device.info().map_err(|_| VfsError::IoError)?;
+
+        let sector_size = info.sector_size as usize;
+        if sector_size > BLOCK_SIZE || BLOCK_SIZE % sector_size != 0 {
+            return Err(VfsError::NotSupported);
+        }
+        let sectors_per_block = (BLOCK_SIZE / sector_size) as u64;
+        Ok((sectors_per_block, info.sectors / sectors_per_block))
+    }
+
+    /// Format a device with SFS, sized to fill the whole device
+    pub fn format(device: &dyn BlockIo) -> VfsResult<()> {
+        let (sectors_per_block, device_blocks) = Self::device_geometry(device)?;
+        // The journal and then the backup superblock take the last blocks
+        let total_blocks = device_blocks.saturating_sub(JOURNAL_BLOCKS + 1);
+
         let mut superblock = Superblock::new();
         superblock.magic = SFS_MAGIC;
         superblock.version_major = SFS_VERSION_MAJOR;
         superblock.version_minor = SFS_VERSION_MINOR;
         superblock.block_size = BLOCK_SIZE as u32;
         superblock.total_blocks = total_blocks;
-        superblock.free_blocks = total_blocks - 1; // Minus superblock
         superblock.total_inodes = total_blocks / 4; // 25% for inodes
-        superblock.free_inodes = superblock.total_inodes - 1; // Minus root
-        superblock.root_inode = 1;
+        // Room for the root inode and a block of data at least
+        let data_start = Self::data_start(&superblock);
+        if superblock.total_inodes < 2 || data_start >= total_blocks {
+            return Err(VfsError::NoSpace);
+        }
+        // Everything but the superblock, inode table, bitmaps and the
+        // root directory's block is free
+        let root_block = data_start;
+        let mut blocks = BlockBitmap::new(total_blocks, data_start, BLOCK_SIZE);
+        blocks.alloc_at(root_block);
+        superblock.free_blocks = blocks.free_count();
+        let bitmap = InodeBitmap::new(superblock.total_inodes, BLOCK_SIZE);
+        superblock.free_inodes = bitmap.free_count();
+        superblock.root_inode = ROOT_INODE;
         superblock.generation = 1;
+        superblock.journal_blocks = JOURNAL_BLOCKS;
+        superblock.seal();
+
+        // Clear any descriptor left on the device so nothing is replayed,
+        // then write the primary and backup superblocks
+        let mut block_buffer = [0u8; BLOCK_SIZE];
+        device.write_blocks(superblock.journal_start() * sectors_per_block, sectors_per_block as u32, &block_buffer)
+            .map_err(|_| VfsError::IoError)?;
+        superblock.write_to(&mut block_buffer);
+        for block in [0, superblock.backup_block()] {
+            device.write_blocks(block * sectors_per_block, sectors_per_block as u32, &block_buffer)
+                .map_err(|_| VfsError::IoError)?;
+        }
 
-        // Write superblock to device
-        // Implement block I/O via block device driver
-        use crate::block_device::write_blocks;
-        let superblock_bytes = unsafe {
-            core::slice::from_raw_parts(
-                &superblock as *const _ as *const u8,
-                core::mem::size_of::<Superblock>()
-            )
-        };
-        let mut block_buffer = [0u8; 4096];
-        let copy_len = superblock_bytes.len().min(4096);
-        block_buffer[0..copy_len].copy_from_slice(&superblock_bytes[0..copy_len]);
-        let _ = write_blocks(device_handle as u8, 0, 1, &block_buffer); // Write to block 0, port 0
+        // Only the reserved inodes start out in use
+        let bitmap_start = Self::inode_bitmap_start(&superblock);
+        for index in 0..bitmap.blocks() {
+            device.write_blocks((bitmap_start + index) * sectors_per_block, sectors_per_block as u32, bitmap.block(index))
+                .map_err(|_| VfsError::IoError)?;
+        }
+        let blocks_start = Self::block_bitmap_start(&superblock);
+        for index in 0..blocks.blocks() {
+            device.write_blocks((blocks_start + index) * sectors_per_block, sectors_per_block as u32, blocks.block(index))
+                .map_err(|_| VfsError::IoError)?;
+        }
+
+        // An empty inode table but for the root directory, whose "." and
+        // ".." both lead back to itself
+        let mut root = Inode::new();
+        root.file_type = InodeType::Directory;
+        root.mode = 0o755;
+        root.links = 2;
+        root.extent_root = root_block;
+        root.blocks = 1;
+        root.size = BLOCK_SIZE as u64;
+
+        let inodes_per_block = (BLOCK_SIZE / core::mem::size_of::<Inode>()) as u64;
+        for block in 1..bitmap_start {
+            block_buffer = [0u8; BLOCK_SIZE];
+            if block == 1 + ROOT_INODE / inodes_per_block {
+                let offset = (ROOT_INODE % inodes_per_block) as usize * core::mem::size_of::<Inode>();
+                unsafe {
+                    core::ptr::write(block_buffer.as_mut_ptr().add(offset) as *mut Inode, root);
+                }
+            }
+            device.write_blocks(block * sectors_per_block, sectors_per_block as u32, &block_buffer)
+                .map_err(|_| VfsError::IoError)?;
+        }
+
+        block_buffer = [0u8; BLOCK_SIZE];
+        write_entry(&mut block_buffer, 0, ROOT_INODE, b".");
+        write_entry(&mut block_buffer, 1, ROOT_INODE, b"..");
+        device.write_blocks(root_block * sectors_per_block, sectors_per_block as u32, &block_buffer)
+            .map_err(|_| VfsError::IoError)
+    }
+
+    /// First block of the inode bitmap, just past the inode table that
+    /// follows the superblock
+    fn inode_bitmap_start(superblock: &Superblock) -> u64 {
+        let inodes_per_block = (BLOCK_SIZE / core::mem::size_of::<Inode>()) as u64;
+        1 + superblock.total_inodes.div_ceil(inodes_per_block)
+    }
+
+    /// First block of the block bitmap, just past the inode bitmap
+    fn block_bitmap_start(superblock: &Superblock) -> u64 {
+        Self::inode_bitmap_start(superblock) + bitmap_blocks(superblock.total_inodes, BLOCK_SIZE)
+    }
+
+    /// First block past the metadata at the start of the filesystem
+    fn data_start(superblock: &Superblock) -> u64 {
+        Self::block_bitmap_start(superblock) + bitmap_blocks(superblock.total_blocks, BLOCK_SIZE)
+    }
+
+    /// Read the inode bitmap of the mounted filesystem
+    fn load_inode_bitmap(&mut self) -> VfsResult<()> {
+        let start = Self::inode_bitmap_start(&self.superblock);
+        let mut bitmap = InodeBitmap::new(self.superblock.total_inodes, BLOCK_SIZE);
+        let mut buffer = [0u8; BLOCK_SIZE];
+        for index in 0..bitmap.blocks() {
+            self.read_block(start + index, &mut buffer)?;
+            bitmap.load_block(index, &buffer);
+        }
+        self.superblock.free_inodes = bitmap.free_count();
+        self.inode_bitmap = bitmap;
+        Ok(())
+    }
 
+    /// Record the bitmap blocks changed since the last sync in the
+    /// running transaction
+    fn flush_inode_bitmap(&mut self) -> VfsResult<()> {
+        let start = Self::inode_bitmap_start(&self.superblock);
+        let mut buffer = [0u8; BLOCK_SIZE];
+        for index in self.inode_bitmap.take_dirty() {
+            buffer.copy_from_slice(self.inode_bitmap.block(index));
+            self.write_meta_block(start + index, &buffer)?;
+        }
         Ok(())
     }
 
-    /// Read a block from device
+    /// Read the block bitmap of the mounted filesystem
+    fn load_block_bitmap(&mut self) -> VfsResult<()> {
+        let start = Self::block_bitmap_start(&self.superblock);
+        let data_start = Self::data_start(&self.superblock);
+        let mut bitmap = BlockBitmap::new(self.superblock.total_blocks, data_start, BLOCK_SIZE);
+        let mut buffer = [0u8; BLOCK_SIZE];
+        for index in 0..bitmap.blocks() {
+            self.read_block(start + index, &mut buffer)?;
+            bitmap.load_block(index, &buffer);
+        }
+        self.superblock.free_blocks = bitmap.free_count();
+        self.block_bitmap = bitmap;
+        Ok(())
+    }
+
+    /// Record the block bitmap blocks changed since the last sync in the
+    /// running transaction
+    fn flush_block_bitmap(&mut self) -> VfsResult<()> {
+        let start = Self::block_bitmap_start(&self.superblock);
+        let mut buffer = [0u8; BLOCK_SIZE];
+        for index in self.block_bitmap.take_dirty() {
+            buffer.copy_from_slice(self.block_bitmap.block(index));
+            self.write_meta_block(start + index, &buffer)?;
+        }
+        Ok(())
+    }
+
+    /// Superblock copy in `block`, if it passes validation and fits the device
+    fn read_superblock(&self, block: u64, device_blocks: u64) -> Option<Superblock> {
+        let mut buffer = [0u8; BLOCK_SIZE];
+        self.read_block(block, &mut buffer).ok()?;
+        // A filesystem larger than its device was formatted elsewhere
+        // or the device shrank; blocks past the end cannot be read
+        Superblock::read_from(&buffer).filter(|sb| sb.backup_block() < device_blocks)
+    }
+
+    /// The primary superblock, or the backup in the device's last block
+    /// when the primary is torn or corrupt (flagged by the `bool`)
+    fn find_superblock(&self, device_blocks: u64) -> VfsResult<(Superblock, bool)> {
+        if let Some(primary) = self.read_superblock(0, device_blocks) {
+            return Ok((primary, false));
+        }
+        // Only a copy that says it belongs in this block is a backup
+        let backup = device_blocks
+            .checked_sub(1)
+            .and_then(|block| self.read_superblock(block, device_blocks).filter(|sb| sb.backup_block() == block));
+        Ok((backup.ok_or(VfsError::InvalidArgument)?, true))
+    }
+
+    /// Record a metadata block in the running transaction. It reaches its
+    /// home block only through a journal commit; a transaction that fills
+    /// the journal is committed early.
+    fn write_meta_block(&mut self, block_num: u64, buffer: &[u8]) -> VfsResult<()> {
+        if !self.read_write {
+            return Err(VfsError::ReadOnly);
+        }
+
+        if buffer.len() < BLOCK_SIZE {
+            return Err(VfsError::InvalidArgument);
+        }
+
+        // Leave room for the superblock, which every commit carries
+        let capacity = transaction_capacity(self.superblock.journal_blocks);
+        if !self.journal.contains(block_num) && self.journal.len() + 1 >= capacity {
+            self.commit()?;
+        }
+
+        self.journal.put(block_num, buffer);
+        // The cached copy is stale and must never be written back
+        self.cache.borrow_mut().invalidate(block_num);
+        Ok(())
+    }
+
+    /// Commit the running transaction with the current superblock: flush
+    /// file data (ordered mode), log the metadata to the journal, then
+    /// checkpoint it to its home blocks and the backup superblock
+    fn commit(&mut self) -> VfsResult<()> {
+        self.flush_dirty()?;
+
+        self.superblock.journal_sequence += 1;
+        self.superblock.seal();
+        let mut superblock = [0u8; BLOCK_SIZE];
+        self.superblock.write_to(&mut superblock);
+        self.journal.put(0, &superblock);
+        self.cache.borrow_mut().invalidate(0);
+
+        // Filesystems without a journal are written in place
+        let journaled = transaction_capacity(self.superblock.journal_blocks) >= self.journal.len();
+        if journaled {
+            let sequence = self.superblock.journal_sequence;
+            let start = self.superblock.journal_start();
+
+            let homes: Vec<u64> = self.journal.iter().map(|(home, _)| home).collect();
+            let mut block = [0u8; BLOCK_SIZE];
+            encode_descriptor(sequence, &homes, &mut block);
+            let mut crc = update_checksum(CRC32_INIT, &block);
+            self.device_write_block(start, &block)?;
+
+            for (i, (_, data)) in self.journal.iter().enumerate() {
+                crc = update_checksum(crc, data);
+                self.device_write_block(start + 1 + i as u64, data)?;
+            }
+
+            // Once this lands the transaction survives a crash
+            encode_commit(sequence, finish_checksum(crc), &mut block);
+            self.device_write_block(start + 1 + homes.len() as u64, &block)?;
+        }
+
+        // Checkpoint
+        for (home, data) in self.journal.iter() {
+            self.device_write_block(home, data)?;
+        }
+        let backup = self.superblock.backup_block();
+        self.cache.borrow_mut().invalidate(backup);
+        self.device_write_block(backup, &superblock)?;
+        self.journal.clear();
+
+        // Everything is home; the journal has nothing left to replay
+        if journaled {
+            self.device_write_block(self.superblock.journal_start(), &[0u8; BLOCK_SIZE])?;
+        }
+        Ok(())
+    }
+
+    /// Apply a committed transaction left in the journal by a crash.
+    /// Returns whether one was replayed; an uncommitted one is dropped.
+    fn replay_journal(&mut self, superblock: &Superblock) -> VfsResult<bool> {
+        if transaction_capacity(superblock.journal_blocks) == 0 {
+            return Ok(false);
+        }
+        let start = superblock.journal_start();
+
+        let mut descriptor = [0u8; BLOCK_SIZE];
+        self.device_read_block(start, &mut descriptor)?;
+        let (sequence, count) = match decode_descriptor(&descriptor) {
+            Some(found) => found,
+            None => return Ok(false),
+        };
+
+        let mut committed = count <= transaction_capacity(superblock.journal_blocks);
+        let mut block = [0u8; BLOCK_SIZE];
+        if committed {
+            let mut crc = update_checksum(CRC32_INIT, &descriptor);
+            for i in 0..count {
+                self.device_read_block(start + 1 + i as u64, &mut block)?;
+                crc = update_checksum(crc, &block);
+            }
+            self.device_read_block(start + 1 + count as u64, &mut block)?;
+            committed = commit_matches(&block, sequence, finish_checksum(crc));
+        }
+
+        if committed {
+            for i in 0..count {
+                self.device_read_block(start + 1 + i as u64, &mut block)?;
+                self.device_write_block(descriptor_home(&descriptor, i), &block)?;
+            }
+        }
+
+        self.device_write_block(start, &[0u8; BLOCK_SIZE])?;
+        Ok(committed)
+    }
+
+    /// Read a block, consulting the block cache first
     fn read_block(&self, block_num: u64, buffer: &mut [u8]) -> VfsResult<()> {
         if buffer.len() < BLOCK_SIZE {
             return Err(VfsError::InvalidArgument);
         }
 
-        // Implement block read via device driver IPC
-        use crate::block_device::read_blocks;
-        // Convert block number to LBA (assuming 4KB blocks, 8 sectors per block)
-        let lba = block_num * 8;
-        match read_blocks(self.device_handle as u8, lba, 8, buffer) {
-            Ok(_) => Ok(()),
-            Err(_) => Err(VfsError::IoError),
+        // Uncommitted metadata is newer than anything cached or on disk
+        if let Some(data) = self.journal.get(block_num) {
+            buffer[0..BLOCK_SIZE].copy_from_slice(data);
+            return Ok(());
+        }
+
+        if let Some(data) = self.cache.borrow_mut().get(block_num) {
+            buffer[0..BLOCK_SIZE].copy_from_slice(&data[0..BLOCK_SIZE]);
+            return Ok(());
         }
+
+        self.device_read_block(block_num, buffer)?;
+
+        let evicted = self.cache.borrow_mut().put(block_num, buffer[0..BLOCK_SIZE].to_vec(), false);
+        if let Some((victim, data)) = evicted {
+            self.device_write_block(victim, &data)?;
+        }
+
+        Ok(())
     }
 
-    /// Write a block to device
+    /// Write a block into the cache; it reaches the device on eviction or flush
     fn write_block(&mut self, block_num: u64, buffer: &[u8]) -> VfsResult<()> {
         if !self.read_write {
             return Err(VfsError::ReadOnly);
@@ -220,11 +509,46 @@ impl SfsFileSystem {
             return Err(VfsError::InvalidArgument);
         }
 
-        // Implement block write via device driver IPC
-        use crate::block_device::write_blocks;
-        // Convert block number to LBA (assuming 4KB blocks, 8 sectors per block)
-        let lba = block_num * 8;
-        match write_blocks(self.device_handle as u8, lba, 8, buffer) {
+        let evicted = self.cache.borrow_mut().put(block_num, buffer[0..BLOCK_SIZE].to_vec(), true);
+        if let Some((victim, data)) = evicted {
+            self.device_write_block(victim, &data)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write all dirty cached blocks back to the device
+    pub fn flush_dirty(&mut self) -> VfsResult<()> {
+        let dirty = self.cache.borrow().dirty_blocks();
+
+        for block_num in dirty {
+            let mut data = [0u8; BLOCK_SIZE];
+            match self.cache.borrow().peek(block_num) {
+                Some(cached) => data.copy_from_slice(&cached[0..BLOCK_SIZE]),
+                None => continue,
+            }
+            self.device_write_block(block_num, &data)?;
+            self.cache.borrow_mut().mark_clean(block_num);
+        }
+
+        Ok(())
+    }
+
+    /// Read a block straight from the device, bypassing the cache
+    fn device_read_block(&self, block_num: u64, buffer: &mut [u8]) -> VfsResult<()> {
+        let device = self.device.as_ref().ok_or(VfsError::IoError)?;
+        let lba = block_num * self.sectors_per_block;
+        match device.read_blocks(lba, self.sectors_per_block as u32, buffer) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(VfsError::IoError),
+        }
+    }
+
+    /// Write a block straight to the device, bypassing the cache
+    fn device_write_block(&self, block_num: u64, buffer: &[u8]) -> VfsResult<()> {
+        let device = self.device.as_ref().ok_or(VfsError::IoError)?;
+        let lba = block_num * self.sectors_per_block;
+        match device.write_blocks(lba, self.sectors_per_block as u32, buffer) {
             Ok(_) => Ok(()),
             Err(_) => Err(VfsError::IoError),
         }
@@ -236,19 +560,34 @@ impl SfsFileSystem {
             return Err(VfsError::ReadOnly);
         }
 
-        if self.superblock.free_blocks == 0 {
+        let block = self.block_bitmap.alloc().ok_or(VfsError::NoSpace)?;
+        self.claim_block(block);
+        Ok(block)
+    }
+
+    /// Allocate `block` itself, to grow an extent that ends just before it
+    fn allocate_block_at(&mut self, block: u64) -> VfsResult<u64> {
+        if !self.read_write {
+            return Err(VfsError::ReadOnly);
+        }
+
+        if !self.block_bitmap.alloc_at(block) {
             return Err(VfsError::NoSpace);
         }
+        self.claim_block(block);
+        Ok(block)
+    }
+
+    /// Bookkeeping for a block just taken from the bitmap
+    fn claim_block(&mut self, block: u64) {
+        self.superblock.free_blocks = self.superblock.free_blocks.saturating_sub(1);
 
-        // Implement block allocation with CoW
-        // Find a free block (simple bitmap would be better, but for now sequential)
-        let block = self.superblock.total_blocks - self.superblock.free_blocks;
-        self.superblock.free_blocks -= 1;
-        
         // Initialize reference count for CoW
         self.cow_manager.inc_refcount(block);
 
-        Ok(block)
+        // The block number may have been cached under a previous owner
+        self.cache.borrow_mut().invalidate(block);
+        self.journal.remove(block);
     }
 
     /// Free a block
@@ -261,9 +600,11 @@ impl SfsFileSystem {
         let refcount = self.cow_manager.dec_refcount(block_num);
         
         // Only free block if reference count reaches zero
-        if refcount == 0 {
+        if refcount == 0 && self.block_bitmap.free(block_num) {
             self.superblock.free_blocks += 1;
-            // In a full implementation, we would also update the free block bitmap
+            // Freed contents must never be written back or served again
+            self.cache.borrow_mut().invalidate(block_num);
+            self.journal.remove(block_num);
         }
 
         Ok(())
@@ -305,7 +646,7 @@ impl SfsFileSystem {
         // Copy-on-Write: Allocate new block if needed
         let new_block = if self.cow_manager.is_shared(block) {
             let new_blk = self.allocate_block()?;
-            self.write_block(new_blk, &buffer)?;
+            self.write_meta_block(new_blk, &buffer)?;
             self.cow_manager.mark_modified(new_blk);
             new_blk
         } else {
@@ -319,35 +660,87 @@ impl SfsFileSystem {
         }
 
         // Write block
-        self.write_block(new_block, &buffer)?;
+        self.write_meta_block(new_block, &buffer)?;
 
         Ok(())
     }
 
     /// Resolve path to inode number
     fn resolve_path(&self, path: &str) -> VfsResult<u64> {
-        if path == "/" {
-            return Ok(self.root_inode);
-        }
+        let mut path = normalize_path(path);
+        let mut links_followed = 0;
+
+        loop {
+            let mut current_inode = self.root_inode;
+            let mut expanded = None;
 
-        // Split path into components
-        let components: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+            for (index, component) in path.split('/').filter(|s| !s.is_empty()).enumerate() {
+                // Read directory inode
+                let dir_inode = self.read_inode(current_inode)?;
 
-        let mut current_inode = self.root_inode;
+                if dir_inode.file_type != InodeType::Directory {
+                    return Err(VfsError::NotDirectory);
+                }
 
-        for component in components {
-            // Read directory inode
-            let dir_inode = self.read_inode(current_inode)?;
+                // Search directory for component using linear scan (B-tree not implemented)
+                let next_inode = self.lookup_dir_entry(current_inode, component)?;
+
+                // Follow a symlink by splicing its target into the path and
+                // starting over from the root; the target's ".." components
+                // are normalized against the link's directory
+                let inode = self.read_inode(next_inode)?;
+                if inode.file_type == InodeType::Symlink {
+                    links_followed += 1;
+                    if links_followed > MAX_SYMLINK_DEPTH {
+                        return Err(VfsError::TooManyLinks);
+                    }
+                    let target = self.read_link_target(&inode)?;
+                    expanded = Some(splice_target(&path, index, &target));
+                    break;
+                }
 
-            if dir_inode.file_type != InodeType::Directory {
-                return Err(VfsError::NotDirectory);
+                current_inode = next_inode;
             }
 
-            // Search directory for component using linear scan (B-tree not implemented)
-            current_inode = self.lookup_dir_entry(current_inode, component)?;
+            match expanded {
+                Some(next_path) => path = normalize_path(&next_path),
+                None => return Ok(current_inode),
+            }
+        }
+    }
+
+    /// Target path stored in a symlink inode
+    fn read_link_target(&self, inode: &Inode) -> VfsResult<String> {
+        let len = inode.size as usize;
+        if len == 0 || len > MAX_TARGET_LEN {
+            return Err(VfsError::InvalidArgument);
         }
 
-        Ok(current_inode)
+        let mut block = [0u8; BLOCK_SIZE];
+        let bytes = if inode.extent_root == 0 {
+            inode.inline_data.get(..len).ok_or(VfsError::InvalidArgument)?
+        } else {
+            self.read_block(inode.extent_root, &mut block)?;
+            &block[..len]
+        };
+
+        core::str::from_utf8(bytes)
+            .map(String::from)
+            .map_err(|_| VfsError::InvalidArgument)
+    }
+
+    /// Take the lowest free inode number
+    fn allocate_inode(&mut self) -> VfsResult<u64> {
+        let inode_num = self.inode_bitmap.alloc().ok_or(VfsError::NoSpace)?;
+        self.superblock.free_inodes = self.superblock.free_inodes.saturating_sub(1);
+        Ok(inode_num)
+    }
+
+    /// Return an inode number to the free pool
+    fn release_inode(&mut self, inode_num: u64) {
+        if self.inode_bitmap.free(inode_num) {
+            self.superblock.free_inodes += 1;
+        }
     }
 
     /// Look up directory entry
@@ -359,46 +752,196 @@ impl SfsFileSystem {
         }
         
         // Scan all blocks to find directory entry (B-tree not implemented)
-        let num_blocks = (inode.size + BLOCK_SIZE as u64 - 1) / BLOCK_SIZE as u64;
+        let mut buffer = [0u8; BLOCK_SIZE];
+        for block in Self::data_blocks(&inode) {
+            self.read_block(block, &mut buffer)?;
+            if let Some((_, entry_inode)) = find_entry(&buffer, name.as_bytes()) {
+                return Ok(entry_inode);
+            }
+        }
         
-        for i in 0..num_blocks {
-            let mut buffer = [0u8; BLOCK_SIZE];
-            // Use extent tree to find block (placeholder, assumes simple mapping)
-            let block_num = if inode.extent_root != 0 {
-                inode.extent_root + i
-            } else {
-                0
+        Err(VfsError::NotFound)
+    }
+
+    /// Blocks holding an inode's data (file contents or directory
+    /// entries). They are laid out contiguously from the extent root; a
+    /// file grown by truncate has fewer of them than its size covers.
+    fn data_blocks(inode: &Inode) -> core::ops::Range<u64> {
+        let extent = Extent::new(inode.extent_root, inode.blocks);
+        extent.start..extent.start + extent.len
+    }
+
+    /// Inode of the directory that would hold `path`, and the final component
+    fn resolve_parent<'a>(&self, path: &'a str) -> VfsResult<(u64, &'a str)> {
+        let (parent, name) = split_parent(path).ok_or(VfsError::InvalidArgument)?;
+        Ok((self.resolve_path(parent)?, name))
+    }
+
+    /// Link `child_inode` into `parent_inode` as `name`, growing the
+    /// directory by a block when every slot is taken
+    fn add_dir_entry(&mut self, parent_inode: u64, name: &str, child_inode: u64) -> VfsResult<()> {
+        if !valid_name(name.as_bytes()) {
+            return Err(if name.len() > DIRENT_NAME_LEN { VfsError::NameTooLong } else { VfsError::InvalidArgument });
+        }
+
+        let mut dir = self.read_inode(parent_inode)?;
+        if dir.file_type != InodeType::Directory {
+            return Err(VfsError::NotDirectory);
+        }
+
+        let mut buffer = [0u8; BLOCK_SIZE];
+        let mut free_slot = None;
+        for block in Self::data_blocks(&dir) {
+            self.read_block(block, &mut buffer)?;
+            if find_entry(&buffer, name.as_bytes()).is_some() {
+                return Err(VfsError::AlreadyExists);
+            }
+            if free_slot.is_none() {
+                free_slot = find_free_slot(&buffer).map(|slot| (block, slot));
+            }
+        }
+
+        let (block, slot) = match free_slot {
+            Some((block, slot)) => {
+                self.read_block(block, &mut buffer)?;
+                (block, slot)
+            }
+            None => {
+                buffer = [0u8; BLOCK_SIZE];
+                (self.grow_dir(&mut dir)?, 0)
+            }
+        };
+
+        write_entry(&mut buffer, slot, child_inode, name.as_bytes());
+        self.write_meta_block(block, &buffer)?;
+
+        dir.touch_modify(get_uptime_ms());
+        self.write_inode(parent_inode, &dir)
+    }
+
+    /// Unlink `name` from `parent_inode`, returning the inode it named.
+    /// An emptied last block is given back and the directory shrinks.
+    fn remove_dir_entry(&mut self, parent_inode: u64, name: &str) -> VfsResult<u64> {
+        let mut dir = self.read_inode(parent_inode)?;
+        if dir.file_type != InodeType::Directory {
+            return Err(VfsError::NotDirectory);
+        }
+
+        let blocks = Self::data_blocks(&dir);
+        let mut buffer = [0u8; BLOCK_SIZE];
+        for block in blocks.clone() {
+            self.read_block(block, &mut buffer)?;
+            let (slot, child_inode) = match find_entry(&buffer, name.as_bytes()) {
+                Some(found) => found,
+                None => continue,
             };
-            
-            if block_num == 0 { continue; }
-            
-            self.read_block(block_num, &mut buffer)?;
-            
-            // Iterate entries (assuming fixed 64-byte entries for now)
-            const ENTRY_SIZE: usize = 68; // 4 bytes for inode + 64 bytes for name
-            const ENTRIES_PER_BLOCK: usize = BLOCK_SIZE / ENTRY_SIZE;
-            
-            for j in 0..ENTRIES_PER_BLOCK {
-                let offset = j * ENTRY_SIZE;
-                let entry_inode = u32::from_le_bytes(buffer[offset..offset+4].try_into().unwrap()) as u64;
-                
-                if entry_inode == 0 { continue; } // Empty entry
-                
-                // Check name
-                let name_bytes = &buffer[offset+4..offset+ENTRY_SIZE];
-                let len = name_bytes.iter().position(|&c| c == 0).unwrap_or(64);
-                let entry_name = core::str::from_utf8(&name_bytes[0..len])
-                    .map_err(|_| VfsError::InvalidData)?;
-                
-                if entry_name == name {
-                    return Ok(entry_inode);
-                }
+
+            clear_entry(&mut buffer, slot);
+            // The first block keeps "." and ".." and is never released
+            if block + 1 == blocks.end && block != blocks.start && !has_children(&buffer) {
+                self.free_block(block)?;
+                dir.size -= BLOCK_SIZE as u64;
+                dir.blocks = dir.blocks.saturating_sub(1);
+            } else {
+                self.write_meta_block(block, &buffer)?;
             }
+
+            dir.touch_modify(get_uptime_ms());
+            self.write_inode(parent_inode, &dir)?;
+            return Ok(child_inode);
         }
-        
+
         Err(VfsError::NotFound)
     }
 
+    /// Append an empty block to a directory
+    fn grow_dir(&mut self, dir: &mut Inode) -> VfsResult<u64> {
+        let blocks = Self::data_blocks(dir);
+        // Entries are found by offset from the extent root, so a directory
+        // can only grow into the block right after it
+        let block = if dir.extent_root == 0 {
+            self.allocate_block()?
+        } else {
+            self.allocate_block_at(blocks.end)?
+        };
+        if dir.extent_root == 0 {
+            dir.extent_root = block;
+        }
+
+        self.write_meta_block(block, &[0u8; BLOCK_SIZE])?;
+        let count = blocks.end - blocks.start + 1;
+        dir.blocks = count;
+        dir.size = count * BLOCK_SIZE as u64;
+        Ok(block)
+    }
+
+    /// Whether a directory holds anything besides "." and ".."
+    fn dir_is_empty(&self, dir: &Inode) -> VfsResult<bool> {
+        let mut buffer = [0u8; BLOCK_SIZE];
+        for block in Self::data_blocks(dir) {
+            self.read_block(block, &mut buffer)?;
+            if has_children(&buffer) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Set the size of a regular file, for `truncate` and `O_TRUNC`
+    fn truncate_inode(&mut self, inode_num: u64, size: u64) -> VfsResult<()> {
+        let mut inode = self.read_inode(inode_num)?;
+        
+        if inode.file_type != InodeType::RegularFile {
+            return Err(VfsError::InvalidArgument);
+        }
+        
+        // Growing leaves a hole that reads as zeros
+        self.shrink_extent(&mut inode, size)?;
+        inode.size = size;
+
+        // Update inode
+        inode.touch_modify(get_uptime_ms());
+        self.write_inode(inode_num, &inode)?;
+        
+        Ok(())
+    }
+
+    /// Give back the blocks of `inode` past the first `size` bytes; a block
+    /// a snapshot still references stays allocated until its last owner
+    /// lets it go
+    fn shrink_extent(&mut self, inode: &mut Inode, size: u64) -> VfsResult<()> {
+        let mut extent = Extent::new(inode.extent_root, inode.blocks);
+        for block in extent.truncate(size, BLOCK_SIZE as u64) {
+            self.free_block(block)?;
+        }
+        inode.extent_root = extent.start;
+        inode.blocks = extent.len;
+        Ok(())
+    }
+
+    /// Free an inode with no names left and no open handles: its blocks,
+    /// its slot in the inode table and its bit in the inode bitmap
+    fn delete_inode(&mut self, inode_num: u64, mut inode: Inode) -> VfsResult<()> {
+        self.shrink_extent(&mut inode, 0)?;
+        let cleared = Inode { file_type: InodeType::Unknown, links: 0, ..Inode::new() };
+        self.write_inode(inode_num, &cleared)?;
+        self.release_inode(inode_num);
+        Ok(())
+    }
+
+    /// Free `inode_num` if its last name was unlinked while it was open and
+    /// no handle to it is left
+    fn release_if_orphan(&mut self, inode_num: u64) -> VfsResult<()> {
+        if !self.read_write || self.open_files.is_open(inode_num) {
+            return Ok(());
+        }
+        let inode = self.read_inode(inode_num)?;
+        if inode.links == 0 && inode.file_type != InodeType::Unknown {
+            self.delete_inode(inode_num, inode)?;
+        }
+        Ok(())
+    }
+
     /// Create snapshot
     pub fn create_snapshot(&mut self, name: &str) -> VfsResult<u64> {
         if !self.read_write {
@@ -433,61 +976,111 @@ impl SfsFileSystem {
 
         Ok(())
     }
-}
 
-impl FileSystemOps for SfsFileSystem {
-    fn mount(&mut self, device: &str, flags: u32) -> VfsResult<()> {
-        // Open device via device manager
-        let device_handle = open_block_device(device)
-            .map_err(|_| VfsError::DeviceNotFound)?;
-        self.device_handle = device_handle;
+    /// Check the mounted filesystem for leaked, dangling and doubly-owned
+    /// blocks. Nothing is repaired.
+    pub fn fsck(&self) -> VfsResult<FsckReport> {
+        let total_inodes = self.superblock.total_inodes;
+        // Block 0 is the superblock, followed by the inode table and both bitmaps
+        let data_start = Self::data_start(&self.superblock);
+
+        let mut blocks = BlockMap::new(self.superblock.total_blocks, data_start, |block| self.block_bitmap.is_used(block));
+        for inode_num in 1..total_inodes {
+            let inode = self.read_inode(inode_num)?;
+            if inode.file_type != InodeType::Unknown {
+                blocks.claim(Self::data_blocks(&inode));
+            }
+        }
 
-        // Read superblock
-        let mut buffer = [0u8; BLOCK_SIZE];
-        self.read_block(0, &mut buffer)?;
+        Ok(blocks.finish(|block| self.cow_manager.refcount(block)))
+    }
 
-        let superblock = unsafe {
-            core::ptr::read(buffer.as_ptr() as *const Superblock)
-        };
+    /// Check an unmounted SFS device (mounted read-only for the duration)
+    pub fn fsck_device(device: &str) -> VfsResult<FsckReport> {
+        let mut fs = SfsFileSystem::new();
+        fs.mount(device, 0)?;
+        fs.fsck()
+    }
 
-        // Verify magic
-        if superblock.magic != SFS_MAGIC {
-            return Err(VfsError::InvalidArgument);
+    /// Mount the SFS on `device`, which the filesystem keeps until unmount
+    pub fn mount_on(&mut self, device: Box<dyn BlockIo>, flags: u32) -> VfsResult<()> {
+        let (sectors_per_block, device_blocks) = Self::device_geometry(device.as_ref())?;
+        self.device = Some(device);
+        self.sectors_per_block = sectors_per_block;
+        self.cache.borrow_mut().clear();
+        self.journal.clear();
+        self.open_files.clear();
+
+        let (mut superblock, mut recovered) = self.find_superblock(device_blocks)?;
+
+        // Finish a transaction a crash interrupted; the superblock it
+        // carries replaces the one just read
+        if self.replay_journal(&superblock)? {
+            self.cache.borrow_mut().clear();
+            (superblock, recovered) = self.find_superblock(device_blocks)?;
         }
 
         self.superblock = superblock;
         self.root_inode = superblock.root_inode;
         self.current_generation = superblock.generation;
-        self.read_write = (flags & 0x01) != 0; // Check read-write flag
+        self.read_write = (flags & MS_RDWR) != 0;
+        self.noatime = (flags & MS_NOATIME) != 0;
+        self.load_inode_bitmap()?;
+        self.load_block_bitmap()?;
+
+        // Repair the primary from the backup
+        if recovered && self.read_write {
+            self.commit()?;
+        }
 
         Ok(())
     }
+}
+
+impl FileSystemOps for SfsFileSystem {
+    fn mount(&mut self, device: &str, flags: u32) -> VfsResult<()> {
+        // A whole disk (`/dev/sda`) or one of its partitions (`/dev/sda1`)
+        let volume = open_volume(device.as_bytes()).ok_or(VfsError::NotFound)?;
+        self.mount_on(Box::new(volume), flags)
+    }
 
     fn unmount(&mut self) -> VfsResult<()> {
+        // Files unlinked while open are freed as if closed
+        let open: Vec<u64> = self.open_files.inodes().collect();
+        self.open_files.clear();
+        for inode_num in open {
+            self.release_if_orphan(inode_num)?;
+        }
+
         // Sync all pending writes
         self.sync()?;
 
-        // Close device
-        // Device handle is just a port index, no explicit close needed
-        // In a full implementation, we would notify device manager
-        self.device_handle = 0;
+        // Nothing to close; the volume is only a port index and an offset
+        self.device = None;
 
         Ok(())
     }
 
     fn open(&mut self, path: &str, flags: u32, mode: u16) -> VfsResult<u64> {
+        // O_EXCL refuses any existing entry, a symlink included
+        if flags & (O_CREAT | O_EXCL) == O_CREAT | O_EXCL {
+            let (parent, name) = self.resolve_parent(path)?;
+            match self.lookup_dir_entry(parent, name) {
+                Ok(_) => return Err(VfsError::AlreadyExists),
+                Err(VfsError::NotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
         // Resolve path to inode
         let inode_num = match self.resolve_path(path) {
             Ok(num) => num,
             Err(VfsError::NotFound) if (flags & O_CREAT) != 0 => {
-                // Create new file
-                // Implement file creation
+                // Create new file in an existing directory
+                let (parent, name) = self.resolve_parent(path)?;
+
                 // Allocate new inode
-                if self.superblock.free_inodes == 0 {
-                    return Err(VfsError::NoSpace);
-                }
-                let new_inode_num = self.superblock.total_inodes - self.superblock.free_inodes;
-                self.superblock.free_inodes -= 1;
+                let new_inode_num = self.allocate_inode()?;
                 
                 // Create new inode
                 let mut new_inode = Inode::new();
@@ -495,29 +1088,49 @@ impl FileSystemOps for SfsFileSystem {
                 new_inode.mode = mode as u16;
                 new_inode.size = 0;
                 new_inode.blocks = 0;
+                new_inode.touch_created(get_uptime_ms());
                 
                 // Write inode
                 self.write_inode(new_inode_num, &new_inode)?;
                 
-                // Add to parent directory (would use B-tree)
-                // For now, just return the inode number
-                return Ok(new_inode_num);
+                // Add to parent directory so lookups find it
+                if let Err(e) = self.add_dir_entry(parent, name, new_inode_num) {
+                    self.release_inode(new_inode_num);
+                    return Err(e);
+                }
+                return Ok(self.open_files.insert(new_inode_num, flags));
             }
             Err(e) => return Err(e),
         };
 
-        // Return inode number as file handle
-        Ok(inode_num)
+        let file = OpenFile { inode: inode_num, flags };
+        if file.can_write() && !self.read_write {
+            return Err(VfsError::ReadOnly);
+        }
+
+        if flags & O_TRUNC != 0 && file.can_write() {
+            let inode = self.read_inode(inode_num)?;
+            if inode.file_type == InodeType::RegularFile {
+                self.truncate_inode(inode_num, 0)?;
+            }
+        }
+
+        Ok(self.open_files.insert(inode_num, flags))
     }
 
     fn close(&mut self, file_handle: u64) -> VfsResult<()> {
-        // Nothing to do for now
-        Ok(())
+        let file = self.open_files.remove(file_handle).ok_or(VfsError::InvalidFd)?;
+        self.release_if_orphan(file.inode)
     }
 
     fn read(&mut self, file_handle: u64, buffer: &mut [u8], offset: u64) -> VfsResult<usize> {
+        let file = self.open_files.get(file_handle).ok_or(VfsError::InvalidFd)?;
+        if !file.can_read() {
+            return Err(VfsError::PermissionDenied);
+        }
+
         // Read inode
-        let inode = self.read_inode(file_handle)?;
+        let mut inode = self.read_inode(file.inode)?;
 
         if inode.file_type != InodeType::RegularFile && inode.file_type != InodeType::Directory {
             return Err(VfsError::InvalidArgument);
@@ -528,31 +1141,29 @@ impl FileSystemOps for SfsFileSystem {
         let block_offset = (offset % BLOCK_SIZE as u64) as usize;
         let mut bytes_read = 0;
 
-        // Read data blocks using extent tree
-        // For now, use extent_root to find block
-        // Full implementation would traverse B-tree extent tree
-        if inode.extent_root != 0 {
-            // Use B-tree to find block number for this block_idx
-            // For now, simple calculation (full implementation would query B-tree)
-            // This is a placeholder - real implementation would:
-            // 1. Query extent B-tree with key=block_idx
-            // 2. Get block number from extent
-            // 3. Read block
-            let block_num = inode.extent_root + block_idx; // Placeholder
-            if block_num != 0 {
-                let mut block_data = [0u8; BLOCK_SIZE];
+        // Past the inline data a file without blocks is all hole
+        if inode.extent_root != 0 || inode.size > 60 {
+            let mut block_data = [0u8; BLOCK_SIZE];
+            // A hole reads as zeros
+            if let Some(block_num) = Extent::new(inode.extent_root, inode.blocks).lookup(block_idx) {
                 self.read_block(block_num, &mut block_data)?;
-                
-                let copy_len = buffer.len().min(BLOCK_SIZE - block_offset);
-                buffer[0..copy_len].copy_from_slice(&block_data[block_offset..block_offset + copy_len]);
-                bytes_read = copy_len;
             }
+
+            let copy_len = buffer.len().min(BLOCK_SIZE - block_offset);
+            buffer[0..copy_len].copy_from_slice(&block_data[block_offset..block_offset + copy_len]);
+            bytes_read = copy_len;
         } else if inode.size > 0 && inode.size <= 60 {
             // Use inline data for small files
             let copy_len = buffer.len().min((inode.size - offset) as usize);
             buffer[0..copy_len].copy_from_slice(&inode.inline_data[offset as usize..offset as usize + copy_len]);
             bytes_read = copy_len;
         }
+
+        // A read-only mount has nowhere to record the access
+        if self.read_write && !self.noatime {
+            inode.touch_access(get_uptime_ms());
+            self.write_inode(file.inode, &inode)?;
+        }
         Ok(bytes_read)
     }
 
@@ -561,30 +1172,52 @@ impl FileSystemOps for SfsFileSystem {
             return Err(VfsError::ReadOnly);
         }
 
+        let file = self.open_files.get(file_handle).ok_or(VfsError::InvalidFd)?;
+        if !file.can_write() {
+            return Err(VfsError::PermissionDenied);
+        }
+
         // Read inode
-        let mut inode = self.read_inode(file_handle)?;
+        let mut inode = self.read_inode(file.inode)?;
 
         if inode.file_type != InodeType::RegularFile {
             return Err(VfsError::InvalidArgument);
         }
 
+        let offset = file.write_offset(offset, inode.size);
+
         // Write data using CoW
         // Calculate block and offset
         let block_idx = offset / BLOCK_SIZE as u64;
         let block_offset = (offset % BLOCK_SIZE as u64) as usize;
         let mut bytes_written = 0;
         
-        // Allocate block if needed (with CoW)
-        let block_num = if inode.extent_root == 0 {
-            // Allocate first block
-            let new_block = self.allocate_block()?;
-            inode.extent_root = new_block;
-            new_block
-        } else {
-            // Find or allocate block for this block_idx
-            // Full implementation would query/extend extent tree
-            inode.extent_root + block_idx // Placeholder
-        };
+        // Extend the extent up to this block, zeroing any hole it covers
+        let mut extent = Extent::new(inode.extent_root, inode.blocks);
+        while extent.len <= block_idx {
+            // The extent can only grow into the block right after it
+            let allocated = if extent.len == 0 {
+                self.allocate_block()
+            } else {
+                self.allocate_block_at(extent.start + extent.len)
+            };
+            let block = match allocated {
+                Ok(block) => block,
+                Err(e) => {
+                    // Keep the blocks already added so they are not leaked
+                    self.write_inode(file.inode, &inode)?;
+                    return Err(e);
+                }
+            };
+            if extent.len == 0 {
+                extent.start = block;
+            }
+            self.write_block(block, &[0u8; BLOCK_SIZE])?;
+            extent.len += 1;
+            inode.extent_root = extent.start;
+            inode.blocks = extent.len;
+        }
+        let block_num = extent.start + block_idx;
         
         // Read existing block (for CoW)
         let mut block_data = [0u8; BLOCK_SIZE];
@@ -605,8 +1238,8 @@ impl FileSystemOps for SfsFileSystem {
         
         // Update inode
         inode.size = inode.size.max(offset + copy_len as u64);
-        inode.mtime = get_uptime_ms();
-        self.write_inode(file_handle, &inode)?;
+        inode.touch_modify(get_uptime_ms());
+        self.write_inode(file.inode, &inode)?;
         
         bytes_written = copy_len;
         Ok(bytes_written)
@@ -638,7 +1271,8 @@ impl FileSystemOps for SfsFileSystem {
     }
 
     fn fstat(&self, file_handle: u64) -> VfsResult<FileStat> {
-        let inode = self.read_inode(file_handle)?;
+        let file = self.open_files.get(file_handle).ok_or(VfsError::InvalidFd)?;
+        let inode = self.read_inode(file.inode)?;
 
         Ok(FileStat {
             file_type: match inode.file_type {
@@ -650,7 +1284,7 @@ impl FileSystemOps for SfsFileSystem {
             size: inode.size,
             blocks: inode.blocks,
             block_size: BLOCK_SIZE as u32,
-            inode: file_handle,
+            inode: file.inode,
             links: inode.links as u32,
             uid: inode.uid,
             gid: inode.gid,
@@ -667,12 +1301,15 @@ impl FileSystemOps for SfsFileSystem {
         }
 
         // Implement directory creation
-        // Allocate new inode for directory
-        if self.superblock.free_inodes == 0 {
-            return Err(VfsError::NoSpace);
+        let (parent, name) = self.resolve_parent(path)?;
+        match self.lookup_dir_entry(parent, name) {
+            Ok(_) => return Err(VfsError::AlreadyExists),
+            Err(VfsError::NotFound) => {}
+            Err(e) => return Err(e),
         }
-        let new_inode_num = self.superblock.total_inodes - self.superblock.free_inodes;
-        self.superblock.free_inodes -= 1;
+
+        // Allocate new inode for directory
+        let new_inode_num = self.allocate_inode()?;
         
         // Create directory inode
         let mut dir_inode = Inode::new();
@@ -680,15 +1317,31 @@ impl FileSystemOps for SfsFileSystem {
         dir_inode.mode = mode;
         dir_inode.size = 0;
         dir_inode.blocks = 0;
-        dir_inode.ctime = get_uptime_ms();
-        dir_inode.mtime = get_uptime_ms();
+        dir_inode.links = 2;
+        dir_inode.touch_created(get_uptime_ms());
+        
+        // Add "." and ".." entries
+        let block = match self.grow_dir(&mut dir_inode) {
+            Ok(block) => block,
+            Err(e) => {
+                self.release_inode(new_inode_num);
+                return Err(e);
+            }
+        };
+        let mut entries = [0u8; BLOCK_SIZE];
+        write_entry(&mut entries, 0, new_inode_num, b".");
+        write_entry(&mut entries, 1, parent, b"..");
+        self.write_meta_block(block, &entries)?;
         
         // Write inode
         self.write_inode(new_inode_num, &dir_inode)?;
         
-        // Add "." and ".." entries (would use B-tree for directory entries)
-        // For now, directory is created but entries not added
-        // Full implementation would add directory entries via B-tree
+        // Link into the parent last, once the directory is complete
+        if let Err(e) = self.add_dir_entry(parent, name, new_inode_num) {
+            self.free_block(block)?;
+            self.release_inode(new_inode_num);
+            return Err(e);
+        }
         
         Ok(())
     }
@@ -698,23 +1351,27 @@ impl FileSystemOps for SfsFileSystem {
             return Err(VfsError::ReadOnly);
         }
 
-        // Implement directory removal
-        let inode_num = self.resolve_path(path)?;
+        // Implement directory removal (the root has no parent to leave)
+        let (parent, name) = self.resolve_parent(path)?;
+        let inode_num = self.lookup_dir_entry(parent, name)?;
         let inode = self.read_inode(inode_num)?;
         
         if inode.file_type != InodeType::Directory {
             return Err(VfsError::NotDirectory);
         }
         
-        // Check if directory is empty (would check B-tree for entries)
-        // For now, just check if size is 0 (only "." and ".." would be present)
-        if inode.size > 0 {
+        // Only "." and ".." may be left
+        if !self.dir_is_empty(&inode)? {
             return Err(VfsError::NotEmpty);
         }
         
-        // Free inode
-        self.superblock.free_inodes += 1;
-        // In full implementation, would also free blocks and update B-tree
+        self.remove_dir_entry(parent, name)?;
+        
+        // Free the directory's blocks and inode
+        for block in Self::data_blocks(&inode) {
+            self.free_block(block)?;
+        }
+        self.release_inode(inode_num);
         
         Ok(())
     }
@@ -725,32 +1382,26 @@ impl FileSystemOps for SfsFileSystem {
         }
 
         // Implement file removal
-        let inode_num = self.resolve_path(path)?;
+        let (parent, name) = self.resolve_parent(path)?;
+        let inode_num = self.lookup_dir_entry(parent, name)?;
         let mut inode = self.read_inode(inode_num)?;
         
         if inode.file_type == InodeType::Directory {
             return Err(VfsError::IsDirectory);
         }
         
-        // Decrement link count
-        if inode.links > 0 {
-            inode.links -= 1;
-        }
+        // Remove from parent directory
+        self.remove_dir_entry(parent, name)?;
         
-        // If no more links, free blocks and inode
-        if inode.links == 0 {
-            // Free blocks (would traverse extent tree)
-            // For now, just free the inode
-            self.superblock.free_inodes += 1;
-            // In full implementation, would free all blocks via CoW reference counting
+        // Other hard links keep the inode alive; the last one frees it,
+        // unless the file is still open, in which case the last close does
+        if inode.drop_link() && !self.open_files.is_open(inode_num) {
+            self.delete_inode(inode_num, inode)?;
         } else {
-            // Update inode
+            inode.touch_change(get_uptime_ms());
             self.write_inode(inode_num, &inode)?;
         }
         
-        // Remove from parent directory (would use B-tree)
-        // For now, just mark as removed
-        
         Ok(())
     }
 
@@ -760,23 +1411,115 @@ impl FileSystemOps for SfsFileSystem {
         }
 
         // Implement rename
-        let inode_num = self.resolve_path(old_path)?;
-        
-        // Remove old name from parent directory
-        // Add new name to new parent directory
-        // Both operations would use B-tree directory entries
-        // For now, just verify paths are valid
-        let _old_inode = self.read_inode(inode_num)?;
+        let (old_parent, old_name) = self.resolve_parent(old_path)?;
+        let (new_parent, new_name) = self.resolve_parent(new_path)?;
+        let inode_num = self.lookup_dir_entry(old_parent, old_name)?;
+        let mut inode = self.read_inode(inode_num)?;
         
-        // In full implementation:
-        // 1. Parse old_path and new_path to get parent directories
-        // 2. Remove entry from old parent's B-tree
-        // 3. Add entry to new parent's B-tree
-        // 4. Update inode if directory moved
+        // Add the new name first so a failure leaves the old one in place
+        self.add_dir_entry(new_parent, new_name, inode_num)?;
+        self.remove_dir_entry(old_parent, old_name)?;
         
+        // A directory that moved must point ".." at its new parent
+        if inode.file_type == InodeType::Directory && new_parent != old_parent {
+            let mut buffer = [0u8; BLOCK_SIZE];
+            if let Some(block) = Self::data_blocks(&inode).next() {
+                self.read_block(block, &mut buffer)?;
+                if let Some((slot, _)) = find_entry(&buffer, b"..") {
+                    write_entry(&mut buffer, slot, new_parent, b"..");
+                    self.write_meta_block(block, &buffer)?;
+                }
+            }
+        }
+
+        inode.touch_change(get_uptime_ms());
+        self.write_inode(inode_num, &inode)
+    }
+
+    fn symlink(&mut self, target: &str, linkpath: &str) -> VfsResult<()> {
+        if !self.read_write {
+            return Err(VfsError::ReadOnly);
+        }
+
+        if target.is_empty() {
+            return Err(VfsError::InvalidArgument);
+        }
+        if target.len() > MAX_TARGET_LEN {
+            return Err(VfsError::NameTooLong);
+        }
+
+        // The link itself is not followed, only the directories above it
+        let (parent, name) = self.resolve_parent(linkpath)?;
+        match self.lookup_dir_entry(parent, name) {
+            Ok(_) => return Err(VfsError::AlreadyExists),
+            Err(VfsError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+
+        let new_inode_num = self.allocate_inode()?;
+
+        let mut link = Inode::new();
+        link.file_type = InodeType::Symlink;
+        link.mode = 0o777;
+        link.size = target.len() as u64;
+        link.touch_created(get_uptime_ms());
+
+        // Short targets live in the inode, longer ones in a block of their own
+        if target.len() <= INLINE_TARGET_LEN {
+            link.inline_data[..target.len()].copy_from_slice(target.as_bytes());
+        } else {
+            let block = match self.allocate_block() {
+                Ok(block) => block,
+                Err(e) => {
+                    self.release_inode(new_inode_num);
+                    return Err(e);
+                }
+            };
+            let mut data = [0u8; BLOCK_SIZE];
+            data[..target.len()].copy_from_slice(target.as_bytes());
+            self.write_meta_block(block, &data)?;
+            link.extent_root = block;
+            link.blocks = 1;
+        }
+
+        self.write_inode(new_inode_num, &link)?;
+
+        if let Err(e) = self.add_dir_entry(parent, name, new_inode_num) {
+            if link.extent_root != 0 {
+                self.free_block(link.extent_root)?;
+            }
+            self.release_inode(new_inode_num);
+            return Err(e);
+        }
+
         Ok(())
     }
 
+    fn link(&mut self, existing_path: &str, new_path: &str) -> VfsResult<()> {
+        if !self.read_write {
+            return Err(VfsError::ReadOnly);
+        }
+
+        // Like symlink, a link at `existing_path` is linked itself, not followed
+        let (old_parent, old_name) = self.resolve_parent(existing_path)?;
+        let inode_num = self.lookup_dir_entry(old_parent, old_name)?;
+        let mut inode = self.read_inode(inode_num)?;
+
+        // Directories keep a single parent so ".." stays meaningful
+        if inode.file_type == InodeType::Directory {
+            return Err(VfsError::IsDirectory);
+        }
+        if !inode.add_link() {
+            return Err(VfsError::LinkLimit);
+        }
+
+        // add_dir_entry rejects a name that already exists
+        let (new_parent, new_name) = self.resolve_parent(new_path)?;
+        self.add_dir_entry(new_parent, new_name, inode_num)?;
+        inode.touch_change(get_uptime_ms());
+        self.write_inode(inode_num, &inode)
+    }
+
     fn opendir(&mut self, path: &str) -> VfsResult<u64> {
         let inode_num = self.resolve_path(path)?;
         let inode = self.read_inode(inode_num)?;
@@ -800,6 +1543,44 @@ impl FileSystemOps for SfsFileSystem {
         Ok(None)
     }
 
+    /// Cursors are slot positions in the directory, so entries unlinked
+    /// between batches do not shift the ones after them
+    fn readdir_batch(&mut self, dir_handle: u64, cursor: u32, batch: &mut DirBatch) -> VfsResult<Option<u32>> {
+        let dir = self.read_inode(dir_handle)?;
+        if dir.file_type != InodeType::Directory {
+            return Err(VfsError::NotDirectory);
+        }
+
+        let slots_per_block = (BLOCK_SIZE / DIRENT_SIZE) as u32;
+        let mut buffer = [0u8; BLOCK_SIZE];
+        for (index, block) in Self::data_blocks(&dir).enumerate() {
+            let first = index as u32 * slots_per_block;
+            if first + slots_per_block <= cursor {
+                continue;
+            }
+            self.read_block(block, &mut buffer)?;
+
+            for slot in 0..slots_per_block {
+                let position = first + slot;
+                let child = entry_inode(&buffer, slot as usize);
+                if position < cursor || child == 0 {
+                    continue;
+                }
+
+                let name = entry_name(&buffer, slot as usize);
+                let mut entry = DirEntry::new();
+                entry.inode = child;
+                entry.file_type = FileType::from_u8(self.read_inode(child)?.file_type as u8);
+                entry.name_len = name.len() as u16;
+                entry.name[..name.len()].copy_from_slice(name);
+                if !batch.push(&entry) {
+                    return Ok(Some(position));
+                }
+            }
+        }
+        Ok(None)
+    }
+
     fn closedir(&mut self, dir_handle: u64) -> VfsResult<()> {
         Ok(())
     }
@@ -811,34 +1592,7 @@ impl FileSystemOps for SfsFileSystem {
 
         // Implement truncate
         let inode_num = self.resolve_path(path)?;
-        let mut inode = self.read_inode(inode_num)?;
-        
-        if inode.file_type != InodeType::RegularFile {
-            return Err(VfsError::InvalidArgument);
-        }
-        
-        // Update size
-        let old_size = inode.size;
-        inode.size = size;
-        
-        // If truncating to smaller size, free blocks
-        if size < old_size {
-            // Calculate blocks to free
-            let old_blocks = (old_size + BLOCK_SIZE as u64 - 1) / BLOCK_SIZE as u64;
-            let new_blocks = (size + BLOCK_SIZE as u64 - 1) / BLOCK_SIZE as u64;
-            
-            // Free blocks beyond new size (would traverse extent tree)
-            for block_idx in new_blocks..old_blocks {
-                // Get block number from extent tree and free it
-                // For now, just update inode
-            }
-        }
-        
-        // Update inode
-        inode.mtime = get_uptime_ms();
-        self.write_inode(inode_num, &inode)?;
-        
-        Ok(())
+        self.truncate_inode(inode_num, size)
     }
 
     fn sync(&mut self) -> VfsResult<()> {
@@ -846,14 +1600,9 @@ impl FileSystemOps for SfsFileSystem {
             return Ok(());
         }
 
-        // Write superblock
-        let mut buffer = [0u8; BLOCK_SIZE];
-        unsafe {
-            let ptr = buffer.as_mut_ptr() as *mut Superblock;
-            core::ptr::write(ptr, self.superblock);
-        }
-        self.write_block(0, &buffer)?;
-
-        Ok(())
+        // File data, then the metadata through the journal
+        self.flush_inode_bitmap()?;
+        self.flush_block_bitmap()?;
+        self.commit()
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,31 @@
+//! SFS path normalization
+//!
+//! "." and ".." are resolved lexically before any component is looked up,
+//! so ".." can never climb above the root, whatever the path or the
+//! symlink targets spliced into it.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// `path` with empty and "." components dropped and each ".." removing the
+/// component before it (or nothing at the root). The result is absolute,
+/// and empty for the root, like `splice_target`'s.
+pub fn normalize_path(path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+
+    let mut normalized = String::new();
+    for component in components {
+        normalized.push('/');
+        normalized.push_str(component);
+    }
+    normalized
+}
@@ -3,6 +3,9 @@
 use crate::file_ops::{VfsResult, VfsError};
 use alloc::collections::BTreeMap;
 use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::get_uptime_ms;
 
 /// Snapshot metadata
 #[derive(Clone)]
@@ -1,6 +1,15 @@
 //! SFS Superblock Structure
+//!
+//! Every copy carries a CRC32 over the whole structure. The primary copy
+//! lives in block 0 and a backup in the block just past the filesystem
+//! and its journal (`backup_block`), so a torn write to one of them leaves
+//! the other intact.
 
 use super::SFS_MAGIC;
+use crate::crc32::crc32;
+
+/// Size of the superblock on disk
+pub const SUPERBLOCK_SIZE: usize = core::mem::size_of::<Superblock>();
 
 /// SFS Superblock
 #[repr(C)]
@@ -58,9 +67,19 @@ pub struct Superblock {
     /// Filesystem state flags
     pub state: u32,
 
+    /// CRC32 of the superblock with this field zeroed (held in what was
+    /// alignment padding, so the layout is unchanged)
+    pub checksum: u32,
+
     /// Snapshot root inode
     pub snapshot_root: u64,
 
+    /// Size of the metadata journal following the filesystem's blocks
+    pub journal_blocks: u64,
+
+    /// Sequence number of the last committed journal transaction
+    pub journal_sequence: u64,
+
     /// Deduplication enabled
     pub dedup_enabled: bool,
 
@@ -68,7 +87,7 @@ pub struct Superblock {
     pub compression_enabled: bool,
 
     /// Padding to 4KB
-    pub _reserved: [u8; 3806],
+    pub _reserved: [u8; 3790],
 }
 
 impl Superblock {
@@ -92,10 +111,64 @@ impl Superblock {
             last_write_time: 0,
             last_check_time: 0,
             state: 0,
+            checksum: 0,
             snapshot_root: 0,
+            journal_blocks: 0,
+            journal_sequence: 0,
             dedup_enabled: true,
             compression_enabled: true,
-            _reserved: [0; 3806],
+            _reserved: [0; 3790],
+        }
+    }
+
+    /// CRC32 of the superblock as stored, ignoring the checksum field
+    pub fn compute_checksum(&self) -> u32 {
+        let mut copy = *self;
+        copy.checksum = 0;
+        crc32(copy.as_bytes())
+    }
+
+    /// Update the checksum; call before every write to disk
+    pub fn seal(&mut self) {
+        self.checksum = self.compute_checksum();
+    }
+
+    /// Whether this copy is an SFS superblock that was written whole
+    pub fn is_valid(&self) -> bool {
+        self.magic == SFS_MAGIC && self.checksum == self.compute_checksum()
+    }
+
+    /// First block of the journal
+    pub fn journal_start(&self) -> u64 {
+        self.total_blocks
+    }
+
+    /// Block holding the backup copy
+    pub fn backup_block(&self) -> u64 {
+        self.total_blocks + self.journal_blocks
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, SUPERBLOCK_SIZE) }
+    }
+
+    /// Copy into the start of a block buffer (at least `SUPERBLOCK_SIZE` long)
+    pub fn write_to(&self, block: &mut [u8]) {
+        block[..SUPERBLOCK_SIZE].copy_from_slice(self.as_bytes());
+    }
+
+    /// Read a copy from the start of a block buffer; `None` if it is not
+    /// a valid superblock
+    pub fn read_from(block: &[u8]) -> Option<Self> {
+        if block.len() < SUPERBLOCK_SIZE {
+            return None;
+        }
+        // The flag bytes must be 0 or 1 before they can be read as bools
+        let flags = core::mem::offset_of!(Superblock, dedup_enabled);
+        if block[flags] > 1 || block[flags + 1] > 1 {
+            return None;
         }
+        let superblock = unsafe { core::ptr::read_unaligned(block.as_ptr() as *const Superblock) };
+        superblock.is_valid().then_some(superblock)
     }
 }
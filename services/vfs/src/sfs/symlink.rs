@@ -0,0 +1,43 @@
+//! SFS symbolic links
+//!
+//! A symlink inode's size is the length of its target path. Targets that
+//! fit in the inode's inline data are kept there; longer ones take one
+//! data block.
+
+use alloc::string::String;
+
+use super::BLOCK_SIZE;
+
+/// Links one lookup may follow before giving up with `TooManyLinks`
+pub const MAX_SYMLINK_DEPTH: usize = 8;
+
+/// Longest target stored inline in the inode
+pub const INLINE_TARGET_LEN: usize = 60;
+
+/// Longest target a symlink can hold
+pub const MAX_TARGET_LEN: usize = BLOCK_SIZE;
+
+/// `path` with its component `index` (counting non-empty components)
+/// replaced by a link's `target`. A relative target is taken from the
+/// directory holding the link; an absolute one drops everything before it.
+/// The result has no empty components (and is empty for the root).
+pub fn splice_target(path: &str, index: usize, target: &str) -> String {
+    let mut spliced = String::new();
+    if !target.starts_with('/') {
+        for component in path.split('/').filter(|c| !c.is_empty()).take(index) {
+            spliced.push('/');
+            spliced.push_str(component);
+        }
+    }
+
+    for component in target.split('/').filter(|c| !c.is_empty()) {
+        spliced.push('/');
+        spliced.push_str(component);
+    }
+
+    for component in path.split('/').filter(|c| !c.is_empty()).skip(index + 1) {
+        spliced.push('/');
+        spliced.push_str(component);
+    }
+    spliced
+}
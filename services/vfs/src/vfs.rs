@@ -1,7 +1,11 @@
 //! VFS implementation for VFS service
 
+extern crate alloc;
+use alloc::boxed::Box;
 use core::mem;
 
+use crate::file_ops::FileSystemOps;
+
 /// File descriptor entry
 #[repr(C)]
 pub struct FdEntry {
@@ -19,6 +23,7 @@ pub struct MountPoint {
     pub fs_id: u64,
     pub device: [u8; 256],
     pub next: u64,  // Pointer to next mount
+    pub fs: Option<Box<dyn FileSystemOps>>,  // Serves the mount, if linked in
 }
 
 const MAX_FDS: usize = 256;
@@ -81,8 +86,53 @@ pub fn get_fd_entry(fd: i32) -> Option<&'static mut FdEntry> {
     }
 }
 
+/// Filesystem ID for mounting `fs_type` on `device`, or None if `device`
+/// names a partition the disk does not have
+pub fn mount_fs_id(device: &[u8], fs_type: &[u8]) -> Option<u64> {
+    // A partition name (`/dev/sda1`) must name a partition on the disk
+    let partition = crate::partition::find_partition(device);
+    if partition.is_none() && matches!(crate::partition::parse_device_name(device), Some((_, number)) if number > 0) {
+        return None;
+    }
+    
+    // No explicit type ("" or "auto"): pick one from the partition table
+    let fs_type = if fs_type.is_empty() || fs_type == b"auto" {
+        partition
+            .and_then(|part| part.fs_type_hint())
+            .unwrap_or(fs_type)
+    } else {
+        fs_type
+    };
+    
+    // Look up filesystem type and get fs_id
+    // Filesystem type mapping:
+    // "sfs" -> 1 (Scarlett File System)
+    // "fat32" -> 2
+    // "ext4" -> 3
+    // "ntfs" -> 4
+    let fstype_str = core::str::from_utf8(fs_type).unwrap_or("");
+    Some(if fstype_str == "sfs" {
+        1
+    } else if fstype_str == "fat32" {
+        2
+    } else if fstype_str == "ext4" {
+        3
+    } else if fstype_str == "ntfs" {
+        4
+    } else {
+        0  // Unknown filesystem type
+    })
+}
+
 /// Mount filesystem
 pub fn vfs_mount(device: &[u8], mountpoint: &[u8], fs_type: &[u8]) -> Result<(), ()> {
+    let fs_id = mount_fs_id(device, fs_type).ok_or(())?;
+    vfs_mount_fs(device, mountpoint, fs_id, None)
+}
+
+/// Mount filesystem `fs_id` on `device`, served by `fs` (already mounted
+/// on the device) if the service has a driver for it
+pub fn vfs_mount_fs(device: &[u8], mountpoint: &[u8], fs_id: u64, fs: Option<Box<dyn FileSystemOps>>) -> Result<(), ()> {
     unsafe {
         if MOUNT_COUNT >= MAX_MOUNTS {
             return Err(());
@@ -100,24 +150,8 @@ pub fn vfs_mount(device: &[u8], mountpoint: &[u8], fs_type: &[u8]) -> Result<(),
         mount.device[0..dev_len].copy_from_slice(&device[0..dev_len]);
         mount.device[dev_len] = 0;
         
-        // Look up filesystem type and get fs_id
-        // Filesystem type mapping:
-        // "sfs" -> 1 (Scarlett File System)
-        // "fat32" -> 2
-        // "ext4" -> 3
-        // "ntfs" -> 4
-        let fstype_str = core::str::from_utf8(fs_type).unwrap_or("");
-        mount.fs_id = if fstype_str == "sfs" {
-            1
-        } else if fstype_str == "fat32" {
-            2
-        } else if fstype_str == "ext4" {
-            3
-        } else if fstype_str == "ntfs" {
-            4
-        } else {
-            0  // Unknown filesystem type
-        };
+        mount.fs_id = fs_id;
+        mount.fs = fs;
         
         // If mounting at root, set as root mount
         if mountpoint.len() == 1 && mountpoint[0] == b'/' {
@@ -129,6 +163,63 @@ pub fn vfs_mount(device: &[u8], mountpoint: &[u8], fs_type: &[u8]) -> Result<(),
     }
 }
 
+/// Whether a mount's device is disk `port_idx` or one of its partitions
+/// (`/dev/sdb`, `/dev/sdb1`)
+fn device_on_disk(device: &[u8], port_idx: u8) -> bool {
+    matches!(crate::partition::parse_device_name(device), Some((port, _)) if port == port_idx)
+}
+
+/// Drop every mount backed by the disk behind device node `node` (the
+/// device went away): a whole-disk name or the device manager node the
+/// disk was attached as. Returns how many were removed.
+pub fn vfs_unmount_device(node: &[u8]) -> usize {
+    let port_idx = match crate::partition::disk_on_node(node) {
+        Some(port_idx) => port_idx,
+        None => return 0,
+    };
+
+    unsafe {
+        let mut removed = 0;
+        let mut i = 0;
+        while i < MOUNT_COUNT {
+            let device = &MOUNT_POINTS[i].device;
+            let len = device.iter().position(|&b| b == 0).unwrap_or(device.len());
+            if !device_on_disk(&device[..len], port_idx) {
+                i += 1;
+                continue;
+            }
+
+            // Move the last mount into the hole; the device is gone, so
+            // there is nothing to flush the filesystem to
+            let last = MOUNT_COUNT - 1;
+            core::ptr::swap(&mut MOUNT_POINTS[i], &mut MOUNT_POINTS[last]);
+            MOUNT_POINTS[last].fs = None;
+            if ROOT_MOUNT == i {
+                ROOT_MOUNT = MAX_MOUNTS; // Root filesystem is gone
+            } else if ROOT_MOUNT == last {
+                ROOT_MOUNT = i;
+            }
+            MOUNT_COUNT -= 1;
+            removed += 1;
+        }
+        removed
+    }
+}
+
+/// Whether any mount is backed by `device` itself
+pub fn vfs_device_mounted(device: &[u8]) -> bool {
+    unsafe {
+        for i in 0..MOUNT_COUNT {
+            let mounted = &MOUNT_POINTS[i].device;
+            let len = mounted.iter().position(|&b| b == 0).unwrap_or(mounted.len());
+            if &mounted[..len] == device {
+                return true;
+            }
+        }
+        false
+    }
+}
+
 /// Resolve path to mount point
 pub fn resolve_path(path: &[u8]) -> Option<usize> {
     unsafe {
@@ -173,3 +264,38 @@ pub fn get_mount_fs_id(mount_idx: usize) -> u64 {
     }
 }
 
+/// Filesystem serving a mount, or None if the service has no driver for
+/// its type
+pub fn get_mount_fs(mount_idx: usize) -> Option<&'static mut dyn FileSystemOps> {
+    unsafe {
+        if mount_idx < MOUNT_COUNT {
+            MOUNT_POINTS[mount_idx].fs.as_deref_mut()
+        } else {
+            None
+        }
+    }
+}
+
+/// `path` as seen from inside the mount `resolve_path` picked for it
+/// (`/mnt/a/x` on `/mnt/a` is `/x`), or None if the mount does not
+/// really cover it (`/mnt/ab` on `/mnt/a`)
+pub fn path_in_mount(mount_idx: usize, path: &[u8]) -> Option<&[u8]> {
+    unsafe {
+        if mount_idx >= MOUNT_COUNT {
+            return None;
+        }
+        let mountpoint = &MOUNT_POINTS[mount_idx].mountpoint;
+        let len = mountpoint.iter().position(|&b| b == 0).unwrap_or(mountpoint.len());
+        let mountpoint = &mountpoint[..len];
+        
+        // Paths no mount prefixes fall through to the root filesystem
+        if mountpoint == b"/" || !path.starts_with(mountpoint) {
+            return Some(path);
+        }
+        match &path[mountpoint.len()..] {
+            [] => Some(b"/"),
+            rest if rest[0] == b'/' => Some(rest),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! ACPI FADT Tests
+//!
+//! Tests for FADT field parsing, checksum validation and the \_S5 lookup
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/acpi/src/fadt.rs"]
+mod fadt;
+
+use fadt::{find_s5_sleep_type, pm1_sleep_value, Fadt, GAS_SYSTEM_IO, PM1_CNT_SLP_EN};
+
+const FADT_LEN: usize = 244;
+
+/// Build an ACPI 2.0 style FADT with legacy PM1 blocks and a reset register
+fn build_fadt() -> [u8; FADT_LEN] {
+    let mut table = [0u8; FADT_LEN];
+    table[0..4].copy_from_slice(b"FACP");
+    table[4..8].copy_from_slice(&(FADT_LEN as u32).to_le_bytes());
+    table[40..44].copy_from_slice(&0x7FE0_0000u32.to_le_bytes()); // DSDT
+    table[48..52].copy_from_slice(&0xB2u32.to_le_bytes()); // SMI_CMD
+    table[52] = 0xF1; // ACPI_ENABLE
+    table[64..68].copy_from_slice(&0x604u32.to_le_bytes()); // PM1a_CNT_BLK
+    table[112..116].copy_from_slice(&(1u32 << 10).to_le_bytes()); // RESET_REG_SUP
+    // RESET_REG: system I/O, 8 bits, port 0xCF9
+    table[116] = GAS_SYSTEM_IO;
+    table[117] = 8;
+    table[120..128].copy_from_slice(&0xCF9u64.to_le_bytes());
+    table[128] = 0x06;
+    fix_checksum(&mut table);
+    table
+}
+
+fn fix_checksum(table: &mut [u8]) {
+    table[9] = 0;
+    let sum = table.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    table[9] = 0u8.wrapping_sub(sum);
+}
+
+/// Test that the PM1 blocks and reset register are read
+pub fn test_parse_fadt() -> bool {
+    let table = build_fadt();
+
+    match Fadt::parse(&table) {
+        Some(fadt) => {
+            fadt.dsdt == 0x7FE0_0000
+                && fadt.smi_cmd == 0xB2
+                && fadt.acpi_enable == 0xF1
+                && fadt.pm1a_cnt.space_id == GAS_SYSTEM_IO
+                && fadt.pm1a_cnt.address == 0x604
+                && fadt.pm1b_cnt.is_none()
+                && matches!(fadt.reset_reg, Some(reg) if reg.address == 0xCF9)
+                && fadt.reset_value == 0x06
+        }
+        None => false,
+    }
+}
+
+/// Test that a table with a bad checksum is rejected
+pub fn test_bad_checksum() -> bool {
+    let mut table = build_fadt();
+    table[128] = 0x0E;
+
+    Fadt::parse(&table).is_none()
+}
+
+/// Test the \_S5 package lookup with byte-prefixed and constant elements
+pub fn test_find_s5() -> bool {
+    let mut dsdt = [0u8; 64];
+    dsdt[0..4].copy_from_slice(b"DSDT");
+    // Name (\_S5, Package (0x04) { 0x05, Zero, Zero, Zero })
+    let aml = [0x08, b'\\', b'_', b'S', b'5', b'_', 0x12, 0x08, 0x04, 0x0A, 0x05, 0x00, 0x00, 0x00];
+    dsdt[40..40 + aml.len()].copy_from_slice(&aml);
+
+    // A method call mentioning _S5_ must not match
+    let mut decoy = [0u8; 64];
+    decoy[40..46].copy_from_slice(&[0x14, b'_', b'S', b'5', b'_', 0x00]);
+
+    find_s5_sleep_type(&dsdt) == Some((5, 0)) && find_s5_sleep_type(&decoy).is_none()
+}
+
+/// Test the PM1 control value keeps unrelated bits
+pub fn test_sleep_value() -> bool {
+    let value = pm1_sleep_value(0x1C01, 5);
+
+    value == (0x0001 | (5 << 10) | PM1_CNT_SLP_EN)
+}
+
+/// Run all FADT tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 4] = [
+        test_parse_fadt,
+        test_bad_checksum,
+        test_find_s5,
+        test_sleep_value,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
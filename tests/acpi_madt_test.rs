@@ -0,0 +1,104 @@
+//! ACPI MADT Tests
+//!
+//! Tests for parsing local APICs, I/O APICs and interrupt source overrides
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/acpi/src/fadt.rs"]
+mod fadt;
+#[path = "../services/acpi/src/madt.rs"]
+mod madt;
+
+use madt::Madt;
+
+/// Append an entry, returning the new length
+fn push(table: &mut [u8], len: usize, entry: &[u8]) -> usize {
+    table[len..len + entry.len()].copy_from_slice(entry);
+    len + entry.len()
+}
+
+/// Build a QEMU-style MADT: two CPUs (one disabled), one I/O APIC and
+/// the IRQ0 -> GSI2 override
+fn build_madt(table: &mut [u8; 128]) -> usize {
+    table[0..4].copy_from_slice(b"APIC");
+    table[36..40].copy_from_slice(&0xFEE0_0000u32.to_le_bytes());
+
+    let mut len = 44;
+    len = push(table, len, &[0, 8, 0, 0, 1, 0, 0, 0]);
+    len = push(table, len, &[0, 8, 1, 1, 1, 0, 0, 0]);
+    len = push(table, len, &[0, 8, 2, 2, 0, 0, 0, 0]);
+    len = push(table, len, &[1, 12, 0, 0, 0x00, 0x00, 0xC0, 0xFE, 0, 0, 0, 0]);
+    len = push(table, len, &[2, 10, 0, 0, 2, 0, 0, 0, 0, 0]);
+
+    table[4..8].copy_from_slice(&(len as u32).to_le_bytes());
+    let sum = table[..len].iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    table[9] = 0u8.wrapping_sub(sum);
+    len
+}
+
+/// Test CPU and I/O APIC discovery
+pub fn test_parse_topology() -> bool {
+    let mut table = [0u8; 128];
+    let len = build_madt(&mut table);
+
+    match Madt::parse(&table[..len]) {
+        Some(madt) => {
+            madt.local_apic_address == 0xFEE0_0000
+                && madt.cpus() == &[0, 1]
+                && madt.ioapics().len() == 1
+                && madt.ioapics()[0].address == 0xFEC0_0000
+                && madt.ioapics()[0].gsi_base == 0
+        }
+        None => false,
+    }
+}
+
+/// Test that overrides remap ISA IRQs and others pass through
+pub fn test_interrupt_override() -> bool {
+    let mut table = [0u8; 128];
+    let len = build_madt(&mut table);
+
+    match Madt::parse(&table[..len]) {
+        Some(madt) => madt.isa_irq_to_gsi(0) == 2 && madt.isa_irq_to_gsi(1) == 1,
+        None => false,
+    }
+}
+
+/// Test that a truncated entry ends parsing instead of overrunning
+pub fn test_truncated_entry() -> bool {
+    let mut table = [0u8; 128];
+    let mut len = build_madt(&mut table);
+
+    // An I/O APIC entry claiming 12 bytes with only 4 present
+    len = push(&mut table, len, &[1, 12, 0, 0]);
+    table[4..8].copy_from_slice(&(len as u32).to_le_bytes());
+    table[9] = 0;
+    let sum = table[..len].iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    table[9] = 0u8.wrapping_sub(sum);
+
+    matches!(Madt::parse(&table[..len]), Some(madt) if madt.ioapics().len() == 1)
+}
+
+/// Run all MADT tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_parse_topology,
+        test_interrupt_override,
+        test_truncated_entry,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
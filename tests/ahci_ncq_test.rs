@@ -0,0 +1,101 @@
+//! AHCI NCQ Tests
+//!
+//! Tests for FPDMA command encoding, queue depth negotiation and command
+//! slot tracking in the AHCI driver
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/storage/ahci/src/ncq.rs"]
+mod ncq;
+
+use ncq::*;
+
+/// Test that FPDMA QUEUED puts the count in the features registers and
+/// the tag in count bits 7:3
+pub fn test_fpdma_fis() -> bool {
+    let fis = fpdma_fis(true, 0x0000_1234_5678_9ABC, 0x0102, 17);
+    let legacy = dma_fis(false, 0x0ABC_DEF0, 8, false);
+
+    fis[2] == ATA_CMD_WRITE_FPDMA_QUEUED
+        && fis[3] == 0x02
+        && fis[11] == 0x01
+        && fis[12] == 17 << 3
+        && fis[13] == 0
+        && fis[4..7] == [0xBC, 0x9A, 0x78]
+        && fis[8..11] == [0x56, 0x34, 0x12]
+        && legacy[2] == ATA_CMD_READ_DMA
+        && legacy[7] == 0x40 | 0x0A
+        && legacy[8..11] == [0, 0, 0]
+        && legacy[12] == 8
+}
+
+/// Test that queuing needs both HBA and drive support and is limited by
+/// the smaller of the two
+pub fn test_queue_depth() -> bool {
+    // 32 slots with NCQ, 8 slots without
+    let cap_ncq = AHCI_CAP_SNCQ | (31 << 8);
+    let cap_plain = 7 << 8;
+    let drive = ncq_depth(31, 1 << 8);
+
+    drive == Some(32)
+        && ncq_depth(31, 0).is_none()
+        && command_slots(cap_plain) == 8
+        && queue_depth(cap_ncq, drive) == 32
+        && queue_depth(cap_ncq | (7 << 8), Some(32)) == 32
+        && queue_depth(AHCI_CAP_SNCQ | (3 << 8), drive) == 4
+        && queue_depth(cap_plain, drive) == 1
+        && queue_depth(cap_ncq, None) == 1
+}
+
+/// Test that requests fill slots up to the depth, are handed back when
+/// all are busy, and are finished once clear in both PxSACT and PxCI
+pub fn test_slot_table() -> bool {
+    let mut table = SlotTable::new(2);
+    let first = table.allocate(10u32);
+    let second = table.allocate(11u32);
+    let full = table.allocate(12u32);
+
+    // Slot 0 is done; slot 1 is still active in PxSACT
+    let finished = table.finished(1 << 1, 0);
+    let taken = table.take(0);
+    let reused = table.allocate(13u32);
+
+    // Slot 1 is done too but PxCI still shows slot 0 issued
+    let finished_later = table.finished(0, 1 << 0);
+    let last = table.take(1);
+    table.take(0);
+
+    first == Ok(0)
+        && second == Ok(1)
+        && full == Err(12)
+        && finished == 1 << 0
+        && taken == Some(10)
+        && reused == Ok(0)
+        && finished_later == 1 << 1
+        && last == Some(11)
+        && table.is_idle()
+}
+
+/// Run all AHCI NCQ tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_fpdma_fis,
+        test_queue_depth,
+        test_slot_table,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
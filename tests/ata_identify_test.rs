@@ -0,0 +1,67 @@
+//! ATA IDENTIFY Tests
+//!
+//! Tests for decoding strings from ATA IDENTIFY DEVICE data
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/storage/ata/src/identify.rs"]
+mod identify;
+
+use identify::*;
+
+/// Pack a string the way a drive reports it
+fn pack(text: &[u8], words: &mut [u16]) {
+    for (i, word) in words.iter_mut().enumerate() {
+        let hi = *text.get(i * 2).unwrap_or(&b' ');
+        let lo = *text.get(i * 2 + 1).unwrap_or(&b' ');
+        *word = u16::from_be_bytes([hi, lo]);
+    }
+}
+
+/// Test that the model comes out in reading order without padding
+pub fn test_model_string() -> bool {
+    let mut data = [0u16; 256];
+    pack(b"QEMU HARDDISK", &mut data[IDENTIFY_MODEL]);
+
+    let mut model = [0u8; 40];
+    let len = identify_string(&data[IDENTIFY_MODEL], &mut model);
+    &model[..len] == b"QEMU HARDDISK"
+}
+
+/// Test that an all-padding field is empty and a short buffer is not
+/// overrun
+pub fn test_blank_and_short() -> bool {
+    let mut data = [0u16; 256];
+    pack(b"", &mut data[IDENTIFY_MODEL]);
+    let mut model = [0u8; 40];
+    let blank = identify_string(&data[IDENTIFY_MODEL], &mut model);
+
+    pack(b"ABCDEFGH", &mut data[IDENTIFY_MODEL]);
+    let mut short = [0u8; 4];
+    let len = identify_string(&data[IDENTIFY_MODEL], &mut short);
+
+    blank == 0 && len == 4 && short == *b"ABCD"
+}
+
+/// Run all ATA IDENTIFY tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 2] = [
+        test_model_string,
+        test_blank_and_short,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
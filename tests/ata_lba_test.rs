@@ -0,0 +1,50 @@
+//! ATA LBA Tests
+//!
+//! Tests for the transfer limits of LBA28 and LBA48 commands
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/storage/ata/src/lba.rs"]
+mod lba;
+
+use lba::*;
+
+/// Test that LBA28 stops at 256 sectors and the 128 GiB mark
+pub fn test_lba28_limits() -> bool {
+    request_fits(false, 0, LBA28_MAX_SECTORS)
+        && request_fits(false, LBA28_LIMIT - 1, 1)
+        && !request_fits(false, 0, LBA28_MAX_SECTORS + 1)
+        && !request_fits(false, LBA28_LIMIT - 1, 2)
+        && !request_fits(false, 0, 0)
+}
+
+/// Test that LBA48 reaches past 128 GiB with up to 65536 sectors
+pub fn test_lba48_limits() -> bool {
+    request_fits(true, LBA28_LIMIT, LBA48_MAX_SECTORS)
+        && !request_fits(true, 0, LBA48_MAX_SECTORS + 1)
+        && !request_fits(true, LBA48_LIMIT - 1, 2)
+        && !request_fits(true, u64::MAX, 1)
+}
+
+/// Run all ATA LBA tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 2] = [
+        test_lba28_limits,
+        test_lba48_limits,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
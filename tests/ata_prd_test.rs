@@ -0,0 +1,64 @@
+//! ATA PRD Table Tests
+//!
+//! Tests for describing DMA buffers to the bus-master IDE controller
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/storage/ata/src/prd.rs"]
+mod prd;
+
+use prd::*;
+
+/// Test that a buffer inside one 64 KiB region takes a single descriptor
+/// flagged as the last
+pub fn test_single_region() -> bool {
+    let mut table = [Prd::EMPTY; 4];
+    let count = build_prd_table(0x20_0000, 4096, &mut table);
+
+    count == Some(1)
+        && table[0] == Prd { phys: 0x20_0000, byte_count: 4096, flags: PRD_END_OF_TABLE }
+}
+
+/// Test that a buffer crossing a 64 KiB boundary is split there, and that
+/// a full region is encoded as a byte count of 0
+pub fn test_boundary_split() -> bool {
+    let mut table = [Prd::EMPTY; 4];
+    let count = build_prd_table(0x1_F000, 0x1_1000, &mut table);
+
+    count == Some(2)
+        && table[0] == Prd { phys: 0x1_F000, byte_count: 0x1000, flags: 0 }
+        && table[1] == Prd { phys: 0x2_0000, byte_count: 0, flags: PRD_END_OF_TABLE }
+}
+
+/// Test that buffers the controller cannot reach are rejected
+pub fn test_rejects_unusable() -> bool {
+    let mut table = [Prd::EMPTY; 1];
+    build_prd_table(0x1000, 0, &mut table).is_none()
+        && build_prd_table(0x1000, 511, &mut table).is_none()
+        && build_prd_table(0xFFFF_F000, 0x2000, &mut table).is_none()
+        && build_prd_table(0xF000, 0x2000, &mut table).is_none()
+}
+
+/// Run all ATA PRD tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_single_region,
+        test_boundary_split,
+        test_rejects_unusable,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,132 @@
+//! Audio Mixer Tests
+//!
+//! Tests for the audio server's stream mixer
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[path = "../services/audio/src/mixer.rs"]
+mod mixer;
+#[path = "../services/audio/src/resample.rs"]
+mod resample;
+
+use mixer::*;
+
+fn pcm(samples: &[i16], out: &mut [u8]) -> usize {
+    for (bytes, sample) in out.chunks_exact_mut(2).zip(samples) {
+        bytes.copy_from_slice(&sample.to_le_bytes());
+    }
+    samples.len() * 2
+}
+
+fn sample(out: &[u8], index: usize) -> i16 {
+    i16::from_le_bytes([out[index * 2], out[index * 2 + 1]])
+}
+
+/// Test that two streams are summed with their gains and saturate
+pub fn test_mix_two_streams() -> bool {
+    let mut mixer = Mixer::new();
+    let (a, b) = match (mixer.open(1, 0), mixer.open(2, 0)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return false,
+    };
+
+    let mut buf = [0u8; 8];
+    let len = pcm(&[1000, -1000, 30000, 30000], &mut buf);
+    let _ = mixer.write(a, 1, &buf[..len]);
+    let len = pcm(&[500, 500, 30000, 30000], &mut buf);
+    let _ = mixer.write(b, 2, &buf[..len]);
+    mixer.set_volume(b, 2, 50);
+
+    let mut out = [0u8; 12];
+    mixer.mix(&mut out);
+
+    sample(&out, 0) == 1250
+        && sample(&out, 1) == -750
+        && sample(&out, 2) == i16::MAX
+        && sample(&out, 4) == 0
+        && mixer.write(a, 1, &[0u8; 4]) == Some(4)
+}
+
+/// Test that a stream at another rate is converted to the device rate
+pub fn test_stream_resampled() -> bool {
+    let mut mixer = Mixer::new();
+    let a = match mixer.open(1, MIX_SAMPLE_RATE / 2) {
+        Some(a) => a,
+        None => return false,
+    };
+
+    let mut buf = [0u8; 8];
+    let len = pcm(&[0, 0, 1000, -1000], &mut buf);
+    let _ = mixer.write(a, 1, &buf[..len]);
+
+    // Each input frame becomes two, the new one halfway between
+    let mut out = [0u8; 8];
+    mixer.mix(&mut out);
+    sample(&out, 0) == 0 && sample(&out, 2) == 500 && sample(&out, 3) == -500
+}
+
+/// Test that a full stream takes only what fits and other streams are
+/// unaffected
+pub fn test_write_never_blocks() -> bool {
+    let mut mixer = Mixer::new();
+    let (a, b) = match (mixer.open(1, 0), mixer.open(2, 0)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return false,
+    };
+
+    let chunk = [0u8; 4096];
+    let mut taken = 0;
+    for _ in 0..16 {
+        taken += mixer.write(a, 1, &chunk).unwrap_or(0);
+    }
+
+    taken == STREAM_RING_SAMPLES * 2
+        && mixer.write(a, 1, &chunk) == Some(0)
+        && mixer.write(b, 2, &chunk[..7]) == Some(4)
+        && mixer.write(b, 1, &chunk).is_none()
+}
+
+/// Test the master volume scales the mix
+pub fn test_master_volume() -> bool {
+    let mut mixer = Mixer::new();
+    let a = match mixer.open(1, 0) {
+        Some(a) => a,
+        None => return false,
+    };
+
+    let mut buf = [0u8; 4];
+    let len = pcm(&[2000, -2000], &mut buf);
+    let _ = mixer.write(a, 1, &buf[..len]);
+    mixer.set_master_volume(25);
+
+    let mut out = [0u8; 4];
+    mixer.mix(&mut out);
+    sample(&out, 0) == 500 && sample(&out, 1) == -500
+}
+
+/// Run all audio mixer tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 4] = [
+        test_mix_two_streams,
+        test_write_never_blocks,
+        test_master_volume,
+        test_stream_resampled,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
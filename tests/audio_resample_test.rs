@@ -0,0 +1,78 @@
+//! Resampler Tests
+//!
+//! Tests for the audio server's sample rate conversion
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[path = "../services/audio/src/resample.rs"]
+mod resample;
+
+use alloc::vec::Vec;
+use resample::*;
+
+/// Test that upsampling interpolates and keeps channels apart
+pub fn test_interleaving() -> bool {
+    let input = [0i16, 100, 300, -100, 600, -300];
+    let out = resample(&input, 1, 2, 2);
+
+    out.len() == 12
+        && out[0..2] == [0, 100]
+        && out[2..4] == [150, 0]
+        && out[4..6] == [300, -100]
+        && out[6..8] == [450, -200]
+}
+
+/// Test that converting in chunks matches converting all at once
+pub fn test_chunks_match() -> bool {
+    let input: Vec<i16> = (0..4410).map(|i| ((i * 37) % 2000) as i16 - 1000).collect();
+    let whole = resample(&input, 44100, 48000, 1);
+
+    let mut resampler = Resampler::new(44100, 48000, 1);
+    let mut chunked = Vec::new();
+    for chunk in input.chunks(147) {
+        resampler.process(chunk, &mut chunked);
+    }
+    resampler.finish(&mut chunked);
+
+    whole == chunked
+}
+
+/// Test that output length tracks the exact ratio over long playback
+pub fn test_no_drift() -> bool {
+    let chunk = [0i16; 441];
+    let mut resampler = Resampler::new(44100, 48000, 1);
+    let mut out = Vec::new();
+    for _ in 0..1000 {
+        resampler.process(&chunk, &mut out);
+    }
+    resampler.finish(&mut out);
+
+    // 441000 input frames at 44.1 kHz are 10 s, 480000 frames at 48 kHz
+    out.len() == 480000
+}
+
+/// Run all resampler tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_interleaving,
+        test_chunks_match,
+        test_no_drift,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
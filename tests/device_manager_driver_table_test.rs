@@ -0,0 +1,77 @@
+//! Device Manager Driver Table Tests
+//!
+//! Tests for matching devices to driver binaries and encoding the device
+//! assignment sent to a spawned driver
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/device_manager/src/driver_table.rs"]
+mod driver_table;
+
+use driver_table::*;
+
+fn device(vendor_id: u16, device_id: u16, class_code: u8, subclass: u8, prog_if: u8) -> DeviceAssignment {
+    DeviceAssignment { vendor_id, device_id, class_code, subclass, prog_if, ..Default::default() }
+}
+
+/// Test that class matches honour prog-if only when the entry gives one
+pub fn test_class_match() -> bool {
+    let ahci = driver_binary_for(&device(0x8086, 0x2922, 0x01, 0x06, 0x01));
+    let ide = driver_binary_for(&device(0x8086, 0x7010, 0x01, 0x01, 0x80));
+    let ohci = driver_binary_for(&device(0x106B, 0x003F, 0x0C, 0x03, 0x10));
+    ahci.map(|b| b.path) == Some("/sbin/drivers/ahci")
+        && ide.map(|b| b.name) == Some("ata")
+        && ohci.is_none()
+}
+
+/// Test that exact ids are required for id-matched drivers
+pub fn test_id_match() -> bool {
+    let e1000 = driver_binary_for(&device(0x8086, 0x100E, 0x02, 0x00, 0x00));
+    let other_nic = driver_binary_for(&device(0x10EC, 0x8139, 0x02, 0x00, 0x00));
+    e1000.map(|b| b.name) == Some("ethernet") && other_nic.is_none()
+}
+
+/// Test the assignment wire layout
+pub fn test_encode_assignment() -> bool {
+    let mut info = device(0x8086, 0x100E, 0x02, 0x00, 0x00);
+    info.bus = 1;
+    info.device = 2;
+    info.function = 3;
+    info.irq_line = 11;
+    info.irq_pin = 1;
+    info.bars[0] = 0xFEBC_0000;
+    info.bars[5] = 0x1_0000_0000;
+
+    let mut out = [0u8; 64];
+    let mut short = [0u8; 16];
+    info.encode(&mut out) == ASSIGNMENT_SIZE
+        && out[0..8] == [1, 2, 3, 0x02, 0x00, 0x00, 11, 1]
+        && out[8..12] == [0x86, 0x80, 0x0E, 0x10]
+        && out[12..20] == 0xFEBC_0000u64.to_le_bytes()
+        && out[52..60] == 0x1_0000_0000u64.to_le_bytes()
+        && info.encode(&mut short) == 0
+}
+
+/// Run all driver table tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_class_match,
+        test_id_match,
+        test_encode_assignment,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,83 @@
+//! Device Manager Hotplug Tests
+//!
+//! Tests for device event encoding and the subscriber list
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/device_manager/src/hotplug.rs"]
+mod hotplug;
+
+use hotplug::*;
+
+/// Test that an event survives encoding and decoding
+pub fn test_event_round_trip() -> bool {
+    let event = DeviceEvent::new(DeviceEventKind::Removed, 7, 2, 0x0781, 0x5567, 0x08).with_name(b"/dev/usb7");
+
+    let mut buf = [0u8; 64];
+    let len = match event.encode(&mut buf) {
+        Some(len) => len,
+        None => return false,
+    };
+    let decoded = DeviceEvent::decode(DeviceEventKind::Removed, &buf[..len]);
+
+    // A truncated payload is rejected rather than misread
+    let truncated = DeviceEvent::decode(DeviceEventKind::Removed, &buf[..len - 1]).is_none();
+
+    len == EVENT_HEADER_SIZE + 9 && decoded == Some(event) && truncated && event.encode(&mut buf[..len - 1]).is_none()
+}
+
+/// Test that device node names carry the id in decimal
+pub fn test_node_names() -> bool {
+    let mut name = [0u8; MAX_NODE_NAME];
+
+    let zero = node_name(b"/dev/usb", 0, &mut name);
+    let zero_ok = &name[..zero] == b"/dev/usb0";
+    let large = node_name(b"/dev/usb", 255, &mut name);
+    let large_ok = &name[..large] == b"/dev/usb255";
+    // Long prefixes give way to the digits
+    let clipped = node_name(b"/dev/very-long-prefix", 12, &mut name);
+
+    zero_ok && large_ok && clipped == MAX_NODE_NAME && &name[clipped - 2..clipped] == b"12"
+}
+
+/// Test subscribing, duplicate subscriptions and unsubscribing
+pub fn test_subscribers() -> bool {
+    let mut subscribers = Subscribers::new();
+
+    let added = subscribers.subscribe(40).is_ok() && subscribers.subscribe(41).is_ok();
+    let duplicate = subscribers.subscribe(40).is_ok() && subscribers.ports().count() == 2;
+    let zero_refused = subscribers.subscribe(0).is_err();
+
+    subscribers.unsubscribe(40);
+    let remaining = subscribers.ports().eq([41].iter().copied());
+
+    let mut full = Subscribers::new();
+    let filled = (1..=MAX_SUBSCRIBERS as u64).all(|port| full.subscribe(port).is_ok());
+    let overflow = full.subscribe(1000).is_err();
+
+    added && duplicate && zero_refused && remaining && filled && overflow
+}
+
+/// Run all hotplug tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_event_round_trip,
+        test_node_names,
+        test_subscribers,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
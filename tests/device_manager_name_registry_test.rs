@@ -0,0 +1,74 @@
+//! Device Manager Name Registry Tests
+//!
+//! Tests for looking up service ports by name
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/device_manager/src/name_registry.rs"]
+mod name_registry;
+
+use name_registry::*;
+
+/// Test that registered names resolve and unknown ones do not
+pub fn test_register_and_lookup() -> bool {
+    let mut registry = NameRegistry::new();
+    registry.register(b"vfs", 7).is_ok()
+        && registry.register(b"network\0", 9).is_ok()
+        && registry.lookup(b"vfs") == Some(7)
+        && registry.lookup(b"network") == Some(9)
+        && registry.lookup(b"vf").is_none()
+        && registry.lookup(b"security").is_none()
+}
+
+/// Test that a restarted service re-registering replaces its port
+pub fn test_reregister_updates() -> bool {
+    let mut registry = NameRegistry::new();
+    let _ = registry.register(b"security", 3);
+    for _ in 0..MAX_NAMED_SERVICES {
+        if registry.register(b"security", 12).is_err() {
+            return false;
+        }
+    }
+    registry.lookup(b"security") == Some(12)
+}
+
+/// Test that empty or oversized names, port 0, and a full table are refused
+pub fn test_invalid_registrations() -> bool {
+    let mut registry = NameRegistry::new();
+    let long = [b'a'; MAX_SERVICE_NAME + 1];
+    let bad = registry.register(b"", 5).is_err()
+        && registry.register(&long, 5).is_err()
+        && registry.register(b"vfs", 0).is_err();
+
+    for i in 0..MAX_NAMED_SERVICES {
+        let name = [b'a' + (i % 26) as u8, b'0' + (i / 26) as u8];
+        if registry.register(&name, 100 + i as u64).is_err() {
+            return false;
+        }
+    }
+    bad && registry.register(b"extra", 5).is_err() && registry.lookup(b"a0") == Some(100)
+}
+
+/// Run all name registry tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_register_and_lookup,
+        test_reregister_updates,
+        test_invalid_registrations,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
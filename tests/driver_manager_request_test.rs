@@ -0,0 +1,131 @@
+//! Driver Manager Request Routing Tests
+//!
+//! Two clients send device requests through the driver manager to the same
+//! driver, which answers out of order; every response must reach the client
+//! that asked and no one else.
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/driver_manager/src/pending.rs"]
+mod pending;
+
+use pending::{PendingRequests, MAX_PENDING_REQUESTS};
+
+const DRIVER_ID: u32 = 1;
+const DRIVER_TID: u64 = 40;
+
+const CLIENT_A_PORT: u64 = 10;
+const CLIENT_B_PORT: u64 = 11;
+const MSG_DEVICE_REQUEST: u64 = 3;
+
+/// Test that interleaved requests from two clients are answered to the right port
+pub fn test_two_clients_no_cross_talk() -> bool {
+    let mut pending = PendingRequests::new();
+
+    let a = match pending.begin(DRIVER_ID, DRIVER_TID, CLIENT_A_PORT, MSG_DEVICE_REQUEST) {
+        Some(id) => id,
+        None => return false,
+    };
+    let b = match pending.begin(DRIVER_ID, DRIVER_TID, CLIENT_B_PORT, MSG_DEVICE_REQUEST) {
+        Some(id) => id,
+        None => return false,
+    };
+    if a == b {
+        return false;
+    }
+
+    // Driver answers B first
+    let first = pending.complete(b, DRIVER_TID);
+    let second = pending.complete(a, DRIVER_TID);
+
+    matches!(first, Some(r) if r.client_port == CLIENT_B_PORT)
+        && matches!(second, Some(r) if r.client_port == CLIENT_A_PORT)
+        && pending.is_empty()
+}
+
+/// Test that a response is delivered once, and only from the driver it went to
+pub fn test_response_matching() -> bool {
+    let mut pending = PendingRequests::new();
+
+    let id = match pending.begin(DRIVER_ID, DRIVER_TID, CLIENT_A_PORT, MSG_DEVICE_REQUEST) {
+        Some(id) => id,
+        None => return false,
+    };
+
+    // Another thread cannot answer on the driver's behalf
+    if pending.complete(id, DRIVER_TID + 1).is_some() {
+        return false;
+    }
+
+    // Unknown transaction ids are dropped
+    if pending.complete(id + 1, DRIVER_TID).is_some() {
+        return false;
+    }
+
+    pending.complete(id, DRIVER_TID).is_some() && pending.complete(id, DRIVER_TID).is_none()
+}
+
+/// Test that a full table refuses new requests until one completes
+pub fn test_table_full() -> bool {
+    let mut pending = PendingRequests::new();
+    let mut first = 0;
+
+    for i in 0..MAX_PENDING_REQUESTS {
+        match pending.begin(DRIVER_ID, DRIVER_TID, CLIENT_A_PORT, i as u64) {
+            Some(id) if i == 0 => first = id,
+            Some(_) => {}
+            None => return false,
+        }
+    }
+
+    if pending.begin(DRIVER_ID, DRIVER_TID, CLIENT_B_PORT, 0).is_some() {
+        return false;
+    }
+
+    pending.complete(first, DRIVER_TID);
+    pending.begin(DRIVER_ID, DRIVER_TID, CLIENT_B_PORT, 0).is_some()
+}
+
+/// Test that a crashed driver's requests are handed back for failing
+pub fn test_take_for_crashed_driver() -> bool {
+    let mut pending = PendingRequests::new();
+
+    pending.begin(DRIVER_ID, DRIVER_TID, CLIENT_A_PORT, MSG_DEVICE_REQUEST);
+    pending.begin(DRIVER_ID + 1, DRIVER_TID + 1, CLIENT_B_PORT, MSG_DEVICE_REQUEST);
+    pending.begin(DRIVER_ID, DRIVER_TID, CLIENT_B_PORT, MSG_DEVICE_REQUEST);
+
+    let mut failed = 0;
+    while let Some(request) = pending.take_for_driver(DRIVER_ID) {
+        if request.driver_id != DRIVER_ID {
+            return false;
+        }
+        failed += 1;
+    }
+
+    failed == 2 && pending.len() == 1
+}
+
+/// Run all driver manager request routing tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 4] = [
+        test_two_clients_no_cross_talk,
+        test_response_matching,
+        test_table_full,
+        test_take_for_crashed_driver,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,93 @@
+//! Driver Manager Restart Policy Tests
+//!
+//! Tests for restart backoff, the stable-period cooldown and the retry
+//! budget used when a driver crashes
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/driver_manager/src/restart.rs"]
+mod restart;
+
+use restart::*;
+
+/// Test that the restart delay doubles per crash and is capped
+pub fn test_backoff_growth() -> bool {
+    backoff_ms(1) == BASE_BACKOFF_MS
+        && backoff_ms(2) == BASE_BACKOFF_MS * 2
+        && backoff_ms(3) == BASE_BACKOFF_MS * 4
+        && backoff_ms(40) == MAX_BACKOFF_MS
+}
+
+/// Test that a driver crashing in a loop is given up on after the budget
+pub fn test_retry_budget() -> bool {
+    let mut tracker = RestartTracker::new(0);
+    let mut now = 0;
+
+    for crash in 1..=MAX_RESTARTS {
+        match tracker.crashed(now) {
+            CrashAction::RestartAt(at) if at == now + backoff_ms(crash) => {
+                // Comes back and dies again shortly after
+                now = at + 10;
+                tracker.started(now);
+            }
+            _ => return false,
+        }
+    }
+
+    tracker.crashed(now) == CrashAction::GiveUp
+}
+
+/// Test that crashes are forgiven after a stable period
+pub fn test_cooldown_resets_count() -> bool {
+    let mut tracker = RestartTracker::new(0);
+
+    tracker.crashed(0);
+    tracker.crashed(100);
+    tracker.started(1_000);
+
+    // Not yet stable
+    tracker.cool_down(1_000 + STABLE_PERIOD_MS - 1);
+    if tracker.crash_count() != 2 {
+        return false;
+    }
+
+    tracker.cool_down(1_000 + STABLE_PERIOD_MS);
+    tracker.crash_count() == 0
+}
+
+/// Test that a crash after a long run restarts with the shortest backoff
+pub fn test_crash_after_stable_run() -> bool {
+    let mut tracker = RestartTracker::new(0);
+
+    tracker.crashed(0);
+    tracker.crashed(0);
+    tracker.started(500);
+
+    let now = 500 + STABLE_PERIOD_MS;
+    tracker.crashed(now) == CrashAction::RestartAt(now + BASE_BACKOFF_MS) && tracker.crash_count() == 1
+}
+
+/// Run all restart policy tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 4] = [
+        test_backoff_growth,
+        test_retry_budget,
+        test_cooldown_resets_count,
+        test_crash_after_stable_run,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
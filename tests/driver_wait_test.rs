@@ -0,0 +1,87 @@
+//! Driver Wait Tests
+//!
+//! Tests for the cooperative wait drivers use in place of fixed sleeps,
+//! against a simulated millisecond clock
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/framework/src/wait.rs"]
+mod wait;
+
+use core::cell::Cell;
+use wait::*;
+
+/// Test that a wait ends as soon as its condition holds instead of after
+/// a fixed sleep: a drive ready at 3ms is seen at 3ms, where a 10ms sleep
+/// would have kept it waiting until 10ms
+pub fn test_wait_ends_when_done() -> bool {
+    let clock = Cell::new(100u64);
+    let yields = Cell::new(0u32);
+
+    let done = poll_until(
+        1000,
+        || clock.get() >= 103,
+        || clock.get(),
+        || {
+            yields.set(yields.get() + 1);
+            clock.set(clock.get() + 1);
+        },
+    );
+    let immediate = poll_until(1000, || true, || clock.get(), || yields.set(yields.get() + 100));
+
+    done && immediate && clock.get() - 100 == 3 && yields.get() == 3
+}
+
+/// Test that a condition that never holds gives up at the deadline
+pub fn test_wait_times_out() -> bool {
+    let clock = Cell::new(0u64);
+    let yields = Cell::new(0u32);
+
+    let done = poll_until(
+        5,
+        || false,
+        || clock.get(),
+        || {
+            yields.set(yields.get() + 1);
+            clock.set(clock.get() + 1);
+        },
+    );
+
+    !done && clock.get() == 5 && yields.get() == 5
+}
+
+/// Test that a condition met while yielding past the deadline still
+/// counts, and that a deadline near the end of the clock does not wrap
+pub fn test_wait_checks_after_deadline() -> bool {
+    let clock = Cell::new(0u64);
+    let late = poll_until(10, || clock.get() >= 50, || clock.get(), || clock.set(50));
+
+    let end = Cell::new(u64::MAX - 1);
+    let near_end = poll_until(10, || false, || end.get(), || end.set(u64::MAX));
+
+    late && !near_end && end.get() == u64::MAX
+}
+
+/// Run all driver wait tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_wait_ends_when_done,
+        test_wait_times_out,
+        test_wait_checks_after_deadline,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,197 @@
+//! E1000 RX Ring Tests
+//!
+//! Drives the RX ring bookkeeping against a model of the NIC that honours
+//! RDH/RDT ownership, checking that bursts larger than the ring are received
+//! without loss or duplication.
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/network/ethernet/src/rx_ring.rs"]
+mod rx_ring;
+
+use rx_ring::{RxAction, RxRing, RX_STATUS_DD, RX_STATUS_EOP};
+
+const RING_SIZE: usize = 32;
+const BURST: usize = 40;
+
+/// Minimal model of the E1000 receive side
+struct FakeNic {
+    rdh: usize,
+    rdt: usize,
+    status: [u8; RING_SIZE],
+    errors: [u8; RING_SIZE],
+    data: [u32; RING_SIZE],
+    /// Frames waiting in the on-chip FIFO for a free descriptor
+    fifo_next: usize,
+    fifo_end: usize,
+    /// Set if hardware ever wrote a descriptor the driver still owned
+    overwrote: bool,
+}
+
+impl FakeNic {
+    fn new(tail: usize) -> Self {
+        Self {
+            rdh: 0,
+            rdt: tail,
+            status: [0; RING_SIZE],
+            errors: [0; RING_SIZE],
+            data: [0; RING_SIZE],
+            fifo_next: 0,
+            fifo_end: 0,
+            overwrote: false,
+        }
+    }
+
+    /// Queue `count` frames as a back-to-back burst
+    fn burst(&mut self, count: usize) {
+        self.fifo_end += count;
+    }
+
+    /// Move up to `max` frames from the FIFO into free descriptors
+    fn dma(&mut self, max: usize) {
+        let mut moved = 0;
+        while moved < max && self.fifo_next < self.fifo_end && self.rdh != self.rdt {
+            if self.status[self.rdh] & RX_STATUS_DD != 0 {
+                self.overwrote = true;
+            }
+            self.data[self.rdh] = self.fifo_next as u32;
+            self.errors[self.rdh] = 0;
+            self.status[self.rdh] = RX_STATUS_DD | RX_STATUS_EOP;
+            self.rdh = (self.rdh + 1) % RING_SIZE;
+            self.fifo_next += 1;
+            moved += 1;
+        }
+    }
+}
+
+/// Mirror of `EthernetDriver::receive_packet` against the model
+fn receive(ring: &mut RxRing, nic: &mut FakeNic) -> Option<u32> {
+    loop {
+        let cur = ring.next_to_clean();
+        let action = ring.classify(nic.status[cur], nic.errors[cur]);
+        if action == RxAction::Empty {
+            return None;
+        }
+
+        let value = nic.data[cur];
+        nic.status[cur] = 0;
+        nic.rdt = ring.advance();
+
+        if action == RxAction::Deliver {
+            return Some(value);
+        }
+    }
+}
+
+/// Descriptors from RDH up to RDT belong to hardware; RDT itself never does
+fn tail_lags(ring: &RxRing, nic: &FakeNic) -> bool {
+    nic.rdt == ring.tail() && (ring.tail() + 1) % RING_SIZE == ring.next_to_clean()
+}
+
+/// Test that a fresh ring exposes all but one descriptor to hardware
+pub fn test_initial_tail() -> bool {
+    let ring = RxRing::new(RING_SIZE);
+    let mut nic = FakeNic::new(ring.tail());
+
+    nic.burst(BURST);
+    nic.dma(BURST);
+
+    // Hardware stops one short of the tail, leaving the rest in its FIFO
+    nic.rdh == RING_SIZE - 1 && nic.fifo_next == RING_SIZE - 1 && tail_lags(&ring, &nic)
+}
+
+/// Test that a 40 packet burst passes through a 32 entry ring intact
+pub fn test_burst_no_loss() -> bool {
+    let mut ring = RxRing::new(RING_SIZE);
+    let mut nic = FakeNic::new(ring.tail());
+    let mut seen = [false; BURST];
+    let mut expected = 0u32;
+
+    nic.burst(BURST);
+
+    // Let hardware fill the ring before the driver gets to run
+    nic.dma(BURST);
+
+    for _ in 0..BURST * 4 {
+        // Drain a few, then let hardware refill whatever was returned
+        for _ in 0..3 {
+            match receive(&mut ring, &mut nic) {
+                Some(value) => {
+                    if value != expected || seen[value as usize] {
+                        return false;
+                    }
+                    seen[value as usize] = true;
+                    expected += 1;
+                }
+                None => break,
+            }
+            if !tail_lags(&ring, &nic) {
+                return false;
+            }
+        }
+        nic.dma(5);
+    }
+
+    expected as usize == BURST
+        && seen.iter().all(|&s| s)
+        && !nic.overwrote
+        && receive(&mut ring, &mut nic).is_none()
+}
+
+/// Test that the ring keeps working across several wraps
+pub fn test_repeated_wrap() -> bool {
+    let mut ring = RxRing::new(RING_SIZE);
+    let mut nic = FakeNic::new(ring.tail());
+    let mut expected = 0u32;
+
+    for _ in 0..5 {
+        nic.burst(BURST);
+        loop {
+            nic.dma(BURST);
+            match receive(&mut ring, &mut nic) {
+                Some(value) if value == expected => expected += 1,
+                Some(_) => return false,
+                None => break,
+            }
+        }
+    }
+
+    expected as usize == BURST * 5 && !nic.overwrote && tail_lags(&ring, &nic)
+}
+
+/// Test that a frame spanning descriptors is dropped as a whole
+pub fn test_fragment_dropped() -> bool {
+    let mut ring = RxRing::new(RING_SIZE);
+
+    // First buffer of an oversized frame, then its EOP buffer, then a good frame
+    ring.classify(RX_STATUS_DD, 0) == RxAction::Drop
+        && { ring.advance(); ring.classify(RX_STATUS_DD | RX_STATUS_EOP, 0) == RxAction::Drop }
+        && { ring.advance(); ring.classify(RX_STATUS_DD | RX_STATUS_EOP, 0) == RxAction::Deliver }
+        && { ring.advance(); ring.classify(RX_STATUS_DD | RX_STATUS_EOP, 0x04) == RxAction::Drop }
+        && ring.classify(0, 0) == RxAction::Empty
+}
+
+/// Run all E1000 RX ring tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 4] = [
+        test_initial_tail,
+        test_burst_no_loss,
+        test_repeated_wrap,
+        test_fragment_dropped,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,85 @@
+//! Block Device Info Tests
+//!
+//! Tests for the geometry storage drivers report through GET_INFO
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/framework/src/block.rs"]
+mod block;
+
+use block::*;
+
+/// Test that a reply decodes back to the geometry and model it encoded
+pub fn test_encode_decode_round_trip() -> bool {
+    let info = BlockDeviceInfo::new(976_773_168, 4096, b"Samsung SSD 870");
+    let mut buf = [0u8; 64];
+    let len = info.encode(&mut buf);
+    let decoded = len.and_then(|len| BlockDeviceInfo::decode(&buf[..len]));
+
+    let too_small = info.encode(&mut [0u8; BLOCK_INFO_HEADER_SIZE]).is_none();
+
+    len == Some(BLOCK_INFO_HEADER_SIZE + 15)
+        && decoded == Some(info)
+        && info.capacity() == 976_773_168 * 4096
+        && too_small
+}
+
+/// Test that the space padding from IDENTIFY is trimmed and long models are cut
+pub fn test_model_trimmed_and_truncated() -> bool {
+    let padded = BlockDeviceInfo::new(2048, 512, b"QEMU HARDDISK        \0\0");
+    let trimmed = padded.model() == b"QEMU HARDDISK";
+
+    let long = BlockDeviceInfo::new(2048, 512, &[b'x'; 64]);
+    let truncated = long.model().len() == MAX_MODEL_LEN;
+
+    let blank = BlockDeviceInfo::new(2048, 512, b"    ").model().is_empty();
+
+    trimmed && truncated && blank
+}
+
+/// Test legacy replies without a model, and rejection of unusable replies
+pub fn test_decode_legacy_and_invalid() -> bool {
+    let mut legacy = [0u8; 12];
+    legacy[0..8].copy_from_slice(&8192u64.to_le_bytes());
+    legacy[8..12].copy_from_slice(&512u32.to_le_bytes());
+    let legacy_ok = matches!(
+        BlockDeviceInfo::decode(&legacy),
+        Some(info) if info.sectors == 8192 && info.sector_size == 512 && info.model().is_empty()
+    );
+
+    let mut zero_size = legacy;
+    zero_size[8..12].copy_from_slice(&0u32.to_le_bytes());
+    let zero_rejected = BlockDeviceInfo::decode(&zero_size).is_none();
+
+    // Model length pointing past the end of the reply
+    let mut cut = [0u8; 16];
+    cut[..12].copy_from_slice(&legacy);
+    cut[12] = 10;
+    let cut_rejected = BlockDeviceInfo::decode(&cut).is_none();
+
+    legacy_ok && zero_rejected && cut_rejected && BlockDeviceInfo::decode(&legacy[..8]).is_none()
+}
+
+/// Run all block device info tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_encode_decode_round_trip,
+        test_model_trimmed_and_truncated,
+        test_decode_legacy_and_invalid,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,86 @@
+//! Graphics Surface Tests
+//!
+//! Tests for pixel format conversion, damage tracking and clipped blits in
+//! the graphics service
+
+#![no_std]
+#![no_main]
+
+#[path = "../gui/graphics/src/surface.rs"]
+mod surface;
+
+use surface::*;
+
+/// Test conversion to 32-bit BGR-ordered and 16-bit 5:6:5 framebuffers
+pub fn test_pixel_formats() -> bool {
+    let xrgb32 = PixelFormat::from_masks(32, 0xFF0000, 0x00FF00, 0x0000FF).unwrap();
+    let bgr32 = PixelFormat::from_masks(32, 0x0000FF, 0x00FF00, 0xFF0000).unwrap();
+    let rgb565 = PixelFormat::from_masks(16, 0xF800, 0x07E0, 0x001F).unwrap();
+
+    xrgb32.encode(0x0012_3456) == 0x0012_3456
+        && bgr32.encode(0x0012_3456) == 0x0056_3412
+        && rgb565.bytes_per_pixel == 2
+        && rgb565.encode(0x00FF_FFFF) == 0xFFFF
+        && rgb565.encode(0x0080_4020) == (0x10 << 11) | (0x10 << 5) | 0x04
+        && PixelFormat::from_masks(8, 0xE0, 0x1C, 0x03).is_none()
+}
+
+/// Test that damage accumulates into one covering rect and is cleared
+pub fn test_damage_union() -> bool {
+    let mut damage = Damage::new();
+    damage.add(Rect { x: 10, y: 10, width: 5, height: 5 });
+    damage.add(Rect { x: 0, y: 20, width: 2, height: 2 });
+
+    damage.take() == Some(Rect { x: 0, y: 10, width: 15, height: 12 }) && damage.take().is_none()
+}
+
+/// Test that a blit hanging off the top-left corner copies only the
+/// on-screen part, from the matching source offset
+pub fn test_blit_clipped() -> bool {
+    // 4x4 screen, 32 bpp, pitch padded to 20 bytes
+    let mut pixels = [0u8; 20 * 4];
+    let format = PixelFormat::from_masks(32, 0xFF0000, 0x00FF00, 0x0000FF).unwrap();
+    let mut target = Target { pixels: &mut pixels, pitch: 20, width: 4, height: 4, format };
+
+    // 3x3 source, each pixel holding its index
+    let mut src = [0u8; 36];
+    for i in 0..9 {
+        src[i * 4] = i as u8;
+    }
+
+    let rect = Rect { x: -1, y: -2, width: 3, height: 3 };
+    if blit(&mut target, rect, &src, 12) != Some(Rect { x: 0, y: 0, width: 2, height: 1 }) {
+        return false;
+    }
+    // Only row 2, columns 1-2 of the source land on screen
+    if pixels[0] != 7 || pixels[4] != 8 || pixels[8] != 0 || pixels[20] != 0 {
+        return false;
+    }
+
+    // A source too short for the rect is refused
+    let mut target = Target { pixels: &mut pixels, pitch: 20, width: 4, height: 4, format };
+    blit(&mut target, Rect { x: 0, y: 0, width: 3, height: 4 }, &src, 12).is_none()
+}
+
+/// Run all tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_pixel_formats,
+        test_damage_union,
+        test_blit_clipped,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,60 @@
+//! HDA Codec Verb Tests
+//!
+//! Tests for CORB command encoding and RIRB response decoding
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/audio/hda/hda_codec.rs"]
+mod hda_codec;
+
+use hda_codec::*;
+
+/// Test encoding a 12-bit verb with an 8-bit payload
+pub fn test_get_parameter_verb() -> bool {
+    make_verb(2, 0x14, VERB_GET_PARAMETER, PARAM_NODE_COUNT as u16) == 0x214F_0004
+}
+
+/// Test encoding a 4-bit verb with a 16-bit payload
+pub fn test_set_amp_verb() -> bool {
+    make_verb(0, 0x02, VERB_SET_AMP_GAIN_MUTE, 0xB03F) == 0x0023_B03F
+}
+
+/// Test decoding solicited and unsolicited RIRB entries
+pub fn test_rirb_decode() -> bool {
+    let solicited = RirbEntry::decode(0x0000_0003_10EC_0269);
+    let unsolicited = RirbEntry::decode(0x0000_0010_0400_0000);
+
+    solicited == RirbEntry { response: 0x10EC_0269, codec_addr: 3, unsolicited: false }
+        && unsolicited.unsolicited
+}
+
+/// Test splitting vendor and node count parameters
+pub fn test_parameter_split() -> bool {
+    split_vendor_id(0x10EC_0269) == (0x10EC, 0x0269)
+        && split_node_count(0x0001_0001) == (1, 1)
+}
+
+/// Run all codec verb tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 4] = [
+        test_get_parameter_verb,
+        test_set_amp_verb,
+        test_rirb_decode,
+        test_parameter_split,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,131 @@
+//! HDA Widget Graph Tests
+//!
+//! Tests for connection list decoding, output pin ranking and DAC path search
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/audio/hda/hda_graph.rs"]
+mod hda_graph;
+
+use hda_graph::*;
+
+// Widget capability words by type
+const CAPS_DAC: u32 = 0x0 << 20;
+const CAPS_MIXER: u32 = (0x2 << 20) | WCAP_CONN_LIST;
+const CAPS_SELECTOR: u32 = (0x3 << 20) | WCAP_CONN_LIST;
+const CAPS_PIN: u32 = (0x4 << 20) | WCAP_CONN_LIST;
+
+// Pin configuration defaults: jack line out, fixed speaker, jack headphone
+const CONFIG_LINE_OUT: u32 = 0x0101_4010;
+const CONFIG_SPEAKER: u32 = 0x9017_0110;
+const CONFIG_HEADPHONE: u32 = 0x0221_4020;
+const CONFIG_UNUSED: u32 = 0x4000_0000;
+
+fn widget(nid: u8, caps: u32, connections: &[u8]) -> Widget {
+    let mut w = Widget::new(nid, caps);
+    w.connections[..connections.len()].copy_from_slice(connections);
+    w.num_connections = connections.len() as u8;
+    w
+}
+
+fn pin(nid: u8, config: u32, present: bool, connections: &[u8]) -> Widget {
+    let mut w = widget(nid, CAPS_PIN, connections);
+    w.pin_caps = PINCAP_OUTPUT | PINCAP_PRESENCE_DETECT;
+    w.pin_config = config;
+    w.present = present;
+    w
+}
+
+/// Test short and long form connection lists, including a range
+pub fn test_connection_list() -> bool {
+    let mut out = [0u8; MAX_CONNECTIONS];
+
+    // Short form: 0x02, 0x03, then a range up to 0x06
+    let n = decode_connection_list(&[0x0086_0302], 3, false, &mut out);
+    if out[..n] != [0x02, 0x03, 0x04, 0x05, 0x06] {
+        return false;
+    }
+
+    // Long form, two entries per response
+    let n = decode_connection_list(&[0x0021_0014, 0x0000_0022], 3, true, &mut out);
+    out[..n] == [0x14, 0x21, 0x22] && connection_list_length(0x83) == (3, true)
+}
+
+/// Test that speakers beat line out, and empty jacks lose to fixed pins
+pub fn test_pin_rank() -> bool {
+    let caps = PINCAP_OUTPUT;
+    pin_output_rank(caps, CONFIG_SPEAKER, false) == Some(0)
+        && pin_output_rank(caps, CONFIG_LINE_OUT, true) == Some(1)
+        && pin_output_rank(caps, CONFIG_HEADPHONE, false) == Some(5)
+        && pin_output_rank(caps, CONFIG_UNUSED, true).is_none()
+        && pin_output_rank(0, CONFIG_SPEAKER, true).is_none()
+}
+
+/// Test a path through a selector and a mixer with the right selections
+pub fn test_path_through_mixer() -> bool {
+    let widgets = [
+        widget(0x02, CAPS_DAC, &[]),
+        widget(0x03, CAPS_DAC, &[]),
+        widget(0x0C, CAPS_MIXER, &[0x03]),
+        widget(0x0D, CAPS_SELECTOR, &[0x0E, 0x0C]),
+        widget(0x0E, CAPS_MIXER, &[]),
+        pin(0x14, CONFIG_SPEAKER, false, &[0x0D]),
+    ];
+
+    match path_from_pin(&widgets, 0x14) {
+        Some(path) => path.nodes() == [0x14, 0x0D, 0x0C, 0x03] && path.select[1] == 1 && path.dac() == 0x03,
+        None => false,
+    }
+}
+
+/// Test that the best reachable pin is chosen
+pub fn test_find_output_path() -> bool {
+    let widgets = [
+        widget(0x02, CAPS_DAC, &[]),
+        // Speaker has no route to a DAC
+        pin(0x14, CONFIG_SPEAKER, true, &[0x20]),
+        pin(0x15, CONFIG_HEADPHONE, true, &[0x02]),
+        pin(0x16, CONFIG_LINE_OUT, true, &[0x02]),
+    ];
+
+    match find_output_path(&widgets) {
+        Some(path) => path.pin() == 0x16 && path.dac() == 0x02,
+        None => false,
+    }
+}
+
+/// Test that a connection loop does not hang the search
+pub fn test_path_loop() -> bool {
+    let widgets = [
+        widget(0x0C, CAPS_MIXER, &[0x0D]),
+        widget(0x0D, CAPS_MIXER, &[0x0C]),
+        pin(0x14, CONFIG_SPEAKER, true, &[0x0C]),
+    ];
+    path_from_pin(&widgets, 0x14).is_none()
+}
+
+/// Run all widget graph tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 5] = [
+        test_connection_list,
+        test_pin_rank,
+        test_path_through_mixer,
+        test_find_output_path,
+        test_path_loop,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,122 @@
+//! HPET Driver Tests
+//!
+//! Tests for HPET table parsing, counter conversion and one-shot timer
+//! bookkeeping
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/timer/hpet/src/hpet.rs"]
+mod hpet;
+
+#[path = "../drivers/timer/hpet/src/timers.rs"]
+mod timers;
+
+use hpet::*;
+use timers::*;
+
+fn build_table(space_id: u8, base: u64) -> [u8; HPET_TABLE_SIZE] {
+    let mut table = [0u8; HPET_TABLE_SIZE];
+    table[0..4].copy_from_slice(HPET_SIGNATURE);
+    table[4..8].copy_from_slice(&(HPET_TABLE_SIZE as u32).to_le_bytes());
+    table[40] = space_id;
+    table[44..52].copy_from_slice(&base.to_le_bytes());
+    table[52] = 0;
+    table[53..55].copy_from_slice(&0x80u16.to_le_bytes());
+    table
+}
+
+/// Test that the table yields the block address and rejects port I/O blocks
+pub fn test_parse_table() -> bool {
+    let parsed = parse_hpet_table(&build_table(0, 0xFED0_0000));
+    if parsed != Some(HpetTable { base: 0xFED0_0000, number: 0, min_tick: 0x80 }) {
+        return false;
+    }
+
+    let mut wrong_signature = build_table(0, 0xFED0_0000);
+    wrong_signature[0] = b'X';
+    parse_hpet_table(&build_table(1, 0xFED0_0000)).is_none()
+        && parse_hpet_table(&build_table(0, 0)).is_none()
+        && parse_hpet_table(&wrong_signature).is_none()
+        && parse_hpet_table(&build_table(0, 0xFED0_0000)[..40]).is_none()
+}
+
+/// Test capability decoding, tick conversion and 32-bit counter extension
+pub fn test_counter() -> bool {
+    // 14.318 MHz, three comparators, 64-bit counter
+    let caps = match Capabilities::decode((69_841_279u64 << 32) | (2 << 8) | (1 << 13) | 1) {
+        Some(caps) => caps,
+        None => return false,
+    };
+    if caps.period_fs != 69_841_279 || caps.timers != 3 || !caps.counter_64bit {
+        return false;
+    }
+    // A block that is not there reads as all ones or zeros
+    if Capabilities::decode(u64::MAX).is_some() || Capabilities::decode(0).is_some() {
+        return false;
+    }
+
+    // One second's worth of ticks, to within a tick
+    let second = ticks_to_ns(14_318_180, caps.period_fs);
+    if !(999_999_000..=1_000_000_100).contains(&second) {
+        return false;
+    }
+
+    let mut extender = CounterExtender::new();
+    extender.extend(0xFFFF_FFF0) == 0xFFFF_FFF0
+        && extender.extend(0x10) == 0x1_0000_0010
+        && extender.extend(0x20) == 0x1_0000_0020
+}
+
+/// Test that timers fire in deadline order and can be moved or cancelled
+pub fn test_timer_queue() -> bool {
+    let mut queue = TimerQueue::new();
+    let timer = |deadline_ns, cookie| OneShot { deadline_ns, port: 7, cookie };
+
+    if queue.arm(timer(300, 1)).is_err() || queue.arm(timer(100, 2)).is_err() || queue.arm(timer(200, 3)).is_err() {
+        return false;
+    }
+    // Re-arming cookie 1 moves it instead of adding a timer
+    if queue.arm(timer(50, 1)).is_err() || queue.next_deadline() != Some(50) {
+        return false;
+    }
+    if !queue.cancel(7, 3) || queue.cancel(7, 3) {
+        return false;
+    }
+
+    let first = queue.take_expired(150);
+    let second = queue.take_expired(150);
+    if first.map(|t| t.cookie) != Some(1) || second.map(|t| t.cookie) != Some(2) || queue.take_expired(150).is_some() {
+        return false;
+    }
+
+    for cookie in 0..MAX_TIMERS as u64 {
+        if queue.arm(timer(1_000, cookie)).is_err() {
+            return false;
+        }
+    }
+    queue.arm(timer(1_000, MAX_TIMERS as u64)).is_err()
+}
+
+/// Run all tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_parse_table,
+        test_counter,
+        test_timer_queue,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
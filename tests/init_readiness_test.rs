@@ -0,0 +1,82 @@
+//! Init Readiness Tests
+//!
+//! Tests for holding back services until their dependencies report ready
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/init/src/service_manager.rs"]
+mod service_manager;
+#[path = "../services/init/src/readiness.rs"]
+mod readiness;
+
+use readiness::*;
+use service_manager::*;
+
+/// Test that readiness reports name the sender with or without padding
+pub fn test_ready_name() -> bool {
+    let mut inline = [0u8; 16];
+    inline[..3].copy_from_slice(b"vfs");
+
+    ready_name(&inline) == Some(&b"vfs"[..])
+        && ready_name(b"security") == Some(&b"security"[..])
+        && ready_name(&[0u8; 4]).is_none()
+}
+
+/// Test that a failed dependency is reported ahead of one still starting
+pub fn test_dependency_states() -> bool {
+    let status_of = |name: &[u8]| match name {
+        b"driver_manager" => Some(ServiceStatus::Running),
+        b"vfs" => Some(ServiceStatus::Starting),
+        b"network" => Some(ServiceStatus::Failed),
+        _ => None,
+    };
+
+    check_dependencies(&[b"driver_manager"], status_of) == DependencyState::Ready
+        && check_dependencies(&[b"driver_manager", b"vfs"], status_of) == DependencyState::Waiting(b"vfs")
+        && check_dependencies(&[b"vfs", b"network"], status_of) == DependencyState::Failed(b"network")
+        && check_dependencies(&[b"audio"], status_of) == DependencyState::Failed(b"audio")
+}
+
+/// Test that a registered service only counts as running once it reports
+/// ready, and that reports after a failure are ignored
+pub fn test_mark_ready() -> bool {
+    if register_service(b"driver_manager\0", 2, 0).is_err()
+        || register_service(b"vfs\0", 3, 0).is_err()
+    {
+        return false;
+    }
+    let deps: &[&[u8]] = &[b"driver_manager\0"];
+    let waiting = check_dependencies(deps, service_status) == DependencyState::Waiting(b"driver_manager\0");
+
+    let ready = mark_ready(b"driver_manager");
+    let started = check_dependencies(deps, service_status) == DependencyState::Ready;
+
+    set_service_status(find_service(b"vfs").unwrap(), ServiceStatus::Failed);
+    let late = mark_ready(b"vfs");
+
+    waiting && ready && started && !late && service_status(b"vfs\0") == Some(ServiceStatus::Failed)
+}
+
+/// Run all init readiness tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_ready_name,
+        test_dependency_states,
+        test_mark_ready,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
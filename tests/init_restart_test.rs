@@ -0,0 +1,67 @@
+//! Init Restart Tests
+//!
+//! Tests for init's policy on restarting crashed services
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/init/src/restart.rs"]
+mod restart;
+
+use restart::*;
+
+/// Test that only nonzero exit codes count as crashes
+pub fn test_crash_classification() -> bool {
+    !is_crash(0) && is_crash(1) && is_crash(-1)
+}
+
+/// Test that restarts back off and stop after the retry budget
+pub fn test_backoff_and_give_up() -> bool {
+    let mut tracker = RestartTracker::new(0);
+    let mut delays = [0u64; MAX_RESTARTS as usize];
+    for (i, delay) in delays.iter_mut().enumerate() {
+        let now = 1000 * i as u64;
+        match tracker.crashed(now) {
+            CrashAction::RestartAt(at) => *delay = at - now,
+            CrashAction::GiveUp => return false,
+        }
+        tracker.started(now);
+    }
+
+    delays == [BASE_BACKOFF_MS, BASE_BACKOFF_MS * 2, BASE_BACKOFF_MS * 4]
+        && tracker.crashed(5000) == CrashAction::GiveUp
+}
+
+/// Test that a crash after a stable run gets a fresh budget
+pub fn test_stable_run_resets() -> bool {
+    let mut tracker = RestartTracker::new(0);
+    tracker.crashed(100);
+    tracker.crashed(200);
+    tracker.started(300);
+
+    let later = 300 + STABLE_PERIOD_MS;
+    tracker.crashed(later) == CrashAction::RestartAt(later + BASE_BACKOFF_MS)
+}
+
+/// Run all init restart tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_crash_classification,
+        test_backoff_and_give_up,
+        test_stable_run_resets,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,72 @@
+//! IPC Framing Tests
+//!
+//! Tests for placing payloads inline or in shared memory, and for finding
+//! them again in received messages
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/framework/src/framing.rs"]
+mod framing;
+
+use framing::*;
+
+/// Test that a payload goes inline only while it fits beside the header
+pub fn test_fits_inline() -> bool {
+    fits_inline(13, IPC_INLINE_SIZE - 13)
+        && !fits_inline(13, IPC_INLINE_SIZE - 12)
+        && fits_inline(0, 0)
+        && !fits_inline(IPC_INLINE_SIZE + 1, 0)
+        && !fits_inline(8, usize::MAX)
+}
+
+/// Test that an inline payload is found after the header, cut to the
+/// inline data
+pub fn test_locate_inline() -> bool {
+    locate(4, 20, 0, 0) == Some(Payload::Inline { offset: 4, len: 16 })
+        && locate(4, 4, 0, 0) == Some(Payload::Inline { offset: 4, len: 0 })
+        && locate(8, 200, 0, 0) == Some(Payload::Inline { offset: 8, len: IPC_INLINE_SIZE - 8 })
+}
+
+/// Test that a shared payload is found from buffer and buffer_size, and
+/// that a message too short for its header or without a region is refused
+pub fn test_locate_shared() -> bool {
+    locate(8, 8, 5, 4096) == Some(Payload::Shared { shm_id: 5, len: 4096 })
+        && locate(13, 12, 5, 4096).is_none()
+        && locate(8, 8, 0, 4096).is_none()
+        && locate(13, 12, 0, 0).is_none()
+}
+
+/// Test that a shared payload is copied only if the region really holds
+/// as much as the sender claims and it fits where it is copied to
+pub fn test_shared_copy_len() -> bool {
+    shared_copy_len(4096, 4096, 8192) == Some(4096)
+        && shared_copy_len(100, 4096, 100) == Some(100)
+        && shared_copy_len(8192, 4096, 65536).is_none()
+        && shared_copy_len(usize::MAX, 4096, usize::MAX).is_none()
+        && shared_copy_len(4096, 4096, 4095).is_none()
+}
+
+/// Run all IPC framing tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 4] = [
+        test_fits_inline,
+        test_locate_inline,
+        test_locate_shared,
+        test_shared_copy_len,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
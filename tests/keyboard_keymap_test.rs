@@ -0,0 +1,165 @@
+//! Keyboard Keymap Tests
+//!
+//! Tests for modifier tracking and scancode set 1 translation
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/keyboard/src/keymap.rs"]
+mod keymap;
+
+use keymap::{Keymap, KEY_DELETE, KEY_PAUSE, KEY_UP, MOD_CAPSLOCK, MOD_LSHIFT, MOD_RALT, MOD_RCTRL};
+
+/// Feed bytes and return the ASCII of the last event
+fn type_bytes(map: &mut Keymap, bytes: &[u8]) -> u8 {
+    let mut ascii = 0;
+    for &byte in bytes {
+        if let Some(event) = map.process(byte) {
+            ascii = event.ascii;
+        }
+    }
+    ascii
+}
+
+/// Test that Shift selects the shifted table and is dropped on release
+pub fn test_shift() -> bool {
+    let mut map = Keymap::new();
+
+    // LShift down, 1 down
+    let bang = type_bytes(&mut map, &[0x2A, 0x02]);
+    let held = map.modifiers() == MOD_LSHIFT;
+    // 1 up, LShift up, a down
+    let a = type_bytes(&mut map, &[0x82, 0xAA, 0x1E]);
+
+    bang == b'!' && held && a == b'a' && map.modifiers() == 0
+}
+
+/// Test that Caps Lock toggles once per press and only affects letters
+pub fn test_caps_lock() -> bool {
+    let mut map = Keymap::new();
+
+    // Caps down with a typematic repeat, then up
+    type_bytes(&mut map, &[0x3A, 0x3A, 0xBA]);
+    let on = map.modifiers() == MOD_CAPSLOCK;
+    let upper = type_bytes(&mut map, &[0x1E]);
+    let digit = type_bytes(&mut map, &[0x02]);
+    // Shift inverts it back
+    let lower = type_bytes(&mut map, &[0x2A, 0x1E]);
+    type_bytes(&mut map, &[0xAA]);
+
+    on && upper == b'A' && digit == b'1' && lower == b'a' && map.modifiers() == MOD_CAPSLOCK
+}
+
+/// Test that the 0xE0 prefix distinguishes right Ctrl/Alt
+pub fn test_extended_modifiers() -> bool {
+    let mut map = Keymap::new();
+
+    let prefix_only = map.process(0xE0).is_none();
+    map.process(0x1D);
+    type_bytes(&mut map, &[0xE0, 0x38]);
+    let both = map.modifiers() == MOD_RCTRL | MOD_RALT;
+    type_bytes(&mut map, &[0xE0, 0x9D, 0xE0, 0xB8]);
+
+    prefix_only && both && map.modifiers() == 0
+}
+
+/// Test that extended navigation keys do not type keypad digits
+pub fn test_extended_no_digits() -> bool {
+    let mut map = Keymap::new();
+
+    // Up arrow (E0 48) vs keypad 8 (48), keypad Enter (E0 1C)
+    let arrow = type_bytes(&mut map, &[0xE0, 0x48]);
+    let keypad = type_bytes(&mut map, &[0x48]);
+    let enter = type_bytes(&mut map, &[0xE0, 0x1C]);
+
+    arrow == 0 && keypad == b'8' && enter == b'\n'
+}
+
+/// Test that navigation keys get key codes on press and release
+pub fn test_navigation_keycodes() -> bool {
+    let mut map = Keymap::new();
+
+    map.process(0xE0);
+    let up = map.process(0x48);
+    map.process(0xE0);
+    let up_release = map.process(0xC8);
+    map.process(0xE0);
+    let delete = map.process(0x53);
+    let a = map.process(0x1E);
+
+    matches!(up, Some(e) if e.keycode == KEY_UP && e.pressed && e.ascii == 0)
+        && matches!(up_release, Some(e) if e.keycode == KEY_UP && !e.pressed)
+        && matches!(delete, Some(e) if e.keycode == KEY_DELETE)
+        && matches!(a, Some(e) if e.keycode == b'a')
+}
+
+/// Test that the six-byte Pause sequence yields one event
+pub fn test_pause_sequence() -> bool {
+    let mut map = Keymap::new();
+    let mut events = 0;
+    let mut keycode = 0;
+
+    for &byte in &[0xE1, 0x1D, 0x45, 0xE1, 0x9D, 0xC5] {
+        if let Some(event) = map.process(byte) {
+            events += 1;
+            keycode = event.keycode;
+        }
+    }
+
+    // Decoding resumes normally afterwards
+    let after = type_bytes(&mut map, &[0x1E]);
+    events == 1 && keycode == KEY_PAUSE && map.modifiers() == 0 && after == b'a'
+}
+
+/// Test that releases are reported and clear the held-key bitmap
+pub fn test_held_keys() -> bool {
+    let mut map = Keymap::new();
+
+    let press = map.process(0x1E);
+    let held = map.is_down(0x1E, false) && !map.is_down(0x1E, true);
+    type_bytes(&mut map, &[0xE0, 0x48]);
+    let arrow_held = map.is_down(0x48, true) && !map.is_down(0x48, false);
+    let release = map.process(0x9E);
+
+    matches!(press, Some(e) if e.pressed)
+        && matches!(release, Some(e) if !e.pressed && e.scancode == 0x1E)
+        && held
+        && arrow_held
+        && !map.is_down(0x1E, false)
+}
+
+/// Test that Ctrl+letter yields a control character
+pub fn test_ctrl_letter() -> bool {
+    let mut map = Keymap::new();
+
+    // LCtrl down, c down
+    type_bytes(&mut map, &[0x1D, 0x2E]) == 0x03
+}
+
+/// Run all keymap tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 8] = [
+        test_shift,
+        test_caps_lock,
+        test_extended_modifiers,
+        test_extended_no_digits,
+        test_navigation_keycodes,
+        test_pause_sequence,
+        test_held_keys,
+        test_ctrl_letter,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -7,6 +7,7 @@
 #include "../../kernel/include/types.h"
 #include "../../kernel/include/mm/vmm.h"
 #include "../../kernel/include/mm/pmm.h"
+#include "../../kernel/include/mm/mmap.h"
 #include "../../kernel/include/kprintf.h"
 
 /**
@@ -119,6 +120,87 @@ bool test_vmm_create_address_space(void) {
     return true;
 }
 
+/**
+ * Test that an MMIO mapping made and dropped while another is held (the
+ * PCI driver maps an MSI-X table while its ECAM window stays mapped)
+ * leaves the held one in place
+ */
+bool test_vmm_mmio_ecam_and_msix(void) {
+    kinfo("  Testing MMIO mappings side by side...\n");
+
+    address_space_t* as = vmm_create_address_space();
+    TEST_ASSERT_NOT_NULL(as, "Address space creation should succeed");
+
+    uint64_t flags = VMM_PRESENT | VMM_WRITE | VMM_USER | VMM_NOCACHE | VMM_WRITETHROUGH;
+    paddr_t ecam_phys = 0xE0000000ULL;
+    size_t ecam_size = 1024 * 1024;  // Bus 0
+    paddr_t table_phys = 0xFEB01000ULL;
+
+    vaddr_t ecam = mmap_device(as, ecam_phys, ecam_size, flags);
+    TEST_ASSERT_NEQ(ecam, 0, "ECAM window should map");
+
+    // MSI-X table entry 1, not page-aligned
+    vaddr_t table = mmap_device(as, table_phys + 0x10, 0x10, flags);
+    TEST_ASSERT_NEQ(table, 0, "MSI-X table should map");
+    TEST_ASSERT((table & ~(PAGE_SIZE - 1)) + PAGE_SIZE <= ecam || table >= ecam + ecam_size,
+                "MSI-X table should not land in the ECAM window");
+    TEST_ASSERT_EQ(vmm_get_physical(as, table & ~(PAGE_SIZE - 1)), table_phys, "MSI-X table maps its page");
+
+    // Enabling MSI-X is done: drop the table
+    memory_mapping_t* mapping = mmap_find(as, table);
+    TEST_ASSERT_NOT_NULL(mapping, "MSI-X table mapping should be tracked");
+    TEST_ASSERT_EQ(mmap_free(as, mapping->start, mapping->size), ERR_OK, "MSI-X table should unmap");
+
+    TEST_ASSERT_EQ(vmm_get_physical(as, ecam), ecam_phys, "ECAM window start still maps bus 0");
+    TEST_ASSERT_EQ(vmm_get_physical(as, ecam + ecam_size - PAGE_SIZE), ecam_phys + ecam_size - PAGE_SIZE,
+                   "ECAM window end still maps");
+
+    mapping = mmap_find(as, ecam);
+    TEST_ASSERT_NOT_NULL(mapping, "ECAM mapping should be tracked");
+    TEST_ASSERT_EQ(mmap_free(as, mapping->start, mapping->size), ERR_OK, "ECAM window should unmap");
+    vmm_destroy_address_space(as);
+
+    return true;
+}
+
+/**
+ * Test that a shared DMA buffer is recorded as a mapping, so anonymous
+ * memory is never placed over it, and that unmapping it leaves the
+ * buffer's pages alone
+ */
+bool test_vmm_dma_share_is_tracked(void) {
+    kinfo("  Testing DMA share mapping...\n");
+
+    address_space_t* as = vmm_create_address_space();
+    TEST_ASSERT_NOT_NULL(as, "Address space creation should succeed");
+
+    paddr_t buffer = pmm_alloc_page();
+    TEST_ASSERT_NEQ(buffer, 0, "Buffer page should allocate");
+
+    uint64_t flags = VMM_PRESENT | VMM_WRITE | VMM_USER | VMM_NX;
+    vaddr_t share = mmap_dma_share(as, buffer, PAGE_SIZE, flags);
+    TEST_ASSERT_NEQ(share, 0, "Share should map");
+    TEST_ASSERT_EQ(vmm_get_physical(as, share), buffer, "Share maps the buffer");
+
+    memory_mapping_t* mapping = mmap_find(as, share);
+    TEST_ASSERT_NOT_NULL(mapping, "Share mapping should be tracked");
+    TEST_ASSERT(mapping->flags & MAP_DMA_SHARE, "Share mapping is marked as such");
+
+    vaddr_t anon = mmap_alloc(as, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    TEST_ASSERT(anon + PAGE_SIZE <= share || anon >= share + PAGE_SIZE,
+                "Anonymous memory should not land on the share");
+
+    TEST_ASSERT_EQ(mmap_free(as, mapping->start, mapping->size), ERR_OK, "Share should unmap");
+    TEST_ASSERT_EQ(vmm_get_physical(as, share), 0, "Share is gone from the target");
+    TEST_ASSERT_EQ(mmap_free(as, anon, PAGE_SIZE), ERR_OK, "Anonymous memory should unmap");
+
+    // Still the owner's: freeing it here must not be a double free
+    pmm_free_page(buffer);
+    vmm_destroy_address_space(as);
+
+    return true;
+}
+
 /**
  * Run all VMM tests
  */
@@ -129,6 +211,8 @@ void run_vmm_tests(void) {
     RUN_TEST(test_vmm_map_multiple);
     RUN_TEST(test_vmm_unmapped);
     RUN_TEST(test_vmm_create_address_space);
+    RUN_TEST(test_vmm_mmio_ecam_and_msix);
+    RUN_TEST(test_vmm_dma_share_is_tracked);
 
     kinfo("=== VMM Tests Complete ===\n\n");
 }
@@ -0,0 +1,87 @@
+//! MMIO Access Tests
+//!
+//! Tests for the bounds and alignment checks on MmioRegion accessors
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/framework/src/mmio.rs"]
+mod mmio;
+
+/// Stand-in for the mapping syscalls: every region is this buffer
+mod syscalls {
+    pub static mut REGISTERS: [u64; 2] = [0; 2];
+
+    pub fn mmio_map(_physical_addr: u64, _size: u64) -> Result<*mut u8, u64> {
+        Ok(unsafe { core::ptr::addr_of_mut!(REGISTERS) as *mut u8 })
+    }
+
+    pub fn mmio_unmap(_vaddr: *mut u8, _size: u64) -> Result<(), u64> {
+        Ok(())
+    }
+}
+
+use mmio::MmioRegion;
+
+/// Test that byte registers read and write individually
+pub fn test_byte_access() -> bool {
+    let region = match MmioRegion::map(0, 16) {
+        Ok(region) => region,
+        Err(_) => return false,
+    };
+    region.write_u32(0, 0);
+    region.write_u8(3, 0x5A);
+
+    region.read_u8(3) == 0x5A && region.read_u32(0) == u32::from_le_bytes([0, 0, 0, 0x5A])
+}
+
+/// Test that accesses past the mapping read 0 and do not write
+pub fn test_out_of_range() -> bool {
+    let region = match MmioRegion::map(0, 8) {
+        Ok(region) => region,
+        Err(_) => return false,
+    };
+    region.write_u32(8, 0xFFFF_FFFF);
+    region.write_u8(usize::MAX, 0xFF);
+
+    let untouched = unsafe { syscalls::REGISTERS[1] == 0 };
+    untouched
+        && region.read_u32(8) == 0
+        && region.read_u64(4) == 0
+        && region.read_u8(usize::MAX) == 0
+}
+
+/// Test that misaligned wide accesses are refused
+pub fn test_misaligned() -> bool {
+    let region = match MmioRegion::map(0, 16) {
+        Ok(region) => region,
+        Err(_) => return false,
+    };
+    region.write_u64(0, u64::MAX);
+    region.write_u16(1, 0);
+
+    region.read_u32(2) == 0 && region.read_u16(0) == 0xFFFF
+}
+
+/// Run all MMIO access tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_byte_access,
+        test_out_of_range,
+        test_misaligned,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
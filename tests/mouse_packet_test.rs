@@ -0,0 +1,104 @@
+//! PS/2 Mouse Packet Tests
+//!
+//! Tests for assembling mouse bytes into movement and wheel packets
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/mouse/src/packet.rs"]
+mod packet;
+
+use packet::{PacketDecoder, MOUSE_ID_INTELLIMOUSE, MOUSE_ID_STANDARD};
+
+/// Test a standard 3-byte packet
+pub fn test_standard_packet() -> bool {
+    let mut decoder = PacketDecoder::new();
+    decoder.set_device_id(MOUSE_ID_STANDARD);
+
+    // Y sign set
+    let first = decoder.process(0x29);
+    let second = decoder.process(5);
+    let packet = decoder.process(0xFE);
+
+    first.is_none()
+        && second.is_none()
+        && matches!(packet, Some(p) if p.buttons == 1 && p.dx == 5 && p.dy == -2 && p.scroll == 0)
+}
+
+/// Test that an IntelliMouse id switches to 4-byte packets with a scroll delta
+pub fn test_wheel_packet() -> bool {
+    let mut decoder = PacketDecoder::new();
+    decoder.set_device_id(MOUSE_ID_INTELLIMOUSE);
+
+    let mut packets = 0;
+    let mut up = 0;
+    let mut down = 0;
+    for &byte in &[0x08, 0, 0, 0x0F, 0x08, 0, 0, 0x01] {
+        if let Some(p) = decoder.process(byte) {
+            packets += 1;
+            if packets == 1 {
+                up = p.scroll;
+            } else {
+                down = p.scroll;
+            }
+        }
+    }
+
+    decoder.packet_size() == 4 && packets == 2 && up == -1 && down == 1
+}
+
+/// Test recovery from a dropped byte and 9-bit movement beyond i8
+pub fn test_resync() -> bool {
+    let mut decoder = PacketDecoder::new();
+    let mut packets = [None; 4];
+    let mut count = 0;
+
+    // A packet missing its flags byte, then two good ones; the second
+    // moves 200 right, which only fits with the sign bit in the flags
+    let stream = [0x05, 0x02, 0x08, 0x01, 0x01, 0x08, 200, 0x00];
+    for &byte in stream.iter() {
+        if let Some(p) = decoder.process(byte) {
+            packets[count] = Some(p);
+            count += 1;
+        }
+    }
+
+    count == 2
+        && matches!(packets[0], Some(p) if p.dx == 1 && p.dy == 1)
+        && matches!(packets[1], Some(p) if p.dx == 200 && p.dy == 0)
+}
+
+/// Test that packets with the overflow bits set are discarded
+pub fn test_overflow_dropped() -> bool {
+    let mut decoder = PacketDecoder::new();
+
+    let overflow = [0x48, 0xFF, 0x00];
+    let dropped = overflow.iter().all(|&byte| decoder.process(byte).is_none());
+    let next = [0x18, 0xFF, 0x00].iter().filter_map(|&byte| decoder.process(byte)).next();
+
+    dropped && matches!(next, Some(p) if p.dx == -1)
+}
+
+/// Run all mouse packet tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 4] = [
+        test_standard_packet,
+        test_wheel_packet,
+        test_resync,
+        test_overflow_dropped,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,83 @@
+//! Network Frame Queue Tests
+//!
+//! Tests for the per-socket queue that link-layer raw sockets read from
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/network/src/frame_queue.rs"]
+mod frame_queue;
+
+use frame_queue::*;
+
+/// Test that frames come back whole and in arrival order
+pub fn test_frames_in_order() -> bool {
+    let mut buf = [0u8; 256];
+    let mut len = 0;
+    let first = [0xAAu8; 60];
+    let second = [0xBBu8; 42];
+
+    let queued = push_frame(&mut buf, &mut len, 256, &first) && push_frame(&mut buf, &mut len, 256, &second);
+
+    let mut out = [0u8; 1518];
+    let a = pop_frame(&mut buf, &mut len, &mut out);
+    let a_ok = a == Some(60) && out[..60] == first;
+    let b = pop_frame(&mut buf, &mut len, &mut out);
+    let b_ok = b == Some(42) && out[..42] == second;
+
+    queued && a_ok && b_ok && len == 0 && pop_frame(&mut buf, &mut len, &mut out).is_none()
+}
+
+/// Test that a full queue drops new frames instead of splitting them
+pub fn test_full_queue_drops() -> bool {
+    let mut buf = [0u8; 256];
+    let mut len = 0;
+    let frame = [1u8; 60];
+
+    // Capacity (SO_RCVBUF) is below the buffer size: two 62-byte records fit in 130
+    let fits = push_frame(&mut buf, &mut len, 130, &frame) && push_frame(&mut buf, &mut len, 130, &frame);
+    let dropped = !push_frame(&mut buf, &mut len, 130, &frame) && len == 124;
+    let empty_refused = !push_frame(&mut buf, &mut len, 256, &[]);
+
+    fits && dropped && empty_refused
+}
+
+/// Test that a short read buffer takes the head of the frame and discards the rest
+pub fn test_short_read_truncates() -> bool {
+    let mut buf = [0u8; 256];
+    let mut len = 0;
+    let mut frame = [0u8; 64];
+    for (i, b) in frame.iter_mut().enumerate() {
+        *b = i as u8;
+    }
+    let _ = push_frame(&mut buf, &mut len, 256, &frame);
+    let _ = push_frame(&mut buf, &mut len, 256, &[7u8; 20]);
+
+    let mut out = [0u8; 14];
+    let head = pop_frame(&mut buf, &mut len, &mut out) == Some(14) && out == frame[..14];
+    let mut next = [0u8; 32];
+    head && pop_frame(&mut buf, &mut len, &mut next) == Some(20) && next[..20] == [7u8; 20]
+}
+
+/// Run all frame queue tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_frames_in_order,
+        test_full_queue_drops,
+        test_short_read_truncates,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,72 @@
+//! Network TCP ISN Tests
+//!
+//! Tests for RFC 6528 initial sequence numbers
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/network/src/isn.rs"]
+mod isn;
+
+use isn::*;
+
+const LOCAL: u32 = 0x0A00_0002;
+const REMOTE: u32 = 0x0A00_0001;
+
+fn secret(seed: u8) -> IsnSecret {
+    let mut bytes = [0u8; 16];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = seed.wrapping_add(i as u8);
+    }
+    IsnSecret::from_bytes(bytes)
+}
+
+/// Test that F is SipHash-2-4: with key 00..0f and the clock at 0, the
+/// ISN is the low half of SipHash over the 16 message bytes
+/// 00 01 .. 0b 00 00 00 00 that this 4-tuple encodes to
+pub fn test_siphash_reference() -> bool {
+    secret(0).initial_sequence(0, 0x0706_0504, 0x0B0A, 0x0302_0100, 0x0908) == 0x5830_5F2A
+}
+
+/// Test that connections to different peers or ports do not share a
+/// sequence space, and that a different boot secret gives different ISNs
+pub fn test_tuple_and_secret_change_isn() -> bool {
+    let key = secret(1);
+    let base = key.initial_sequence(1_000, LOCAL, 49152, REMOTE, 80);
+
+    base != key.initial_sequence(1_000, LOCAL, 49153, REMOTE, 80)
+        && base != key.initial_sequence(1_000, LOCAL, 49152, REMOTE, 443)
+        && base != key.initial_sequence(1_000, LOCAL, 49152, REMOTE + 1, 80)
+        && base != secret(2).initial_sequence(1_000, LOCAL, 49152, REMOTE, 80)
+}
+
+/// Test that the ISN for one 4-tuple advances with the 4 microsecond clock
+pub fn test_isn_follows_clock() -> bool {
+    let key = secret(3);
+    let before = key.initial_sequence(1_000, LOCAL, 49152, REMOTE, 80);
+    let after = key.initial_sequence(1_010, LOCAL, 49152, REMOTE, 80);
+    after.wrapping_sub(before) == 2_500
+}
+
+/// Run all ISN tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_siphash_reference,
+        test_tuple_and_secret_change_isn,
+        test_isn_follows_clock,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
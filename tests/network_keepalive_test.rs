@@ -0,0 +1,77 @@
+//! Network TCP Keepalive Tests
+//!
+//! Tests for keepalive probing of idle TCP connections and for the
+//! connection-establishment timeout
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/network/src/keepalive.rs"]
+mod keepalive;
+
+use keepalive::*;
+
+const TIMING: Keepalive = Keepalive { idle_ms: 10_000, interval_ms: 1_000, probes: 3 };
+
+/// Test that nothing is sent until the connection has been idle for the
+/// idle time, and that hearing from the peer starts the wait over
+pub fn test_probe_after_idle() -> bool {
+    TIMING.action(5_000, 0, 14_999) == KeepaliveAction::Wait
+        && TIMING.action(5_000, 0, 15_000) == KeepaliveAction::Probe
+        // Peer answered at 15_200: probes reset, idle timer restarts
+        && TIMING.action(15_200, 0, 16_000) == KeepaliveAction::Wait
+        && TIMING.action(15_200, 0, 25_200) == KeepaliveAction::Probe
+}
+
+/// Test that probes repeat every interval and the connection is dropped
+/// an interval after the last one goes unanswered
+pub fn test_drop_after_unanswered_probes() -> bool {
+    let mut now = 0;
+    let mut probes = 0;
+    let mut dropped_at = None;
+
+    while now <= 20_000 && dropped_at.is_none() {
+        match TIMING.action(0, probes, now) {
+            KeepaliveAction::Wait => {}
+            KeepaliveAction::Probe => probes += 1,
+            KeepaliveAction::Drop => dropped_at = Some(now),
+        }
+        now += 100;
+    }
+
+    // Probes at 10s, 11s and 12s; given up on at 13s
+    probes == 3 && dropped_at == Some(13_000) && Keepalive::DEFAULT.action(0, 0, u64::MAX - 1) == KeepaliveAction::Probe
+}
+
+/// Test that a handshake is abandoned once its timeout passes, unless the
+/// timeout is 0
+pub fn test_handshake_timeout() -> bool {
+    !handshake_expired(1_000, 75_000, 75_999)
+        && handshake_expired(1_000, 75_000, 76_000)
+        && !handshake_expired(1_000, 0, u64::MAX)
+        // A clock reading before the start never counts as expired
+        && !handshake_expired(5_000, 1, 4_000)
+}
+
+/// Run all network TCP keepalive tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_probe_after_idle,
+        test_drop_after_unanswered_probes,
+        test_handshake_timeout,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,91 @@
+//! Network Broadcast and Multicast Tests
+//!
+//! Tests for link-layer addressing of broadcast and multicast datagrams,
+//! socket group membership, and delivering one datagram to many sockets
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/network/src/multicast.rs"]
+mod multicast;
+
+#[path = "../services/network/src/udp_demux.rs"]
+mod udp_demux;
+
+use multicast::*;
+use udp_demux::*;
+
+const PEER: u32 = 0x0A00_0001; // 10.0.0.1
+const MDNS: u32 = 0xE000_00FB; // 224.0.0.251
+const SSDP: u32 = 0xEFFF_FFFA; // 239.255.255.250
+
+/// Test that broadcast frames go to ff:ff:ff:ff:ff:ff, multicast frames to
+/// 01:00:5e plus the group's low 23 bits, and unicast is left to ARP
+pub fn test_link_destination() -> bool {
+    link_destination(0xFFFF_FFFF, true) == Some([0xFF; 6])
+        && link_destination(0x0A00_00FF, true) == Some([0xFF; 6])
+        && link_destination(MDNS, false) == Some([0x01, 0x00, 0x5E, 0x00, 0x00, 0xFB])
+        && link_destination(SSDP, false) == Some([0x01, 0x00, 0x5E, 0x7F, 0xFF, 0xFA])
+        // Bit 23 of the group does not reach the MAC
+        && multicast_mac(0xEF80_0001) == multicast_mac(0xEF00_0001)
+        && link_destination(PEER, false).is_none()
+}
+
+/// Test joining and leaving groups, including the per-socket limit
+pub fn test_memberships() -> bool {
+    let mut groups = Memberships::new();
+    if !groups.join(MDNS) || !groups.join(MDNS) || groups.join(PEER) {
+        return false;
+    }
+    for i in 1..IP_MAX_MEMBERSHIPS as u32 {
+        if !groups.join(SSDP - i) {
+            return false;
+        }
+    }
+    let full = !groups.join(SSDP);
+
+    groups.leave(MDNS) && !groups.leave(MDNS) && !groups.contains(MDNS) && groups.contains(SSDP - 1)
+        && full && groups.join(SSDP) && groups.contains(SSDP)
+}
+
+/// Test that a group datagram reaches every socket on its port that it
+/// matches, not just the most specific one
+pub fn test_group_delivery() -> bool {
+    let sockets = [
+        (0, UdpEndpoint { local_ip: 0, local_port: 5353, remote: None }),
+        (1, UdpEndpoint { local_ip: MDNS, local_port: 5353, remote: None }),
+        (2, UdpEndpoint { local_ip: 0, local_port: 1900, remote: None }),
+        (4, UdpEndpoint { local_ip: 0, local_port: 5353, remote: Some((0x0A00_0009, 5353)) }),
+        (5, UdpEndpoint { local_ip: 0x0A00_0002, local_port: 5353, remote: None }),
+    ];
+
+    let mut all = udp_demux_all(sockets, PEER, 5353, MDNS, 5353);
+    let delivered = all.next() == Some(0) && all.next() == Some(1) && all.next().is_none();
+
+    // Unicast still picks a single socket
+    let unicast = udp_demux(sockets, PEER, 5353, MDNS, 5353) == Some(1);
+    delivered && unicast && udp_demux_all(sockets, PEER, 5353, MDNS, 80).count() == 0
+}
+
+/// Run all network broadcast and multicast tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_link_destination,
+        test_memberships,
+        test_group_delivery,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,83 @@
+//! Network Port Table Tests
+//!
+//! Tests for local port bindings and ephemeral port allocation
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/network/src/port_table.rs"]
+mod port_table;
+
+use port_table::*;
+
+/// Test that a held port is refused unless both sockets set SO_REUSEADDR
+pub fn test_bind_conflicts() -> bool {
+    let mut table = PortTable::new();
+
+    let first = table.bind(PortProtocol::Tcp, 8080, 1, false).is_ok();
+    let conflict = table.bind(PortProtocol::Tcp, 8080, 2, true).is_err();
+    // TCP and UDP ports are separate namespaces
+    let other_protocol = table.bind(PortProtocol::Udp, 8080, 2, false).is_ok();
+    let zero_refused = table.bind(PortProtocol::Udp, 0, 3, false).is_err();
+
+    let shared = table.bind(PortProtocol::Udp, 5353, 4, true).is_ok()
+        && table.bind(PortProtocol::Udp, 5353, 5, true).is_ok()
+        && table.bind(PortProtocol::Udp, 5353, 6, false).is_err();
+
+    first && conflict && other_protocol && zero_refused && shared
+}
+
+/// Test that ephemeral ports are unique, skip ports in use and wrap around
+pub fn test_ephemeral_allocation() -> bool {
+    let mut table = PortTable::new();
+
+    let a = table.bind_ephemeral(PortProtocol::Tcp, 1, |_| false);
+    // A port lingering in TIME_WAIT is passed over
+    let b = table.bind_ephemeral(PortProtocol::Tcp, 2, |port| port == EPHEMERAL_FIRST + 1);
+    let distinct = a == Ok(EPHEMERAL_FIRST) && b == Ok(EPHEMERAL_FIRST + 2);
+
+    // Taking every port but the first forces a wrap back to the (now free) start
+    table.release(1);
+    let wrapped = table.bind_ephemeral(PortProtocol::Tcp, 3, |port| port != EPHEMERAL_FIRST);
+
+    let exhausted = table.bind_ephemeral(PortProtocol::Udp, 4, |_| true).is_err();
+
+    distinct && wrapped == Ok(EPHEMERAL_FIRST) && exhausted
+}
+
+/// Test that closing a socket frees its port for the next bind
+pub fn test_release_frees_port() -> bool {
+    let mut table = PortTable::new();
+
+    let _ = table.bind(PortProtocol::Udp, 9000, 7, false);
+    let _ = table.bind(PortProtocol::Tcp, 9000, 7, false);
+    let held = table.is_bound(PortProtocol::Udp, 9000) && table.bind(PortProtocol::Udp, 9000, 8, false).is_err();
+
+    table.release(7);
+    let freed = !table.is_bound(PortProtocol::Udp, 9000) && !table.is_bound(PortProtocol::Tcp, 9000);
+
+    held && freed && table.bind(PortProtocol::Udp, 9000, 8, false).is_ok()
+}
+
+/// Run all port table tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_bind_conflicts,
+        test_ephemeral_allocation,
+        test_release_frees_port,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,84 @@
+//! Network UDP Demultiplexing Tests
+//!
+//! Tests for choosing which socket receives a UDP datagram
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/network/src/udp_demux.rs"]
+mod udp_demux;
+
+use udp_demux::*;
+
+const LOCAL: u32 = 0x0A00_0002; // 10.0.0.2
+const PEER: u32 = 0x0A00_0001; // 10.0.0.1
+
+fn endpoint(local_ip: u32, local_port: u16, remote: Option<(u32, u16)>) -> UdpEndpoint {
+    UdpEndpoint { local_ip, local_port, remote }
+}
+
+/// Test that datagrams only reach the socket bound to their destination port
+pub fn test_demux_by_port() -> bool {
+    let sockets = [
+        (0, endpoint(0, 5000, None)),
+        (3, endpoint(0, 6000, None)),
+    ];
+
+    let first = udp_demux(sockets, PEER, 40000, LOCAL, 5000) == Some(0);
+    let second = udp_demux(sockets, PEER, 40000, LOCAL, 6000) == Some(3);
+    let unbound = udp_demux(sockets, PEER, 40000, LOCAL, 7000).is_none();
+
+    first && second && unbound
+}
+
+/// Test that a socket bound to one address ignores datagrams for another
+pub fn test_demux_by_local_address() -> bool {
+    let sockets = [
+        (1, endpoint(0, 5353, None)),
+        (2, endpoint(LOCAL, 5353, None)),
+    ];
+
+    // The specific bind beats the wildcard for its own address only
+    let specific = udp_demux(sockets, PEER, 5353, LOCAL, 5353) == Some(2);
+    let wildcard = udp_demux(sockets, PEER, 5353, 0xFFFF_FFFF, 5353) == Some(1);
+    let only_specific = udp_demux([(2, endpoint(LOCAL, 5353, None))], PEER, 5353, 0x0A00_0003, 5353).is_none();
+
+    specific && wildcard && only_specific
+}
+
+/// Test that a connected socket gets its peer's datagrams and nobody else's
+pub fn test_demux_connected_peer() -> bool {
+    let sockets = [
+        (4, endpoint(0, 9000, None)),
+        (5, endpoint(0, 9000, Some((PEER, 53)))),
+    ];
+
+    let from_peer = udp_demux(sockets, PEER, 53, LOCAL, 9000) == Some(5);
+    let from_other = udp_demux(sockets, 0x0A00_0009, 53, LOCAL, 9000) == Some(4);
+    let connected_only = udp_demux([(5, endpoint(0, 9000, Some((PEER, 53))))], PEER, 54, LOCAL, 9000).is_none();
+
+    from_peer && from_other && connected_only
+}
+
+/// Run all UDP demultiplexing tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_demux_by_port,
+        test_demux_by_local_address,
+        test_demux_connected_peer,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,192 @@
+//! Partition Table Tests
+//!
+//! Parses MBR and GPT partition tables from an in-memory disk image,
+//! including the fallbacks taken when a GPT fails its checks
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/vfs/src/ipc.rs"]
+mod ipc;
+#[path = "../services/vfs/src/syscalls.rs"]
+mod syscalls;
+#[path = "../services/vfs/src/block_device.rs"]
+mod block_device;
+#[path = "../services/vfs/src/crc32.rs"]
+mod crc32;
+#[path = "../services/vfs/src/partition.rs"]
+mod partition;
+
+use block_device::{BlockDeviceInfo, BlockIo};
+use crc32::crc32;
+use partition::*;
+
+const DISK_SECTORS: usize = 64;
+const GPT_ENTRIES_LBA: usize = 2;
+const GPT_ENTRY_SIZE: usize = 128;
+
+/// Disk image held in memory
+struct MemDisk {
+    data: [u8; DISK_SECTORS * SECTOR_SIZE],
+}
+
+impl BlockIo for MemDisk {
+    fn read_blocks(&self, lba: u64, count: u32, buffer: &mut [u8]) -> Result<usize, ()> {
+        let start = lba as usize * SECTOR_SIZE;
+        let len = count as usize * SECTOR_SIZE;
+        if start + len > self.data.len() || buffer.len() < len {
+            return Err(());
+        }
+        buffer[..len].copy_from_slice(&self.data[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_blocks(&self, _lba: u64, _count: u32, _data: &[u8]) -> Result<(), ()> {
+        Err(())
+    }
+
+    fn info(&self) -> Result<BlockDeviceInfo, ()> {
+        Ok(BlockDeviceInfo { sectors: DISK_SECTORS as u64, sector_size: SECTOR_SIZE as u32 })
+    }
+}
+
+impl MemDisk {
+    fn new() -> Self {
+        let mut disk = Self { data: [0; DISK_SECTORS * SECTOR_SIZE] };
+        disk.data[510] = 0x55;
+        disk.data[511] = 0xAA;
+        disk
+    }
+
+    /// Fill in primary MBR entry `index`
+    fn mbr_entry(&mut self, index: usize, part_type: u8, start: u32, sectors: u32) {
+        let entry = &mut self.data[0x1BE + index * 16..0x1BE + (index + 1) * 16];
+        entry[4] = part_type;
+        entry[8..12].copy_from_slice(&start.to_le_bytes());
+        entry[12..16].copy_from_slice(&sectors.to_le_bytes());
+    }
+
+    /// Fill in GPT entry `index` (0-based) of the array at LBA 2
+    fn gpt_entry(&mut self, index: usize, type_guid: [u8; 16], first: u64, last: u64) {
+        let offset = GPT_ENTRIES_LBA * SECTOR_SIZE + index * GPT_ENTRY_SIZE;
+        let entry = &mut self.data[offset..offset + GPT_ENTRY_SIZE];
+        entry[0..16].copy_from_slice(&type_guid);
+        entry[32..40].copy_from_slice(&first.to_le_bytes());
+        entry[40..48].copy_from_slice(&last.to_le_bytes());
+    }
+
+    /// Write the GPT header at LBA 1 for `num_entries` entries, with both
+    /// CRCs computed over what is on the disk
+    fn gpt_header(&mut self, num_entries: u32) {
+        let array_start = GPT_ENTRIES_LBA * SECTOR_SIZE;
+        let array_len = (num_entries as usize * GPT_ENTRY_SIZE).min(self.data.len() - array_start);
+        let entries_crc = crc32(&self.data[array_start..array_start + array_len]);
+
+        let header = &mut self.data[SECTOR_SIZE..2 * SECTOR_SIZE];
+        header.fill(0);
+        header[0..8].copy_from_slice(b"EFI PART");
+        header[8..12].copy_from_slice(&0x0001_0000u32.to_le_bytes());
+        header[12..16].copy_from_slice(&92u32.to_le_bytes());
+        header[24..32].copy_from_slice(&1u64.to_le_bytes());
+        header[72..80].copy_from_slice(&(GPT_ENTRIES_LBA as u64).to_le_bytes());
+        header[80..84].copy_from_slice(&num_entries.to_le_bytes());
+        header[84..88].copy_from_slice(&(GPT_ENTRY_SIZE as u32).to_le_bytes());
+        header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+        let header_crc = crc32(&header[..92]);
+        header[16..20].copy_from_slice(&header_crc.to_le_bytes());
+    }
+}
+
+/// Test that the primary MBR entries are found by number, skipping empty
+/// and extended ones
+pub fn test_mbr_partitions() -> bool {
+    let mut disk = MemDisk::new();
+    disk.mbr_entry(0, MBR_TYPE_LINUX, 2048, 4096);
+    disk.mbr_entry(1, MBR_TYPE_EXTENDED, 8192, 1000);
+    disk.mbr_entry(2, MBR_TYPE_FAT32_LBA, 6144, 2048);
+
+    let mut found = [EMPTY_PARTITION; MAX_GPT_ENTRIES as usize];
+    let count = match read_partition_table(&disk, 3, &mut found) {
+        Ok(count) => count,
+        Err(_) => return false,
+    };
+
+    let linux = &found[0];
+    let fat = &found[1];
+    count == 2
+        && linux.port_idx == 3 && linux.number == 1 && linux.start_lba == 2048 && linux.sector_count == 4096
+        && linux.scheme == PartitionScheme::Mbr && linux.fs_type_hint() == Some(&b"ext4"[..])
+        && fat.number == 3 && fat.start_lba == 6144 && fat.fs_type_hint() == Some(&b"fat32"[..])
+}
+
+/// Test that a GPT behind a protective MBR is preferred, and that a damaged
+/// entry array sends the parser back to the MBR instead
+pub fn test_gpt_partitions() -> bool {
+    let mut disk = MemDisk::new();
+    disk.mbr_entry(0, MBR_TYPE_GPT_PROTECTIVE, 1, DISK_SECTORS as u32 - 1);
+    disk.gpt_entry(0, GPT_TYPE_EFI_SYSTEM, 40, 47);
+    disk.gpt_entry(2, GPT_TYPE_LINUX_FS, 48, 63);
+    disk.gpt_header(128);
+
+    let mut found = [EMPTY_PARTITION; MAX_GPT_ENTRIES as usize];
+    let gpt = read_partition_table(&disk, 0, &mut found) == Ok(2)
+        && found[0].number == 1 && found[0].start_lba == 40 && found[0].sector_count == 8
+        && found[0].scheme == PartitionScheme::Gpt && found[0].part_type == PartitionType::Gpt(GPT_TYPE_EFI_SYSTEM)
+        && found[1].number == 3 && found[1].start_lba == 48 && found[1].sector_count == 16;
+
+    // Change an entry without updating the array CRC: only the protective
+    // MBR entry is left, and that is not a partition
+    disk.gpt_entry(1, GPT_TYPE_BASIC_DATA, 30, 39);
+    let fallback = read_partition_table(&disk, 0, &mut found) == Ok(0);
+
+    gpt && fallback
+}
+
+/// Test that an entry array past the limit is refused rather than read,
+/// and that partition I/O stays inside the partition
+pub fn test_gpt_limits() -> bool {
+    let mut disk = MemDisk::new();
+    disk.mbr_entry(0, MBR_TYPE_GPT_PROTECTIVE, 1, DISK_SECTORS as u32 - 1);
+    disk.gpt_entry(0, GPT_TYPE_LINUX_FS, 40, 63);
+
+    // One entry too many, with both CRCs valid
+    disk.gpt_header(MAX_GPT_ENTRIES + 1);
+    let mut found = [EMPTY_PARTITION; MAX_GPT_ENTRIES as usize];
+    let too_many = read_partition_table(&disk, 0, &mut found) == Ok(0);
+
+    // A header claiming billions of entries is refused before any are read
+    disk.gpt_header(u32::MAX);
+    let huge = read_partition_table(&disk, 0, &mut found) == Ok(0);
+
+    disk.gpt_header(MAX_GPT_ENTRIES);
+    let fits = read_partition_table(&disk, 0, &mut found) == Ok(1);
+
+    let part = found[0];
+    let bounded = part.translate(0, 24) == Ok(40) && part.translate(23, 1) == Ok(63)
+        && part.translate(23, 2).is_err() && part.translate(u64::MAX, 1).is_err();
+
+    too_many && huge && fits && bounded
+}
+
+/// Run all partition table tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_mbr_partitions,
+        test_gpt_partitions,
+        test_gpt_limits,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,96 @@
+//! PCI BAR Decoding Tests
+//!
+//! Tests for decoding base and size from a BAR and its all-ones readback
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/pci/src/bar.rs"]
+mod bar;
+
+use bar::{decode, is_64bit, BarInfo};
+
+/// Test a 32-bit non-prefetchable memory BAR (AHCI ABAR style, 8 KiB)
+pub fn test_mmio_32() -> bool {
+    let low = 0xFEBF_1000;
+    let mask = 0xFFFF_E000;
+
+    decode(low, 0, mask, 0) == Some(BarInfo {
+        base: 0xFEBF_1000,
+        size: 0x2000,
+        is_mmio: true,
+        is_64bit: false,
+    })
+}
+
+/// Test a 64-bit prefetchable memory BAR above 4 GiB
+pub fn test_mmio_64() -> bool {
+    // Type 64-bit, prefetchable
+    let low = 0x0000_000C;
+    let high = 0x0000_0008;
+    let mask_low = 0xFFF0_000C;
+    let mask_high = 0xFFFF_FFFF;
+
+    is_64bit(low)
+        && decode(low, high, mask_low, mask_high) == Some(BarInfo {
+            base: 0x8_0000_0000,
+            size: 0x10_0000,
+            is_mmio: true,
+            is_64bit: true,
+        })
+}
+
+/// Test a 64-bit BAR whose size spans into the upper dword
+pub fn test_mmio_64_large() -> bool {
+    let low = 0x0000_0004;
+    let mask_low = 0x0000_0004;
+    let mask_high = 0xFFFF_FFFE;
+
+    match decode(low, 0x4, mask_low, mask_high) {
+        Some(info) => info.size == 0x2_0000_0000 && info.base == 0x4_0000_0000,
+        None => false,
+    }
+}
+
+/// Test an I/O BAR whose upper half reads back as zero
+pub fn test_io() -> bool {
+    let low = 0x0000_C041;
+    let mask = 0x0000_FFE1;
+
+    decode(low, 0, mask, 0) == Some(BarInfo {
+        base: 0xC040,
+        size: 0x20,
+        is_mmio: false,
+        is_64bit: false,
+    })
+}
+
+/// Test that an unimplemented BAR decodes to nothing
+pub fn test_unimplemented() -> bool {
+    decode(0, 0, 0, 0).is_none()
+}
+
+/// Run all BAR decoding tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 5] = [
+        test_mmio_32,
+        test_mmio_64,
+        test_mmio_64_large,
+        test_io,
+        test_unimplemented,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
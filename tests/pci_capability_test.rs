@@ -0,0 +1,87 @@
+//! PCI Capability Lookup Tests
+//!
+//! Tests for the capability walk behind MSG_PCI_FIND_CAP
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/pci/src/msi.rs"]
+mod msi;
+
+use msi::*;
+
+/// Fake configuration space with the capability list bit set and the
+/// given (offset, id, next) capabilities
+fn config_space(first: u8, caps: &[(u8, u8, u8)]) -> [u32; 64] {
+    let mut space = [0u32; 64];
+    space[1] = PCI_STATUS_CAP_LIST << 16;
+    space[0x34 / 4] = first as u32;
+    for &(offset, id, next) in caps {
+        space[offset as usize / 4] = id as u32 | ((next as u32) << 8);
+    }
+    space
+}
+
+/// Test finding power management and PCI Express capabilities
+pub fn test_find_pm_and_pcie() -> bool {
+    let space = config_space(
+        0x40,
+        &[(0x40, PCI_CAP_ID_PM, 0x60), (0x60, PCI_CAP_ID_PCIE, 0xA0), (0xA0, PCI_CAP_ID_MSI, 0)],
+    );
+    let read = |offset: u8| space[offset as usize / 4];
+
+    find_capability(read, PCI_CAP_ID_PM) == Some(0x40)
+        && find_capability(read, PCI_CAP_ID_PCIE) == Some(0x60)
+        && find_capability(read, PCI_CAP_ID_MSIX).is_none()
+}
+
+/// Test that reserved low pointer bits are ignored and that a pointer back
+/// into the header ends the walk
+pub fn test_malformed_pointers() -> bool {
+    // Pointers with their two low bits set still name dword offsets
+    let space = config_space(0x43, &[(0x40, PCI_CAP_ID_PM, 0x53), (0x50, PCI_CAP_ID_PCIE, 0)]);
+    if find_capability(|offset| space[offset as usize / 4], PCI_CAP_ID_PCIE) != Some(0x50) {
+        return false;
+    }
+
+    let space = config_space(0x40, &[(0x40, PCI_CAP_ID_PM, 0x10)]);
+    find_capability(|offset| space[offset as usize / 4], PCI_CAP_ID_PCIE).is_none()
+}
+
+/// Test that the iteration bound still reaches the last of a full list
+pub fn test_full_list() -> bool {
+    // One 4-byte capability in every dword from 0x40 to 0xFC
+    let mut caps = [(0u8, 0u8, 0u8); PCI_CAP_MAX_ITERATIONS];
+    for (i, cap) in caps.iter_mut().enumerate() {
+        let offset = 0x40 + i as u8 * 4;
+        let next = if i + 1 == PCI_CAP_MAX_ITERATIONS { 0 } else { offset + 4 };
+        *cap = (offset, 0x09, next);
+    }
+    caps[PCI_CAP_MAX_ITERATIONS - 1].1 = PCI_CAP_ID_PCIE;
+
+    let space = config_space(0x40, &caps);
+    find_capability(|offset| space[offset as usize / 4], PCI_CAP_ID_PCIE) == Some(0xFC)
+}
+
+/// Run all tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_find_pm_and_pcie,
+        test_malformed_pointers,
+        test_full_list,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,54 @@
+//! PCI Command Register Tests
+//!
+//! Tests for the command value written by MSG_PCI_ENABLE_DEVICE
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/pci/src/command.rs"]
+mod command;
+
+use command::*;
+
+/// Test that both memory decode and bus mastering end up set
+pub fn test_enable_sets_memory_and_bus_master() -> bool {
+    let value = enable_device(0);
+    value & PCI_COMMAND_MEMORY != 0 && value & PCI_COMMAND_BUS_MASTER != 0
+}
+
+/// Test that bits the device already had stay set
+pub fn test_enable_keeps_existing_bits() -> bool {
+    let value = enable_device(PCI_COMMAND_IO | PCI_COMMAND_INTX_DISABLE);
+    value == PCI_COMMAND_IO | PCI_COMMAND_MEMORY | PCI_COMMAND_BUS_MASTER | PCI_COMMAND_INTX_DISABLE
+        && enable_device(value) == value
+}
+
+/// Test that status bits are not written back, which would clear them
+pub fn test_enable_drops_status() -> bool {
+    // Detected parity error and received master abort, plus the cap list bit
+    let dword = (0x8000_u32 | 0x2000 | 0x0010) << 16 | PCI_COMMAND_IO;
+    enable_device(dword) >> 16 == 0 && command_bits(dword) == PCI_COMMAND_IO
+}
+
+/// Run all tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_enable_sets_memory_and_bus_master,
+        test_enable_keeps_existing_bits,
+        test_enable_drops_status,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
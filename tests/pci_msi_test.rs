@@ -0,0 +1,88 @@
+//! PCI Capability and MSI Layout Tests
+//!
+//! Tests for walking the capability list and decoding MSI/MSI-X registers
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/pci/src/msi.rs"]
+mod msi;
+
+use msi::*;
+
+/// Fake configuration space with the capability list bit set
+fn config_space(caps: &[(u8, u8, u8)]) -> [u32; 64] {
+    let mut space = [0u32; 64];
+    space[1] = PCI_STATUS_CAP_LIST << 16;
+    if let Some(&(first, _, _)) = caps.first() {
+        space[0x34 / 4] = first as u32;
+    }
+    // (offset, id, next)
+    for &(offset, id, next) in caps {
+        space[offset as usize / 4] = id as u32 | ((next as u32) << 8);
+    }
+    space
+}
+
+/// Test finding MSI-X behind a power management capability
+pub fn test_find_capability() -> bool {
+    let space = config_space(&[(0x40, 0x01, 0x50), (0x50, PCI_CAP_ID_MSI, 0x70), (0x70, PCI_CAP_ID_MSIX, 0)]);
+    let read = |offset: u8| space[offset as usize / 4];
+
+    find_capability(read, PCI_CAP_ID_MSIX) == Some(0x70)
+        && find_capability(read, PCI_CAP_ID_MSI) == Some(0x50)
+        && find_capability(read, 0x10).is_none()
+}
+
+/// Test that a device without the capability list bit has no capabilities
+pub fn test_no_capability_list() -> bool {
+    let mut space = config_space(&[(0x40, PCI_CAP_ID_MSI, 0)]);
+    space[1] = 0;
+    find_capability(|offset| space[offset as usize / 4], PCI_CAP_ID_MSI).is_none()
+}
+
+/// Test that a looping capability list terminates
+pub fn test_capability_loop() -> bool {
+    let space = config_space(&[(0x40, 0x01, 0x48), (0x48, 0x09, 0x40)]);
+    find_capability(|offset| space[offset as usize / 4], PCI_CAP_ID_MSI).is_none()
+}
+
+/// Test 32-bit and 64-bit MSI layouts and single-vector enable
+pub fn test_msi_layout() -> bool {
+    let narrow = msi_layout(0);
+    let wide = msi_layout(MSI_CONTROL_64BIT);
+
+    narrow.address_high.is_none() && narrow.data == 0x08
+        && wide.address_high == Some(0x08) && wide.data == 0x0C
+        && msi_enable_control(MSI_CONTROL_64BIT | (0x3 << 4)) == MSI_CONTROL_64BIT | MSI_CONTROL_ENABLE
+}
+
+/// Test splitting the MSI-X table register into BAR and offset
+pub fn test_msix_table_location() -> bool {
+    msix_table_location(0x0000_2003) == (3, 0x2000)
+}
+
+/// Run all capability and MSI tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 5] = [
+        test_find_capability,
+        test_no_capability_list,
+        test_capability_loop,
+        test_msi_layout,
+        test_msix_table_location,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
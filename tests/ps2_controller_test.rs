@@ -0,0 +1,65 @@
+//! PS/2 Controller Tests
+//!
+//! Tests for byte routing and configuration in the PS/2 controller driver
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/ps2/src/controller.rs"]
+mod controller;
+
+use controller::*;
+
+/// Test that bytes are routed by the AUX bit, not by which IRQ fired
+pub fn test_route_by_aux_bit() -> bool {
+    Channel::from_status(0).is_none()
+        && Channel::from_status(STATUS_AUX_DATA).is_none()
+        && Channel::from_status(STATUS_OUTPUT_FULL) == Some(Channel::Keyboard)
+        && Channel::from_status(STATUS_OUTPUT_FULL | STATUS_AUX_DATA) == Some(Channel::Aux)
+        && Channel::from_status(STATUS_OUTPUT_FULL | STATUS_INPUT_FULL) == Some(Channel::Keyboard)
+}
+
+/// Test that probing turns both IRQs off and keeps scancode translation
+pub fn test_init_config() -> bool {
+    let translation = 1 << 6;
+    let config = init_config(CONFIG_PORT1_IRQ | CONFIG_PORT2_IRQ | translation);
+    config == translation
+        && aux_present(config)
+        && !aux_present(config | CONFIG_PORT2_CLOCK_OFF)
+}
+
+/// Test that only ports that exist get their clock and IRQ enabled
+pub fn test_run_config() -> bool {
+    let off = CONFIG_PORT1_CLOCK_OFF | CONFIG_PORT2_CLOCK_OFF;
+
+    let dual = run_config(off, true);
+    let single = run_config(off | CONFIG_PORT2_IRQ, false);
+
+    dual == CONFIG_PORT1_IRQ | CONFIG_PORT2_IRQ
+        && single == CONFIG_PORT1_IRQ | CONFIG_PORT2_CLOCK_OFF
+        && Channel::from_u8(Channel::Aux as u8) == Some(Channel::Aux)
+        && Channel::from_u8(2).is_none()
+}
+
+/// Run all tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_route_by_aux_bit,
+        test_init_config,
+        test_run_config,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
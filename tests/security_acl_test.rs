@@ -0,0 +1,92 @@
+//! ACL Tests
+//!
+//! Tests for the ACL table and its on-disk format
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/security/src/acl.rs"]
+mod acl;
+
+use acl::*;
+
+fn sample_table() -> AclTable {
+    let mut table = AclTable::new();
+    let _ = table.set_entry(1, AclEntry::new(AclEntryType::User, 1000, ACL_READ | ACL_WRITE));
+    let _ = table.set_entry(1, AclEntry::new(AclEntryType::Other, 0, ACL_READ));
+    let _ = table.set_entry(42, AclEntry::new(AclEntryType::Group, 7, ACL_EXECUTE));
+    table
+}
+
+/// Test that a saved table loads back with the same rules
+pub fn test_round_trip() -> bool {
+    let mut buffer = [0u8; ACL_DB_MAX_SIZE];
+    let len = match sample_table().serialize(&mut buffer) {
+        Some(len) => len,
+        None => return false,
+    };
+
+    match AclTable::deserialize(&buffer[..len]) {
+        Some(table) => {
+            table.check_access(1, 1000, 0, ACL_WRITE)
+                && table.check_access(1, 5, 0, ACL_READ)
+                && !table.check_access(1, 5, 0, ACL_WRITE)
+                && table.check_access(42, 5, 7, ACL_EXECUTE)
+                && table.get(1).map_or(0, |acl| acl.entries().count()) == 2
+        }
+        None => false,
+    }
+}
+
+/// Test that damaged or foreign files are rejected
+pub fn test_corrupt_rejected() -> bool {
+    let mut buffer = [0u8; ACL_DB_MAX_SIZE];
+    let len = match sample_table().serialize(&mut buffer) {
+        Some(len) => len,
+        None => return false,
+    };
+
+    let mut flipped = buffer;
+    flipped[len - 1] ^= 0x01;
+    let mut version = buffer;
+    version[4] = 2;
+
+    AclTable::deserialize(&flipped[..len]).is_none()
+        && AclTable::deserialize(&version[..len]).is_none()
+        && AclTable::deserialize(&buffer[..len - 1]).is_none()
+        && AclTable::deserialize(&[]).is_none()
+}
+
+/// Test that resources without rules deny access, and that removing the
+/// last entry drops the resource
+pub fn test_default_deny() -> bool {
+    let mut table = sample_table();
+    let removed = table.remove_entry(42, AclEntryType::Group, 7).is_ok();
+
+    removed && table.get(42).is_none()
+        && !table.check_access(42, 5, 7, ACL_EXECUTE)
+        && !AclTable::new().check_access(1, 0, 0, ACL_READ)
+}
+
+/// Run all ACL tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_round_trip,
+        test_corrupt_rejected,
+        test_default_deny,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
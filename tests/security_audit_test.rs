@@ -0,0 +1,80 @@
+//! Audit Log Tests
+//!
+//! Tests for the security service's ring of access decisions
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/security/src/audit.rs"]
+mod audit;
+
+use audit::*;
+
+/// Test that a full log drops the oldest records and counts them
+pub fn test_overflow_drops_oldest() -> bool {
+    let mut log = AuditLog::new();
+    for pid in 0..AUDIT_CAPACITY as u32 + 3 {
+        log.record(AuditRecord::capability(pid as u64, pid, 1, 0, true));
+    }
+
+    let full = log.len() == AUDIT_CAPACITY;
+    let dropped = log.take_dropped();
+    let oldest = log.pop().map(|r| r.pid);
+
+    full && dropped == 3 && log.take_dropped() == 0 && oldest == Some(3)
+}
+
+/// Test that denials-only mode skips allowed decisions
+pub fn test_denials_only() -> bool {
+    let mut log = AuditLog::new();
+    log.denials_only = true;
+    log.record(AuditRecord::capability(1, 5, 1, 10, true));
+    log.record(AuditRecord::access(2, 5, 0, b"/etc/passwd", false));
+
+    match log.pop() {
+        Some(record) => {
+            record.kind == AuditKind::Access
+                && !record.allowed
+                && &record.name[..11] == b"/etc/passwd"
+                && log.pop().is_none()
+        }
+        None => false,
+    }
+}
+
+/// Test the wire layout of a record
+pub fn test_encode() -> bool {
+    let mut out = [0xAAu8; AUDIT_RECORD_SIZE];
+    AuditRecord::capability(0x1122, 7, 2, 0x99, false).encode(&mut out);
+
+    out[0..8] == 0x1122u64.to_le_bytes()
+        && out[8..12] == 7u32.to_le_bytes()
+        && out[12] == AuditKind::Capability as u8
+        && out[13] == 2
+        && out[14] == 0
+        && out[16..24] == 0x99u64.to_le_bytes()
+        && out[24..].iter().all(|&b| b == 0)
+}
+
+/// Run all audit log tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_overflow_drops_oldest,
+        test_denials_only,
+        test_encode,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
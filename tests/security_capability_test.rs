@@ -0,0 +1,193 @@
+//! Capability Tests
+//!
+//! Tests for capability inheritance, delegation and expiry in the security
+//! service
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/security/src/syscalls.rs"]
+mod syscalls;
+#[path = "../services/security/src/capability.rs"]
+mod capability;
+
+use capability::*;
+
+const PARENT: u32 = 5;
+const CHILD: u32 = 6;
+
+/// Test that a FileRead cap reaches the child only if marked inheritable
+pub fn test_inherit_file_read() -> bool {
+    let mut parent: CapabilityTable = CapabilityTable::new();
+    let mut inheritable = Capability::new(CapabilityType::FileRead, 1, PARENT);
+    inheritable.flags = CAP_FLAG_INHERITABLE;
+    let private = Capability::new(CapabilityType::FileRead, 2, PARENT);
+    if parent.add(inheritable).is_err() || parent.add(private).is_err() {
+        return false;
+    }
+
+    let mut child: CapabilityTable = CapabilityTable::new();
+    let added = child.inherit_from(&parent, CHILD);
+
+    let copy = child.find(CapabilityType::FileRead, 1).and_then(|idx| child.get(idx));
+    added == 1
+        && copy.map_or(false, |cap| cap.owner_pid == CHILD && cap.id != inheritable.id)
+        && child.find(CapabilityType::FileRead, 2).is_none()
+}
+
+/// Test that hardware capabilities never propagate, even when flagged
+pub fn test_hardware_not_inherited() -> bool {
+    let mut parent: CapabilityTable = CapabilityTable::new();
+    let mut mmio = Capability::new(CapabilityType::HardwareMMIO, 0xFEE0_0000, PARENT);
+    mmio.flags = CAP_FLAG_INHERITABLE;
+    if parent.add(mmio).is_err() {
+        return false;
+    }
+
+    let mut child: CapabilityTable = CapabilityTable::new();
+    child.inherit_from(&parent, CHILD) == 0
+        && child.find(CapabilityType::HardwareMMIO, 0xFEE0_0000).is_none()
+}
+
+/// Test that delegation can narrow but never widen permissions
+pub fn test_delegate_attenuation() -> bool {
+    let mut cap = Capability::new(CapabilityType::DeviceRead, 4, PARENT);
+    cap.permissions = 0b0110;
+
+    let narrowed = cap.delegate(CHILD, 0b0010);
+    narrowed.map_or(false, |d| {
+        d.permissions == 0b0010 && d.parent_id == cap.id && d.owner_pid == CHILD && d.id != cap.id
+            && d.delegation_depth == cap.delegation_depth - 1
+    }) && cap.delegate(CHILD, 0b0111).is_err()
+}
+
+/// Test that revoking a capability revokes everything delegated from it
+pub fn test_revoke_cascades() -> bool {
+    // Pids 0 to 3 with 16 capabilities each; the default size is too big
+    // for the stack
+    let mut mgr = CapabilityManager::<4, 16>::new();
+    for pid in 0..4 {
+        if mgr.init_process(pid).is_err() {
+            return false;
+        }
+    }
+
+    let root = match mgr.grant(0, Capability::new(CapabilityType::DeviceRead, 4, 0)) {
+        Ok(idx) => idx,
+        Err(_) => return false,
+    };
+    let unrelated = mgr.grant(3, Capability::new(CapabilityType::DeviceRead, 4, 3));
+    // 0 -> 1 -> 2
+    let first = mgr.delegate(0, 0, 1, root, 0);
+    let second = first.and_then(|idx| mgr.delegate(1, 1, 2, idx, 0));
+    if unrelated.is_err() || second.is_err() || !mgr.check(2, CapabilityType::DeviceRead, 4) {
+        return false;
+    }
+
+    mgr.revoke(0, root).is_ok()
+        && !mgr.check(1, CapabilityType::DeviceRead, 4)
+        && !mgr.check(2, CapabilityType::DeviceRead, 4)
+        && mgr.check(3, CapabilityType::DeviceRead, 4)
+        && mgr.init_process(4).is_err()
+}
+
+/// Test that a process cannot hand out another process's capability
+pub fn test_third_party_delegation_refused() -> bool {
+    let mut mgr = CapabilityManager::<3, 16>::new();
+    for pid in 0..3 {
+        if mgr.init_process(pid).is_err() {
+            return false;
+        }
+    }
+
+    let held = match mgr.grant(0, Capability::new(CapabilityType::DeviceWrite, 7, 0)) {
+        Ok(idx) => idx,
+        Err(_) => return false,
+    };
+
+    // Pid 2 asks for pid 0's capability to go to itself, then to pid 1
+    mgr.delegate(2, 0, 2, held, 0).is_err()
+        && mgr.delegate(2, 0, 1, held, 0).is_err()
+        && !mgr.check(2, CapabilityType::DeviceWrite, 7)
+        && !mgr.check(1, CapabilityType::DeviceWrite, 7)
+        && mgr.delegate(0, 0, 1, held, 0).is_ok()
+        && mgr.check(1, CapabilityType::DeviceWrite, 7)
+}
+
+/// Test that only the parent or init may ask for inheritance, and never
+/// over a child that already has capabilities
+pub fn test_inherit_guarded() -> bool {
+    const INIT: u32 = 1;
+    const OTHER: u32 = 3;
+    let mut mgr = CapabilityManager::<4, 16>::new();
+    if mgr.init_process(0).is_err() || mgr.init_process(OTHER).is_err() {
+        return false;
+    }
+    let mut cap = Capability::new(CapabilityType::FileRead, 2, 0);
+    cap.flags = CAP_FLAG_INHERITABLE;
+    let own = Capability::new(CapabilityType::DeviceRead, 8, OTHER);
+    if mgr.grant(0, cap).is_err() || mgr.grant(OTHER, own).is_err() {
+        return false;
+    }
+
+    // A bystander cannot trigger it; pid 3 already has a table of its own
+    let refused = mgr.inherit(OTHER, 0, 2, INIT).is_err()
+        && mgr.inherit(0, 0, OTHER, INIT).is_err()
+        && mgr.inherit(INIT, 0, OTHER, INIT).is_err()
+        && mgr.check(OTHER, CapabilityType::DeviceRead, 8)
+        && !mgr.check(OTHER, CapabilityType::FileRead, 2);
+
+    refused
+        && mgr.inherit(0, 0, 2, INIT) == Ok(1)
+        && mgr.check(2, CapabilityType::FileRead, 2)
+        && mgr.inherit(INIT, 0, 2, INIT).is_err()
+}
+
+/// Test that a lapsed capability reads as expired, not as never granted
+pub fn test_expiry() -> bool {
+    let mut table: CapabilityTable = CapabilityTable::new();
+    let mut lease = Capability::new(CapabilityType::DeviceWrite, 9, PARENT);
+    lease.expires_at_ms = 500;
+    if table.add(lease).is_err() {
+        return false;
+    }
+
+    let before = table.lookup(CapabilityType::DeviceWrite, 9, 499) == CapabilityStatus::Granted;
+    let after = table.lookup(CapabilityType::DeviceWrite, 9, 500) == CapabilityStatus::Expired;
+    // Pruned: the slot is free again
+    let pruned = table.find(CapabilityType::DeviceWrite, 9).is_none();
+
+    // Granting it again replaces the lease
+    let renewed = table.add(Capability::new(CapabilityType::DeviceWrite, 9, PARENT)).is_ok()
+        && table.lookup(CapabilityType::DeviceWrite, 9, 10_000) == CapabilityStatus::Granted;
+
+    before && after && pruned && renewed
+        && table.lookup(CapabilityType::DeviceWrite, 10, 0) == CapabilityStatus::Absent
+}
+
+/// Run all capability tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 7] = [
+        test_inherit_file_read,
+        test_hardware_not_inherited,
+        test_delegate_attenuation,
+        test_revoke_cascades,
+        test_third_party_delegation_refused,
+        test_inherit_guarded,
+        test_expiry,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
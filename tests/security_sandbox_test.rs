@@ -0,0 +1,118 @@
+//! Sandbox Tests
+//!
+//! Tests for sandbox file system path rules
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/security/src/syscalls.rs"]
+mod syscalls;
+#[path = "../services/security/src/capability.rs"]
+mod capability;
+#[path = "../services/security/src/sandbox.rs"]
+mod sandbox;
+
+use sandbox::*;
+
+fn app_config() -> Option<SandboxConfig> {
+    SandboxConfig::new_default()
+        .allow_path("/home/app")
+        .and_then(|c| c.deny_path("/home/app/secrets"))
+        .and_then(|c| c.deny_path("/etc"))
+        .ok()
+}
+
+/// Test that the longest matching rule decides, on component boundaries
+pub fn test_longest_prefix() -> bool {
+    let config = match app_config() {
+        Some(config) => config,
+        None => return false,
+    };
+
+    config.check_path_allowed("/home/app")
+        && config.check_path_allowed("/home/app/data/file.txt")
+        && !config.check_path_allowed("/home/app/secrets/key")
+        && !config.check_path_allowed("/home/apple")
+        && !config.check_path_allowed("/etc/passwd")
+        && !config.check_path_allowed("/tmp")
+}
+
+/// Test that a path cannot climb out of its allowed directory
+pub fn test_no_escape() -> bool {
+    let config = match app_config() {
+        Some(config) => config,
+        None => return false,
+    };
+
+    !config.check_path_allowed("/home/app/../../etc/passwd")
+        && !config.check_path_allowed("home/app/file")
+        && SandboxConfig::new_default().allow_path("/home/../etc").is_err()
+}
+
+/// Test that a new rule replaces one for the same path and that processes
+/// without a sandbox are not confined
+pub fn test_replace_and_unsandboxed() -> bool {
+    let mut config = SandboxConfig::new_permissive();
+    let _ = config.add_denied_path("/etc/");
+    let _ = config.add_allowed_path("/etc");
+
+    // Room for pids 0 and 1 only; the full table is too big for the stack
+    let mut manager = SandboxManager::<2>::new();
+    let confined = match app_config() {
+        Some(config) => manager.create_sandbox(1, 0, config).is_ok(),
+        None => false,
+    };
+
+    config.path_rules().len() == 2
+        && config.check_path_allowed("/etc/hosts")
+        && config.check_path_allowed("/var/log")
+        && confined
+        && !manager.check_access(1, "file", "/etc/passwd")
+        && manager.check_access(0, "file", "/etc/passwd")
+}
+
+/// Test that only the creator or init may change a sandbox, and never the
+/// sandboxed process itself
+pub fn test_only_creator_configures() -> bool {
+    const APP: u32 = 1;
+    const LAUNCHER: u32 = 2;
+    const INIT: u32 = 3;
+    const OTHER: u32 = 4;
+
+    // Only the sandboxed pid needs a slot
+    let mut manager = SandboxManager::<2>::new();
+    let may_confine = manager.may_configure(APP, LAUNCHER, INIT) && !manager.may_configure(APP, APP, INIT);
+    let confined = manager.create_sandbox(APP, LAUNCHER, SandboxConfig::new_default()).is_ok();
+
+    may_confine
+        && confined
+        && manager.get_sandbox(APP).map(|s| s.creator) == Some(LAUNCHER)
+        && manager.may_configure(APP, LAUNCHER, INIT)
+        && manager.may_configure(APP, INIT, INIT)
+        && !manager.may_configure(APP, APP, INIT)
+        && !manager.may_configure(APP, OTHER, INIT)
+}
+
+/// Run all sandbox tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 4] = [
+        test_longest_prefix,
+        test_no_escape,
+        test_replace_and_unsandboxed,
+        test_only_creator_configures,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,55 @@
+//! Serial Line Settings Tests
+//!
+//! Tests for the serial driver's divisor and line control computation
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/serial/src/line.rs"]
+mod line;
+
+use line::*;
+
+/// Test divisors for common rates and rejection of unreachable ones
+pub fn test_divisor() -> bool {
+    divisor(115200) == Some(1)
+        && divisor(38400) == Some(3)
+        && divisor(9600) == Some(12)
+        && divisor(50) == Some(2304)
+        && divisor(0).is_none()
+        && divisor(230400).is_none()
+        && divisor(100000).is_none()
+}
+
+/// Test Line Control Register values and invalid combinations
+pub fn test_line_control() -> bool {
+    line_control(8, Parity::None, 1) == Some(0x03)
+        && line_control(7, Parity::Even, 1) == Some(0x1A)
+        && line_control(5, Parity::Odd, 2) == Some(0x0C)
+        && line_control(8, Parity::Space, 2) == Some(0x3F)
+        && line_control(9, Parity::None, 1).is_none()
+        && line_control(8, Parity::None, 3).is_none()
+        && Parity::from_u8(5).is_none()
+}
+
+/// Run all serial line settings tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 2] = [
+        test_divisor,
+        test_line_control,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
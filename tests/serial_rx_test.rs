@@ -0,0 +1,62 @@
+//! Serial Receive Tests
+//!
+//! Tests for the serial driver's interrupt receive buffer
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/serial/src/rx_buffer.rs"]
+mod rx_buffer;
+
+use rx_buffer::*;
+
+/// Test that bytes come out in the order they arrived
+pub fn test_fifo_order() -> bool {
+    let buffer = RxBuffer::new();
+    for byte in b"ls\r" {
+        buffer.push(*byte);
+    }
+
+    buffer.pop() == Some(b'l')
+        && buffer.pop() == Some(b's')
+        && buffer.pop() == Some(b'\r')
+        && buffer.pop().is_none()
+}
+
+/// Test that a full buffer keeps unread input and counts what it drops
+pub fn test_full_drops_new() -> bool {
+    let buffer = RxBuffer::new();
+    let mut accepted = 0;
+    for i in 0..RX_BUFFER_SIZE + 10 {
+        if buffer.push(i as u8) {
+            accepted += 1;
+        }
+    }
+
+    accepted == RX_BUFFER_SIZE - 1
+        && buffer.dropped() == 11
+        && buffer.pop() == Some(0)
+        && buffer.push(0xAA)
+}
+
+/// Run all serial receive tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 2] = [
+        test_fifo_order,
+        test_full_drops_new,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
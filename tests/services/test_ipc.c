@@ -6,6 +6,7 @@
 #include "../../kernel/include/types.h"
 #include "../../kernel/include/ipc/ipc.h"
 #include "../../kernel/include/syscall/syscall.h"
+#include "../../kernel/include/sched/scheduler.h"
 #include "../../kernel/include/kprintf.h"
 
 /**
@@ -111,3 +112,77 @@ void test_capability_enforcement(void) {
     ipc_destroy_port(port);
 }
 
+
+// Port created by another thread for the reply port test
+static volatile uint64_t foreign_port = 0;
+
+static void create_foreign_port(void* arg) {
+    (void)arg;
+    foreign_port = ipc_create_port();
+}
+
+/**
+ * Test that a message cannot name a reply port the sender may not send to
+ */
+void test_reply_port_enforcement(void) {
+    kinfo("Testing reply port enforcement...\n");
+    
+    uint64_t server = ipc_create_port();
+    uint64_t own_reply = ipc_create_port();
+    if (server == 0 || own_reply == 0) {
+        kerror("Failed to create IPC ports\n");
+        return;
+    }
+    
+    if (thread_create(create_foreign_port, NULL, 0, "ipc_test_owner") == 0) {
+        kerror("Failed to create port owner thread\n");
+        return;
+    }
+    while (foreign_port == 0) {
+        thread_yield();
+    }
+    
+    ipc_message_t msg;
+    memset(&msg, 0, sizeof(msg));
+    msg.msg_id = 1;
+    msg.type = IPC_MSG_REQUEST;
+    
+    // A port this thread does not own and holds no capability for
+    msg.reply_port = foreign_port;
+    if (ipc_send(server, &msg) == 0) {
+        kerror("Message with a foreign reply port was accepted\n");
+        return;
+    }
+    
+    // Its own port is fine, and the server's reply reaches it
+    msg.reply_port = own_reply;
+    if (ipc_send(server, &msg) != 0) {
+        kerror("Message with an owned reply port was refused\n");
+        return;
+    }
+    
+    ipc_message_t request;
+    ipc_message_t response;
+    memset(&response, 0, sizeof(response));
+    if (ipc_receive(server, &request) != 0 || request.reply_port != own_reply) {
+        kerror("Request did not carry its reply port\n");
+        return;
+    }
+    if (ipc_reply(&request, &response) != 0) {
+        kerror("Reply to an owned reply port failed\n");
+        return;
+    }
+    
+    // Forged after receipt, the request still cannot reach the foreign port
+    request.reply_port = foreign_port;
+    if (ipc_reply(&request, &response) == 0) {
+        kerror("Reply to a foreign port was delivered\n");
+        return;
+    }
+    
+    kinfo("Reply port enforcement test PASSED\n");
+    
+    // Cleanup
+    ipc_destroy_port(server);
+    ipc_destroy_port(own_reply);
+}
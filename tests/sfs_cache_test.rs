@@ -0,0 +1,116 @@
+//! SFS Block Cache Tests
+//!
+//! Tests for LRU eviction and write-back behaviour of the SFS block cache
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[path = "../services/vfs/src/sfs/cache.rs"]
+mod cache;
+
+use alloc::vec;
+use cache::BlockCache;
+
+/// Test that the least recently used block is evicted first
+pub fn test_lru_eviction() -> bool {
+    let mut cache = BlockCache::with_capacity(2);
+
+    cache.put(1, vec![1u8; 16], false);
+    cache.put(2, vec![2u8; 16], false);
+
+    // Touch block 1 so block 2 becomes the LRU entry
+    if cache.get(1).is_none() {
+        return false;
+    }
+
+    // Inserting a third block must evict block 2, not block 1
+    cache.put(3, vec![3u8; 16], false);
+
+    cache.len() == 2 && cache.contains(1) && !cache.contains(2) && cache.contains(3)
+}
+
+/// Test that evicting a dirty block hands it back for write-back
+pub fn test_dirty_eviction_writeback() -> bool {
+    let mut cache = BlockCache::with_capacity(1);
+
+    if cache.put(7, vec![0xAAu8; 16], true).is_some() {
+        return false;
+    }
+
+    match cache.put(8, vec![0xBBu8; 16], false) {
+        Some((block_num, data)) => block_num == 7 && data[0] == 0xAA,
+        None => false,
+    }
+}
+
+/// Test that clean blocks are dropped silently and flushed blocks become clean
+pub fn test_clean_eviction_and_flush() -> bool {
+    let mut cache = BlockCache::with_capacity(1);
+
+    cache.put(1, vec![0u8; 16], false);
+    if cache.put(2, vec![0u8; 16], true).is_some() {
+        return false; // Block 1 was clean, nothing to write back
+    }
+
+    if cache.dirty_blocks() != vec![2] {
+        return false;
+    }
+    cache.mark_clean(2);
+
+    cache.dirty_blocks().is_empty() && cache.put(3, vec![0u8; 16], false).is_none()
+}
+
+/// Test that an invalidated (reallocated) block is never served stale
+pub fn test_invalidate_on_realloc() -> bool {
+    let mut cache = BlockCache::with_capacity(4);
+
+    cache.put(5, vec![0x11u8; 16], true);
+    cache.invalidate(5);
+
+    cache.get(5).is_none() && cache.dirty_blocks().is_empty()
+}
+
+/// Test that shrinking the capacity evicts down to the new size
+pub fn test_shrink_capacity() -> bool {
+    let mut cache = BlockCache::with_capacity(4);
+
+    for block in 0..4 {
+        cache.put(block, vec![block as u8; 16], block % 2 == 0);
+    }
+
+    let evicted = cache.set_capacity(1);
+
+    // Blocks 0..=2 are evicted in LRU order; only 0 and 2 were dirty
+    cache.len() == 1
+        && cache.contains(3)
+        && evicted.len() == 2
+        && evicted[0].0 == 0
+        && evicted[1].0 == 2
+}
+
+/// Run all block cache tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 5] = [
+        test_lru_eviction,
+        test_dirty_eviction_writeback,
+        test_clean_eviction_and_flush,
+        test_invalidate_on_realloc,
+        test_shrink_capacity,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,95 @@
+//! SFS Directory Entry Tests
+//!
+//! Tests for the entries that link files and directories into their parent
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/vfs/src/sfs/dirent.rs"]
+mod dirent;
+
+use dirent::*;
+
+const BLOCK_SIZE: usize = 4096;
+
+/// Test that entries are found by name and free slots are reused
+pub fn test_add_and_find_entries() -> bool {
+    let mut block = [0u8; BLOCK_SIZE];
+    write_entry(&mut block, 0, 5, b".");
+    write_entry(&mut block, 1, 1, b"..");
+
+    let slot = find_free_slot(&block);
+    if let Some(slot) = slot {
+        write_entry(&mut block, slot, 42, b"notes.txt");
+    }
+    let found = slot == Some(2) && find_entry(&block, b"notes.txt") == Some((2, 42));
+    // A prefix of a stored name is a different name
+    let exact = find_entry(&block, b"notes").is_none();
+
+    clear_entry(&mut block, 2);
+    let cleared = find_entry(&block, b"notes.txt").is_none() && find_free_slot(&block) == Some(2);
+
+    found && exact && cleared && find_entry(&block, b"..") == Some((1, 1))
+}
+
+/// Test that only "." and ".." leave a directory empty
+pub fn test_has_children() -> bool {
+    let mut block = [0u8; BLOCK_SIZE];
+    write_entry(&mut block, 0, 5, b".");
+    write_entry(&mut block, 1, 1, b"..");
+    let empty = !has_children(&block);
+
+    // An entry at the last slot still counts
+    let last = BLOCK_SIZE / DIRENT_SIZE - 1;
+    write_entry(&mut block, last, 9, b"a");
+    let full = has_children(&block);
+
+    // A longer name overwritten by a shorter one leaves no trailing bytes
+    write_entry(&mut block, last, 9, b"abcdef");
+    write_entry(&mut block, last, 9, b"ab");
+
+    empty && full && entry_name(&block, last) == b"ab"
+}
+
+/// Test name validation and splitting paths into parent and name
+pub fn test_names_and_paths() -> bool {
+    let long = [b'x'; DIRENT_NAME_LEN + 1];
+    let names = valid_name(b"file")
+        && valid_name(&long[..DIRENT_NAME_LEN])
+        && !valid_name(&long)
+        && !valid_name(b"")
+        && !valid_name(b".")
+        && !valid_name(b"..")
+        && !valid_name(b"a/b");
+
+    let paths = split_parent("/a/b/c") == Some(("/a/b", "c"))
+        && split_parent("/top") == Some(("/", "top"))
+        && split_parent("/dir/") == Some(("/", "dir"))
+        && split_parent("//a//b") == Some(("//a", "b"))
+        && split_parent("/").is_none();
+
+    names && paths
+}
+
+/// Run all directory entry tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_add_and_find_entries,
+        test_has_children,
+        test_names_and_paths,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,87 @@
+//! SFS Consistency Check Tests
+//!
+//! Tests for the block ownership tally behind `SfsFileSystem::fsck`
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[path = "../services/vfs/src/sfs/fsck.rs"]
+mod fsck;
+
+use fsck::*;
+
+/// Test that a consistent filesystem reports clean
+pub fn test_clean_filesystem() -> bool {
+    // Blocks 0-1 are metadata, 2..10 allocated, 10..16 free
+    let mut blocks = BlockMap::new(16, 2, |block| block < 10);
+    blocks.claim(2..5);
+    blocks.claim(5..10);
+    // Small files keep their data inline and own no blocks
+    blocks.claim(0..0);
+
+    let report = blocks.finish(|_| Some(1));
+
+    report.is_clean() && report.inodes_checked == 3 && report.blocks_in_use == 8
+}
+
+/// Test that leaked blocks and dangling extents are counted
+pub fn test_leaks_and_dangling_extents() -> bool {
+    let mut blocks = BlockMap::new(16, 2, |block| block < 10);
+    blocks.claim(2..4);
+    // Into the inode table, into free blocks, and past the end
+    blocks.claim(1..3);
+    blocks.claim(9..12);
+    blocks.claim(15..20);
+
+    // Untracked refcounts (e.g. after a remount) are not held against it
+    let report = blocks.finish(|_| None);
+
+    report.leaked_blocks == 6
+        && report.dangling_extents == 3
+        && report.blocks_in_use == 2
+        && report.refcount_mismatches == 0
+        && !report.is_clean()
+}
+
+/// Test that sharing needs CoW refcounts to back it, and the report round trip
+pub fn test_shared_blocks_and_encoding() -> bool {
+    let mut blocks = BlockMap::new(8, 1, |_| true);
+    blocks.claim(1..8);
+    blocks.claim(1..3);
+    blocks.claim(3..4);
+
+    // Blocks 1 and 2 are CoW-shared, block 3 is owned twice but counted once
+    let report = blocks.finish(|block| Some(if block < 3 { 2 } else { 1 }));
+    let tallied = report.doubly_owned_blocks == 1 && report.refcount_mismatches == 1 && report.leaked_blocks == 0;
+
+    let mut buf = [0u8; 64];
+    let encoded = report.encode(&mut buf) == Some(FSCK_REPORT_SIZE);
+    let decoded = FsckReport::decode(&buf[..FSCK_REPORT_SIZE]) == Some(report);
+
+    tallied && encoded && decoded && FsckReport::decode(&buf[..FSCK_REPORT_SIZE - 1]).is_none()
+}
+
+/// Run all consistency check tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_clean_filesystem,
+        test_leaks_and_dangling_extents,
+        test_shared_blocks_and_encoding,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,80 @@
+//! SFS Open File Tests
+//!
+//! Tests for per-handle open flags in the SFS open-file table
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[path = "../services/vfs/src/file_ops.rs"]
+mod file_ops;
+
+#[path = "../services/vfs/src/sfs/handle.rs"]
+mod handle;
+
+use file_ops::*;
+use handle::*;
+
+/// Test that handles are distinct, non-zero and carry their flags
+pub fn test_handles_keep_flags() -> bool {
+    let mut table = OpenFileTable::new();
+    let reader = table.insert(5, O_RDONLY);
+    let appender = table.insert(5, O_WRONLY | O_APPEND);
+
+    reader != 0
+        && reader != appender
+        && table.get(reader) == Some(OpenFile { inode: 5, flags: O_RDONLY })
+        && table.get(appender).map(|file| file.flags) == Some(O_WRONLY | O_APPEND)
+        && table.remove(reader).is_some()
+        && table.get(reader).is_none()
+        && table.len() == 1
+}
+
+/// Test that O_APPEND writes land at end of file whatever the offset
+pub fn test_append_offset() -> bool {
+    let append = OpenFile { inode: 1, flags: O_RDWR | O_APPEND };
+    let plain = OpenFile { inode: 1, flags: O_RDWR };
+
+    append.write_offset(0, 100) == 100
+        && append.write_offset(500, 100) == 100
+        && plain.write_offset(0, 100) == 0
+        && plain.write_offset(500, 100) == 500
+}
+
+/// Test that the access mode decides which operations a handle allows
+pub fn test_access_modes() -> bool {
+    let read_only = OpenFile { inode: 1, flags: O_RDONLY | O_TRUNC };
+    let write_only = OpenFile { inode: 1, flags: O_WRONLY };
+    let read_write = OpenFile { inode: 1, flags: O_RDWR | O_CREAT };
+
+    read_only.can_read()
+        && !read_only.can_write()
+        && !write_only.can_read()
+        && write_only.can_write()
+        && read_write.can_read()
+        && read_write.can_write()
+}
+
+/// Run all tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_handles_keep_flags,
+        test_append_offset,
+        test_access_modes,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
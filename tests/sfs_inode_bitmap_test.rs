@@ -0,0 +1,99 @@
+//! SFS Inode Bitmap Tests
+//!
+//! Tests for inode allocation through the SFS inode bitmap, reuse of
+//! freed inodes and the bitmap's trip to and from disk
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[path = "../services/vfs/src/sfs/inode_bitmap.rs"]
+mod inode_bitmap;
+
+use inode_bitmap::*;
+
+const BLOCK_SIZE: usize = 4096;
+
+/// Test that deleting a file and creating another reuses the freed inode
+/// number instead of colliding with one still in use
+pub fn test_delete_and_recreate_reuses_inode() -> bool {
+    let mut bitmap = InodeBitmap::new(64, BLOCK_SIZE);
+
+    // Create a, b and c, then unlink b
+    let a = bitmap.alloc();
+    let b = bitmap.alloc();
+    let c = bitmap.alloc();
+    let freed = b.is_some_and(|b| bitmap.free(b));
+
+    // Recreating b gets b's number back; the next file gets a fresh one
+    let recreated = bitmap.alloc();
+    let next = bitmap.alloc();
+
+    a == Some(2) && c == Some(4) && freed && recreated == b && next == Some(5)
+        && bitmap.free_count() == 64 - 6
+}
+
+/// Test that the root inode and inode 0 are never handed out or freed,
+/// and that allocation stops at the end of the inode table
+pub fn test_root_stays_reserved() -> bool {
+    let mut bitmap = InodeBitmap::new(10, BLOCK_SIZE);
+    let reserved = bitmap.is_used(0) && bitmap.is_used(ROOT_INODE)
+        && !bitmap.free(ROOT_INODE) && !bitmap.free(0);
+
+    let mut allocated = 0;
+    while let Some(inode) = bitmap.alloc() {
+        if inode <= ROOT_INODE || inode >= 10 {
+            return false;
+        }
+        allocated += 1;
+    }
+
+    reserved && allocated == 8 && bitmap.free_count() == 0 && !bitmap.free(10)
+}
+
+/// Test that only changed blocks are written back, and that a bitmap
+/// loaded from them matches the one written
+pub fn test_bitmap_round_trip() -> bool {
+    let total = (BLOCK_SIZE * 8 + 100) as u64;
+    let mut bitmap = InodeBitmap::new(total, BLOCK_SIZE);
+    let fresh = bitmap_blocks(total, BLOCK_SIZE) == 2 && bitmap.take_dirty().len() == 2;
+
+    for _ in 0..10 {
+        bitmap.alloc();
+    }
+    let changed = bitmap.take_dirty();
+    let first_only = changed.len() == 1 && changed[0] == 0;
+
+    let mut loaded = InodeBitmap::new(total, BLOCK_SIZE);
+    for index in 0..bitmap.blocks() {
+        loaded.load_block(index, bitmap.block(index));
+    }
+
+    fresh && first_only && loaded.take_dirty().is_empty()
+        && loaded.free_count() == bitmap.free_count() && loaded.is_used(11) && !loaded.is_used(12)
+        && loaded.alloc() == Some(12)
+}
+
+/// Run all SFS inode bitmap tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_delete_and_recreate_reuses_inode,
+        test_root_stays_reserved,
+        test_bitmap_round_trip,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
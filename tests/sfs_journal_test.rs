@@ -0,0 +1,109 @@
+//! SFS Journal Tests
+//!
+//! Tests for the metadata journal's transaction and on-disk records
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[path = "../services/vfs/src/crc32.rs"]
+mod crc32;
+
+#[path = "../services/vfs/src/sfs/journal.rs"]
+mod journal;
+
+use journal::*;
+
+pub const BLOCK_SIZE: usize = 4096;
+
+/// Test that a descriptor lists its home blocks and rejects garbage
+pub fn test_descriptor_round_trip() -> bool {
+    let mut block = [0u8; BLOCK_SIZE];
+    encode_descriptor(9, &[0, 7, 42], &mut block);
+
+    let decoded = decode_descriptor(&block) == Some((9, 3))
+        && descriptor_home(&block, 0) == 0
+        && descriptor_home(&block, 2) == 42;
+
+    // A cleared journal, an empty transaction and an impossible count
+    let cleared = decode_descriptor(&[0u8; BLOCK_SIZE]).is_none();
+    let mut empty = [0u8; BLOCK_SIZE];
+    encode_descriptor(9, &[], &mut empty);
+    let mut oversized = block;
+    oversized[16..20].copy_from_slice(&(DESCRIPTOR_CAPACITY as u32 + 1).to_le_bytes());
+
+    decoded && cleared && decode_descriptor(&empty).is_none() && decode_descriptor(&oversized).is_none()
+}
+
+/// Test that only a commit record over the same contents and sequence matches
+pub fn test_commit_detects_torn_transaction() -> bool {
+    let mut descriptor = [0u8; BLOCK_SIZE];
+    encode_descriptor(3, &[0, 5], &mut descriptor);
+    let images = [[0x11u8; BLOCK_SIZE], [0x22u8; BLOCK_SIZE]];
+
+    let checksum = |images: &[[u8; BLOCK_SIZE]]| {
+        let crc = images.iter().fold(update_checksum(crc32::CRC32_INIT, &descriptor), |crc, image| update_checksum(crc, image));
+        finish_checksum(crc)
+    };
+
+    let mut commit = [0u8; BLOCK_SIZE];
+    encode_commit(3, checksum(&images), &mut commit);
+    let matches = commit_matches(&commit, 3, checksum(&images));
+
+    // An image that did not make it to disk before the crash
+    let mut torn = images;
+    torn[1][100] = 0;
+    let torn_rejected = !commit_matches(&commit, 3, checksum(&torn));
+
+    // A commit record left over from an earlier transaction
+    let stale_rejected = !commit_matches(&commit, 4, checksum(&images));
+    let missing_rejected = !commit_matches(&[0u8; BLOCK_SIZE], 3, checksum(&images));
+
+    matches && torn_rejected && stale_rejected && missing_rejected
+}
+
+/// Test that a transaction keeps the latest image of each block, in block order
+pub fn test_transaction_blocks() -> bool {
+    let mut transaction = Transaction::new();
+    transaction.put(12, &[1u8; BLOCK_SIZE]);
+    transaction.put(0, &[2u8; BLOCK_SIZE]);
+    transaction.put(12, &[3u8; BLOCK_SIZE]);
+    transaction.put(30, &[4u8; BLOCK_SIZE]);
+    transaction.remove(30);
+
+    let mut order = [0u64; 2];
+    for (i, (home, _)) in transaction.iter().enumerate() {
+        order[i] = home;
+    }
+    let latest = transaction.get(12).is_some_and(|data| data[0] == 3);
+
+    let capacity = transaction_capacity(JOURNAL_BLOCKS) == JOURNAL_BLOCKS as usize - 2
+        && transaction_capacity(2) == 0
+        && transaction_capacity(1 << 20) == DESCRIPTOR_CAPACITY;
+
+    transaction.len() == 2 && order == [0, 12] && latest && !transaction.contains(30) && capacity
+}
+
+/// Run all journal tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_descriptor_round_trip,
+        test_commit_detects_torn_transaction,
+        test_transaction_blocks,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,271 @@
+//! SFS Hard Link Tests
+//!
+//! Tests for inode link counting across link and unlink, and for when an
+//! unlinked file's blocks and inode are freed, on an SFS formatted onto a
+//! disk in memory
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[path = "../services/vfs/src/ipc.rs"]
+mod ipc;
+#[path = "../services/vfs/src/syscalls.rs"]
+mod syscalls;
+#[path = "../services/vfs/src/block_device.rs"]
+mod block_device;
+#[path = "../services/vfs/src/crc32.rs"]
+mod crc32;
+#[path = "../services/vfs/src/partition.rs"]
+mod partition;
+#[path = "../services/vfs/src/file_ops.rs"]
+mod file_ops;
+#[path = "../services/vfs/src/sfs/mod.rs"]
+mod sfs;
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use block_device::{BlockDeviceInfo, BlockIo};
+use file_ops::*;
+use sfs::inode::*;
+use sfs::{SfsFileSystem, BLOCK_SIZE};
+
+const SECTOR_SIZE: usize = 512;
+const DISK_BLOCKS: usize = 128;
+
+/// Disk held in memory
+struct MemDisk {
+    data: RefCell<Vec<u8>>,
+}
+
+impl BlockIo for MemDisk {
+    fn read_blocks(&self, lba: u64, count: u32, buffer: &mut [u8]) -> Result<usize, ()> {
+        let start = lba as usize * SECTOR_SIZE;
+        let len = count as usize * SECTOR_SIZE;
+        let data = self.data.borrow();
+        if start + len > data.len() || buffer.len() < len {
+            return Err(());
+        }
+        buffer[..len].copy_from_slice(&data[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_blocks(&self, lba: u64, count: u32, buffer: &[u8]) -> Result<(), ()> {
+        let start = lba as usize * SECTOR_SIZE;
+        let len = count as usize * SECTOR_SIZE;
+        let mut data = self.data.borrow_mut();
+        if start + len > data.len() || buffer.len() < len {
+            return Err(());
+        }
+        data[start..start + len].copy_from_slice(&buffer[..len]);
+        Ok(())
+    }
+
+    fn info(&self) -> Result<BlockDeviceInfo, ()> {
+        let sectors = (self.data.borrow().len() / SECTOR_SIZE) as u64;
+        Ok(BlockDeviceInfo { sectors, sector_size: SECTOR_SIZE as u32 })
+    }
+}
+
+/// A freshly formatted SFS, mounted read-write
+fn mounted() -> Option<SfsFileSystem> {
+    let disk = MemDisk { data: RefCell::new(vec![0; DISK_BLOCKS * BLOCK_SIZE]) };
+    SfsFileSystem::format(&disk).ok()?;
+    let mut fs = SfsFileSystem::new();
+    fs.mount_on(Box::new(disk), MS_RDWR).ok()?;
+    Some(fs)
+}
+
+/// Create `path` holding a block's worth of `fill`, left open read-write
+fn create_file(fs: &mut SfsFileSystem, path: &str, fill: u8) -> Option<u64> {
+    let handle = fs.open(path, O_CREAT | O_RDWR, 0o644).ok()?;
+    let written = fs.write(handle, &[fill; BLOCK_SIZE], 0).ok()?;
+    (written == BLOCK_SIZE).then_some(handle)
+}
+
+/// Inodes and blocks in use, as fsck counts them; None if it finds damage
+fn usage(fs: &SfsFileSystem) -> Option<(u64, u64)> {
+    let report = fs.fsck().ok()?;
+    report.is_clean().then_some((report.inodes_checked, report.blocks_in_use))
+}
+
+/// Test that a new file has one link and a hard link makes two
+pub fn test_link_adds_count() -> bool {
+    // Both names resolve to the same inode, so stat sees the same count
+    let mut file = Inode::new();
+    if file.links != 1 {
+        return false;
+    }
+    file.add_link() && file.links == 2
+}
+
+/// Test that unlinking one name keeps the inode until the last link goes
+pub fn test_unlink_keeps_other_link() -> bool {
+    let mut file = Inode::new();
+    file.size = 42;
+    if !file.add_link() {
+        return false;
+    }
+
+    // First unlink leaves the other name readable
+    if file.drop_link() || file.links != 1 || file.size != 42 {
+        return false;
+    }
+    // Last unlink frees the inode
+    file.drop_link() && file.links == 0
+}
+
+/// Test that the link count stops at its limit instead of wrapping
+pub fn test_link_limit() -> bool {
+    let mut file = Inode::new();
+    file.links = u16::MAX - 1;
+    if !file.add_link() || file.links != u16::MAX {
+        return false;
+    }
+    if file.add_link() || file.links != u16::MAX {
+        return false;
+    }
+
+    // Dropping a link from an unlinked inode must not underflow
+    let mut orphan = Inode::new();
+    orphan.links = 0;
+    orphan.drop_link() && orphan.links == 0
+}
+
+/// Test that unlinking one of two names leaves the file readable under the
+/// other, and unlinking that one frees its block and inode
+pub fn test_unlink_last_link_frees_file() -> bool {
+    let mut fs = match mounted() {
+        Some(fs) => fs,
+        None => return false,
+    };
+    let empty = usage(&fs);
+
+    let handle = match create_file(&mut fs, "/a", 0x5A) {
+        Some(handle) => handle,
+        None => return false,
+    };
+    let closed = fs.close(handle).is_ok();
+    let linked = fs.link("/a", "/b").is_ok() && fs.stat("/b").is_ok_and(|st| st.links == 2);
+    let in_use = usage(&fs);
+
+    // "/b" still reaches the data, with one link left
+    let first = fs.unlink("/a").is_ok();
+    let mut data = [0u8; BLOCK_SIZE];
+    let still_there = fs.stat("/a").is_err()
+        && fs.stat("/b").is_ok_and(|st| st.links == 1 && st.size == BLOCK_SIZE as u64)
+        && fs.open("/b", O_RDONLY, 0).is_ok_and(|h| {
+            fs.read(h, &mut data, 0) == Ok(BLOCK_SIZE) && fs.close(h).is_ok()
+        })
+        && data.iter().all(|&b| b == 0x5A)
+        && usage(&fs) == in_use;
+
+    // The last name takes the inode and its block with it
+    let last = fs.unlink("/b").is_ok() && fs.stat("/b").is_err();
+
+    closed && linked && in_use.is_some_and(|(inodes, blocks)| inodes == 2 && blocks == 2)
+        && first && still_there && last && usage(&fs) == empty
+}
+
+/// Test that a file unlinked while open stays readable through its handle
+/// and is only freed when that handle is closed
+pub fn test_unlink_open_file_frees_on_close() -> bool {
+    let mut fs = match mounted() {
+        Some(fs) => fs,
+        None => return false,
+    };
+    let empty = usage(&fs);
+
+    let handle = match create_file(&mut fs, "/tmp", 0xC3) {
+        Some(handle) => handle,
+        None => return false,
+    };
+    let in_use = usage(&fs);
+
+    let unlinked = fs.unlink("/tmp").is_ok() && fs.stat("/tmp").is_err();
+    let mut data = [0u8; BLOCK_SIZE];
+    let readable = fs.read(handle, &mut data, 0) == Ok(BLOCK_SIZE)
+        && data.iter().all(|&b| b == 0xC3)
+        && fs.fstat(handle).is_ok_and(|st| st.links == 0)
+        && usage(&fs) == in_use;
+
+    // Its inode number is not handed out while the handle lives
+    let other = fs.open("/other", O_CREAT | O_RDWR, 0o644);
+    let distinct = match other {
+        Ok(h) => fs.fstat(h).is_ok_and(|st| st.inode != fs.fstat(handle).map(|s| s.inode).unwrap_or(0))
+            && fs.close(h).is_ok() && fs.unlink("/other").is_ok(),
+        Err(_) => false,
+    };
+
+    let closed = fs.close(handle).is_ok();
+
+    unlinked && readable && distinct && closed && usage(&fs) == empty
+}
+
+/// Test that a block freed by unlinking one file goes to the next file
+/// created, leaving another file's data alone
+pub fn test_unlink_keeps_other_files() -> bool {
+    let mut fs = match mounted() {
+        Some(fs) => fs,
+        None => return false,
+    };
+    let a = create_file(&mut fs, "/a", 0xAA);
+    let b = create_file(&mut fs, "/b", 0xBB);
+    let closed = a.zip(b).is_some_and(|(a, b)| fs.close(a).is_ok() && fs.close(b).is_ok());
+    if !closed || fs.unlink("/a").is_err() {
+        return false;
+    }
+
+    // Use up the space past /b, so the only free block left is the one
+    // /a gave back
+    let fill = match fs.open("/fill", O_CREAT | O_RDWR, 0o644) {
+        Ok(handle) => handle,
+        Err(_) => return false,
+    };
+    let mut offset = 0;
+    while fs.write(fill, &[0xFF; BLOCK_SIZE], offset) == Ok(BLOCK_SIZE) {
+        offset += BLOCK_SIZE as u64;
+    }
+    let c = match create_file(&mut fs, "/c", 0xCC) {
+        Some(handle) => handle,
+        None => return false,
+    };
+
+    let mut data = [0u8; BLOCK_SIZE];
+    let kept = fs.open("/b", O_RDONLY, 0).is_ok_and(|h| {
+        fs.read(h, &mut data, 0) == Ok(BLOCK_SIZE) && fs.close(h).is_ok()
+    }) && data.iter().all(|&byte| byte == 0xBB);
+    let written = fs.read(c, &mut data, 0) == Ok(BLOCK_SIZE) && data.iter().all(|&byte| byte == 0xCC);
+
+    kept && written && usage(&fs).is_some() && fs.close(c).is_ok() && fs.close(fill).is_ok()
+}
+
+/// Run all tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 6] = [
+        test_link_adds_count,
+        test_unlink_keeps_other_link,
+        test_link_limit,
+        test_unlink_last_link_frees_file,
+        test_unlink_open_file_frees_on_close,
+        test_unlink_keeps_other_files,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
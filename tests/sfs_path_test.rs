@@ -0,0 +1,58 @@
+//! SFS Path Tests
+//!
+//! Tests for "." and ".." normalization ahead of path resolution
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[path = "../services/vfs/src/sfs/path.rs"]
+mod path;
+
+use path::*;
+
+/// Test that ".." removes the component before it
+pub fn test_parent_components() -> bool {
+    normalize_path("/a/b/../c") == "/a/c"
+        && normalize_path("/a/b/c/../../d") == "/a/d"
+        && normalize_path("/a/b/..") == "/a"
+}
+
+/// Test that ".." cannot climb above the root
+pub fn test_parent_of_root() -> bool {
+    normalize_path("/../a") == "/a"
+        && normalize_path("/a/../../../b") == "/b"
+        && normalize_path("/..").is_empty()
+}
+
+/// Test that "." and empty components are dropped
+pub fn test_current_components() -> bool {
+    normalize_path("/a/./b") == "/a/b"
+        && normalize_path("//a///b/") == "/a/b"
+        && normalize_path("/./.").is_empty()
+        && normalize_path("/").is_empty()
+}
+
+/// Run all tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_parent_components,
+        test_parent_of_root,
+        test_current_components,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
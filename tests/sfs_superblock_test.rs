@@ -0,0 +1,105 @@
+//! SFS Superblock Tests
+//!
+//! Tests for superblock checksums and the backup copy mount falls back to
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/vfs/src/crc32.rs"]
+mod crc32;
+
+#[path = "../services/vfs/src/sfs/superblock.rs"]
+mod superblock;
+
+use superblock::*;
+
+pub const SFS_MAGIC: u64 = 0x5343415246535F31;
+
+const BLOCK_SIZE: usize = 4096;
+
+fn formatted() -> Superblock {
+    let mut sb = Superblock::new();
+    sb.total_blocks = 1023;
+    sb.free_blocks = 1022;
+    sb.seal();
+    sb
+}
+
+/// Test that a sealed superblock survives a trip through a block buffer
+pub fn test_sealed_round_trip() -> bool {
+    let sb = formatted();
+    let mut block = [0u8; BLOCK_SIZE];
+    sb.write_to(&mut block);
+
+    let read = Superblock::read_from(&block);
+    let matches = matches!(read, Some(copy) if copy.checksum == sb.checksum && copy.free_blocks == 1022);
+
+    // Forgetting to reseal after a change is caught
+    let mut stale = sb;
+    stale.free_blocks -= 1;
+
+    matches && sb.is_valid() && !stale.is_valid() && sb.backup_block() == 1023
+}
+
+/// Test that a torn or corrupted copy fails validation
+pub fn test_corruption_detected() -> bool {
+    let mut block = [0u8; BLOCK_SIZE];
+    formatted().write_to(&mut block);
+
+    // Torn write: the second half of the block never made it to disk
+    let mut torn = block;
+    torn[64..].fill(0);
+    let torn_rejected = Superblock::read_from(&torn).is_none();
+
+    let mut flipped = block;
+    flipped[40] ^= 0x01;
+    let flip_rejected = Superblock::read_from(&flipped).is_none();
+
+    let blank_rejected = Superblock::read_from(&[0u8; BLOCK_SIZE]).is_none();
+    let short_rejected = Superblock::read_from(&block[..100]).is_none();
+
+    torn_rejected && flip_rejected && blank_rejected && short_rejected
+}
+
+/// Test that the checksum covers the whole structure but not itself
+pub fn test_checksum_coverage() -> bool {
+    let sb = formatted();
+
+    let mut renamed = sb;
+    renamed.volume_name[0] = b'x';
+    let name_covered = renamed.compute_checksum() != sb.checksum;
+
+    // A superblock whose checksum field is garbage still hashes the same
+    let mut garbage = sb;
+    garbage.checksum = 0xDEAD_BEEF;
+    let self_excluded = garbage.compute_checksum() == sb.checksum;
+
+    let mut wrong_magic = sb;
+    wrong_magic.magic = 0;
+    wrong_magic.seal();
+
+    name_covered && self_excluded && !wrong_magic.is_valid() && SUPERBLOCK_SIZE <= BLOCK_SIZE
+}
+
+/// Run all superblock tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_sealed_round_trip,
+        test_corruption_detected,
+        test_checksum_coverage,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
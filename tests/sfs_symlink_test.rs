@@ -0,0 +1,71 @@
+//! SFS Symlink Tests
+//!
+//! Tests for splicing symlink targets into the path being resolved
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[path = "../services/vfs/src/sfs/inode.rs"]
+mod inode;
+
+#[path = "../services/vfs/src/sfs/symlink.rs"]
+mod symlink;
+
+use symlink::*;
+
+pub const BLOCK_SIZE: usize = 4096;
+
+/// Test that an absolute target replaces everything up to the link
+pub fn test_absolute_targets() -> bool {
+    // `/bin` -> `/usr/bin`
+    splice_target("/bin/ls", 0, "/usr/bin") == "/usr/bin/ls"
+        && splice_target("/a/b/link", 2, "/x") == "/x"
+        && splice_target("/a/link/c/d", 1, "/") == "/c/d"
+        && splice_target("/link", 0, "/").is_empty()
+}
+
+/// Test that a relative target is resolved from the link's directory
+pub fn test_relative_targets() -> bool {
+    let sibling = splice_target("/a/link/c", 1, "b") == "/a/b/c";
+    // ".." is left for the walk to resolve through the directory's entry
+    let parent = splice_target("/usr/local/lib", 1, "../opt") == "/usr/../opt/lib";
+    let untidy = splice_target("//a///link/", 1, "b//") == "/a/b";
+
+    sibling && parent && untidy
+}
+
+/// Test a chain of links expanding step by step, and the inline target limit
+pub fn test_link_chain() -> bool {
+    // `/bin` -> `/usr/bin`, then `/usr/bin/sh` -> `bash`
+    let first = splice_target("/bin/sh", 0, "/usr/bin");
+    let second = splice_target(&first, 2, "bash");
+
+    let inline_fits = INLINE_TARGET_LEN == inode::Inode::new().inline_data.len();
+
+    second == "/usr/bin/bash" && inline_fits && MAX_SYMLINK_DEPTH >= 8 && MAX_TARGET_LEN <= BLOCK_SIZE
+}
+
+/// Run all symlink tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_absolute_targets,
+        test_relative_targets,
+        test_link_chain,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
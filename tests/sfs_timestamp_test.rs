@@ -0,0 +1,62 @@
+//! SFS Timestamp Tests
+//!
+//! Tests for which inode timestamps each kind of operation updates
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/vfs/src/sfs/inode.rs"]
+mod inode;
+
+use inode::*;
+
+/// Test that a new inode starts with all three timestamps equal
+pub fn test_created() -> bool {
+    let mut file = Inode::new();
+    file.touch_created(100);
+    file.atime == 100 && file.mtime == 100 && file.ctime == 100
+}
+
+/// Test that a read moves only atime
+pub fn test_access() -> bool {
+    let mut file = Inode::new();
+    file.touch_created(100);
+    file.touch_access(250);
+    file.atime == 250 && file.mtime == 100 && file.ctime == 100
+}
+
+/// Test that a write moves mtime and ctime, and a metadata change only ctime
+pub fn test_modify_and_change() -> bool {
+    let mut file = Inode::new();
+    file.touch_created(100);
+    file.touch_modify(200);
+    if file.atime != 100 || file.mtime != 200 || file.ctime != 200 {
+        return false;
+    }
+
+    file.touch_change(300);
+    file.atime == 100 && file.mtime == 200 && file.ctime == 300
+}
+
+/// Run all tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_created,
+        test_access,
+        test_modify_and_change,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,264 @@
+//! SFS Truncate Tests
+//!
+//! Tests for cutting file extents on truncate and giving the blocks back,
+//! on an SFS formatted onto a disk in memory
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[path = "../services/vfs/src/ipc.rs"]
+mod ipc;
+#[path = "../services/vfs/src/syscalls.rs"]
+mod syscalls;
+#[path = "../services/vfs/src/block_device.rs"]
+mod block_device;
+#[path = "../services/vfs/src/crc32.rs"]
+mod crc32;
+#[path = "../services/vfs/src/partition.rs"]
+mod partition;
+#[path = "../services/vfs/src/file_ops.rs"]
+mod file_ops;
+#[path = "../services/vfs/src/sfs/mod.rs"]
+mod sfs;
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use block_device::{BlockDeviceInfo, BlockIo};
+use file_ops::*;
+use sfs::{SfsFileSystem, BLOCK_SIZE};
+
+const SECTOR_SIZE: usize = 512;
+const DISK_BLOCKS: usize = 512;
+
+/// Disk held in memory
+struct MemDisk {
+    data: RefCell<Vec<u8>>,
+}
+
+impl BlockIo for MemDisk {
+    fn read_blocks(&self, lba: u64, count: u32, buffer: &mut [u8]) -> Result<usize, ()> {
+        let start = lba as usize * SECTOR_SIZE;
+        let len = count as usize * SECTOR_SIZE;
+        let data = self.data.borrow();
+        if start + len > data.len() || buffer.len() < len {
+            return Err(());
+        }
+        buffer[..len].copy_from_slice(&data[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_blocks(&self, lba: u64, count: u32, buffer: &[u8]) -> Result<(), ()> {
+        let start = lba as usize * SECTOR_SIZE;
+        let len = count as usize * SECTOR_SIZE;
+        let mut data = self.data.borrow_mut();
+        if start + len > data.len() || buffer.len() < len {
+            return Err(());
+        }
+        data[start..start + len].copy_from_slice(&buffer[..len]);
+        Ok(())
+    }
+
+    fn info(&self) -> Result<BlockDeviceInfo, ()> {
+        let sectors = (self.data.borrow().len() / SECTOR_SIZE) as u64;
+        Ok(BlockDeviceInfo { sectors, sector_size: SECTOR_SIZE as u32 })
+    }
+}
+
+/// A freshly formatted SFS, mounted read-write
+fn mounted() -> Option<SfsFileSystem> {
+    let disk = MemDisk { data: RefCell::new(vec![0; DISK_BLOCKS * BLOCK_SIZE]) };
+    SfsFileSystem::format(&disk).ok()?;
+    let mut fs = SfsFileSystem::new();
+    fs.mount_on(Box::new(disk), MS_RDWR).ok()?;
+    Some(fs)
+}
+
+/// Create `path` and write `blocks` blocks of `fill` to it one block at a
+/// time, left open read-write
+fn write_file(fs: &mut SfsFileSystem, path: &str, blocks: u64, fill: u8) -> Option<u64> {
+    let handle = fs.open(path, O_CREAT | O_RDWR, 0o644).ok()?;
+    for block in 0..blocks {
+        let written = fs.write(handle, &[fill; BLOCK_SIZE], block * BLOCK_SIZE as u64).ok()?;
+        if written != BLOCK_SIZE {
+            return None;
+        }
+    }
+    Some(handle)
+}
+
+/// Inodes and blocks in use, as fsck counts them; None if it finds damage
+/// such as blocks left allocated with no file owning them
+fn usage(fs: &SfsFileSystem) -> Option<(u64, u64)> {
+    let report = fs.fsck().ok()?;
+    report.is_clean().then_some((report.inodes_checked, report.blocks_in_use))
+}
+
+/// Test that truncating a 1 MB file to 4 KB gives back every block but
+/// the first, and truncating it to nothing gives back that one too
+pub fn test_truncate_frees_tail() -> bool {
+    let mut fs = match mounted() {
+        Some(fs) => fs,
+        None => return false,
+    };
+    let (_, empty) = match usage(&fs) {
+        Some(usage) => usage,
+        None => return false,
+    };
+
+    let handle = match write_file(&mut fs, "/big", 256, 0x77) {
+        Some(handle) => handle,
+        None => return false,
+    };
+    let written = usage(&fs).is_some_and(|(_, blocks)| blocks == empty + 256)
+        && fs.fstat(handle).is_ok_and(|st| st.size == 1024 * 1024 && st.blocks == 256);
+
+    let mut data = [0u8; BLOCK_SIZE];
+    let cut = fs.truncate("/big", 4096).is_ok()
+        && usage(&fs).is_some_and(|(_, blocks)| blocks == empty + 1)
+        && fs.fstat(handle).is_ok_and(|st| st.size == 4096 && st.blocks == 1)
+        && fs.read(handle, &mut data, 0) == Ok(BLOCK_SIZE)
+        && data.iter().all(|&b| b == 0x77);
+
+    let emptied = fs.truncate("/big", 0).is_ok()
+        && usage(&fs).is_some_and(|(_, blocks)| blocks == empty)
+        && fs.fstat(handle).is_ok_and(|st| st.size == 0 && st.blocks == 0);
+
+    written && cut && emptied && fs.close(handle).is_ok()
+}
+
+/// Test that opening with `O_TRUNC` frees the file's blocks, and that the
+/// space comes back for the next write
+pub fn test_open_trunc_frees_blocks() -> bool {
+    let mut fs = match mounted() {
+        Some(fs) => fs,
+        None => return false,
+    };
+    let handle = match write_file(&mut fs, "/log", 8, 0x11) {
+        Some(handle) => handle,
+        None => return false,
+    };
+    let full = usage(&fs);
+    if fs.close(handle).is_err() {
+        return false;
+    }
+
+    let handle = match fs.open("/log", O_RDWR | O_TRUNC, 0) {
+        Ok(handle) => handle,
+        Err(_) => return false,
+    };
+    let truncated = fs.fstat(handle).is_ok_and(|st| st.size == 0 && st.blocks == 0)
+        && usage(&fs).zip(full).is_some_and(|((_, now), (_, before))| now == before - 8);
+
+    // Writing the same amount again takes exactly the blocks given back
+    let rewritten = (0..8).all(|block| fs.write(handle, &[0x22; BLOCK_SIZE], block * BLOCK_SIZE as u64) == Ok(BLOCK_SIZE))
+        && usage(&fs) == full;
+
+    let mut data = [0u8; BLOCK_SIZE];
+    let fresh = fs.read(handle, &mut data, 7 * BLOCK_SIZE as u64) == Ok(BLOCK_SIZE)
+        && data.iter().all(|&b| b == 0x22);
+
+    truncated && rewritten && fresh && fs.close(handle).is_ok()
+}
+
+/// Test that growing a file leaves a hole instead of allocating, and that
+/// partial blocks are kept
+pub fn test_grow_leaves_hole() -> bool {
+    let mut fs = match mounted() {
+        Some(fs) => fs,
+        None => return false,
+    };
+    let handle = match write_file(&mut fs, "/sparse", 1, 0x33) {
+        Some(handle) => handle,
+        None => return false,
+    };
+    if fs.write(handle, &[0x44; 10], BLOCK_SIZE as u64) != Ok(10) {
+        return false;
+    }
+    let before = usage(&fs);
+
+    let grown = fs.truncate("/sparse", 100 * BLOCK_SIZE as u64).is_ok()
+        && usage(&fs) == before
+        && fs.fstat(handle).is_ok_and(|st| st.size == 100 * BLOCK_SIZE as u64 && st.blocks == 2);
+
+    let mut data = [0xFFu8; BLOCK_SIZE];
+    let kept = fs.read(handle, &mut data, BLOCK_SIZE as u64) == Ok(BLOCK_SIZE)
+        && data[..10].iter().all(|&b| b == 0x44)
+        && data[10..].iter().all(|&b| b == 0);
+    let mut hole = [0xFFu8; BLOCK_SIZE];
+    let zeros = fs.read(handle, &mut hole, 99 * BLOCK_SIZE as u64) == Ok(BLOCK_SIZE)
+        && hole.iter().all(|&b| b == 0);
+
+    grown && kept && zeros && fs.close(handle).is_ok()
+}
+
+/// Test that blocks freed by truncating one file are reused without
+/// touching another file's data
+pub fn test_truncate_keeps_other_files() -> bool {
+    let mut fs = match mounted() {
+        Some(fs) => fs,
+        None => return false,
+    };
+    let a = write_file(&mut fs, "/a", 3, 0xAA);
+    let b = write_file(&mut fs, "/b", 1, 0xBB);
+    let (a, b) = match a.zip(b) {
+        Some(handles) => handles,
+        None => return false,
+    };
+    if fs.truncate("/a", 0).is_err() {
+        return false;
+    }
+
+    // Use up the space past /b, so the only free blocks left are the ones
+    // /a gave back
+    let fill = match fs.open("/fill", O_CREAT | O_RDWR, 0o644) {
+        Ok(handle) => handle,
+        Err(_) => return false,
+    };
+    let mut offset = 0;
+    while fs.write(fill, &[0xFF; BLOCK_SIZE], offset) == Ok(BLOCK_SIZE) {
+        offset += BLOCK_SIZE as u64;
+    }
+
+    let c = match write_file(&mut fs, "/c", 3, 0xCC) {
+        Some(handle) => handle,
+        None => return false,
+    };
+
+    let mut data = [0u8; BLOCK_SIZE];
+    let kept = fs.read(b, &mut data, 0) == Ok(BLOCK_SIZE) && data.iter().all(|&byte| byte == 0xBB);
+    let written = (0..3).all(|block| {
+        fs.read(c, &mut data, block * BLOCK_SIZE as u64) == Ok(BLOCK_SIZE)
+            && data.iter().all(|&byte| byte == 0xCC)
+    });
+
+    kept && written && usage(&fs).is_some() && [a, b, c, fill].iter().all(|&handle| fs.close(handle).is_ok())
+}
+
+/// Run all SFS truncate tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 4] = [
+        test_truncate_frees_tail,
+        test_open_trunc_frees_blocks,
+        test_grow_leaves_hole,
+        test_truncate_keeps_other_files,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
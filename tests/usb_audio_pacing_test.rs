@@ -0,0 +1,72 @@
+//! USB Audio Packet Pacing Tests
+//!
+//! Tests for splitting PCM into per-interval isochronous packets
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/audio/usb/usb_audio_pacing.rs"]
+mod usb_audio_pacing;
+
+use usb_audio_pacing::{intervals_per_second, PacketPacer};
+
+/// Test that 48 kHz stereo at full speed is 192 bytes every frame
+pub fn test_48k_full_speed() -> bool {
+    let mut pacer = PacketPacer::new(48000, 2, 2, intervals_per_second(1, false));
+
+    for _ in 0..1000 {
+        if pacer.packet_bytes() != 192 {
+            return false;
+        }
+        pacer.commit();
+    }
+    pacer.max_packet_bytes() == 192
+}
+
+/// Test that 44.1 kHz alternates 44/45 frames and averages out per second
+pub fn test_44k1_remainder() -> bool {
+    let mut pacer = PacketPacer::new(44100, 2, 2, 1000);
+    let mut total = 0;
+
+    for _ in 0..1000 {
+        let bytes = pacer.packet_bytes();
+        if bytes != 176 && bytes != 180 {
+            return false;
+        }
+        total += bytes;
+        pacer.commit();
+    }
+    total == 44100 * 4 && pacer.max_packet_bytes() == 180
+}
+
+/// Test that high speed bInterval counts microframes
+pub fn test_high_speed_interval() -> bool {
+    let pacer = PacketPacer::new(48000, 2, 3, intervals_per_second(1, true));
+
+    intervals_per_second(4, true) == 1000
+        && intervals_per_second(1, true) == 8000
+        && pacer.packet_bytes() == 6 * 6
+}
+
+/// Run all pacing tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_48k_full_speed,
+        test_44k1_remainder,
+        test_high_speed_interval,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
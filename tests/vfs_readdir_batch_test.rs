@@ -0,0 +1,149 @@
+//! VFS Batch Readdir Tests
+//!
+//! Tests for packing directory entries into VFS_OP_READDIR_BATCH replies
+//! and resuming from a cursor
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/vfs/src/file_ops.rs"]
+mod file_ops;
+
+use file_ops::*;
+
+/// Reply size used by the VFS service
+const BATCH_MAX: usize = 4096;
+
+fn entry(name: &[u8]) -> DirEntry {
+    let mut entry = DirEntry::new();
+    entry.file_type = FileType::Regular;
+    entry.name_len = name.len() as u16;
+    entry.name[..name.len()].copy_from_slice(name);
+    entry
+}
+
+/// Name of file `n` in a directory of numbered files
+fn numbered(n: u32, name: &mut [u8; 8]) {
+    name.copy_from_slice(b"file0000");
+    let mut n = n;
+    for digit in name[4..].iter_mut().rev() {
+        *digit = b'0' + (n % 10) as u8;
+        n /= 10;
+    }
+}
+
+/// Test that entries are packed whole until the next one does not fit
+pub fn test_batch_packs_until_full() -> bool {
+    let mut out = [0u8; 12];
+    let mut batch = DirBatch::new(&mut out);
+
+    let packed = batch.push(&entry(b"bin")) && batch.push(&entry(b"etc"));
+    // 10 bytes used; "usr" needs 5 and must not be cut to fit
+    let refused = !batch.push(&entry(b"usr"));
+    let small = batch.push(&entry(b""));
+
+    packed && refused && small && batch.count() == 3 && batch.bytes().len() == 12 && !batch.is_empty()
+}
+
+/// Test that a packed batch decodes back to the same entries in order
+pub fn test_batch_decodes_entries() -> bool {
+    let mut out = [0u8; 64];
+    let mut batch = DirBatch::new(&mut out);
+    let mut dir = entry(b"home");
+    dir.file_type = FileType::Directory;
+    let names: [&[u8]; 3] = [b"home", b"a", b"notes.txt"];
+    if !batch.push(&dir) || !batch.push(&entry(b"a")) || !batch.push(&entry(b"notes.txt")) {
+        return false;
+    }
+
+    let mut decoded = 0;
+    for (i, entry) in batch_entries(batch.bytes()).enumerate() {
+        let expected_type = if i == 0 { FileType::Directory } else { FileType::Regular };
+        if entry.get_name() != names[i] || entry.file_type != expected_type {
+            return false;
+        }
+        decoded += 1;
+    }
+
+    // A truncated tail stops decoding instead of yielding a short name
+    let cut = batch_entries(&batch.bytes()[..batch.bytes().len() - 1]).count();
+    decoded == 3 && cut == 2 && batch_entries(&[]).count() == 0
+}
+
+/// Test that listing 1000 files through cursors takes a handful of
+/// batches rather than a call per entry, and sees every file once
+pub fn test_listing_resumes_from_cursor() -> bool {
+    let total = 1000u32;
+    let mut cursor = 0u32;
+    let mut calls = 0;
+    let mut seen = 0u32;
+
+    loop {
+        let mut out = [0u8; BATCH_MAX];
+        let mut batch = DirBatch::new(&mut out);
+        let mut name = [0u8; 8];
+
+        // What `FileSystemOps::readdir_batch` does for a counting cursor
+        let mut next = None;
+        for n in cursor..total {
+            numbered(n, &mut name);
+            if !batch.push(&entry(&name)) {
+                next = Some(n);
+                break;
+            }
+        }
+        calls += 1;
+
+        for entry in batch_entries(batch.bytes()) {
+            numbered(seen, &mut name);
+            if entry.get_name() != name {
+                return false;
+            }
+            seen += 1;
+        }
+
+        match next {
+            Some(n) => cursor = n,
+            None => break,
+        }
+    }
+
+    seen == total && calls == 3
+}
+
+/// Test that a limited batch stops at its count with room to spare, as
+/// VFS_OP_READDIR uses for one entry at a time
+pub fn test_batch_limit() -> bool {
+    let mut out = [0u8; 64];
+    let mut batch = DirBatch::with_limit(&mut out, 1);
+
+    let first = batch.push(&entry(b"bin"));
+    let refused = !batch.push(&entry(b"etc"));
+
+    first && refused && batch.count() == 1
+        && batch_entries(batch.bytes()).next().is_some_and(|e| e.get_name() == b"bin")
+}
+
+/// Run all VFS batch readdir tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 4] = [
+        test_batch_packs_until_full,
+        test_batch_decodes_entries,
+        test_listing_resumes_from_cursor,
+        test_batch_limit,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,126 @@
+//! VFS Stat/Readdir Wire Format Tests
+//!
+//! Tests for the FileStat and DirEntry encodings used by VFS_OP_STAT and
+//! VFS_OP_READDIR replies
+
+#![no_std]
+#![no_main]
+
+#[path = "../services/vfs/src/file_ops.rs"]
+mod file_ops;
+
+use file_ops::*;
+
+fn sample_stat() -> FileStat {
+    FileStat {
+        file_type: FileType::Regular,
+        size: 12345,
+        blocks: 4,
+        block_size: 4096,
+        inode: 77,
+        links: 2,
+        uid: 1000,
+        gid: 100,
+        mode: 0o644,
+        atime: 1_000,
+        mtime: 2_000,
+        ctime: 3_000,
+    }
+}
+
+/// Test that a stat survives encoding and fits beside a status byte
+pub fn test_stat_round_trip() -> bool {
+    let stat = sample_stat();
+    let mut reply = [0u8; 64];
+    if stat.encode(&mut reply[1..]) != Some(FILE_STAT_SIZE) || FILE_STAT_SIZE + 1 > 64 {
+        return false;
+    }
+
+    match FileStat::decode(&reply[1..]) {
+        Some(decoded) => {
+            decoded.file_type == FileType::Regular
+                && decoded.size == 12345
+                && decoded.blocks == 4
+                && decoded.block_size == 4096
+                && decoded.inode == 77
+                && decoded.links == 2
+                && decoded.uid == 1000
+                && decoded.gid == 100
+                && decoded.mode == 0o644
+                && decoded.atime == 1_000
+                && decoded.mtime == 2_000
+                && decoded.ctime == 3_000
+        }
+        None => false,
+    }
+}
+
+/// Test that stats that cannot be encoded are refused
+pub fn test_stat_limits() -> bool {
+    let mut stat = sample_stat();
+    let mut out = [0u8; FILE_STAT_SIZE];
+    if stat.encode(&mut out[..FILE_STAT_SIZE - 1]).is_some() {
+        return false;
+    }
+
+    // Link counts saturate rather than wrap
+    stat.links = 70_000;
+    if stat.encode(&mut out).is_none() || FileStat::decode(&out).map(|s| s.links) != Some(65535) {
+        return false;
+    }
+
+    stat.block_size = 3000;
+    stat.encode(&mut out).is_none()
+}
+
+/// Test directory entry encoding, including a name cut short to fit
+pub fn test_dir_entry() -> bool {
+    let mut entry = DirEntry::new();
+    entry.file_type = FileType::Directory;
+    entry.name_len = 3;
+    entry.name[..3].copy_from_slice(b"bin");
+
+    let mut out = [0u8; 59];
+    let len = match entry.encode(&mut out) {
+        Some(len) => len,
+        None => return false,
+    };
+    let decoded = match DirEntry::decode(&out[..len]) {
+        Some(decoded) => decoded,
+        None => return false,
+    };
+    if len != 5 || decoded.file_type != FileType::Directory || decoded.get_name() != b"bin" {
+        return false;
+    }
+
+    // A 64-byte name does not fit and must not decode as a shorter one
+    entry.name_len = 64;
+    entry.name[..64].fill(b'x');
+    match entry.encode(&mut out) {
+        Some(len) => len == 59 && out[1] == 64 && DirEntry::decode(&out[..len]).is_none(),
+        None => false,
+    }
+}
+
+/// Run all tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_stat_round_trip,
+        test_stat_limits,
+        test_dir_entry,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,244 @@
+//! VFS Device Removal Tests
+//!
+//! Tests for unmounting the filesystems on a disk when the device manager
+//! reports it removed, using the node names the device manager gives out
+//! and the partition names the VFS registers, and for the paths a mount's
+//! filesystem is handed
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[path = "../services/vfs/src/ipc.rs"]
+mod ipc;
+#[path = "../services/vfs/src/syscalls.rs"]
+mod syscalls;
+#[path = "../services/vfs/src/block_device.rs"]
+mod block_device;
+#[path = "../services/vfs/src/crc32.rs"]
+mod crc32;
+#[path = "../services/vfs/src/partition.rs"]
+mod partition;
+#[path = "../services/vfs/src/file_ops.rs"]
+mod file_ops;
+#[path = "../services/vfs/src/vfs.rs"]
+mod vfs;
+#[path = "../services/device_manager/src/hotplug.rs"]
+mod hotplug;
+
+use block_device::{BlockDeviceInfo, BlockIo};
+use hotplug::{node_name, MAX_NODE_NAME};
+use partition::*;
+use vfs::*;
+
+const DISK_SECTORS: usize = 64;
+
+/// Disk image holding an MBR with two Linux partitions
+struct MemDisk {
+    data: [u8; DISK_SECTORS * SECTOR_SIZE],
+}
+
+impl BlockIo for MemDisk {
+    fn read_blocks(&self, lba: u64, count: u32, buffer: &mut [u8]) -> Result<usize, ()> {
+        let start = lba as usize * SECTOR_SIZE;
+        let len = count as usize * SECTOR_SIZE;
+        if start + len > self.data.len() || buffer.len() < len {
+            return Err(());
+        }
+        buffer[..len].copy_from_slice(&self.data[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_blocks(&self, _lba: u64, _count: u32, _data: &[u8]) -> Result<(), ()> {
+        Err(())
+    }
+
+    fn info(&self) -> Result<BlockDeviceInfo, ()> {
+        Ok(BlockDeviceInfo { sectors: DISK_SECTORS as u64, sector_size: SECTOR_SIZE as u32 })
+    }
+}
+
+impl MemDisk {
+    fn new() -> Self {
+        let mut disk = Self { data: [0; DISK_SECTORS * SECTOR_SIZE] };
+        disk.data[510] = 0x55;
+        disk.data[511] = 0xAA;
+        for (index, start) in [(0usize, 8u32), (1, 32)] {
+            let entry = &mut disk.data[0x1BE + index * 16..0x1BE + (index + 1) * 16];
+            entry[4] = MBR_TYPE_LINUX;
+            entry[8..12].copy_from_slice(&start.to_le_bytes());
+            entry[12..16].copy_from_slice(&16u32.to_le_bytes());
+        }
+        disk
+    }
+}
+
+/// Node name the device manager gives USB device `id` (`/dev/usb3`)
+fn usb_node(id: u32, out: &mut [u8; MAX_NODE_NAME]) -> usize {
+    node_name(b"/dev/usb", id, out)
+}
+
+/// `/dev/sdX` followed by `number` (none for 0), as the VFS names disks
+/// and their partitions
+fn disk_name(port_idx: u8, number: u8) -> ([u8; 9], usize) {
+    let mut name = *b"/dev/sdX0";
+    name[7] = b'a' + port_idx;
+    if number == 0 {
+        return (name, 8);
+    }
+    name[8] = b'0' + number;
+    (name, 9)
+}
+
+/// Attach USB device `id` as a disk with its partitions registered, and
+/// mount both partitions under `/mnt`
+fn attach_and_mount(id: u32) -> Option<u8> {
+    let mut node = [0u8; MAX_NODE_NAME];
+    let len = usb_node(id, &mut node);
+    let port_idx = attach_disk(&node[..len])?;
+    if register_partitions(&MemDisk::new(), port_idx) != Ok(2) {
+        return None;
+    }
+
+    for (number, mountpoint) in [(1, &b"/mnt/a"[..]), (2, &b"/mnt/b"[..])] {
+        let (name, len) = disk_name(port_idx, number);
+        vfs_mount(&name[..len], mountpoint, b"ext4").ok()?;
+    }
+    Some(port_idx)
+}
+
+/// Test that pulling a USB disk unmounts the partitions mounted from it,
+/// named as the VFS registered them, and only those
+pub fn test_usb_removal_unmounts_partitions() -> bool {
+    let _ = vfs_init();
+    let port_idx = match attach_and_mount(3) {
+        Some(port_idx) => port_idx,
+        None => return false,
+    };
+    // A fixed disk on another letter stays mounted
+    let (other, other_len) = disk_name(port_idx + 1, 0);
+    if vfs_mount(&other[..other_len], b"/", b"sfs").is_err() {
+        return false;
+    }
+
+    let (first, first_len) = disk_name(port_idx, 1);
+    let (second, second_len) = disk_name(port_idx, 2);
+    let mounted = vfs_device_mounted(&first[..first_len]) && vfs_device_mounted(&second[..second_len]);
+
+    let mut node = [0u8; MAX_NODE_NAME];
+    let len = usb_node(3, &mut node);
+    let removed = vfs_unmount_device(&node[..len]) == 2;
+    let detached = detach_disk(&node[..len]) == Some(port_idx) && attached_disk(&node[..len]).is_none();
+
+    mounted && removed && detached
+        && !vfs_device_mounted(&first[..first_len])
+        && !vfs_device_mounted(&second[..second_len])
+        && vfs_device_mounted(&other[..other_len])
+        && get_partition(port_idx, 1).is_none()
+}
+
+/// Test that removal events for other devices leave the mounts alone,
+/// including nodes whose name extends the disk's own node
+pub fn test_other_nodes_keep_mounts() -> bool {
+    let _ = vfs_init();
+    let port_idx = match attach_and_mount(1) {
+        Some(port_idx) => port_idx,
+        None => return false,
+    };
+
+    let mut node = [0u8; MAX_NODE_NAME];
+    let longer = usb_node(12, &mut node);
+    let untouched = vfs_unmount_device(&node[..longer]) == 0;
+    let pci = node_name(b"/dev/pci", 1, &mut node);
+    let untouched = untouched && vfs_unmount_device(&node[..pci]) == 0;
+    // A partition is not a device node
+    let (part, part_len) = disk_name(port_idx, 1);
+    let untouched = untouched && vfs_unmount_device(&part[..part_len]) == 0;
+
+    // The whole-disk name reaches the same mounts as the USB node
+    let (disk, disk_len) = disk_name(port_idx, 0);
+    let removed = vfs_unmount_device(&disk[..disk_len]) == 2;
+
+    let own = usb_node(1, &mut node);
+    untouched && removed && detach_disk(&node[..own]) == Some(port_idx)
+}
+
+/// Test that attached disks get distinct letters, that a letter comes
+/// back when its disk is detached, and that read disks are skipped
+pub fn test_attach_letters() -> bool {
+    let mut first = [0u8; MAX_NODE_NAME];
+    let first_len = usb_node(5, &mut first);
+    let mut second = [0u8; MAX_NODE_NAME];
+    let second_len = usb_node(6, &mut second);
+
+    let a = attach_disk(&first[..first_len]);
+    let b = attach_disk(&second[..second_len]);
+    let distinct = a.is_some() && b.is_some() && a != b && attach_disk(&first[..first_len]) == a;
+
+    // A disk whose table has been read keeps its letter to itself
+    let reused = match a {
+        Some(port_idx) => {
+            detach_disk(&first[..first_len]) == a
+                && register_partitions(&MemDisk::new(), port_idx).is_ok()
+                && attach_disk(&first[..first_len]).is_some_and(|p| p != port_idx && Some(p) != b)
+        }
+        None => false,
+    };
+
+    let cleared = detach_disk(&first[..first_len]).is_some()
+        && detach_disk(&second[..second_len]).is_some()
+        && detach_disk(&second[..second_len]).is_none();
+    if let Some(port_idx) = a {
+        forget_partitions(port_idx);
+    }
+
+    distinct && reused && cleared
+}
+
+/// Test that paths are handed to a mount's filesystem relative to its
+/// mountpoint, and that a longer name beside the mountpoint is not inside it
+pub fn test_path_in_mount() -> bool {
+    let _ = vfs_init();
+    if vfs_mount(b"/dev/sdz", b"/", b"sfs").is_err() || vfs_mount(b"/dev/sdy", b"/mnt/a", b"sfs").is_err() {
+        return false;
+    }
+
+    let root = resolve_path(b"/etc/passwd");
+    let mnt = resolve_path(b"/mnt/a/x");
+    match (root, mnt) {
+        (Some(root), Some(mnt)) => {
+            path_in_mount(root, b"/etc/passwd") == Some(&b"/etc/passwd"[..])
+                && path_in_mount(mnt, b"/mnt/a/x") == Some(&b"/x"[..])
+                && path_in_mount(mnt, b"/mnt/a") == Some(&b"/"[..])
+                && resolve_path(b"/mnt/ab").and_then(|idx| path_in_mount(idx, b"/mnt/ab")).is_none()
+                // Nothing in the service serves these
+                && get_mount_fs(root).is_none()
+        }
+        _ => false,
+    }
+}
+
+/// Run all device removal tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 4] = [
+        test_usb_removal_unmounts_partitions,
+        test_other_nodes_keep_mounts,
+        test_attach_letters,
+        test_path_in_mount,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
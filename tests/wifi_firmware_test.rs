@@ -0,0 +1,121 @@
+//! WiFi Firmware Tests
+//!
+//! Tests for splitting iwlwifi firmware files and building scan requests
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/network/wifi/src/firmware.rs"]
+mod firmware;
+#[path = "../drivers/network/wifi/src/scan_cmd.rs"]
+mod scan_cmd;
+
+use firmware::*;
+use scan_cmd::*;
+
+const SEC_RT: u32 = 19;
+const OTHER_TLV: u32 = 1;
+
+/// Append one TLV holding a section at `load_addr`, padded to 4 bytes
+fn push_section(file: &mut [u8], len: &mut usize, tlv_type: u32, load_addr: u32, data: &[u8]) {
+    let body_len = 4 + data.len();
+    file[*len..*len + 4].copy_from_slice(&tlv_type.to_le_bytes());
+    file[*len + 4..*len + 8].copy_from_slice(&(body_len as u32).to_le_bytes());
+    file[*len + 8..*len + 12].copy_from_slice(&load_addr.to_le_bytes());
+    file[*len + 12..*len + 12 + data.len()].copy_from_slice(data);
+    *len += 8 + ((body_len + 3) & !3);
+}
+
+fn firmware_file(file: &mut [u8; 512]) -> usize {
+    *file = [0; 512];
+    file[4..8].copy_from_slice(&IWL_TLV_UCODE_MAGIC.to_le_bytes());
+    let mut len = 88;
+    push_section(file, &mut len, SEC_RT, 0x0040_0000, &[1, 2, 3]);
+    push_section(file, &mut len, OTHER_TLV, 0, &[9; 6]);
+    push_section(file, &mut len, SEC_RT, 0x0080_0000, &[4; 8]);
+    push_section(file, &mut len, SEC_RT, 0xFFFF_CCCC, &[]);
+    push_section(file, &mut len, SEC_RT, 0x0000_1000, &[5; 5]);
+    push_section(file, &mut len, SEC_RT, 0xAAAA_BBBB, &[]);
+    push_section(file, &mut len, SEC_RT, 0x0000_2000, &[6; 4]);
+    len
+}
+
+/// Test that runtime sections split into LMAC and UMAC at the separator
+/// and that paged sections are left out
+pub fn test_parse_runtime_image() -> bool {
+    let mut file = [0u8; 512];
+    let len = firmware_file(&mut file);
+    let image = match parse_runtime_image(&file[..len]) {
+        Ok(image) => image,
+        Err(_) => return false,
+    };
+
+    let lmac0 = image.lmac[0].is_some_and(|s| s.load_addr == 0x0040_0000 && s.data == [1, 2, 3]);
+    let lmac1 = image.lmac[1].is_some_and(|s| s.load_addr == 0x0080_0000 && s.data.len() == 8);
+    let umac0 = image.umac[0].is_some_and(|s| s.load_addr == 0x1000 && s.data == [5; 5]);
+    image.lmac_count == 2 && image.umac_count == 1 && lmac0 && lmac1 && umac0
+}
+
+/// Test that bad magic and truncated TLVs are refused
+pub fn test_reject_bad_files() -> bool {
+    let mut file = [0u8; 512];
+    let len = firmware_file(&mut file);
+
+    let mut bad_magic = file;
+    bad_magic[4] ^= 0xFF;
+
+    // Cut the file inside the first section's data
+    let truncated = parse_runtime_image(&file[..88 + 10]).is_err();
+
+    parse_runtime_image(&bad_magic[..len]).is_err()
+        && truncated
+        && parse_runtime_image(&file[..40]).is_err()
+        && parse_runtime_image(&file[..88]).is_err()
+}
+
+/// Test the passive scan request's channel list and flags
+pub fn test_build_passive_scan() -> bool {
+    let mut buf = [0u8; SCAN_REQ_UMAC_SIZE];
+    if build_passive_scan(7, &mut buf[..SCAN_REQ_UMAC_SIZE - 1]).is_some() {
+        return false;
+    }
+    if build_passive_scan(7, &mut buf) != Some(SCAN_REQ_UMAC_SIZE) {
+        return false;
+    }
+
+    let uid = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+    let flags = u16::from_le_bytes([buf[8], buf[9]]);
+    let count = buf[45] as usize;
+    let first = &buf[48..56];
+    let last = &buf[48 + (count - 1) * 8..48 + count * 8];
+
+    uid == 7
+        && flags & (1 << 1) != 0
+        && count == SCAN_CHANNELS.len()
+        && first[4] == 1 && first[5] == PHY_BAND_24
+        && last[4] == 165 && last[5] == PHY_BAND_5
+        && SCAN_REQ_UMAC_SIZE == 1940
+}
+
+/// Run all WiFi firmware tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_parse_runtime_image,
+        test_reject_bad_files,
+        test_build_passive_scan,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,137 @@
+//! WiFi Scan Tests
+//!
+//! Tests for turning received beacons into a list of access points
+
+#![no_std]
+#![no_main]
+
+#[path = "../drivers/network/wifi/src/ieee80211.rs"]
+mod ieee80211;
+#[path = "../drivers/network/wifi/src/rx.rs"]
+mod rx;
+
+use ieee80211::*;
+
+/// Build a beacon (subtype 8) or probe response (subtype 5) with the given elements
+fn mgmt_frame(subtype: u8, bssid: [u8; 6], elements: &[u8], out: &mut [u8; 128]) -> usize {
+    *out = [0; 128];
+    out[0] = subtype << 4;
+    out[16..22].copy_from_slice(&bssid);
+    out[36..36 + elements.len()].copy_from_slice(elements);
+    36 + elements.len()
+}
+
+fn ap(bssid_last: u8, ssid: &[u8], rssi: i8) -> AccessPoint {
+    let mut ap = AccessPoint { ssid: [0; MAX_SSID_LEN], ssid_len: ssid.len() as u8, bssid: [2, 0, 0, 0, 0, bssid_last], channel: 6, rssi };
+    ap.ssid[..ssid.len()].copy_from_slice(ssid);
+    ap
+}
+
+/// Test SSID, BSSID and channel extraction, and rejection of other frames
+pub fn test_parse_beacon() -> bool {
+    let bssid = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55];
+    let mut frame = [0u8; 128];
+
+    // SSID "home", DS parameter set says channel 11; radio was on 10
+    let len = mgmt_frame(8, bssid, &[0, 4, b'h', b'o', b'm', b'e', 1, 1, 0x82, 3, 1, 11], &mut frame);
+    let beacon = match parse_beacon(&frame[..len], 10, -40) {
+        Some(ap) => ap,
+        None => return false,
+    };
+    let beacon_ok = beacon.ssid() == b"home" && beacon.bssid == bssid && beacon.channel == 11 && beacon.rssi == -40;
+
+    // 5 GHz probe response: no DS element, channel from HT operation
+    let len = mgmt_frame(5, bssid, &[0, 2, b'a', b'p', 61, 1, 36], &mut frame);
+    let probe_ok = parse_beacon(&frame[..len], 40, -60).is_some_and(|ap| ap.channel == 36);
+
+    // Nothing to go on: the channel the frame arrived on
+    let len = mgmt_frame(8, bssid, &[0, 0], &mut frame);
+    let fallback_ok = parse_beacon(&frame[..len], 6, -70).is_some_and(|ap| ap.channel == 6 && ap.ssid_len == 0);
+
+    // A data frame, and a beacon cut off before its fixed fields
+    frame[0] = 0x08;
+    let rejected = parse_beacon(&frame[..48], 6, -50).is_none() && parse_beacon(&frame[..30], 6, -50).is_none();
+
+    beacon_ok && probe_ok && fallback_ok && rejected
+}
+
+/// Test that repeated sightings merge and a full list keeps the strongest
+pub fn test_scan_list_merge() -> bool {
+    let mut list = ScanList::new();
+    list.insert(ap(1, b"", -70));
+    list.insert(ap(1, b"hidden", -80));
+    list.insert(ap(1, b"", -50));
+    let merged = list.len() == 1
+        && list.get(0).is_some_and(|e| e.ssid() == b"hidden" && e.rssi == -50);
+
+    for i in 2..=MAX_SCAN_RESULTS as u8 {
+        list.insert(ap(i, b"x", -90));
+    }
+    let full = list.len() == MAX_SCAN_RESULTS;
+    list.insert(ap(200, b"near", -30));
+    list.insert(ap(201, b"far", -95));
+    let kept = (0..list.len()).any(|i| list.get(i).is_some_and(|e| e.bssid[5] == 200))
+        && !(0..list.len()).any(|i| list.get(i).is_some_and(|e| e.bssid[5] == 201));
+
+    list.clear();
+    merged && full && kept && list.is_empty()
+}
+
+/// Test unpacking a received frame and its signal from an RX buffer
+pub fn test_parse_rx_mpdu() -> bool {
+    let mut buf = [0u8; 128];
+    let frame_len = 40usize;
+    let payload_len = 52 + frame_len;
+    buf[0..4].copy_from_slice(&((4 + payload_len) as u32 | 0xC000).to_le_bytes());
+    buf[4] = rx::REPLY_RX_MPDU_CMD;
+    buf[8..10].copy_from_slice(&(frame_len as u16).to_le_bytes());
+    buf[8 + 32] = 0; // antenna A had no reading
+    buf[8 + 33] = 47;
+    buf[8 + 34] = 149;
+    buf[8 + 52] = 0x80;
+
+    let packet = match rx::parse_packet(&buf) {
+        Some(packet) => packet,
+        None => return false,
+    };
+    let mpdu = match rx::parse_mpdu(packet.payload) {
+        Some(mpdu) => mpdu,
+        None => return false,
+    };
+
+    let mut alive = [0u8; 4];
+    alive[0..2].copy_from_slice(&rx::IWL_ALIVE_STATUS_OK.to_le_bytes());
+
+    packet.cmd == rx::REPLY_RX_MPDU_CMD
+        && packet.payload.len() == payload_len
+        && mpdu.frame.len() == frame_len
+        && mpdu.frame[0] == 0x80
+        && mpdu.channel == 149
+        && mpdu.rssi == -47
+        && rx::parse_packet(&buf[..20]).is_none()
+        && rx::alive_ok(&alive)
+        && !rx::alive_ok(&[0, 0])
+}
+
+/// Run all WiFi scan tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_parse_beacon,
+        test_scan_list_merge,
+        test_parse_rx_mpdu,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,99 @@
+//! Window Damage Tests
+//!
+//! Tests for damage rect coalescing and partial compositing in the window
+//! manager
+
+#![no_std]
+#![no_main]
+
+#[path = "../gui/window_manager/src/window.rs"]
+mod window;
+#[path = "../gui/window_manager/src/damage.rs"]
+mod damage;
+#[path = "../gui/window_manager/src/compose.rs"]
+mod compose;
+
+use compose::*;
+use damage::*;
+use window::*;
+
+/// Test that a rect bridging two damaged rects merges all three, while a
+/// separate rect stays on its own
+pub fn test_damage_coalesce() -> bool {
+    let mut damage = DamageList::new();
+    damage.add(Rect { x: 0, y: 0, width: 10, height: 10 });
+    damage.add(Rect { x: 20, y: 0, width: 10, height: 10 });
+    damage.add(Rect { x: 100, y: 100, width: 5, height: 5 });
+    damage.add(Rect { x: 0, y: 0, width: 0, height: 50 });
+    let separate = damage.rects().len() == 3;
+
+    damage.add(Rect { x: 5, y: 5, width: 20, height: 2 });
+    let rects = damage.rects();
+
+    separate
+        && rects.len() == 2
+        && rects.contains(&Rect { x: 0, y: 0, width: 30, height: 10 })
+        && rects.contains(&Rect { x: 100, y: 100, width: 5, height: 5 })
+}
+
+/// Test that a full list folds everything into one covering rect
+pub fn test_damage_fold_when_full() -> bool {
+    let mut damage = DamageList::new();
+    for i in 0..MAX_DAMAGE_RECTS as i32 {
+        damage.add(Rect { x: i * 10, y: 0, width: 5, height: 5 });
+    }
+    let full = damage.rects().len() == MAX_DAMAGE_RECTS;
+
+    damage.add(Rect { x: 0, y: 100, width: 5, height: 5 });
+    let folded = damage.rects() == [Rect { x: 0, y: 0, width: (MAX_DAMAGE_RECTS as u32 - 1) * 10 + 5, height: 105 }];
+
+    damage.clear();
+    full && folded && damage.is_empty()
+}
+
+/// Test that only the clip rect is painted and that a window hidden there
+/// by a higher one is not drawn through it
+pub fn test_compose_clip_and_occlusion() -> bool {
+    let mut pixels = [0u32; 16 * 16];
+    let lower = Window::new(1, 7, Rect { x: 0, y: 0, width: 8, height: 8 }, [0; 64]);
+    let upper = Window::new(2, 7, Rect { x: 2, y: 2, width: 12, height: 12 }, [0; 64]);
+    let stack = [lower, upper];
+
+    let mut canvas = Canvas {
+        pixels: &mut pixels,
+        width: 16,
+        height: 16,
+        clip: Rect { x: 4, y: 4, width: 4, height: 4 },
+    };
+    compose(&mut canvas, stack.iter(), 0, |_| None);
+
+    // Inside the clip the upper window's title bar covers the lower one;
+    // outside it nothing was touched
+    pixels[4 * 16 + 4] == TITLE_COLOR
+        && pixels[7 * 16 + 7] == TITLE_COLOR
+        && pixels[3 * 16 + 3] == 0
+        && pixels[8 * 16 + 8] == 0
+}
+
+/// Run all window damage tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_damage_coalesce,
+        test_damage_fold_when_full,
+        test_compose_clip_and_occlusion,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
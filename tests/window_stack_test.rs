@@ -0,0 +1,87 @@
+//! Window Stacking Tests
+//!
+//! Tests for z-order maintenance and hit-testing in the window manager
+
+#![no_std]
+#![no_main]
+
+#[path = "../gui/window_manager/src/window.rs"]
+mod window;
+#[path = "../gui/window_manager/src/stack.rs"]
+mod stack;
+
+use stack::*;
+use window::*;
+
+/// Two overlapping windows: 1 at (0,0) 200x200 and 2 at (100,100) 200x200
+fn windows() -> [Window; 2] {
+    [
+        Window::new(1, 7, Rect { x: 0, y: 0, width: 200, height: 200 }, [0; 64]),
+        Window::new(2, 8, Rect { x: 100, y: 100, width: 200, height: 200 }, [0; 64]),
+    ]
+}
+
+/// Test that raise moves a window to the top and keeps the rest in order
+pub fn test_raise() -> bool {
+    let mut order = ZOrder::new();
+    order.push(1);
+    order.push(2);
+    order.push(3);
+
+    let raised = order.raise(1) && order.ids() == [2, 3, 1];
+    order.remove(3);
+    raised && order.ids() == [2, 1] && !order.raise(3)
+}
+
+/// Test that the topmost window under a point wins
+pub fn test_hit_test() -> bool {
+    let windows = windows();
+    let lookup = |id: u32| windows.iter().find(|w| w.id == id);
+    let mut order = ZOrder::new();
+    order.push(1);
+    order.push(2);
+
+    let before = order.hit_test(150, 150, lookup) == Some(2)
+        && order.hit_test(50, 50, lookup) == Some(1)
+        && order.hit_test(250, 50, lookup).is_none()
+        && order.hit_test(300, 150, lookup).is_none();
+
+    order.raise(1);
+    before && order.hit_test(150, 150, lookup) == Some(1)
+}
+
+/// Test that minimized windows are skipped
+pub fn test_hit_test_skips_minimized() -> bool {
+    let mut windows = windows();
+    windows[1].minimize();
+    let lookup = |id: u32| windows.iter().find(|w| w.id == id);
+    let mut order = ZOrder::new();
+    order.push(1);
+    order.push(2);
+
+    order.hit_test(150, 150, lookup) == Some(1)
+        && order.topmost(lookup, |w| w.is_visible()) == Some(1)
+}
+
+/// Run all window stacking tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_raise,
+        test_hit_test,
+        test_hit_test_skips_minimized,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,117 @@
+//! Window State Tests
+//!
+//! Tests for minimize, maximize and restore of window manager windows
+
+#![no_std]
+#![no_main]
+
+#[path = "../gui/window_manager/src/window.rs"]
+mod window;
+
+use window::*;
+
+const SCREEN: Rect = Rect { x: 0, y: 0, width: 1024, height: 768 };
+
+fn test_window() -> Window {
+    Window::new(1, 7, Rect { x: 100, y: 50, width: 320, height: 200 }, [0; 64])
+}
+
+/// Test that maximize covers the screen and restore brings the old rect back
+pub fn test_maximize_restore() -> bool {
+    let mut window = test_window();
+    window.maximize(SCREEN);
+    let maximized = window.rect() == SCREEN
+        && window.flags == WINDOW_FLAG_MAXIMIZED | WINDOW_FLAG_SAVED_GEOMETRY;
+
+    // Maximizing twice must not overwrite the saved geometry
+    window.maximize(SCREEN);
+    window.restore();
+
+    maximized
+        && window.rect() == Rect { x: 100, y: 50, width: 320, height: 200 }
+        && window.flags == 0
+}
+
+/// Test that restoring a minimized window keeps it maximized
+pub fn test_minimize_restore() -> bool {
+    let mut window = test_window();
+    window.maximize(SCREEN);
+    window.minimize();
+    let hidden = !window.is_visible();
+
+    window.restore();
+    hidden && window.is_visible() && window.rect() == SCREEN && window.flags & WINDOW_FLAG_MAXIMIZED != 0
+}
+
+/// Test that moving a maximized window drops the maximized state
+pub fn test_geometry_leaves_maximized() -> bool {
+    let mut window = test_window();
+    window.maximize(SCREEN);
+    window.set_geometry(Rect { x: 10, y: 10, width: 640, height: 480 });
+    window.restore();
+
+    window.flags == 0 && window.rect() == Rect { x: 10, y: 10, width: 640, height: 480 }
+}
+
+/// Test the window list entry layout and title truncation
+pub fn test_encode_summary() -> bool {
+    let mut title = [0u8; 64];
+    title[..31].copy_from_slice(b"A rather long window title here");
+    let mut window = Window::new(9, 7, Rect { x: -5, y: 20, width: 640, height: 480 }, title);
+    window.minimize();
+
+    let mut entry = [0xAAu8; WINDOW_SUMMARY_SIZE];
+    window.encode_summary(&mut entry);
+
+    entry[0..4] == 9u32.to_le_bytes()
+        && entry[4..8] == (-5i32).to_le_bytes()
+        && entry[12..16] == 640u32.to_le_bytes()
+        && entry[20..24] == WINDOW_FLAG_MINIMIZED.to_le_bytes()
+        && entry[24..47] == title[..23]
+        && entry[47] == 0
+}
+
+/// Test that titles keep up to 63 bytes, stop at NUL and drop split UTF-8
+pub fn test_parse_title() -> bool {
+    let mut long = [b'a'; 100];
+    let full = parse_title(&long);
+
+    long[10] = 0;
+    let short = parse_title(&long);
+
+    // "é" is two bytes; the limit cuts the one starting at byte 62
+    let mut split = [b'b'; 64];
+    split[62] = 0xC3;
+    split[63] = 0xA9;
+    let cut = parse_title(&split);
+
+    full[..63] == [b'a'; 63] && full[63] == 0
+        && short[..10] == [b'a'; 10] && short[10..] == [0; 54]
+        && cut[..62] == [b'b'; 62] && cut[62] == 0
+        && parse_title(&[]) == [0; 64]
+}
+
+/// Run all window state tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 5] = [
+        test_maximize_restore,
+        test_minimize_restore,
+        test_geometry_leaves_maximized,
+        test_encode_summary,
+        test_parse_title,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}
@@ -0,0 +1,94 @@
+//! Window Surface Tests
+//!
+//! Tests for client surface layout and compositing of committed frames in
+//! the window manager
+
+#![no_std]
+#![no_main]
+
+#[path = "../gui/window_manager/src/window.rs"]
+mod window;
+#[path = "../gui/window_manager/src/surface.rs"]
+mod surface;
+#[path = "../gui/window_manager/src/compose.rs"]
+mod compose;
+
+use compose::*;
+use surface::*;
+use window::*;
+
+/// Test that a layout is refused unless both frames fit in the region and
+/// rows hold whole XRGB pixels
+pub fn test_layout_validation() -> bool {
+    SurfaceLayout::new(100, 50, 400, 2 * 400 * 50).is_some()
+        && SurfaceLayout::new(100, 50, 400, 2 * 400 * 50 - 1).is_none()
+        && SurfaceLayout::new(100, 50, 396, 1 << 20).is_none()
+        && SurfaceLayout::new(100, 50, 402, 1 << 20).is_none()
+        && SurfaceLayout::new(0, 50, 400, 1 << 20).is_none()
+}
+
+/// Test that frames lie one after the other and only two exist
+pub fn test_frame_offsets() -> bool {
+    let layout = SurfaceLayout::new(10, 8, 64, 1024).unwrap();
+
+    layout.frame_size() == 512
+        && layout.frame_offset(0) == Some(0)
+        && layout.frame_offset(1) == Some(512)
+        && layout.frame_offset(SURFACE_BUFFERS as u32).is_none()
+}
+
+/// Test that a committed frame is drawn into the window body, cut to the
+/// body, with uncovered body left plain
+pub fn test_compose_content() -> bool {
+    const SIZE: usize = 32;
+    let mut pixels = [0u32; SIZE * SIZE];
+
+    // Body at (1, 21), 10x8 pixels
+    let window = Window::new(1, 7, Rect { x: 0, y: 0, width: 12, height: 30 }, [0; 64]);
+    let body = content_rect(&window);
+
+    // 12x4 frame, rows 16 pixels apart, each pixel its column number
+    let mut frame = [0u32; 16 * 4];
+    for (i, pixel) in frame.iter_mut().enumerate() {
+        *pixel = 0x100 + (i % 16) as u32;
+    }
+    let content = Content { pixels: &frame, width: 12, height: 4, stride: 16 };
+
+    let mut canvas = Canvas {
+        pixels: &mut pixels,
+        width: SIZE as u32,
+        height: SIZE as u32,
+        clip: Rect { x: 0, y: 0, width: SIZE as u32, height: SIZE as u32 },
+    };
+    draw_window(&mut canvas, &window, false, Some(&content));
+
+    let at = |x: i32, y: i32| pixels[y as usize * SIZE + x as usize];
+    body == Rect { x: 1, y: 21, width: 10, height: 8 }
+        && at(1, 21) == 0x100
+        && at(10, 24) == 0x109
+        && at(11, 21) == BORDER_COLOR
+        && at(1, 25) == BODY_COLOR
+}
+
+/// Run all window surface tests
+pub fn run_all_tests() {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    let tests: [fn() -> bool; 3] = [
+        test_layout_validation,
+        test_frame_offsets,
+        test_compose_content,
+    ];
+
+    for test in tests.iter() {
+        if test() {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    // In real implementation, would print results
+    // kprintf!("Tests: %d passed, %d failed\n", passed, failed);
+}